@@ -0,0 +1,136 @@
+//! Throughput of common operations on every `Graph` backend, to make regressions in the backend
+//! internals visible.
+//!
+//! Each case reports the number of vertices of its input processed per second, with one result
+//! per backend. New cases go in `backend`, and new backends in `backends`.
+//!
+//! Run with `cargo bench --bench backends`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use hermitq::zx::generate::random_clifford_circuit;
+use hermitq::zx::graph::*;
+use hermitq::zx::io::json;
+use hermitq::zx::io::sink::Builder;
+use hermitq::zx::random::Seed;
+use hermitq::zx::simple_graph::GGraph;
+use hermitq::zx::simplify;
+use hermitq::zx::stable_graph::StableGGraph;
+use hermitq::zx::vec_graph::VecGraph;
+use rand::Rng;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+const VERTICES: usize = 100_000;
+
+/// Qubits of the Clifford circuits given to `clifford_simp`
+const QUBITS: [u32; 3] = [10, 30, 50];
+
+/// Gates per qubit of the Clifford circuits
+const GATES_PER_QUBIT: usize = 40;
+
+/// Random graph-like diagram: Z spiders with Clifford+T phases and about three Hadamard edges
+/// per vertex, with a hundred of them connected to boundaries.
+fn random_graph_like<G: Graph + Default>(seed: u64) -> G {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let mut g = G::default();
+    let vs: Vec<_> = (0..VERTICES)
+        .map(|_| g.add_vertex(VertexKind::Z, Phase::new(rng.gen_range(0..8), 4)))
+        .collect();
+    for _ in 0..VERTICES * 3 / 2 {
+        let (a, b) = (rng.gen_range(0..VERTICES), rng.gen_range(0..VERTICES));
+        if a != b {
+            g.add_edge_smart(vs[a], vs[b], EdgeKind::Hadam);
+        }
+    }
+    for (i, &v) in vs.iter().take(100).enumerate() {
+        let b = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        g.add_edge(b, v, EdgeKind::Regular);
+        if i % 2 == 0 {
+            g.set_input(b, true);
+        } else {
+            g.set_output(b, true);
+        }
+    }
+    g
+}
+
+/// Random diagram of Z and X spiders with about three edges per vertex, a third of them
+/// regular, so that fusion merges small clusters.
+fn random_fusable<G: Graph + Default>(seed: u64) -> G {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let mut g = G::default();
+    let vs: Vec<_> = (0..VERTICES)
+        .map(|_| {
+            let kind = if rng.gen() {
+                VertexKind::Z
+            } else {
+                VertexKind::X
+            };
+            g.add_vertex(kind, Phase::new(rng.gen_range(0..8), 4))
+        })
+        .collect();
+    for _ in 0..VERTICES * 3 / 2 {
+        let (a, b) = (rng.gen_range(0..VERTICES), rng.gen_range(0..VERTICES));
+        let kind = if rng.gen_ratio(1, 3) {
+            EdgeKind::Regular
+        } else {
+            EdgeKind::Hadam
+        };
+        g.add_edge(vs[a], vs[b], kind);
+    }
+    g
+}
+
+/// The cases of the request, on one backend
+fn backend<G: Graph + Default + Clone>(c: &mut Criterion, name: &str) {
+    let mut group = c.benchmark_group("build graph-like");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(VERTICES as u64));
+    group.bench_function(name, |b| b.iter(|| random_graph_like::<G>(0)));
+    group.finish();
+
+    let fusable: G = random_fusable(0);
+    let mut group = c.benchmark_group("fuse_spiders");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(fusable.num_vertices() as u64));
+    group.bench_with_input(BenchmarkId::from_parameter(name), &fusable, |b, g| {
+        b.iter(|| simplify::fuse_spiders(&mut g.clone()))
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("clifford_simp");
+    group.sample_size(10);
+    for &qubits in QUBITS.iter() {
+        let circuit = random_clifford_circuit(qubits, qubits as usize * GATES_PER_QUBIT, Seed(0));
+        let g: G = circuit.to_graph();
+        group.throughput(Throughput::Elements(g.num_vertices() as u64));
+        group.bench_with_input(BenchmarkId::new(name, qubits), &g, |b, g| {
+            b.iter(|| simplify::clifford_simp(&mut g.clone()))
+        });
+    }
+    group.finish();
+
+    let graph_like: G = random_graph_like(0);
+    let src = json::to_json(&graph_like);
+    let h: G = json::parse_into(&src, Builder::new()).unwrap();
+    assert_eq!(h.num_vertices(), graph_like.num_vertices());
+    let mut group = c.benchmark_group("JSON round trip");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(graph_like.num_vertices() as u64));
+    group.bench_with_input(BenchmarkId::from_parameter(name), &graph_like, |b, g| {
+        b.iter(|| {
+            let src = json::to_json(g);
+            json::parse_into(&src, Builder::<G>::new()).unwrap()
+        })
+    });
+    group.finish();
+}
+
+fn backends(c: &mut Criterion) {
+    backend::<GGraph>(c, "GGraph");
+    backend::<StableGGraph>(c, "StableGGraph");
+    backend::<VecGraph>(c, "VecGraph");
+}
+
+criterion_group!(benches, backends);
+criterion_main!(benches);
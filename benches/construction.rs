@@ -0,0 +1,153 @@
+//! Translation of a long circuit into a diagram with `GraphBuilder` against a naive
+//! construction through the `Graph` trait, which finds the end of each wire in the graph for
+//! every gate.
+//!
+//! Both report the number of vertices of the diagram built per second, with one result per
+//! backend. Run with `cargo bench --bench construction`.
+
+use criterion::measurement::WallTime;
+use criterion::{
+    criterion_group, criterion_main, BenchmarkGroup, BenchmarkId, Criterion, Throughput,
+};
+use hermitq::zx::analysis::is_isomorphic;
+use hermitq::zx::circuit::{Circuit, Gate, GraphBuilder};
+use hermitq::zx::generate::random_clifford_t_circuit;
+use hermitq::zx::graph::*;
+use hermitq::zx::io::sink::{Builder, GraphSink};
+use hermitq::zx::random::Seed;
+use hermitq::zx::simple_graph::GGraph;
+use hermitq::zx::vec_graph::VecGraph;
+
+const QUBITS: u32 = 50;
+const GATES: usize = 100_000;
+const T_PROBABILITY: f64 = 0.1;
+
+/// Diagram of a circuit where each gate is inserted before the outputs of its qubits, as it
+/// would be written without tracking the ends of the wires.
+fn naive<G: Graph + Default>(circuit: &Circuit) -> G {
+    let mut g = G::default();
+    let mut outputs = Vec::new();
+    for _ in 0..circuit.num_qubits {
+        let i = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        let o = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        g.set_input(i, true);
+        g.add_edge(i, o, EdgeKind::Regular);
+        outputs.push(o);
+    }
+    for &o in &outputs {
+        g.set_output(o, true);
+    }
+    // The edge to the output of a qubit and the vertex before it
+    let last = |g: &G, q: u32| {
+        let (e, v) = g
+            .incident_edges_with_neighbours(outputs[q as usize])
+            .next()
+            .unwrap();
+        (e, v, g.edge(e).unwrap().kind())
+    };
+    let spider = |g: &mut G, q: u32, kind: VertexKind, phase: Phase| {
+        let (e, prev, edge) = last(g, q);
+        g.remove_edge(e);
+        let v = g.add_vertex(kind, phase);
+        g.add_edge(prev, v, edge);
+        g.add_edge(v, outputs[q as usize], EdgeKind::Regular);
+        v
+    };
+    let toggle = |g: &mut G, q: u32| {
+        let (e, _, edge) = last(g, q);
+        let kind = match edge {
+            EdgeKind::Regular => EdgeKind::Hadam,
+            EdgeKind::Hadam => EdgeKind::Regular,
+        };
+        g.set_edge_kind(e, kind);
+    };
+    let hbox = |g: &mut G, qubits: &[u32], phase: Phase| {
+        let h = g.add_vertex(VertexKind::HBox, phase);
+        for &q in qubits {
+            let z = spider(g, q, VertexKind::Z, Phase::zero());
+            g.add_edge(z, h, EdgeKind::Regular);
+        }
+    };
+    for gate in &circuit.gates {
+        if let Some((q, kind, phase)) = gate.as_spider() {
+            spider(&mut g, q, kind, phase);
+            continue;
+        }
+        match *gate {
+            Gate::H(q) => toggle(&mut g, q),
+            Gate::Swap(a, b) => {
+                let ((ea, va, ka), (eb, vb, kb)) = (last(&g, a), last(&g, b));
+                g.remove_edges([ea, eb]);
+                g.add_edge(va, outputs[b as usize], ka);
+                g.add_edge(vb, outputs[a as usize], kb);
+            }
+            Gate::Cx(c, t) | Gate::Cz(c, t) => {
+                let (kind, edge) = match gate {
+                    Gate::Cx(..) => (VertexKind::X, EdgeKind::Regular),
+                    _ => (VertexKind::Z, EdgeKind::Hadam),
+                };
+                let a = spider(&mut g, c, VertexKind::Z, Phase::zero());
+                let b = spider(&mut g, t, kind, Phase::zero());
+                g.add_edge(a, b, edge);
+            }
+            Gate::Ccz(a, b, c) => hbox(&mut g, &[a, b, c], Phase::pi()),
+            Gate::Ccx(a, b, c) => {
+                toggle(&mut g, c);
+                hbox(&mut g, &[a, b, c], Phase::pi());
+                toggle(&mut g, c);
+            }
+            Gate::CPhase(a, b, phase) => hbox(&mut g, &[a, b], phase),
+            _ => unreachable!("single-qubit rotations are handled above"),
+        }
+    }
+    g
+}
+
+/// Diagram of a circuit built by streaming its gates into a `GraphBuilder` over a sink
+fn streamed<S: GraphSink>(circuit: &Circuit, sink: S) -> S::Graph {
+    let mut builder = GraphBuilder::new(circuit.num_qubits, sink);
+    for gate in &circuit.gates {
+        builder.push_gate(gate);
+    }
+    builder.finish()
+}
+
+/// Naive and streamed construction of the diagram of `circuit` into one backend, after
+/// checking that they agree on a small circuit
+fn backend<S: GraphSink>(
+    group: &mut BenchmarkGroup<WallTime>,
+    name: &str,
+    circuit: &Circuit,
+    sink: impl Fn() -> S,
+) where
+    S::Graph: Graph + Default,
+{
+    let small = random_clifford_t_circuit(4, 200, T_PROBABILITY, Seed(1));
+    assert!(is_isomorphic(
+        &naive::<S::Graph>(&small),
+        &streamed(&small, sink())
+    ));
+    let vertices = streamed(circuit, sink()).num_vertices();
+    group.throughput(Throughput::Elements(vertices as u64));
+    group.bench_with_input(BenchmarkId::new("naive", name), circuit, |b, circuit| {
+        b.iter(|| naive::<S::Graph>(circuit))
+    });
+    group.bench_with_input(
+        BenchmarkId::new("GraphBuilder", name),
+        circuit,
+        |b, circuit| b.iter(|| streamed(circuit, sink())),
+    );
+}
+
+fn backends(c: &mut Criterion) {
+    let circuit = random_clifford_t_circuit(QUBITS, GATES, T_PROBABILITY, Seed(0));
+    let mut group = c.benchmark_group("construction");
+    group.sample_size(10);
+    backend(&mut group, "GGraph", &circuit, Builder::<GGraph>::new);
+    backend(&mut group, "GGraph, bulk", &circuit, GGraph::<u32>::builder);
+    backend(&mut group, "VecGraph", &circuit, Builder::<VecGraph>::new);
+    group.finish();
+}
+
+criterion_group!(benches, backends);
+criterion_main!(benches);
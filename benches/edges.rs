@@ -0,0 +1,162 @@
+//! Edge and neighbourhood scans with per-edge lookups against the iterators that yield the
+//! endpoints together with the edges.
+//!
+//! Each scan reports the number of vertices of the diagram visited per second, with one result
+//! per backend. Run with `cargo bench --bench edges`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use hermitq::zx::graph::*;
+use hermitq::zx::simple_graph::GGraph;
+use hermitq::zx::vec_graph::VecGraph;
+use rand::Rng;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+const VERTICES: usize = 100_000;
+
+/// Random diagram of Z spiders with about three edges per vertex, a third of them regular
+fn random_diagram<G: Graph + Default>(seed: u64) -> G {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let mut g = G::default();
+    let vs: Vec<_> = (0..VERTICES)
+        .map(|_| g.add_vertex(VertexKind::Z, Phase::zero()))
+        .collect();
+    for _ in 0..VERTICES * 3 / 2 {
+        let (a, b) = (rng.gen_range(0..VERTICES), rng.gen_range(0..VERTICES));
+        let kind = if rng.gen_ratio(1, 3) {
+            EdgeKind::Regular
+        } else {
+            EdgeKind::Hadam
+        };
+        g.add_edge(vs[a], vs[b], kind);
+    }
+    g
+}
+
+/// Result of a scan: a checksum, and the number of edge lookups by index per visited edge
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Scan {
+    hadamards: usize,
+    lookups_per_edge: f64,
+}
+
+/// Count the Hadamard edges with distinct endpoints, looking up each edge by index.
+fn edges_by_index<G: Graph>(g: &G) -> Scan {
+    let (mut hadamards, mut visited, mut lookups) = (0, 0, 0);
+    for e in g.edges() {
+        let (a, b) = g.edge_endpoints(e).unwrap();
+        let kind = g.edge(e).unwrap().kind();
+        lookups += 2;
+        visited += 1;
+        if a != b && kind == EdgeKind::Hadam {
+            hadamards += 1;
+        }
+    }
+    Scan {
+        hadamards,
+        lookups_per_edge: lookups as f64 / visited as f64,
+    }
+}
+
+/// Count the Hadamard edges with distinct endpoints with `edges_with_data`.
+fn edges_with_data<G: Graph>(g: &G) -> Scan {
+    let mut hadamards = 0;
+    for (a, b, edata) in g.edges_with_data() {
+        if a != b && edata.kind() == EdgeKind::Hadam {
+            hadamards += 1;
+        }
+    }
+    Scan {
+        hadamards,
+        lookups_per_edge: 0.0,
+    }
+}
+
+/// Count the Hadamard edges to other vertices around every vertex, finding the other endpoint
+/// of each incident edge by index, as the rewrite rules used to.
+fn neighbourhoods_by_index<G: Graph>(g: &G) -> Scan {
+    let (mut hadamards, mut visited, mut lookups) = (0, 0, 0);
+    for v in g.vertices() {
+        for e in g.incident_edges(v) {
+            let (a, b) = g.edge_endpoints(e).unwrap();
+            let n = if a == v { b } else { a };
+            let kind = g.edge(e).unwrap().kind();
+            lookups += 2;
+            visited += 1;
+            if n != v && kind == EdgeKind::Hadam {
+                hadamards += 1;
+            }
+        }
+    }
+    Scan {
+        hadamards,
+        lookups_per_edge: lookups as f64 / visited as f64,
+    }
+}
+
+/// Count the Hadamard edges to other vertices around every vertex with
+/// `incident_edges_with_neighbours`, looking up only the edge kind.
+fn neighbourhoods_with_neighbours<G: Graph>(g: &G) -> Scan {
+    let (mut hadamards, mut visited, mut lookups) = (0, 0, 0);
+    for v in g.vertices() {
+        for (e, n) in g.incident_edges_with_neighbours(v) {
+            let kind = g.edge(e).unwrap().kind();
+            lookups += 1;
+            visited += 1;
+            if n != v && kind == EdgeKind::Hadam {
+                hadamards += 1;
+            }
+        }
+    }
+    Scan {
+        hadamards,
+        lookups_per_edge: lookups as f64 / visited as f64,
+    }
+}
+
+/// Named scan of a graph
+type Pass<G> = (&'static str, fn(&G) -> Scan);
+
+/// Every scan on one backend, after checking that the scans agree
+fn backend<G: Graph + Default>(c: &mut Criterion, name: &str) {
+    let g: G = random_diagram(0);
+    let passes: [Pass<G>; 4] = [
+        ("edges, by index", edges_by_index),
+        ("edges_with_data", edges_with_data),
+        ("neighbourhoods, by index", neighbourhoods_by_index),
+        (
+            "incident_edges_with_neighbours",
+            neighbourhoods_with_neighbours,
+        ),
+    ];
+    let mut checksums = Vec::new();
+    for &(pass, f) in passes.iter() {
+        let scan = f(&g);
+        checksums.push(scan.hadamards);
+        println!(
+            "{}, {}: {:.1} lookups/edge",
+            pass, name, scan.lookups_per_edge
+        );
+    }
+    // Each neighbourhood scan sees every edge twice.
+    assert_eq!(checksums[0], checksums[1]);
+    assert_eq!(checksums[2], checksums[3]);
+    assert_eq!(checksums[2], 2 * checksums[0]);
+
+    for &(pass, f) in passes.iter() {
+        let mut group = c.benchmark_group(pass);
+        group.throughput(Throughput::Elements(g.num_vertices() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &g, |b, g| {
+            b.iter(|| f(g))
+        });
+        group.finish();
+    }
+}
+
+fn backends(c: &mut Criterion) {
+    backend::<GGraph>(c, "GGraph");
+    backend::<VecGraph>(c, "VecGraph");
+}
+
+criterion_group!(benches, backends);
+criterion_main!(benches);
@@ -0,0 +1,81 @@
+//! Neighbour iteration and spider fusion on `VecGraph` against the petgraph-backed `GGraph`.
+//!
+//! Both operations report the number of vertices of the diagram processed per second, with one
+//! result per backend. Run with `cargo bench --bench graph`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use hermitq::zx::graph::*;
+use hermitq::zx::simple_graph::GGraph;
+use hermitq::zx::simplify;
+use hermitq::zx::vec_graph::VecGraph;
+use rand::Rng;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+const VERTICES: usize = 100_000;
+
+/// Random diagram of Z and X spiders with about three edges per vertex, a third of them
+/// regular, so that fusion merges small clusters.
+fn random_diagram<G: Graph + Default>(seed: u64) -> G {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let mut g = G::default();
+    let vs: Vec<_> = (0..VERTICES)
+        .map(|_| {
+            let kind = if rng.gen() {
+                VertexKind::Z
+            } else {
+                VertexKind::X
+            };
+            g.add_vertex(kind, Phase::new(rng.gen_range(0..8), 4))
+        })
+        .collect();
+    for _ in 0..VERTICES * 3 / 2 {
+        let (a, b) = (rng.gen_range(0..VERTICES), rng.gen_range(0..VERTICES));
+        let kind = if rng.gen_ratio(1, 3) {
+            EdgeKind::Regular
+        } else {
+            EdgeKind::Hadam
+        };
+        g.add_edge(vs[a], vs[b], kind);
+    }
+    g
+}
+
+fn self_loops<G: Graph>(g: &G) -> usize {
+    g.edges()
+        .filter(|&e| {
+            let (a, b) = g.edge_endpoints(e).unwrap();
+            a == b
+        })
+        .count()
+}
+
+/// Visit every neighbour of every vertex, and fuse all the spiders of a copy, on one backend
+fn backend<G: Graph + Default + Clone>(c: &mut Criterion, name: &str) {
+    let g: G = random_diagram(0);
+    let visited: usize = g.vertices().map(|v| g.neighbours(v).count()).sum();
+    assert_eq!(visited, 2 * g.num_edges() as usize - self_loops(&g));
+
+    let mut group = c.benchmark_group("neighbours");
+    group.throughput(Throughput::Elements(g.num_vertices() as u64));
+    group.bench_with_input(BenchmarkId::from_parameter(name), &g, |b, g| {
+        b.iter(|| g.vertices().map(|v| g.neighbours(v).count()).sum::<usize>())
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("fusion");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(g.num_vertices() as u64));
+    group.bench_with_input(BenchmarkId::from_parameter(name), &g, |b, g| {
+        b.iter(|| simplify::fuse_spiders(&mut g.clone()))
+    });
+    group.finish();
+}
+
+fn backends(c: &mut Criterion) {
+    backend::<GGraph>(c, "GGraph");
+    backend::<VecGraph>(c, "VecGraph");
+}
+
+criterion_group!(benches, backends);
+criterion_main!(benches);
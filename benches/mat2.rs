@@ -0,0 +1,56 @@
+//! Gaussian elimination of `Mat2` against a naive matrix of booleans.
+//!
+//! Both report the number of matrix entries reduced per second. Run with `cargo bench --bench mat2`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use hermitq::linalg::Mat2;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+/// Row echelon form of a matrix of booleans, one entry at a time. Returns the rank.
+fn naive_gauss(m: &mut [Vec<bool>]) -> usize {
+    let cols = m.first().map_or(0, |row| row.len());
+    let mut rank = 0;
+    for c in 0..cols {
+        let pivot = match (rank..m.len()).find(|&r| m[r][c]) {
+            Some(pivot) => pivot,
+            None => continue,
+        };
+        m.swap(rank, pivot);
+        let (top, bottom) = m.split_at_mut(rank + 1);
+        for row in bottom.iter_mut().filter(|row| row[c]) {
+            for (x, &p) in row[c..].iter_mut().zip(&top[rank][c..]) {
+                *x ^= p;
+            }
+        }
+        rank += 1;
+    }
+    rank
+}
+
+fn gauss(c: &mut Criterion) {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let mut group = c.benchmark_group("gauss");
+    for &n in &[64, 128, 256, 512, 1024] {
+        let rows: Vec<Vec<bool>> = (0..n)
+            .map(|_| (0..n).map(|_| rng.gen()).collect())
+            .collect();
+        let m = Mat2::from_rows(&rows);
+        assert_eq!(
+            m.clone().gauss(false, None),
+            naive_gauss(&mut rows.clone()),
+            "implementations disagree on the rank"
+        );
+        group.throughput(Throughput::Elements(n as u64 * n as u64));
+        group.bench_with_input(BenchmarkId::new("Mat2", n), &m, |b, m| {
+            b.iter(|| m.clone().gauss(false, None))
+        });
+        group.bench_with_input(BenchmarkId::new("naive", n), &rows, |b, rows| {
+            b.iter(|| naive_gauss(&mut rows.clone()))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, gauss);
+criterion_main!(benches);
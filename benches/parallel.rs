@@ -0,0 +1,66 @@
+//! Clifford simplification of a large graph-like diagram, matching rewrites on one thread
+//! against all the threads of the rayon pool.
+//!
+//! Both report the number of vertices of the diagram simplified per second. Run with `cargo bench --bench parallel --features rayon`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use hermitq::zx::generate::random_graph_like;
+use hermitq::zx::graph::*;
+use hermitq::zx::random::Seed;
+use hermitq::zx::simple_graph::GGraph;
+use hermitq::zx::simplify;
+
+const SPIDERS: usize = 50_000;
+const BLOCK: usize = 50;
+
+/// Random graph-like diagram with `SPIDERS` spiders, made of independent blocks of
+/// `BLOCK` spiders so that simplifying it stays sparse
+fn random_diagram() -> GGraph {
+    let mut g = GGraph::new();
+    for seed in 0..(SPIDERS / BLOCK) as u64 {
+        g.tensor(random_graph_like(BLOCK, 0.08, Seed(seed)));
+    }
+    g
+}
+
+/// `clifford_simp` on a copy of `g` within `pool`
+fn simplify_in(pool: &rayon::ThreadPool, g: &GGraph) -> GGraph {
+    let mut h = g.clone();
+    pool.install(|| simplify::clifford_simp(&mut h));
+    h
+}
+
+fn clifford(c: &mut Criterion) {
+    let g = random_diagram();
+    let mut threads = vec![1, rayon::current_num_threads()];
+    threads.dedup();
+    let pools: Vec<_> = threads
+        .into_iter()
+        .map(|threads| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            (threads, pool)
+        })
+        .collect();
+    // The matches are picked in the same order, whatever the number of threads.
+    let remaining: Vec<_> = pools
+        .iter()
+        .map(|(_, pool)| simplify_in(pool, &g).num_vertices())
+        .collect();
+    assert!(remaining.iter().all(|&n| n == remaining[0]));
+
+    let mut group = c.benchmark_group("clifford_simp");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(g.num_vertices() as u64));
+    for (threads, pool) in &pools {
+        group.bench_with_input(BenchmarkId::new("threads", threads), &g, |b, g| {
+            b.iter(|| simplify_in(pool, g))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, clifford);
+criterion_main!(benches);
@@ -0,0 +1,92 @@
+//! T-count reduction of `optimize::phase_poly` after `full_reduce`, on CNOT+T circuits.
+//!
+//! CNOT+T circuits denote a single phase polynomial, so they show what the TODD-like pass
+//! finds beyond merging equal parities. The T-counts are printed once, and the TODD-like pass
+//! reports the number of vertices it processes per second. Run with `cargo bench --bench phase_poly`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use hermitq::zx::circuit::{Circuit, Gate};
+use hermitq::zx::graph::*;
+use hermitq::zx::optimize;
+use hermitq::zx::random::Seed;
+use hermitq::zx::simple_graph::GGraph;
+use hermitq::zx::simplify;
+use rand::Rng;
+
+const SIZES: [(u32, usize); 4] = [(4, 20), (5, 40), (6, 60), (8, 100)];
+const SEEDS: u64 = 20;
+
+/// Random circuit of `depth` gates, each a T or a CNOT with equal probability
+fn random_cnot_t_circuit(qubits: u32, depth: usize, seed: Seed) -> Circuit {
+    let mut rng = seed.rng();
+    let mut circuit = Circuit::new(qubits);
+    for _ in 0..depth {
+        let q = rng.gen_range(0..qubits);
+        if rng.gen() {
+            circuit.add_gate(Gate::T(q));
+        } else {
+            let t = (q + rng.gen_range(1..qubits)) % qubits;
+            circuit.add_gate(Gate::Cx(q, t));
+        }
+    }
+    circuit
+}
+
+/// Diagrams of `SEEDS` random circuits of the given size, after `full_reduce`
+fn reduced(qubits: u32, depth: usize) -> Vec<GGraph> {
+    (0..SEEDS)
+        .map(|seed| {
+            let mut g: GGraph = random_cnot_t_circuit(qubits, depth, Seed(seed)).to_graph();
+            simplify::full_reduce(&mut g);
+            g
+        })
+        .collect()
+}
+
+/// Total T-count of the diagrams after `pass`
+fn tcount(diagrams: &[GGraph], pass: fn(&mut GGraph) -> u32) -> u32 {
+    diagrams
+        .iter()
+        .map(|g| {
+            let mut h = g.clone();
+            pass(&mut h);
+            h.tcount()
+        })
+        .sum()
+}
+
+fn phase_poly(c: &mut Criterion) {
+    println!(
+        "{:>8} {:>8} {:>10} {:>12} {:>10}",
+        "qubits", "gates", "full", "phase_poly", "todd"
+    );
+    let mut group = c.benchmark_group("phase_poly_todd");
+    for &(qubits, depth) in SIZES.iter() {
+        let diagrams = reduced(qubits, depth);
+        println!(
+            "{:>8} {:>8} {:>10} {:>12} {:>10}",
+            qubits,
+            depth,
+            tcount(&diagrams, |_| 0),
+            tcount(&diagrams, optimize::phase_poly),
+            tcount(&diagrams, optimize::phase_poly_todd),
+        );
+        let vertices: u32 = diagrams.iter().map(|g| g.num_vertices()).sum();
+        group.throughput(Throughput::Elements(vertices as u64));
+        group.bench_with_input(
+            BenchmarkId::new("qubits", qubits),
+            &diagrams,
+            |b, diagrams| {
+                b.iter(|| {
+                    for g in diagrams {
+                        optimize::phase_poly_todd(&mut g.clone());
+                    }
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, phase_poly);
+criterion_main!(benches);
@@ -0,0 +1,59 @@
+//! Reduction quality and running time of `full_reduce` on a mid-size Clifford+T circuit.
+//!
+//! Each strategy reports the number of vertices of the diagram simplified per second, with one
+//! result per backend. Run with `cargo bench --bench reduce`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use hermitq::zx::circuit::Circuit;
+use hermitq::zx::generate::random_clifford_t_circuit;
+use hermitq::zx::graph::*;
+use hermitq::zx::random::Seed;
+use hermitq::zx::simple_graph::GGraph;
+use hermitq::zx::simplify;
+use hermitq::zx::stable_graph::StableGGraph;
+use hermitq::zx::vec_graph::VecGraph;
+
+const QUBITS: u32 = 16;
+const GATES: usize = 2000;
+const T_PROBABILITY: f64 = 0.1;
+
+type Strategy<G> = fn(&mut G) -> simplify::SimplifyStats;
+
+/// Both strategies on one backend, printing the T-count and size they reduce the circuit to
+fn backend<G: Graph + Default + Clone>(c: &mut Criterion, name: &str, circuit: &Circuit) {
+    let g: G = circuit.to_graph();
+    let strategies: [(&str, Strategy<G>); 2] = [
+        ("clifford", simplify::clifford_simp),
+        ("full", simplify::full_reduce),
+    ];
+    for &(strategy, f) in strategies.iter() {
+        let mut h = g.clone();
+        f(&mut h);
+        println!(
+            "{}, {}: T-count {} -> {}, {} -> {} vertices",
+            strategy,
+            name,
+            g.tcount(),
+            h.tcount(),
+            g.num_vertices(),
+            h.num_vertices()
+        );
+        let mut group = c.benchmark_group(strategy);
+        group.sample_size(10);
+        group.throughput(Throughput::Elements(g.num_vertices() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(name), &g, |b, g| {
+            b.iter(|| f(&mut g.clone()))
+        });
+        group.finish();
+    }
+}
+
+fn backends(c: &mut Criterion) {
+    let circuit = random_clifford_t_circuit(QUBITS, GATES, T_PROBABILITY, Seed(0));
+    backend::<GGraph>(c, "GGraph", &circuit);
+    backend::<StableGGraph>(c, "StableGGraph", &circuit);
+    backend::<VecGraph>(c, "VecGraph", &circuit);
+}
+
+criterion_group!(benches, backends);
+criterion_main!(benches);
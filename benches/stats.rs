@@ -0,0 +1,74 @@
+//! Cost-model evaluation inside a local search, recomputing the statistics with a full scan
+//! against reading the counters kept by `GGraph::enable_stat_tracking`.
+//!
+//! Each step changes the phase of a random spider and evaluates the cost of the diagram, as an
+//! annealer does after every move. Both variants report the number of steps per second. Run with
+//! `cargo bench --bench stats`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use hermitq::zx::generate::random_clifford_t_circuit;
+use hermitq::zx::graph::*;
+use hermitq::zx::random::Seed;
+use hermitq::zx::simple_graph::GGraph;
+use hermitq::zx::stats::TrackedStats;
+use rand::Rng;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+const QUBITS: u32 = 64;
+const GATES: usize = 20_000;
+const T_PROBABILITY: f64 = 0.1;
+const STEPS: usize = 1000;
+
+type V = <GGraph as Graph>::VertexIx;
+
+/// Cost of a diagram, weighting T spiders above the other spiders and Hadamard edges
+fn cost(stats: &TrackedStats) -> u32 {
+    10 * stats.tcount + stats.num_z + stats.num_x + stats.hadamard_edges
+}
+
+/// Lowest cost seen over `STEPS` random phase changes
+fn search(g: &mut GGraph, spiders: &[V], tracked: bool) -> u32 {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    let mut best = u32::MAX;
+    for _ in 0..STEPS {
+        let v = spiders[rng.gen_range(0..spiders.len())];
+        g.set_phase(v, Phase::new(rng.gen_range(0..8), 4));
+        let stats = if tracked {
+            g.tracked_stats().unwrap()
+        } else {
+            TrackedStats::new(g)
+        };
+        best = best.min(cost(&stats));
+    }
+    best
+}
+
+fn cost_model(c: &mut Criterion) {
+    let circuit = random_clifford_t_circuit(QUBITS, GATES, T_PROBABILITY, Seed(0));
+    let mut g: GGraph = circuit.to_graph();
+    let spiders: Vec<_> = g
+        .vertices()
+        .filter(|&v| g.vertex(v).unwrap().kind() != VertexKind::Boundary)
+        .collect();
+    let mut tracked = g.clone();
+    tracked.enable_stat_tracking();
+    assert_eq!(
+        search(&mut g.clone(), &spiders, false),
+        search(&mut tracked.clone(), &spiders, true)
+    );
+
+    let mut group = c.benchmark_group("cost_model");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(STEPS as u64));
+    group.bench_function(BenchmarkId::from_parameter("full_scan"), |b| {
+        b.iter(|| search(&mut g, &spiders, false))
+    });
+    group.bench_function(BenchmarkId::from_parameter("tracked"), |b| {
+        b.iter(|| search(&mut tracked, &spiders, true))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, cost_model);
+criterion_main!(benches);
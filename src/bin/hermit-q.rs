@@ -0,0 +1,285 @@
+//! Command-line interface for simplifying, inspecting and converting diagrams.
+//!
+//! The file formats are inferred from the extensions, see `USAGE`.
+
+use hermitq::zx::analysis::resource_estimate;
+use hermitq::zx::circuit::{Circuit, CircuitFormat};
+use hermitq::zx::graph::*;
+use hermitq::zx::io::{self, Compression, ReadError};
+use hermitq::zx::simple_graph::GGraph;
+use hermitq::zx::simplify::{self, SimplifyStats};
+use itertools::Itertools;
+use std::fmt;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process;
+
+const USAGE: &str = "\
+usage: hermit-q simplify <input> -o <output> [--strategy clifford|full|gadgets]
+       hermit-q stats <input> [--resources]
+       hermit-q convert <input> -o <output>
+
+Formats are inferred from the file extensions: .qasm, .json, .dot and .tikz.
+A further .gz extension, as in diagram.json.gz, compresses the file with gzip.
+TikZ files can only be written, and QASM files can only be written from an
+unsimplified circuit, since diagrams cannot be extracted back into circuits.
+With --resources, stats also prints a forecast of the resources of the diagram.";
+
+/// Error reported to the user, with the exit code of the process
+enum CliError {
+    /// Malformed command line, exits with 2
+    Usage(String),
+    /// Failure while running a valid command, exits with 1
+    Failed(String),
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CliError::Usage(message) => write!(f, "{}\n\n{}", message, USAGE),
+            CliError::Failed(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Qasm,
+    Json,
+    Dot,
+    Tikz,
+}
+
+impl Format {
+    /// Format and compression of a file, from its extensions
+    fn of(path: &str) -> Result<(Format, Compression), CliError> {
+        let (ext, compression) = io::extension(Path::new(path));
+        let format = match ext {
+            Some("qasm") => Format::Qasm,
+            Some("json") => Format::Json,
+            Some("dot") => Format::Dot,
+            Some("tikz") => Format::Tikz,
+            _ => {
+                return Err(CliError::Usage(format!(
+                    "cannot infer the format of `{}` from its extension",
+                    path
+                )))
+            }
+        };
+        Ok((format, compression))
+    }
+
+    /// The diagram format of the IO layer, if this is not QASM
+    fn diagram(self) -> Option<io::Format> {
+        match self {
+            Format::Qasm => None,
+            Format::Json => Some(io::Format::Json),
+            Format::Dot => Some(io::Format::Dot),
+            Format::Tikz => Some(io::Format::Tikz),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Strategy {
+    Clifford,
+    Full,
+    Gadgets,
+}
+
+/// Parsed command line
+struct Args {
+    command: String,
+    input: String,
+    output: Option<String>,
+    strategy: Strategy,
+    resources: bool,
+}
+
+impl Args {
+    fn parse(args: &[String]) -> Result<Args, CliError> {
+        let mut args = args.iter();
+        let command = match args.next() {
+            Some(command) if ["simplify", "stats", "convert"].contains(&command.as_str()) => {
+                command.clone()
+            }
+            Some(command) => return Err(CliError::Usage(format!("unknown command `{}`", command))),
+            None => return Err(CliError::Usage("missing command".to_string())),
+        };
+        let mut input = None;
+        let mut output = None;
+        let mut strategy = Strategy::Clifford;
+        let mut resources = false;
+        while let Some(arg) = args.next() {
+            let mut value = |flag: &str| {
+                args.next()
+                    .cloned()
+                    .ok_or_else(|| CliError::Usage(format!("missing value for `{}`", flag)))
+            };
+            match arg.as_str() {
+                "-o" | "--output" => output = Some(value(arg)?),
+                "--strategy" => {
+                    strategy = match value(arg)?.as_str() {
+                        "clifford" => Strategy::Clifford,
+                        "full" => Strategy::Full,
+                        "gadgets" => Strategy::Gadgets,
+                        other => {
+                            return Err(CliError::Usage(format!("unknown strategy `{}`", other)))
+                        }
+                    }
+                }
+                "--resources" => resources = true,
+                flag if flag.starts_with('-') => {
+                    return Err(CliError::Usage(format!("unknown option `{}`", flag)))
+                }
+                path if input.is_none() => input = Some(path.to_string()),
+                path => return Err(CliError::Usage(format!("unexpected argument `{}`", path))),
+            }
+        }
+        let input = input.ok_or_else(|| CliError::Usage("missing input file".to_string()))?;
+        Ok(Args {
+            command,
+            input,
+            output,
+            strategy,
+            resources,
+        })
+    }
+
+    fn output(&self) -> Result<&str, CliError> {
+        self.output
+            .as_deref()
+            .ok_or_else(|| CliError::Usage(format!("`{}` needs an output file", self.command)))
+    }
+}
+
+/// A diagram read from a file, with its circuit if it was read from QASM
+struct Input {
+    graph: GGraph,
+    circuit: Option<Circuit>,
+}
+
+fn read(path: &str) -> Result<Input, CliError> {
+    let (format, _) = Format::of(path)?;
+    if let Some(format) = format.diagram() {
+        let graph = io::read(path, format).map_err(|e| match e {
+            ReadError::Dot(_) | ReadError::Json(_) => {
+                CliError::Failed(format!("cannot parse `{}`\n{}", path, e))
+            }
+            _ => CliError::Failed(format!("cannot read `{}`: {}", path, e)),
+        })?;
+        return Ok(Input {
+            graph,
+            circuit: None,
+        });
+    }
+    let mut contents = String::new();
+    io::open_reader(path)
+        .and_then(|mut reader| reader.read_to_string(&mut contents))
+        .map_err(|e| CliError::Failed(format!("cannot read `{}`: {}", path, e)))?;
+    let circuit = Circuit::parse_all(&contents, CircuitFormat::Qasm).map_err(|errors| {
+        let errors = errors.iter().join("\n\n");
+        CliError::Failed(format!("cannot parse `{}`\n{}", path, errors))
+    })?;
+    Ok(Input {
+        graph: circuit.to_graph_into(GGraph::builder()),
+        circuit: Some(circuit),
+    })
+}
+
+/// Write a diagram, compressed if the path ends in `.gz`. The circuit, if given, must denote
+/// the diagram.
+fn write(path: &str, g: &GGraph, circuit: Option<&Circuit>) -> Result<(), CliError> {
+    let (format, compression) = Format::of(path)?;
+    let written = match (format.diagram(), circuit) {
+        (Some(format), _) => io::write(path, g, format, compression),
+        (None, Some(circuit)) => io::open_writer(path, compression).and_then(|mut w| {
+            w.write_all(circuit.to_qasm().as_bytes())?;
+            w.finish()
+        }),
+        (None, None) => return Err(qasm_unsupported(path)),
+    };
+    written.map_err(|e| CliError::Failed(format!("cannot write `{}`: {}", path, e)))
+}
+
+fn qasm_unsupported(path: &str) -> CliError {
+    CliError::Failed(format!(
+        "cannot write `{}`: only unsimplified circuits can be written as QASM",
+        path
+    ))
+}
+
+fn simplify(args: &Args) -> Result<(), CliError> {
+    let output = args.output()?;
+    // Fail on an unsupported output before doing the work.
+    if Format::of(output)?.0 == Format::Qasm {
+        return Err(qasm_unsupported(output));
+    }
+    let mut g = read(&args.input)?.graph;
+    let tcount = g.tcount();
+    let mut stats = match args.strategy {
+        Strategy::Clifford => simplify::clifford_simp(&mut g),
+        Strategy::Full => simplify::full_reduce(&mut g),
+        Strategy::Gadgets => SimplifyStats {
+            gadgets: simplify::gadget_simp(&mut g),
+            ..Default::default()
+        },
+    };
+    stats.tcount = Some((tcount, g.tcount()));
+    print!("{}", stats);
+    write(output, &g, None)
+}
+
+fn stats(args: &Args) -> Result<(), CliError> {
+    let g = read(&args.input)?.graph;
+    println!("{}", g.summary());
+    println!("Clifford phases: {}", g.clifford_count());
+    println!("non-Clifford phases:");
+    let phases = g.non_clifford_phases();
+    for (phase, count) in phases
+        .iter()
+        .sorted_by_key(|(p, _)| (p.denominator(), p.numerator()))
+    {
+        println!(
+            "  {}/{} pi: {}",
+            phase.numerator(),
+            phase.denominator(),
+            count
+        );
+    }
+    if args.resources {
+        print!("{}", resource_estimate(&g));
+    }
+    Ok(())
+}
+
+fn convert(args: &Args) -> Result<(), CliError> {
+    let output = args.output()?;
+    let input = read(&args.input)?;
+    write(output, &input.graph, input.circuit.as_ref())
+}
+
+fn run(args: &[String]) -> Result<(), CliError> {
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+    let args = Args::parse(args)?;
+    match args.command.as_str() {
+        "simplify" => simplify(&args),
+        "stats" => stats(&args),
+        "convert" => convert(&args),
+        _ => unreachable!("commands are checked while parsing"),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(e) = run(&args) {
+        eprintln!("hermit-q: {}", e);
+        process::exit(match e {
+            CliError::Usage(_) => 2,
+            CliError::Failed(_) => 1,
+        });
+    }
+}
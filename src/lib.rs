@@ -1,2 +1,2 @@
+pub mod linalg;
 pub mod zx;
-
@@ -0,0 +1,380 @@
+//! Linear algebra over GF(2).
+
+use std::fmt;
+use std::ops::Mul;
+
+/// Addition of the row `src` into the row `dst`.
+///
+/// A sequence of row additions applied to a parity matrix is a CNOT circuit, with `src` as the
+/// control and `dst` as the target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RowAdd {
+    pub src: usize,
+    pub dst: usize,
+}
+
+/// Dense matrix over GF(2), with each row packed in 64-bit words.
+///
+/// Row operations work a word at a time, so eliminating an `n x n` matrix takes `O(n^3 / 64)`
+/// word operations.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Mat2 {
+    rows: usize,
+    cols: usize,
+    data: Vec<Vec<u64>>,
+}
+
+impl Mat2 {
+    /// Matrix of the given shape with all entries 0
+    pub fn zeros(rows: usize, cols: usize) -> Self {
+        Mat2 {
+            rows,
+            cols,
+            data: vec![vec![0; cols.div_ceil(64)]; rows],
+        }
+    }
+
+    pub fn identity(n: usize) -> Self {
+        let mut res = Mat2::zeros(n, n);
+        for i in 0..n {
+            res.set(i, i, true);
+        }
+        res
+    }
+
+    /// Matrix with the entries given by a function of the row and column
+    pub fn from_fn(rows: usize, cols: usize, f: impl Fn(usize, usize) -> bool) -> Self {
+        let mut res = Mat2::zeros(rows, cols);
+        for r in 0..rows {
+            for c in 0..cols {
+                if f(r, c) {
+                    res.set(r, c, true);
+                }
+            }
+        }
+        res
+    }
+
+    /// Matrix with the given rows. Panics if they have different lengths.
+    pub fn from_rows(rows: &[Vec<bool>]) -> Self {
+        let cols = rows.first().map_or(0, |row| row.len());
+        assert!(
+            rows.iter().all(|row| row.len() == cols),
+            "rows of different lengths"
+        );
+        Mat2::from_fn(rows.len(), cols, |r, c| rows[r][c])
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn get(&self, r: usize, c: usize) -> bool {
+        assert!(c < self.cols, "column {} out of bounds", c);
+        self.data[r][c / 64] >> (c % 64) & 1 == 1
+    }
+
+    pub fn set(&mut self, r: usize, c: usize, value: bool) {
+        assert!(c < self.cols, "column {} out of bounds", c);
+        let bit = 1 << (c % 64);
+        if value {
+            self.data[r][c / 64] |= bit;
+        } else {
+            self.data[r][c / 64] &= !bit;
+        }
+    }
+
+    /// Entries of a row
+    pub fn row(&self, r: usize) -> Vec<bool> {
+        (0..self.cols).map(|c| self.get(r, c)).collect()
+    }
+
+    /// Whether every entry of a row is 0
+    pub fn is_zero_row(&self, r: usize) -> bool {
+        self.data[r].iter().all(|&w| w == 0)
+    }
+
+    /// Add the row `src` into the row `dst`.
+    pub fn row_add(&mut self, src: usize, dst: usize) {
+        self.row_add_from(src, dst, 0);
+    }
+
+    /// Add the row `src` into the row `dst`, skipping the words before `first_word`, which must
+    /// be zero in `src`.
+    fn row_add_from(&mut self, src: usize, dst: usize, first_word: usize) {
+        assert_ne!(src, dst, "adding a row to itself");
+        let (src, dst) = if src < dst {
+            let (a, b) = self.data.split_at_mut(dst);
+            (&a[src], &mut b[0])
+        } else {
+            let (a, b) = self.data.split_at_mut(src);
+            (&b[0], &mut a[dst])
+        };
+        xor_words(&mut dst[first_word..], &src[first_word..]);
+    }
+
+    pub fn swap_rows(&mut self, a: usize, b: usize) {
+        self.data.swap(a, b);
+    }
+
+    pub fn transpose(&self) -> Mat2 {
+        Mat2::from_fn(self.cols, self.rows, |r, c| self.get(c, r))
+    }
+
+    /// Reduce the matrix to row echelon form using only row additions, returning its rank.
+    ///
+    /// The pivot of each of the first `rank` rows is to the right of the one above, and the
+    /// remaining rows are zero. With `full_reduce`, the entries above each pivot are also
+    /// cleared, giving the reduced row echelon form. The row additions performed are appended
+    /// to `ops`, if given.
+    pub fn gauss(&mut self, full_reduce: bool, mut ops: Option<&mut Vec<RowAdd>>) -> usize {
+        let mut pivots = Vec::new();
+        for c in 0..self.cols {
+            let row = pivots.len();
+            if row == self.rows {
+                break;
+            }
+            let (word, bit) = (c / 64, 1 << (c % 64));
+            let pivot = match (row..self.rows).find(|&r| self.data[r][word] & bit != 0) {
+                Some(pivot) => pivot,
+                None => continue,
+            };
+            // The rows from `row` on are zero before column `c`, so only the words from `word`
+            // on change.
+            if pivot != row {
+                self.row_add_from(pivot, row, word);
+                record(&mut ops, pivot, row);
+            }
+            let (top, bottom) = self.data.split_at_mut(row + 1);
+            let src = &top[row][word..];
+            for (i, dst) in bottom.iter_mut().enumerate() {
+                if dst[word] & bit != 0 {
+                    xor_words(&mut dst[word..], src);
+                    record(&mut ops, row, row + 1 + i);
+                }
+            }
+            pivots.push(c);
+        }
+        if full_reduce {
+            for (row, &c) in pivots.iter().enumerate().rev() {
+                let (word, bit) = (c / 64, 1 << (c % 64));
+                let (top, bottom) = self.data.split_at_mut(row);
+                let src = &bottom[0][word..];
+                for (r, dst) in top.iter_mut().enumerate() {
+                    if dst[word] & bit != 0 {
+                        xor_words(&mut dst[word..], src);
+                        record(&mut ops, row, r);
+                    }
+                }
+            }
+        }
+        pivots.len()
+    }
+
+    pub fn rank(&self) -> usize {
+        self.clone().gauss(false, None)
+    }
+
+    /// Inverse of a square matrix, if it is invertible.
+    pub fn inverse(&self) -> Option<Mat2> {
+        assert_eq!(self.rows, self.cols, "inverting a non-square matrix");
+        let mut ops = Vec::new();
+        if self.clone().gauss(true, Some(&mut ops)) < self.rows {
+            return None;
+        }
+        // The row operations turned the matrix into the identity, so they build the inverse.
+        let mut res = Mat2::identity(self.rows);
+        for op in ops {
+            res.row_add(op.src, op.dst);
+        }
+        Some(res)
+    }
+
+    /// Basis of the vectors `x` with `self * x == 0`, as the rows of a matrix with `cols`
+    /// columns.
+    pub fn nullspace(&self) -> Mat2 {
+        let mut m = self.clone();
+        let rank = m.gauss(true, None);
+        let pivots: Vec<_> = (0..rank)
+            .map(|r| (0..self.cols).find(|&c| m.get(r, c)).unwrap())
+            .collect();
+        let free: Vec<_> = (0..self.cols).filter(|c| !pivots.contains(c)).collect();
+        let mut res = Mat2::zeros(free.len(), self.cols);
+        for (i, &f) in free.iter().enumerate() {
+            // Set the free variable and solve each pivot variable from its row.
+            res.set(i, f, true);
+            for (r, &p) in pivots.iter().enumerate() {
+                if m.get(r, f) {
+                    res.set(i, p, true);
+                }
+            }
+        }
+        res
+    }
+}
+
+fn xor_words(dst: &mut [u64], src: &[u64]) {
+    for (d, s) in dst.iter_mut().zip(src) {
+        *d ^= s;
+    }
+}
+
+fn record(ops: &mut Option<&mut Vec<RowAdd>>, src: usize, dst: usize) {
+    if let Some(ops) = ops {
+        ops.push(RowAdd { src, dst });
+    }
+}
+
+impl<'a> Mul<&'a Mat2> for &'a Mat2 {
+    type Output = Mat2;
+
+    /// Matrix product. Panics if the shapes do not match.
+    fn mul(self, rhs: &Mat2) -> Mat2 {
+        assert_eq!(
+            self.cols, rhs.rows,
+            "multiplying a matrix with {} columns by one with {} rows",
+            self.cols, rhs.rows
+        );
+        // Each row of the product is the sum of the rows of `rhs` selected by a row of `self`.
+        let mut res = Mat2::zeros(self.rows, rhs.cols);
+        for r in 0..self.rows {
+            for k in (0..self.cols).filter(|&k| self.get(r, k)) {
+                xor_words(&mut res.data[r], &rhs.data[k]);
+            }
+        }
+        res
+    }
+}
+
+impl Mul for Mat2 {
+    type Output = Mat2;
+
+    fn mul(self, rhs: Mat2) -> Mat2 {
+        &self * &rhs
+    }
+}
+
+impl fmt::Display for Mat2 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for r in 0..self.rows {
+            let row: String = (0..self.cols)
+                .map(|c| if self.get(r, c) { '1' } else { '0' })
+                .collect();
+            writeln!(f, "{}", row)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Mat2 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Mat2({}x{})\n{}", self.rows, self.cols, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::random::Seed;
+    use rand::Rng;
+
+    fn random(rows: usize, cols: usize, seed: u64) -> Mat2 {
+        let mut rng = Seed(seed).rng();
+        let mut m = Mat2::zeros(rows, cols);
+        for r in 0..rows {
+            for c in 0..cols {
+                m.set(r, c, rng.gen_bool(0.5));
+            }
+        }
+        m
+    }
+
+    /// Invertible matrix built from random row additions on the identity
+    fn random_invertible(n: usize, seed: u64) -> Mat2 {
+        let mut rng = Seed(seed).rng();
+        let mut m = Mat2::identity(n);
+        for _ in 0..4 * n {
+            let (src, dst) = (rng.gen_range(0..n), rng.gen_range(0..n));
+            if src != dst {
+                m.row_add(src, dst);
+            }
+        }
+        m
+    }
+
+    #[test]
+    fn rank_deficient() {
+        let m = Mat2::from_rows(&[
+            vec![true, false, true, true],
+            vec![false, true, true, false],
+            vec![true, true, false, true],
+        ]);
+        assert_eq!(m.rank(), 2);
+        let mut reduced = m.clone();
+        assert_eq!(reduced.gauss(true, None), 2);
+        assert_eq!(reduced.to_string(), "1011\n0110\n0000\n");
+        assert_eq!(Mat2::zeros(5, 3).rank(), 0);
+
+        // Rows spanning several words, where the last row repeats the sum of two others
+        let mut m = random(20, 150, 1);
+        for c in 0..150 {
+            let v = m.get(3, c) ^ m.get(17, c);
+            m.set(19, c, v);
+        }
+        assert_eq!(m.rank(), 19);
+        assert_eq!(m.transpose().rank(), 19);
+    }
+
+    #[test]
+    fn recorded_operations_replay() {
+        for full_reduce in [false, true] {
+            let m = random(40, 70, 2);
+            let mut reduced = m.clone();
+            let mut ops = Vec::new();
+            let rank = reduced.gauss(full_reduce, Some(&mut ops));
+            let mut replayed = m.clone();
+            for op in &ops {
+                replayed.row_add(op.src, op.dst);
+            }
+            assert_eq!(replayed, reduced);
+            for r in rank..40 {
+                assert!(reduced.is_zero_row(r));
+            }
+        }
+    }
+
+    #[test]
+    fn inverse_round_trip() {
+        for (n, seed) in [(1, 0), (7, 1), (64, 2), (100, 3)] {
+            let m = random_invertible(n, seed);
+            let inv = m.inverse().unwrap();
+            assert_eq!(&m * &inv, Mat2::identity(n));
+            assert_eq!(&inv * &m, Mat2::identity(n));
+            assert_eq!(inv.inverse().unwrap(), m);
+        }
+
+        // Repeating a row makes the matrix singular.
+        let mut singular = random_invertible(10, 4);
+        for c in 0..10 {
+            let v = singular.get(2, c);
+            singular.set(5, c, v);
+        }
+        assert_eq!(singular.inverse(), None);
+    }
+
+    #[test]
+    fn nullspace() {
+        let m = random(30, 100, 5);
+        let rank = m.rank();
+        let null = m.nullspace();
+        assert_eq!(null.rows(), 100 - rank);
+        assert_eq!(null.rank(), null.rows());
+        assert_eq!(&m * &null.transpose(), Mat2::zeros(30, null.rows()));
+
+        assert_eq!(Mat2::identity(4).nullspace().rows(), 0);
+    }
+}
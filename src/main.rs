@@ -1,5 +0,0 @@
-mod zx;
-
-fn main() {
-    println!("Hello, world!");
-}
@@ -0,0 +1,806 @@
+//! Structural queries on diagrams.
+
+use crate::linalg::Mat2;
+use crate::zx::bit_graph::BitGraph;
+use crate::zx::flow::{gflow, is_graph_like};
+use crate::zx::graph::*;
+use crate::zx::simplify::gadgets;
+use crate::zx::zh::ccz_at;
+use petgraph::unionfind::UnionFind;
+use petgraph::visit::EdgeRef;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+
+/// Check whether a diagram is a permutation of wires.
+///
+/// This is the case when every input is connected to a distinct output through a chain of
+/// phase-free degree-2 spiders, with an even number of Hadamard edges along the chain, and there
+/// are no other vertices.
+///
+/// Inputs and outputs are numbered in wire order. Returns `perm` such that input `i` is connected
+/// to output `perm[i]`.
+pub fn as_wire_permutation<G: Graph>(g: &G) -> Option<Vec<u32>> {
+    let inputs: Vec<_> = g.inputs().collect();
+    let outputs: Vec<_> = g.outputs().collect();
+    if inputs.len() != outputs.len() {
+        return None;
+    }
+    let output_pos: HashMap<_, _> = outputs
+        .iter()
+        .enumerate()
+        .map(|(i, &v)| (v, i as u32))
+        .collect();
+
+    let mut adjacency: HashMap<G::VertexIx, Vec<(G::VertexIx, EdgeKind)>> = HashMap::new();
+    for e in g.edges() {
+        let (v, u) = g.edge_endpoints(e).unwrap();
+        let kind = g.edge(e).unwrap().kind();
+        adjacency.entry(v).or_default().push((u, kind));
+        adjacency.entry(u).or_default().push((v, kind));
+    }
+    let neighbours = |v| adjacency.get(&v).map_or(&[][..], |n| &n[..]);
+
+    let mut visited = HashSet::new();
+    let mut perm = Vec::with_capacity(inputs.len());
+    for &input in &inputs {
+        visited.insert(input);
+        if g.is_output(input) && neighbours(input).is_empty() {
+            perm.push(output_pos[&input]);
+            continue;
+        }
+        let (mut prev, (mut cur, kind)) = match neighbours(input) {
+            &[n] => (input, n),
+            _ => return None,
+        };
+        let mut odd_hadamards = kind == EdgeKind::Hadam;
+        loop {
+            if !visited.insert(cur) {
+                return None;
+            }
+            let vdata = g.vertex(cur).unwrap();
+            if vdata.kind() == VertexKind::Boundary {
+                break;
+            }
+            let is_identity = (vdata.kind() == VertexKind::Z || vdata.kind() == VertexKind::X)
+                && vdata.phase().is_zero();
+            let (next, kind) = match neighbours(cur) {
+                &[a, b] if is_identity => {
+                    if a.0 == prev {
+                        b
+                    } else {
+                        a
+                    }
+                }
+                _ => return None,
+            };
+            odd_hadamards ^= kind == EdgeKind::Hadam;
+            prev = cur;
+            cur = next;
+        }
+        if odd_hadamards || g.is_input(cur) || neighbours(cur).len() != 1 {
+            return None;
+        }
+        perm.push(*output_pos.get(&cur)?);
+    }
+
+    if visited.len() != g.num_vertices() as usize {
+        return None;
+    }
+    Some(perm)
+}
+
+/// Check whether a diagram is the identity on its wires, see `as_wire_permutation`.
+pub fn is_identity_diagram<G: Graph>(g: &G) -> bool {
+    match as_wire_permutation(g) {
+        Some(perm) => perm.iter().enumerate().all(|(i, &j)| i as u32 == j),
+        None => false,
+    }
+}
+
+/// Check whether two diagrams are equal up to renumbering their vertices.
+///
+/// The isomorphism must preserve vertex kinds, phases and ground flags, the kind and multiplicity
+/// of the edges between each pair of vertices, self-loops included, and the order of the inputs
+/// and outputs. Scalars are not compared.
+///
+/// Vertices are first partitioned by colour refinement, starting from their labels, and the
+/// remaining ambiguity is resolved by a backtracking search over vertices of the same colour.
+/// This is meant for comparing small diagrams in tests, and can take exponential time on highly
+/// symmetric ones.
+pub fn is_isomorphic<A: Graph, B: Graph>(a: &A, b: &B) -> bool {
+    if (a.num_vertices(), a.num_edges()) != (b.num_vertices(), b.num_edges())
+        || a.inputs().count() != b.inputs().count()
+        || a.outputs().count() != b.outputs().count()
+    {
+        return false;
+    }
+    let (a, b) = (LabelledGraph::new(a), LabelledGraph::new(b));
+
+    // Colour refinement, with colours numbered consistently across both graphs.
+    let mut colours: HashMap<VertexLabel, usize> = HashMap::new();
+    let mut colour_of = |label: &VertexLabel| {
+        let next = colours.len();
+        *colours.entry(*label).or_insert(next)
+    };
+    let mut ca: Vec<usize> = a.labels.iter().map(&mut colour_of).collect();
+    let mut cb: Vec<usize> = b.labels.iter().map(&mut colour_of).collect();
+    let mut num_colours = colours.len();
+    loop {
+        if colour_histogram(&ca) != colour_histogram(&cb) {
+            return false;
+        }
+        let mut refined = HashMap::new();
+        let (na, nb) = (a.refine(&ca, &mut refined), b.refine(&cb, &mut refined));
+        ca = na;
+        cb = nb;
+        if refined.len() == num_colours {
+            break;
+        }
+        num_colours = refined.len();
+    }
+
+    // Map the vertices of `a` in an order where each one has as many already mapped neighbours
+    // as possible, preferring small colour classes.
+    let histogram = colour_histogram(&ca);
+    let mut order = Vec::with_capacity(ca.len());
+    let mut placed = vec![false; ca.len()];
+    let mut mapped_neighbours = vec![0; ca.len()];
+    for _ in 0..ca.len() {
+        let v = (0..ca.len())
+            .filter(|&v| !placed[v])
+            .min_by_key(|&v| (Reverse(mapped_neighbours[v]), histogram[&ca[v]]))
+            .unwrap();
+        placed[v] = true;
+        order.push(v);
+        for &u in a.adjacency[v].keys() {
+            mapped_neighbours[u] += 1;
+        }
+    }
+
+    let mut search = IsomorphismSearch {
+        a: &a,
+        b: &b,
+        ca: &ca,
+        cb: &cb,
+        forward: vec![None; ca.len()],
+        backward: vec![None; cb.len()],
+    };
+    search.extend(&order)
+}
+
+/// Label of a vertex that an isomorphism must preserve: its kind, phase as a numerator and
+/// denominator, ground flag, and position among the inputs and the outputs
+type VertexLabel = (VertexKind, (u64, u64), bool, Option<usize>, Option<usize>);
+
+/// Colour of a vertex together with the colours of its neighbours and the number of regular and
+/// Hadamard edges to each
+type Neighbourhood = (usize, Vec<(usize, [u32; 2])>);
+
+/// Diagram with its vertices numbered in iteration order
+struct LabelledGraph {
+    labels: Vec<VertexLabel>,
+    /// Number of regular and Hadamard edges from each vertex to each of its neighbours
+    adjacency: Vec<BTreeMap<usize, [u32; 2]>>,
+}
+
+impl LabelledGraph {
+    fn new<G: Graph>(g: &G) -> Self {
+        let vertices: Vec<_> = g.vertices().collect();
+        let index: HashMap<_, _> = vertices.iter().enumerate().map(|(i, &v)| (v, i)).collect();
+        let inputs: HashMap<_, _> = g.inputs().enumerate().map(|(i, v)| (v, i)).collect();
+        let outputs: HashMap<_, _> = g.outputs().enumerate().map(|(i, v)| (v, i)).collect();
+        let labels = vertices
+            .iter()
+            .map(|v| {
+                let data = g.vertex(*v).unwrap();
+                let phase = data.phase();
+                (
+                    data.kind(),
+                    (phase.numerator(), phase.denominator()),
+                    g.is_ground(*v),
+                    inputs.get(v).copied(),
+                    outputs.get(v).copied(),
+                )
+            })
+            .collect();
+        let mut adjacency = vec![BTreeMap::new(); vertices.len()];
+        for e in g.edges() {
+            let (v, u) = g.edge_endpoints(e).unwrap();
+            let (v, u) = (index[&v], index[&u]);
+            let kind = (g.edge(e).unwrap().kind() == EdgeKind::Hadam) as usize;
+            adjacency[v].entry(u).or_insert([0, 0])[kind] += 1;
+            if u != v {
+                adjacency[u].entry(v).or_insert([0, 0])[kind] += 1;
+            }
+        }
+        LabelledGraph { labels, adjacency }
+    }
+
+    /// Colours distinguishing the vertices by their colour and the colours of their
+    /// neighbours, numbered in `refined`
+    fn refine(&self, colours: &[usize], refined: &mut HashMap<Neighbourhood, usize>) -> Vec<usize> {
+        (0..colours.len())
+            .map(|v| {
+                let mut neighbours: Vec<_> = self.adjacency[v]
+                    .iter()
+                    .map(|(&u, &counts)| (colours[u], counts))
+                    .collect();
+                neighbours.sort_unstable();
+                let next = refined.len();
+                *refined.entry((colours[v], neighbours)).or_insert(next)
+            })
+            .collect()
+    }
+}
+
+fn colour_histogram(colours: &[usize]) -> HashMap<usize, usize> {
+    let mut histogram = HashMap::new();
+    for &c in colours {
+        *histogram.entry(c).or_insert(0) += 1;
+    }
+    histogram
+}
+
+/// Partial isomorphism between two labelled graphs, extended by backtracking
+struct IsomorphismSearch<'a> {
+    a: &'a LabelledGraph,
+    b: &'a LabelledGraph,
+    ca: &'a [usize],
+    cb: &'a [usize],
+    forward: Vec<Option<usize>>,
+    backward: Vec<Option<usize>>,
+}
+
+impl IsomorphismSearch<'_> {
+    /// Map the vertices of `a` in `order`, given that the previous ones are already mapped.
+    fn extend(&mut self, order: &[usize]) -> bool {
+        let (v, rest) = match order.split_first() {
+            Some(split) => split,
+            None => return true,
+        };
+        let v = *v;
+        for w in 0..self.cb.len() {
+            if self.cb[w] != self.ca[v] || self.backward[w].is_some() || !self.consistent(v, w) {
+                continue;
+            }
+            self.forward[v] = Some(w);
+            self.backward[w] = Some(v);
+            if self.extend(rest) {
+                return true;
+            }
+            self.forward[v] = None;
+            self.backward[w] = None;
+        }
+        false
+    }
+
+    /// Whether mapping `v` to `w` preserves the edges to the already mapped vertices
+    fn consistent(&self, v: usize, w: usize) -> bool {
+        let (adj_a, adj_b) = (&self.a.adjacency[v], &self.b.adjacency[w]);
+        let loops_match = adj_a.get(&v) == adj_b.get(&w);
+        let edges_match = adj_a.iter().all(|(&u, counts)| match self.forward[u] {
+            Some(x) => adj_b.get(&x) == Some(counts),
+            None => true,
+        });
+        let no_extra_edges = adj_b
+            .keys()
+            .all(|&x| x == w || self.backward[x].is_none_or(|u| adj_a.contains_key(&u)));
+        loops_match && edges_match && no_extra_edges
+    }
+}
+
+/// Number of vertices with each phase denominator.
+///
+/// Large denominators usually come from imported floating-point angles, see
+/// `transform::coarsen_phases`.
+pub fn phase_spectrum<G: Graph>(g: &G) -> BTreeMap<u64, u32> {
+    let mut spectrum = BTreeMap::new();
+    for v in g.vertices() {
+        *spectrum
+            .entry(g.vertex(v).unwrap().phase().denominator())
+            .or_insert(0) += 1;
+    }
+    spectrum
+}
+
+/// Result of exploring a local complementation orbit
+#[derive(Debug, Clone)]
+pub struct OrbitResult {
+    /// Number of distinct graphs found
+    pub size: usize,
+    /// Whether the whole orbit was explored, or the search stopped at the size limit
+    pub complete: bool,
+    /// A graph in the explored orbit with the minimum number of edges
+    pub representative: BitGraph,
+    /// Vertices to locally complement, in order, to go from the initial graph to the
+    /// representative
+    pub sequence: Vec<usize>,
+}
+
+/// Breadth-first exploration of the local complementation orbit of a graph, visiting at most
+/// `max_size` distinct graphs.
+pub fn lc_orbit(g: &BitGraph, max_size: usize) -> OrbitResult {
+    // Each visited graph with the graph and vertex it was reached from.
+    let mut parents: HashMap<BitGraph, Option<(BitGraph, usize)>> = HashMap::new();
+    parents.insert(g.clone(), None);
+    let mut queue = vec![g.clone()];
+    let mut best = g.clone();
+    let mut complete = true;
+    let mut i = 0;
+    'search: while i < queue.len() {
+        let current = queue[i].clone();
+        i += 1;
+        for v in 0..current.num_vertices() {
+            let mut next = current.clone();
+            next.local_complement(v);
+            if parents.contains_key(&next) {
+                continue;
+            }
+            if parents.len() >= max_size {
+                complete = false;
+                break 'search;
+            }
+            if next.num_edges() < best.num_edges() {
+                best = next.clone();
+            }
+            parents.insert(next.clone(), Some((current.clone(), v)));
+            queue.push(next);
+        }
+    }
+
+    let mut sequence = Vec::new();
+    let mut node = &best;
+    while let Some((parent, v)) = &parents[node] {
+        sequence.push(*v);
+        node = parent;
+    }
+    sequence.reverse();
+
+    OrbitResult {
+        size: parents.len(),
+        complete,
+        representative: best,
+        sequence,
+    }
+}
+
+/// Replace a graph with an element of its local complementation orbit with the fewest edges,
+/// exploring at most `max_size` graphs. Returns the applied complementations.
+pub fn minimize_edges_by_lc(g: &mut BitGraph, max_size: usize) -> Vec<usize> {
+    let orbit = lc_orbit(g, max_size);
+    *g = orbit.representative;
+    orbit.sequence
+}
+
+/// Split a diagram into its connected components, through edges of any kind.
+///
+/// Each component lists its vertices in the order of `Graph::vertices`, and the components are
+/// ordered by their first vertex. Inputs and outputs belong to the component of their wire, so
+/// `g.is_input` and `g.is_output` tell which boundaries each component gets.
+pub fn connected_components<G: Graph>(g: &G) -> Vec<Vec<G::VertexIx>>
+where
+    G::VertexData: Clone,
+    G::EdgeData: Clone,
+{
+    let pg = g.to_petgraph();
+    let mut components = UnionFind::new(pg.node_count());
+    for e in pg.edge_references() {
+        components.union(e.source().index(), e.target().index());
+    }
+    // Nodes of `pg` are numbered in the order of `g.vertices()`.
+    let mut res: Vec<Vec<G::VertexIx>> = Vec::new();
+    let mut position = HashMap::new();
+    for (i, v) in g.vertices().enumerate() {
+        let root = components.find(i);
+        let c = *position.entry(root).or_insert_with(|| {
+            res.push(Vec::new());
+            res.len() - 1
+        });
+        res[c].push(v);
+    }
+    res
+}
+
+/// Quick forecast of the resources needed by a diagram, see `resource_estimate`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ResourceReport {
+    /// Largest of the numbers of inputs and outputs
+    pub qubits: u32,
+    /// Spiders with a phase that is an odd multiple of pi/4, see `Graph::tcount`
+    pub t_count: u32,
+    /// H-boxes making a CCZ, see `zh::ccz_at`
+    pub ccz_count: u32,
+    /// Distinct target sets of the phase gadgets
+    pub gadget_parities: u32,
+    /// Forecast of the number of CNOTs of an extracted circuit
+    pub cnot_estimate: u32,
+    /// Number of layers of a minimal gflow, if the diagram is graph-like and has one
+    pub measurement_depth: Option<u32>,
+}
+
+impl fmt::Display for ResourceReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "qubits: {}", self.qubits)?;
+        writeln!(f, "T-count: {}", self.t_count)?;
+        writeln!(f, "CCZ-count: {}", self.ccz_count)?;
+        writeln!(f, "gadget parities: {}", self.gadget_parities)?;
+        writeln!(f, "estimated CNOTs: {}", self.cnot_estimate)?;
+        match self.measurement_depth {
+            Some(depth) => writeln!(f, "measurement depth: {}", depth),
+            None => writeln!(f, "measurement depth: no gflow"),
+        }
+    }
+}
+
+/// Number of frontier steps whose CNOT cost is computed by `cnot_estimate`
+const SAMPLED_STEPS: usize = 4;
+
+/// Forecast of the CNOTs needed to extract a circuit from a diagram, without extracting it.
+///
+/// The spiders are split into layers by their distance to the outputs, as successive frontiers
+/// of an extraction would be. For the first `SAMPLED_STEPS` pairs of consecutive layers, the
+/// row additions of the Gaussian elimination of their biadjacency matrix are counted, and the
+/// total is scaled to the number of pairs.
+fn cnot_estimate<G: Graph>(g: &G) -> u32 {
+    let spider = |v: &G::VertexIx| g.vertex(*v).unwrap().kind() != VertexKind::Boundary;
+    let layers: Vec<Vec<_>> = g
+        .bfs_layers(g.outputs())
+        .into_iter()
+        .map(|layer| layer.into_iter().filter(spider).collect::<Vec<_>>())
+        .filter(|layer| !layer.is_empty())
+        .collect();
+    let steps = layers.len().saturating_sub(1);
+    let sampled = steps.min(SAMPLED_STEPS);
+    let mut row_adds = 0;
+    for pair in layers.windows(2).take(sampled) {
+        let (frontier, next) = (&pair[0], &pair[1]);
+        let mut m = Mat2::from_fn(frontier.len(), next.len(), |r, c| {
+            g.connected(frontier[r], next[c])
+        });
+        let mut ops = Vec::new();
+        m.gauss(true, Some(&mut ops));
+        row_adds += ops.len();
+    }
+    match sampled {
+        0 => 0,
+        _ => ((row_adds * steps + sampled / 2) / sampled) as u32,
+    }
+}
+
+/// Estimate the resources of a diagram before optimizing or extracting it.
+///
+/// The counts are read off the diagram directly. The CNOT count is a forecast from the
+/// connectivity between successive frontiers, see `ResourceReport`, and the measurement depth is
+/// only computed for graph-like diagrams, such as those left by `simplify::full_reduce`.
+pub fn resource_estimate<G: Graph>(g: &G) -> ResourceReport {
+    let parities: BTreeSet<_> = gadgets(g).into_iter().map(|g| g.targets).collect();
+    ResourceReport {
+        qubits: g.inputs().count().max(g.outputs().count()) as u32,
+        t_count: g.tcount(),
+        ccz_count: g.vertices().filter(|&h| ccz_at(g, h).is_some()).count() as u32,
+        gadget_parities: parities.len() as u32,
+        cnot_estimate: cnot_estimate(g),
+        measurement_depth: if is_graph_like(g) {
+            gflow(g).map(|flow| flow.depth())
+        } else {
+            None
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::circuit::{Circuit, Gate};
+    use crate::zx::generate::random_clifford_circuit;
+    use crate::zx::random::Seed;
+    use crate::zx::simple_graph::GGraph;
+    use crate::zx::simplify::{clifford_simp, full_reduce};
+    use crate::zx::zh::ccz_to_gadgets;
+
+    /// `c` followed by its adjoint, fully reduced
+    fn reduced_with_adjoint(c: &Circuit) -> GGraph {
+        let mut g: GGraph = c.to_graph();
+        let mut adjoint = g.clone();
+        adjoint.adjoint();
+        g.compose(adjoint).unwrap();
+        full_reduce(&mut g);
+        g
+    }
+
+    #[test]
+    fn adjoint_composed_cliffords_are_identities() {
+        for seed in 0..10 {
+            let c = random_clifford_circuit(4, 40, Seed(seed));
+            let g = reduced_with_adjoint(&c);
+            assert_eq!(
+                as_wire_permutation(&g),
+                Some(vec![0, 1, 2, 3]),
+                "seed {}",
+                seed
+            );
+            assert!(is_identity_diagram(&g));
+        }
+    }
+
+    #[test]
+    fn leftover_phase_is_not_an_identity() {
+        let mut g: GGraph = random_clifford_circuit(3, 20, Seed(3)).to_graph();
+        let mut adjoint = g.clone();
+        adjoint.adjoint();
+        let mut t = Circuit::new(3);
+        t.add_gate(Gate::T(1));
+        g.compose(t.to_graph()).unwrap();
+        g.compose(adjoint).unwrap();
+        full_reduce(&mut g);
+        assert_eq!(as_wire_permutation(&g), None);
+        assert!(!is_identity_diagram(&g));
+    }
+
+    #[test]
+    fn swap_is_a_permutation() {
+        let mut c = Circuit::new(3);
+        for gate in [Gate::Cx(0, 2), Gate::Cx(2, 0), Gate::Cx(0, 2)] {
+            c.add_gate(gate);
+        }
+        let mut g: GGraph = c.to_graph();
+        full_reduce(&mut g);
+        assert_eq!(as_wire_permutation(&g), Some(vec![2, 1, 0]));
+        assert!(!is_identity_diagram(&g));
+    }
+
+    #[test]
+    fn chains_of_identity_spiders() {
+        let mut g: GGraph = GGraph::new();
+        let i = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        let a = g.add_vertex(VertexKind::Z, Phase::zero());
+        let b = g.add_vertex(VertexKind::X, Phase::zero());
+        let o = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        g.add_edge(i, a, EdgeKind::Hadam);
+        g.add_edge(a, b, EdgeKind::Regular);
+        let e = g.add_edge(b, o, EdgeKind::Hadam).unwrap();
+        g.set_input(i, true);
+        g.set_output(o, true);
+        assert!(is_identity_diagram(&g));
+
+        // An odd number of Hadamards is not a wire.
+        g.set_edge_kind(e, EdgeKind::Regular);
+        assert_eq!(as_wire_permutation(&g), None);
+        g.set_edge_kind(e, EdgeKind::Hadam);
+
+        // Neither is a spider with a phase or an extra leg.
+        g.set_phase(a, Phase::pi());
+        assert_eq!(as_wire_permutation(&g), None);
+        g.set_phase(a, Phase::zero());
+        let c = g.add_vertex(VertexKind::Z, Phase::zero());
+        g.add_edge(a, c, EdgeKind::Regular);
+        assert_eq!(as_wire_permutation(&g), None);
+    }
+
+    #[test]
+    fn bare_wires() {
+        let mut g: GGraph = GGraph::new();
+        let v = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        g.set_input(v, true);
+        g.set_output(v, true);
+        assert!(is_identity_diagram(&g));
+        g.add_vertex(VertexKind::Z, Phase::zero());
+        assert_eq!(as_wire_permutation(&g), None);
+    }
+
+    /// The graph on `n` vertices with the given edges
+    fn bit_graph(n: usize, edges: &[(usize, usize)]) -> BitGraph {
+        let mut g = BitGraph::new(n);
+        for &(v, u) in edges {
+            g.add_edge(v, u);
+        }
+        g
+    }
+
+    fn complete_graph(n: usize) -> BitGraph {
+        let edges: Vec<_> = (0..n)
+            .flat_map(|v| (v + 1..n).map(move |u| (v, u)))
+            .collect();
+        bit_graph(n, &edges)
+    }
+
+    /// Apply the complementations of an orbit result to `g`
+    fn replay(g: &BitGraph, sequence: &[usize]) -> BitGraph {
+        let mut g = g.clone();
+        for &v in sequence {
+            g.local_complement(v);
+        }
+        g
+    }
+
+    #[test]
+    fn lc_orbit_sizes() {
+        let c5 = bit_graph(5, &[(0, 1), (1, 2), (2, 3), (3, 4), (4, 0)]);
+        let orbit = lc_orbit(&c5, 1000);
+        assert!(orbit.complete);
+        assert_eq!(orbit.size, 132);
+        assert_eq!(orbit.representative.num_edges(), 5);
+        assert_eq!(replay(&c5, &orbit.sequence), orbit.representative);
+
+        let p4 = bit_graph(4, &[(0, 1), (1, 2), (2, 3)]);
+        let orbit = lc_orbit(&p4, 1000);
+        assert!(orbit.complete);
+        assert_eq!(orbit.size, 11);
+        assert_eq!(orbit.representative.num_edges(), 3);
+
+        // The complete graph and the n stars.
+        for n in 3..6 {
+            assert_eq!(lc_orbit(&complete_graph(n), 1000).size, n + 1);
+        }
+    }
+
+    #[test]
+    fn lc_orbit_size_limit() {
+        let c5 = bit_graph(5, &[(0, 1), (1, 2), (2, 3), (3, 4), (4, 0)]);
+        let orbit = lc_orbit(&c5, 10);
+        assert!(!orbit.complete);
+        assert_eq!(orbit.size, 10);
+        assert_eq!(replay(&c5, &orbit.sequence), orbit.representative);
+    }
+
+    #[test]
+    fn complete_graph_minimizes_to_star() {
+        let n = 6;
+        let k = complete_graph(n);
+        let mut g = k.clone();
+        let sequence = minimize_edges_by_lc(&mut g, 1000);
+        assert_eq!(sequence.len(), 1);
+        assert_eq!(g.num_edges(), n as u32 - 1);
+        let centre = sequence[0];
+        for v in 0..n {
+            let expected = if v == centre {
+                ((1u64 << n) - 1) & !(1 << v)
+            } else {
+                1 << centre
+            };
+            assert_eq!(g.neighbours(v), expected);
+        }
+        assert_eq!(replay(&k, &sequence), g);
+    }
+
+    /// A circuit diagram with every kind of vertex and edge
+    fn circuit_diagram() -> GGraph {
+        let mut c = Circuit::new(3);
+        for gate in [
+            Gate::T(0),
+            Gate::H(1),
+            Gate::Cx(0, 1),
+            Gate::Ccz(0, 1, 2),
+            Gate::Rz(2, Phase::new(3, 4)),
+            Gate::Cz(1, 2),
+            Gate::H(0),
+        ] {
+            c.add_gate(gate);
+        }
+        c.to_graph()
+    }
+
+    /// Copy of a diagram with its vertices added in the order given by `order`, a permutation of
+    /// its vertex indices
+    fn permuted(g: &GGraph, order: &[usize]) -> GGraph {
+        let vs: Vec<_> = g.vertices().collect();
+        let mut h: GGraph = GGraph::new();
+        let mut map = HashMap::new();
+        for &i in order {
+            let vdata = g.vertex(vs[i]).unwrap();
+            map.insert(vs[i], h.add_vertex(vdata.kind(), vdata.phase()));
+        }
+        for (v, u, edata) in g.edges_with_data() {
+            h.add_edge(map[&v], map[&u], edata.kind());
+        }
+        for v in g.inputs() {
+            h.set_input(map[&v], true);
+        }
+        for v in g.outputs() {
+            h.set_output(map[&v], true);
+        }
+        h
+    }
+
+    #[test]
+    fn isomorphic_in_any_vertex_order() {
+        let g = circuit_diagram();
+        let n = g.num_vertices() as usize;
+        let reversed: Vec<_> = (0..n).rev().collect();
+        // Multiplying by a unit modulo `n` permutes the indices.
+        assert_ne!(n % 7, 0);
+        let shuffled: Vec<_> = (0..n).map(|i| (i * 7) % n).collect();
+        for order in [reversed, shuffled] {
+            let h = permuted(&g, &order);
+            assert!(is_isomorphic(&g, &h));
+            assert!(is_isomorphic(&h, &g));
+        }
+    }
+
+    #[test]
+    fn near_misses() {
+        let g = circuit_diagram();
+        let reversed: Vec<_> = (0..g.num_vertices() as usize).rev().collect();
+
+        // One phase differs
+        let mut h = permuted(&g, &reversed);
+        let t = h
+            .vertices()
+            .find(|&v| h.vertex(v).unwrap().phase() == Phase::new(1, 4))
+            .unwrap();
+        h.set_phase(t, Phase::new(3, 4));
+        assert!(!is_isomorphic(&g, &h));
+
+        // One edge kind differs
+        let mut h = permuted(&g, &reversed);
+        let e = h
+            .edges()
+            .find(|&e| h.edge(e).unwrap().kind() == EdgeKind::Hadam)
+            .unwrap();
+        let (v, u) = h.edge_endpoints(e).unwrap();
+        h.remove_edge(e);
+        h.add_edge(v, u, EdgeKind::Regular);
+        assert!(!is_isomorphic(&g, &h));
+
+        // The same outputs, in a different order
+        let mut h = permuted(&g, &reversed);
+        let outputs: Vec<_> = h.outputs().collect();
+        for &o in &outputs {
+            h.set_output(o, false);
+        }
+        for &o in [outputs[1], outputs[0], outputs[2]].iter() {
+            h.set_output(o, true);
+        }
+        assert!(!is_isomorphic(&g, &h));
+    }
+
+    fn report(
+        qubits: u32,
+        [t_count, ccz_count, gadget_parities, cnot_estimate]: [u32; 4],
+        measurement_depth: Option<u32>,
+    ) -> ResourceReport {
+        ResourceReport {
+            qubits,
+            t_count,
+            ccz_count,
+            gadget_parities,
+            cnot_estimate,
+            measurement_depth,
+        }
+    }
+
+    #[test]
+    fn toffoli_resources() {
+        let c = Circuit::from_qasm(include_str!("../../tests/fixtures/toffoli.qasm")).unwrap();
+        let g: GGraph = c.to_graph();
+        assert_eq!(resource_estimate(&g), report(3, [7, 0, 0, 11], None));
+        // Graph-like, and measurable in the XY plane.
+        let mut clifford = g.clone();
+        clifford_simp(&mut clifford);
+        assert_eq!(
+            resource_estimate(&clifford),
+            report(3, [7, 0, 0, 9], Some(8))
+        );
+        // The phase gadgets have no gflow in the XY plane.
+        let mut reduced = g.clone();
+        full_reduce(&mut reduced);
+        assert_eq!(resource_estimate(&reduced), report(3, [7, 0, 4, 4], None));
+        assert_eq!(
+            resource_estimate(&reduced).to_string(),
+            "qubits: 3\nT-count: 7\nCCZ-count: 0\ngadget parities: 4\nestimated CNOTs: 4\n\
+             measurement depth: no gflow\n"
+        );
+    }
+
+    #[test]
+    fn ccz_ladder_resources() {
+        let c = Circuit::from_qasm(include_str!("../../tests/fixtures/ccz_ladder.qasm")).unwrap();
+        let mut g: GGraph = c.to_graph();
+        assert_eq!(resource_estimate(&g), report(4, [3, 3, 0, 8], None));
+        ccz_to_gadgets(&mut g);
+        full_reduce(&mut g);
+        assert_eq!(resource_estimate(&g), report(4, [12, 0, 6, 25], None));
+    }
+}
@@ -0,0 +1,99 @@
+//! Compact simple graphs for graph-state manipulations.
+
+use crate::zx::graph::*;
+use std::collections::HashMap;
+
+/// Simple undirected graph on at most 64 vertices, with one adjacency bitset per vertex.
+///
+/// Used to represent graph states, where local complementations become a few word operations.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BitGraph {
+    adj: Vec<u64>,
+}
+
+impl BitGraph {
+    /// Maximum number of vertices
+    pub const MAX_VERTICES: usize = 64;
+
+    /// Graph with `n` vertices and no edges
+    pub fn new(n: usize) -> Self {
+        assert!(
+            n <= Self::MAX_VERTICES,
+            "BitGraph supports at most 64 vertices"
+        );
+        BitGraph { adj: vec![0; n] }
+    }
+
+    /// Graph on the Z spiders of a diagram, with an edge for each Hadamard edge between them.
+    ///
+    /// Returns the graph and the diagram vertex of each `BitGraph` vertex.
+    pub fn from_hadamard_edges<G: Graph>(g: &G) -> (Self, Vec<G::VertexIx>) {
+        let spiders: Vec<_> = g
+            .vertices()
+            .filter(|&v| g.vertex(v).unwrap().kind() == VertexKind::Z)
+            .collect();
+        let index: HashMap<_, _> = spiders.iter().enumerate().map(|(i, &v)| (v, i)).collect();
+        let mut res = BitGraph::new(spiders.len());
+        for e in g.edges() {
+            if g.edge(e).unwrap().kind() != EdgeKind::Hadam {
+                continue;
+            }
+            let (v, u) = g.edge_endpoints(e).unwrap();
+            if let (Some(&a), Some(&b)) = (index.get(&v), index.get(&u)) {
+                if a != b {
+                    res.add_edge(a, b);
+                }
+            }
+        }
+        (res, spiders)
+    }
+
+    pub fn num_vertices(&self) -> usize {
+        self.adj.len()
+    }
+
+    pub fn num_edges(&self) -> u32 {
+        self.adj.iter().map(|row| row.count_ones()).sum::<u32>() / 2
+    }
+
+    /// Neighbourhood of a vertex as a bitset
+    pub fn neighbours(&self, v: usize) -> u64 {
+        self.adj[v]
+    }
+
+    pub fn has_edge(&self, v: usize, u: usize) -> bool {
+        self.adj[v] & (1 << u) != 0
+    }
+
+    pub fn add_edge(&mut self, v: usize, u: usize) {
+        assert_ne!(v, u, "BitGraph does not support self-loops");
+        self.adj[v] |= 1 << u;
+        self.adj[u] |= 1 << v;
+    }
+
+    pub fn remove_edge(&mut self, v: usize, u: usize) {
+        self.adj[v] &= !(1 << u);
+        self.adj[u] &= !(1 << v);
+    }
+
+    /// Edges as pairs `(v, u)` with `v < u`
+    pub fn edges(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (0..self.adj.len()).flat_map(move |v| {
+            (v + 1..self.adj.len())
+                .filter(move |&u| self.has_edge(v, u))
+                .map(move |u| (v, u))
+        })
+    }
+
+    /// Complement the edges between the neighbours of `v`.
+    pub fn local_complement(&mut self, v: usize) {
+        let n = self.adj[v];
+        let mut rest = n;
+        while rest != 0 {
+            let u = rest.trailing_zeros() as usize;
+            rest &= rest - 1;
+            // Toggle the edges to the other neighbours, without creating a self-loop.
+            self.adj[u] ^= n & !(1 << u);
+        }
+    }
+}
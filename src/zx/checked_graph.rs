@@ -0,0 +1,468 @@
+use crate::zx::graph::*;
+use std::collections::{HashMap, HashSet};
+
+/// Vertex handle of a `CheckedGraph`, tagging the backend index with the generation of its slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CheckedIx<V> {
+    pub ix: V,
+    pub generation: u32,
+}
+
+/// Debugging wrapper over any backend that detects the use of stale vertex handles.
+///
+/// The generation of a slot is bumped whenever its vertex is removed, or replaced by another
+/// vertex moved there by the backend (as `petgraph::Graph::remove_node` does). Using a handle
+/// from an older generation panics, instead of silently pointing at a different vertex.
+///
+/// Edge indices are passed through unchecked.
+#[derive(Debug, Clone, Default)]
+pub struct CheckedGraph<G: Graph> {
+    g: G,
+    generations: HashMap<G::VertexIx, u32>,
+    live: HashSet<G::VertexIx>,
+}
+
+impl<G: Graph> CheckedGraph<G> {
+    /// Wrap a graph. Every existing vertex starts at generation 0.
+    pub fn new(g: G) -> Self {
+        let live = g.vertices().collect();
+        CheckedGraph {
+            g,
+            generations: HashMap::new(),
+            live,
+        }
+    }
+
+    /// The wrapped graph
+    pub fn inner(&self) -> &G {
+        &self.g
+    }
+
+    /// Unwrap the graph, dropping the generation information
+    pub fn into_inner(self) -> G {
+        self.g
+    }
+
+    fn generation(&self, ix: G::VertexIx) -> u32 {
+        self.generations.get(&ix).copied().unwrap_or(0)
+    }
+
+    fn checked(&self, ix: G::VertexIx) -> CheckedIx<G::VertexIx> {
+        CheckedIx {
+            ix,
+            generation: self.generation(ix),
+        }
+    }
+
+    /// Backend index of a handle, panicking if it is stale.
+    fn raw(&self, v: CheckedIx<G::VertexIx>) -> G::VertexIx {
+        let current = self.generation(v.ix);
+        if v.generation != current {
+            panic!(
+                "stale vertex handle {:?}: generation {} vs current {}",
+                v.ix, v.generation, current
+            );
+        }
+        v.ix
+    }
+
+    fn bump(&mut self, ix: G::VertexIx) {
+        *self.generations.entry(ix).or_insert(0) += 1;
+    }
+
+    /// Register any vertex added by a bulk operation on the backend.
+    fn sync_live(&mut self) {
+        self.live.extend(self.g.vertices());
+    }
+}
+
+/// Iterator wrapping backend indices into checked handles
+pub struct CheckedIter<'a, I, V> {
+    iter: I,
+    generations: &'a HashMap<V, u32>,
+}
+
+impl<I, V> Iterator for CheckedIter<'_, I, V>
+where
+    I: Iterator<Item = V>,
+    V: Copy + Eq + std::hash::Hash,
+{
+    type Item = CheckedIx<V>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let ix = self.iter.next()?;
+        Some(CheckedIx {
+            ix,
+            generation: self.generations.get(&ix).copied().unwrap_or(0),
+        })
+    }
+}
+
+/// Iterator wrapping the endpoints of edges with data into checked handles
+pub struct CheckedEdgeIter<'a, I, V> {
+    iter: I,
+    generations: &'a HashMap<V, u32>,
+}
+
+impl<'a, I, V, D> Iterator for CheckedEdgeIter<'a, I, V>
+where
+    I: Iterator<Item = (V, V, &'a D)>,
+    V: Copy + Eq + std::hash::Hash,
+    D: 'a,
+{
+    type Item = (CheckedIx<V>, CheckedIx<V>, &'a D);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (a, b, data) = self.iter.next()?;
+        let checked = |ix| CheckedIx {
+            ix,
+            generation: self.generations.get(&ix).copied().unwrap_or(0),
+        };
+        Some((checked(a), checked(b), data))
+    }
+}
+
+/// Iterator wrapping the neighbours of incident edges into checked handles
+pub struct CheckedIncidentIter<'a, I, V> {
+    iter: I,
+    generations: &'a HashMap<V, u32>,
+}
+
+impl<I, V, E> Iterator for CheckedIncidentIter<'_, I, V>
+where
+    I: Iterator<Item = (E, V)>,
+    V: Copy + Eq + std::hash::Hash,
+{
+    type Item = (E, CheckedIx<V>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (e, ix) = self.iter.next()?;
+        Some((
+            e,
+            CheckedIx {
+                ix,
+                generation: self.generations.get(&ix).copied().unwrap_or(0),
+            },
+        ))
+    }
+}
+
+impl<G: Graph> Graph for CheckedGraph<G> {
+    type VertexIx = CheckedIx<G::VertexIx>;
+    type EdgeIx = G::EdgeIx;
+    type VertexData = G::VertexData;
+    type EdgeData = G::EdgeData;
+    type EdgeIterator<'a>
+        = G::EdgeIterator<'a>
+    where
+        Self: 'a;
+    type VertexIterator<'a>
+        = CheckedIter<'a, G::VertexIterator<'a>, G::VertexIx>
+    where
+        Self: 'a;
+    type NeighbourIterator<'a>
+        = CheckedIter<'a, G::NeighbourIterator<'a>, G::VertexIx>
+    where
+        Self: 'a;
+    type IncidentEdgeIterator<'a>
+        = G::IncidentEdgeIterator<'a>
+    where
+        Self: 'a;
+    type EdgeDataIterator<'a>
+        = CheckedEdgeIter<'a, G::EdgeDataIterator<'a>, G::VertexIx>
+    where
+        Self: 'a;
+    type IncidentNeighbourIterator<'a>
+        = CheckedIncidentIter<'a, G::IncidentNeighbourIterator<'a>, G::VertexIx>
+    where
+        Self: 'a;
+    type NeighbourKindIterator<'a>
+        = NeighboursWithKind<'a, Self>
+    where
+        Self: 'a;
+    type InputIterator<'a>
+        = CheckedIter<'a, G::InputIterator<'a>, G::VertexIx>
+    where
+        Self: 'a;
+    type OutputIterator<'a>
+        = CheckedIter<'a, G::OutputIterator<'a>, G::VertexIx>
+    where
+        Self: 'a;
+
+    const BACKEND: &'static str = G::BACKEND;
+
+    fn set_input(&mut self, v: Self::VertexIx, flag: bool) {
+        let v = self.raw(v);
+        self.g.set_input(v, flag)
+    }
+
+    fn set_output(&mut self, v: Self::VertexIx, flag: bool) {
+        let v = self.raw(v);
+        self.g.set_output(v, flag)
+    }
+
+    fn is_input(&self, v: Self::VertexIx) -> bool {
+        self.g.is_input(self.raw(v))
+    }
+
+    fn is_output(&self, v: Self::VertexIx) -> bool {
+        self.g.is_output(self.raw(v))
+    }
+
+    fn inputs(&self) -> Self::InputIterator<'_> {
+        CheckedIter {
+            iter: self.g.inputs(),
+            generations: &self.generations,
+        }
+    }
+
+    fn outputs(&self) -> Self::OutputIterator<'_> {
+        CheckedIter {
+            iter: self.g.outputs(),
+            generations: &self.generations,
+        }
+    }
+
+    fn num_vertices(&self) -> u32 {
+        self.g.num_vertices()
+    }
+
+    fn num_edges(&self) -> u32 {
+        self.g.num_edges()
+    }
+
+    fn vertices(&self) -> Self::VertexIterator<'_> {
+        CheckedIter {
+            iter: self.g.vertices(),
+            generations: &self.generations,
+        }
+    }
+
+    fn edges(&self) -> Self::EdgeIterator<'_> {
+        self.g.edges()
+    }
+
+    fn edges_with_data(&self) -> Self::EdgeDataIterator<'_> {
+        CheckedEdgeIter {
+            iter: self.g.edges_with_data(),
+            generations: &self.generations,
+        }
+    }
+
+    fn vertex(&self, v: Self::VertexIx) -> Option<&Self::VertexData> {
+        self.g.vertex(self.raw(v))
+    }
+
+    fn vertex_mut(&mut self, v: Self::VertexIx) -> Option<&mut Self::VertexData> {
+        let v = self.raw(v);
+        self.g.vertex_mut(v)
+    }
+
+    fn edge(&self, e: Self::EdgeIx) -> Option<&Self::EdgeData> {
+        self.g.edge(e)
+    }
+
+    fn edge_mut(&mut self, e: Self::EdgeIx) -> Option<&mut Self::EdgeData> {
+        self.g.edge_mut(e)
+    }
+
+    fn scalar(&self) -> &Scalar {
+        self.g.scalar()
+    }
+
+    fn scalar_mut(&mut self) -> &mut Scalar {
+        self.g.scalar_mut()
+    }
+
+    fn edge_endpoints(&self, e: Self::EdgeIx) -> Option<(Self::VertexIx, Self::VertexIx)> {
+        let (v, u) = self.g.edge_endpoints(e)?;
+        Some((self.checked(v), self.checked(u)))
+    }
+
+    fn vertex_degree(&self, v: Self::VertexIx) -> u32 {
+        self.g.vertex_degree(self.raw(v))
+    }
+
+    fn neighbours(&self, v: Self::VertexIx) -> Self::NeighbourIterator<'_> {
+        CheckedIter {
+            iter: self.g.neighbours(self.raw(v)),
+            generations: &self.generations,
+        }
+    }
+
+    fn incident_edges(&self, v: Self::VertexIx) -> Self::IncidentEdgeIterator<'_> {
+        self.g.incident_edges(self.raw(v))
+    }
+
+    fn incident_edges_with_neighbours(
+        &self,
+        v: Self::VertexIx,
+    ) -> Self::IncidentNeighbourIterator<'_> {
+        CheckedIncidentIter {
+            iter: self.g.incident_edges_with_neighbours(self.raw(v)),
+            generations: &self.generations,
+        }
+    }
+
+    fn neighbours_with_kind(&self, v: Self::VertexIx) -> Self::NeighbourKindIterator<'_> {
+        NeighboursWithKind::new(self, v)
+    }
+
+    fn connected(&self, v: Self::VertexIx, u: Self::VertexIx) -> bool {
+        self.g.connected(self.raw(v), self.raw(u))
+    }
+
+    fn edge_between(&self, v: Self::VertexIx, u: Self::VertexIx) -> Option<Self::EdgeIx> {
+        self.g.edge_between(self.raw(v), self.raw(u))
+    }
+
+    fn is_ground(&self, v: Self::VertexIx) -> bool {
+        self.g.is_ground(self.raw(v))
+    }
+
+    fn adjoint(&mut self) {
+        self.g.adjoint()
+    }
+
+    /// Composition may remove and renumber vertices, so it invalidates every existing handle.
+    fn compose(&mut self, other: Self) -> Result<(), ComposeError> {
+        self.g.compose(other.g)?;
+        for v in std::mem::take(&mut self.live) {
+            self.bump(v);
+        }
+        self.sync_live();
+        Ok(())
+    }
+
+    fn tensor(&mut self, other: Self) {
+        self.g.tensor(other.g);
+        self.sync_live();
+    }
+
+    fn add_vertices(&mut self, count: u32) -> Vec<Self::VertexIx> {
+        let vs = self.g.add_vertices(count);
+        self.live.extend(vs.iter().copied());
+        vs.into_iter().map(|v| self.checked(v)).collect()
+    }
+
+    fn mode(&self) -> GraphMode {
+        self.g.mode()
+    }
+
+    fn add_edge(
+        &mut self,
+        v: Self::VertexIx,
+        u: Self::VertexIx,
+        ty: EdgeKind,
+    ) -> Option<Self::EdgeIx> {
+        let (v, u) = (self.raw(v), self.raw(u));
+        self.g.add_edge(v, u, ty)
+    }
+
+    fn remove_vertex(&mut self, v: Self::VertexIx) {
+        let v = self.raw(v);
+        self.g.remove_vertex(v);
+        self.bump(v);
+        if self.g.vertex(v).is_none() {
+            self.live.remove(&v);
+            return;
+        }
+        // The backend moved another vertex into the slot. Its old slot is no longer present.
+        let present: HashSet<_> = self.g.vertices().collect();
+        let vanished: Vec<_> = self
+            .live
+            .iter()
+            .copied()
+            .filter(|w| !present.contains(w))
+            .collect();
+        for w in vanished {
+            self.bump(w);
+            self.live.remove(&w);
+        }
+    }
+
+    fn remove_edge(&mut self, e: Self::EdgeIx) {
+        self.g.remove_edge(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::vec_graph::VecGraph;
+
+    /// `in - Z - Z - Z - out`, returning the spiders
+    fn wire<G: Graph + Default>() -> (G, Vec<G::VertexIx>) {
+        let mut g = G::default();
+        let i = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        let zs: Vec<_> = (0..3)
+            .map(|_| g.add_vertex(VertexKind::Z, Phase::new(1, 4)))
+            .collect();
+        let o = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        for pair in [i, zs[0], zs[1], zs[2], o].windows(2) {
+            g.add_edge(pair[0], pair[1], EdgeKind::Regular);
+        }
+        g.set_input(i, true);
+        g.set_output(o, true);
+        (g, zs)
+    }
+
+    /// A fusion pass with the usual bug: the worklist is collected up front, so it still holds
+    /// the spiders fused away, and a vertex added in the meantime reuses their slot. Returns the
+    /// phases found at the worklist entries.
+    fn fuse_worklist<G: Graph>(g: &mut G, worklist: &[G::VertexIx]) -> Vec<Phase> {
+        let mut phases = Vec::new();
+        for &v in worklist {
+            phases.push(g.vertex(v).unwrap().phase());
+            let next = g
+                .neighbours(v)
+                .find(|&n| g.vertex(n).unwrap().kind() == VertexKind::Z);
+            if let Some(u) = next {
+                let phase = g.vertex(v).unwrap().phase() + g.vertex(u).unwrap().phase();
+                let outside: Vec<_> = g.neighbours(u).filter(|&n| n != v).collect();
+                g.remove_vertex(u);
+                for n in outside {
+                    g.add_edge(v, n, EdgeKind::Regular);
+                }
+                g.set_phase(v, phase);
+                g.add_vertex(VertexKind::X, Phase::pi());
+            }
+        }
+        phases
+    }
+
+    #[test]
+    fn stale_handles_go_unnoticed_without_checks() {
+        let (mut g, zs) = wire::<VecGraph>();
+        // The second entry silently reads the X spider that took the slot of the fused one.
+        let phases = fuse_worklist(&mut g, &zs);
+        assert_eq!(phases[1], Phase::pi());
+    }
+
+    #[test]
+    #[should_panic(expected = "stale vertex handle")]
+    fn stale_worklist_entry_is_caught() {
+        let (g, _) = wire::<VecGraph>();
+        let mut g = CheckedGraph::new(g);
+        let zs: Vec<_> = g
+            .vertices()
+            .filter(|&v| g.vertex(v).unwrap().kind() == VertexKind::Z)
+            .collect();
+        fuse_worklist(&mut g, &zs);
+    }
+
+    #[test]
+    #[should_panic(expected = "generation 0 vs current 3")]
+    fn generations_in_the_message() {
+        let (g, _) = wire::<VecGraph>();
+        let mut g = CheckedGraph::new(g);
+        let v = g.vertices().nth(1).unwrap();
+        // Each removal bumps the generation of the slot, which the next vertex reuses.
+        for _ in 0..3 {
+            let w = g.vertices().nth(1).unwrap();
+            g.remove_vertex(w);
+            g.add_vertex(VertexKind::Z, Phase::zero());
+        }
+        g.vertex(v);
+    }
+}
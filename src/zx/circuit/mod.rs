@@ -0,0 +1,688 @@
+//! Quantum circuits, and their translation into diagrams.
+
+pub mod optimize;
+pub mod verify;
+
+use crate::zx::graph::*;
+use crate::zx::io::qasm::{self, QasmError};
+use crate::zx::io::qc::{self, QcError};
+use crate::zx::io::quipper::{self, QuipperError};
+use crate::zx::io::sink::{Builder, GraphSink, IoFlags};
+use crate::zx::io::span::Diagnostic;
+use std::path::Path;
+
+/// Gate of a circuit, acting on qubits numbered from 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Gate {
+    H(u32),
+    X(u32),
+    Z(u32),
+    S(u32),
+    Sdg(u32),
+    T(u32),
+    Tdg(u32),
+    /// Rotation around the Z axis, by an angle given as a phase
+    Rz(u32, Phase),
+    /// Rotation around the X axis, by an angle given as a phase
+    Rx(u32, Phase),
+    /// Controlled NOT, with the control first
+    Cx(u32, u32),
+    Cz(u32, u32),
+    /// Controlled phase, adding the phase to the states where both qubits are set
+    CPhase(u32, u32, Phase),
+    Ccz(u32, u32, u32),
+    /// Toffoli, with the two controls first
+    Ccx(u32, u32, u32),
+    Swap(u32, u32),
+}
+
+/// Gate sets that `Circuit::decompose` can lower circuits to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GateSet {
+    /// Single-qubit gates, CX and CZ. CCZ and Toffoli gates become Clifford+T, and controlled
+    /// phases Z rotations.
+    CliffordT,
+    /// Only H, CX and `Rz`. The other rotations are written as `Rz`, between H gates for the X
+    /// rotations.
+    CxHRz,
+}
+
+impl Gate {
+    /// Qubits the gate acts on
+    pub fn qubits(&self) -> Vec<u32> {
+        match *self {
+            Gate::H(q)
+            | Gate::X(q)
+            | Gate::Z(q)
+            | Gate::S(q)
+            | Gate::Sdg(q)
+            | Gate::T(q)
+            | Gate::Tdg(q)
+            | Gate::Rz(q, _)
+            | Gate::Rx(q, _) => vec![q],
+            Gate::Cx(a, b) | Gate::Cz(a, b) | Gate::CPhase(a, b, _) | Gate::Swap(a, b) => {
+                vec![a, b]
+            }
+            Gate::Ccz(a, b, c) | Gate::Ccx(a, b, c) => vec![a, b, c],
+        }
+    }
+
+    /// The spider implementing a single-qubit rotation, as its qubit, kind and phase.
+    ///
+    /// Returns `None` for the Hadamard gate and for multi-qubit gates.
+    pub fn as_spider(&self) -> Option<(u32, VertexKind, Phase)> {
+        match *self {
+            Gate::X(q) => Some((q, VertexKind::X, Phase::pi())),
+            Gate::Z(q) => Some((q, VertexKind::Z, Phase::pi())),
+            Gate::S(q) => Some((q, VertexKind::Z, Phase::new(1, 2))),
+            Gate::Sdg(q) => Some((q, VertexKind::Z, Phase::new(-1, 2))),
+            Gate::T(q) => Some((q, VertexKind::Z, Phase::new(1, 4))),
+            Gate::Tdg(q) => Some((q, VertexKind::Z, Phase::new(-1, 4))),
+            Gate::Rz(q, phase) => Some((q, VertexKind::Z, phase)),
+            Gate::Rx(q, phase) => Some((q, VertexKind::X, phase)),
+            _ => None,
+        }
+    }
+
+    /// Gates applying X to `target` when all the `controls` are set: X, CX or a Toffoli, which
+    /// is written as a CCZ between Hadamard gates on the target. `None` with more than two
+    /// controls.
+    pub fn controlled_x(controls: &[u32], target: u32) -> Option<Vec<Gate>> {
+        match *controls {
+            [] => Some(vec![Gate::X(target)]),
+            [c] => Some(vec![Gate::Cx(c, target)]),
+            [a, b] => Some(vec![
+                Gate::H(target),
+                Gate::Ccz(a, b, target),
+                Gate::H(target),
+            ]),
+            _ => None,
+        }
+    }
+
+    /// Gates of `set` implementing this gate, exactly.
+    ///
+    /// Each CCZ becomes seven T or T-dagger gates and six CNOTs, a Toffoli the same between H
+    /// gates on the target, and each swap three CNOTs. A controlled phase `θ` becomes a `θ/2`
+    /// rotation on each qubit and a `-θ/2` rotation on the target between two CNOTs.
+    pub fn decompose(&self, set: GateSet) -> Vec<Gate> {
+        let mut gates = Vec::new();
+        self.decompose_into(set, &mut gates);
+        gates
+    }
+
+    fn decompose_into(&self, set: GateSet, gates: &mut Vec<Gate>) {
+        let composite = match *self {
+            Gate::Ccz(a, b, c) => vec![
+                Gate::Cx(b, c),
+                Gate::Tdg(c),
+                Gate::Cx(a, c),
+                Gate::T(c),
+                Gate::Cx(b, c),
+                Gate::Tdg(c),
+                Gate::Cx(a, c),
+                Gate::T(b),
+                Gate::T(c),
+                Gate::Cx(a, b),
+                Gate::T(a),
+                Gate::Tdg(b),
+                Gate::Cx(a, b),
+            ],
+            Gate::Ccx(a, b, c) => vec![Gate::H(c), Gate::Ccz(a, b, c), Gate::H(c)],
+            Gate::Swap(a, b) => vec![Gate::Cx(a, b), Gate::Cx(b, a), Gate::Cx(a, b)],
+            Gate::CPhase(a, b, phase) => {
+                // Any half works, since only twice its value matters.
+                let half = Phase::new(phase.numerator() as i64, 2 * phase.denominator());
+                vec![
+                    Gate::Rz(a, half),
+                    Gate::Rz(b, half),
+                    Gate::Cx(a, b),
+                    Gate::Rz(b, -half),
+                    Gate::Cx(a, b),
+                ]
+            }
+            Gate::Cz(a, b) if set == GateSet::CxHRz => vec![Gate::H(b), Gate::Cx(a, b), Gate::H(b)],
+            gate => {
+                match (set, gate.as_spider()) {
+                    (GateSet::CxHRz, Some((q, VertexKind::Z, phase))) => {
+                        gates.push(Gate::Rz(q, phase))
+                    }
+                    (GateSet::CxHRz, Some((q, _, phase))) => {
+                        gates.extend([Gate::H(q), Gate::Rz(q, phase), Gate::H(q)])
+                    }
+                    _ => gates.push(gate),
+                }
+                return;
+            }
+        };
+        for gate in composite {
+            gate.decompose_into(set, gates);
+        }
+    }
+
+    /// Gates applying Z to `target` when all the `controls` are set: Z, CZ or CCZ. `None` with
+    /// more than two controls.
+    pub fn controlled_z(controls: &[u32], target: u32) -> Option<Vec<Gate>> {
+        match *controls {
+            [] => Some(vec![Gate::Z(target)]),
+            [c] => Some(vec![Gate::Cz(c, target)]),
+            [a, b] => Some(vec![Gate::Ccz(a, b, target)]),
+            _ => None,
+        }
+    }
+}
+
+/// Text formats of circuits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitFormat {
+    /// OpenQASM 2.0, see `io::qasm`
+    Qasm,
+    /// Quipper ASCII, see `io::quipper`
+    Quipper,
+    /// `.qc`, see `io::qc`
+    Qc,
+}
+
+impl CircuitFormat {
+    /// Format of a file from its extension: `qasm`, `quipper` or `quip`, or `qc`
+    pub fn from_path(path: impl AsRef<Path>) -> Option<Self> {
+        match path.as_ref().extension()?.to_str()? {
+            "qasm" => Some(CircuitFormat::Qasm),
+            "quipper" | "quip" => Some(CircuitFormat::Quipper),
+            "qc" => Some(CircuitFormat::Qc),
+            _ => None,
+        }
+    }
+
+    /// Guess the format of a circuit from its first line that is not blank or a comment:
+    /// `OPENQASM` starts a QASM program, `Inputs:` a Quipper circuit, and a directive like `.v`
+    /// or `BEGIN` a `.qc` circuit.
+    pub fn sniff(src: &str) -> Option<Self> {
+        let line = src
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with("//") && !line.starts_with('#'))?;
+        if line.starts_with("OPENQASM") {
+            Some(CircuitFormat::Qasm)
+        } else if line.starts_with("Inputs:") {
+            Some(CircuitFormat::Quipper)
+        } else if line.starts_with('.') || line.starts_with("BEGIN") {
+            Some(CircuitFormat::Qc)
+        } else {
+            None
+        }
+    }
+}
+
+/// A sequence of gates on a fixed number of qubits
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Circuit {
+    pub num_qubits: u32,
+    pub gates: Vec<Gate>,
+}
+
+/// Fails if a gate acts on a qubit outside the circuit, as `Circuit::add_gate` would panic.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Circuit {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(serde::Deserialize)]
+        #[serde(rename = "Circuit")]
+        struct Fields {
+            num_qubits: u32,
+            gates: Vec<Gate>,
+        }
+        let fields = Fields::deserialize(deserializer)?;
+        if let Some(gate) = fields
+            .gates
+            .iter()
+            .find(|gate| gate.qubits().iter().any(|&q| q >= fields.num_qubits))
+        {
+            return Err(serde::de::Error::custom(format!(
+                "gate {:?} acts outside a circuit of {} qubits",
+                gate, fields.num_qubits
+            )));
+        }
+        Ok(Circuit {
+            num_qubits: fields.num_qubits,
+            gates: fields.gates,
+        })
+    }
+}
+
+impl Circuit {
+    /// Create an empty circuit
+    pub fn new(num_qubits: u32) -> Self {
+        Circuit {
+            num_qubits,
+            gates: Vec::new(),
+        }
+    }
+
+    /// Create a circuit from a list of gates. Panics if one acts on a qubit outside the circuit.
+    pub fn from_gates(num_qubits: u32, gates: &[Gate]) -> Self {
+        let mut c = Circuit::new(num_qubits);
+        for &gate in gates {
+            c.add_gate(gate);
+        }
+        c
+    }
+
+    /// Append a gate. Panics if it acts on a qubit outside the circuit.
+    pub fn add_gate(&mut self, gate: Gate) {
+        assert!(
+            gate.qubits().iter().all(|&q| q < self.num_qubits),
+            "gate {:?} acts outside a circuit of {} qubits",
+            gate,
+            self.num_qubits
+        );
+        self.gates.push(gate);
+    }
+
+    /// Parse an OpenQASM 2.0 program, see `io::qasm`.
+    pub fn from_qasm(src: &str) -> Result<Circuit, QasmError> {
+        qasm::parse(src)
+    }
+
+    /// Parse a Quipper ASCII circuit, see `io::quipper`.
+    pub fn from_quipper(src: &str) -> Result<Circuit, QuipperError> {
+        quipper::parse(src)
+    }
+
+    /// Parse a `.qc` circuit, see `io::qc`.
+    pub fn from_qc(src: &str) -> Result<Circuit, QcError> {
+        qc::parse(src)
+    }
+
+    /// Parse a circuit in the given format
+    pub fn parse(src: &str, format: CircuitFormat) -> Result<Circuit, Diagnostic> {
+        match format {
+            CircuitFormat::Qasm => Circuit::from_qasm(src),
+            CircuitFormat::Quipper => Circuit::from_quipper(src),
+            CircuitFormat::Qc => Circuit::from_qc(src),
+        }
+    }
+
+    /// Parse a circuit in the given format, failing with all its errors in source order
+    pub fn parse_all(src: &str, format: CircuitFormat) -> Result<Circuit, Vec<Diagnostic>> {
+        match format {
+            CircuitFormat::Qasm => qasm::parse_all(src),
+            CircuitFormat::Quipper => quipper::parse_all(src),
+            CircuitFormat::Qc => qc::parse_all(src),
+        }
+    }
+
+    /// Write the circuit as an OpenQASM 2.0 program, see `io::qasm::write`.
+    ///
+    /// CCZ gates are written as `ccz`, which is not part of `qelib1.inc`. Use
+    /// `decompose_composite` first for programs that only use the standard gates.
+    pub fn to_qasm(&self) -> String {
+        qasm::write(self)
+    }
+
+    /// Replace the CCZ, Toffoli, controlled phase and swap gates by equivalent sequences of
+    /// single-qubit gates and CNOTs, see `GateSet::CliffordT`.
+    pub fn decompose_composite(&self) -> Circuit {
+        let mut res = self.clone();
+        res.decompose(GateSet::CliffordT);
+        res
+    }
+
+    /// Lower every gate to the given gate set, see `Gate::decompose`. Lowering again changes
+    /// nothing.
+    pub fn decompose(&mut self, set: GateSet) {
+        self.gates = self.gates.iter().flat_map(|g| g.decompose(set)).collect();
+    }
+
+    /// Cancel adjacent self-inverse gates and merge rotations, moving Z rotations through CNOT
+    /// controls when that enables more rewrites. Returns the number of removed gates.
+    ///
+    /// See `optimize` for the rules.
+    pub fn basic_optimize(&mut self) -> usize {
+        optimize::basic_optimize(self)
+    }
+
+    /// Translate the circuit into a diagram, see `to_graph_into`.
+    pub fn to_graph<G: Graph + Default>(&self) -> G {
+        self.to_graph_into(Builder::new())
+    }
+
+    /// Translate the circuit into a diagram, emitting it into a `GraphSink` through a
+    /// `GraphBuilder`.
+    pub fn to_graph_into<S: GraphSink>(&self, sink: S) -> S::Graph {
+        let mut builder = GraphBuilder::with_capacity(self.num_qubits, self.gates.len(), sink);
+        for gate in &self.gates {
+            builder.push_gate(gate);
+        }
+        builder.finish()
+    }
+}
+
+fn edge_kind(hadamard: bool) -> EdgeKind {
+    if hadamard {
+        EdgeKind::Hadam
+    } else {
+        EdgeKind::Regular
+    }
+}
+
+/// Translation of a stream of gates into a diagram, emitted into a `GraphSink`.
+///
+/// Each qubit is a wire from an input to an output, in qubit order. Single-qubit rotations
+/// become spiders, Hadamard gates become Hadamard edges, CX and CZ gates become a pair of
+/// connected spiders with a factor of sqrt(2) on the scalar, and CCZ gates become three Z
+/// spiders connected to an H-box. Toffoli gates are CCZ gates between Hadamard edges on the
+/// target, and a controlled phase is two Z spiders connected to an H-box with that phase. Swap
+/// gates exchange the wires. Vertices are positioned on their qubit, with a row per layer of
+/// gates.
+///
+/// The builder keeps the open end of each wire, so each gate is appended in constant time
+/// without looking anything up in the graph.
+#[derive(Debug, Clone)]
+pub struct GraphBuilder<S> {
+    sink: S,
+    /// Last vertex of each wire, its row, and whether a Hadamard gate is pending
+    ends: Vec<(u32, f64, bool)>,
+}
+
+impl<S: GraphSink> GraphBuilder<S> {
+    /// Start a diagram with an input on each qubit.
+    pub fn new(num_qubits: u32, sink: S) -> Self {
+        Self::with_capacity(num_qubits, 0, sink)
+    }
+
+    /// Start a diagram with an input on each qubit, allocating room for about `num_gates`
+    /// gates.
+    pub fn with_capacity(num_qubits: u32, num_gates: usize, mut sink: S) -> Self {
+        let n = num_qubits as usize;
+        sink.begin(2 * n + 3 * num_gates, n + 4 * num_gates);
+        let mut ends = Vec::with_capacity(n);
+        for q in 0..n {
+            let v = sink.vertex(VertexKind::Boundary, Phase::zero(), IoFlags::INPUT);
+            sink.position(v, q as f64, 0.0);
+            ends.push((v, 0.0, false));
+        }
+        GraphBuilder { sink, ends }
+    }
+
+    /// Append a gate. Panics if it acts on a qubit outside the diagram.
+    pub fn push_gate(&mut self, gate: &Gate) {
+        if let Some((q, kind, phase)) = gate.as_spider() {
+            let row = self.next_row(&[q]);
+            self.spider(q, kind, phase, row);
+            return;
+        }
+        match *gate {
+            Gate::H(q) => self.ends[q as usize].2 ^= true,
+            Gate::Swap(a, b) => self.ends.swap(a as usize, b as usize),
+            Gate::Cx(c, t) | Gate::Cz(c, t) => {
+                let row = self.next_row(&[c, t]);
+                let (kind, edge) = match gate {
+                    Gate::Cx(..) => (VertexKind::X, EdgeKind::Regular),
+                    _ => (VertexKind::Z, EdgeKind::Hadam),
+                };
+                let a = self.spider(c, VertexKind::Z, Phase::zero(), row);
+                let b = self.spider(t, kind, Phase::zero(), row);
+                self.sink.edge(a, b, edge);
+                // The pair of spiders is the gate divided by sqrt(2).
+                self.sink.scalar(Scalar::new(1, Phase::zero()));
+            }
+            Gate::Ccz(a, b, c) => self.hbox(&[a, b, c], Phase::pi()),
+            Gate::Ccx(a, b, c) => {
+                self.ends[c as usize].2 ^= true;
+                self.hbox(&[a, b, c], Phase::pi());
+                self.ends[c as usize].2 ^= true;
+            }
+            Gate::CPhase(a, b, phase) => self.hbox(&[a, b], phase),
+            _ => unreachable!("single-qubit rotations are handled above"),
+        }
+    }
+
+    /// Close the wires with an output on each qubit and build the diagram.
+    pub fn finish(mut self) -> S::Graph {
+        let row = self.ends.iter().fold(0.0, |acc: f64, end| acc.max(end.1)) + 1.0;
+        for (q, &(last, _, hadamard)) in self.ends.iter().enumerate() {
+            let v = self
+                .sink
+                .vertex(VertexKind::Boundary, Phase::zero(), IoFlags::OUTPUT);
+            self.sink.position(v, q as f64, row);
+            self.sink.edge(last, v, edge_kind(hadamard));
+        }
+        self.sink
+            .finish()
+            .expect("circuit translation only connects emitted vertices")
+    }
+
+    /// First row after the last vertex of all the given qubits
+    fn next_row(&self, qubits: &[u32]) -> f64 {
+        qubits
+            .iter()
+            .map(|&q| self.ends[q as usize].1)
+            .fold(0.0, f64::max)
+            + 1.0
+    }
+
+    /// Add a spider at the end of a wire.
+    fn spider(&mut self, q: u32, kind: VertexKind, phase: Phase, row: f64) -> u32 {
+        let v = self.sink.vertex(kind, phase, IoFlags::NONE);
+        self.sink.position(v, q as f64, row);
+        let (last, _, hadamard) = self.ends[q as usize];
+        self.sink.edge(last, v, edge_kind(hadamard));
+        self.ends[q as usize] = (v, row, false);
+        v
+    }
+
+    /// Add a Z spider at the end of each wire, all connected to an H-box on the next row.
+    fn hbox(&mut self, qubits: &[u32], phase: Phase) {
+        let row = self.next_row(qubits);
+        let hbox = self.sink.vertex(VertexKind::HBox, phase, IoFlags::NONE);
+        let qubit = qubits.iter().sum::<u32>() as f64 / qubits.len() as f64;
+        self.sink.position(hbox, qubit, row + 1.0);
+        for &q in qubits {
+            let z = self.spider(q, VertexKind::Z, Phase::zero(), row);
+            self.sink.edge(z, hbox, EdgeKind::Regular);
+            self.ends[q as usize].1 = row + 1.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::analysis::is_isomorphic;
+    use crate::zx::generate::random_clifford_t_circuit;
+    use crate::zx::random::Seed;
+    use crate::zx::simple_graph::GGraph;
+    use crate::zx::tensor::assert_graph_eq;
+    use crate::zx::vec_graph::VecGraph;
+
+    /// Gates with a native construction, next to single-qubit gates that do not commute with
+    /// them
+    fn composite() -> Vec<Circuit> {
+        let third = Phase::new(1, 3);
+        vec![
+            Circuit::from_gates(3, &[Gate::H(0), Gate::Ccz(0, 1, 2), Gate::T(2)]),
+            Circuit::from_gates(3, &[Gate::H(2), Gate::Ccz(2, 0, 1), Gate::H(1)]),
+            Circuit::from_gates(3, &[Gate::Ccx(0, 1, 2), Gate::H(0), Gate::Ccx(2, 0, 1)]),
+            Circuit::from_gates(2, &[Gate::T(0), Gate::Swap(0, 1), Gate::H(1)]),
+            Circuit::from_gates(2, &[Gate::H(0), Gate::CPhase(0, 1, third), Gate::H(1)]),
+            Circuit::from_gates(2, &[Gate::CPhase(1, 0, Phase::new(-3, 4)), Gate::Cz(0, 1)]),
+        ]
+    }
+
+    #[test]
+    fn decompositions_match_the_native_constructions() {
+        for set in [GateSet::CliffordT, GateSet::CxHRz] {
+            for c in composite() {
+                let native: GGraph = c.to_graph();
+                let mut lowered = c.clone();
+                lowered.decompose(set);
+                assert!(lowered.gates.iter().all(|g| !matches!(
+                    g,
+                    Gate::Ccz(..) | Gate::Ccx(..) | Gate::Swap(..) | Gate::CPhase(..)
+                )));
+                assert_graph_eq(&lowered.to_graph::<GGraph>(), &native);
+            }
+        }
+    }
+
+    #[test]
+    fn decompose_is_idempotent() {
+        for set in [GateSet::CliffordT, GateSet::CxHRz] {
+            for c in composite() {
+                let mut lowered = c.clone();
+                lowered.decompose(set);
+                let once = lowered.clone();
+                lowered.decompose(set);
+                assert_eq!(lowered, once);
+            }
+        }
+    }
+
+    #[test]
+    fn gate_counts_of_the_decompositions() {
+        let count = |gates: &[Gate], f: fn(&Gate) -> bool| gates.iter().filter(|g| f(g)).count();
+        let ccz = Gate::Ccz(0, 1, 2).decompose(GateSet::CliffordT);
+        assert_eq!(count(&ccz, |g| matches!(g, Gate::T(_) | Gate::Tdg(_))), 7);
+        assert_eq!(count(&ccz, |g| matches!(g, Gate::Cx(..))), 6);
+        assert_eq!(
+            Gate::Swap(0, 1).decompose(GateSet::CliffordT),
+            [Gate::Cx(0, 1), Gate::Cx(1, 0), Gate::Cx(0, 1)]
+        );
+        let cphase = Gate::CPhase(0, 1, Phase::new(1, 2)).decompose(GateSet::CxHRz);
+        assert_eq!(count(&cphase, |g| matches!(g, Gate::Cx(..))), 2);
+        assert_eq!(count(&cphase, |g| matches!(g, Gate::Rz(..))), 3);
+        // Only H, CX and Z rotations are left in `CxHRz`.
+        let mut c = Circuit::from_gates(
+            2,
+            &[
+                Gate::X(0),
+                Gate::S(1),
+                Gate::Tdg(0),
+                Gate::Rx(1, Phase::new(1, 8)),
+            ],
+        );
+        c.add_gate(Gate::Cz(0, 1));
+        c.decompose(GateSet::CxHRz);
+        assert!(c
+            .gates
+            .iter()
+            .all(|g| matches!(g, Gate::H(_) | Gate::Cx(..) | Gate::Rz(..))));
+        assert_eq!(c.gates.len(), 11);
+    }
+
+    /// Diagram of a circuit inserting each gate before the outputs of its qubits, through the
+    /// `Graph` trait only
+    fn naive(circuit: &Circuit) -> GGraph {
+        let mut g: GGraph = GGraph::new();
+        let mut outputs = Vec::new();
+        for _ in 0..circuit.num_qubits {
+            let i = g.add_vertex(VertexKind::Boundary, Phase::zero());
+            let o = g.add_vertex(VertexKind::Boundary, Phase::zero());
+            g.set_input(i, true);
+            g.add_edge(i, o, EdgeKind::Regular);
+            outputs.push(o);
+        }
+        for &o in &outputs {
+            g.set_output(o, true);
+        }
+        let last = |g: &GGraph, q: u32| {
+            let (e, v) = g
+                .incident_edges_with_neighbours(outputs[q as usize])
+                .next()
+                .unwrap();
+            (e, v, g.edge(e).unwrap().kind())
+        };
+        let spider = |g: &mut GGraph, q: u32, kind: VertexKind, phase: Phase| {
+            let (e, prev, edge) = last(g, q);
+            g.remove_edge(e);
+            let v = g.add_vertex(kind, phase);
+            g.add_edge(prev, v, edge);
+            g.add_edge(v, outputs[q as usize], EdgeKind::Regular);
+            v
+        };
+        let toggle = |g: &mut GGraph, q: u32| {
+            let (e, _, edge) = last(g, q);
+            g.set_edge_kind(e, edge_kind(edge == EdgeKind::Regular));
+        };
+        let hbox = |g: &mut GGraph, qubits: &[u32], phase: Phase| {
+            let h = g.add_vertex(VertexKind::HBox, phase);
+            for &q in qubits {
+                let z = spider(g, q, VertexKind::Z, Phase::zero());
+                g.add_edge(z, h, EdgeKind::Regular);
+            }
+        };
+        for gate in &circuit.gates {
+            if let Some((q, kind, phase)) = gate.as_spider() {
+                spider(&mut g, q, kind, phase);
+                continue;
+            }
+            match *gate {
+                Gate::H(q) => toggle(&mut g, q),
+                Gate::Swap(a, b) => {
+                    let ((ea, va, ka), (eb, vb, kb)) = (last(&g, a), last(&g, b));
+                    g.remove_edges([ea, eb]);
+                    g.add_edge(va, outputs[b as usize], ka);
+                    g.add_edge(vb, outputs[a as usize], kb);
+                }
+                Gate::Cx(c, t) | Gate::Cz(c, t) => {
+                    let (kind, edge) = match gate {
+                        Gate::Cx(..) => (VertexKind::X, EdgeKind::Regular),
+                        _ => (VertexKind::Z, EdgeKind::Hadam),
+                    };
+                    let a = spider(&mut g, c, VertexKind::Z, Phase::zero());
+                    let b = spider(&mut g, t, kind, Phase::zero());
+                    g.add_edge(a, b, edge);
+                    g.scalar_mut().add_power(1);
+                }
+                Gate::Ccz(a, b, c) => hbox(&mut g, &[a, b, c], Phase::pi()),
+                Gate::Ccx(a, b, c) => {
+                    toggle(&mut g, c);
+                    hbox(&mut g, &[a, b, c], Phase::pi());
+                    toggle(&mut g, c);
+                }
+                Gate::CPhase(a, b, phase) => hbox(&mut g, &[a, b], phase),
+                _ => unreachable!("single-qubit rotations are handled above"),
+            }
+        }
+        g
+    }
+
+    #[test]
+    fn builder_matches_the_naive_construction() {
+        let mut circuits: Vec<_> = (0..5)
+            .map(|seed| random_clifford_t_circuit(4, 300, 0.2, Seed(seed)))
+            .collect();
+        circuits.extend(composite());
+        // A swap of wires that are still bare, and Hadamards that cancel on a wire
+        circuits.push(Circuit::from_gates(
+            3,
+            &[Gate::Swap(0, 2), Gate::H(1), Gate::H(1), Gate::Cx(1, 0)],
+        ));
+        for c in circuits {
+            let expected = naive(&c);
+            for g in [c.to_graph::<GGraph>(), c.to_graph_into(GGraph::builder())] {
+                assert!(is_isomorphic(&g, &expected));
+                assert_eq!(g.scalar(), expected.scalar());
+            }
+            let g: VecGraph = c.to_graph();
+            assert!(is_isomorphic(&g, &expected));
+        }
+    }
+
+    #[test]
+    fn builder_layout() {
+        let c = Circuit::from_gates(2, &[Gate::T(0), Gate::Cx(0, 1), Gate::H(1), Gate::S(1)]);
+        let g: GGraph = c.to_graph_into(GGraph::builder());
+        // Inputs, the T gate, the CNOT after it, the S gate, and the outputs
+        assert_eq!(g.depth(), 5);
+        assert_eq!(g.qubit_count(), 2);
+        for (q, v) in g.inputs().enumerate() {
+            assert_eq!((g.qubit(v), g.row(v)), (q as i32, 0));
+        }
+        for (q, v) in g.outputs().enumerate() {
+            assert_eq!((g.qubit(v), g.row(v)), (q as i32, 4));
+        }
+    }
+}
@@ -0,0 +1,257 @@
+//! Peephole optimizations of circuits, applied before translating them into diagrams.
+//!
+//! Each gate is compared with the later gates on its qubits, moving past the gates it commutes
+//! with. Pairs of self-inverse gates cancel, and rotations around the same axis on the same
+//! qubit merge into one, which is dropped if its angle is zero. All the rewrites preserve the
+//! linear map exactly, including the global phase, with rotations read as the spiders given by
+//! `Gate::as_spider`.
+
+use crate::zx::circuit::{Circuit, Gate};
+use crate::zx::graph::*;
+
+/// Whether a gate is diagonal in the computational basis
+fn is_diagonal(gate: Gate) -> bool {
+    match gate {
+        Gate::Cz(..) | Gate::CPhase(..) | Gate::Ccz(..) => true,
+        gate => matches!(gate.as_spider(), Some((_, VertexKind::Z, _))),
+    }
+}
+
+/// Qubit of a rotation around the X axis
+fn x_rotation_qubit(gate: Gate) -> Option<u32> {
+    match gate.as_spider() {
+        Some((q, VertexKind::X, _)) => Some(q),
+        _ => None,
+    }
+}
+
+/// Whether two gates commute, by sufficient conditions.
+///
+/// Gates on disjoint qubits commute, and so do diagonal gates. A CNOT commutes with the diagonal
+/// gates that do not act on its target, with the X rotations of its target, and with the CNOTs
+/// that share only its control or only its target.
+fn commutes(a: Gate, b: Gate) -> bool {
+    let (qa, qb) = (a.qubits(), b.qubits());
+    if qa.iter().all(|q| !qb.contains(q)) || (is_diagonal(a) && is_diagonal(b)) {
+        return true;
+    }
+    let through_cx = |cx: Gate, other: Gate| match cx {
+        Gate::Cx(c, t) => match other {
+            Gate::Cx(c2, t2) => c != t2 && t != c2,
+            other if is_diagonal(other) => !other.qubits().contains(&t),
+            other => x_rotation_qubit(other) == Some(t),
+        },
+        _ => false,
+    };
+    through_cx(a, b) || through_cx(b, a)
+}
+
+/// Single Z rotation with the given angle, using the named gates when possible, or `None` for
+/// the identity
+fn z_rotation(q: u32, phase: Phase) -> Option<Gate> {
+    let named = [
+        (Phase::pi(), Gate::Z(q)),
+        (Phase::new(1, 2), Gate::S(q)),
+        (Phase::new(-1, 2), Gate::Sdg(q)),
+        (Phase::new(1, 4), Gate::T(q)),
+        (Phase::new(-1, 4), Gate::Tdg(q)),
+    ];
+    if phase.is_zero() {
+        None
+    } else if let Some(&(_, gate)) = named.iter().find(|&&(p, _)| p == phase) {
+        Some(gate)
+    } else {
+        Some(Gate::Rz(q, phase))
+    }
+}
+
+/// Single X rotation with the given angle, or `None` for the identity
+fn x_rotation(q: u32, phase: Phase) -> Option<Gate> {
+    if phase.is_zero() {
+        None
+    } else if phase == Phase::pi() {
+        Some(Gate::X(q))
+    } else {
+        Some(Gate::Rx(q, phase))
+    }
+}
+
+/// The gate equivalent to `a` followed by `b`, or `None` for the identity. Returns `Some` only
+/// when the pair reduces to at most one gate.
+fn combine(a: Gate, b: Gate) -> Option<Option<Gate>> {
+    match (a.as_spider(), b.as_spider()) {
+        (Some((q, kind, pa)), Some((r, kind2, pb))) if q == r && kind == kind2 => {
+            return Some(match kind {
+                VertexKind::Z => z_rotation(q, pa + pb),
+                _ => x_rotation(q, pa + pb),
+            });
+        }
+        _ => {}
+    }
+    let self_inverse = match (a, b) {
+        (Gate::H(q), Gate::H(r)) => q == r,
+        (Gate::Cx(c, t), Gate::Cx(c2, t2)) => c == c2 && t == t2,
+        (Gate::Ccx(a, b, t), Gate::Ccx(a2, b2, t2)) => {
+            t == t2 && ((a, b) == (a2, b2) || (a, b) == (b2, a2))
+        }
+        // Symmetric in their qubits
+        (Gate::Cz(..), Gate::Cz(..))
+        | (Gate::Swap(..), Gate::Swap(..))
+        | (Gate::Ccz(..), Gate::Ccz(..)) => {
+            let (mut qa, mut qb) = (a.qubits(), b.qubits());
+            qa.sort_unstable();
+            qb.sort_unstable();
+            qa == qb
+        }
+        _ => false,
+    };
+    if self_inverse {
+        Some(None)
+    } else {
+        None
+    }
+}
+
+/// Cancel self-inverse pairs and merge rotations until no rewrite applies, see the module
+/// documentation. Returns the number of removed gates.
+pub fn basic_optimize(circuit: &mut Circuit) -> usize {
+    let before = circuit.gates.len();
+    let mut gates: Vec<_> = circuit.gates.iter().copied().map(Some).collect();
+    loop {
+        let mut changed = false;
+        for i in 0..gates.len() {
+            let a = match gates[i] {
+                Some(a) => a,
+                None => continue,
+            };
+            if matches!(a.as_spider(), Some((_, _, phase)) if phase.is_zero()) {
+                gates[i] = None;
+                changed = true;
+                continue;
+            }
+            // A gate that combines with `a` can be moved back to it, since it commutes with the
+            // same gates.
+            for j in i + 1..gates.len() {
+                let b = match gates[j] {
+                    Some(b) => b,
+                    None => continue,
+                };
+                if let Some(merged) = combine(a, b) {
+                    gates[i] = merged;
+                    gates[j] = None;
+                    changed = true;
+                    break;
+                }
+                if !commutes(a, b) {
+                    break;
+                }
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    circuit.gates = gates.into_iter().flatten().collect();
+    before - circuit.gates.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::generate::random_clifford_t_circuit;
+    use crate::zx::random::Seed;
+    use crate::zx::simple_graph::GGraph;
+    use crate::zx::tensor::assert_graph_eq;
+
+    /// Optimize `gates`, checking the number of removed gates, the remaining gates and that
+    /// the linear map is unchanged
+    fn check(num_qubits: u32, gates: &[Gate], removed: usize, expected: &[Gate]) {
+        let original = Circuit::from_gates(num_qubits, gates);
+        let mut c = original.clone();
+        assert_eq!(basic_optimize(&mut c), removed, "{:?}", c.gates);
+        assert_eq!(c.gates, expected);
+        let (g, h): (GGraph, GGraph) = (c.to_graph(), original.to_graph());
+        assert_graph_eq(&g, &h);
+    }
+
+    #[test]
+    fn t_cancels_through_a_cnot_control() {
+        check(
+            2,
+            &[Gate::T(0), Gate::Cx(0, 1), Gate::Tdg(0)],
+            2,
+            &[Gate::Cx(0, 1)],
+        );
+        // A phase on the target does not commute with the CNOT.
+        let gates = [Gate::T(1), Gate::Cx(0, 1), Gate::Tdg(1)];
+        check(2, &gates, 0, &gates);
+    }
+
+    #[test]
+    fn self_inverse_pairs() {
+        check(1, &[Gate::H(0), Gate::H(0)], 2, &[]);
+        check(1, &[Gate::Z(0), Gate::Z(0)], 2, &[]);
+        check(2, &[Gate::Cx(0, 1), Gate::Cx(0, 1)], 2, &[]);
+        check(2, &[Gate::Cz(0, 1), Gate::Cz(1, 0)], 2, &[]);
+        check(3, &[Gate::Ccx(0, 1, 2), Gate::Ccx(1, 0, 2)], 2, &[]);
+        let gates = [Gate::Cx(0, 1), Gate::Cx(1, 0)];
+        check(2, &gates, 0, &gates);
+        // Cancelling the inner pair exposes the outer one.
+        check(
+            2,
+            &[Gate::H(1), Gate::Cx(0, 1), Gate::Cx(0, 1), Gate::H(1)],
+            4,
+            &[],
+        );
+    }
+
+    #[test]
+    fn rotations_merge() {
+        check(1, &[Gate::S(0), Gate::S(0)], 1, &[Gate::Z(0)]);
+        check(1, &[Gate::T(0), Gate::T(0), Gate::Z(0)], 2, &[Gate::Sdg(0)]);
+        check(
+            1,
+            &[Gate::Rz(0, Phase::new(1, 8)), Gate::Rz(0, Phase::new(1, 8))],
+            1,
+            &[Gate::T(0)],
+        );
+        check(
+            1,
+            &[Gate::Rz(0, Phase::new(1, 3)), Gate::Rz(0, Phase::new(5, 3))],
+            2,
+            &[],
+        );
+        check(
+            1,
+            &[Gate::Rz(0, Phase::zero()), Gate::H(0)],
+            1,
+            &[Gate::H(0)],
+        );
+        check(
+            2,
+            &[
+                Gate::Rx(1, Phase::new(1, 2)),
+                Gate::Cx(0, 1),
+                Gate::Rx(1, Phase::new(1, 2)),
+            ],
+            1,
+            &[Gate::X(1), Gate::Cx(0, 1)],
+        );
+        // An X rotation does not merge with a Z rotation.
+        let gates = [Gate::X(0), Gate::Z(0), Gate::X(0)];
+        check(1, &gates, 0, &gates);
+    }
+
+    #[test]
+    fn random_circuits() {
+        for seed in 0..20 {
+            let original = random_clifford_t_circuit(3, 40, 0.3, Seed(seed));
+            let mut c = original.clone();
+            let removed = basic_optimize(&mut c);
+            assert_eq!(c.gates.len() + removed, original.gates.len());
+            assert_eq!(basic_optimize(&mut c), 0);
+            let (g, h): (GGraph, GGraph) = (c.to_graph(), original.to_graph());
+            assert_graph_eq(&g, &h);
+        }
+    }
+}
@@ -0,0 +1,559 @@
+//! Equality checking of circuits by reducing diagrams.
+//!
+//! Two circuits `a` and `b` on the same qubits are equal when `a` followed by the adjoint of `b`
+//! is the identity. The diagram of that composition is reduced with `simplify::full_reduce`; if
+//! it becomes plain wires, the scalar tells the global phase. The reduction is incomplete, so a
+//! diagram that does not become plain wires only proves the circuits different when its shape
+//! makes it clear, see `verify_equality`.
+//!
+//! `equivalent` falls back to numerical methods when the reduction stalls: the dense tensors of
+//! small circuits are compared, and larger circuits are searched for a counterexample by
+//! evaluating amplitudes between random basis states with `evaluate::scalar_value`.
+
+use crate::zx::analysis::is_identity_diagram;
+use crate::zx::circuit::{Circuit, Gate, GateSet};
+use crate::zx::evaluate::scalar_value;
+use crate::zx::graph::*;
+use crate::zx::random::Seed;
+use crate::zx::simple_graph::GGraph;
+use crate::zx::tensor::to_tensor;
+use crate::zx::{simplify, zh};
+use num_complex::Complex64;
+use rand::Rng;
+use std::collections::{HashMap, HashSet};
+use std::f64::consts::{FRAC_1_SQRT_2, PI};
+
+/// Outcome of `verify_equality`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyResult {
+    /// The circuits have the same linear map
+    Equal,
+    /// The circuits differ by a global phase: the map of `a` is `e^{i phase}` times the map of
+    /// `b`
+    EqualUpToGlobalPhase(Phase),
+    /// The circuits have different linear maps, even up to a global phase
+    NotEqual,
+    /// The reduction stalled before deciding
+    Unknown,
+}
+
+/// How `equivalent` reached its verdict
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    /// Reduction of the composition of a circuit with the adjoint of the other, as in
+    /// `verify_equality`
+    Reduction,
+    /// Comparison of the dense tensors of both circuits
+    Tensor,
+    /// Comparison of amplitudes between random basis states
+    Sampling,
+}
+
+/// Basis states on which two circuits have different amplitudes, beyond a global phase
+#[derive(Debug, Clone, PartialEq)]
+pub struct Counterexample {
+    /// Input basis state, a bit per qubit
+    pub input: Vec<bool>,
+    /// Output basis state, a bit per qubit
+    pub output: Vec<bool>,
+    /// The amplitude `<output|a|input>` of the first circuit
+    pub amplitude_a: Complex64,
+    /// The amplitude `<output|b|input>` of the second circuit
+    pub amplitude_b: Complex64,
+}
+
+/// Outcome of `equivalent`
+#[derive(Debug, Clone, PartialEq)]
+pub struct EquivalenceResult {
+    pub verdict: VerifyResult,
+    pub method: Method,
+    /// Distinguishing basis states, when the method found them
+    pub counterexample: Option<Counterexample>,
+}
+
+/// Options of `equivalent`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EquivalenceOptions {
+    /// Largest number of qubits for which dense tensors are compared
+    pub max_tensor_qubits: u32,
+    /// Number of random amplitudes compared for larger circuits
+    pub samples: usize,
+    pub seed: Seed,
+}
+
+impl Default for EquivalenceOptions {
+    fn default() -> Self {
+        EquivalenceOptions {
+            max_tensor_qubits: 8,
+            samples: 64,
+            seed: Seed(0),
+        }
+    }
+}
+
+type Matrix = [[Complex64; 2]; 2];
+
+fn mul(a: &Matrix, b: &Matrix) -> Matrix {
+    let entry = |i: usize, j: usize| a[i][0] * b[0][j] + a[i][1] * b[1][j];
+    [[entry(0, 0), entry(0, 1)], [entry(1, 0), entry(1, 1)]]
+}
+
+fn hadamard() -> Matrix {
+    let h = Complex64::new(FRAC_1_SQRT_2, 0.0);
+    [[h, h], [h, -h]]
+}
+
+/// Matrix of a degree-2 Z or X spider
+fn spider_matrix(kind: VertexKind, phase: Phase) -> Option<Matrix> {
+    let zero = Complex64::new(0.0, 0.0);
+    let one = Complex64::new(1.0, 0.0);
+    let z = [
+        [one, zero],
+        [zero, Complex64::from_polar(&1.0, &(PI * phase.to_f64()))],
+    ];
+    match kind {
+        VertexKind::Z => Some(z),
+        VertexKind::X => Some(mul(&hadamard(), &mul(&z, &hadamard()))),
+        _ => None,
+    }
+}
+
+fn edge_matrix(kind: EdgeKind) -> Matrix {
+    match kind {
+        EdgeKind::Regular => [
+            [Complex64::new(1.0, 0.0), Complex64::new(0.0, 0.0)],
+            [Complex64::new(0.0, 0.0), Complex64::new(1.0, 0.0)],
+        ],
+        EdgeKind::Hadam => hadamard(),
+    }
+}
+
+/// Whether a matrix is a non-zero multiple of the identity, up to rounding errors
+fn is_scaled_identity(m: &Matrix) -> bool {
+    let tol = 1e-9;
+    m[0][1].norm() < tol
+        && m[1][0].norm() < tol
+        && (m[0][0] - m[1][1]).norm() < tol
+        && m[0][0].norm() > tol
+}
+
+/// Split a diagram into single-qubit wires, if it is a permutation of wires each carrying a
+/// chain of degree-2 Z and X spiders.
+///
+/// Returns, for each input in wire order, the position of the output it is connected to and
+/// the matrix of its chain, without the scalar of the diagram.
+fn wire_chains<G: Graph>(g: &G) -> Option<Vec<(usize, Matrix)>> {
+    let outputs: HashMap<_, _> = g.outputs().enumerate().map(|(i, v)| (v, i)).collect();
+    if outputs.len() != g.inputs().count() {
+        return None;
+    }
+    let mut visited = HashSet::new();
+    let mut chains = Vec::new();
+    for input in g.inputs() {
+        visited.insert(input);
+        let wire: Vec<_> = g.neighbours_with_kind(input).collect();
+        if wire.is_empty() && g.is_output(input) {
+            chains.push((outputs[&input], edge_matrix(EdgeKind::Regular)));
+            continue;
+        }
+        let (mut prev, (mut cur, kind)) = match wire[..] {
+            [n] => (input, n),
+            _ => return None,
+        };
+        let mut matrix = edge_matrix(kind);
+        loop {
+            if !visited.insert(cur) {
+                return None;
+            }
+            let vdata = g.vertex(cur).unwrap();
+            if vdata.kind() == VertexKind::Boundary {
+                break;
+            }
+            let (next, kind) = match g.neighbours_with_kind(cur).collect::<Vec<_>>()[..] {
+                [a, b] => {
+                    if a.0 == prev {
+                        b
+                    } else {
+                        a
+                    }
+                }
+                _ => return None,
+            };
+            matrix = mul(&spider_matrix(vdata.kind(), vdata.phase())?, &matrix);
+            matrix = mul(&edge_matrix(kind), &matrix);
+            prev = cur;
+            cur = next;
+        }
+        if g.is_input(cur) || g.vertex_degree(cur) != 1 {
+            return None;
+        }
+        chains.push((*outputs.get(&cur)?, matrix));
+    }
+    (visited.len() == g.num_vertices() as usize).then_some(chains)
+}
+
+/// Copy of a circuit with its controlled phases lowered, see `GateSet::CliffordT`
+fn lower_controlled_phases(c: &Circuit) -> Circuit {
+    let gates = c.gates.iter().flat_map(|gate| match gate {
+        Gate::CPhase(..) => gate.decompose(GateSet::CliffordT),
+        gate => vec![*gate],
+    });
+    Circuit {
+        num_qubits: c.num_qubits,
+        gates: gates.collect(),
+    }
+}
+
+/// Check whether two circuits have the same linear map, by reducing `a` followed by the
+/// adjoint of `b`.
+///
+/// Controlled phases are first lowered to CNOTs with `Gate::decompose`, CCZ and Toffoli gates
+/// are turned into phase gadgets with `zh::ccz_to_gadgets`, and the diagram is reduced with
+/// `simplify::full_reduce`. If it becomes plain wires connecting each input to the output of the
+/// same qubit, the circuits are equal, up to the global phase given by the scalar. If it becomes
+/// a permutation of wires each carrying single-qubit spiders, and either the permutation is not
+/// the identity or the spiders of some wire do not multiply to a multiple of the identity, the
+/// circuits are different. Otherwise the result is `Unknown`.
+///
+/// Circuits on different numbers of qubits are never equal.
+pub fn verify_equality(a: &Circuit, b: &Circuit) -> VerifyResult {
+    if a.num_qubits != b.num_qubits {
+        return VerifyResult::NotEqual;
+    }
+    let (a, b) = (lower_controlled_phases(a), lower_controlled_phases(b));
+    let mut g: GGraph = a.to_graph();
+    let mut adjoint: GGraph = b.to_graph();
+    adjoint.adjoint();
+    g.compose(adjoint)
+        .expect("circuits on the same qubits have matching wires");
+    zh::ccz_to_gadgets(&mut g);
+    simplify::full_reduce(&mut g);
+
+    if is_identity_diagram(&g) {
+        let scalar = *g.scalar();
+        return if scalar.is_zero() || scalar.power2() != 0 {
+            VerifyResult::NotEqual
+        } else if scalar.phase().is_zero() {
+            VerifyResult::Equal
+        } else {
+            VerifyResult::EqualUpToGlobalPhase(scalar.phase())
+        };
+    }
+    match wire_chains(&g) {
+        Some(chains)
+            if chains
+                .iter()
+                .enumerate()
+                .any(|(i, (j, m))| i != *j || !is_scaled_identity(m)) =>
+        {
+            VerifyResult::NotEqual
+        }
+        _ => VerifyResult::Unknown,
+    }
+}
+
+/// Tolerance of the numerical comparisons of `equivalent`
+const TOLERANCE: f64 = 1e-9;
+
+/// Bits of a basis state index, first wire as the most significant bit
+fn bits(index: usize, num_qubits: usize) -> Vec<bool> {
+    (0..num_qubits)
+        .map(|q| index >> (num_qubits - 1 - q) & 1 == 1)
+        .collect()
+}
+
+/// Global phase of a unit complex number, if it is a multiple of pi with a small denominator
+fn phase_of(ratio: Complex64) -> Option<Phase> {
+    let turns = ratio.arg() / PI;
+    (1..=64).find_map(|den: u64| {
+        let num = (turns * den as f64).round();
+        ((turns * den as f64 - num).abs() < TOLERANCE).then(|| Phase::new(num as i64, den))
+    })
+}
+
+/// Compare amplitudes `(a, b)` of two circuits, returning the ratio `a / b` of the first pair
+/// of non-zero amplitudes and the first counterexample to `a = ratio * b`.
+fn compare_amplitudes(
+    amplitudes: impl IntoIterator<Item = Counterexample>,
+) -> (Option<Complex64>, Option<Counterexample>) {
+    let mut ratio = None;
+    for sample in amplitudes {
+        let (a, b) = (sample.amplitude_a, sample.amplitude_b);
+        if ratio.is_none() && a.norm() > TOLERANCE && b.norm() > TOLERANCE {
+            ratio = Some(a / b);
+        }
+        if (a - ratio.unwrap_or(Complex64::new(1.0, 0.0)) * b).norm() > TOLERANCE {
+            return (ratio, Some(sample));
+        }
+    }
+    (ratio, None)
+}
+
+/// Verdict from the ratio between all the amplitudes of two circuits
+fn verdict(ratio: Option<Complex64>) -> VerifyResult {
+    match ratio {
+        Some(r) if (r.norm() - 1.0).abs() > TOLERANCE => VerifyResult::NotEqual,
+        Some(r) => match phase_of(r) {
+            Some(phase) if phase.is_zero() => VerifyResult::Equal,
+            Some(phase) => VerifyResult::EqualUpToGlobalPhase(phase),
+            None => VerifyResult::Unknown,
+        },
+        // Both maps are zero, which unitary circuits never are.
+        None => VerifyResult::Equal,
+    }
+}
+
+/// Closed diagram of the amplitude `<output|c|input>` of a circuit between basis states
+fn amplitude_diagram(c: &Circuit, input: &[bool], output: &[bool]) -> GGraph {
+    let mut g: GGraph = c.to_graph();
+    zh::ccz_to_gadgets(&mut g);
+    let inputs: Vec<_> = g.inputs().zip(input).collect();
+    let outputs: Vec<_> = g.outputs().zip(output).collect();
+    for &(v, &bit) in inputs.iter().chain(&outputs) {
+        g.set_input(v, false);
+        g.set_output(v, false);
+        g.set_vertex_kind(v, VertexKind::X);
+        if bit {
+            g.set_phase(v, Phase::pi());
+        }
+    }
+    // Each X spider is sqrt(2) times a basis state.
+    g.scalar_mut()
+        .add_power(-((inputs.len() + outputs.len()) as i32));
+    g
+}
+
+/// Compare the dense tensors of two circuits on the same qubits
+fn compare_tensors(a: &Circuit, b: &Circuit) -> EquivalenceResult {
+    let n = a.num_qubits as usize;
+    let (ta, tb) = (
+        to_tensor(&a.to_graph::<GGraph>()),
+        to_tensor(&b.to_graph::<GGraph>()),
+    );
+    let entries = (0..1 << n).flat_map(|i| {
+        let (ta, tb) = (&ta, &tb);
+        (0..1 << n).map(move |o| Counterexample {
+            input: bits(i, n),
+            output: bits(o, n),
+            amplitude_a: ta.entry(i, o),
+            amplitude_b: tb.entry(i, o),
+        })
+    });
+    let (ratio, counterexample) = compare_amplitudes(entries);
+    EquivalenceResult {
+        verdict: match counterexample {
+            Some(_) => VerifyResult::NotEqual,
+            None => verdict(ratio),
+        },
+        method: Method::Tensor,
+        counterexample,
+    }
+}
+
+/// Compare amplitudes of two circuits on the same qubits between random basis states
+fn compare_samples(a: &Circuit, b: &Circuit, opts: &EquivalenceOptions) -> EquivalenceResult {
+    let n = a.num_qubits as usize;
+    let (a, b) = (lower_controlled_phases(a), lower_controlled_phases(b));
+    let mut rng = opts.seed.rng();
+    let mut samples = Vec::new();
+    for _ in 0..opts.samples {
+        let input: Vec<bool> = (0..n).map(|_| rng.gen()).collect();
+        let output: Vec<bool> = (0..n).map(|_| rng.gen()).collect();
+        let amplitudes = (
+            scalar_value(&amplitude_diagram(&a, &input, &output)),
+            scalar_value(&amplitude_diagram(&b, &input, &output)),
+        );
+        match amplitudes {
+            (Ok(amplitude_a), Ok(amplitude_b)) => samples.push(Counterexample {
+                input,
+                output,
+                amplitude_a,
+                amplitude_b,
+            }),
+            // Too many non-Clifford spiders to evaluate.
+            _ => break,
+        }
+    }
+    let (_, counterexample) = compare_amplitudes(samples);
+    EquivalenceResult {
+        verdict: match counterexample {
+            Some(_) => VerifyResult::NotEqual,
+            None => VerifyResult::Unknown,
+        },
+        method: Method::Sampling,
+        counterexample,
+    }
+}
+
+/// Check whether two circuits have the same linear map, falling back to numerical methods
+/// when the diagram reduction of `verify_equality` stalls.
+///
+/// The dense tensors of the circuits are compared when they have at most
+/// `opts.max_tensor_qubits` qubits, which decides equality. Otherwise `opts.samples` amplitudes
+/// `<y|a|x>` and `<y|b|x>` between random basis states are evaluated as closed diagrams. That can
+/// only prove the circuits different, by finding amplitudes that are not related by a common
+/// global phase, and the result is `Unknown` if there are none. Amplitudes of circuits with
+/// many T gates may be too expensive to evaluate, which stops the sampling early.
+pub fn equivalent(a: &Circuit, b: &Circuit, opts: &EquivalenceOptions) -> EquivalenceResult {
+    let verdict = verify_equality(a, b);
+    if verdict != VerifyResult::Unknown {
+        return EquivalenceResult {
+            verdict,
+            method: Method::Reduction,
+            counterexample: None,
+        };
+    }
+    if a.num_qubits <= opts.max_tensor_qubits {
+        compare_tensors(a, b)
+    } else {
+        compare_samples(a, b, opts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::circuit::optimize::basic_optimize;
+    use crate::zx::generate::random_clifford_t_circuit;
+    use crate::zx::random::Seed;
+
+    #[test]
+    fn equal_circuits_written_differently() {
+        // A CZ between Hadamards on the target is a CNOT.
+        let a = Circuit::from_gates(2, &[Gate::Cx(0, 1), Gate::T(0)]);
+        let b = Circuit::from_gates(2, &[Gate::H(1), Gate::T(0), Gate::Cz(0, 1), Gate::H(1)]);
+        assert_eq!(verify_equality(&a, &b), VerifyResult::Equal);
+        // Three CNOTs make a swap.
+        let a = Circuit::from_gates(2, &[Gate::Cx(0, 1), Gate::Cx(1, 0), Gate::Cx(0, 1)]);
+        let b = Circuit::from_gates(2, &[Gate::Swap(0, 1)]);
+        assert_eq!(verify_equality(&a, &b), VerifyResult::Equal);
+        let a = Circuit::from_gates(1, &[Gate::T(0), Gate::T(0)]);
+        let b = Circuit::from_gates(1, &[Gate::S(0)]);
+        assert_eq!(verify_equality(&a, &b), VerifyResult::Equal);
+        let a = Circuit::from_gates(3, &[Gate::Ccx(0, 1, 2)]);
+        let b = Circuit::from_gates(3, &[Gate::H(2), Gate::Ccz(1, 0, 2), Gate::H(2)]);
+        assert_eq!(verify_equality(&a, &b), VerifyResult::Equal);
+    }
+
+    #[test]
+    fn circuits_differing_by_a_phase() {
+        let a = Circuit::from_gates(2, &[Gate::Cx(0, 1), Gate::T(0)]);
+        let b = Circuit::from_gates(2, &[Gate::Cx(0, 1), Gate::Tdg(0)]);
+        assert_eq!(verify_equality(&a, &b), VerifyResult::NotEqual);
+        let a = Circuit::from_gates(1, &[Gate::H(0), Gate::Rz(0, Phase::new(1, 8)), Gate::H(0)]);
+        let b = Circuit::from_gates(1, &[Gate::Rx(0, Phase::new(3, 8))]);
+        assert_eq!(verify_equality(&a, &b), VerifyResult::NotEqual);
+        let a = Circuit::from_gates(2, &[Gate::Cx(0, 1)]);
+        let b = Circuit::from_gates(2, &[Gate::Cx(1, 0)]);
+        assert_ne!(verify_equality(&a, &b), VerifyResult::Equal);
+        assert_eq!(
+            verify_equality(&Circuit::from_gates(1, &[]), &Circuit::from_gates(2, &[])),
+            VerifyResult::NotEqual
+        );
+    }
+
+    #[test]
+    fn circuits_differing_by_a_global_phase() {
+        // XZ = -ZX
+        let a = Circuit::from_gates(1, &[Gate::X(0), Gate::Z(0)]);
+        let b = Circuit::from_gates(1, &[Gate::Z(0), Gate::X(0)]);
+        assert_eq!(
+            verify_equality(&a, &b),
+            VerifyResult::EqualUpToGlobalPhase(Phase::pi())
+        );
+        // Rotations are spiders, so HSH is exactly `Rx(π/2)`.
+        let a = Circuit::from_gates(1, &[Gate::H(0), Gate::S(0), Gate::H(0)]);
+        let b = Circuit::from_gates(1, &[Gate::Rx(0, Phase::new(1, 2))]);
+        assert_eq!(verify_equality(&a, &b), VerifyResult::Equal);
+    }
+
+    #[test]
+    fn optimized_random_circuits() {
+        for seed in 0..5 {
+            let c = random_clifford_t_circuit(3, 30, 0.3, Seed(seed));
+            assert_eq!(verify_equality(&c, &c), VerifyResult::Equal);
+            let mut optimized = c.clone();
+            basic_optimize(&mut optimized);
+            assert_eq!(verify_equality(&c, &optimized), VerifyResult::Equal);
+        }
+    }
+
+    /// A random Clifford+T circuit and a copy with one of its T gates turned into a T dagger
+    fn one_phase_apart(num_qubits: u32, seed: u64) -> (Circuit, Circuit) {
+        let a = random_clifford_t_circuit(num_qubits, 40, 0.3, Seed(seed));
+        let mut b = a.clone();
+        let t = b
+            .gates
+            .iter()
+            .rposition(|g| matches!(g, Gate::T(_)))
+            .unwrap();
+        if let Gate::T(q) = b.gates[t] {
+            b.gates[t] = Gate::Tdg(q);
+        }
+        (a, b)
+    }
+
+    #[test]
+    fn equivalent_optimized_circuits() {
+        for seed in 0..3 {
+            let c = random_clifford_t_circuit(4, 40, 0.3, Seed(seed));
+            let mut optimized = c.clone();
+            basic_optimize(&mut optimized);
+            let result = equivalent(&c, &optimized, &EquivalenceOptions::default());
+            assert_eq!(result.verdict, VerifyResult::Equal);
+            assert_eq!(result.method, Method::Reduction);
+            assert_eq!(result.counterexample, None);
+        }
+    }
+
+    #[test]
+    fn inequivalent_circuits_one_phase_apart() {
+        let (a, b) = one_phase_apart(3, 1);
+        assert_eq!(verify_equality(&a, &b), VerifyResult::Unknown);
+        let result = equivalent(&a, &b, &EquivalenceOptions::default());
+        assert_eq!(result.verdict, VerifyResult::NotEqual);
+        assert_eq!(result.method, Method::Tensor);
+        assert!(result.counterexample.is_some());
+
+        let opts = EquivalenceOptions {
+            max_tensor_qubits: 0,
+            ..EquivalenceOptions::default()
+        };
+        let result = equivalent(&a, &b, &opts);
+        assert_eq!(result.verdict, VerifyResult::NotEqual);
+        assert_eq!(result.method, Method::Sampling);
+        // The counterexample holds the true amplitudes of both circuits.
+        let c = result.counterexample.unwrap();
+        let index = |bits: &[bool]| bits.iter().fold(0, |i, &b| i << 1 | b as usize);
+        let (i, o) = (index(&c.input), index(&c.output));
+        let ta = to_tensor(&a.to_graph::<GGraph>());
+        let tb = to_tensor(&b.to_graph::<GGraph>());
+        assert!((ta.entry(i, o) - c.amplitude_a).norm() < 1e-9);
+        assert!((tb.entry(i, o) - c.amplitude_b).norm() < 1e-9);
+    }
+
+    #[test]
+    fn fallbacks_agree_on_equivalent_circuits() {
+        // XZ = -ZX, which the tensors see as a global phase and sampling cannot refute.
+        let a = Circuit::from_gates(2, &[Gate::H(0), Gate::X(0), Gate::Z(0), Gate::Cx(0, 1)]);
+        let b = Circuit::from_gates(2, &[Gate::H(0), Gate::Z(0), Gate::X(0), Gate::Cx(0, 1)]);
+        assert_eq!(
+            compare_tensors(&a, &b).verdict,
+            VerifyResult::EqualUpToGlobalPhase(Phase::pi())
+        );
+        let result = compare_samples(&a, &b, &EquivalenceOptions::default());
+        assert_eq!(result.verdict, VerifyResult::Unknown);
+        assert_eq!(result.counterexample, None);
+    }
+
+    #[test]
+    fn large_clifford_circuits_reduce() {
+        // Far too many qubits for tensors or sampling to be conclusive.
+        let a = random_clifford_t_circuit(40, 400, 0.0, Seed(1));
+        let mut b = a.clone();
+        basic_optimize(&mut b);
+        let result = equivalent(&a, &b, &EquivalenceOptions::default());
+        assert_eq!(result.verdict, VerifyResult::Equal);
+        assert_eq!(result.method, Method::Reduction);
+    }
+}
@@ -0,0 +1,366 @@
+//! Differences between two diagrams, to see what a rewrite changed.
+//!
+//! `diff` compares two graphs of the same type by vertex index, which is meaningful when the
+//! indices are stable across the change, as with `StableGGraph` and `VecGraph`. `GGraph`
+//! renumbers vertices on removal (see `Graph::remove_vertex`), and graphs of different types do
+//! not share indices, so those are compared with `diff_with` and an explicit mapping.
+
+use crate::zx::graph::*;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Debug};
+
+/// A vertex of one of the diagrams, with its kind and phase
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VertexEntry<V> {
+    pub vertex: V,
+    pub kind: VertexKind,
+    pub phase: Phase,
+}
+
+/// Formatted like the start of a line of `Graph::dump`, with the index of the vertex
+impl<V: Debug> fmt::Display for VertexEntry<V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}: {}", self.vertex, self.kind)?;
+        if self.phase != self.kind.default_phase() {
+            write!(f, "({})", self.phase)?;
+        }
+        Ok(())
+    }
+}
+
+/// An edge of one of the diagrams, with its kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EdgeEntry<V> {
+    pub endpoints: (V, V),
+    pub kind: EdgeKind,
+}
+
+impl<V: Debug> fmt::Display for EdgeEntry<V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (a, b) = &self.endpoints;
+        write!(f, "{:?} -{}- {:?}", a, self.kind, b)
+    }
+}
+
+/// A vertex present in both diagrams whose kind or phase changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VertexChange<VA, VB> {
+    pub from: VertexEntry<VA>,
+    pub to: VertexEntry<VB>,
+}
+
+/// Differences from a diagram `a` to a diagram `b`, see `diff` and `diff_with`.
+///
+/// Removed vertices and edges are given by their indices in `a`, and added ones by their
+/// indices in `b`. Vertices are sorted by index and edges by endpoints. Edges are compared as
+/// a multiset, so adding a parallel edge shows up as one added edge.
+///
+/// The `Display` output is in the style of a unified diff: a `-` line for each removed vertex
+/// or edge, a `+` line for each added one, and a `-` and `+` pair for each changed vertex and
+/// for the scalar, under a `@@` header for each non-empty section. Equal diagrams print
+/// nothing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphDiff<VA, VB = VA> {
+    pub removed_vertices: Vec<VertexEntry<VA>>,
+    pub added_vertices: Vec<VertexEntry<VB>>,
+    pub changed_vertices: Vec<VertexChange<VA, VB>>,
+    pub removed_edges: Vec<EdgeEntry<VA>>,
+    pub added_edges: Vec<EdgeEntry<VB>>,
+    /// The scalars of `a` and `b`, if they differ
+    pub scalar: Option<(Scalar, Scalar)>,
+}
+
+impl<VA, VB> GraphDiff<VA, VB> {
+    /// Whether the diagrams are equal, up to the mapping of the vertices
+    pub fn is_empty(&self) -> bool {
+        self.removed_vertices.is_empty()
+            && self.added_vertices.is_empty()
+            && self.changed_vertices.is_empty()
+            && self.removed_edges.is_empty()
+            && self.added_edges.is_empty()
+            && self.scalar.is_none()
+    }
+}
+
+impl<VA: Debug, VB: Debug> fmt::Display for GraphDiff<VA, VB> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.removed_vertices.is_empty()
+            || !self.added_vertices.is_empty()
+            || !self.changed_vertices.is_empty()
+        {
+            writeln!(f, "@@ vertices @@")?;
+            for v in &self.removed_vertices {
+                writeln!(f, "-{}", v)?;
+            }
+            for c in &self.changed_vertices {
+                writeln!(f, "-{}", c.from)?;
+                writeln!(f, "+{}", c.to)?;
+            }
+            for v in &self.added_vertices {
+                writeln!(f, "+{}", v)?;
+            }
+        }
+        if !self.removed_edges.is_empty() || !self.added_edges.is_empty() {
+            writeln!(f, "@@ edges @@")?;
+            for e in &self.removed_edges {
+                writeln!(f, "-{}", e)?;
+            }
+            for e in &self.added_edges {
+                writeln!(f, "+{}", e)?;
+            }
+        }
+        if let Some((a, b)) = &self.scalar {
+            writeln!(f, "@@ scalar @@")?;
+            writeln!(f, "-{}", a)?;
+            writeln!(f, "+{}", b)?;
+        }
+        Ok(())
+    }
+}
+
+fn vertex_entry<G: Graph>(g: &G, v: G::VertexIx) -> VertexEntry<G::VertexIx> {
+    let vdata = g.vertex(v).unwrap();
+    VertexEntry {
+        vertex: v,
+        kind: vdata.kind(),
+        phase: vdata.phase(),
+    }
+}
+
+/// Endpoints of an undirected edge, smallest first
+fn ordered<V: Ord>(x: V, y: V) -> (V, V) {
+    if x <= y {
+        (x, y)
+    } else {
+        (y, x)
+    }
+}
+
+/// Differences from `a` to `b`, identifying the vertices with the same index.
+///
+/// A vertex whose index is only in `a` is removed, and one only in `b` is added. See
+/// `GraphDiff`.
+pub fn diff<G: Graph>(a: &G, b: &G) -> GraphDiff<G::VertexIx> {
+    let mapping: HashMap<_, _> = a
+        .vertices()
+        .filter(|&v| b.vertex(v).is_some())
+        .map(|v| (v, v))
+        .collect();
+    diff_with(a, b, &mapping)
+}
+
+/// Differences from `a` to `b`, identifying each vertex of `a` with its image by `mapping`.
+///
+/// Vertices of `a` missing from `mapping` are removed, and vertices of `b` outside its image are
+/// added. The mapping must be injective and only give vertices of `b`. See `GraphDiff`.
+pub fn diff_with<A: Graph, B: Graph>(
+    a: &A,
+    b: &B,
+    mapping: &HashMap<A::VertexIx, B::VertexIx>,
+) -> GraphDiff<A::VertexIx, B::VertexIx> {
+    let mut removed_vertices = Vec::new();
+    let mut changed_vertices = Vec::new();
+    let mut vertices: Vec<_> = a.vertices().collect();
+    vertices.sort_unstable();
+    for v in vertices {
+        let from = vertex_entry(a, v);
+        match mapping.get(&v) {
+            None => removed_vertices.push(from),
+            Some(&u) => {
+                let to = vertex_entry(b, u);
+                if (from.kind, from.phase) != (to.kind, to.phase) {
+                    changed_vertices.push(VertexChange { from, to });
+                }
+            }
+        }
+    }
+    let image: HashSet<_> = mapping.values().copied().collect();
+    let mut added_vertices: Vec<_> = b
+        .vertices()
+        .filter(|v| !image.contains(v))
+        .map(|v| vertex_entry(b, v))
+        .collect();
+    added_vertices.sort_unstable_by_key(|e| e.vertex);
+
+    // Edges of `a` between mapped vertices, by their endpoints in `b`, to be matched with the
+    // edges of `b`.
+    let mut removed_edges = Vec::new();
+    let mut unmatched: HashMap<_, Vec<_>> = HashMap::new();
+    for (x, y, edata) in a.edges_with_data() {
+        let kind = edata.kind();
+        let edge = EdgeEntry {
+            endpoints: ordered(x, y),
+            kind,
+        };
+        match (mapping.get(&x), mapping.get(&y)) {
+            (Some(&u), Some(&w)) => unmatched
+                .entry((ordered(u, w), kind))
+                .or_default()
+                .push(edge),
+            _ => removed_edges.push(edge),
+        }
+    }
+    let mut added_edges = Vec::new();
+    for (u, w, edata) in b.edges_with_data() {
+        let kind = edata.kind();
+        let key = (ordered(u, w), kind);
+        if unmatched.get_mut(&key).and_then(|es| es.pop()).is_none() {
+            added_edges.push(EdgeEntry {
+                endpoints: key.0,
+                kind,
+            });
+        }
+    }
+    removed_edges.extend(unmatched.into_values().flatten());
+    removed_edges.sort_by_key(|e| e.endpoints);
+    added_edges.sort_by_key(|e| e.endpoints);
+
+    let scalar = (a.scalar() != b.scalar()).then(|| (*a.scalar(), *b.scalar()));
+    GraphDiff {
+        removed_vertices,
+        added_vertices,
+        changed_vertices,
+        removed_edges,
+        added_edges,
+        scalar,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::simple_graph::GGraph;
+    use crate::zx::simplify::fuse_spiders;
+    use crate::zx::vec_graph::VecGraph;
+
+    /// `in -- Z(π/4) -- Z(π/2) -H- X -- out`
+    fn wire<G: Graph + Default>() -> (G, [G::VertexIx; 5]) {
+        let mut g = G::default();
+        let i = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        let a = g.add_vertex(VertexKind::Z, Phase::new(1, 4));
+        let b = g.add_vertex(VertexKind::Z, Phase::new(1, 2));
+        let x = g.add_vertex(VertexKind::X, Phase::zero());
+        let o = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        g.add_edge(i, a, EdgeKind::Regular);
+        g.add_edge(a, b, EdgeKind::Regular);
+        g.add_edge(b, x, EdgeKind::Hadam);
+        g.add_edge(x, o, EdgeKind::Regular);
+        g.set_input(i, true);
+        g.set_output(o, true);
+        (g, [i, a, b, x, o])
+    }
+
+    #[test]
+    fn one_fused_spider() {
+        let (g, [i, a, b, x, _]) = wire::<VecGraph>();
+        let mut fused = g.clone();
+        assert_eq!(fuse_spiders(&mut fused), 1);
+        let d = diff(&g, &fused);
+        // One of the two spiders is kept, with the sum of the phases, and takes over the edge of
+        // the other to the X spider.
+        let (kept, gone) = if fused.vertex(a).is_some() {
+            (a, b)
+        } else {
+            (b, a)
+        };
+        assert_eq!(d.removed_vertices, [vertex_entry(&g, gone)]);
+        assert!(d.added_vertices.is_empty());
+        assert_eq!(
+            d.changed_vertices,
+            [VertexChange {
+                from: vertex_entry(&g, kept),
+                to: vertex_entry(&fused, kept),
+            }]
+        );
+        assert_eq!(fused.vertex(kept).unwrap().phase(), Phase::new(3, 4));
+        assert_eq!(d.removed_edges.len(), 2);
+        assert!(d.removed_edges.contains(&EdgeEntry {
+            endpoints: ordered(a, b),
+            kind: EdgeKind::Regular,
+        }));
+        let (end, kind) = if kept == a {
+            (x, EdgeKind::Hadam)
+        } else {
+            (i, EdgeKind::Regular)
+        };
+        assert_eq!(
+            d.added_edges,
+            [EdgeEntry {
+                endpoints: ordered(kept, end),
+                kind,
+            }]
+        );
+        assert_eq!(d.scalar, None);
+        assert!(!d.is_empty());
+        assert!(diff(&g, &g).is_empty());
+        assert_eq!(diff(&g, &g).to_string(), "");
+    }
+
+    #[test]
+    fn display() {
+        let (g, [_, a, b, x, o]) = wire::<VecGraph>();
+        let mut h = g.clone();
+        h.set_phase(a, Phase::new(3, 4));
+        // Added before the removal, so that it does not reuse the index of `b`.
+        let extra = h.add_vertex(VertexKind::HBox, Phase::pi());
+        h.add_edge(extra, o, EdgeKind::Regular);
+        h.remove_vertex(b);
+        h.add_edge(a, x, EdgeKind::Hadam);
+        h.scalar_mut().add_power(-1);
+        let expected = format!(
+            "@@ vertices @@\n\
+             -{b:?}: Z(π/2)\n\
+             -{a:?}: Z(π/4)\n\
+             +{a:?}: Z(3π/4)\n\
+             +{extra:?}: HBox\n\
+             @@ edges @@\n\
+             -{a:?} -R- {b:?}\n\
+             -{b:?} -H- {x:?}\n\
+             +{a:?} -H- {x:?}\n\
+             +{o:?} -R- {extra:?}\n\
+             @@ scalar @@\n\
+             -{}\n\
+             +{}\n",
+            g.scalar(),
+            h.scalar(),
+        );
+        assert_eq!(diff(&g, &h).to_string(), expected);
+    }
+
+    #[test]
+    fn across_backends() {
+        let (a, va) = wire::<GGraph>();
+        let (mut b, vb) = wire::<VecGraph>();
+        let mapping: HashMap<_, _> = va.iter().copied().zip(vb.iter().copied()).collect();
+        assert!(diff_with(&a, &b, &mapping).is_empty());
+
+        b.set_vertex_kind(vb[3], VertexKind::Z);
+        let d = diff_with(&a, &b, &mapping);
+        assert_eq!(d.changed_vertices.len(), 1);
+        assert_eq!(d.changed_vertices[0].from.vertex, va[3]);
+        assert_eq!(d.changed_vertices[0].to.vertex, vb[3]);
+        assert_eq!(d.changed_vertices[0].to.kind, VertexKind::Z);
+
+        // Unmapped vertices are removed and added, with their edges, and parallel edges count
+        // as many times as they appear.
+        let partial: HashMap<_, _> = mapping
+            .iter()
+            .filter(|(&v, _)| v != va[0])
+            .map(|(&v, &u)| (v, u))
+            .collect();
+        let d = diff_with(&a, &b, &partial);
+        assert_eq!(d.removed_vertices.len(), 1);
+        assert_eq!(d.added_vertices.len(), 1);
+        assert_eq!((d.removed_edges.len(), d.added_edges.len()), (1, 1));
+        b.add_edge(vb[1], vb[2], EdgeKind::Regular);
+        let d = diff_with(&a, &b, &mapping);
+        assert_eq!(
+            d.added_edges,
+            [EdgeEntry {
+                endpoints: ordered(vb[1], vb[2]),
+                kind: EdgeKind::Regular,
+            }]
+        );
+        assert!(d.removed_edges.is_empty());
+    }
+}
@@ -0,0 +1,390 @@
+//! Causal flow and gflow of graph-like diagrams.
+//!
+//! A graph-like diagram is read as an open graph for measurement-based computation: its Z
+//! spiders are the qubits, the Hadamard edges between them are the graph edges, and the spiders
+//! connected to input and output boundaries are the input and output qubits. Every non-output
+//! qubit is measured in the XY plane. Grounded spiders are treated as outputs, since they need
+//! no correction.
+//!
+//! Both flows are computed backwards from the outputs, one layer at a time. Layer 0 holds the
+//! outputs, and every other vertex is in the layer after the latest vertex it is corrected by.
+
+use crate::linalg::Mat2;
+use crate::zx::graph::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Debug;
+
+/// Causal flow of an open graph.
+///
+/// Each measured vertex `v` is corrected by its successor `f(v)`, a neighbour of `v` in an
+/// earlier layer such that every other neighbour of `f(v)` is in a later layer than `v`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CausalFlow<V: Ord> {
+    /// Successor of each non-output vertex
+    pub successor: BTreeMap<V, V>,
+    /// Layer of each vertex, counted from the outputs
+    pub layer: BTreeMap<V, u32>,
+}
+
+/// Generalized flow of an open graph.
+///
+/// Each measured vertex `v` is corrected by a set of vertices in earlier layers, none of them
+/// an input, whose odd neighbourhood contains `v` and otherwise only vertices in earlier layers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GFlow<V: Ord> {
+    /// Correction set of each non-output vertex, sorted
+    pub correction: BTreeMap<V, Vec<V>>,
+    /// Layer of each vertex, counted from the outputs
+    pub layer: BTreeMap<V, u32>,
+}
+
+impl<V: Ord + Copy> CausalFlow<V> {
+    /// Vertices of each layer, starting from the outputs
+    pub fn layers(&self) -> Vec<Vec<V>> {
+        group_layers(&self.layer)
+    }
+
+    /// Number of layers
+    pub fn depth(&self) -> u32 {
+        self.layer.values().max().map_or(0, |&l| l + 1)
+    }
+}
+
+impl<V: Ord + Copy> GFlow<V> {
+    /// Vertices of each layer, starting from the outputs
+    pub fn layers(&self) -> Vec<Vec<V>> {
+        group_layers(&self.layer)
+    }
+
+    /// Number of layers
+    pub fn depth(&self) -> u32 {
+        self.layer.values().max().map_or(0, |&l| l + 1)
+    }
+}
+
+impl<V: Ord + Copy> From<CausalFlow<V>> for GFlow<V> {
+    /// A causal flow is a gflow with single-vertex correction sets.
+    fn from(flow: CausalFlow<V>) -> Self {
+        GFlow {
+            correction: flow
+                .successor
+                .into_iter()
+                .map(|(v, s)| (v, vec![s]))
+                .collect(),
+            layer: flow.layer,
+        }
+    }
+}
+
+fn group_layers<V: Ord + Copy>(layer: &BTreeMap<V, u32>) -> Vec<Vec<V>> {
+    let mut res = Vec::new();
+    for (&v, &l) in layer {
+        if res.len() <= l as usize {
+            res.resize(l as usize + 1, Vec::new());
+        }
+        res[l as usize].push(v);
+    }
+    res
+}
+
+/// The open graph of a graph-like diagram
+struct OpenGraph<V> {
+    adjacency: BTreeMap<V, BTreeSet<V>>,
+    inputs: BTreeSet<V>,
+    outputs: BTreeSet<V>,
+}
+
+impl<V: Ord + Copy + Debug> OpenGraph<V> {
+    /// Read the open graph of a diagram. Panics if the diagram is not graph-like.
+    fn new<G: Graph<VertexIx = V>>(g: &G) -> Self {
+        let kind = |v| g.vertex(v).unwrap().kind();
+        let mut res = OpenGraph {
+            adjacency: BTreeMap::new(),
+            inputs: BTreeSet::new(),
+            outputs: BTreeSet::new(),
+        };
+        for v in g.vertices() {
+            match kind(v) {
+                VertexKind::Z => {
+                    res.adjacency.insert(v, BTreeSet::new());
+                    if g.is_ground(v) {
+                        res.outputs.insert(v);
+                    }
+                }
+                VertexKind::Boundary => assert!(
+                    g.vertex_degree(v) <= 1,
+                    "boundary vertex {:?} has more than one neighbour",
+                    v
+                ),
+                k => panic!("flows need a graph-like diagram, found a {:?} vertex", k),
+            }
+        }
+        for e in g.edges() {
+            let (v, u) = g.edge_endpoints(e).unwrap();
+            match (kind(v), kind(u)) {
+                (VertexKind::Z, VertexKind::Z) => {
+                    assert!(
+                        v != u && g.edge(e).unwrap().kind() == EdgeKind::Hadam,
+                        "flows need a graph-like diagram, found a regular edge or a self-loop"
+                    );
+                    res.adjacency.get_mut(&v).unwrap().insert(u);
+                    res.adjacency.get_mut(&u).unwrap().insert(v);
+                }
+                (VertexKind::Z, _) | (_, VertexKind::Z) => {
+                    let (spider, boundary) = if kind(v) == VertexKind::Z {
+                        (v, u)
+                    } else {
+                        (u, v)
+                    };
+                    if g.is_input(boundary) {
+                        res.inputs.insert(spider);
+                    }
+                    if g.is_output(boundary) {
+                        res.outputs.insert(spider);
+                    }
+                }
+                _ => {}
+            }
+        }
+        res
+    }
+
+    fn neighbours(&self, v: V) -> &BTreeSet<V> {
+        &self.adjacency[&v]
+    }
+}
+
+/// Whether a diagram is graph-like, as needed by `causal_flow` and `gflow`: only Z spiders and
+/// boundaries, Hadamard edges between distinct spiders, and boundaries with at most one
+/// neighbour.
+pub fn is_graph_like<G: Graph>(g: &G) -> bool {
+    let kind = |v| g.vertex(v).unwrap().kind();
+    g.vertices().all(|v| match kind(v) {
+        VertexKind::Z => true,
+        VertexKind::Boundary => g.vertex_degree(v) <= 1,
+        _ => false,
+    }) && g.edges().all(|e| {
+        let (v, u) = g.edge_endpoints(e).unwrap();
+        kind(v) != VertexKind::Z
+            || kind(u) != VertexKind::Z
+            || (v != u && g.edge(e).unwrap().kind() == EdgeKind::Hadam)
+    })
+}
+
+/// Find a causal flow of a graph-like diagram, if it has one.
+///
+/// Uses the layer-by-layer algorithm of Mhalla and Perdrix, in time `O(V * E)`. Panics if the
+/// diagram is not graph-like: only Z spiders and boundaries, Hadamard edges between the spiders,
+/// and boundaries with at most one neighbour.
+pub fn causal_flow<G: Graph>(g: &G) -> Option<CausalFlow<G::VertexIx>> {
+    let og = OpenGraph::new(g);
+    let mut layer: BTreeMap<_, _> = og.outputs.iter().map(|&v| (v, 0)).collect();
+    let mut successor = BTreeMap::new();
+    // Vertices that may still correct an unprocessed neighbour
+    let mut correctors: BTreeSet<_> = og.outputs.difference(&og.inputs).copied().collect();
+    let mut k = 1;
+    loop {
+        let mut used = Vec::new();
+        let mut corrected = Vec::new();
+        for &c in &correctors {
+            let mut unprocessed = og.neighbours(c).iter().filter(|u| !layer.contains_key(*u));
+            if let (Some(&u), None) = (unprocessed.next(), unprocessed.next()) {
+                if successor.contains_key(&u) {
+                    continue;
+                }
+                successor.insert(u, c);
+                used.push(c);
+                corrected.push(u);
+            }
+        }
+        if corrected.is_empty() {
+            return (layer.len() == og.adjacency.len()).then_some(CausalFlow { successor, layer });
+        }
+        for c in used {
+            correctors.remove(&c);
+        }
+        for u in corrected {
+            layer.insert(u, k);
+            if !og.inputs.contains(&u) {
+                correctors.insert(u);
+            }
+        }
+        k += 1;
+    }
+}
+
+/// Find a gflow of a graph-like diagram, if it has one.
+///
+/// Uses the backwards algorithm of Mhalla and Perdrix: at each step, the unprocessed vertices
+/// whose correction can be solved over GF(2) using only processed non-input vertices form the
+/// next layer. The result has the minimal depth among the gflows of the diagram. The number of
+/// inputs and outputs may differ. Panics if the diagram is not graph-like, see `causal_flow`.
+pub fn gflow<G: Graph>(g: &G) -> Option<GFlow<G::VertexIx>> {
+    let og = OpenGraph::new(g);
+    let mut layer: BTreeMap<_, _> = og.outputs.iter().map(|&v| (v, 0)).collect();
+    let mut correction = BTreeMap::new();
+    let mut k = 1;
+    loop {
+        // Processed non-inputs with an unprocessed neighbour are the useful correctors, and the
+        // unprocessed vertices next to them are the candidates for the next layer.
+        let correctors: Vec<_> = layer
+            .keys()
+            .copied()
+            .filter(|v| !og.inputs.contains(v))
+            .filter(|&v| og.neighbours(v).iter().any(|u| !layer.contains_key(u)))
+            .collect();
+        let corrector_set: BTreeSet<_> = correctors.iter().copied().collect();
+        let candidates: Vec<_> = og
+            .adjacency
+            .keys()
+            .copied()
+            .filter(|v| !layer.contains_key(v))
+            .filter(|&v| !og.neighbours(v).is_disjoint(&corrector_set))
+            .collect();
+
+        // Row `i` is the equation for the parity of candidate `i`: the correction set must have
+        // an odd number of neighbours of the candidate being corrected and an even number of
+        // neighbours of every other candidate.
+        let mut m = Mat2::from_fn(candidates.len(), correctors.len(), |i, j| {
+            og.neighbours(candidates[i]).contains(&correctors[j])
+        });
+        let mut ops = Vec::new();
+        let rank = m.gauss(true, Some(&mut ops));
+        // Replaying the row operations on the identity gives `t` with `t * matrix == m`, so the
+        // right-hand side for candidate `i` becomes column `i` of `t`.
+        let mut t = Mat2::identity(candidates.len());
+        for op in ops {
+            t.row_add(op.src, op.dst);
+        }
+        let pivots: Vec<_> = (0..rank)
+            .map(|r| (0..correctors.len()).find(|&c| m.get(r, c)).unwrap())
+            .collect();
+        let mut corrected = Vec::new();
+        for (i, &v) in candidates.iter().enumerate() {
+            if (rank..candidates.len()).any(|r| t.get(r, i)) {
+                continue;
+            }
+            // Setting the free variables to 0 leaves one pivot variable per row.
+            let set = (0..rank)
+                .filter(|&r| t.get(r, i))
+                .map(|r| correctors[pivots[r]])
+                .collect();
+            correction.insert(v, set);
+            corrected.push(v);
+        }
+        if corrected.is_empty() {
+            return (layer.len() == og.adjacency.len()).then_some(GFlow { correction, layer });
+        }
+        for v in corrected {
+            layer.insert(v, k);
+        }
+        k += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::simple_graph::GGraph;
+
+    type V = <GGraph as Graph>::VertexIx;
+
+    /// Graph-like diagram on `n` Z spiders with Hadamard edges between them, and a boundary on
+    /// each input and output spider. Returns the diagram and its spiders.
+    fn open_graph(
+        n: usize,
+        edges: &[(usize, usize)],
+        inputs: &[usize],
+        outputs: &[usize],
+    ) -> (GGraph, Vec<V>) {
+        let mut g: GGraph = GGraph::new();
+        let vs: Vec<_> = (0..n)
+            .map(|_| g.add_vertex(VertexKind::Z, Phase::zero()))
+            .collect();
+        for &(a, b) in edges {
+            g.add_edge(vs[a], vs[b], EdgeKind::Hadam);
+        }
+        for &i in inputs {
+            let b = g.add_vertex(VertexKind::Boundary, Phase::zero());
+            g.add_edge(b, vs[i], EdgeKind::Regular);
+            g.set_input(b, true);
+        }
+        for &o in outputs {
+            let b = g.add_vertex(VertexKind::Boundary, Phase::zero());
+            g.add_edge(vs[o], b, EdgeKind::Regular);
+            g.set_output(b, true);
+        }
+        (g, vs)
+    }
+
+    /// Check the conditions of a gflow: every correction set is made of non-inputs in earlier
+    /// layers, and its odd neighbourhood is the corrected vertex and vertices in earlier layers.
+    fn assert_gflow(g: &GGraph, flow: &GFlow<V>) {
+        let og = OpenGraph::new(g);
+        assert_eq!(flow.layer.len(), og.adjacency.len());
+        for (&v, set) in &flow.correction {
+            assert!(!og.outputs.contains(&v));
+            let mut odd = BTreeSet::new();
+            for w in set {
+                assert!(!og.inputs.contains(w), "input {:?} corrects {:?}", w, v);
+                assert!(flow.layer[w] < flow.layer[&v]);
+                for &u in og.neighbours(*w) {
+                    if !odd.insert(u) {
+                        odd.remove(&u);
+                    }
+                }
+            }
+            assert!(odd.contains(&v), "{:?} is not corrected by {:?}", v, set);
+            for u in odd.iter().filter(|&&u| u != v) {
+                assert!(flow.layer[u] < flow.layer[&v], "{:?} disturbs {:?}", set, u);
+            }
+        }
+        let outputs = flow.layer.values().filter(|&&l| l == 0).count();
+        assert_eq!(flow.correction.len(), flow.layer.len() - outputs);
+    }
+
+    #[test]
+    fn line_has_causal_flow() {
+        let (g, vs) = open_graph(4, &[(0, 1), (1, 2), (2, 3)], &[0], &[3]);
+        let flow = causal_flow(&g).unwrap();
+        for k in 0..3 {
+            assert_eq!(flow.successor[&vs[k]], vs[k + 1]);
+        }
+        assert_eq!(
+            flow.layers(),
+            vec![vec![vs[3]], vec![vs[2]], vec![vs[1]], vec![vs[0]]]
+        );
+        assert_eq!(flow.depth(), 4);
+
+        let gf = gflow(&g).unwrap();
+        assert_gflow(&g, &gf);
+        assert_eq!(gf, GFlow::from(flow));
+    }
+
+    #[test]
+    fn gflow_without_causal_flow() {
+        let edges = [(0, 2), (0, 3), (0, 4), (1, 3), (1, 4), (2, 3)];
+        let (g, _) = open_graph(5, &edges, &[0, 1], &[3, 4]);
+        assert_eq!(causal_flow(&g), None);
+        let flow = gflow(&g).unwrap();
+        assert_gflow(&g, &flow);
+        assert!(flow.correction.values().any(|set| set.len() > 1));
+    }
+
+    #[test]
+    fn no_gflow() {
+        // Two inputs merged into a single output
+        let (g, _) = open_graph(3, &[(0, 2), (1, 2)], &[0, 1], &[2]);
+        assert_eq!(causal_flow(&g), None);
+        assert_eq!(gflow(&g), None);
+    }
+
+    #[test]
+    fn more_outputs_than_inputs() {
+        // A cluster state prepared from one input, with four outputs
+        let (g, _) = open_graph(5, &[(0, 1), (0, 2), (1, 3), (2, 4)], &[0], &[1, 2, 3, 4]);
+        let flow = gflow(&g).unwrap();
+        assert_gflow(&g, &flow);
+        assert_eq!(flow.depth(), 2);
+    }
+}
@@ -0,0 +1,202 @@
+//! Random circuits and diagrams, for testing and benchmarking.
+//!
+//! All the generators take an explicit `Seed`, see `random`.
+
+use crate::zx::circuit::{Circuit, Gate};
+use crate::zx::graph::*;
+use crate::zx::random::Seed;
+use crate::zx::simple_graph::GGraph;
+use rand::Rng;
+
+/// Random Clifford circuit with `depth` gates, chosen uniformly among H, S, S-dagger, Z, X,
+/// CX and CZ on random qubits.
+///
+/// Panics if the circuit has no qubits but some gates.
+pub fn random_clifford_circuit(qubits: u32, depth: usize, seed: Seed) -> Circuit {
+    random_clifford_t_circuit(qubits, depth, 0.0, seed)
+}
+
+/// Random Clifford+T circuit with `depth` gates.
+///
+/// Each gate is a T or T-dagger with probability `t_probability`, and otherwise a Clifford gate
+/// as in `random_clifford_circuit`. Two-qubit gates are only used with at least two qubits.
+pub fn random_clifford_t_circuit(
+    qubits: u32,
+    depth: usize,
+    t_probability: f64,
+    seed: Seed,
+) -> Circuit {
+    assert!(
+        qubits > 0 || depth == 0,
+        "cannot place gates on a circuit without qubits"
+    );
+    let mut rng = seed.rng();
+    let mut circuit = Circuit::new(qubits);
+    let kinds = if qubits > 1 { 7 } else { 5 };
+    for _ in 0..depth {
+        let q = rng.gen_range(0..qubits);
+        let gate = if rng.gen_bool(t_probability) {
+            if rng.gen() {
+                Gate::T(q)
+            } else {
+                Gate::Tdg(q)
+            }
+        } else {
+            match rng.gen_range(0..kinds) {
+                0 => Gate::H(q),
+                1 => Gate::S(q),
+                2 => Gate::Sdg(q),
+                3 => Gate::Z(q),
+                4 => Gate::X(q),
+                k => {
+                    // Pick a second qubit different from the first.
+                    let t = (q + rng.gen_range(1..qubits)) % qubits;
+                    if k == 5 {
+                        Gate::Cx(q, t)
+                    } else {
+                        Gate::Cz(q, t)
+                    }
+                }
+            }
+        };
+        circuit.add_gate(gate);
+    }
+    circuit
+}
+
+/// Random graph-like diagram with `n_vertices` Z spiders, each with phase `π/2` or `-π/2`.
+///
+/// Each pair of spiders is connected by a Hadamard edge with probability `edge_prob`. Each
+/// spider is connected to an input boundary with probability 1/4, and otherwise to an output
+/// boundary with probability 1/3, so that about half of the spiders are interior.
+pub fn random_graph_like(n_vertices: usize, edge_prob: f64, seed: Seed) -> GGraph {
+    let mut rng = seed.rng();
+    let mut g = GGraph::new();
+    let spiders: Vec<_> = (0..n_vertices)
+        .map(|_| {
+            let phase = if rng.gen() {
+                Phase::new(1, 2)
+            } else {
+                Phase::new(-1, 2)
+            };
+            g.add_vertex(VertexKind::Z, phase)
+        })
+        .collect();
+    for (i, &v) in spiders.iter().enumerate() {
+        for &u in &spiders[i + 1..] {
+            if rng.gen_bool(edge_prob) {
+                g.add_edge(v, u, EdgeKind::Hadam);
+            }
+        }
+    }
+    for &v in &spiders {
+        let (input, output) = if rng.gen_bool(0.25) {
+            (true, false)
+        } else {
+            (false, rng.gen_bool(1.0 / 3.0))
+        };
+        if input || output {
+            let b = g.add_vertex(VertexKind::Boundary, Phase::zero());
+            g.set_input(b, input);
+            g.set_output(b, output);
+            g.add_edge(v, b, EdgeKind::Regular);
+        }
+    }
+    g
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::simplify::clifford_simp;
+    use crate::zx::tensor::assert_graph_eq;
+
+    type V = <GGraph as Graph>::VertexIx;
+
+    /// Kind, phase and neighbours of each vertex, in index order
+    fn dump(g: &GGraph) -> Vec<(VertexKind, Phase, Vec<V>)> {
+        g.vertices()
+            .map(|v| {
+                let vdata = g.vertex(v).unwrap();
+                (vdata.kind(), vdata.phase(), g.neighbours(v).collect())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn seeds_are_reproducible() {
+        let a = random_clifford_t_circuit(5, 100, 0.2, Seed(7));
+        assert_eq!(a, random_clifford_t_circuit(5, 100, 0.2, Seed(7)));
+        assert_ne!(a, random_clifford_t_circuit(5, 100, 0.2, Seed(8)));
+        assert_eq!(a.gates.len(), 100);
+
+        let g = random_graph_like(12, 0.3, Seed(7));
+        assert_eq!(dump(&g), dump(&random_graph_like(12, 0.3, Seed(7))));
+    }
+
+    #[test]
+    fn gate_distribution() {
+        let c = random_clifford_circuit(3, 200, Seed(1));
+        assert!(c
+            .gates
+            .iter()
+            .all(|g| !matches!(g, Gate::T(_) | Gate::Tdg(_))));
+        assert!(c.gates.iter().any(|g| matches!(g, Gate::Cx(..))));
+
+        let c = random_clifford_t_circuit(3, 200, 1.0, Seed(1));
+        assert!(c
+            .gates
+            .iter()
+            .all(|g| matches!(g, Gate::T(_) | Gate::Tdg(_))));
+
+        // A single qubit only gets single-qubit gates.
+        let c = random_clifford_t_circuit(1, 200, 0.5, Seed(1));
+        assert!(c.gates.iter().all(|g| g.qubits() == vec![0]));
+    }
+
+    #[test]
+    fn graph_like_diagrams() {
+        let g = random_graph_like(20, 0.4, Seed(2));
+        for v in g.vertices() {
+            let vdata = g.vertex(v).unwrap();
+            match vdata.kind() {
+                VertexKind::Z => {
+                    assert!([Phase::new(1, 2), Phase::new(3, 2)].contains(&vdata.phase()));
+                }
+                VertexKind::Boundary => {
+                    assert!(g.is_input(v) != g.is_output(v));
+                    assert_eq!(g.neighbours(v).count(), 1);
+                }
+                kind => panic!("unexpected {:?} vertex", kind),
+            }
+        }
+        for (v, u, edata) in g.edges_with_data() {
+            let boundary = [v, u]
+                .iter()
+                .any(|&w| g.vertex(w).unwrap().kind() == VertexKind::Boundary);
+            let expected = if boundary {
+                EdgeKind::Regular
+            } else {
+                EdgeKind::Hadam
+            };
+            assert_eq!(edata.kind(), expected);
+        }
+    }
+
+    #[test]
+    fn clifford_simp_preserves_random_diagrams() {
+        for seed in 0..24 {
+            let mut g: GGraph = random_clifford_t_circuit(4, 40, 0.2, Seed(seed)).to_graph();
+            let (original, tcount) = (g.clone(), g.tcount());
+            clifford_simp(&mut g);
+            // Fusion can merge T spiders, but never creates new ones.
+            assert!(g.tcount() <= tcount, "seed {}", seed);
+            assert_graph_eq(&g, &original);
+
+            let mut g = random_graph_like(10, 0.4, Seed(seed));
+            let original = g.clone();
+            clifford_simp(&mut g);
+            assert_graph_eq(&g, &original);
+        }
+    }
+}
@@ -1,9 +1,20 @@
+use crate::zx::stats::{GraphStats, WireStats};
 use counter::Counter;
-use fraction::Fraction;
+use fraction::{Fraction, ToPrimitive};
 use itertools::Itertools;
+use num_complex::Complex64;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryInto;
+use std::f64::consts::{PI, SQRT_2};
+use std::fmt::{self, Debug};
+use std::hash::Hash;
+use std::ops::{Add, AddAssign, Mul, MulAssign, Neg, Sub};
 
-/// Phase of a node, represented as a fraction of pi
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd)]
+/// Phase of a node, represented as a fraction of pi.
+///
+/// Phases are always normalized to the range [0, 2).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Hash)]
 pub struct Phase {
     f: Fraction,
 }
@@ -14,32 +25,536 @@ impl Default for Phase {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+impl From<Fraction> for Phase {
+    fn from(f: Fraction) -> Self {
+        Phase { f: normalize(f) }
+    }
+}
+
+/// Wrap a fraction into [0, 2)
+fn normalize(f: Fraction) -> Fraction {
+    let (num, den) = match (f.numer(), f.denom()) {
+        (Some(&num), Some(&den)) => (num as u128, den as u128),
+        // NaN and infinities are left as they are
+        _ => return f,
+    };
+    let mut num = num % (2 * den);
+    if f.is_sign_negative() && num != 0 {
+        num = 2 * den - num;
+    }
+    // `num < 2 * den`, so it fits in a u64 for any denominator below 2^63.
+    Fraction::new(num as u64, den as u64)
+}
+
+impl Phase {
+    /// The phase `num/den * pi`. Panics if `den` is zero.
+    pub fn new(num: i64, den: u64) -> Self {
+        assert!(den != 0, "phase with a zero denominator");
+        let f = Fraction::new(num.unsigned_abs(), den);
+        Phase::from(if num < 0 { -f } else { f })
+    }
+
+    pub fn zero() -> Self {
+        Phase::default()
+    }
+
+    pub fn pi() -> Self {
+        Phase::new(1, 1)
+    }
+
+    /// Numerator of the phase, as a fraction of pi in lowest terms
+    pub fn numerator(&self) -> u64 {
+        self.f.numer().copied().unwrap_or(0)
+    }
+
+    /// Denominator of the phase, as a fraction of pi
+    pub fn denominator(&self) -> u64 {
+        self.f.denom().copied().unwrap_or(1)
+    }
+
+    /// Value of the phase as a float, in multiples of pi
+    pub fn to_f64(&self) -> f64 {
+        self.f.to_f64().unwrap_or(f64::NAN)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        *self == Phase::zero()
+    }
+
+    /// Whether the phase is a multiple of pi
+    pub fn is_pauli(&self) -> bool {
+        self.denominator() == 1
+    }
+
+    /// Whether the phase is a multiple of pi/2
+    pub fn is_clifford(&self) -> bool {
+        self.denominator() <= 2
+    }
+
+    /// Whether the phase is an odd multiple of pi/4
+    pub fn is_proper_t(&self) -> bool {
+        self.denominator() == 4
+    }
+}
+
+/// Serialized as the pair `[numerator, denominator]` of the normalized fraction of pi.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Phase {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (self.numerator(), self.denominator()).serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Phase {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (num, den) = <(u64, u64)>::deserialize(deserializer)?;
+        if den == 0 {
+            return Err(serde::de::Error::custom("phase with a zero denominator"));
+        }
+        Ok(Phase::from(Fraction::new(num, den)))
+    }
+}
+
+/// Prints the phase as a multiple of π, such as `0`, `π`, `π/4` or `3π/2`. The alternate form
+/// `{:#}` prints the fraction of π alone, such as `1/4`.
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (num, den) = (self.numerator(), self.denominator());
+        if f.alternate() {
+            return write!(f, "{}", self.f);
+        }
+        match (num, den) {
+            (0, _) => write!(f, "0"),
+            (1, 1) => write!(f, "π"),
+            (1, den) => write!(f, "π/{}", den),
+            (num, 1) => write!(f, "{}π", num),
+            (num, den) => write!(f, "{}π/{}", num, den),
+        }
+    }
+}
+
+impl Add for Phase {
+    type Output = Phase;
+
+    fn add(self, rhs: Phase) -> Phase {
+        Phase::from(self.f + rhs.f)
+    }
+}
+
+impl AddAssign for Phase {
+    fn add_assign(&mut self, rhs: Phase) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub for Phase {
+    type Output = Phase;
+
+    fn sub(self, rhs: Phase) -> Phase {
+        Phase::from(self.f - rhs.f)
+    }
+}
+
+impl Neg for Phase {
+    type Output = Phase;
+
+    fn neg(self) -> Phase {
+        Phase::from(-self.f)
+    }
+}
+
+/// Global scalar factor of a diagram, `sqrt(2)^power2 * e^{i phase}`, or zero.
+///
+/// Rewrite rules that change the linear map of a diagram by a constant factor record it here,
+/// so the diagram keeps denoting the same map. The default is one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Scalar {
+    power2: i32,
+    phase: Phase,
+    is_zero: bool,
+}
+
+impl Scalar {
+    pub fn one() -> Self {
+        Scalar::default()
+    }
+
+    pub fn zero() -> Self {
+        Scalar {
+            is_zero: true,
+            ..Scalar::default()
+        }
+    }
+
+    /// The scalar `sqrt(2)^power2 * e^{i phase}`
+    pub fn new(power2: i32, phase: Phase) -> Self {
+        Scalar {
+            power2,
+            phase,
+            is_zero: false,
+        }
+    }
+
+    /// Power of sqrt(2) of the scalar, meaningless if it is zero
+    pub fn power2(&self) -> i32 {
+        self.power2
+    }
+
+    /// Phase of the scalar, meaningless if it is zero
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.is_zero
+    }
+
+    pub fn is_one(&self) -> bool {
+        *self == Scalar::one()
+    }
+
+    /// Multiply by `sqrt(2)^power2`
+    pub fn add_power(&mut self, power2: i32) {
+        self.power2 += power2;
+    }
+
+    /// Multiply by `e^{i phase}`
+    pub fn add_phase(&mut self, phase: Phase) {
+        self.phase += phase;
+    }
+
+    /// Complex conjugate of the scalar
+    pub fn conjugate(&self) -> Self {
+        Scalar {
+            phase: -self.phase,
+            ..*self
+        }
+    }
+
+    /// Value of the scalar as a complex number
+    pub fn to_complex(&self) -> Complex64 {
+        if self.is_zero {
+            return Complex64::new(0.0, 0.0);
+        }
+        Complex64::from_polar(&SQRT_2.powi(self.power2), &(PI * self.phase.to_f64()))
+    }
+}
+
+impl Mul for Scalar {
+    type Output = Scalar;
+
+    fn mul(self, rhs: Scalar) -> Scalar {
+        Scalar {
+            power2: self.power2 + rhs.power2,
+            phase: self.phase + rhs.phase,
+            is_zero: self.is_zero || rhs.is_zero,
+        }
+    }
+}
+
+impl MulAssign for Scalar {
+    fn mul_assign(&mut self, rhs: Scalar) {
+        *self = *self * rhs;
+    }
+}
+
+impl fmt::Display for Scalar {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_zero {
+            return write!(f, "0");
+        }
+        write!(
+            f,
+            "sqrt(2)^{} * e^(i*pi*{}/{})",
+            self.power2,
+            self.phase.numerator(),
+            self.phase.denominator()
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum VertexKind {
     Z,
     X,
+    #[default]
     Boundary,
+    /// H-box labelled `e^{i phase}`. An arity-2 H-box with phase pi is a Hadamard gate.
     HBox,
+    /// Placeholder for a sub-diagram, with its name and wire names in the vertex data, see
+    /// `Hole` and `CartesianGraph::substitute`. A hole has no tensor.
+    Hole,
 }
 
-impl Default for VertexKind {
-    fn default() -> Self {
-        VertexKind::Boundary
+impl fmt::Display for VertexKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VertexKind::Z => write!(f, "Z"),
+            VertexKind::X => write!(f, "X"),
+            VertexKind::Boundary => write!(f, "Boundary"),
+            VertexKind::HBox => write!(f, "HBox"),
+            VertexKind::Hole => write!(f, "Hole"),
+        }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+impl VertexKind {
+    /// Phase of a vertex of this kind when none is given: pi for H-boxes, labelling them with
+    /// -1 as in the ZH-calculus, and 0 otherwise.
+    pub fn default_phase(self) -> Phase {
+        match self {
+            VertexKind::HBox => Phase::pi(),
+            _ => Phase::zero(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum EdgeKind {
+    #[default]
     Regular,
     Hadam,
 }
 
-impl Default for EdgeKind {
-    fn default() -> Self {
-        EdgeKind::Regular
+/// Prints `R` for regular edges and `H` for Hadamard edges, as in `Graph::dump`.
+impl fmt::Display for EdgeKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EdgeKind::Regular => write!(f, "R"),
+            EdgeKind::Hadam => write!(f, "H"),
+        }
     }
 }
 
+/// Error produced when composing two diagrams with incompatible boundaries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComposeError {
+    /// The number of outputs of the first diagram differs from the number of inputs of the second
+    ArityMismatch { outputs: usize, inputs: usize },
+}
+
+impl fmt::Display for ComposeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ComposeError::ArityMismatch { outputs, inputs } => write!(
+                f,
+                "cannot compose a diagram with {} outputs with one with {} inputs",
+                outputs, inputs
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ComposeError {}
+
+/// Interface of a vertex of kind `VertexKind::Hole`: the name of the sub-diagram it stands for,
+/// and the names of its wires.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Hole {
+    pub name: String,
+    /// One name per incident edge, in the order described by `CartesianGraph::substitute`
+    pub wires: Vec<String>,
+}
+
+impl Hole {
+    pub fn new<S: Into<String>>(
+        name: impl Into<String>,
+        wires: impl IntoIterator<Item = S>,
+    ) -> Self {
+        Hole {
+            name: name.into(),
+            wires: wires.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Declared number of wires of the hole
+    pub fn arity(&self) -> usize {
+        self.wires.len()
+    }
+}
+
+/// Error produced by `CartesianGraph::substitute`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubstError {
+    /// The vertex is not of kind `VertexKind::Hole`, or has no `Hole` interface
+    NotAHole,
+    /// The hole does not have one incident edge per wire
+    ArityMismatch { wires: usize, edges: usize },
+    /// The implementation does not have one boundary per wire of the hole
+    BoundaryMismatch { wires: usize, boundaries: usize },
+    /// A name of the wire map is not a wire of the hole
+    UnknownWire { name: String },
+    /// A wire is named twice, by the hole or by the wire map
+    DuplicateWire { name: String },
+    /// A wire of the hole is not in the wire map
+    MissingWire { name: String },
+    /// A boundary index is not below the number of boundaries of the implementation
+    BoundaryOutOfRange { index: u32 },
+    /// Two wires are mapped to the same boundary
+    DuplicateBoundary { index: u32 },
+}
+
+impl fmt::Display for SubstError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SubstError::NotAHole => write!(f, "the vertex is not a hole"),
+            SubstError::ArityMismatch { wires, edges } => write!(
+                f,
+                "the hole has {} wires but {} incident edges",
+                wires, edges
+            ),
+            SubstError::BoundaryMismatch { wires, boundaries } => write!(
+                f,
+                "cannot fill a hole with {} wires with a diagram with {} boundaries",
+                wires, boundaries
+            ),
+            SubstError::UnknownWire { name } => write!(f, "the hole has no wire `{}`", name),
+            SubstError::DuplicateWire { name } => write!(f, "wire `{}` is named twice", name),
+            SubstError::MissingWire { name } => write!(f, "wire `{}` is not mapped", name),
+            SubstError::BoundaryOutOfRange { index } => {
+                write!(f, "the implementation has no boundary {}", index)
+            }
+            SubstError::DuplicateBoundary { index } => {
+                write!(f, "boundary {} is mapped twice", index)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SubstError {}
+
+/// Edge policy of a backend, see `Graph::mode`.
+///
+/// In `Multi` mode `add_edge` inserts edges as they are given, so the graph can have parallel
+/// edges and self-loops, which is what a user implementing their own rewrites may want. In
+/// `Simple` mode edges between Z and X spiders are merged on insertion following
+/// `Graph::add_edge_smart`, so there is at most one edge between two spiders and none from a
+/// spider to itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GraphMode {
+    /// Parallel edges and self-loops between spiders are merged by `add_edge`
+    Simple,
+    /// `add_edge` always inserts a new edge
+    #[default]
+    Multi,
+}
+
+/// Broken invariant of a diagram, found by `Graph::check_invariants`.
+///
+/// Each violation carries the offending vertex or edge, and its `Display` is the message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvariantViolation<V, E> {
+    /// A boundary that is not a bare wire does not have exactly one edge
+    BoundaryDegree { vertex: V, degree: u32 },
+    /// A boundary has a non-zero phase
+    BoundaryPhase { vertex: V, phase: Phase },
+    /// A boundary is both an input and an output but has edges, so it is not a bare wire
+    InputAndOutput { vertex: V, degree: u32 },
+    /// An input or output is not a vertex of the graph
+    UnknownBoundary { vertex: V },
+    /// An endpoint of an edge is not a vertex of the graph
+    UnknownEndpoint { edge: E },
+    /// A self-loop on a vertex other than a Z or X spider
+    SelfLoop { edge: E, vertex: V },
+    /// A self-loop on a spider of a graph in `GraphMode::Simple`
+    SpiderSelfLoop { edge: E, vertex: V },
+    /// A second edge between two spiders of a graph in `GraphMode::Simple`
+    ParallelEdge { edge: E, vertices: (V, V) },
+    /// An input is not on row 0, see `CartesianGraph::check_layout`
+    InputRow { vertex: V },
+    /// An output is not on the last row, see `CartesianGraph::check_layout`
+    OutputRow { vertex: V },
+}
+
+impl<V: Debug, E: Debug> fmt::Display for InvariantViolation<V, E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InvariantViolation::BoundaryDegree { vertex, degree } => write!(
+                f,
+                "boundary {:?} has {} edges instead of one",
+                vertex, degree
+            ),
+            InvariantViolation::BoundaryPhase { vertex, phase } => write!(
+                f,
+                "boundary {:?} has phase {}/{} pi",
+                vertex,
+                phase.numerator(),
+                phase.denominator()
+            ),
+            InvariantViolation::InputAndOutput { vertex, degree } => write!(
+                f,
+                "boundary {:?} is an input and an output but has {} edges",
+                vertex, degree
+            ),
+            InvariantViolation::UnknownBoundary { vertex } => {
+                write!(f, "input or output {:?} is not in the graph", vertex)
+            }
+            InvariantViolation::UnknownEndpoint { edge } => {
+                write!(
+                    f,
+                    "edge {:?} has an endpoint that is not in the graph",
+                    edge
+                )
+            }
+            InvariantViolation::SelfLoop { edge, vertex } => write!(
+                f,
+                "edge {:?} is a self-loop on {:?}, which is not a spider",
+                edge, vertex
+            ),
+            InvariantViolation::SpiderSelfLoop { edge, vertex } => write!(
+                f,
+                "edge {:?} is a self-loop on spider {:?} of a simple graph",
+                edge, vertex
+            ),
+            InvariantViolation::ParallelEdge {
+                edge,
+                vertices: (a, b),
+            } => write!(
+                f,
+                "edge {:?} is parallel to another edge between spiders {:?} and {:?}",
+                edge, a, b
+            ),
+            InvariantViolation::InputRow { vertex } => {
+                write!(f, "input {:?} is not on row 0", vertex)
+            }
+            InvariantViolation::OutputRow { vertex } => {
+                write!(f, "output {:?} is not on the last row", vertex)
+            }
+        }
+    }
+}
+
+impl<V: Debug, E: Debug> std::error::Error for InvariantViolation<V, E> {}
+
+/// Violations found by `Graph::check_invariants` on a graph of type `G`
+pub type Violations<G> = Vec<InvariantViolation<<G as Graph>::VertexIx, <G as Graph>::EdgeIx>>;
+
+/// Compile-time check that a type can be shared between threads.
+pub(crate) fn assert_send_sync<T: Send + Sync>() {}
+
+/// Types that can be shared between threads when the `rayon` feature is enabled, so that the
+/// simplification passes can match rewrites in parallel. Without the feature, every type
+/// implements it.
+#[cfg(feature = "rayon")]
+pub trait MaybeSync: Send + Sync {}
+
+#[cfg(feature = "rayon")]
+impl<T: Send + Sync + ?Sized> MaybeSync for T {}
+
+/// Types that can be shared between threads when the `rayon` feature is enabled, so that the
+/// simplification passes can match rewrites in parallel. Without the feature, every type
+/// implements it.
+#[cfg(not(feature = "rayon"))]
+pub trait MaybeSync {}
+
+#[cfg(not(feature = "rayon"))]
+impl<T: ?Sized> MaybeSync for T {}
+
 /// Attributes of a graph's vertex
 pub trait VData {
     fn phase(&self) -> Phase;
@@ -60,16 +575,50 @@ pub trait EData {
 ///
 /// Both vertices and edges have an specified kind and associated data.
 /// Vertices can be marked as inputs and outputs of the graph.
-pub trait Graph<'a> {
-    type VertexIx: Copy;
-    type EdgeIx: Copy;
+///
+/// All the read-only methods take `&self`, so a graph can be shared between threads when the
+/// implementation is `Sync`. Implementations should not use interior mutability (e.g. lazily
+/// filled caches) behind `&self`.
+///
+/// Iteration order must be deterministic: two graphs built by the same sequence of operations
+/// yield their vertices, edges, neighbours, inputs and outputs in the same order. Code that
+/// walks a graph should keep this property, iterating over ordered collections (or sorting)
+/// instead of hash maps whenever the order affects the result.
+///
+/// With the `rayon` feature, graphs and their indices must also be `Send + Sync`, see
+/// `MaybeSync`.
+pub trait Graph: MaybeSync {
+    type VertexIx: Copy + Eq + Ord + Hash + Debug + MaybeSync;
+    type EdgeIx: Copy + Eq + Ord + Hash + Debug + MaybeSync;
     type VertexData: VData;
     type EdgeData: EData;
-    type EdgeIterator: Iterator<Item = Self::EdgeIx>;
-    type VertexIterator: Iterator<Item = Self::VertexIx>;
-    type NeighbourIterator: Iterator<Item = Self::VertexIx>;
-    type InputIterator: Iterator<Item = Self::EdgeIx>;
-    type OutputIterator: Iterator<Item = Self::VertexIx>;
+    type EdgeIterator<'a>: Iterator<Item = Self::EdgeIx>
+    where
+        Self: 'a;
+    type VertexIterator<'a>: Iterator<Item = Self::VertexIx>
+    where
+        Self: 'a;
+    type NeighbourIterator<'a>: Iterator<Item = Self::VertexIx>
+    where
+        Self: 'a;
+    type IncidentEdgeIterator<'a>: Iterator<Item = Self::EdgeIx>
+    where
+        Self: 'a;
+    type EdgeDataIterator<'a>: Iterator<Item = (Self::VertexIx, Self::VertexIx, &'a Self::EdgeData)>
+    where
+        Self: 'a;
+    type IncidentNeighbourIterator<'a>: Iterator<Item = (Self::EdgeIx, Self::VertexIx)>
+    where
+        Self: 'a;
+    type NeighbourKindIterator<'a>: Iterator<Item = (Self::VertexIx, EdgeKind)>
+    where
+        Self: 'a;
+    type InputIterator<'a>: Iterator<Item = Self::VertexIx>
+    where
+        Self: 'a;
+    type OutputIterator<'a>: Iterator<Item = Self::VertexIx>
+    where
+        Self: 'a;
 
     /// Name of the graph backend
     const BACKEND: &'static str;
@@ -78,8 +627,41 @@ pub trait Graph<'a> {
     fn set_output(&mut self, v: Self::VertexIx, flag: bool);
     fn is_input(&self, v: Self::VertexIx) -> bool;
     fn is_output(&self, v: Self::VertexIx) -> bool;
-    fn inputs(&self) -> Self::InputIterator;
-    fn outputs(&self) -> Self::OutputIterator;
+    /// Input vertices, in wire order
+    fn inputs(&self) -> Self::InputIterator<'_>;
+    /// Output vertices, in wire order
+    fn outputs(&self) -> Self::OutputIterator<'_>;
+
+    /// The `i`-th input in wire order, if there are that many
+    fn input_at(&self, i: usize) -> Option<Self::VertexIx> {
+        self.inputs().nth(i)
+    }
+
+    /// The `i`-th output in wire order, if there are that many
+    fn output_at(&self, i: usize) -> Option<Self::VertexIx> {
+        self.outputs().nth(i)
+    }
+
+    /// Replace the inputs and the outputs, in the given wire order.
+    ///
+    /// Each list must not repeat a vertex. `compose` pairs the `i`-th output of a diagram with
+    /// the `i`-th input of the next one, so this also permutes the wires.
+    fn set_io_order(&mut self, inputs: Vec<Self::VertexIx>, outputs: Vec<Self::VertexIx>) {
+        let old: Vec<_> = self.inputs().collect();
+        for v in old {
+            self.set_input(v, false);
+        }
+        let old: Vec<_> = self.outputs().collect();
+        for v in old {
+            self.set_output(v, false);
+        }
+        for v in inputs {
+            self.set_input(v, true);
+        }
+        for v in outputs {
+            self.set_output(v, true);
+        }
+    }
 
     /// Vertex count of the graph
     fn num_vertices(&self) -> u32;
@@ -87,74 +669,613 @@ pub trait Graph<'a> {
     fn num_edges(&self) -> u32;
 
     /// Set of vertices of the graph
-    fn vertices(&self) -> Self::VertexIterator;
+    fn vertices(&self) -> Self::VertexIterator<'_>;
 
     /// Set of edges of the graph
-    fn edges(&self) -> Self::EdgeIterator;
+    fn edges(&self) -> Self::EdgeIterator<'_>;
+
+    /// Edges of the graph with their endpoints, as given by `edge_endpoints`, and their data,
+    /// in the order of `edges`
+    fn edges_with_data(&self) -> Self::EdgeDataIterator<'_>;
 
     /// Vertex data
-    fn vertex(&'a self, v: Self::VertexIx) -> Option<&'a Self::VertexData>;
+    fn vertex(&self, v: Self::VertexIx) -> Option<&Self::VertexData>;
 
     /// Vertex data
-    fn vertex_mut(&'a mut self, v: Self::VertexIx) -> Option<&'a mut Self::VertexData>;
+    fn vertex_mut(&mut self, v: Self::VertexIx) -> Option<&mut Self::VertexData>;
 
     /// Edge data
-    fn edge(&'a self, e: Self::EdgeIx) -> Option<&'a Self::EdgeData>;
+    fn edge(&self, e: Self::EdgeIx) -> Option<&Self::EdgeData>;
 
     /// Edge data
-    fn edge_mut(&'a mut self, e: Self::EdgeIx) -> Option<&'a mut Self::EdgeData>;
+    fn edge_mut(&mut self, e: Self::EdgeIx) -> Option<&mut Self::EdgeData>;
+
+    /// Global scalar factor of the diagram
+    fn scalar(&self) -> &Scalar;
+
+    /// Global scalar factor of the diagram. Rewrites that change the linear map by a constant
+    /// factor must compensate for it here.
+    fn scalar_mut(&mut self) -> &mut Scalar;
+
+    /// Set the phase of a vertex
+    fn set_phase(&mut self, v: Self::VertexIx, phase: Phase) {
+        *self.vertex_mut(v).unwrap().phase_mut() = phase;
+    }
+
+    /// Set the kind of a vertex
+    fn set_vertex_kind(&mut self, v: Self::VertexIx, kind: VertexKind) {
+        *self.vertex_mut(v).unwrap().kind_mut() = kind;
+    }
+
+    /// Set the kind of an edge
+    fn set_edge_kind(&mut self, e: Self::EdgeIx, kind: EdgeKind) {
+        *self.edge_mut(e).unwrap().kind_mut() = kind;
+    }
 
     fn edge_endpoints(&self, e: Self::EdgeIx) -> Option<(Self::VertexIx, Self::VertexIx)>;
 
     /// Number of neighbours of a vertex
-    fn vertex_degree(&self, v: Self::VertexIx) -> u32;
+    fn vertex_degree(&self, v: Self::VertexIx) -> u32 {
+        self.incident_edges_with_neighbours(v).count() as u32
+    }
 
     /// Neighbours of a vertex
-    fn neighbours(&'a self, v: Self::VertexIx) -> Self::NeighbourIterator;
+    fn neighbours(&self, v: Self::VertexIx) -> Self::NeighbourIterator<'_>;
+
+    /// Edges incident to a vertex. Self-loops are yielded once.
+    fn incident_edges(&self, v: Self::VertexIx) -> Self::IncidentEdgeIterator<'_>;
+
+    /// Edges incident to a vertex, each with its other endpoint, in the order of
+    /// `incident_edges`. Self-loops are yielded once, with `v` as the other endpoint.
+    fn incident_edges_with_neighbours(
+        &self,
+        v: Self::VertexIx,
+    ) -> Self::IncidentNeighbourIterator<'_>;
+
+    /// Neighbours of a vertex with the kinds of the connecting edges, in the order of
+    /// `incident_edges`. A neighbour connected by parallel edges is yielded once per edge, and a
+    /// self-loop yields `v` once. Backends without a faster way can use `NeighboursWithKind`.
+    fn neighbours_with_kind(&self, v: Self::VertexIx) -> Self::NeighbourKindIterator<'_>;
 
     /// Check if two nodes are connected by any type of edge
-    fn connected(&self, v: Self::VertexIx, u: Self::VertexIx) -> bool;
+    fn connected(&self, v: Self::VertexIx, u: Self::VertexIx) -> bool {
+        self.incident_edges_with_neighbours(v).any(|(_, n)| n == u)
+    }
+
+    /// An edge between two vertices, or a self-loop if `v == u`.
+    ///
+    /// With parallel edges, this is the first one in the order of `incident_edges(v)`, so
+    /// swapping the vertices may give a different edge.
+    fn edge_between(&self, v: Self::VertexIx, u: Self::VertexIx) -> Option<Self::EdgeIx> {
+        self.incident_edges_with_neighbours(v)
+            .find(|&(_, n)| n == u)
+            .map(|(e, _)| e)
+    }
+
+    /// Kind of the edge given by `edge_between`
+    fn edge_kind_between(&self, v: Self::VertexIx, u: Self::VertexIx) -> Option<EdgeKind> {
+        self.edge_between(v, u)
+            .map(|e| self.edge(e).unwrap().kind())
+    }
 
-    /// Transform the diagram into its adjoint
+    /// Layers of a breadth-first search from the given vertices.
+    ///
+    /// The first layer holds the vertices of `from`, without repetitions, and each following
+    /// layer the vertices first reached from the previous one, in the order they are reached.
+    /// Vertices not connected to `from` are left out.
+    fn bfs_layers(
+        &self,
+        from: impl IntoIterator<Item = Self::VertexIx>,
+    ) -> Vec<Vec<Self::VertexIx>> {
+        let mut visited = HashSet::new();
+        let mut layer: Vec<_> = from.into_iter().filter(|&v| visited.insert(v)).collect();
+        let mut layers = Vec::new();
+        while !layer.is_empty() {
+            let next = layer
+                .iter()
+                .flat_map(|&v| self.neighbours(v))
+                .filter(|&u| visited.insert(u))
+                .collect();
+            layers.push(std::mem::replace(&mut layer, next));
+        }
+        layers
+    }
+
+    /// Vertices other than boundaries connected to the outputs, in the order of the outputs and
+    /// without repetitions.
+    ///
+    /// These are the vertices that extraction removes next when walking back from the outputs.
+    fn frontier(&self) -> Vec<Self::VertexIx> {
+        let mut seen = HashSet::new();
+        self.outputs()
+            .flat_map(|o| self.neighbours(o))
+            .filter(|&v| {
+                self.vertex(v).unwrap().kind() != VertexKind::Boundary
+                    && !self.is_input(v)
+                    && !self.is_output(v)
+                    && seen.insert(v)
+            })
+            .collect()
+    }
+
+    /// A path with the fewest edges from `v` to `u`, listing both ends, or `None` if they are
+    /// not connected.
+    fn shortest_path(&self, v: Self::VertexIx, u: Self::VertexIx) -> Option<Vec<Self::VertexIx>> {
+        let mut parent = HashMap::new();
+        parent.insert(v, v);
+        let mut layer = vec![v];
+        while !parent.contains_key(&u) {
+            let mut next = Vec::new();
+            for &w in &layer {
+                for n in self.neighbours(w) {
+                    if let Entry::Vacant(entry) = parent.entry(n) {
+                        entry.insert(w);
+                        next.push(n);
+                    }
+                }
+            }
+            if next.is_empty() {
+                return None;
+            }
+            layer = next;
+        }
+        let mut path = vec![u];
+        while *path.last().unwrap() != v {
+            path.push(parent[path.last().unwrap()]);
+        }
+        path.reverse();
+        Some(path)
+    }
+
+    /// Whether a vertex is grounded, see `GroundGraph`. Backends without grounds never are.
+    fn is_ground(&self, _v: Self::VertexIx) -> bool {
+        false
+    }
+
+    /// Transform the diagram into its adjoint, conjugating the scalar
     fn adjoint(&mut self);
 
-    /// Append another graph after this one, connecting their inputs/outputs.
-    fn compose(&mut self, other: Self);
+    /// Append another graph after this one, connecting the outputs of `self` to the inputs of
+    /// `other` in wire order. The outputs of `other` become the new outputs.
+    ///
+    /// The scalars are multiplied. Fails without modifying the graph if the number of wires does
+    /// not match.
+    fn compose(&mut self, other: Self) -> Result<(), ComposeError>;
 
-    /// Parallel compose another graph, adding its inputs and outputs to the current ones.
+    /// Parallel compose another graph, adding its inputs and outputs to the current ones and
+    /// multiplying the scalars.
     fn tensor(&mut self, other: Self);
 
     /// Add multiple unconnected vertices
     fn add_vertices(&mut self, count: u32) -> Vec<Self::VertexIx>;
 
     /// Add a single vertex
-    fn add_vertex(&'a mut self, kind: VertexKind, phase: Phase) -> Self::VertexIx {
+    fn add_vertex(&mut self, kind: VertexKind, phase: Phase) -> Self::VertexIx {
         let v = self.add_vertices(1)[0];
-        let vdata: &'a mut _ = self.vertex_mut(v).unwrap();
+        let vdata = self.vertex_mut(v).unwrap();
         *vdata.kind_mut() = kind;
         *vdata.phase_mut() = phase;
         v
     }
 
-    /// Add a single edge between vertices
-    fn add_edge(&mut self, v: Self::VertexIx, u: Self::VertexIx, ty: EdgeKind) -> Self::EdgeIx;
+    /// Edge policy of the graph. Backends without a configurable mode are always in
+    /// `GraphMode::Multi`.
+    fn mode(&self) -> GraphMode {
+        GraphMode::Multi
+    }
+
+    /// Add a single edge between vertices.
+    ///
+    /// In `GraphMode::Multi` the edge is always inserted and returned. In `GraphMode::Simple`
+    /// this is `add_edge_smart`, which returns the edge left between the vertices, if any.
+    fn add_edge(
+        &mut self,
+        v: Self::VertexIx,
+        u: Self::VertexIx,
+        ty: EdgeKind,
+    ) -> Option<Self::EdgeIx>;
+
+    /// Add multiple edges, given by their endpoints and kind. Returns the edges left by each
+    /// insertion, see `add_edge`.
+    fn add_edges(
+        &mut self,
+        edges: impl IntoIterator<Item = (Self::VertexIx, Self::VertexIx, EdgeKind)>,
+    ) -> Vec<Self::EdgeIx> {
+        edges
+            .into_iter()
+            .filter_map(|(v, u, kind)| self.add_edge(v, u, kind))
+            .collect()
+    }
+
+    /// Add an edge, merging it with an existing edge between the same vertices by the Hopf law.
+    /// Returns the edge left between the vertices, if any.
+    ///
+    /// This differs from `add_edge` only when both ends are Z or X spiders. An edge the spiders
+    /// could be fused along (a regular edge between spiders of the same colour, or a Hadamard
+    /// edge between spiders of different colours) is called fusing. Assuming there is at most
+    /// one edge between the spiders:
+    ///
+    /// - two fusing edges reduce to one,
+    /// - two non-fusing edges cancel, multiplying the scalar by 1/2,
+    /// - a fusing and a non-fusing edge reduce to the fusing one, adding pi to the phase of `v`
+    ///   and multiplying the scalar by 1/sqrt(2).
+    ///
+    /// A regular self-loop on a spider is not added, and a Hadamard one adds pi to its phase and
+    /// multiplies the scalar by 1/sqrt(2).
+    fn add_edge_smart(
+        &mut self,
+        v: Self::VertexIx,
+        u: Self::VertexIx,
+        kind: EdgeKind,
+    ) -> Option<Self::EdgeIx> {
+        merge_edge(self, v, u, kind, Self::add_edge)
+    }
 
-    /// Remove multiple vertices
+    /// Remove multiple vertices and their edges.
+    ///
+    /// The vertices can be given in any order, with repetitions. They are removed in decreasing
+    /// index order, so a list collected before the call stays valid, see `remove_vertex`.
     fn remove_vertices(&mut self, vs: impl IntoIterator<Item = Self::VertexIx>) {
-        for v in vs {self.remove_vertex(v)}
+        let mut vs: Vec<_> = vs.into_iter().collect();
+        vs.sort_unstable_by(|a, b| b.cmp(a));
+        vs.dedup();
+        for v in vs {
+            self.remove_vertex(v)
+        }
     }
 
-    /// Remove multiple vertices
+    /// Remove a single vertex and its edges.
+    ///
+    /// Whether other vertices keep their indices depends on the backend: `GGraph` moves its last
+    /// vertex into the removed slot, while `StableGGraph` and `VecGraph` never renumber vertices.
+    /// No backend may renumber the vertices with lower indices than the removed one, so a batch
+    /// of vertices can always be removed safely in decreasing index order.
     fn remove_vertex(&mut self, v: Self::VertexIx);
 
-    /// Remove multiple edges from the graph
+    /// Remove multiple edges from the graph.
+    ///
+    /// Like `remove_vertices`, the edges can be given in any order, with repetitions.
     fn remove_edges(&mut self, es: impl IntoIterator<Item = Self::EdgeIx>) {
-        for e in es {self.remove_edge(e)}
+        let mut es: Vec<_> = es.into_iter().collect();
+        es.sort_unstable_by(|a, b| b.cmp(a));
+        es.dedup();
+        for e in es {
+            self.remove_edge(e)
+        }
     }
 
-    /// Remove a single edge from the graph
+    /// Remove a single edge from the graph.
+    ///
+    /// Like `remove_vertex`, this may renumber other edges depending on the backend, but never
+    /// the ones with lower indices.
     fn remove_edge(&mut self, e: Self::EdgeIx);
 
+    /// Edges with exactly one endpoint among the given vertices.
+    ///
+    /// They are listed going through the vertices in the given order, and through the incident
+    /// edges of each vertex in backend order. This is the order used by `induced_subgraph`.
+    fn cut_edges(&self, vs: &[Self::VertexIx]) -> Vec<Self::EdgeIx> {
+        let inside: HashSet<_> = vs.iter().copied().collect();
+        let mut seen = HashSet::new();
+        let mut res = Vec::new();
+        for &v in vs.iter().filter(|&&v| seen.insert(v)) {
+            for (e, n) in self.incident_edges_with_neighbours(v) {
+                if !inside.contains(&n) {
+                    res.push(e);
+                }
+            }
+        }
+        res
+    }
+
+    /// Copy of the given vertices and the edges among them, with the map from the old vertex
+    /// indices to the new ones.
+    ///
+    /// The vertex data is copied whole, so positions and grounds are kept. The vertices that are
+    /// inputs or outputs stay so, in the same relative order, and the scalar of the result is 1.
+    ///
+    /// With `cut_boundaries`, each edge in `cut_edges(vs)` becomes a regular edge to a fresh
+    /// boundary vertex, so the result is an open diagram on its own. A Hadamard on a cut edge is
+    /// considered outside of the subgraph, and `replace_subgraph` puts it back. The fresh
+    /// boundaries are appended to the outputs in cut order; callers that want some of them as
+    /// inputs can move them with `set_output` and `set_input`.
+    fn induced_subgraph(
+        &self,
+        vs: &[Self::VertexIx],
+        cut_boundaries: bool,
+    ) -> (Self, HashMap<Self::VertexIx, Self::VertexIx>)
+    where
+        Self: Sized + Default,
+        Self::VertexData: Clone,
+    {
+        let mut res = Self::default();
+        let mut map = HashMap::new();
+        for &v in vs {
+            if let Entry::Vacant(entry) = map.entry(v) {
+                let new_v = res.add_vertex(VertexKind::Boundary, Phase::zero());
+                *res.vertex_mut(new_v).unwrap() = self.vertex(v).unwrap().clone();
+                entry.insert(new_v);
+            }
+        }
+        for v in self.inputs() {
+            if let Some(&new_v) = map.get(&v) {
+                res.set_input(new_v, true);
+            }
+        }
+        for v in self.outputs() {
+            if let Some(&new_v) = map.get(&v) {
+                res.set_output(new_v, true);
+            }
+        }
+
+        let mut seen = HashSet::new();
+        for &v in vs {
+            for e in self.incident_edges(v) {
+                let (a, b) = self.edge_endpoints(e).unwrap();
+                if let (Some(&a), Some(&b)) = (map.get(&a), map.get(&b)) {
+                    if seen.insert(e) {
+                        res.add_edge(a, b, self.edge(e).unwrap().kind());
+                    }
+                }
+            }
+        }
+        if cut_boundaries {
+            for e in self.cut_edges(vs) {
+                let (a, b) = self.edge_endpoints(e).unwrap();
+                let inner = map.get(&a).or_else(|| map.get(&b)).copied().unwrap();
+                let boundary = res.add_vertex(VertexKind::Boundary, Phase::zero());
+                res.add_edge(inner, boundary, EdgeKind::Regular);
+                res.set_output(boundary, true);
+            }
+        }
+        (res, map)
+    }
+
+    /// Replace the given vertices by another diagram, the inverse of `induced_subgraph`.
+    ///
+    /// `cut` must list the edges of `cut_edges(vs)`, in any order. The boundaries of
+    /// `replacement`, its inputs followed by its outputs in wire order, are matched one to one
+    /// with the edges in `cut`: the vertex outside `vs` is connected to the replacement through
+    /// the matched boundary, which becomes a plain wire, and the kind of the cut edge is
+    /// combined with the kind of the boundary's edge. The scalars are multiplied.
+    ///
+    /// Panics if some vertex of `vs` is an input or an output, if `cut` does not match the cut
+    /// edges, or if the number of boundaries of `replacement` differs from the number of cut
+    /// edges. Indices collected before the call may be invalidated, see `remove_vertex`.
+    fn replace_subgraph(&mut self, vs: &[Self::VertexIx], replacement: Self, cut: &[Self::EdgeIx])
+    where
+        Self: Sized,
+        Self::VertexData: Clone,
+    {
+        assert!(
+            vs.iter().all(|&v| !self.is_input(v) && !self.is_output(v)),
+            "the replaced vertices must not be inputs or outputs"
+        );
+        let mut expected = self.cut_edges(vs);
+        let mut given = cut.to_vec();
+        expected.sort_unstable();
+        given.sort_unstable();
+        assert_eq!(expected, given, "the given edges are not the cut edges");
+        let boundaries: Vec<_> = replacement.inputs().chain(replacement.outputs()).collect();
+        assert_eq!(
+            boundaries.len(),
+            cut.len(),
+            "the replacement has {} boundaries for {} cut edges",
+            boundaries.len(),
+            cut.len()
+        );
+
+        let inside: HashSet<_> = vs.iter().copied().collect();
+        let outer: Vec<_> = cut
+            .iter()
+            .map(|&e| {
+                let (a, b) = self.edge_endpoints(e).unwrap();
+                let w = if inside.contains(&a) { b } else { a };
+                (w, self.edge(e).unwrap().kind())
+            })
+            .collect();
+
+        *self.scalar_mut() *= *replacement.scalar();
+        let mut map = HashMap::new();
+        for v in replacement.vertices() {
+            let new_v = self.add_vertex(VertexKind::Boundary, Phase::zero());
+            *self.vertex_mut(new_v).unwrap() = replacement.vertex(v).unwrap().clone();
+            map.insert(v, new_v);
+        }
+        for e in replacement.edges() {
+            let (a, b) = replacement.edge_endpoints(e).unwrap();
+            self.add_edge(map[&a], map[&b], replacement.edge(e).unwrap().kind());
+        }
+        let mut seams = Vec::new();
+        for (b, (w, kind)) in boundaries.into_iter().zip(outer) {
+            let b = map[&b];
+            self.set_vertex_kind(b, VertexKind::Z);
+            self.set_phase(b, Phase::zero());
+            self.add_edge(w, b, kind);
+            seams.push(b);
+        }
+
+        // Turn the seams into plain edges, as `compose` does. Removing a vertex from `GGraph`
+        // moves the last vertex into its slot, and that vertex may be a neighbour of the seam,
+        // so the bridging edge is added before the seam is removed. The seams are removed in
+        // decreasing index order, so the moved vertex is never a seam still to be visited, nor
+        // one of `vs` or the outer vertices, which all come before the replacement.
+        seams.sort_unstable_by(|a, b| b.cmp(a));
+        seams.dedup();
+        for s in seams {
+            let wire: Vec<_> = self.neighbours_with_kind(s).collect();
+            if let [(a, k1), (b, k2)] = wire[..] {
+                if a != s && b != s && a != b && !self.is_ground(s) {
+                    let kind = if k1 == k2 {
+                        EdgeKind::Regular
+                    } else {
+                        EdgeKind::Hadam
+                    };
+                    self.add_edge(a, b, kind);
+                    self.remove_vertex(s);
+                }
+            }
+        }
+        self.remove_vertices(vs.iter().copied());
+    }
+
+    /// Copy of the vertices at distance at most `k` from `center`, counting edges of any kind,
+    /// with the map from the old vertex indices to the new ones.
+    ///
+    /// This is `induced_subgraph` of those vertices in index order, with cut boundaries, except
+    /// that each fresh boundary keeps the kind of its cut edge. A fresh boundary is an input if
+    /// the vertex it replaces is closer to the inputs than to the outputs of the diagram, and an
+    /// output otherwise, so the result reads in the same direction. Finding the sides takes a
+    /// traversal of the whole diagram when there are cut edges.
+    fn neighbourhood(
+        &self,
+        center: Self::VertexIx,
+        k: u32,
+    ) -> (Self, HashMap<Self::VertexIx, Self::VertexIx>)
+    where
+        Self: Sized + Default,
+        Self::VertexData: Clone,
+    {
+        let mut region: Vec<_> = self
+            .bfs_layers([center])
+            .into_iter()
+            .take(k as usize + 1)
+            .flatten()
+            .collect();
+        region.sort_unstable();
+        let (mut res, map) = self.induced_subgraph(&region, true);
+        let cut = self.cut_edges(&region);
+        if cut.is_empty() {
+            return (res, map);
+        }
+
+        let distances = |from: Vec<Self::VertexIx>| -> HashMap<_, _> {
+            self.bfs_layers(from)
+                .into_iter()
+                .enumerate()
+                .flat_map(|(d, layer)| layer.into_iter().map(move |v| (v, d)))
+                .collect()
+        };
+        let to_inputs = distances(self.inputs().collect());
+        let to_outputs = distances(self.outputs().collect());
+        let inner: HashSet<_> = map.values().copied().collect();
+        let stubs: Vec<_> = res.outputs().filter(|v| !inner.contains(v)).collect();
+        for (e, stub) in cut.into_iter().zip(stubs) {
+            let (a, b) = self.edge_endpoints(e).unwrap();
+            let outside = if map.contains_key(&a) { b } else { a };
+            let stub_edge = res.incident_edges(stub).next().unwrap();
+            res.set_edge_kind(stub_edge, self.edge(e).unwrap().kind());
+            let closer_to_inputs = match (to_inputs.get(&outside), to_outputs.get(&outside)) {
+                (Some(i), Some(o)) => i < o,
+                (Some(_), None) => true,
+                _ => false,
+            };
+            if closer_to_inputs {
+                res.set_output(stub, false);
+                res.set_input(stub, true);
+            }
+        }
+        (res, map)
+    }
+
+    /// Check the structure of the diagram, reporting every broken invariant.
+    ///
+    /// Inputs and outputs must be vertices of the graph, and edge endpoints too. Boundaries
+    /// must have phase 0 and exactly one edge, except for a boundary that is both an input
+    /// and an output, which is a bare wire and has no edges. Self-loops are only allowed on Z
+    /// and X spiders, where the simplifications know how to remove them. In
+    /// `GraphMode::Simple` spiders have no self-loops and at most one edge between them.
+    fn check_invariants(&self) -> Result<(), Violations<Self>> {
+        let simple = self.mode() == GraphMode::Simple;
+        let is_spider = |v| {
+            matches!(
+                self.vertex(v).unwrap().kind(),
+                VertexKind::Z | VertexKind::X
+            )
+        };
+        let mut spider_edges = HashSet::new();
+        let mut violations = Vec::new();
+        for v in self.inputs().chain(self.outputs()) {
+            if self.vertex(v).is_none() {
+                violations.push(InvariantViolation::UnknownBoundary { vertex: v });
+            }
+        }
+        for e in self.edges() {
+            let ends = self
+                .edge_endpoints(e)
+                .filter(|&(a, b)| self.vertex(a).is_some() && self.vertex(b).is_some());
+            match ends {
+                None => violations.push(InvariantViolation::UnknownEndpoint { edge: e }),
+                Some((a, b)) if a == b => {
+                    if !is_spider(a) {
+                        violations.push(InvariantViolation::SelfLoop { edge: e, vertex: a });
+                    } else if simple {
+                        violations.push(InvariantViolation::SpiderSelfLoop { edge: e, vertex: a });
+                    }
+                }
+                Some((a, b)) => {
+                    if simple
+                        && is_spider(a)
+                        && is_spider(b)
+                        && !spider_edges.insert((a.min(b), a.max(b)))
+                    {
+                        violations.push(InvariantViolation::ParallelEdge {
+                            edge: e,
+                            vertices: (a, b),
+                        });
+                    }
+                }
+            }
+        }
+        for v in self.vertices() {
+            let vdata = self.vertex(v).unwrap();
+            if vdata.kind() != VertexKind::Boundary {
+                continue;
+            }
+            if !vdata.phase().is_zero() {
+                violations.push(InvariantViolation::BoundaryPhase {
+                    vertex: v,
+                    phase: vdata.phase(),
+                });
+            }
+            let degree = self.vertex_degree(v);
+            if self.is_input(v) && self.is_output(v) {
+                if degree != 0 {
+                    violations.push(InvariantViolation::InputAndOutput { vertex: v, degree });
+                }
+            } else if degree != 1 {
+                violations.push(InvariantViolation::BoundaryDegree { vertex: v, degree });
+            }
+        }
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
+    /// Copy the diagram into an undirected `petgraph::Graph`, to run petgraph's algorithms on it.
+    ///
+    /// The node with index `i` is the `i`-th vertex yielded by `vertices`, and edges keep the
+    /// order of `edges_with_data`. Inputs, outputs and the scalar are not copied. Use
+    /// `pet_adaptor::PetAdaptor` instead to traverse a diagram without copying it.
+    fn to_petgraph(&self) -> petgraph::Graph<Self::VertexData, Self::EdgeData, petgraph::Undirected>
+    where
+        Self::VertexData: Clone,
+        Self::EdgeData: Clone,
+    {
+        let mut pg =
+            petgraph::Graph::with_capacity(self.num_vertices() as usize, self.num_edges() as usize);
+        let mut nodes = HashMap::new();
+        for v in self.vertices() {
+            nodes.insert(v, pg.add_node(self.vertex(v).unwrap().clone()));
+        }
+        for (a, b, edata) in self.edges_with_data() {
+            pg.add_edge(nodes[&a], nodes[&b], edata.clone());
+        }
+        pg
+    }
+
     // Utility functions
 
     /// Returns a string with some information regarding the degree distribution of the graph.
@@ -173,10 +1294,169 @@ pub trait Graph<'a> {
         );
         header + "degree distribution: \n" + &degrees_s
     }
+
+    /// Human-readable listing of the vertices, one per line, such as
+    /// `v3: Z(π/4) [in] -- {v1:H, v5:R}`.
+    ///
+    /// Vertices are sorted by index and numbered by their position in that order, which is
+    /// their index for `GGraph`. Each line gives the kind of the vertex, its phase if it is not
+    /// the default one for the kind, whether it is an input or an output, and its neighbours
+    /// with the kind of the edge to them.
+    fn dump(&self) -> String {
+        let vertices: Vec<_> = self.vertices().sorted().collect();
+        let number: HashMap<_, _> = vertices.iter().enumerate().map(|(i, &v)| (v, i)).collect();
+        let mut res = String::new();
+        for (i, &v) in vertices.iter().enumerate() {
+            let vdata = self.vertex(v).unwrap();
+            res += &format!("v{}: {}", i, vdata.kind());
+            if vdata.phase() != vdata.kind().default_phase() {
+                res += &format!("({})", vdata.phase());
+            }
+            if self.is_input(v) {
+                res += " [in]";
+            }
+            if self.is_output(v) {
+                res += " [out]";
+            }
+            let neighbours = self
+                .neighbours_with_kind(v)
+                .map(|(n, kind)| (number[&n], kind))
+                .sorted_by_key(|&(n, _)| n)
+                .map(|(n, kind)| format!("v{}:{}", n, kind))
+                .join(", ");
+            res += &format!(" -- {{{}}}\n", neighbours);
+        }
+        res
+    }
+
+    /// Summarized statistics of the graph, see `GraphStats`.
+    fn graph_stats(&self) -> GraphStats
+    where
+        Self: Sized,
+    {
+        GraphStats::new(self)
+    }
+
+    /// Number of Z and X spiders with a phase that is an odd multiple of pi/4.
+    ///
+    /// A phase gadget counts once, since only its leaf carries the phase.
+    fn tcount(&self) -> u32 {
+        self.vertices()
+            .filter_map(|v| spider_phase(self, v))
+            .filter(|p| p.is_proper_t())
+            .count() as u32
+    }
+
+    /// Number of Z and X spiders with a non-zero phase that is a multiple of pi/2
+    fn clifford_count(&self) -> u32 {
+        self.vertices()
+            .filter_map(|v| spider_phase(self, v))
+            .filter(|p| p.is_clifford() && !p.is_zero())
+            .count() as u32
+    }
+
+    /// Number of Z and X spiders with each phase that is not a multiple of pi/2
+    fn non_clifford_phases(&self) -> Counter<Phase> {
+        self.vertices()
+            .filter_map(|v| spider_phase(self, v))
+            .filter(|p| !p.is_clifford())
+            .collect()
+    }
+}
+
+/// Neighbours of a vertex with the kinds of the connecting edges, found by looking up each
+/// incident edge. See `Graph::neighbours_with_kind`.
+pub struct NeighboursWithKind<'a, G: Graph> {
+    graph: &'a G,
+    iter: G::IncidentNeighbourIterator<'a>,
+}
+
+impl<'a, G: Graph> NeighboursWithKind<'a, G> {
+    pub fn new(graph: &'a G, v: G::VertexIx) -> Self {
+        NeighboursWithKind {
+            graph,
+            iter: graph.incident_edges_with_neighbours(v),
+        }
+    }
+}
+
+impl<G: Graph> Iterator for NeighboursWithKind<'_, G> {
+    type Item = (G::VertexIx, EdgeKind);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (e, n) = self.iter.next()?;
+        Some((n, self.graph.edge(e).unwrap().kind()))
+    }
+}
+
+/// Phase of a vertex, if it is a Z or X spider
+fn spider_phase<G: Graph + ?Sized>(g: &G, v: G::VertexIx) -> Option<Phase> {
+    let vdata = g.vertex(v).unwrap();
+    match vdata.kind() {
+        VertexKind::Z | VertexKind::X => Some(vdata.phase()),
+        _ => None,
+    }
+}
+
+/// Add an edge between `v` and `u` like `Graph::add_edge_smart`, inserting new edges with
+/// `insert`. Backends in `GraphMode::Simple` use it in `Graph::add_edge` with their raw
+/// insertion.
+pub(crate) fn merge_edge<G: Graph + ?Sized>(
+    g: &mut G,
+    v: G::VertexIx,
+    u: G::VertexIx,
+    kind: EdgeKind,
+    insert: impl FnOnce(&mut G, G::VertexIx, G::VertexIx, EdgeKind) -> Option<G::EdgeIx>,
+) -> Option<G::EdgeIx> {
+    let (kv, ku) = (g.vertex(v).unwrap().kind(), g.vertex(u).unwrap().kind());
+    let is_spider = |k| matches!(k, VertexKind::Z | VertexKind::X);
+    if !is_spider(kv) || !is_spider(ku) {
+        return insert(g, v, u, kind);
+    }
+    let add_pi = |g: &mut G| {
+        let phase = g.vertex(v).unwrap().phase() + Phase::pi();
+        g.set_phase(v, phase);
+        g.scalar_mut().add_power(-1);
+    };
+    if v == u {
+        if kind == EdgeKind::Hadam {
+            add_pi(g);
+        }
+        return None;
+    }
+    let e = match g.edge_between(v, u) {
+        Some(e) => e,
+        None => return insert(g, v, u, kind),
+    };
+    let fusing = |k| is_fusing_edge(k, kv, ku);
+    match (fusing(g.edge(e).unwrap().kind()), fusing(kind)) {
+        (true, true) => Some(e),
+        (false, false) => {
+            g.remove_edge(e);
+            g.scalar_mut().add_power(-2);
+            None
+        }
+        (true, false) => {
+            add_pi(g);
+            Some(e)
+        }
+        (false, true) => {
+            g.remove_edge(e);
+            add_pi(g);
+            insert(g, v, u, kind)
+        }
+    }
+}
+
+/// Whether two spiders connected by an edge of the given kind can be fused along it, that is,
+/// it is a regular edge between spiders of the same colour or a Hadamard edge between spiders of
+/// different colours.
+pub(crate) fn is_fusing_edge(kind: EdgeKind, a: VertexKind, b: VertexKind) -> bool {
+    (kind == EdgeKind::Regular) == (a == b)
 }
 
 /// A graph with added qubit and row information for each vertex
-pub trait CartesianGraph<'a>: Graph<'a> {
+pub trait CartesianGraph: Graph {
     /// Qubit identifier for vertices in a CartesianGraph
     type Qubit;
     /// Row identifier for vertices in a CartesianGraph
@@ -198,18 +1478,242 @@ pub trait CartesianGraph<'a>: Graph<'a> {
     fn depth(&self) -> u32;
     /// Number of qubits in the graph
     fn qubit_count(&self) -> u32;
+
+    /// Vertices on a qubit, ordered by row
+    fn qubit_slice(&self, q: Self::Qubit) -> Vec<Self::VertexIx>
+    where
+        Self::Qubit: PartialEq,
+        Self::Row: PartialOrd,
+    {
+        let mut slice: Vec<_> = self.vertices().filter(|&v| self.qubit(v) == q).collect();
+        slice.sort_by(|&a, &b| {
+            self.row(a)
+                .partial_cmp(&self.row(b))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        slice
+    }
+
+    /// Replace a hole by an implementation of it, see `VertexKind::Hole`.
+    ///
+    /// The wires of the hole are its incident edges, read like the boundaries of a diagram: first
+    /// the edges to neighbours on an earlier row than the hole, then the others, each side by the
+    /// qubit of the neighbour (and in `incident_edges` order on the same qubit). They are named by
+    /// `Hole::wires` in this order. Since the order depends on the neighbours, two holes to be
+    /// substituted should not be adjacent.
+    ///
+    /// `wire_map` gives, for each wire name, the index of the boundary of `implementation` it is
+    /// connected to, counting the inputs and then the outputs in wire order. The implementation
+    /// is spliced in with `replace_subgraph`, so the scalars are multiplied, after translating it
+    /// so that its smallest qubit and row are those of the hole.
+    ///
+    /// Fails without changing the graph if `hole` is not a hole, if the arity of the hole does not
+    /// match its incident edges or the boundaries of `implementation`, or if `wire_map` does not
+    /// map each wire to its own boundary. Indices collected before the call may be invalidated,
+    /// see `remove_vertex`.
+    fn substitute(
+        &mut self,
+        hole: Self::VertexIx,
+        implementation: &Self,
+        wire_map: &[(String, u32)],
+    ) -> Result<(), SubstError>
+    where
+        Self: Sized + Clone,
+        Self::VertexData: HoleData + Clone,
+        Self::Qubit: Copy + Ord + Add<Output = Self::Qubit> + Sub<Output = Self::Qubit>,
+        Self::Row: Copy + Ord + Add<Output = Self::Row> + Sub<Output = Self::Row>,
+    {
+        let vdata = self.vertex(hole).unwrap();
+        let wires = match vdata.hole() {
+            Some(h) if vdata.kind() == VertexKind::Hole => &h.wires,
+            _ => return Err(SubstError::NotAHole),
+        };
+        let mut edges = self.cut_edges(&[hole]);
+        if edges.len() != wires.len() {
+            return Err(SubstError::ArityMismatch {
+                wires: wires.len(),
+                edges: edges.len(),
+            });
+        }
+        let boundaries = implementation.inputs().count() + implementation.outputs().count();
+        if boundaries != wires.len() {
+            return Err(SubstError::BoundaryMismatch {
+                wires: wires.len(),
+                boundaries,
+            });
+        }
+
+        let (q, r) = (self.qubit(hole), self.row(hole));
+        edges.sort_by_key(|&e| {
+            let (a, b) = self.edge_endpoints(e).unwrap();
+            let n = if a == hole { b } else { a };
+            (self.row(n) >= r, self.qubit(n))
+        });
+        let mut boundary_of = HashMap::new();
+        let mut used = HashSet::new();
+        for (name, index) in wire_map {
+            if !wires.contains(name) {
+                return Err(SubstError::UnknownWire { name: name.clone() });
+            }
+            if *index as usize >= boundaries {
+                return Err(SubstError::BoundaryOutOfRange { index: *index });
+            }
+            if boundary_of.insert(name, *index as usize).is_some() {
+                return Err(SubstError::DuplicateWire { name: name.clone() });
+            }
+            if !used.insert(*index) {
+                return Err(SubstError::DuplicateBoundary { index: *index });
+            }
+        }
+        let mut cut = vec![None; boundaries];
+        for (name, e) in wires.iter().zip(edges) {
+            let index = *boundary_of
+                .get(name)
+                .ok_or_else(|| SubstError::MissingWire { name: name.clone() })?;
+            if cut[index].replace(e).is_some() {
+                return Err(SubstError::DuplicateWire { name: name.clone() });
+            }
+        }
+        // Each wire has its own boundary and there are as many of both, so all are matched.
+        let cut: Vec<_> = cut.into_iter().map(Option::unwrap).collect();
+
+        let mut replacement = implementation.clone();
+        let vs: Vec<_> = replacement.vertices().collect();
+        let q0 = vs.iter().map(|&v| replacement.qubit(v)).min();
+        let r0 = vs.iter().map(|&v| replacement.row(v)).min();
+        if let (Some(q0), Some(r0)) = (q0, r0) {
+            for v in vs {
+                let (vq, vr) = (replacement.qubit(v), replacement.row(v));
+                replacement.set_position(v, vq - q0 + q, vr - r0 + r);
+            }
+        }
+        self.replace_subgraph(&[hole], replacement, &cut);
+        Ok(())
+    }
+
+    /// Like `Graph::check_invariants`, but also check that the inputs are on row 0 and the
+    /// outputs on the last row of the graph.
+    fn check_layout(&self) -> Result<(), Violations<Self>>
+    where
+        Self::Row: Copy + Ord + Default,
+    {
+        let mut violations = self.check_invariants().err().unwrap_or_default();
+        let exists = |&v: &Self::VertexIx| self.vertex(v).is_some();
+        let last = self.vertices().map(|v| self.row(v)).max();
+        for v in self.inputs().filter(exists) {
+            if self.row(v) != Self::Row::default() {
+                violations.push(InvariantViolation::InputRow { vertex: v });
+            }
+        }
+        for v in self.outputs().filter(exists) {
+            if Some(self.row(v)) != last {
+                violations.push(InvariantViolation::OutputRow { vertex: v });
+            }
+        }
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
+    /// Per-qubit statistics, indexed by qubit.
+    ///
+    /// Every vertex must be on a non-negative qubit below `qubit_count()`.
+    fn wire_profile(&self) -> Vec<WireStats>
+    where
+        Self::Qubit: TryInto<u32>,
+    {
+        let mut profile = vec![WireStats::default(); self.qubit_count() as usize];
+        for v in self.vertices() {
+            let vdata = self.vertex(v).unwrap();
+            let stats = &mut profile[qubit_index(self.qubit(v))];
+            if let VertexKind::Z | VertexKind::X = vdata.kind() {
+                stats.spiders += 1;
+                if vdata.phase().is_proper_t() {
+                    stats.t_spiders += 1;
+                }
+            }
+        }
+        for (v, u, _) in self.edges_with_data() {
+            let (a, b) = (qubit_index(self.qubit(v)), qubit_index(self.qubit(u)));
+            if a != b {
+                profile[a].crossing_edges += 1;
+                profile[b].crossing_edges += 1;
+            }
+        }
+        profile
+    }
+
+    /// Symmetric matrix with the number of edges between each pair of qubits.
+    ///
+    /// The diagonal counts the edges between vertices on the same qubit. Every vertex must be on
+    /// a non-negative qubit below `qubit_count()`.
+    fn interaction_matrix(&self) -> Vec<Vec<u32>>
+    where
+        Self::Qubit: TryInto<u32>,
+    {
+        let n = self.qubit_count() as usize;
+        let mut matrix = vec![vec![0; n]; n];
+        for (v, u, _) in self.edges_with_data() {
+            let (a, b) = (qubit_index(self.qubit(v)), qubit_index(self.qubit(u)));
+            matrix[a][b] += 1;
+            if a != b {
+                matrix[b][a] += 1;
+            }
+        }
+        matrix
+    }
+
+    /// One-line report of the figures of merit of a circuit-like diagram: qubits, depth,
+    /// T-count, edges between different qubits, and boundaries.
+    fn summary(&self) -> String
+    where
+        Self::Qubit: PartialEq,
+    {
+        let two_qubit_edges = self
+            .edges_with_data()
+            .filter(|&(v, u, _)| self.qubit(v) != self.qubit(u))
+            .count();
+        format!(
+            "Graph(backend={}, qubits={}, depth={}, tcount={}, two_qubit_edges={}, inputs={}, outputs={})",
+            Self::BACKEND,
+            self.qubit_count(),
+            self.depth(),
+            self.tcount(),
+            two_qubit_edges,
+            self.inputs().count(),
+            self.outputs().count()
+        )
+    }
 }
 
-/// Attributes of a grounded graph's edge
-///
-/// Two equivalent undirected edges will have the same source and the same target.
+/// Index of a qubit in per-qubit tables. Panics on qubits that do not fit, like negative ones.
+fn qubit_index<Q: TryInto<u32>>(q: Q) -> usize {
+    match q.try_into() {
+        Ok(q) => q as usize,
+        Err(_) => panic!("vertex on a qubit outside the qubit range"),
+    }
+}
+
+/// Attributes of a grounded graph's vertex
 pub trait GroundData {
     fn ground(&self) -> bool;
     fn ground_mut(&mut self) -> &mut bool;
 }
 
+/// Attributes of a vertex that can be a hole, see `VertexKind::Hole`
+pub trait HoleData {
+    /// Interface of the hole, `None` for other vertices
+    fn hole(&self) -> Option<&Hole>;
+    fn set_hole(&mut self, hole: Option<Hole>);
+}
+
 /// Graph with measurement operators
-pub trait GroundGraph<'a>: Graph<'a>
+///
+/// A grounded vertex has a discard attached to it, so the diagram denotes a mixed map. The
+/// phase of a grounded Z spider is irrelevant, since any Z phase is absorbed by the discard.
+pub trait GroundGraph: Graph
 where
     Self::VertexData: GroundData,
 {
@@ -217,4 +1721,1224 @@ where
 
     /// Return an iterator over the ground vertex indices
     fn grounds(&self) -> Self::GroundIterator;
+
+    /// Ground or unground a vertex
+    fn set_ground(&mut self, v: Self::VertexIx, flag: bool) {
+        *self.vertex_mut(v).unwrap().ground_mut() = flag;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::analysis::is_isomorphic;
+    use crate::zx::checked_graph::CheckedGraph;
+    use crate::zx::circuit::{Circuit, Gate};
+    use crate::zx::generate::random_clifford_t_circuit;
+    use crate::zx::random::Seed;
+    use crate::zx::simple_graph::GGraph;
+    use crate::zx::simplify::{clifford_simp, full_reduce, remove_identities};
+    use crate::zx::stable_graph::StableGGraph;
+    use crate::zx::templates;
+    use crate::zx::tensor::assert_graph_eq;
+    use crate::zx::vec_graph::VecGraph;
+
+    /// Wire `in - Z(phase) - out`, returning the spider
+    fn spider_wire(g: &mut GGraph, kind: VertexKind, phase: Phase) -> <GGraph as Graph>::VertexIx {
+        let i = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        let v = g.add_vertex(kind, phase);
+        let o = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        g.add_edge(i, v, EdgeKind::Regular);
+        g.add_edge(v, o, EdgeKind::Regular);
+        g.set_input(i, true);
+        g.set_output(o, true);
+        v
+    }
+
+    #[test]
+    fn replace_subgraph_seam_next_to_last_vertex() {
+        let mut g: GGraph = GGraph::new();
+        let v = spider_wire(&mut g, VertexKind::X, Phase::new(1, 2));
+        let cut = g.cut_edges(&[v]);
+
+        // The interior spider is added after the boundaries, so it is the last vertex of `g`
+        // once the replacement is copied in, and a neighbour of both seams.
+        let mut replacement: GGraph = GGraph::new();
+        let i = replacement.add_vertex(VertexKind::Boundary, Phase::zero());
+        let o = replacement.add_vertex(VertexKind::Boundary, Phase::zero());
+        let z = replacement.add_vertex(VertexKind::Z, Phase::new(1, 4));
+        replacement.add_edge(i, z, EdgeKind::Hadam);
+        replacement.add_edge(z, o, EdgeKind::Hadam);
+        replacement.set_input(i, true);
+        replacement.set_output(o, true);
+
+        g.replace_subgraph(&[v], replacement, &cut);
+        assert_eq!(g.num_vertices(), 3);
+        assert!(g.check_invariants().is_ok());
+
+        let mut expected: GGraph = GGraph::new();
+        let z = spider_wire(&mut expected, VertexKind::Z, Phase::new(1, 4));
+        for e in expected.incident_edges(z).collect::<Vec<_>>() {
+            expected.set_edge_kind(e, EdgeKind::Hadam);
+        }
+        assert_graph_eq(&g, &expected);
+    }
+
+    fn positioned(g: &mut GGraph, kind: VertexKind, q: i32, r: i32) -> V {
+        let v = g.add_vertex(kind, Phase::zero());
+        g.set_position(v, q, r);
+        v
+    }
+
+    fn positioned_circuit(num_qubits: u32, gates: &[Gate]) -> GGraph {
+        Circuit::from_gates(num_qubits, gates).to_graph_into(GGraph::builder())
+    }
+
+    /// Map the i-th name to the i-th boundary
+    fn wire_map(names: &[&str]) -> Vec<(String, u32)> {
+        names
+            .iter()
+            .enumerate()
+            .map(|(i, &name)| (name.to_string(), i as u32))
+            .collect()
+    }
+
+    fn find_hole(g: &GGraph, name: &str) -> V {
+        g.vertices()
+            .find(|&v| g.vertex(v).unwrap().hole().is_some_and(|h| h.name == name))
+            .unwrap()
+    }
+
+    /// Two-qubit diagram `CNOT; (id x PHASE); QFT2` of holes, with identity spiders between
+    /// them so that no two holes are adjacent.
+    fn hole_diagram() -> GGraph {
+        let mut g: GGraph = GGraph::new();
+        let wire = |g: &mut GGraph, kind, r| -> Vec<V> {
+            (0..2).map(|q| positioned(g, kind, q, r)).collect()
+        };
+        let i = wire(&mut g, VertexKind::Boundary, 0);
+        let a = positioned(&mut g, VertexKind::Hole, 0, 2);
+        let s = wire(&mut g, VertexKind::Z, 4);
+        let b = positioned(&mut g, VertexKind::Hole, 1, 6);
+        let t = wire(&mut g, VertexKind::Z, 8);
+        let c = positioned(&mut g, VertexKind::Hole, 0, 10);
+        let o = wire(&mut g, VertexKind::Boundary, 12);
+        // The edges of each hole are added against the wire order, which is read from the
+        // positions of the neighbours.
+        let edges = [
+            (i[1], a),
+            (i[0], a),
+            (a, s[1]),
+            (a, s[0]),
+            (s[0], t[0]),
+            (s[1], b),
+            (b, t[1]),
+            (t[1], c),
+            (t[0], c),
+            (c, o[1]),
+            (c, o[0]),
+        ];
+        for &(x, y) in edges.iter() {
+            g.add_edge(x, y, EdgeKind::Regular);
+        }
+        g.set_io_order(i, o);
+        for &(v, name, wires) in [
+            (a, "CNOT", ["c", "t", "c'", "t'"].as_ref()),
+            (b, "PHASE", ["in", "out"].as_ref()),
+            (c, "QFT2", ["a", "b", "a'", "b'"].as_ref()),
+        ]
+        .iter()
+        {
+            g.vertex_mut(v)
+                .unwrap()
+                .set_hole(Some(Hole::new(name, wires.iter().copied())));
+        }
+        g
+    }
+
+    /// `a; (id x b); c`, built with `compose` and `tensor`
+    fn manual_composition(a: GGraph, b: GGraph, c: GGraph) -> GGraph {
+        let mut middle = positioned_circuit(1, &[]);
+        middle.tensor(b);
+        let mut res = a;
+        res.compose(middle).unwrap();
+        res.compose(c).unwrap();
+        res
+    }
+
+    #[test]
+    fn substitute_holes() {
+        let diagram = hole_diagram();
+        assert_eq!(
+            diagram
+                .vertices()
+                .filter(|&v| diagram.vertex(v).unwrap().kind() == VertexKind::Hole)
+                .count(),
+            3
+        );
+
+        let phase = positioned_circuit(1, &[Gate::T(0)]);
+        let mut g = diagram.clone();
+        g.substitute(find_hole(&g, "PHASE"), &phase, &wire_map(&["in", "out"]))
+            .unwrap();
+        // The implementation is moved to the qubit and row of the hole.
+        let spider = |g: &GGraph| {
+            g.vertices()
+                .find(|&v| g.vertex(v).unwrap().phase() == Phase::new(1, 4))
+                .unwrap()
+        };
+        let t = spider(&phase);
+        let t_row = phase.row(t) - phase.vertices().map(|v| phase.row(v)).min().unwrap();
+        assert_eq!((g.qubit(spider(&g)), g.row(spider(&g))), (1, 6 + t_row));
+        let cnot = wire_map(&["c", "t", "c'", "t'"]);
+        g.substitute(find_hole(&g, "CNOT"), &templates::cnot(), &cnot)
+            .unwrap();
+        g.substitute(
+            find_hole(&g, "QFT2"),
+            &templates::qft(2),
+            &wire_map(&["a", "b", "a'", "b'"]),
+        )
+        .unwrap();
+        assert!(g.check_invariants().is_ok());
+        assert_graph_eq(
+            &g,
+            &manual_composition(templates::cnot(), phase, templates::qft(2)),
+        );
+
+        // Other implementations, with the control and target of the CNOT exchanged by the map.
+        let phase = positioned_circuit(1, &[Gate::H(0), Gate::S(0)]);
+        let qft = positioned_circuit(
+            2,
+            &[Gate::Cz(0, 1), Gate::H(1), Gate::Rx(0, Phase::new(1, 3))],
+        );
+        let mut g = diagram;
+        g.substitute(
+            find_hole(&g, "QFT2"),
+            &qft,
+            &wire_map(&["a", "b", "a'", "b'"]),
+        )
+        .unwrap();
+        let swapped = wire_map(&["t", "c", "t'", "c'"]);
+        g.substitute(find_hole(&g, "CNOT"), &templates::cnot(), &swapped)
+            .unwrap();
+        g.substitute(find_hole(&g, "PHASE"), &phase, &wire_map(&["in", "out"]))
+            .unwrap();
+        assert!(g.check_invariants().is_ok());
+        let mut swapped_cnot = positioned_circuit(2, &[Gate::Swap(0, 1)]);
+        swapped_cnot.compose(templates::cnot()).unwrap();
+        swapped_cnot
+            .compose(positioned_circuit(2, &[Gate::Swap(0, 1)]))
+            .unwrap();
+        assert_graph_eq(&g, &manual_composition(swapped_cnot, phase, qft));
+    }
+
+    #[test]
+    fn substitute_validates_the_interface() {
+        let g = hole_diagram();
+        let b = find_hole(&g, "PHASE");
+        let phase = positioned_circuit(1, &[Gate::T(0)]);
+        let named = |pairs: &[(&str, u32)]| -> Vec<(String, u32)> {
+            pairs.iter().map(|&(n, i)| (n.to_string(), i)).collect()
+        };
+        let cases = [
+            (
+                named(&[("in", 0), ("x", 1)]),
+                SubstError::UnknownWire { name: "x".into() },
+            ),
+            (
+                named(&[("in", 0)]),
+                SubstError::MissingWire { name: "out".into() },
+            ),
+            (
+                named(&[("in", 0), ("in", 1)]),
+                SubstError::DuplicateWire { name: "in".into() },
+            ),
+            (
+                named(&[("in", 0), ("out", 2)]),
+                SubstError::BoundaryOutOfRange { index: 2 },
+            ),
+            (
+                named(&[("in", 1), ("out", 1)]),
+                SubstError::DuplicateBoundary { index: 1 },
+            ),
+        ];
+        for (map, err) in cases.iter() {
+            let mut h = g.clone();
+            assert_eq!(h.substitute(b, &phase, map).as_ref(), Err(err));
+            assert!(is_isomorphic(&h, &g));
+        }
+
+        let mut h = g.clone();
+        let map = wire_map(&["in", "out"]);
+        let input = h.inputs().next().unwrap();
+        assert_eq!(h.substitute(input, &phase, &map), Err(SubstError::NotAHole));
+        let a = find_hole(&h, "CNOT");
+        assert_eq!(
+            h.substitute(a, &phase, &wire_map(&["c", "t", "c'", "t'"])),
+            Err(SubstError::BoundaryMismatch {
+                wires: 4,
+                boundaries: 2
+            })
+        );
+        h.vertex_mut(b)
+            .unwrap()
+            .set_hole(Some(Hole::new("PHASE", ["in", "in"].iter().copied())));
+        assert_eq!(
+            h.substitute(b, &phase, &named(&[("in", 0)])),
+            Err(SubstError::DuplicateWire { name: "in".into() })
+        );
+        let extra = h.add_vertex(VertexKind::Z, Phase::zero());
+        h.add_edge(b, extra, EdgeKind::Regular);
+        assert_eq!(
+            h.substitute(b, &phase, &map),
+            Err(SubstError::ArityMismatch { wires: 2, edges: 3 })
+        );
+    }
+
+    #[test]
+    fn induced_subgraph_follows_given_order() {
+        let mut g: GGraph = GGraph::new();
+        let vs: Vec<_> = (0..6)
+            .map(|k| g.add_vertex(VertexKind::Z, Phase::new(k, 4)))
+            .collect();
+        for (&a, &b) in vs.iter().tuple_windows() {
+            g.add_edge(a, b, EdgeKind::Hadam);
+        }
+        let order = [vs[4], vs[1], vs[3], vs[2]];
+        let (sub, map) = g.induced_subgraph(&order, false);
+        let new: Vec<_> = order.iter().map(|v| map[v]).collect();
+        assert_eq!(new, sub.vertices().collect::<Vec<_>>());
+        let edges: Vec<_> = sub
+            .edges()
+            .map(|e| {
+                let (a, b) = sub.edge_endpoints(e).unwrap();
+                (a.index(), b.index())
+            })
+            .collect();
+        // Edges come from the incident edges of the vertices, in the given order.
+        assert_eq!(edges, [(2, 0), (1, 3), (3, 2)]);
+    }
+
+    #[test]
+    fn induced_and_replace_subgraph_round_trip() {
+        let mut g: GGraph = GGraph::new();
+        let a = spider_wire(&mut g, VertexKind::Z, Phase::new(1, 4));
+        let b = g.add_vertex(VertexKind::X, Phase::new(3, 4));
+        let out = g.outputs().next().unwrap();
+        let e = g.incident_edges(out).next().unwrap();
+        g.remove_edge(e);
+        g.add_edge(a, b, EdgeKind::Hadam);
+        g.add_edge(b, out, EdgeKind::Regular);
+        let original = g.clone();
+
+        let cut = g.cut_edges(&[a, b]);
+        let (mut sub, _) = g.induced_subgraph(&[a, b], true);
+        let first = sub.outputs().next().unwrap();
+        sub.set_output(first, false);
+        sub.set_input(first, true);
+        g.replace_subgraph(&[a, b], sub, &cut);
+        assert!(g.check_invariants().is_ok());
+        assert_graph_eq(&g, &original);
+    }
+
+    /// Diagram of a 3-qubit circuit, with its layout
+    fn circuit_of(gates: &[Gate]) -> GGraph {
+        let mut c = Circuit::new(3);
+        for &gate in gates {
+            c.add_gate(gate);
+        }
+        c.to_graph_into(GGraph::builder())
+    }
+
+    #[test]
+    fn neighbourhood_of_a_spider() {
+        let gates = [Gate::T(0), Gate::Cx(0, 1), Gate::T(0)];
+        let g = circuit_of(&gates);
+        let control = g
+            .vertices()
+            .find(|&v| {
+                let data = g.vertex(v).unwrap();
+                g.qubit(v) == 0 && data.kind() == VertexKind::Z && data.phase().is_zero()
+            })
+            .unwrap();
+
+        // The spider and a stub for each of its three edges.
+        let (sub, map) = g.neighbourhood(control, 0);
+        assert_eq!(map.len(), 1);
+        assert_eq!(sub.num_vertices(), 4);
+        assert!(sub.check_invariants().is_ok());
+        // The stub of the first T gate is on the input side, and the stub of the target, as far
+        // from the inputs as from the outputs, is an output.
+        assert_eq!(sub.inputs().count(), 1);
+        assert_eq!(sub.outputs().count(), 2);
+        assert!(sub.connected(map[&control], sub.inputs().next().unwrap()));
+
+        // The whole component of the first two qubits.
+        let (sub, map) = g.neighbourhood(control, 100);
+        assert_eq!(map.len(), 8);
+        assert_eq!(sub.num_vertices(), 8);
+        let mut c = Circuit::new(2);
+        for gate in gates {
+            c.add_gate(gate);
+        }
+        assert!(is_isomorphic(&sub, &c.to_graph::<GGraph>()));
+    }
+
+    #[test]
+    fn replace_middle_slice_by_identity() {
+        // A layer of T gates on rows 1, a slice on rows 2 and 3, and another layer on row 4
+        let left = [Gate::T(0), Gate::T(1), Gate::T(2)];
+        let slice = [Gate::Cx(0, 1), Gate::Cz(1, 2), Gate::X(0)];
+        let right = [Gate::Tdg(0), Gate::S(1), Gate::Z(2)];
+        let mut g = circuit_of(&[&left[..], &slice, &right].concat());
+        let vs: Vec<_> = g
+            .vertices()
+            .filter(|&v| (2..=3).contains(&g.row(v)))
+            .collect();
+        assert_eq!(vs.len(), 5);
+
+        // Match the cut edges with the wires of the identity: the ones on the left of the slice
+        // with the inputs, in qubit order, and the ones on the right with the outputs.
+        let mut cut = g.cut_edges(&vs);
+        assert_eq!(cut.len(), 6);
+        let outside = |g: &GGraph, e| {
+            let (a, b) = g.edge_endpoints(e).unwrap();
+            if vs.contains(&a) {
+                b
+            } else {
+                a
+            }
+        };
+        cut.sort_by_key(|&e| {
+            let v = outside(&g, e);
+            (g.row(v), g.qubit(v))
+        });
+        let mut identity: GGraph = GGraph::new();
+        let inputs: Vec<_> = (0..3)
+            .map(|_| identity.add_vertex(VertexKind::Boundary, Phase::zero()))
+            .collect();
+        for &i in &inputs {
+            let o = identity.add_vertex(VertexKind::Boundary, Phase::zero());
+            identity.add_edge(i, o, EdgeKind::Regular);
+            identity.set_input(i, true);
+        }
+        for o in identity.vertices().skip(3).collect::<Vec<_>>() {
+            identity.set_output(o, true);
+        }
+        g.replace_subgraph(&vs, identity, &cut);
+        // The CX and CZ gates of the slice contributed a factor of 2 to the scalar.
+        g.scalar_mut().add_power(-2);
+
+        let expected = circuit_of(&[&left[..], &right].concat());
+        assert!(g.check_invariants().is_ok());
+        assert_graph_eq(&g, &expected);
+        remove_identities(&mut g);
+        assert!(is_isomorphic(&g, &expected));
+    }
+
+    /// Two rounds of CNOTs between neighbouring qubits of a 4-qubit ladder, then a T on qubit 1,
+    /// with the layout of the circuit
+    fn ladder() -> GGraph {
+        let mut c = Circuit::new(4);
+        for _ in 0..2 {
+            for q in 0..3 {
+                c.add_gate(Gate::Cx(q, q + 1));
+            }
+        }
+        c.add_gate(Gate::T(1));
+        c.to_graph_into(GGraph::builder())
+    }
+
+    #[test]
+    fn qubit_slices() {
+        let g = ladder();
+        let slice = g.qubit_slice(1);
+        assert_eq!(slice.len(), 7);
+        assert!(g.is_input(slice[0]));
+        assert!(g.is_output(slice[6]));
+        assert_eq!(g.vertex(slice[5]).unwrap().phase(), Phase::new(1, 4));
+        assert!(slice
+            .iter()
+            .tuple_windows()
+            .all(|(&a, &b)| g.row(a) < g.row(b)));
+        assert!(slice.iter().all(|&v| g.qubit(v) == 1));
+        assert!(g.qubit_slice(4).is_empty());
+    }
+
+    #[test]
+    fn ladder_interactions() {
+        let g = ladder();
+        let matrix = g.interaction_matrix();
+        assert_eq!(
+            matrix,
+            [[3, 2, 0, 0], [2, 6, 2, 0], [0, 2, 5, 2], [0, 0, 2, 3]]
+        );
+        for (a, row) in matrix.iter().enumerate() {
+            for (b, &count) in row.iter().enumerate() {
+                assert_eq!(count, matrix[b][a]);
+                if a.abs_diff(b) > 1 {
+                    assert_eq!(count, 0);
+                }
+            }
+        }
+
+        let profile = g.wire_profile();
+        let wire = |spiders, t_spiders, crossing_edges| WireStats {
+            spiders,
+            t_spiders,
+            crossing_edges,
+        };
+        assert_eq!(
+            profile,
+            [wire(2, 0, 2), wire(5, 1, 4), wire(4, 0, 4), wire(2, 0, 2)]
+        );
+    }
+
+    #[test]
+    fn phase_counts() {
+        let mut g: GGraph = GGraph::new();
+        let targets: Vec<_> = [
+            (VertexKind::Z, Phase::new(1, 4)),
+            (VertexKind::X, Phase::new(3, 4)),
+            (VertexKind::Z, Phase::new(1, 2)),
+            (VertexKind::X, Phase::pi()),
+            (VertexKind::Z, Phase::zero()),
+            (VertexKind::Z, Phase::new(1, 3)),
+        ]
+        .iter()
+        .map(|&(kind, phase)| spider_wire(&mut g, kind, phase))
+        .collect();
+        // H-boxes are not counted, whatever their phase.
+        spider_wire(&mut g, VertexKind::HBox, Phase::new(1, 4));
+        // A T gadget on two of the spiders: only the leaf has a phase.
+        let axle = g.add_vertex(VertexKind::Z, Phase::zero());
+        let leaf = g.add_vertex(VertexKind::Z, Phase::new(1, 4));
+        g.add_edge(axle, leaf, EdgeKind::Hadam);
+        g.add_edge(axle, targets[2], EdgeKind::Hadam);
+        g.add_edge(axle, targets[4], EdgeKind::Hadam);
+
+        assert_eq!(g.tcount(), 3);
+        assert_eq!(g.clifford_count(), 2);
+        let phases = g.non_clifford_phases();
+        assert_eq!(phases.len(), 3);
+        assert_eq!(phases[&Phase::new(1, 4)], 2);
+        assert_eq!(phases[&Phase::new(3, 4)], 1);
+        assert_eq!(phases[&Phase::new(1, 3)], 1);
+    }
+
+    #[test]
+    fn ladder_summary() {
+        assert_eq!(
+            ladder().summary(),
+            "Graph(backend=GGraph-rs, qubits=4, depth=7, tcount=1, two_qubit_edges=6, \
+             inputs=4, outputs=4)"
+        );
+    }
+
+    #[test]
+    fn phase_wrapping() {
+        assert_eq!(Phase::new(3, 2) + Phase::new(1, 2), Phase::zero());
+        assert_eq!(Phase::new(2, 1), Phase::zero());
+        assert_eq!(Phase::new(9, 4), Phase::new(1, 4));
+        assert_eq!(Phase::new(7, 2), Phase::new(3, 2));
+        assert_eq!(Phase::new(2, 4), Phase::new(1, 2));
+        let mut p = Phase::new(7, 4);
+        p += Phase::new(3, 4);
+        assert_eq!((p.numerator(), p.denominator()), (1, 2));
+    }
+
+    #[test]
+    fn negative_phases() {
+        assert_eq!(Phase::new(-1, 4), Phase::new(7, 4));
+        assert_eq!(Phase::new(-9, 4), Phase::new(7, 4));
+        assert_eq!(Phase::new(-2, 1), Phase::zero());
+        assert_eq!(-Phase::new(1, 4), Phase::new(7, 4));
+        assert_eq!(-Phase::zero(), Phase::zero());
+        assert_eq!(-Phase::pi(), Phase::pi());
+        assert_eq!(Phase::new(1, 4) - Phase::new(1, 2), Phase::new(7, 4));
+        let p = Phase::new(-1, 3);
+        assert_eq!((p.numerator(), p.denominator()), (5, 3));
+    }
+
+    #[test]
+    fn non_dyadic_phases() {
+        assert_eq!(Phase::new(1, 3) + Phase::new(5, 3), Phase::zero());
+        assert_eq!(Phase::new(1, 3) + Phase::new(1, 6), Phase::new(1, 2));
+        assert_eq!(Phase::new(2, 5) - Phase::new(3, 5), Phase::new(9, 5));
+        assert_eq!(Phase::new(13, 7), -Phase::new(1, 7));
+        let p = Phase::new(1, 3) + Phase::new(1, 5);
+        assert_eq!((p.numerator(), p.denominator()), (8, 15));
+        assert!(!p.is_clifford());
+        assert!(!p.is_proper_t());
+    }
+
+    #[test]
+    fn phase_classes() {
+        assert!(Phase::zero().is_zero());
+        assert!(Phase::zero().is_pauli());
+        assert!(Phase::pi().is_pauli());
+        assert!(!Phase::pi().is_zero());
+        assert!(!Phase::new(1, 2).is_pauli());
+        assert!(Phase::new(1, 2).is_clifford());
+        assert!(Phase::new(-1, 2).is_clifford());
+        assert!(Phase::pi().is_clifford());
+        assert!(!Phase::new(1, 4).is_clifford());
+        assert!(Phase::new(1, 4).is_proper_t());
+        assert!(Phase::new(-3, 4).is_proper_t());
+        assert!(!Phase::new(2, 4).is_proper_t());
+        assert!(!Phase::new(1, 8).is_proper_t());
+    }
+
+    #[test]
+    fn phase_display() {
+        assert_eq!(Phase::zero().to_string(), "0");
+        assert_eq!(Phase::pi().to_string(), "π");
+        assert_eq!(Phase::new(-1, 4).to_string(), "7π/4");
+        assert_eq!(Phase::new(1, 2).to_string(), "π/2");
+        assert_eq!(format!("{:#}", Phase::new(3, 2)), "3/2");
+    }
+
+    #[test]
+    fn dump_snapshot() {
+        assert_eq!(VertexKind::HBox.to_string(), "HBox");
+        assert_eq!(EdgeKind::Hadam.to_string(), "H");
+
+        // in -- Z(π/4) -H- X, with the Z spider also connected to an H-box and an output
+        let mut g: GGraph = GGraph::new();
+        let i = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        let z = g.add_vertex(VertexKind::Z, Phase::new(1, 4));
+        let x = g.add_vertex(VertexKind::X, Phase::zero());
+        let h = g.add_vertex(VertexKind::HBox, Phase::pi());
+        let o = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        g.add_edge(z, i, EdgeKind::Regular);
+        g.add_edge(z, x, EdgeKind::Hadam);
+        g.add_edge(z, h, EdgeKind::Regular);
+        g.add_edge(z, o, EdgeKind::Regular);
+        g.set_input(i, true);
+        g.set_output(o, true);
+        g.set_phase(h, Phase::new(1, 2));
+        let dump = "\
+v0: Boundary [in] -- {v1:R}
+v1: Z(π/4) -- {v0:R, v2:H, v3:R, v4:R}
+v2: X -- {v1:H}
+v3: HBox(π/2) -- {v1:R}
+v4: Boundary [out] -- {v1:R}
+";
+        assert_eq!(g.dump(), dump);
+        assert_eq!(
+            format!("{:?}", g),
+            format!("GGraph(scalar={})\n{}", g.scalar(), dump)
+        );
+
+        // The default phase of H-boxes is left out, and vertices are renumbered after a removal.
+        g.set_phase(h, Phase::pi());
+        g.remove_vertex(x);
+        assert!(g.dump().contains("HBox -- {"));
+        assert_eq!(g.dump().lines().count(), 4);
+        assert!(g.dump().lines().all(|l| !l.contains("v4")));
+    }
+
+    #[test]
+    fn add_edge_smart_for_each_combination() {
+        let (r, h) = (EdgeKind::Regular, EdgeKind::Hadam);
+        let kinds = [VertexKind::Z, VertexKind::X];
+        for (ka, kb) in [
+            (kinds[0], kinds[0]),
+            (kinds[0], kinds[1]),
+            (kinds[1], kinds[1]),
+        ] {
+            for (first, second) in [(r, r), (r, h), (h, r), (h, h)] {
+                let mut g: GGraph = GGraph::new();
+                let a = spider_wire(&mut g, ka, Phase::new(1, 4));
+                let b = spider_wire(&mut g, kb, Phase::new(1, 2));
+                g.add_edge_smart(a, b, first);
+                // The same edges, kept parallel
+                let mut multi = g.clone();
+                multi.add_edge(a, b, second);
+
+                let left = g.add_edge_smart(a, b, second);
+                let (f1, f2) = (
+                    is_fusing_edge(first, ka, kb),
+                    is_fusing_edge(second, ka, kb),
+                );
+                let context = format!("{:?} {:?} {:?} {:?}", ka, kb, first, second);
+                let expected = match (f1, f2) {
+                    (false, false) => None,
+                    (true, _) => Some(first),
+                    (false, true) => Some(second),
+                };
+                assert_eq!(
+                    left.map(|e| g.edge(e).unwrap().kind()),
+                    expected,
+                    "{}",
+                    context
+                );
+                assert_eq!(g.edge_between(a, b), left, "{}", context);
+                assert_eq!(g.num_edges(), 4 + left.iter().count() as u32, "{}", context);
+                let phase = if f1 != f2 {
+                    Phase::new(5, 4)
+                } else {
+                    Phase::new(1, 4)
+                };
+                assert_eq!(g.vertex(a).unwrap().phase(), phase, "{}", context);
+                assert_graph_eq(&g, &multi);
+            }
+        }
+    }
+
+    #[test]
+    fn add_edge_smart_self_loops_and_hboxes() {
+        let mut g: GGraph = GGraph::new();
+        let v = spider_wire(&mut g, VertexKind::X, Phase::new(1, 4));
+        let original = g.clone();
+        assert_eq!(g.add_edge_smart(v, v, EdgeKind::Regular), None);
+        assert_eq!(g.num_edges(), 2);
+        assert_graph_eq(&g, &original);
+        assert_eq!(g.add_edge_smart(v, v, EdgeKind::Hadam), None);
+        assert_eq!(g.vertex(v).unwrap().phase(), Phase::new(5, 4));
+        assert_eq!(*g.scalar(), Scalar::new(-1, Phase::zero()));
+
+        // Edges at H-boxes are always added.
+        let h = spider_wire(&mut g, VertexKind::HBox, Phase::pi());
+        g.add_edge_smart(v, h, EdgeKind::Regular);
+        g.add_edge_smart(v, h, EdgeKind::Regular);
+        assert_eq!(g.neighbours(h).filter(|&n| n == v).count(), 2);
+    }
+
+    #[test]
+    fn add_edges_in_simple_mode() {
+        let mut g: GGraph = GGraph::new();
+        g.set_mode(GraphMode::Simple);
+        let a = spider_wire(&mut g, VertexKind::Z, Phase::zero());
+        let b = spider_wire(&mut g, VertexKind::Z, Phase::zero());
+        let c = spider_wire(&mut g, VertexKind::X, Phase::zero());
+        let left = g.add_edges([
+            (a, b, EdgeKind::Hadam),
+            (b, c, EdgeKind::Regular),
+            (a, b, EdgeKind::Hadam),
+            (b, c, EdgeKind::Regular),
+            (a, c, EdgeKind::Hadam),
+        ]);
+        // The Hadamard edges between a and b cancel, and so do the regular ones between b and
+        // c, each with a factor of 1/2.
+        assert_eq!(left.len(), 3);
+        assert_eq!(g.edge_between(a, b), None);
+        assert_eq!(g.edge_between(b, c), None);
+        assert!(g.edge_between(a, c).is_some());
+        assert_eq!(*g.scalar(), Scalar::new(-4, Phase::zero()));
+        assert!(g.check_invariants().is_ok());
+    }
+
+    /// Two connected spiders on their own wires, with the given edge between them, added twice,
+    /// and a Hadamard self-loop on the first one
+    fn check_duplicate_edges<G: Graph + Default>(set_mode: fn(&mut G, GraphMode)) {
+        let build = |mode, kind_b, edge| {
+            let mut g = G::default();
+            set_mode(&mut g, mode);
+            let mut spiders = Vec::new();
+            for kind in [VertexKind::Z, kind_b] {
+                let i = g.add_vertex(VertexKind::Boundary, Phase::zero());
+                let v = g.add_vertex(kind, Phase::new(1, 4));
+                let o = g.add_vertex(VertexKind::Boundary, Phase::zero());
+                g.add_edge(i, v, EdgeKind::Regular);
+                g.add_edge(v, o, EdgeKind::Regular);
+                g.set_input(i, true);
+                g.set_output(o, true);
+                spiders.push(v);
+            }
+            let (a, b) = (spiders[0], spiders[1]);
+            let first = g.add_edge(a, b, edge);
+            let second = g.add_edge(a, b, edge);
+            let self_loop = g.add_edge(a, a, EdgeKind::Hadam);
+            (g, [a, b], [first, second, self_loop])
+        };
+        for kind_b in [VertexKind::Z, VertexKind::X] {
+            for edge in [EdgeKind::Regular, EdgeKind::Hadam] {
+                let (multi, _, added) = build(GraphMode::Multi, kind_b, edge);
+                assert!(added.iter().all(Option::is_some));
+                assert_eq!(multi.num_edges(), 7);
+                assert_eq!(multi.mode(), GraphMode::Multi);
+
+                let (simple, [a, b], added) = build(GraphMode::Simple, kind_b, edge);
+                assert_eq!(simple.mode(), GraphMode::Simple);
+                assert!(added[0].is_some());
+                // The self-loop becomes a pi phase.
+                assert!(added[2].is_none());
+                assert_eq!(simple.vertex(a).unwrap().phase(), Phase::new(5, 4));
+                let kept = simple.edge_between(a, b).is_some();
+                // Parallel Hadamard edges between spiders of the same colour cancel, like
+                // parallel regular edges between spiders of different colours. The others
+                // are kept as one edge.
+                let cancel = (edge == EdgeKind::Hadam) == (kind_b == VertexKind::Z);
+                assert_eq!(kept, !cancel);
+                assert_eq!(added[1].is_some(), kept);
+                assert_eq!(simple.num_edges(), 4 + kept as u32);
+                assert_eq!(simple.check_invariants(), Ok(()));
+                assert_graph_eq(&simple, &multi);
+            }
+        }
+    }
+
+    #[test]
+    fn duplicate_edges_in_each_mode() {
+        check_duplicate_edges::<GGraph>(GGraph::set_mode);
+        check_duplicate_edges::<StableGGraph>(StableGGraph::set_mode);
+        check_duplicate_edges::<VecGraph>(VecGraph::set_mode);
+    }
+
+    fn circuit_graph<G: Graph + Default>(num_qubits: u32, gates: &[Gate]) -> G {
+        Circuit::from_gates(num_qubits, gates).to_graph()
+    }
+
+    fn check_wire_order<G: Graph + Default + Clone>() {
+        // The outputs of `a` are listed in the reverse of their vertex order, so pairing them by
+        // vertex order instead of wire order would drop the swap.
+        let mut a: G = circuit_graph(2, &[Gate::T(0)]);
+        let inputs: Vec<_> = a.inputs().collect();
+        let outputs: Vec<_> = a.outputs().collect();
+        a.set_io_order(inputs.clone(), vec![outputs[1], outputs[0]]);
+        assert_eq!(a.output_at(0), Some(outputs[1]));
+        assert_eq!(a.output_at(2), None);
+        assert_eq!(a.input_at(1), Some(inputs[1]));
+        let mut g = a.clone();
+        g.compose(circuit_graph(2, &[Gate::H(0), Gate::S(1)]))
+            .unwrap();
+        let expected: G = circuit_graph(2, &[Gate::T(0), Gate::Swap(0, 1), Gate::H(0), Gate::S(1)]);
+        assert_graph_eq(&g, &expected);
+        // The first wire still starts at the T gate, though composing may invalidate handles.
+        let first = g.input_at(0).unwrap();
+        let next = g.neighbours(first).next().unwrap();
+        assert_eq!(g.vertex(next).unwrap().phase(), Phase::new(1, 4));
+
+        // The adjoint exchanges the lists, keeping their order.
+        let mut adjoint = a.clone();
+        adjoint.adjoint();
+        assert_eq!(
+            adjoint.inputs().collect::<Vec<_>>(),
+            [outputs[1], outputs[0]]
+        );
+        assert_eq!(adjoint.outputs().collect::<Vec<_>>(), inputs);
+
+        // Tensoring appends the wires of the second diagram after those of the first.
+        let mut g = a;
+        g.tensor(circuit_graph(1, &[Gate::H(0)]));
+        assert_eq!(g.inputs().count(), 3);
+        assert_eq!(
+            &g.outputs().collect::<Vec<_>>()[..2],
+            [outputs[1], outputs[0]]
+        );
+        let expected: G = circuit_graph(3, &[Gate::T(0), Gate::Swap(0, 1), Gate::H(2)]);
+        assert_graph_eq(&g, &expected);
+    }
+
+    /// A triangle `a, b, c` with parallel edges between `a` and `b`, a self-loop on `c`, and an
+    /// isolated vertex, after removing a vertex so that the indices have a gap
+    fn edge_fixture<G: Graph + Default>() -> (G, [G::VertexIx; 4]) {
+        let mut g = G::default();
+        let removed = g.add_vertex(VertexKind::X, Phase::zero());
+        let a = g.add_vertex(VertexKind::Z, Phase::new(1, 4));
+        let b = g.add_vertex(VertexKind::X, Phase::zero());
+        let c = g.add_vertex(VertexKind::HBox, Phase::pi());
+        let d = g.add_vertex(VertexKind::Z, Phase::zero());
+        g.add_edge(removed, a, EdgeKind::Regular);
+        g.add_edge(a, b, EdgeKind::Regular);
+        g.add_edge(a, b, EdgeKind::Hadam);
+        g.add_edge(b, c, EdgeKind::Hadam);
+        g.add_edge(c, a, EdgeKind::Regular);
+        g.add_edge(c, c, EdgeKind::Regular);
+        g.remove_vertex(removed);
+        // Backends that fill the gap move the last vertex into it.
+        let d = if g.vertex(d).is_some() { d } else { removed };
+        (g, [a, b, c, d])
+    }
+
+    fn check_edge_iterators<G: Graph + Default>() {
+        let (g, [a, b, c, d]) = edge_fixture::<G>();
+        assert_eq!(g.num_edges(), 5);
+        let expected: Vec<_> = g
+            .edges()
+            .map(|e| {
+                let (s, t) = g.edge_endpoints(e).unwrap();
+                (s, t, g.edge(e).unwrap().kind())
+            })
+            .collect();
+        let found: Vec<_> = g
+            .edges_with_data()
+            .map(|(s, t, edata)| (s, t, edata.kind()))
+            .collect();
+        assert_eq!(found, expected);
+
+        for v in [a, b, c, d] {
+            let expected: Vec<_> = g
+                .incident_edges(v)
+                .map(|e| {
+                    let (s, t) = g.edge_endpoints(e).unwrap();
+                    (e, if s == v { t } else { s })
+                })
+                .collect();
+            let found: Vec<_> = g.incident_edges_with_neighbours(v).collect();
+            assert_eq!(found, expected);
+            assert_eq!(g.vertex_degree(v) as usize, expected.len());
+        }
+        assert_eq!([a, b, c, d].map(|v| g.vertex_degree(v)), [3, 3, 3, 0]);
+        assert!(g.connected(a, b) && g.connected(b, a) && g.connected(c, c));
+        assert!(!g.connected(a, a) && !g.connected(a, d) && !g.connected(d, d));
+    }
+
+    /// `in -- a`, then `a` to `b` and `c`, both to `d`, and `d -- out`, plus an isolated `e`.
+    /// Returns the vertices `[in, a, b, c, d, out, e]`.
+    fn diamond<G: Graph + Default>() -> (G, [G::VertexIx; 7]) {
+        let mut g = G::default();
+        let i = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        let vs: Vec<_> = (0..4)
+            .map(|_| g.add_vertex(VertexKind::Z, Phase::zero()))
+            .collect();
+        let o = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        let e = g.add_vertex(VertexKind::X, Phase::zero());
+        let [a, b, c, d] = [vs[0], vs[1], vs[2], vs[3]];
+        for (s, t) in [(i, a), (a, b), (a, c), (b, d), (c, d), (d, o)] {
+            g.add_edge(s, t, EdgeKind::Hadam);
+        }
+        g.set_input(i, true);
+        g.set_output(o, true);
+        (g, [i, a, b, c, d, o, e])
+    }
+
+    /// Layers with their vertices sorted, as the order within a layer depends on the backend
+    fn sorted_layers<V: Ord>(mut layers: Vec<Vec<V>>) -> Vec<Vec<V>> {
+        for layer in &mut layers {
+            layer.sort();
+        }
+        layers
+    }
+
+    fn check_traversals<G: Graph + Default>() {
+        let (g, [i, a, b, c, d, o, e]) = diamond::<G>();
+        let layers = |from: &[G::VertexIx]| sorted_layers(g.bfs_layers(from.iter().copied()));
+        assert_eq!(
+            layers(&[i]),
+            vec![vec![i], vec![a], vec![b, c], vec![d], vec![o]]
+        );
+        // The seeds keep their order.
+        assert_eq!(g.bfs_layers([o, e, o])[0], vec![o, e]);
+        assert_eq!(
+            layers(&[o, e]),
+            vec![vec![o, e], vec![d], vec![b, c], vec![a], vec![i]]
+        );
+        assert_eq!(layers(&[b, c]), vec![vec![b, c], vec![a, d], vec![i, o]]);
+        assert!(g.bfs_layers([]).is_empty());
+
+        assert_eq!(g.frontier(), vec![d]);
+
+        let path = g.shortest_path(i, o).unwrap();
+        assert_eq!(path.len(), 5);
+        assert_eq!((path[0], path[1], path[3], path[4]), (i, a, d, o));
+        assert!(path[2] == b || path[2] == c);
+        assert_eq!(g.shortest_path(b, b), Some(vec![b]));
+        assert_eq!(g.shortest_path(b, c).unwrap().len(), 3);
+        assert_eq!(g.shortest_path(i, e), None);
+    }
+
+    #[test]
+    fn frontier_of_a_diamond() {
+        // Boundaries and repeated neighbours are left out of the frontier.
+        let (mut g, [_, _, _, _, d, o, _]) = diamond::<GGraph>();
+        let o2 = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        g.add_edge(d, o2, EdgeKind::Regular);
+        g.set_output(o2, true);
+        let wire = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        g.add_edge(o, wire, EdgeKind::Regular);
+        g.set_input(wire, true);
+        assert_eq!(g.frontier(), vec![d]);
+        g.remove_vertex(d);
+        assert_eq!(g.frontier(), Vec::<V>::new());
+    }
+
+    fn check_edge_queries<G: Graph + Default>() {
+        let (mut g, [a, b, c, d]) = edge_fixture::<G>();
+        // The parallel edges between `a` and `b` have different kinds, so the first one in the
+        // order of `incident_edges` decides the kind.
+        for (v, u) in [(a, b), (b, a)] {
+            let first = g.incident_edges_with_neighbours(v).find(|&(_, n)| n == u);
+            assert_eq!(g.edge_between(v, u), first.map(|(e, _)| e));
+            let kind = g.edge(first.unwrap().0).unwrap().kind();
+            assert_eq!(g.edge_kind_between(v, u), Some(kind));
+        }
+        let mut kinds: Vec<_> = g
+            .neighbours_with_kind(a)
+            .filter(|&(n, _)| n == b)
+            .map(|(_, kind)| kind)
+            .collect();
+        kinds.sort_by_key(|&kind| kind == EdgeKind::Hadam);
+        assert_eq!(kinds, [EdgeKind::Regular, EdgeKind::Hadam]);
+
+        assert_eq!(g.edge_kind_between(b, c), Some(EdgeKind::Hadam));
+        assert_eq!(g.edge_kind_between(c, a), Some(EdgeKind::Regular));
+        assert_eq!(g.edge_kind_between(c, c), Some(EdgeKind::Regular));
+        assert_eq!(g.edge_between(a, d), None);
+        assert_eq!(g.edge_kind_between(a, a), None);
+        assert_eq!(g.neighbours_with_kind(d).count(), 0);
+        for v in [a, b, c] {
+            let expected: Vec<_> = g
+                .incident_edges_with_neighbours(v)
+                .map(|(e, n)| (n, g.edge(e).unwrap().kind()))
+                .collect();
+            assert_eq!(g.neighbours_with_kind(v).collect::<Vec<_>>(), expected);
+        }
+        assert_eq!(
+            g.neighbours_with_kind(c).filter(|&(n, _)| n == c).count(),
+            1
+        );
+
+        // Once the first edge is gone, the remaining one is found.
+        let (e, kind) = (
+            g.edge_between(a, b).unwrap(),
+            g.edge_kind_between(a, b).unwrap(),
+        );
+        g.remove_edge(e);
+        let other = match kind {
+            EdgeKind::Regular => EdgeKind::Hadam,
+            EdgeKind::Hadam => EdgeKind::Regular,
+        };
+        assert_eq!(g.edge_kind_between(a, b), Some(other));
+        assert_eq!(g.edge_kind_between(b, a), Some(other));
+    }
+
+    /// Vertices and edges added, changed and removed one at a time, with the slots of removed
+    /// vertices reused
+    fn check_vertex_slots<G: Graph + Default>() {
+        let mut g = G::default();
+        let a = g.add_vertex(VertexKind::Z, Phase::new(1, 4));
+        let b = g.add_vertex(VertexKind::X, Phase::zero());
+        let c = g.add_vertex(VertexKind::Z, Phase::zero());
+        let ab = g.add_edge(a, b, EdgeKind::Hadam).unwrap();
+        let bc = g.add_edge(b, c, EdgeKind::Regular).unwrap();
+        assert_eq!((g.num_vertices(), g.num_edges()), (3, 2));
+        // The endpoints may come in either order.
+        let (s, t) = g.edge_endpoints(ab).unwrap();
+        assert!((s, t) == (a, b) || (s, t) == (b, a));
+        assert_eq!(g.neighbours(b).count(), 2);
+
+        g.set_phase(a, Phase::new(3, 2));
+        g.set_vertex_kind(b, VertexKind::HBox);
+        g.set_edge_kind(bc, EdgeKind::Hadam);
+        assert_eq!(g.vertex(a).unwrap().phase(), Phase::new(3, 2));
+        assert_eq!(g.vertex(b).unwrap().kind(), VertexKind::HBox);
+        assert_eq!(g.edge_kind_between(c, b), Some(EdgeKind::Hadam));
+
+        g.remove_edge(ab);
+        assert_eq!(g.num_edges(), 1);
+        assert!(!g.connected(a, b));
+        g.remove_vertex(a);
+        assert_eq!((g.num_vertices(), g.num_edges()), (2, 1));
+        // Backends that fill the gap move the last vertex into it.
+        let c = if g.vertex(c).is_some() { c } else { a };
+        assert_eq!(g.edge_kind_between(b, c), Some(EdgeKind::Hadam));
+
+        // A new vertex may reuse the freed slot, and starts with no edges.
+        let d = g.add_vertex(VertexKind::X, Phase::pi());
+        assert_eq!(g.num_vertices(), 3);
+        assert_eq!(g.vertex_degree(d), 0);
+        assert_eq!(g.vertex(d).unwrap().phase(), Phase::pi());
+        g.add_edge(d, c, EdgeKind::Regular);
+        assert_eq!(g.vertex_degree(c), 2);
+        g.remove_vertices([b, d]);
+        assert_eq!((g.num_vertices(), g.num_edges()), (1, 0));
+        assert_eq!(g.check_invariants(), Ok(()));
+    }
+
+    /// The rewrite drivers preserve the linear map of a circuit and the invariants of the graph.
+    fn check_rewrite_drivers<G: Graph + Default + Clone>() {
+        let c = random_clifford_t_circuit(3, 40, 0.3, Seed(0));
+        let original: G = c.to_graph();
+        for driver in [clifford_simp::<G>, full_reduce::<G>] {
+            let mut g = original.clone();
+            driver(&mut g);
+            assert_eq!(g.check_invariants(), Ok(()));
+            assert!(g.num_vertices() < original.num_vertices());
+            assert_graph_eq(&original, &g);
+        }
+    }
+
+    /// Trait-level tests that every backend must pass
+    fn test_graph_impl<G: Graph + Default + Clone>() {
+        check_vertex_slots::<G>();
+        check_wire_order::<G>();
+        check_edge_iterators::<G>();
+        check_traversals::<G>();
+        check_edge_queries::<G>();
+        check_rewrite_drivers::<G>();
+    }
+
+    #[test]
+    fn graph_impls() {
+        test_graph_impl::<GGraph>();
+        test_graph_impl::<StableGGraph>();
+        test_graph_impl::<VecGraph>();
+    }
+
+    /// The rewrite drivers see through the generations of `CheckedGraph`, and never use a stale
+    /// handle.
+    #[test]
+    fn checked_graph_impl() {
+        test_graph_impl::<CheckedGraph<VecGraph>>();
+    }
+
+    type V = <GGraph as Graph>::VertexIx;
+
+    /// `in - Z - Z - out`, returning the vertices in order
+    fn short_wire() -> (GGraph, [V; 4]) {
+        let mut g: GGraph = GGraph::new();
+        let i = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        let a = g.add_vertex(VertexKind::Z, Phase::zero());
+        let b = g.add_vertex(VertexKind::Z, Phase::new(1, 4));
+        let o = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        g.add_edge(i, a, EdgeKind::Regular);
+        g.add_edge(a, b, EdgeKind::Hadam);
+        g.add_edge(b, o, EdgeKind::Regular);
+        g.set_input(i, true);
+        g.set_output(o, true);
+        (g, [i, a, b, o])
+    }
+
+    fn violations(g: &GGraph) -> Violations<GGraph> {
+        g.check_invariants().unwrap_err()
+    }
+
+    #[test]
+    fn invariants_of_boundaries() {
+        let (g, _) = short_wire();
+        assert_eq!(g.check_invariants(), Ok(()));
+
+        let (mut g, [i, _, b, _]) = short_wire();
+        g.add_edge(i, b, EdgeKind::Regular);
+        g.set_phase(i, Phase::new(1, 2));
+        let lone = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        assert_eq!(
+            violations(&g),
+            [
+                InvariantViolation::BoundaryPhase {
+                    vertex: i,
+                    phase: Phase::new(1, 2)
+                },
+                InvariantViolation::BoundaryDegree {
+                    vertex: i,
+                    degree: 2
+                },
+                InvariantViolation::BoundaryDegree {
+                    vertex: lone,
+                    degree: 0
+                },
+            ]
+        );
+        assert_eq!(
+            violations(&g)[1].to_string(),
+            format!("boundary {:?} has 2 edges instead of one", i)
+        );
+
+        // A bare wire is both an input and an output, without edges.
+        let (mut g, [i, ..]) = short_wire();
+        let wire = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        g.set_input(wire, true);
+        g.set_output(wire, true);
+        assert_eq!(g.check_invariants(), Ok(()));
+        g.set_output(i, true);
+        assert_eq!(
+            violations(&g),
+            [InvariantViolation::InputAndOutput {
+                vertex: i,
+                degree: 1
+            }]
+        );
+
+        let (mut g, _) = short_wire();
+        let missing = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        g.remove_vertex(missing);
+        g.set_input(missing, true);
+        assert_eq!(
+            violations(&g),
+            [InvariantViolation::UnknownBoundary { vertex: missing }]
+        );
+        assert_eq!(
+            violations(&g)[0].to_string(),
+            format!("input or output {:?} is not in the graph", missing)
+        );
+    }
+
+    #[test]
+    fn invariants_of_edges() {
+        // Self-loops are only allowed on spiders.
+        let (mut g, [i, a, ..]) = short_wire();
+        g.add_edge(a, a, EdgeKind::Hadam);
+        assert_eq!(g.check_invariants(), Ok(()));
+        let h = g.add_vertex(VertexKind::HBox, Phase::pi());
+        g.add_edge(h, a, EdgeKind::Regular);
+        let hloop = g.add_edge(h, h, EdgeKind::Regular).unwrap();
+        let iloop = g.add_edge(i, i, EdgeKind::Regular).unwrap();
+        let errors = violations(&g);
+        assert!(errors.contains(&InvariantViolation::SelfLoop {
+            edge: hloop,
+            vertex: h
+        }));
+        assert!(errors.contains(&InvariantViolation::SelfLoop {
+            edge: iloop,
+            vertex: i
+        }));
+
+        // Simple graphs allow neither self-loops nor parallel edges between spiders.
+        let (mut g, [_, a, b, _]) = short_wire();
+        let aloop = g.add_edge(a, a, EdgeKind::Hadam).unwrap();
+        let parallel = g.add_edge(b, a, EdgeKind::Regular).unwrap();
+        assert_eq!(g.check_invariants(), Ok(()));
+        g.set_mode(GraphMode::Simple);
+        let errors = violations(&g);
+        assert_eq!(errors.len(), 2);
+        assert!(errors.contains(&InvariantViolation::SpiderSelfLoop {
+            edge: aloop,
+            vertex: a
+        }));
+        assert!(errors.contains(&InvariantViolation::ParallelEdge {
+            edge: parallel,
+            vertices: (a, b)
+        }));
+        // Parallel edges to a boundary are already reported by its degree.
+        let (mut g, [i, a, ..]) = short_wire();
+        g.add_edge(i, a, EdgeKind::Regular);
+        g.set_mode(GraphMode::Simple);
+        assert_eq!(
+            violations(&g),
+            [InvariantViolation::BoundaryDegree {
+                vertex: i,
+                degree: 2
+            }]
+        );
+    }
+
+    #[test]
+    fn layout_invariants() {
+        let mut c = Circuit::new(2);
+        c.add_gate(Gate::Cx(0, 1));
+        let mut g: GGraph = c.to_graph_into(GGraph::builder());
+        assert_eq!(g.check_layout(), Ok(()));
+        let (i, o) = (g.inputs().next().unwrap(), g.outputs().next().unwrap());
+        g.set_row(i, 1);
+        g.set_row(o, 1);
+        assert_eq!(
+            g.check_layout(),
+            Err(vec![
+                InvariantViolation::InputRow { vertex: i },
+                InvariantViolation::OutputRow { vertex: o },
+            ])
+        );
+        assert_eq!(g.check_invariants(), Ok(()));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "broke the diagram: boundary")]
+    fn drivers_check_invariants() {
+        let (mut g, [i, _, b, _]) = short_wire();
+        g.add_edge(i, b, EdgeKind::Regular);
+        crate::zx::simplify::clifford_simp(&mut g);
+    }
 }
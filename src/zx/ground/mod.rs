@@ -0,0 +1,5 @@
+//! Diagrams of mixed states and channels.
+//!
+//! Grounded vertices themselves are part of the backends, see `GroundGraph`.
+
+pub mod noise;
@@ -0,0 +1,280 @@
+//! Noise channels on diagrams.
+//!
+//! A channel that is not a single Kraus operator up to discarding, like dephasing with a
+//! probability below 1/2, is a weighted sum of diagrams, and the scalar of a diagram cannot
+//! carry a probability. These channels act on a `Mixture` instead, an ensemble of pure diagrams
+//! with their probabilities. Only complete dephasing has a diagram of its own: a grounded Z
+//! spider, see `insert_complete_dephasing`.
+
+use crate::zx::graph::*;
+
+/// Ensemble of pure diagrams with the same boundaries, each with its probability.
+///
+/// The channels in this module give every component the same vertices and edges, differing
+/// only in their phases, so vertex and edge indices of one component are valid in all of them
+/// until the components are modified separately.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mixture<G> {
+    pub components: Vec<(f64, G)>,
+}
+
+impl<G: Graph + Clone> Mixture<G> {
+    /// The mixture with a single component
+    pub fn pure(g: G) -> Self {
+        Mixture {
+            components: vec![(1.0, g)],
+        }
+    }
+
+    /// Number of components
+    pub fn len(&self) -> usize {
+        self.components.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.components.is_empty()
+    }
+
+    /// Sum of the probabilities of the components
+    pub fn total_probability(&self) -> f64 {
+        self.components.iter().map(|(p, _)| p).sum()
+    }
+
+    /// Apply a function to every component, like a simplification
+    pub fn map(&mut self, mut f: impl FnMut(&mut G)) {
+        for (_, g) in &mut self.components {
+            f(g);
+        }
+    }
+
+    /// Sum of a function of the components, weighted by their probabilities, like an
+    /// expectation value. Stops at the first error.
+    pub fn evaluate<E>(&self, mut f: impl FnMut(&G) -> Result<f64, E>) -> Result<f64, E> {
+        self.components.iter().map(|(p, g)| Ok(p * f(g)?)).sum()
+    }
+
+    /// Replace every component by the given weighted variants of it, dropping the variants
+    /// with probability 0.
+    fn split(&mut self, weights: &[f64], mut variant: impl FnMut(&mut G, usize)) {
+        let components = std::mem::take(&mut self.components);
+        for (p, g) in components {
+            for (i, &w) in weights.iter().enumerate() {
+                if w > 0.0 {
+                    let mut h = g.clone();
+                    variant(&mut h, i);
+                    self.components.push((p * w, h));
+                }
+            }
+        }
+    }
+}
+
+/// Pauli noise on an edge of every component: `ρ -> (1 - px - py - pz) ρ + px XρX + py YρY +
+/// pz ZρZ`.
+///
+/// The edge is split by a Z spider and an X spider, next to its first endpoint as given by
+/// `Graph::edge_endpoints`, so on a Hadamard edge the noise acts before the Hadamard gate. The
+/// spiders have phase pi for the Paulis of each variant, with `Y = iXZ` up to its phase, which
+/// the channel ignores. Panics if the probabilities are negative or add up to more than 1.
+pub fn insert_pauli_noise<G: Graph + Clone>(
+    m: &mut Mixture<G>,
+    edge: G::EdgeIx,
+    px: f64,
+    py: f64,
+    pz: f64,
+) {
+    assert!(
+        px >= 0.0 && py >= 0.0 && pz >= 0.0 && px + py + pz <= 1.0 + 1e-12,
+        "Pauli noise probabilities must be non-negative and add up to at most 1"
+    );
+    let weights = [1.0 - px - py - pz, px, py, pz];
+    // Phases of the Z and the X spider for I, X, Y and Z
+    let phases = [(false, false), (false, true), (true, true), (true, false)];
+    m.split(&weights, |g, i| {
+        let (a, b) = g.edge_endpoints(edge).unwrap();
+        let kind = g.edge(edge).unwrap().kind();
+        let (z, x) = phases[i];
+        let phase = |flag| if flag { Phase::pi() } else { Phase::zero() };
+        let zs = g.add_vertex(VertexKind::Z, phase(z));
+        let xs = g.add_vertex(VertexKind::X, phase(x));
+        g.remove_edge(edge);
+        g.add_edge(a, zs, EdgeKind::Regular);
+        g.add_edge(zs, xs, EdgeKind::Regular);
+        g.add_edge(xs, b, kind);
+    });
+}
+
+/// Dephasing on an edge of every component: `ρ -> (1 - p) ρ + p ZρZ`. See
+/// `insert_pauli_noise`.
+pub fn insert_dephasing<G: Graph + Clone>(m: &mut Mixture<G>, edge: G::EdgeIx, p: f64) {
+    insert_pauli_noise(m, edge, 0.0, 0.0, p);
+}
+
+/// Depolarizing noise on an edge of every component: `ρ -> (1 - p) ρ + p/3 (XρX + YρY + ZρZ)`.
+/// See `insert_pauli_noise`.
+pub fn insert_depolarizing<G: Graph + Clone>(m: &mut Mixture<G>, edge: G::EdgeIx, p: f64) {
+    insert_pauli_noise(m, edge, p / 3.0, p / 3.0, p / 3.0);
+}
+
+/// Complete dephasing on an edge, `ρ -> (ρ + ZρZ) / 2`, which measures the wire in the Z basis
+/// and forgets the outcome: the edge is split by a grounded Z spider, next to its first endpoint.
+/// Returns the spider.
+pub fn insert_complete_dephasing<G>(g: &mut G, edge: G::EdgeIx) -> G::VertexIx
+where
+    G: GroundGraph,
+    G::VertexData: GroundData,
+{
+    let (a, b) = g.edge_endpoints(edge).unwrap();
+    let kind = g.edge(edge).unwrap().kind();
+    let v = g.add_vertex(VertexKind::Z, Phase::zero());
+    g.set_ground(v, true);
+    g.remove_edge(edge);
+    g.add_edge(a, v, EdgeKind::Regular);
+    g.add_edge(v, b, kind);
+    v
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::circuit::{Circuit, Gate};
+    use crate::zx::evaluate::{pauli_expectation, PauliString, SimError};
+    use crate::zx::simple_graph::GGraph;
+    use crate::zx::simplify::full_reduce;
+
+    /// Bell pair `(|00> + |11>) / sqrt(2)`
+    fn bell() -> GGraph {
+        let mut c = Circuit::new(2);
+        c.add_gate(Gate::H(0));
+        c.add_gate(Gate::Cx(0, 1));
+        let mut g: GGraph = c.to_graph();
+        let inputs: Vec<_> = g.inputs().collect();
+        for v in inputs {
+            g.set_input(v, false);
+            g.set_vertex_kind(v, VertexKind::X);
+            g.scalar_mut().add_power(-1);
+        }
+        g
+    }
+
+    /// Edge of the first output
+    fn first_wire(g: &GGraph) -> <GGraph as Graph>::EdgeIx {
+        g.incident_edges(g.output_at(0).unwrap()).next().unwrap()
+    }
+
+    fn expectation(m: &Mixture<GGraph>, pauli: &str) -> Result<f64, SimError> {
+        let pauli: PauliString = pauli.parse().unwrap();
+        m.evaluate(|g| pauli_expectation(g, &pauli))
+    }
+
+    fn assert_correlations(m: &Mixture<GGraph>, expected: [(&str, f64); 4]) {
+        for (pauli, value) in expected {
+            let found = expectation(m, pauli).unwrap();
+            assert!(
+                (found - value).abs() < 1e-9,
+                "{}: {} vs {}",
+                pauli,
+                found,
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn dephased_bell_pair() {
+        for p in [0.0, 0.1, 0.25, 0.5, 1.0] {
+            let g = bell();
+            let mut m = Mixture::pure(g.clone());
+            insert_dephasing(&mut m, first_wire(&g), p);
+            assert!((m.total_probability() - 1.0).abs() < 1e-12);
+            let c = 1.0 - 2.0 * p;
+            assert_correlations(&m, [("XX", c), ("YY", -c), ("ZZ", 1.0), ("ZI", 0.0)]);
+        }
+    }
+
+    #[test]
+    fn depolarized_bell_pair() {
+        for p in [0.0, 0.3, 0.75] {
+            let g = bell();
+            let mut m = Mixture::pure(g.clone());
+            insert_depolarizing(&mut m, first_wire(&g), p);
+            let c = 1.0 - 4.0 * p / 3.0;
+            assert_correlations(&m, [("XX", c), ("YY", -c), ("ZZ", c), ("IX", 0.0)]);
+        }
+    }
+
+    #[test]
+    fn channels_on_both_wires() {
+        // Two dephasings on the same wire add up, and the components share their indices.
+        let g = bell();
+        let mut m = Mixture::pure(g.clone());
+        let e = first_wire(&g);
+        insert_dephasing(&mut m, e, 0.1);
+        insert_dephasing(&mut m, e, 0.2);
+        let second = m.components[0].1.output_at(1).unwrap();
+        let e = m.components[0].1.incident_edges(second).next().unwrap();
+        insert_pauli_noise(&mut m, e, 0.05, 0.0, 0.0);
+        assert_eq!(m.len(), 8);
+        assert!(m
+            .components
+            .iter()
+            .all(|(_, h)| h.num_vertices() == g.num_vertices() + 6));
+        // The X flips of the second qubit leave XX and flip ZZ.
+        let xx = (1.0 - 0.2) * (1.0 - 0.4);
+        assert_correlations(
+            &m,
+            [("XX", xx), ("YY", -xx * 0.9), ("ZZ", 0.9), ("ZI", 0.0)],
+        );
+
+        // Simplifying the components keeps the values.
+        m.map(|h| {
+            full_reduce(h);
+        });
+        assert_correlations(
+            &m,
+            [("XX", xx), ("YY", -xx * 0.9), ("ZZ", 0.9), ("ZI", 0.0)],
+        );
+    }
+
+    #[test]
+    fn certain_and_failing_evaluations() {
+        let g = bell();
+        let mut m = Mixture::pure(g.clone());
+        insert_dephasing(&mut m, first_wire(&g), 0.0);
+        assert_eq!(m.len(), 1);
+        assert_eq!(
+            expectation(&m, "XXX"),
+            Err(SimError::ArityMismatch {
+                qubits: 3,
+                outputs: 2
+            })
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "add up to at most 1")]
+    fn probabilities_above_one() {
+        let g = bell();
+        let mut m = Mixture::pure(g.clone());
+        insert_pauli_noise(&mut m, first_wire(&g), 0.5, 0.5, 0.5);
+    }
+
+    #[test]
+    fn complete_dephasing_is_a_grounded_spider() {
+        let mut g = bell();
+        let e = first_wire(&g);
+        let (a, b) = g.edge_endpoints(e).unwrap();
+        let v = insert_complete_dephasing(&mut g, e);
+        assert!(g.is_ground(v));
+        assert_eq!(g.grounds().collect::<Vec<_>>(), [v]);
+        let mut neighbours: Vec<_> = g.neighbours(v).collect();
+        neighbours.sort();
+        let mut expected = vec![a, b];
+        expected.sort();
+        assert_eq!(neighbours, expected);
+        assert_eq!(
+            pauli_expectation(&g, &"XX".parse().unwrap()),
+            Err(SimError::Grounded)
+        );
+    }
+}
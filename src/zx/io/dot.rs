@@ -0,0 +1,932 @@
+//! Graphviz DOT import and export.
+//!
+//! Only undirected graphs are supported. Vertices are described by the attributes
+//! - `kind`: one of `Z`, `X`, `HBox`, `Hole`, `Boundary` (the default),
+//! - `label`: the phase as a multiple of pi, e.g. `π/4`, `3pi/2`, `-π` or `0`,
+//! - `io`: the boundary role, one of `input`, `output` or `both`,
+//!
+//! and Hadamard edges are marked with `style=dashed`. Any other attribute is accepted and ignored,
+//! and subgraphs are read as if their statements were at the top level.
+//!
+//! Vertices are emitted in the order they are first mentioned, which also defines the wire order
+//! of the inputs and outputs.
+//!
+//! The parser recovers from errors in a statement by skipping to its end, so that `parse_all`
+//! reports every error of a file.
+
+use crate::zx::graph::*;
+use crate::zx::io::sink::{GraphSink, IoFlags};
+use crate::zx::io::span::{Diagnostic, SourceMap, Span};
+use crate::zx::simple_graph::GGraph;
+use crate::zx::view::CollapsedView;
+use fraction::Fraction;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+
+/// Error produced while parsing a DOT file
+pub type DotError = Diagnostic;
+
+/// Parse a DOT description of a diagram, failing with its first error.
+pub fn parse(src: &str) -> Result<GGraph, DotError> {
+    parse_into(src, GGraph::builder())
+}
+
+/// Parse a DOT description of a diagram, failing with all its errors in source order.
+///
+/// After an error, the rest of the statement is skipped up to the next `;`, the `}` closing its
+/// graph or the end of its line, whichever comes first.
+pub fn parse_all(src: &str) -> Result<GGraph, Vec<DotError>> {
+    parse_all_into(src, GGraph::builder())
+}
+
+/// Parse a DOT description of a diagram, emitting it into a `GraphSink`.
+pub fn parse_into<S: GraphSink>(src: &str, sink: S) -> Result<S::Graph, DotError> {
+    parse_all_into(src, sink).map_err(|mut errors| errors.swap_remove(0))
+}
+
+/// Parse a DOT description of a diagram, emitting it into a `GraphSink` and failing with all its
+/// errors, see `parse_all`.
+pub fn parse_all_into<S: GraphSink>(src: &str, mut sink: S) -> Result<S::Graph, Vec<DotError>> {
+    let source = SourceMap::new(src);
+    let mut errors = Vec::new();
+    let tokens = tokenize(&source, &mut errors);
+    let mut parser = Parser {
+        source,
+        tokens,
+        pos: 0,
+        vertices: Vec::new(),
+        edges: Vec::new(),
+        names: HashMap::new(),
+        errors,
+    };
+    parser.parse();
+    let mut errors = std::mem::take(&mut parser.errors);
+    if !errors.is_empty() {
+        errors.sort_by_key(|e| e.span.start);
+        return Err(errors);
+    }
+
+    sink.begin(parser.vertices.len(), parser.edges.len());
+    for &(kind, phase, flags) in &parser.vertices {
+        sink.vertex(kind, phase, flags);
+    }
+    for &(u, v, kind) in &parser.edges {
+        sink.edge(u, v, kind);
+    }
+    sink.finish().map_err(|errors| {
+        let errors: Vec<_> = errors.iter().map(ToString::to_string).collect();
+        vec![parser.source.diagnostic(
+            Span::default(),
+            format!("invalid diagram: {}", errors.join("; ")),
+        )]
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Tok {
+    /// Identifier, number or quoted string
+    Id(String),
+    LBrace,
+    RBrace,
+    LBracket,
+    RBracket,
+    Equals,
+    Semicolon,
+    Comma,
+    /// Undirected edge operator `--`
+    Edge,
+    /// Directed edge operator `->`
+    Arrow,
+    Eof,
+}
+
+impl fmt::Display for Tok {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Tok::Id(s) => write!(f, "`{}`", s),
+            Tok::LBrace => write!(f, "`{{`"),
+            Tok::RBrace => write!(f, "`}}`"),
+            Tok::LBracket => write!(f, "`[`"),
+            Tok::RBracket => write!(f, "`]`"),
+            Tok::Equals => write!(f, "`=`"),
+            Tok::Semicolon => write!(f, "`;`"),
+            Tok::Comma => write!(f, "`,`"),
+            Tok::Edge => write!(f, "`--`"),
+            Tok::Arrow => write!(f, "`->`"),
+            Tok::Eof => write!(f, "end of file"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    tok: Tok,
+    span: Span,
+}
+
+fn is_id_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '.' || c == '-'
+}
+
+/// Split a file into tokens, skipping the characters that cannot start one and reporting them
+/// in `errors`. An unterminated comment or string ends the file.
+fn tokenize(source: &SourceMap, errors: &mut Vec<DotError>) -> Vec<Token> {
+    let src = source.source();
+    let mut chars = src.char_indices().peekable();
+    let mut tokens = Vec::new();
+    let at = |i: usize| src[i..].chars().next();
+
+    while let Some((start, c)) = chars.next() {
+        let next = chars.peek().map(|&(_, c)| c);
+        let simple = match c {
+            '{' => Some(Tok::LBrace),
+            '}' => Some(Tok::RBrace),
+            '[' => Some(Tok::LBracket),
+            ']' => Some(Tok::RBracket),
+            '=' => Some(Tok::Equals),
+            ';' => Some(Tok::Semicolon),
+            ',' => Some(Tok::Comma),
+            _ => None,
+        };
+        let tok = if let Some(tok) = simple {
+            tok
+        } else if c.is_whitespace() {
+            continue;
+        } else if c == '#' || (c == '/' && next == Some('/')) {
+            while chars.next_if(|&(_, c)| c != '\n').is_some() {}
+            continue;
+        } else if c == '/' && next == Some('*') {
+            chars.next();
+            loop {
+                match chars.next() {
+                    None => {
+                        let span = Span::new(start, start + 2);
+                        errors.push(source.diagnostic(span, "unterminated comment"));
+                        break;
+                    }
+                    Some((i, '*')) if at(i + 1) == Some('/') => {
+                        chars.next();
+                        break;
+                    }
+                    Some(_) => {}
+                }
+            }
+            continue;
+        } else if c == '-' && (next == Some('-') || next == Some('>')) {
+            chars.next();
+            if next == Some('-') {
+                Tok::Edge
+            } else {
+                Tok::Arrow
+            }
+        } else if c == '"' {
+            let mut s = String::new();
+            loop {
+                match chars.next() {
+                    None => {
+                        let span = Span::new(start, start + 1);
+                        errors.push(source.diagnostic(span, "unterminated string"));
+                        break;
+                    }
+                    Some((_, '"')) => break,
+                    Some((i, '\\')) if at(i + 1) == Some('"') => {
+                        chars.next();
+                        s.push('"');
+                    }
+                    Some((_, c)) => s.push(c),
+                }
+            }
+            Tok::Id(s)
+        } else if is_id_char(c) {
+            let mut s = c.to_string();
+            while let Some(&(i, c)) = chars.peek() {
+                let starts_edge = c == '-' && matches!(at(i + 1), Some('-') | Some('>'));
+                if !is_id_char(c) || starts_edge {
+                    break;
+                }
+                s.push(c);
+                chars.next();
+            }
+            Tok::Id(s)
+        } else {
+            let span = Span::new(start, start + c.len_utf8());
+            errors.push(
+                source
+                    .diagnostic(span, format!("unexpected character `{}`", c))
+                    .with_found(c.to_string()),
+            );
+            continue;
+        };
+        let end = chars.peek().map_or(src.len(), |&(i, _)| i);
+        tokens.push(Token {
+            tok,
+            span: Span::new(start, end),
+        });
+    }
+    tokens.push(Token {
+        tok: Tok::Eof,
+        span: Span::new(src.len(), src.len()),
+    });
+    tokens
+}
+
+struct Parser<'s> {
+    source: SourceMap<'s>,
+    tokens: Vec<Token>,
+    pos: usize,
+    vertices: Vec<(VertexKind, Phase, IoFlags)>,
+    edges: Vec<(u32, u32, EdgeKind)>,
+    names: HashMap<String, u32>,
+    errors: Vec<DotError>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos]
+    }
+
+    fn bump(&mut self) -> Token {
+        let t = self.tokens[self.pos].clone();
+        if t.tok != Tok::Eof {
+            self.pos += 1;
+        }
+        t
+    }
+
+    fn unexpected(&self, expected: &[&str]) -> DotError {
+        let t = self.peek();
+        let message = format!("expected {}, found {}", expected.join(" or "), t.tok);
+        self.source
+            .diagnostic(t.span, message)
+            .with_found(t.tok.to_string())
+            .with_expected(expected.iter().copied())
+    }
+
+    fn expect(&mut self, tok: Tok) -> Result<Token, DotError> {
+        if self.peek().tok == tok {
+            Ok(self.bump())
+        } else {
+            Err(self.unexpected(&[&tok.to_string()]))
+        }
+    }
+
+    fn keyword(&self, kw: &str) -> bool {
+        matches!(&self.peek().tok, Tok::Id(s) if s.eq_ignore_ascii_case(kw))
+    }
+
+    fn id(&mut self) -> Result<(String, Token), DotError> {
+        match self.peek().tok.clone() {
+            Tok::Id(s) => Ok((s, self.bump())),
+            _ => Err(self.unexpected(&["an identifier"])),
+        }
+    }
+
+    /// Parse the file, collecting the errors of each statement
+    fn parse(&mut self) {
+        if let Err(e) = self.header() {
+            self.errors.push(e);
+            return;
+        }
+        self.statements();
+        if let Err(e) = self.expect(Tok::Eof) {
+            self.errors.push(e);
+        }
+    }
+
+    fn header(&mut self) -> Result<(), DotError> {
+        if self.keyword("strict") {
+            self.bump();
+        }
+        if self.keyword("digraph") {
+            let t = self.peek();
+            return Err(self
+                .source
+                .diagnostic(t.span, "directed graphs are not supported")
+                .with_found(t.tok.to_string())
+                .with_expected(vec!["`graph`"]));
+        }
+        if !self.keyword("graph") {
+            return Err(self.unexpected(&["`graph`"]));
+        }
+        self.bump();
+        if let Tok::Id(_) = self.peek().tok {
+            self.bump();
+        }
+        self.expect(Tok::LBrace)?;
+        Ok(())
+    }
+
+    /// Parse statements up to and including the `}` closing their graph, recovering from the
+    /// errors of each one
+    fn statements(&mut self) {
+        while self.peek().tok != Tok::RBrace {
+            if self.peek().tok == Tok::Eof {
+                self.errors.push(self.unexpected(&["`}`"]));
+                return;
+            }
+            let start = self.pos;
+            match self.statement() {
+                Ok(()) => {
+                    if self.peek().tok == Tok::Semicolon {
+                        self.bump();
+                    }
+                }
+                Err(e) => {
+                    self.errors.push(e);
+                    self.recover(start);
+                }
+            }
+        }
+        self.bump();
+    }
+
+    /// Skip the rest of the statement starting at token `start` that failed to parse: up to
+    /// and including its `;`, or up to the `}` closing its graph or the first token on a later
+    /// line outside brackets.
+    fn recover(&mut self, start: usize) {
+        let line = |p: &Self, i: usize| p.source.position(p.tokens[i].span.start).0;
+        let first_line = line(self, start);
+        let mut depth = 0usize;
+        // Always make progress, even if the statement failed on its first token.
+        if self.pos == start && self.peek().tok != Tok::RBrace {
+            self.bump();
+        }
+        loop {
+            if depth == 0 {
+                match self.peek().tok {
+                    Tok::Eof | Tok::RBrace => return,
+                    Tok::Semicolon => {
+                        self.bump();
+                        return;
+                    }
+                    _ if line(self, self.pos) > first_line => return,
+                    _ => {}
+                }
+            }
+            match self.peek().tok {
+                Tok::Eof => return,
+                Tok::LBrace | Tok::LBracket => depth += 1,
+                Tok::RBrace | Tok::RBracket => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            self.bump();
+        }
+    }
+
+    fn statement(&mut self) -> Result<(), DotError> {
+        // Default attribute statements only carry styling, which is ignored.
+        if self.keyword("graph") || self.keyword("node") || self.keyword("edge") {
+            self.bump();
+            self.attributes()?;
+            return Ok(());
+        }
+        // Subgraphs only group vertices for the layout.
+        if self.keyword("subgraph") || self.peek().tok == Tok::LBrace {
+            if self.keyword("subgraph") {
+                self.bump();
+                if let Tok::Id(_) = self.peek().tok {
+                    self.bump();
+                }
+            }
+            self.expect(Tok::LBrace)?;
+            self.statements();
+            return Ok(());
+        }
+        let (name, _) = self.id()?;
+        match self.peek().tok {
+            Tok::Equals => {
+                self.bump();
+                self.id()?;
+            }
+            Tok::Edge | Tok::Arrow => {
+                let mut chain = vec![self.node(&name)];
+                // Statements need not end with `;`, so the chain ends at any other token.
+                while let Tok::Edge | Tok::Arrow = self.peek().tok {
+                    if self.peek().tok == Tok::Arrow {
+                        let t = self.peek();
+                        return Err(self
+                            .source
+                            .diagnostic(t.span, "directed edges are not supported")
+                            .with_found(t.tok.to_string())
+                            .with_expected(vec!["`--`"]));
+                    }
+                    self.expect(Tok::Edge)?;
+                    let (name, _) = self.id()?;
+                    chain.push(self.node(&name));
+                }
+                let attrs = self.attributes()?;
+                let kind = match attrs.iter().find(|(k, _, _)| k == "style") {
+                    Some((_, v, _)) if v == "dashed" => EdgeKind::Hadam,
+                    _ => EdgeKind::Regular,
+                };
+                for w in chain.windows(2) {
+                    self.edges.push((w[0], w[1], kind));
+                }
+            }
+            _ => {
+                let v = self.node(&name);
+                let attrs = self.attributes()?;
+                self.node_attributes(v, &attrs).map_err(|(t, msg)| {
+                    self.source
+                        .diagnostic(t.span, msg)
+                        .with_found(t.tok.to_string())
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Get a vertex by name, creating it if it's the first time it's mentioned.
+    fn node(&mut self, name: &str) -> u32 {
+        if let Some(&v) = self.names.get(name) {
+            return v;
+        }
+        let v = self.vertices.len() as u32;
+        self.vertices.push(Default::default());
+        self.names.insert(name.to_string(), v);
+        v
+    }
+
+    /// Parse an optional list of attributes, returning the key, value, and value token of each.
+    fn attributes(&mut self) -> Result<Vec<(String, String, Token)>, DotError> {
+        let mut attrs = Vec::new();
+        while self.peek().tok == Tok::LBracket {
+            self.bump();
+            while self.peek().tok != Tok::RBracket {
+                let (key, _) = self.id()?;
+                self.expect(Tok::Equals)?;
+                let (value, tok) = self.id()?;
+                attrs.push((key, value, tok));
+                if self.peek().tok == Tok::Comma || self.peek().tok == Tok::Semicolon {
+                    self.bump();
+                }
+            }
+            self.bump();
+        }
+        Ok(attrs)
+    }
+
+    fn node_attributes(
+        &mut self,
+        v: u32,
+        attrs: &[(String, String, Token)],
+    ) -> Result<(), (Token, String)> {
+        let (kind, phase, flags) = &mut self.vertices[v as usize];
+        for (key, value, tok) in attrs {
+            match key.as_str() {
+                "kind" => {
+                    *kind = match value.to_ascii_lowercase().as_str() {
+                        "z" => VertexKind::Z,
+                        "x" => VertexKind::X,
+                        "hbox" => VertexKind::HBox,
+                        "hole" => VertexKind::Hole,
+                        "boundary" => VertexKind::Boundary,
+                        _ => return Err((tok.clone(), format!("unknown vertex kind `{}`", value))),
+                    };
+                }
+                "label" => {
+                    *phase = parse_phase(value)
+                        .ok_or_else(|| (tok.clone(), format!("invalid phase `{}`", value)))?;
+                }
+                "io" => {
+                    *flags = match value.as_str() {
+                        "input" => IoFlags::INPUT,
+                        "output" => IoFlags::OUTPUT,
+                        "both" => IoFlags {
+                            input: true,
+                            output: true,
+                        },
+                        _ => {
+                            return Err((tok.clone(), format!("unknown boundary role `{}`", value)))
+                        }
+                    };
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parse a phase written as a multiple of pi, like `π/4`, `3pi/2`, `-π` or `0`.
+fn parse_phase(s: &str) -> Option<Phase> {
+    let s: String = s.chars().filter(|c| !c.is_whitespace()).collect();
+    if s.is_empty() || s == "0" {
+        return Some(Phase::default());
+    }
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s.as_str()),
+    };
+    let (num, rest): (u64, &str) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(0) => (1, s),
+        Some(i) => (s[..i].parse().ok()?, &s[i..]),
+        None => return None,
+    };
+    let rest = rest.strip_prefix('π').or_else(|| rest.strip_prefix("pi"))?;
+    let den: u64 = match rest.strip_prefix('/') {
+        Some(den) => den.parse().ok().filter(|&d| d != 0)?,
+        None if rest.is_empty() => 1,
+        None => return None,
+    };
+    let f = Fraction::new(num, den);
+    Some(Phase::from(if negative { -f } else { f }))
+}
+
+/// Write a diagram in the DOT format read by `parse`, styled for Graphviz.
+///
+/// Z spiders are drawn as green circles, X spiders as red circles, H-boxes as yellow squares
+/// and boundaries as small black dots, with the phases as labels. Hadamard edges are dashed and
+/// blue. Inputs are ranked on the left and outputs on the right.
+pub fn write_dot<G: Graph, W: Write>(g: &G, w: W) -> io::Result<()> {
+    write_graph(g, Vec::new(), w)
+}
+
+/// Write a diagram in the DOT format, see `write_dot`.
+pub fn to_dot<G: Graph>(g: &G) -> String {
+    let mut out = Vec::new();
+    write_dot(g, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+/// Write a diagram in the DOT format, see `write_dot`, ranking the vertices on the same row
+/// together.
+pub fn write_dot_with_layout<G, W>(g: &G, w: W) -> io::Result<()>
+where
+    G: CartesianGraph,
+    G::Row: Into<f64>,
+    W: Write,
+{
+    let mut rows: Vec<(f64, G::VertexIx)> = g
+        .vertices()
+        .filter(|&v| !g.is_input(v) && !g.is_output(v))
+        .map(|v| (g.row(v).into(), v))
+        .collect();
+    rows.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let mut ranks: Vec<Vec<G::VertexIx>> = Vec::new();
+    let mut last_row = None;
+    for (row, v) in rows {
+        if last_row != Some(row) {
+            ranks.push(Vec::new());
+            last_row = Some(row);
+        }
+        ranks.last_mut().unwrap().push(v);
+    }
+    write_graph(g, ranks, w)
+}
+
+/// Write a diagram in the DOT format, see `write_dot_with_layout`.
+pub fn to_dot_with_layout<G>(g: &G) -> String
+where
+    G: CartesianGraph,
+    G::Row: Into<f64>,
+{
+    let mut out = Vec::new();
+    write_dot_with_layout(g, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+/// Write the summary graph of a `CollapsedView` in the DOT format.
+///
+/// Each group is a box labelled with its index, its vertex counts by kind and its internal
+/// edges. Two groups are joined by a single edge labelled and weighted with the number of edges
+/// crossing between them. The output is meant for rendering, `parse` does not read it back.
+pub fn write_collapsed_dot<G: Graph, W: Write>(
+    view: &CollapsedView<G>,
+    mut w: W,
+) -> io::Result<()> {
+    writeln!(w, "graph {{")?;
+    writeln!(w, "    rankdir=LR;")?;
+    for i in 0..view.num_groups() {
+        let s = view.summary(i);
+        let kinds: Vec<_> = [
+            (s.num_z, "Z"),
+            (s.num_x, "X"),
+            (s.num_hbox, "H-box"),
+            (s.num_boundary, "boundary"),
+            (s.num_hole, "hole"),
+        ]
+        .iter()
+        .filter(|&&(n, _)| n > 0)
+        .map(|(n, kind)| format!("{} {}", n, kind))
+        .collect();
+        writeln!(
+            w,
+            "    g{} [shape=box, label=\"group {}\\n{} vertices ({})\\n{} edges, {} Hadamard\"];",
+            i,
+            i,
+            s.num_vertices,
+            kinds.join(", "),
+            s.internal_edges,
+            s.internal_hadamard_edges
+        )?;
+    }
+    for ((a, b), n) in view.summary_edges() {
+        writeln!(w, "    g{} -- g{} [label=\"{}\", weight={}];", a, b, n, n)?;
+    }
+    writeln!(w, "}}")
+}
+
+/// Write the summary graph of a `CollapsedView` in the DOT format, see `write_collapsed_dot`.
+pub fn collapsed_to_dot<G: Graph>(view: &CollapsedView<G>) -> String {
+    let mut out = Vec::new();
+    write_collapsed_dot(view, &mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+/// Write a phase as a label accepted by `parse_phase`, empty for zero.
+fn phase_label(phase: Phase) -> String {
+    match (phase.numerator(), phase.denominator()) {
+        (0, _) => String::new(),
+        (1, 1) => "π".to_string(),
+        (num, 1) => format!("{}π", num),
+        (1, den) => format!("π/{}", den),
+        (num, den) => format!("{}π/{}", num, den),
+    }
+}
+
+/// Write the diagram, with each of `ranks` on the same rank.
+fn write_graph<G: Graph, W: Write>(
+    g: &G,
+    ranks: Vec<Vec<G::VertexIx>>,
+    mut w: W,
+) -> io::Result<()> {
+    let mut names = HashMap::new();
+    for (i, v) in g.vertices().enumerate() {
+        names.insert(v, format!("v{}", i));
+    }
+
+    writeln!(w, "graph {{")?;
+    writeln!(w, "    rankdir=LR;")?;
+    // Boundaries go first, so the wire order is kept when the file is read back.
+    let mut order: Vec<_> = g.inputs().collect();
+    order.extend(g.outputs().filter(|&v| !g.is_input(v)));
+    order.extend(g.vertices().filter(|&v| !g.is_input(v) && !g.is_output(v)));
+    for v in order {
+        let vdata = g.vertex(v).unwrap();
+        let style = match vdata.kind() {
+            VertexKind::Z => "kind=Z, shape=circle, style=filled, fillcolor=green",
+            VertexKind::X => "kind=X, shape=circle, style=filled, fillcolor=red",
+            VertexKind::HBox => "kind=HBox, shape=square, style=filled, fillcolor=yellow",
+            VertexKind::Hole => "kind=Hole, shape=box",
+            VertexKind::Boundary => "kind=Boundary, shape=point, width=0.1, color=black",
+        };
+        let io = match (g.is_input(v), g.is_output(v)) {
+            (true, true) => ", io=both",
+            (true, false) => ", io=input",
+            (false, true) => ", io=output",
+            (false, false) => "",
+        };
+        writeln!(
+            w,
+            "    {} [{}{}, label=\"{}\"];",
+            names[&v],
+            style,
+            io,
+            phase_label(vdata.phase())
+        )?;
+    }
+    for (s, t, edata) in g.edges_with_data() {
+        let style = match edata.kind() {
+            EdgeKind::Regular => "",
+            EdgeKind::Hadam => " [style=dashed, color=blue]",
+        };
+        writeln!(w, "    {} -- {}{};", names[&s], names[&t], style)?;
+    }
+
+    let inputs: Vec<_> = g.inputs().collect();
+    let outputs: Vec<_> = g.outputs().filter(|&v| !g.is_input(v)).collect();
+    let groups = vec![("source", inputs), ("sink", outputs)]
+        .into_iter()
+        .chain(ranks.into_iter().map(|rank| ("same", rank)));
+    for (rank, vs) in groups {
+        if vs.is_empty() {
+            continue;
+        }
+        let vs: Vec<_> = vs.iter().map(|v| names[v].as_str()).collect();
+        writeln!(w, "    {{ rank={}; {}; }}", rank, vs.join("; "))?;
+    }
+    writeln!(w, "}}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::analysis::is_isomorphic;
+    use crate::zx::templates;
+
+    #[test]
+    fn export_import_round_trip() {
+        for g in [
+            templates::cnot(),
+            templates::toffoli(),
+            templates::cluster_state(2, 2),
+        ] {
+            let h = parse(&to_dot(&g)).unwrap();
+            assert!(is_isomorphic(&g, &h), "{}", to_dot(&g));
+            assert!(h.inputs().eq(g.inputs()));
+            assert!(h.outputs().eq(g.outputs()));
+        }
+    }
+
+    #[test]
+    fn hand_edited_phase_label() {
+        let mut g: GGraph = GGraph::new();
+        let i = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        let spider = g.add_vertex(VertexKind::Z, Phase::new(1, 4));
+        let o = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        g.add_edge(i, spider, EdgeKind::Regular);
+        g.add_edge(spider, o, EdgeKind::Hadam);
+        g.set_input(i, true);
+        g.set_output(o, true);
+        let exported = to_dot(&g);
+        assert!(exported.contains("label=\"π/4\""));
+
+        let edited = exported.replace("label=\"π/4\"", "label=\"3pi/2\"");
+        let h = parse(&edited).unwrap();
+        let z = h
+            .vertices()
+            .find(|&v| h.vertex(v).unwrap().kind() == VertexKind::Z)
+            .unwrap();
+        assert_eq!(h.vertex(z).unwrap().phase(), Phase::new(3, 2));
+        g.set_phase(spider, Phase::new(3, 2));
+        assert!(is_isomorphic(&g, &h));
+    }
+
+    #[test]
+    fn extra_attributes_and_subgraphs() {
+        let src = r#"
+            // drawn by hand
+            strict graph annotated {
+                node [fontname="Helvetica"];
+                a [kind=boundary, io=input, pos="0,0!"];
+                subgraph cluster_0 { b [kind=z, label="-π/2", color=green]; }
+                c [kind=HBox];
+                d [io=output];
+                a -- b -- c [weight=2];
+                c -- d [style=dashed];
+            }
+        "#;
+        let g = parse(src).unwrap();
+        assert_eq!((g.num_vertices(), g.num_edges()), (4, 3));
+        let kinds: Vec<_> = g.vertices().map(|v| g.vertex(v).unwrap().kind()).collect();
+        use VertexKind::*;
+        assert_eq!(kinds, [Boundary, Z, HBox, Boundary]);
+        let b = g.vertices().nth(1).unwrap();
+        assert_eq!(g.vertex(b).unwrap().phase(), Phase::new(3, 2));
+        let kinds: Vec<_> = g.edges_with_data().map(|(_, _, e)| e.kind()).collect();
+        assert_eq!(
+            kinds,
+            [EdgeKind::Regular, EdgeKind::Regular, EdgeKind::Hadam]
+        );
+        assert_eq!(g.inputs().count(), 1);
+        assert_eq!(g.outputs().count(), 1);
+    }
+
+    #[test]
+    fn errors_have_positions() {
+        let err = parse("graph {\n  a [kind=Y];\n}").unwrap_err();
+        assert_eq!((err.line, err.col), (2, 11));
+        assert_eq!(err.found.as_deref(), Some("`Y`"));
+
+        let err = parse("digraph { a -> b }").unwrap_err();
+        assert_eq!((err.line, err.col), (1, 1));
+        assert_eq!(err.expected, ["`graph`"]);
+    }
+
+    #[test]
+    fn every_error_is_reported() {
+        let src = "\
+graph {
+  a [kind=Y];
+  a -- b
+  c [label=\"π/0\"]
+  d -> e;
+  subgraph { f [io=sideways] }
+  g $ -- h;
+}
+";
+        let errors = parse_all(src).unwrap_err();
+        let found: Vec<_> = errors
+            .iter()
+            .map(|e| (e.line, e.message.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (2, "unknown vertex kind `Y`"),
+                (4, "invalid phase `π/0`"),
+                (5, "directed edges are not supported"),
+                (6, "unknown boundary role `sideways`"),
+                (7, "unexpected character `$`"),
+            ]
+        );
+        // `parse` stops at the first one.
+        assert_eq!(parse(src).unwrap_err(), errors[0]);
+
+        // An unclosed graph is reported at the end of the file, after the other errors.
+        let errors = parse_all("graph {\n  a [kind=Y]\n  b -- c").unwrap_err();
+        let lines: Vec<_> = errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, [2, 3]);
+        assert_eq!(errors[1].expected, ["`}`"]);
+    }
+
+    #[test]
+    fn phase_labels() {
+        assert_eq!(parse_phase("π/4"), Some(Phase::new(1, 4)));
+        assert_eq!(parse_phase("3pi/2"), Some(Phase::new(3, 2)));
+        assert_eq!(parse_phase("-π"), Some(Phase::pi()));
+        assert_eq!(parse_phase(" 0 "), Some(Phase::zero()));
+        assert_eq!(parse_phase(""), Some(Phase::zero()));
+        assert_eq!(parse_phase("pi/0"), None);
+        assert_eq!(parse_phase("1/4"), None);
+        for phase in [
+            Phase::new(1, 4),
+            Phase::new(7, 4),
+            Phase::pi(),
+            Phase::new(3, 1),
+        ] {
+            let label = phase_label(phase);
+            assert_eq!(parse_phase(&label), Some(phase), "{}", label);
+        }
+    }
+
+    /// `in -- Z(π/4) -H- X(π) -- H-box -- out`, with the X spider and the H-box on the same row
+    fn every_kind() -> GGraph {
+        let mut g: GGraph = GGraph::new();
+        let i = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        let z = g.add_vertex(VertexKind::Z, Phase::new(1, 4));
+        let x = g.add_vertex(VertexKind::X, Phase::pi());
+        let h = g.add_vertex(VertexKind::HBox, Phase::pi());
+        let o = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        g.add_edge(i, z, EdgeKind::Regular);
+        g.add_edge(z, x, EdgeKind::Hadam);
+        g.add_edge(x, h, EdgeKind::Regular);
+        g.add_edge(h, o, EdgeKind::Regular);
+        g.set_input(i, true);
+        g.set_output(o, true);
+        for (v, row) in [(i, 0), (z, 1), (x, 2), (h, 2), (o, 3)] {
+            g.set_row(v, row);
+        }
+        g
+    }
+
+    const EVERY_KIND_VERTICES: &str = "\
+graph {
+    rankdir=LR;
+    v0 [kind=Boundary, shape=point, width=0.1, color=black, io=input, label=\"\"];
+    v4 [kind=Boundary, shape=point, width=0.1, color=black, io=output, label=\"\"];
+    v1 [kind=Z, shape=circle, style=filled, fillcolor=green, label=\"π/4\"];
+    v2 [kind=X, shape=circle, style=filled, fillcolor=red, label=\"π\"];
+    v3 [kind=HBox, shape=square, style=filled, fillcolor=yellow, label=\"π\"];
+    v1 -- v0;
+    v2 -- v1 [style=dashed, color=blue];
+    v3 -- v2;
+    v4 -- v3;
+    { rank=source; v0; }
+    { rank=sink; v4; }
+";
+
+    #[test]
+    fn snapshot() {
+        let expected = format!("{}}}\n", EVERY_KIND_VERTICES);
+        assert_eq!(to_dot(&every_kind()), expected);
+    }
+
+    #[test]
+    fn collapsed_view_snapshot() {
+        let mut g = templates::cnot();
+        g.tensor(templates::bell_pair());
+        let vs: Vec<_> = g.vertices().collect();
+        // The CNOT split into its inputs, its gate and its outputs, and the Bell pair whole
+        let partition = vec![
+            vec![vs[0], vs[1]],
+            vec![vs[4], vs[5]],
+            vec![vs[2], vs[3]],
+            vs[6..].to_vec(),
+        ];
+        let view = CollapsedView::new(&g, &partition);
+        let expected = "\
+graph {
+    rankdir=LR;
+    g0 [shape=box, label=\"group 0\\n2 vertices (2 boundary)\\n0 edges, 0 Hadamard\"];
+    g1 [shape=box, label=\"group 1\\n2 vertices (1 Z, 1 X)\\n1 edges, 0 Hadamard\"];
+    g2 [shape=box, label=\"group 2\\n2 vertices (2 boundary)\\n0 edges, 0 Hadamard\"];
+    g3 [shape=box, label=\"group 3\\n3 vertices (1 Z, 2 boundary)\\n2 edges, 0 Hadamard\"];
+    g0 -- g1 [label=\"2\", weight=2];
+    g1 -- g2 [label=\"2\", weight=2];
+}
+";
+        assert_eq!(collapsed_to_dot(&view), expected);
+    }
+
+    #[test]
+    fn snapshot_with_layout() {
+        let expected = format!(
+            "{}    {{ rank=same; v1; }}\n    {{ rank=same; v2; v3; }}\n}}\n",
+            EVERY_KIND_VERTICES
+        );
+        assert_eq!(to_dot_with_layout(&every_kind()), expected);
+    }
+}
@@ -0,0 +1,264 @@
+//! Plain adjacency-list representation of graph states, for exchange with stabilizer tooling.
+
+use crate::zx::bit_graph::BitGraph;
+use crate::zx::graph::*;
+use crate::zx::io::sink::{GraphSink, IoFlags, SinkError};
+use crate::zx::simple_graph::GGraph;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Local Clifford applied on an output of a graph state, after the phase of its spider
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum LocalClifford {
+    #[default]
+    Identity,
+    Hadamard,
+}
+
+/// A graph state with one qubit per output, numbered in wire order.
+///
+/// Qubit `q` is a Z spider with phase `phases[q]` connected to output `q` through
+/// `local_cliffords[q]`, and every pair in `adjacency` is a Hadamard edge between two spiders.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct GraphStateData {
+    /// Edges of the graph, as pairs of qubits with the smallest first, sorted
+    pub adjacency: Vec<(u32, u32)>,
+    pub local_cliffords: Vec<LocalClifford>,
+    pub phases: Vec<Phase>,
+}
+
+impl GraphStateData {
+    pub fn num_qubits(&self) -> usize {
+        self.phases.len()
+    }
+
+    /// The underlying graph, without the phases and local Cliffords.
+    ///
+    /// Panics if there are more than `BitGraph::MAX_VERTICES` qubits.
+    pub fn to_bit_graph(&self) -> BitGraph {
+        let mut res = BitGraph::new(self.num_qubits());
+        for &(a, b) in &self.adjacency {
+            res.add_edge(a as usize, b as usize);
+        }
+        res
+    }
+}
+
+/// Reason why a diagram could not be exported as a graph state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NotGraphState<V> {
+    /// The offending vertex
+    pub vertex: V,
+    pub reason: &'static str,
+}
+
+impl<V: fmt::Debug> fmt::Display for NotGraphState<V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "not a graph state at vertex {:?}: {}",
+            self.vertex, self.reason
+        )
+    }
+}
+
+impl<V: fmt::Debug> std::error::Error for NotGraphState<V> {}
+
+/// Export a diagram as a graph state.
+///
+/// The diagram must have no inputs, and consist only of outputs each connected to its own Z
+/// spider, with Hadamard edges between the spiders. The edge between an output and its spider
+/// may be a Hadamard edge, recorded as a local Clifford.
+pub fn export<G: Graph>(g: &G) -> Result<GraphStateData, NotGraphState<G::VertexIx>> {
+    let reject = |vertex, reason| Err(NotGraphState { vertex, reason });
+    if let Some(v) = g.inputs().next() {
+        return reject(v, "graph states have no inputs");
+    }
+    for v in g.vertices() {
+        match g.vertex(v).unwrap().kind() {
+            VertexKind::Z => {}
+            VertexKind::Boundary if g.is_output(v) => {}
+            VertexKind::Boundary => return reject(v, "boundary vertex is not an output"),
+            _ => return reject(v, "vertex is not a Z spider"),
+        }
+    }
+
+    let is_output = |v| g.vertex(v).unwrap().kind() == VertexKind::Boundary;
+    let mut spider_of: HashMap<G::VertexIx, (G::VertexIx, EdgeKind)> = HashMap::new();
+    let mut spider_edges = Vec::new();
+    for (v, u, edata) in g.edges_with_data() {
+        let kind = edata.kind();
+        match (is_output(v), is_output(u)) {
+            (true, true) => return reject(v, "output is connected to another boundary"),
+            (true, false) | (false, true) => {
+                let (o, s) = if is_output(v) { (v, u) } else { (u, v) };
+                if spider_of.insert(o, (s, kind)).is_some() {
+                    return reject(o, "output is connected to more than one spider");
+                }
+            }
+            (false, false) if v == u => return reject(v, "spider has a self-loop"),
+            (false, false) if kind != EdgeKind::Hadam => {
+                return reject(v, "spiders are connected by a regular edge")
+            }
+            (false, false) => spider_edges.push((v, u)),
+        }
+    }
+
+    let mut qubit_of = HashMap::new();
+    let mut data = GraphStateData::default();
+    for (q, o) in g.outputs().enumerate() {
+        let (s, kind) = match spider_of.get(&o) {
+            Some(&found) => found,
+            None => return reject(o, "output is not connected to a spider"),
+        };
+        if qubit_of.insert(s, q as u32).is_some() {
+            return reject(s, "spider is connected to more than one output");
+        }
+        data.phases.push(g.vertex(s).unwrap().phase());
+        data.local_cliffords.push(match kind {
+            EdgeKind::Regular => LocalClifford::Identity,
+            EdgeKind::Hadam => LocalClifford::Hadamard,
+        });
+    }
+
+    for (v, u) in spider_edges {
+        let (a, b) = match (qubit_of.get(&v), qubit_of.get(&u)) {
+            (Some(&a), Some(&b)) => (a, b),
+            (None, _) => return reject(v, "spider is not connected to an output"),
+            (_, None) => return reject(u, "spider is not connected to an output"),
+        };
+        data.adjacency.push((a.min(b), a.max(b)));
+    }
+    data.adjacency.sort_unstable();
+    if let Some(w) = data.adjacency.windows(2).find(|w| w[0] == w[1]) {
+        let v = g.outputs().nth(w[0].0 as usize).unwrap();
+        return reject(spider_of[&v].0, "spiders are connected by parallel edges");
+    }
+    if let Some(s) = g
+        .vertices()
+        .find(|&v| !is_output(v) && !qubit_of.contains_key(&v))
+    {
+        return reject(s, "spider is not connected to an output");
+    }
+    Ok(data)
+}
+
+/// Build the diagram of a graph state, see `GraphStateData`.
+///
+/// Outputs are created first, in qubit order, followed by the spiders. Panics if
+/// `local_cliffords` and `phases` have different lengths.
+pub fn import(data: &GraphStateData) -> Result<GGraph, Vec<SinkError>> {
+    assert_eq!(
+        data.local_cliffords.len(),
+        data.phases.len(),
+        "a graph state needs a local Clifford and a phase per qubit"
+    );
+    let n = data.num_qubits() as u32;
+    let mut sink = GGraph::builder();
+    sink.begin(2 * n as usize, n as usize + data.adjacency.len());
+    for _ in 0..n {
+        sink.vertex(VertexKind::Boundary, Phase::default(), IoFlags::OUTPUT);
+    }
+    for (q, (&phase, &lc)) in data.phases.iter().zip(&data.local_cliffords).enumerate() {
+        let s = sink.vertex(VertexKind::Z, phase, IoFlags::NONE);
+        let kind = match lc {
+            LocalClifford::Identity => EdgeKind::Regular,
+            LocalClifford::Hadamard => EdgeKind::Hadam,
+        };
+        sink.edge(q as u32, s, kind);
+    }
+    for &(a, b) in &data.adjacency {
+        sink.edge(n + a, n + b, EdgeKind::Hadam);
+    }
+    sink.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::analysis::is_isomorphic;
+    use crate::zx::templates;
+    use crate::zx::tensor::assert_graph_eq;
+
+    fn sample() -> GraphStateData {
+        GraphStateData {
+            adjacency: vec![(0, 1), (0, 3), (1, 2), (2, 3)],
+            local_cliffords: vec![
+                LocalClifford::Identity,
+                LocalClifford::Hadamard,
+                LocalClifford::Identity,
+                LocalClifford::Hadamard,
+            ],
+            phases: vec![
+                Phase::zero(),
+                Phase::new(1, 2),
+                Phase::pi(),
+                Phase::new(1, 4),
+            ],
+        }
+    }
+
+    #[test]
+    fn data_round_trip() {
+        let data = sample();
+        let g = import(&data).unwrap();
+        assert_eq!(g.outputs().count(), 4);
+        assert_eq!(export(&g).unwrap(), data);
+        assert_eq!(data.to_bit_graph().num_edges(), 4);
+    }
+
+    #[test]
+    fn diagram_round_trip() {
+        let g = templates::cluster_state(2, 3);
+        let data = export(&g).unwrap();
+        assert_eq!(data.num_qubits(), 6);
+        assert_eq!(
+            data.adjacency,
+            [(0, 1), (0, 3), (1, 2), (1, 4), (2, 5), (3, 4), (4, 5)]
+        );
+        let h = import(&data).unwrap();
+        assert!(is_isomorphic(&g, &h));
+        assert_graph_eq(&g, &h);
+    }
+
+    #[test]
+    fn rejects_non_graph_states() {
+        // An X spider on a qubit.
+        let mut g = templates::ghz(3);
+        let z = g
+            .vertices()
+            .find(|&v| g.vertex(v).unwrap().kind() == VertexKind::Z)
+            .unwrap();
+        g.set_vertex_kind(z, VertexKind::X);
+        let err = export(&g).unwrap_err();
+        assert_eq!(err.vertex, z);
+        assert_eq!(err.reason, "vertex is not a Z spider");
+
+        // Spiders connected by a regular edge.
+        let mut g = import(&sample()).unwrap();
+        let e = g
+            .edges()
+            .find(|&e| {
+                let (a, b) = g.edge_endpoints(e).unwrap();
+                !g.is_output(a) && !g.is_output(b)
+            })
+            .unwrap();
+        g.set_edge_kind(e, EdgeKind::Regular);
+        let err = export(&g).unwrap_err();
+        assert_eq!(err.vertex, g.edge_endpoints(e).unwrap().0);
+        assert_eq!(err.reason, "spiders are connected by a regular edge");
+
+        // A dangling spider.
+        let mut g = import(&sample()).unwrap();
+        let s = g.add_vertex(VertexKind::Z, Phase::zero());
+        let err = export(&g).unwrap_err();
+        assert_eq!(err.vertex, s);
+        assert_eq!(err.reason, "spider is not connected to an output");
+
+        // A circuit has inputs.
+        let g = templates::cnot();
+        let err = export(&g).unwrap_err();
+        assert_eq!(err.vertex, g.inputs().next().unwrap());
+        assert!(err.to_string().contains("no inputs"));
+    }
+}
@@ -0,0 +1,931 @@
+//! pyzx JSON import and export.
+//!
+//! Diagrams are described by three objects, each mapping names to attributes:
+//! - `wire_vertices`: the boundary vertices, with the `annotation.input` and `annotation.output`
+//!   flags,
+//! - `node_vertices`: the other vertices, with `data.type` one of `Z` (the default), `X` or
+//!   `hadamard`, and `data.value` the phase as a multiple of pi, like `1/4`, `\pi/4` or `-π`,
+//! - `undir_edges`: the edges, with the names of their endpoints in `src` and `tgt`.
+//!
+//! The scalar of the diagram is stored like pyzx does, as a string holding a JSON object with
+//! `power2`, `phase` as a multiple of pi, and an optional `is_zero` flag. It is only written
+//! when it is not 1.
+//!
+//! A Hadamard edge is either an edge with `"had": true`, or a `hadamard` node with
+//! `data.is_edge` set to `"true"` and two edges. Other `hadamard` nodes are H-boxes, with a
+//! phase of pi when `data.value` is missing. Layout positions are stored in `annotation.coord`
+//! as `[row, -qubit]`. Any other key is accepted and ignored.
+//!
+//! Node vertices are emitted before the wire vertices, each in the order they appear in the
+//! file, which also defines the wire order of the inputs and outputs.
+
+use crate::zx::graph::*;
+use crate::zx::io::sink::{GraphSink, IoFlags};
+use crate::zx::io::span::{Diagnostic, SourceMap, Span};
+use crate::zx::simple_graph::GGraph;
+use fraction::Fraction;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Error produced while parsing a JSON file
+pub type JsonError = Diagnostic;
+
+/// Write a diagram in the pyzx JSON format, with every vertex at the origin.
+///
+/// Use `to_json_with_layout` to keep the positions of a `CartesianGraph`.
+pub fn to_json<G: Graph>(g: &G) -> String {
+    write_json(g, |_| (0.0, 0.0))
+}
+
+/// Write a diagram in the pyzx JSON format, including the qubit and row of each vertex.
+pub fn to_json_with_layout<G>(g: &G) -> String
+where
+    G: CartesianGraph,
+    G::Qubit: Into<f64>,
+    G::Row: Into<f64>,
+{
+    write_json(g, |v| (g.qubit(v).into(), g.row(v).into()))
+}
+
+/// Format a coordinate, without negative zeros.
+fn coordinate(x: f64) -> f64 {
+    x + 0.0
+}
+
+fn write_json<G: Graph>(g: &G, position: impl Fn(G::VertexIx) -> (f64, f64)) -> String {
+    let is_boundary = |v| g.vertex(v).unwrap().kind() == VertexKind::Boundary;
+    let mut names = HashMap::new();
+    for (i, v) in g.vertices().enumerate() {
+        let prefix = if is_boundary(v) { 'b' } else { 'v' };
+        names.insert(v, format!("{}{}", prefix, i));
+    }
+    let coord = |v| {
+        let (q, r) = position(v);
+        (coordinate(r), coordinate(-q))
+    };
+
+    // Boundaries are listed in wire order, inputs first.
+    let mut wires: Vec<_> = g.inputs().collect();
+    wires.extend(g.outputs().filter(|&v| !g.is_input(v)));
+    wires.extend(
+        g.vertices()
+            .filter(|&v| is_boundary(v) && !g.is_input(v) && !g.is_output(v)),
+    );
+    let mut wire_vertices = Vec::new();
+    for v in wires {
+        let (x, y) = coord(v);
+        wire_vertices.push(format!(
+            "\"{}\": {{\"annotation\": {{\"boundary\": true, \"coord\": [{}, {}], \
+             \"input\": {}, \"output\": {}}}}}",
+            names[&v],
+            x,
+            y,
+            g.is_input(v),
+            g.is_output(v)
+        ));
+    }
+
+    let mut node_vertices = Vec::new();
+    for v in g.vertices().filter(|&v| !is_boundary(v)) {
+        let vdata = g.vertex(v).unwrap();
+        let (x, y) = coord(v);
+        let mut data = match vdata.kind() {
+            VertexKind::X => "\"type\": \"X\"".to_string(),
+            VertexKind::HBox => "\"type\": \"hadamard\", \"is_edge\": \"false\"".to_string(),
+            VertexKind::Hole => "\"type\": \"hole\"".to_string(),
+            _ => "\"type\": \"Z\"".to_string(),
+        };
+        let phase = vdata.phase();
+        if !phase.is_zero() || vdata.kind() == VertexKind::HBox {
+            write!(data, ", \"value\": \"{}\"", phase_value(phase)).unwrap();
+        }
+        node_vertices.push(format!(
+            "\"{}\": {{\"annotation\": {{\"coord\": [{}, {}]}}, \"data\": {{{}}}}}",
+            names[&v], x, y, data
+        ));
+    }
+
+    let mut undir_edges = Vec::new();
+    let mut hadamards = 0;
+    for (s, t, edata) in g.edges_with_data() {
+        if edata.kind() == EdgeKind::Regular {
+            undir_edges.push((names[&s].clone(), names[&t].clone()));
+            continue;
+        }
+        let h = format!("h{}", hadamards);
+        hadamards += 1;
+        let ((x1, y1), (x2, y2)) = (coord(s), coord(t));
+        node_vertices.push(format!(
+            "\"{}\": {{\"annotation\": {{\"coord\": [{}, {}]}}, \
+             \"data\": {{\"type\": \"hadamard\", \"is_edge\": \"true\"}}}}",
+            h,
+            (x1 + x2) / 2.0,
+            (y1 + y2) / 2.0
+        ));
+        undir_edges.push((names[&s].clone(), h.clone()));
+        undir_edges.push((names[&t].clone(), h));
+    }
+    let undir_edges: Vec<_> = undir_edges
+        .iter()
+        .enumerate()
+        .map(|(i, (s, t))| format!("\"e{}\": {{\"src\": \"{}\", \"tgt\": \"{}\"}}", i, s, t))
+        .collect();
+
+    let section = |entries: &[String]| {
+        if entries.is_empty() {
+            "{}".to_string()
+        } else {
+            format!("{{\n    {}\n  }}", entries.join(",\n    "))
+        }
+    };
+    let scalar = g.scalar();
+    let scalar = if scalar.is_one() {
+        String::new()
+    } else if scalar.is_zero() {
+        ",\n  \"scalar\": \"{\\\"power2\\\": 0, \\\"phase\\\": \\\"0\\\", \\\"is_zero\\\": true}\""
+            .to_string()
+    } else {
+        format!(
+            ",\n  \"scalar\": \"{{\\\"power2\\\": {}, \\\"phase\\\": \\\"{}\\\"}}\"",
+            scalar.power2(),
+            phase_value(scalar.phase())
+        )
+    };
+    format!(
+        "{{\n  \"wire_vertices\": {},\n  \"node_vertices\": {},\n  \"undir_edges\": {}{}\n}}\n",
+        section(&wire_vertices),
+        section(&node_vertices),
+        section(&undir_edges),
+        scalar
+    )
+}
+
+/// Write a phase as a fraction of pi, like `1/4` or `1`.
+fn phase_value(phase: Phase) -> String {
+    match phase.denominator() {
+        1 => phase.numerator().to_string(),
+        den => format!("{}/{}", phase.numerator(), den),
+    }
+}
+
+/// Parse a phase written as a multiple of pi, like `1/4`, `\pi/4`, `3π/2`, `-pi` or `0`.
+fn parse_phase(s: &str) -> Option<Phase> {
+    let s: String = s.chars().filter(|c| !c.is_whitespace()).collect();
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s.as_str()),
+    };
+    let (num, den) = match s.split_once('/') {
+        Some((num, den)) => (num, Some(den)),
+        None => (s, None),
+    };
+    let num = match ["\\pi", "pi", "π"]
+        .iter()
+        .find_map(|pi| num.strip_suffix(pi))
+    {
+        Some("") => 1u64,
+        Some(num) => num.parse().ok()?,
+        None if num.is_empty() && den.is_none() => 0,
+        None => num.parse().ok()?,
+    };
+    let den: u64 = match den {
+        Some(den) => den.parse().ok().filter(|&d| d != 0)?,
+        None => 1,
+    };
+    let f = Fraction::new(num, den);
+    Some(Phase::from(if negative { -f } else { f }))
+}
+
+/// Parse a pyzx JSON description of a diagram.
+pub fn from_json(src: &str) -> Result<GGraph, JsonError> {
+    parse_into(src, GGraph::builder())
+}
+
+/// Parse a pyzx JSON description of a diagram, emitting it into a `GraphSink`.
+pub fn parse_into<S: GraphSink>(src: &str, mut sink: S) -> Result<S::Graph, JsonError> {
+    let source = SourceMap::new(src);
+    let root = Reader::new(&source).document()?;
+    let mut diagram = Diagram::default();
+    diagram.read(&source, &root)?;
+
+    sink.begin(diagram.vertices.len(), diagram.edges.len());
+    for (v, &(kind, phase, flags, coord)) in diagram.vertices.iter().enumerate() {
+        sink.vertex(kind, phase, flags);
+        if let Some((x, y)) = coord {
+            sink.position(v as u32, -y, x);
+        }
+    }
+    for &(u, v, kind) in &diagram.edges {
+        sink.edge(u, v, kind);
+    }
+    sink.scalar(diagram.scalar);
+    sink.finish().map_err(|errors| {
+        let errors: Vec<_> = errors.iter().map(ToString::to_string).collect();
+        source.diagnostic(
+            Span::default(),
+            format!("invalid diagram: {}", errors.join("; ")),
+        )
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    /// Members in file order
+    Object(Vec<(String, Value)>),
+}
+
+/// JSON value with its location in the source
+#[derive(Debug, Clone, PartialEq)]
+struct Value {
+    json: Json,
+    span: Span,
+}
+
+impl Value {
+    /// Member of an object, if the value is an object containing it
+    fn get(&self, key: &str) -> Option<&Value> {
+        match &self.json {
+            Json::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self.json {
+            Json::Null => "null",
+            Json::Bool(_) => "a boolean",
+            Json::Number(_) => "a number",
+            Json::String(_) => "a string",
+            Json::Array(_) => "an array",
+            Json::Object(_) => "an object",
+        }
+    }
+}
+
+struct Reader<'a, 's> {
+    source: &'a SourceMap<'s>,
+    src: &'s str,
+    pos: usize,
+}
+
+impl<'a, 's> Reader<'a, 's> {
+    fn new(source: &'a SourceMap<'s>) -> Self {
+        Reader {
+            source,
+            src: source.source(),
+            pos: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.src[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn unexpected(&self, expected: &str) -> JsonError {
+        match self.peek() {
+            Some(c) => self
+                .source
+                .diagnostic(
+                    Span::new(self.pos, self.pos + c.len_utf8()),
+                    format!("expected {}, found `{}`", expected, c),
+                )
+                .with_found(c.to_string())
+                .with_expected(vec![expected]),
+            None => self
+                .source
+                .diagnostic(
+                    Span::new(self.pos, self.pos),
+                    format!("expected {}, found end of file", expected),
+                )
+                .with_expected(vec![expected]),
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), JsonError> {
+        self.skip_whitespace();
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("`{}`", c)))
+        }
+    }
+
+    fn document(&mut self) -> Result<Value, JsonError> {
+        let value = self.value()?;
+        self.skip_whitespace();
+        if self.pos < self.src.len() {
+            return Err(self.unexpected("end of file"));
+        }
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Value, JsonError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        let json = match self.peek() {
+            Some('{') => self.object()?,
+            Some('[') => self.array()?,
+            Some('"') => Json::String(self.string()?),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number()?,
+            Some(c) if c.is_ascii_alphabetic() => self.literal()?,
+            _ => return Err(self.unexpected("a value")),
+        };
+        Ok(Value {
+            json,
+            span: Span::new(start, self.pos),
+        })
+    }
+
+    /// Comma separated items up to the `close` delimiter, after the opening one.
+    fn items(
+        &mut self,
+        close: char,
+        mut item: impl FnMut(&mut Self) -> Result<(), JsonError>,
+    ) -> Result<(), JsonError> {
+        self.pos += 1;
+        self.skip_whitespace();
+        if self.peek() == Some(close) {
+            self.pos += 1;
+            return Ok(());
+        }
+        loop {
+            item(self)?;
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(c) if c == close => {
+                    self.pos += 1;
+                    return Ok(());
+                }
+                _ => return Err(self.unexpected(&format!("`,` or `{}`", close))),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, JsonError> {
+        let mut members = Vec::new();
+        self.items('}', |r| {
+            r.skip_whitespace();
+            if r.peek() != Some('"') {
+                return Err(r.unexpected("a string"));
+            }
+            let key = r.string()?;
+            r.expect(':')?;
+            members.push((key, r.value()?));
+            Ok(())
+        })?;
+        Ok(Json::Object(members))
+    }
+
+    fn array(&mut self) -> Result<Json, JsonError> {
+        let mut items = Vec::new();
+        self.items(']', |r| {
+            items.push(r.value()?);
+            Ok(())
+        })?;
+        Ok(Json::Array(items))
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        let start = self.pos;
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            let at = self.pos;
+            let c = match self.peek() {
+                Some(c) => c,
+                None => {
+                    let span = Span::new(start, start + 1);
+                    return Err(self.source.diagnostic(span, "unterminated string"));
+                }
+            };
+            self.pos += c.len_utf8();
+            match c {
+                '"' => return Ok(s),
+                '\\' => s.push(self.escape(at)?),
+                c if c < ' ' => {
+                    let span = Span::new(at, self.pos);
+                    return Err(self.source.diagnostic(span, "control character in string"));
+                }
+                c => s.push(c),
+            }
+        }
+    }
+
+    /// Character of the escape sequence starting at `at`, after its backslash.
+    fn escape(&mut self, at: usize) -> Result<char, JsonError> {
+        let c = self.peek();
+        self.pos += c.map_or(0, char::len_utf8);
+        let escaped = match c {
+            Some('"') => Some('"'),
+            Some('\\') => Some('\\'),
+            Some('/') => Some('/'),
+            Some('b') => Some('\u{8}'),
+            Some('f') => Some('\u{c}'),
+            Some('n') => Some('\n'),
+            Some('r') => Some('\r'),
+            Some('t') => Some('\t'),
+            Some('u') => {
+                let high = self.hex4(at)?;
+                if (0xd800..0xdc00).contains(&high) && self.src[self.pos..].starts_with("\\u") {
+                    self.pos += 2;
+                    let low = self.hex4(at)?;
+                    Some(0x10000 + ((high - 0xd800) << 10) + low.wrapping_sub(0xdc00))
+                        .filter(|_| (0xdc00..0xe000).contains(&low))
+                        .and_then(char::from_u32)
+                } else {
+                    char::from_u32(high)
+                }
+            }
+            _ => None,
+        };
+        escaped.ok_or_else(|| {
+            self.source
+                .diagnostic(Span::new(at, self.pos), "invalid escape sequence")
+        })
+    }
+
+    /// Value of four hexadecimal digits in a unicode escape starting at `at`.
+    fn hex4(&mut self, at: usize) -> Result<u32, JsonError> {
+        let digits = self
+            .src
+            .get(self.pos..self.pos + 4)
+            .filter(|d| d.chars().all(|c| c.is_ascii_hexdigit()));
+        match digits {
+            Some(d) => {
+                self.pos += 4;
+                Ok(u32::from_str_radix(d, 16).unwrap())
+            }
+            None => {
+                let span = Span::new(at, self.pos);
+                Err(self.source.diagnostic(span, "invalid escape sequence"))
+            }
+        }
+    }
+
+    /// Consume the characters matching a predicate, returning them.
+    fn take_while(&mut self, pred: impl Fn(char) -> bool) -> &'s str {
+        let start = self.pos;
+        let rest = &self.src[start..];
+        self.pos += rest.find(|c| !pred(c)).unwrap_or(rest.len());
+        &self.src[start..self.pos]
+    }
+
+    fn number(&mut self) -> Result<Json, JsonError> {
+        let start = self.pos;
+        let text = self.take_while(|c| c.is_ascii_digit() || "+-.eE".contains(c));
+        match text.parse() {
+            Ok(x) => Ok(Json::Number(x)),
+            Err(_) => Err(self
+                .source
+                .diagnostic(
+                    Span::new(start, self.pos),
+                    format!("invalid number `{}`", text),
+                )
+                .with_found(text)),
+        }
+    }
+
+    fn literal(&mut self) -> Result<Json, JsonError> {
+        let start = self.pos;
+        match self.take_while(|c| c.is_ascii_alphanumeric()) {
+            "true" => Ok(Json::Bool(true)),
+            "false" => Ok(Json::Bool(false)),
+            "null" => Ok(Json::Null),
+            text => Err(self
+                .source
+                .diagnostic(
+                    Span::new(start, self.pos),
+                    format!("unknown literal `{}`", text),
+                )
+                .with_found(text)
+                .with_expected(vec!["`true`", "`false`", "`null`"])),
+        }
+    }
+}
+
+/// What a vertex name refers to
+#[derive(Debug, Clone, Copy)]
+enum Name {
+    Vertex(u32),
+    /// Index of a `hadamard` node standing for a Hadamard edge
+    HadamardEdge(usize),
+}
+
+/// Emitted vertex, with its position as `annotation.coord` if any
+type VertexEntry = (VertexKind, Phase, IoFlags, Option<(f64, f64)>);
+
+/// Diagram read from a JSON document, before it is emitted
+#[derive(Debug, Default)]
+struct Diagram<'v> {
+    vertices: Vec<VertexEntry>,
+    edges: Vec<(u32, u32, EdgeKind)>,
+    names: HashMap<&'v str, Name>,
+    /// Hadamard edge nodes, with the neighbours found so far
+    hadamard_edges: Vec<(&'v Value, Vec<u32>)>,
+    scalar: Scalar,
+}
+
+/// Error pointing at a value
+fn invalid(source: &SourceMap, value: &Value, message: impl Into<String>) -> JsonError {
+    source.diagnostic(value.span, message)
+}
+
+/// Members of an object value
+fn members<'v>(
+    source: &SourceMap,
+    value: &'v Value,
+    what: &str,
+) -> Result<&'v [(String, Value)], JsonError> {
+    match &value.json {
+        Json::Object(members) => Ok(members),
+        _ => Err(invalid(
+            source,
+            value,
+            format!(
+                "expected {} to be an object, found {}",
+                what,
+                value.describe()
+            ),
+        )),
+    }
+}
+
+/// A string value
+fn string<'v>(source: &SourceMap, value: &'v Value, what: &str) -> Result<&'v str, JsonError> {
+    match &value.json {
+        Json::String(s) => Ok(s),
+        _ => Err(invalid(
+            source,
+            value,
+            format!(
+                "expected {} to be a string, found {}",
+                what,
+                value.describe()
+            ),
+        )),
+    }
+}
+
+/// A flag, written either as a boolean or as the strings `"true"` and `"false"`
+fn flag(source: &SourceMap, value: Option<&Value>, what: &str) -> Result<bool, JsonError> {
+    match value.map(|v| (v, &v.json)) {
+        None => Ok(false),
+        Some((_, Json::Bool(b))) => Ok(*b),
+        Some((_, Json::String(s))) if s == "true" || s == "false" => Ok(s == "true"),
+        Some((v, _)) => Err(invalid(
+            source,
+            v,
+            format!("expected {} to be a boolean, found {}", what, v.describe()),
+        )),
+    }
+}
+
+/// The scalar of a diagram, a string holding a JSON object
+fn scalar(source: &SourceMap, value: &Value) -> Result<Scalar, JsonError> {
+    let error = || {
+        invalid(
+            source,
+            value,
+            "expected the scalar to hold an object with an integer `power2` and a `phase`",
+        )
+    };
+    let inner_source = SourceMap::new(string(source, value, "the scalar")?);
+    let inner = Reader::new(&inner_source).document().map_err(|_| error())?;
+    members(&inner_source, &inner, "the scalar").map_err(|_| error())?;
+    let power2 = match inner.get("power2").map(|v| &v.json) {
+        None => 0,
+        Some(Json::Number(n)) if n.fract() == 0.0 && n.abs() <= i32::MAX as f64 => *n as i32,
+        Some(_) => return Err(error()),
+    };
+    let phase = match inner.get("phase").map(|v| &v.json) {
+        None => Phase::zero(),
+        Some(Json::String(s)) => parse_phase(s).ok_or_else(error)?,
+        Some(_) => return Err(error()),
+    };
+    if flag(&inner_source, inner.get("is_zero"), "`is_zero`").map_err(|_| error())? {
+        Ok(Scalar::zero())
+    } else {
+        Ok(Scalar::new(power2, phase))
+    }
+}
+
+/// The `annotation.coord` position of a vertex, if present
+fn coord(source: &SourceMap, attrs: &Value) -> Result<Option<(f64, f64)>, JsonError> {
+    let value = match attrs.get("annotation").and_then(|a| a.get("coord")) {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    match &value.json {
+        Json::Array(items) => match items.as_slice() {
+            [Value {
+                json: Json::Number(x),
+                ..
+            }, Value {
+                json: Json::Number(y),
+                ..
+            }] => Ok(Some((*x, *y))),
+            _ => Err(invalid(source, value, "expected a coordinate pair")),
+        },
+        _ => Err(invalid(source, value, "expected a coordinate pair")),
+    }
+}
+
+impl<'v> Diagram<'v> {
+    fn read(&mut self, source: &SourceMap, root: &'v Value) -> Result<(), JsonError> {
+        members(source, root, "the document")?;
+        let section = |key| match root.get(key) {
+            Some(value) => members(source, value, &format!("`{}`", key)),
+            None => Ok(&[][..]),
+        };
+        for (name, attrs) in section("node_vertices")? {
+            self.node_vertex(source, name, attrs)?;
+        }
+        for (name, attrs) in section("wire_vertices")? {
+            let annotation = attrs.get("annotation");
+            let flags = IoFlags {
+                input: flag(source, annotation.and_then(|a| a.get("input")), "`input`")?,
+                output: flag(source, annotation.and_then(|a| a.get("output")), "`output`")?,
+            };
+            let coord = coord(source, attrs)?;
+            self.add_vertex(
+                source,
+                name,
+                attrs,
+                (VertexKind::Boundary, Phase::zero(), flags, coord),
+            )?;
+        }
+        for (_, attrs) in section("undir_edges")? {
+            self.edge(source, attrs)?;
+        }
+        if let Some(value) = root.get("scalar") {
+            self.scalar = scalar(source, value)?;
+        }
+        for (node, neighbours) in &self.hadamard_edges {
+            match neighbours[..] {
+                [u, v] => self.edges.push((u, v, EdgeKind::Hadam)),
+                _ => {
+                    return Err(invalid(
+                        source,
+                        node,
+                        format!(
+                            "Hadamard edge node has {} neighbours instead of 2",
+                            neighbours.len()
+                        ),
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn add_vertex(
+        &mut self,
+        source: &SourceMap,
+        name: &'v str,
+        attrs: &Value,
+        vertex: VertexEntry,
+    ) -> Result<(), JsonError> {
+        let v = self.vertices.len() as u32;
+        self.vertices.push(vertex);
+        self.insert_name(source, name, attrs, Name::Vertex(v))
+    }
+
+    fn insert_name(
+        &mut self,
+        source: &SourceMap,
+        name: &'v str,
+        attrs: &Value,
+        target: Name,
+    ) -> Result<(), JsonError> {
+        match self.names.insert(name, target) {
+            Some(_) => Err(invalid(
+                source,
+                attrs,
+                format!("duplicate vertex name `{}`", name),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn node_vertex(
+        &mut self,
+        source: &SourceMap,
+        name: &'v str,
+        attrs: &'v Value,
+    ) -> Result<(), JsonError> {
+        let data = attrs.get("data");
+        let kind = match data.and_then(|d| d.get("type")) {
+            None => VertexKind::Z,
+            Some(t) => match string(source, t, "the vertex type")? {
+                "Z" => VertexKind::Z,
+                "X" => VertexKind::X,
+                "hadamard" => VertexKind::HBox,
+                "hole" => VertexKind::Hole,
+                other => {
+                    return Err(
+                        invalid(source, t, format!("unknown vertex type `{}`", other))
+                            .with_found(other)
+                            .with_expected(vec!["`Z`", "`X`", "`hadamard`", "`hole`"]),
+                    )
+                }
+            },
+        };
+        if kind == VertexKind::HBox
+            && flag(source, data.and_then(|d| d.get("is_edge")), "`is_edge`")?
+        {
+            let target = Name::HadamardEdge(self.hadamard_edges.len());
+            self.hadamard_edges.push((attrs, Vec::new()));
+            return self.insert_name(source, name, attrs, target);
+        }
+        let phase = match data.and_then(|d| d.get("value")) {
+            Some(value) => {
+                let s = string(source, value, "the phase")?;
+                parse_phase(s)
+                    .ok_or_else(|| invalid(source, value, format!("invalid phase `{}`", s)))?
+            }
+            None => kind.default_phase(),
+        };
+        let coord = coord(source, attrs)?;
+        self.add_vertex(source, name, attrs, (kind, phase, IoFlags::NONE, coord))
+    }
+
+    fn edge(&mut self, source: &SourceMap, attrs: &Value) -> Result<(), JsonError> {
+        let endpoint = |key| -> Result<Name, JsonError> {
+            let value = attrs
+                .get(key)
+                .ok_or_else(|| invalid(source, attrs, format!("edge without `{}`", key)))?;
+            let name = string(source, value, &format!("`{}`", key))?;
+            self.names
+                .get(name)
+                .copied()
+                .ok_or_else(|| invalid(source, value, format!("unknown vertex `{}`", name)))
+        };
+        let (src, tgt) = (endpoint("src")?, endpoint("tgt")?);
+        let had = flag(source, attrs.get("had"), "`had`")?;
+        let kind = if had {
+            EdgeKind::Hadam
+        } else {
+            EdgeKind::Regular
+        };
+        match (src, tgt) {
+            (Name::Vertex(u), Name::Vertex(v)) => self.edges.push((u, v, kind)),
+            _ if had => {
+                return Err(invalid(
+                    source,
+                    attrs,
+                    "Hadamard edge connected to a Hadamard edge node",
+                ))
+            }
+            (Name::HadamardEdge(h), Name::Vertex(v)) | (Name::Vertex(v), Name::HadamardEdge(h)) => {
+                self.hadamard_edges[h].1.push(v)
+            }
+            (Name::HadamardEdge(h1), Name::HadamardEdge(h2)) => {
+                // Two consecutive Hadamard gates, separated by an identity spider.
+                let v = self.vertices.len() as u32;
+                self.vertices
+                    .push((VertexKind::Z, Phase::zero(), IoFlags::NONE, None));
+                self.hadamard_edges[h1].1.push(v);
+                self.hadamard_edges[h2].1.push(v);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::analysis::is_isomorphic;
+    use crate::zx::circuit::{Circuit, Gate};
+    use crate::zx::templates;
+    use crate::zx::tensor::assert_graph_eq;
+
+    /// A T on qubit 0, a CNOT and a Hadamard on qubit 1, as saved by pyzx
+    const PYZX_FIXTURE: &str = r#"{"wire_vertices": {"b0": {"annotation": {"boundary": true, "coord": [0.0, 0.0], "input": true, "output": false}}, "b1": {"annotation": {"boundary": true, "coord": [0.0, -1.0], "input": true, "output": false}}, "b2": {"annotation": {"boundary": true, "coord": [4.0, 0.0], "input": false, "output": true}}, "b3": {"annotation": {"boundary": true, "coord": [4.0, -1.0], "input": false, "output": true}}}, "node_vertices": {"v0": {"annotation": {"coord": [1.0, 0.0]}, "data": {"type": "Z", "value": "\\pi/4"}}, "v1": {"annotation": {"coord": [2.0, 0.0]}, "data": {"type": "Z"}}, "v2": {"annotation": {"coord": [2.0, -1.0]}, "data": {"type": "X"}}, "v3": {"annotation": {"coord": [3.0, -1.0]}, "data": {"type": "hadamard", "is_edge": "true"}}}, "undir_edges": {"e0": {"src": "b0", "tgt": "v0"}, "e1": {"src": "v0", "tgt": "v1"}, "e2": {"src": "v1", "tgt": "b2"}, "e3": {"src": "b1", "tgt": "v2"}, "e4": {"src": "v1", "tgt": "v2"}, "e5": {"src": "v2", "tgt": "v3"}, "e6": {"src": "v3", "tgt": "b3"}}, "variables": {}, "scalar": "{\"power2\": 1, \"phase\": \"0\"}"}"#;
+
+    #[test]
+    fn pyzx_fixture() {
+        let g = from_json(PYZX_FIXTURE).unwrap();
+        assert_eq!(g.num_vertices(), 7);
+        assert_eq!(g.num_edges(), 6);
+        assert_eq!(g.tcount(), 1);
+        assert_eq!(g.inputs().count(), 2);
+        assert_eq!(g.outputs().count(), 2);
+        assert_eq!(g.qubit_count(), 2);
+        let hadamards = g
+            .edges()
+            .filter(|&e| g.edge(e).unwrap().kind() == EdgeKind::Hadam)
+            .count();
+        assert_eq!(hadamards, 1);
+
+        let mut c = Circuit::new(2);
+        c.add_gate(Gate::T(0));
+        c.add_gate(Gate::Cx(0, 1));
+        c.add_gate(Gate::H(1));
+        let expected: GGraph = c.to_graph();
+        assert!(is_isomorphic(&g, &expected));
+        assert_graph_eq(&g, &expected);
+    }
+
+    #[test]
+    fn round_trip() {
+        for g in [
+            templates::toffoli(),
+            templates::toffoli_gadgets(),
+            templates::cluster_state(2, 2),
+        ] {
+            // Node vertices are read first, so the vertices are renumbered.
+            let h = from_json(&to_json(&g)).unwrap();
+            assert!(is_isomorphic(&g, &h));
+            assert_graph_eq(&g, &h);
+        }
+    }
+
+    #[test]
+    fn scalar_round_trip() {
+        // pyzx scales each CNOT by sqrt(2).
+        let mut g = from_json(PYZX_FIXTURE).unwrap();
+        assert_eq!(*g.scalar(), Scalar::new(1, Phase::zero()));
+        *g.scalar_mut() = Scalar::default();
+        assert!(!to_json(&g).contains("scalar"));
+        for scalar in [
+            Scalar::new(-1, Phase::new(1, 4)),
+            Scalar::new(3, Phase::pi()),
+            Scalar::zero(),
+        ] {
+            let mut g = templates::toffoli();
+            *g.scalar_mut() = scalar;
+            let h = from_json(&to_json(&g)).unwrap();
+            assert_eq!(*h.scalar(), scalar);
+            assert_graph_eq(&g, &h);
+        }
+    }
+
+    #[test]
+    fn layout_round_trip() {
+        let g = from_json(PYZX_FIXTURE).unwrap();
+        let h = from_json(&to_json_with_layout(&g)).unwrap();
+        assert!(is_isomorphic(&g, &h));
+        let layout = |g: &GGraph| {
+            let mut res: Vec<_> = g
+                .vertices()
+                .map(|v| (g.vertex(v).unwrap().kind(), g.qubit(v), g.row(v)))
+                .collect();
+            // Every vertex of the fixture has its own position.
+            res.sort_unstable_by_key(|&(_, q, r)| (q, r));
+            res
+        };
+        assert_eq!(layout(&h), layout(&g));
+        assert_eq!(g.row(g.outputs().next().unwrap()), 4);
+    }
+
+    #[test]
+    fn phase_strings() {
+        for (s, phase) in [
+            ("1/4", Phase::new(1, 4)),
+            ("\\pi/4", Phase::new(1, 4)),
+            ("3π/2", Phase::new(3, 2)),
+            ("-pi", Phase::pi()),
+            ("-1/2", Phase::new(3, 2)),
+            ("1", Phase::pi()),
+            ("0", Phase::zero()),
+            ("", Phase::zero()),
+        ] {
+            assert_eq!(parse_phase(s), Some(phase), "{:?}", s);
+        }
+        assert_eq!(parse_phase("1/0"), None);
+        assert_eq!(parse_phase("x"), None);
+        assert_eq!(phase_value(Phase::new(7, 4)), "7/4");
+        assert_eq!(phase_value(Phase::pi()), "1");
+    }
+
+    #[test]
+    fn errors_point_at_values() {
+        let src = "{\"node_vertices\": {\"v0\": {\"data\": {\"type\": \"Y\"}}}}";
+        let err = from_json(src).unwrap_err();
+        assert_eq!(err.message, "unknown vertex type `Y`");
+        assert_eq!((err.line, err.col), (1, 44));
+        let err =
+            from_json("{\"undir_edges\": {\"e0\": {\"src\": \"a\", \"tgt\": \"b\"}}}").unwrap_err();
+        assert_eq!(err.message, "unknown vertex `a`");
+        let err = from_json("{\"scalar\": \"{\\\"power2\\\": 0.5}\"}").unwrap_err();
+        assert!(
+            err.message.starts_with("expected the scalar"),
+            "{}",
+            err.message
+        );
+        assert_eq!((err.line, err.col), (1, 12));
+    }
+}
@@ -0,0 +1,326 @@
+/// Reading and writing diagrams in external formats
+pub mod dot;
+pub mod graphstate;
+pub mod json;
+pub mod qasm;
+pub mod qc;
+pub mod quipper;
+#[cfg(feature = "serde")]
+pub mod serial;
+pub mod sink;
+pub mod span;
+pub mod tikz;
+
+use crate::zx::graph::CartesianGraph;
+use crate::zx::simple_graph::GGraph;
+use flate2::bufread::GzDecoder;
+use flate2::write::GzEncoder;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// Magic bytes at the start of a gzip stream
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Compression applied to a written file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    /// Gzip with a compression level between 0 and 9
+    Gzip(u32),
+}
+
+impl Compression {
+    /// Gzip at the default level of gzip itself
+    pub const GZIP: Compression = Compression::Gzip(6);
+}
+
+/// Format of a diagram file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// pyzx JSON
+    Json,
+    Dot,
+    /// TikZ picture, which can only be written
+    Tikz,
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Format::Json => write!(f, "JSON"),
+            Format::Dot => write!(f, "DOT"),
+            Format::Tikz => write!(f, "TikZ"),
+        }
+    }
+}
+
+/// Extension of the contents of a file and the compression implied by its name.
+///
+/// A final `.gz` is stripped, so `diagram.json.gz` is gzipped JSON and has extension `json`.
+pub fn extension(path: &Path) -> (Option<&str>, Compression) {
+    match path.extension().and_then(OsStr::to_str) {
+        Some("gz") => {
+            let stem = path.file_stem().map(Path::new);
+            let ext = stem.and_then(Path::extension).and_then(OsStr::to_str);
+            (ext, Compression::GZIP)
+        }
+        ext => (ext, Compression::None),
+    }
+}
+
+/// Error produced while reading a diagram from a file
+#[derive(Debug)]
+pub enum ReadError {
+    Io(io::Error),
+    Dot(dot::DotError),
+    Json(json::JsonError),
+    /// The contents do not match any supported format
+    UnknownFormat,
+    /// The format can be written but not read
+    WriteOnly(Format),
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReadError::Io(e) => write!(f, "{}", e),
+            ReadError::Dot(e) => write!(f, "{}", e),
+            ReadError::Json(e) => write!(f, "{}", e),
+            ReadError::UnknownFormat => write!(f, "unrecognized file format"),
+            ReadError::WriteOnly(format) => write!(f, "{} files can only be written", format),
+        }
+    }
+}
+
+impl std::error::Error for ReadError {}
+
+impl From<io::Error> for ReadError {
+    fn from(e: io::Error) -> Self {
+        ReadError::Io(e)
+    }
+}
+
+impl From<dot::DotError> for ReadError {
+    fn from(e: dot::DotError) -> Self {
+        ReadError::Dot(e)
+    }
+}
+
+/// Open a file for reading, transparently decompressing it if it is gzipped.
+pub fn open_reader(path: impl AsRef<Path>) -> io::Result<Box<dyn BufRead>> {
+    let mut file = BufReader::new(File::open(path)?);
+    if file.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        Ok(Box::new(BufReader::new(GzDecoder::new(file))))
+    } else {
+        Ok(Box::new(file))
+    }
+}
+
+/// File opened for writing by `open_writer`, compressing its contents as requested.
+///
+/// The file must be closed with `finish`, which writes the gzip trailer and reports any error
+/// doing so. Dropping it instead ignores those errors, and may leave a truncated file.
+pub struct FileWriter {
+    inner: Writer,
+}
+
+enum Writer {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+}
+
+impl FileWriter {
+    /// Flush the contents and write the gzip trailer, if any.
+    pub fn finish(self) -> io::Result<()> {
+        match self.inner {
+            Writer::Plain(mut w) => w.flush(),
+            Writer::Gzip(w) => w.finish()?.flush(),
+        }
+    }
+}
+
+impl Write for FileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.inner {
+            Writer::Plain(w) => w.write(buf),
+            Writer::Gzip(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.inner {
+            Writer::Plain(w) => w.flush(),
+            Writer::Gzip(w) => w.flush(),
+        }
+    }
+}
+
+/// Create a file for writing, compressing its contents as requested. See `FileWriter`.
+pub fn open_writer(path: impl AsRef<Path>, compression: Compression) -> io::Result<FileWriter> {
+    let file = BufWriter::new(File::create(path)?);
+    let inner = match compression {
+        Compression::None => Writer::Plain(file),
+        Compression::Gzip(level) => {
+            Writer::Gzip(GzEncoder::new(file, flate2::Compression::new(level)))
+        }
+    };
+    Ok(FileWriter { inner })
+}
+
+/// Write a diagram to a file in the given format, with the positions of its vertices.
+pub fn write<G>(
+    path: impl AsRef<Path>,
+    g: &G,
+    format: Format,
+    compression: Compression,
+) -> io::Result<()>
+where
+    G: CartesianGraph,
+    G::Qubit: Into<f64>,
+    G::Row: Into<f64>,
+{
+    let mut w = open_writer(path, compression)?;
+    match format {
+        Format::Json => w.write_all(json::to_json_with_layout(g).as_bytes())?,
+        Format::Dot => dot::write_dot_with_layout(g, &mut w)?,
+        Format::Tikz => w.write_all(tikz::to_tikz_with_layout(g).as_bytes())?,
+    }
+    w.finish()
+}
+
+/// Read a diagram in the given format from a file, transparently decompressing it.
+pub fn read(path: impl AsRef<Path>, format: Format) -> Result<GGraph, ReadError> {
+    parse(open_reader(path)?, format)
+}
+
+fn parse(mut reader: impl BufRead, format: Format) -> Result<GGraph, ReadError> {
+    if format == Format::Tikz {
+        return Err(ReadError::WriteOnly(format));
+    }
+    // The parsers report spans into the whole text, so it is read at once.
+    let mut contents = String::new();
+    reader.read_to_string(&mut contents)?;
+    match format {
+        Format::Json => json::from_json(&contents).map_err(ReadError::Json),
+        Format::Dot => Ok(dot::parse(&contents)?),
+        Format::Tikz => unreachable!("TikZ files are rejected above"),
+    }
+}
+
+/// Format of a text from its first bytes, after any whitespace
+fn sniff(head: &[u8]) -> Option<Format> {
+    let start = head
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .map_or(&[][..], |i| &head[i..]);
+    let is_dot = [&b"graph"[..], b"strict", b"//", b"/*", b"#"]
+        .iter()
+        .any(|prefix| start.starts_with(prefix));
+    if is_dot {
+        Some(Format::Dot)
+    } else if start.starts_with(b"{") {
+        Some(Format::Json)
+    } else {
+        None
+    }
+}
+
+/// Read a diagram from a file, detecting its compression and format from the contents.
+///
+/// The gzip magic bytes and the start of the decompressed text are sniffed from the buffered
+/// head of the file, so anything else, like binary data, is rejected without reading it
+/// further. DOT and pyzx JSON are supported.
+pub fn read_auto(path: impl AsRef<Path>) -> Result<GGraph, ReadError> {
+    let mut reader = open_reader(path)?;
+    let format = sniff(reader.fill_buf()?).ok_or(ReadError::UnknownFormat)?;
+    parse(reader, format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::analysis::is_isomorphic;
+    use crate::zx::templates;
+    use std::path::PathBuf;
+
+    /// A file in the temporary directory, unique to this process and test
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("hermitq-{}-{}", std::process::id(), name))
+    }
+
+    fn write_file(path: &Path, contents: &str, compression: Compression) {
+        let mut w = open_writer(path, compression).unwrap();
+        w.write_all(contents.as_bytes()).unwrap();
+        w.finish().unwrap();
+    }
+
+    #[test]
+    fn round_trip_each_format() {
+        let g = templates::toffoli();
+        for format in [Format::Dot, Format::Json] {
+            for compression in [Compression::None, Compression::Gzip(6)] {
+                let path = temp_path(&format!("round-trip-{:?}.{}", compression, format));
+                write(&path, &g, format, compression).unwrap();
+                let gzipped = std::fs::read(&path).unwrap().starts_with(&GZIP_MAGIC);
+                assert_eq!(gzipped, compression != Compression::None);
+                let h = read_auto(&path).unwrap();
+                let h2 = read(&path, format).unwrap();
+                std::fs::remove_file(&path).unwrap();
+                assert!(is_isomorphic(&g, &h), "{} {:?}", format, compression);
+                assert!(is_isomorphic(&g, &h2), "{} {:?}", format, compression);
+            }
+        }
+    }
+
+    #[test]
+    fn extensions() {
+        fn ext(path: &str) -> (Option<&str>, Compression) {
+            extension(Path::new(path))
+        }
+        assert_eq!(ext("a/b.json"), (Some("json"), Compression::None));
+        assert_eq!(ext("b.json.gz"), (Some("json"), Compression::GZIP));
+        assert_eq!(ext("b.dot.gz"), (Some("dot"), Compression::GZIP));
+        assert_eq!(ext("b.gz"), (None, Compression::GZIP));
+        assert_eq!(ext("b"), (None, Compression::None));
+    }
+
+    #[test]
+    fn truncated_gzip_is_an_error() {
+        let path = temp_path("truncated.json.gz");
+        write_file(
+            &path,
+            &json::to_json(&templates::cnot()),
+            Compression::Gzip(9),
+        );
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+        let res = read_auto(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(res, Err(ReadError::Io(_))), "{:?}", res);
+    }
+
+    #[test]
+    fn unknown_format() {
+        let path = temp_path("unknown.txt");
+        write_file(&path, "OPENQASM 2.0;", Compression::Gzip(1));
+        let res = read_auto(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(res, Err(ReadError::UnknownFormat)));
+    }
+
+    #[test]
+    fn binary_input_is_not_read() {
+        let path = temp_path("binary.bin");
+        let mut bytes = vec![0u8, 159, 146, 150];
+        bytes.resize(1 << 20, 0xff);
+        std::fs::write(&path, &bytes).unwrap();
+        let res = read_auto(&path);
+        let tikz = read(&path, Format::Tikz);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(res, Err(ReadError::UnknownFormat)));
+        assert!(matches!(tikz, Err(ReadError::WriteOnly(Format::Tikz))));
+    }
+}
@@ -0,0 +1,815 @@
+//! OpenQASM 2.0 import and export.
+//!
+//! Programs must start with the `OPENQASM 2.0;` header, and may include `qelib1.inc`. The
+//! supported gates are `h`, `x`, `z`, `s`, `sdg`, `t`, `tdg`, `rz`, `rx`, `cx`, `cz`, `cu1` (or
+//! `cp`), `ccx`, `ccz` and `swap`, applied to single qubits or broadcast over whole registers.
+//! Rotation angles must be rational multiples of pi, like `pi/4`, `-3*pi/2` or `0.25*pi`.
+//!
+//! Quantum registers are numbered consecutively in declaration order. Classical registers and
+//! barriers are accepted and ignored, and any other statement is rejected.
+//!
+//! The parser recovers from errors by skipping to the end of the statement, so that
+//! `parse_all` reports every error of a program.
+
+use crate::zx::circuit::{Circuit, Gate};
+use crate::zx::graph::Phase;
+use crate::zx::io::span::{Diagnostic, SourceMap, Span};
+use fraction::{Fraction, Zero};
+use std::fmt;
+
+/// Error produced while parsing an OpenQASM program
+pub type QasmError = Diagnostic;
+
+/// Parse an OpenQASM 2.0 program into a circuit, failing with its first error.
+pub fn parse(src: &str) -> Result<Circuit, QasmError> {
+    parse_all(src).map_err(|mut errors| errors.swap_remove(0))
+}
+
+/// Parse an OpenQASM 2.0 program into a circuit, failing with all its errors in source order.
+///
+/// After an error, the rest of the statement is skipped up to the next `;`, so a missing `;`
+/// also hides the statement that follows it.
+pub fn parse_all(src: &str) -> Result<Circuit, Vec<QasmError>> {
+    let source = SourceMap::new(src);
+    let mut errors = Vec::new();
+    let tokens = tokenize(&source, &mut errors);
+    let mut parser = Parser {
+        source,
+        tokens,
+        pos: 0,
+        qregs: Vec::new(),
+        cregs: Vec::new(),
+        circuit: Circuit::default(),
+        errors,
+    };
+    parser.parse();
+    let mut errors = parser.errors;
+    if errors.is_empty() {
+        return Ok(parser.circuit);
+    }
+    errors.sort_by_key(|e| e.span.start);
+    Err(errors)
+}
+
+/// Number of angle parameters and qubits of each supported gate
+fn signature(name: &str) -> Option<(usize, usize)> {
+    match name {
+        "h" | "x" | "z" | "s" | "sdg" | "t" | "tdg" => Some((0, 1)),
+        "rz" | "rx" => Some((1, 1)),
+        "cx" | "cz" | "swap" => Some((0, 2)),
+        "cu1" | "cp" => Some((1, 2)),
+        "ccx" | "ccz" => Some((0, 3)),
+        _ => None,
+    }
+}
+
+/// Build a gate from its name and arguments, checked against its `signature`.
+fn gate(name: &str, params: &[Phase], q: &[u32]) -> Gate {
+    match name {
+        "h" => Gate::H(q[0]),
+        "x" => Gate::X(q[0]),
+        "z" => Gate::Z(q[0]),
+        "s" => Gate::S(q[0]),
+        "sdg" => Gate::Sdg(q[0]),
+        "t" => Gate::T(q[0]),
+        "tdg" => Gate::Tdg(q[0]),
+        "rz" => Gate::Rz(q[0], params[0]),
+        "rx" => Gate::Rx(q[0], params[0]),
+        "cx" => Gate::Cx(q[0], q[1]),
+        "cz" => Gate::Cz(q[0], q[1]),
+        "cu1" | "cp" => Gate::CPhase(q[0], q[1], params[0]),
+        "ccx" => Gate::Ccx(q[0], q[1], q[2]),
+        "ccz" => Gate::Ccz(q[0], q[1], q[2]),
+        "swap" => Gate::Swap(q[0], q[1]),
+        _ => unreachable!("gate `{}` has no signature", name),
+    }
+}
+
+/// Write a circuit as an OpenQASM 2.0 program, on a single register `q`.
+///
+/// Rotation angles are written exactly, like `rz(3*pi/4)`.
+pub fn write(circuit: &Circuit) -> String {
+    let mut out = String::new();
+    out.push_str("OPENQASM 2.0;\ninclude \"qelib1.inc\";\n");
+    out.push_str(&format!("qreg q[{}];\n", circuit.num_qubits));
+    for gate in &circuit.gates {
+        let (name, angle) = match *gate {
+            Gate::H(_) => ("h", None),
+            Gate::X(_) => ("x", None),
+            Gate::Z(_) => ("z", None),
+            Gate::S(_) => ("s", None),
+            Gate::Sdg(_) => ("sdg", None),
+            Gate::T(_) => ("t", None),
+            Gate::Tdg(_) => ("tdg", None),
+            Gate::Rz(_, phase) => ("rz", Some(phase)),
+            Gate::Rx(_, phase) => ("rx", Some(phase)),
+            Gate::Cx(..) => ("cx", None),
+            Gate::Cz(..) => ("cz", None),
+            Gate::CPhase(_, _, phase) => ("cu1", Some(phase)),
+            Gate::Ccx(..) => ("ccx", None),
+            Gate::Ccz(..) => ("ccz", None),
+            Gate::Swap(..) => ("swap", None),
+        };
+        let qubits: Vec<_> = gate.qubits().iter().map(|q| format!("q[{}]", q)).collect();
+        match angle {
+            Some(phase) => out.push_str(&format!(
+                "{}({}) {};\n",
+                name,
+                angle_text(phase),
+                qubits.join(", ")
+            )),
+            None => out.push_str(&format!("{} {};\n", name, qubits.join(", "))),
+        }
+    }
+    out
+}
+
+/// Write a phase as an angle expression, like `0`, `pi` or `3*pi/4`.
+fn angle_text(phase: Phase) -> String {
+    match (phase.numerator(), phase.denominator()) {
+        (0, _) => "0".to_string(),
+        (1, 1) => "pi".to_string(),
+        (num, 1) => format!("{}*pi", num),
+        (1, den) => format!("pi/{}", den),
+        (num, den) => format!("{}*pi/{}", num, den),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Tok {
+    Id(String),
+    /// Integer or real literal
+    Number(String),
+    Str(String),
+    /// Single character punctuation or operator
+    Punct(char),
+    /// `->`
+    Arrow,
+    /// `==`
+    EqEq,
+    Eof,
+}
+
+impl fmt::Display for Tok {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Tok::Id(s) | Tok::Number(s) => write!(f, "`{}`", s),
+            Tok::Str(s) => write!(f, "`\"{}\"`", s),
+            Tok::Punct(c) => write!(f, "`{}`", c),
+            Tok::Arrow => write!(f, "`->`"),
+            Tok::EqEq => write!(f, "`==`"),
+            Tok::Eof => write!(f, "end of file"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    tok: Tok,
+    span: Span,
+}
+
+/// Split a program into tokens, skipping the characters that cannot start one and reporting
+/// them in `errors`.
+fn tokenize(source: &SourceMap, errors: &mut Vec<QasmError>) -> Vec<Token> {
+    let src = source.source();
+    let mut chars = src.char_indices().peekable();
+    let mut tokens = Vec::new();
+
+    while let Some((start, c)) = chars.next() {
+        let next = chars.peek().map(|&(_, c)| c);
+        let tok = if c.is_whitespace() {
+            continue;
+        } else if c == '/' && next == Some('/') {
+            while chars.next_if(|&(_, c)| c != '\n').is_some() {}
+            continue;
+        } else if c == '-' && next == Some('>') {
+            chars.next();
+            Tok::Arrow
+        } else if c == '=' && next == Some('=') {
+            chars.next();
+            Tok::EqEq
+        } else if c == '"' {
+            let mut s = String::new();
+            loop {
+                match chars.next() {
+                    None | Some((_, '\n')) => {
+                        let span = Span::new(start, start + 1);
+                        errors.push(source.diagnostic(span, "unterminated string"));
+                        break;
+                    }
+                    Some((_, '"')) => break,
+                    Some((_, c)) => s.push(c),
+                }
+            }
+            Tok::Str(s)
+        } else if c.is_ascii_digit() || (c == '.' && next.is_some_and(|c| c.is_ascii_digit())) {
+            let mut s = c.to_string();
+            while let Some((_, c)) = chars.next_if(|&(_, c)| c.is_ascii_digit() || c == '.') {
+                s.push(c);
+            }
+            if let Some((_, e)) = chars.next_if(|&(_, c)| c == 'e' || c == 'E') {
+                s.push(e);
+                if let Some((_, sign)) = chars.next_if(|&(_, c)| c == '+' || c == '-') {
+                    s.push(sign);
+                }
+                while let Some((_, c)) = chars.next_if(|&(_, c)| c.is_ascii_digit()) {
+                    s.push(c);
+                }
+            }
+            Tok::Number(s)
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut s = c.to_string();
+            while let Some((_, c)) = chars.next_if(|&(_, c)| c.is_ascii_alphanumeric() || c == '_')
+            {
+                s.push(c);
+            }
+            Tok::Id(s)
+        } else if ";,[](){}+-*/^<>=".contains(c) {
+            Tok::Punct(c)
+        } else {
+            let span = Span::new(start, start + c.len_utf8());
+            errors.push(
+                source
+                    .diagnostic(span, format!("unexpected character `{}`", c))
+                    .with_found(c.to_string()),
+            );
+            continue;
+        };
+        let end = chars.peek().map_or(src.len(), |&(i, _)| i);
+        tokens.push(Token {
+            tok,
+            span: Span::new(start, end),
+        });
+    }
+    tokens.push(Token {
+        tok: Tok::Eof,
+        span: Span::new(src.len(), src.len()),
+    });
+    tokens
+}
+
+/// Value of an angle expression, `coef * pi^pi_power`
+#[derive(Debug, Clone, Copy)]
+struct Angle {
+    coef: Fraction,
+    pi_power: i32,
+}
+
+impl Angle {
+    fn new(coef: Fraction, pi_power: i32) -> Self {
+        Angle { coef, pi_power }
+    }
+}
+
+/// Parse a decimal literal exactly, like `3`, `0.25` or `1e-2`.
+fn parse_number(s: &str) -> Option<Fraction> {
+    let (mantissa, exponent) = match s.find(['e', 'E']) {
+        Some(i) => (&s[..i], s[i + 1..].parse::<i32>().ok()?),
+        None => (s, 0),
+    };
+    let (int, frac) = match mantissa.split_once('.') {
+        Some((int, frac)) => (int, frac),
+        None => (mantissa, ""),
+    };
+    if int.is_empty() && frac.is_empty() {
+        return None;
+    }
+    let digits = format!("{}{}", int, frac);
+    let num: u64 = digits.parse().ok()?;
+    let exponent = exponent - frac.len() as i32;
+    let scale = 10u64.checked_pow(exponent.unsigned_abs())?;
+    Some(if exponent >= 0 {
+        Fraction::from(num.checked_mul(scale)?)
+    } else {
+        Fraction::new(num, scale)
+    })
+}
+
+struct Parser<'s> {
+    source: SourceMap<'s>,
+    tokens: Vec<Token>,
+    pos: usize,
+    /// Quantum registers, with the global index of their first qubit and their size
+    qregs: Vec<(String, u32, u32)>,
+    cregs: Vec<String>,
+    circuit: Circuit,
+    errors: Vec<QasmError>,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos]
+    }
+
+    fn bump(&mut self) -> Token {
+        let t = self.tokens[self.pos].clone();
+        if t.tok != Tok::Eof {
+            self.pos += 1;
+        }
+        t
+    }
+
+    fn error(&self, span: Span, message: impl Into<String>) -> QasmError {
+        self.source.diagnostic(span, message)
+    }
+
+    fn unexpected(&self, expected: &[&str]) -> QasmError {
+        let t = self.peek();
+        let message = format!("expected {}, found {}", expected.join(" or "), t.tok);
+        self.error(t.span, message)
+            .with_found(t.tok.to_string())
+            .with_expected(expected.iter().copied())
+    }
+
+    fn punct(&self, c: char) -> bool {
+        self.peek().tok == Tok::Punct(c)
+    }
+
+    fn expect(&mut self, c: char) -> Result<Token, QasmError> {
+        if self.punct(c) {
+            Ok(self.bump())
+        } else {
+            Err(self.unexpected(&[&Tok::Punct(c).to_string()]))
+        }
+    }
+
+    fn keyword(&self, kw: &str) -> bool {
+        matches!(&self.peek().tok, Tok::Id(s) if s == kw)
+    }
+
+    fn id(&mut self) -> Result<(String, Span), QasmError> {
+        match self.peek().tok.clone() {
+            Tok::Id(s) => Ok((s, self.bump().span)),
+            _ => Err(self.unexpected(&["an identifier"])),
+        }
+    }
+
+    fn integer(&mut self) -> Result<u32, QasmError> {
+        match self.peek().tok.clone() {
+            Tok::Number(s) if s.bytes().all(|b| b.is_ascii_digit()) => {
+                let t = self.bump();
+                s.parse()
+                    .map_err(|_| self.error(t.span, format!("integer `{}` is too large", s)))
+            }
+            _ => Err(self.unexpected(&["an integer"])),
+        }
+    }
+
+    /// Parse the program, collecting the errors of each statement
+    fn parse(&mut self) {
+        if let Err(e) = self.header() {
+            self.errors.push(e);
+            // Without a header, the first statement is likely a valid one.
+            if self.keyword("OPENQASM") {
+                self.recover();
+            }
+        }
+        while self.peek().tok != Tok::Eof {
+            if let Err(e) = self.statement() {
+                self.errors.push(e);
+                self.recover();
+            }
+        }
+    }
+
+    /// Skip the rest of a statement that failed to parse, up to and including its `;`
+    fn recover(&mut self) {
+        while self.peek().tok != Tok::Eof && self.bump().tok != Tok::Punct(';') {}
+    }
+
+    fn header(&mut self) -> Result<(), QasmError> {
+        if !self.keyword("OPENQASM") {
+            return Err(self.unexpected(&["`OPENQASM`"]));
+        }
+        self.bump();
+        match self.peek().tok.clone() {
+            Tok::Number(v) if v == "2.0" => {
+                self.bump();
+            }
+            Tok::Number(v) => {
+                let span = self.peek().span;
+                return Err(self
+                    .error(span, format!("unsupported OpenQASM version {}", v))
+                    .with_found(v)
+                    .with_expected(vec!["`2.0`"]));
+            }
+            _ => return Err(self.unexpected(&["a version number"])),
+        }
+        self.expect(';')?;
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<(), QasmError> {
+        let (name, span) = self.id()?;
+        match name.as_str() {
+            "include" => match self.peek().tok.clone() {
+                Tok::Str(file) if file == "qelib1.inc" => {
+                    self.bump();
+                }
+                Tok::Str(file) => {
+                    let span = self.peek().span;
+                    return Err(self
+                        .error(span, format!("cannot include `{}`", file))
+                        .with_expected(vec!["`\"qelib1.inc\"`"]));
+                }
+                _ => return Err(self.unexpected(&["a file name"])),
+            },
+            "qreg" | "creg" => {
+                let (reg, reg_span) = self.id()?;
+                self.expect('[')?;
+                let size = self.integer()?;
+                self.expect(']')?;
+                let taken =
+                    self.qregs.iter().any(|(r, _, _)| *r == reg) || self.cregs.contains(&reg);
+                if taken {
+                    let message = format!("register `{}` is already declared", reg);
+                    return Err(self.error(reg_span, message));
+                }
+                if name == "creg" {
+                    self.cregs.push(reg);
+                } else {
+                    let start = self.circuit.num_qubits;
+                    self.circuit.num_qubits = start
+                        .checked_add(size)
+                        .ok_or_else(|| self.error(reg_span, "too many qubits"))?;
+                    self.qregs.push((reg, start, size));
+                }
+            }
+            "barrier" => {
+                self.arguments()?;
+            }
+            "gate" | "opaque" | "measure" | "reset" | "if" | "U" | "CX" => {
+                let message = format!("`{}` statements are not supported", name);
+                return Err(self.error(span, message).with_found(name));
+            }
+            _ => self.application(name, span)?,
+        }
+        self.expect(';')?;
+        Ok(())
+    }
+
+    /// Parse a gate application after its name, adding the gates to the circuit.
+    fn application(&mut self, name: String, span: Span) -> Result<(), QasmError> {
+        let (num_params, num_qubits) = signature(&name).ok_or_else(|| {
+            self.error(span, format!("unknown gate `{}`", name))
+                .with_found(name.clone())
+        })?;
+        let mut params = Vec::new();
+        if self.punct('(') {
+            self.bump();
+            if !self.punct(')') {
+                params.push(self.angle()?);
+                while self.punct(',') {
+                    self.bump();
+                    params.push(self.angle()?);
+                }
+            }
+            self.expect(')')?;
+        }
+        let args = self.arguments()?;
+        if params.len() != num_params || args.len() != num_qubits {
+            let message = format!(
+                "gate `{}` takes {} parameters and {} qubits, found {} and {}",
+                name,
+                num_params,
+                num_qubits,
+                params.len(),
+                args.len()
+            );
+            return Err(self.error(span, message));
+        }
+
+        // Register arguments broadcast the gate over their qubits.
+        let mut width = 1;
+        for (qubits, arg_span) in &args {
+            if qubits.len() != 1 {
+                if width != 1 && width != qubits.len() {
+                    let message = "registers in a gate application must have the same size";
+                    return Err(self.error(*arg_span, message));
+                }
+                width = qubits.len();
+            }
+        }
+        for i in 0..width {
+            let qubits: Vec<u32> = args
+                .iter()
+                .map(|(qubits, _)| {
+                    if qubits.len() == 1 {
+                        qubits[0]
+                    } else {
+                        qubits[i]
+                    }
+                })
+                .collect();
+            if let Some(j) = (1..qubits.len()).find(|&j| qubits[..j].contains(&qubits[j])) {
+                let message = format!("qubit {} is used twice by gate `{}`", qubits[j], name);
+                return Err(self.error(args[j].1, message));
+            }
+            self.circuit.add_gate(gate(&name, &params, &qubits));
+        }
+        Ok(())
+    }
+
+    /// Parse a comma separated list of qubits or registers, as global qubit indices.
+    fn arguments(&mut self) -> Result<Vec<(Vec<u32>, Span)>, QasmError> {
+        let mut args = vec![self.argument()?];
+        while self.punct(',') {
+            self.bump();
+            args.push(self.argument()?);
+        }
+        Ok(args)
+    }
+
+    fn argument(&mut self) -> Result<(Vec<u32>, Span), QasmError> {
+        let (reg, span) = self.id()?;
+        let (start, size) = match self.qregs.iter().find(|(r, _, _)| *r == reg) {
+            Some(&(_, start, size)) => (start, size),
+            None => {
+                return Err(self
+                    .error(span, format!("unknown quantum register `{}`", reg))
+                    .with_found(reg))
+            }
+        };
+        if !self.punct('[') {
+            return Ok(((start..start + size).collect(), span));
+        }
+        self.bump();
+        let index = self.integer()?;
+        let end = self.expect(']')?.span.end;
+        let span = Span::new(span.start, end);
+        if index >= size {
+            let message = format!("index {} is out of range for `{}[{}]`", index, reg, size);
+            return Err(self.error(span, message));
+        }
+        Ok((vec![start + index], span))
+    }
+
+    /// Parse an angle expression, which must be a rational multiple of pi.
+    fn angle(&mut self) -> Result<Phase, QasmError> {
+        let start = self.peek().span.start;
+        let angle = self.sum()?;
+        let end = self.tokens[self.pos - 1].span.end;
+        if angle.pi_power == 1 || angle.coef.is_zero() {
+            Ok(Phase::from(angle.coef))
+        } else {
+            let message = "angle is not a rational multiple of pi";
+            Err(self.error(Span::new(start, end), message))
+        }
+    }
+
+    fn sum(&mut self) -> Result<Angle, QasmError> {
+        let mut acc = self.product()?;
+        while self.punct('+') || self.punct('-') {
+            let op = self.bump();
+            let rhs = self.product()?;
+            let rhs = if op.tok == Tok::Punct('-') {
+                Angle::new(-rhs.coef, rhs.pi_power)
+            } else {
+                rhs
+            };
+            acc = if acc.coef.is_zero() {
+                rhs
+            } else if rhs.coef.is_zero() {
+                acc
+            } else if acc.pi_power == rhs.pi_power {
+                Angle::new(acc.coef + rhs.coef, acc.pi_power)
+            } else {
+                let message = "cannot add a multiple of pi and a plain number";
+                return Err(self.error(op.span, message));
+            };
+        }
+        Ok(acc)
+    }
+
+    fn product(&mut self) -> Result<Angle, QasmError> {
+        let mut acc = self.unary()?;
+        while self.punct('*') || self.punct('/') {
+            let op = self.bump();
+            let rhs = self.unary()?;
+            acc = if op.tok == Tok::Punct('*') {
+                Angle::new(acc.coef * rhs.coef, acc.pi_power + rhs.pi_power)
+            } else if rhs.coef.is_zero() {
+                return Err(self.error(op.span, "division by zero"));
+            } else {
+                Angle::new(acc.coef / rhs.coef, acc.pi_power - rhs.pi_power)
+            };
+        }
+        Ok(acc)
+    }
+
+    fn unary(&mut self) -> Result<Angle, QasmError> {
+        if self.punct('-') {
+            self.bump();
+            let a = self.unary()?;
+            return Ok(Angle::new(-a.coef, a.pi_power));
+        }
+        if self.punct('+') {
+            self.bump();
+            return self.unary();
+        }
+        match self.peek().tok.clone() {
+            Tok::Punct('(') => {
+                self.bump();
+                let a = self.sum()?;
+                self.expect(')')?;
+                Ok(a)
+            }
+            Tok::Id(s) if s == "pi" => {
+                self.bump();
+                Ok(Angle::new(Fraction::from(1u64), 1))
+            }
+            Tok::Number(s) => {
+                let t = self.bump();
+                match parse_number(&s) {
+                    Some(f) => Ok(Angle::new(f, 0)),
+                    None => Err(self.error(t.span, format!("invalid number `{}`", s))),
+                }
+            }
+            _ => Err(self.unexpected(&["a number", "`pi`", "`(`"])),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::graph::*;
+    use crate::zx::simple_graph::GGraph;
+
+    /// A Toffoli-like benchmark on two registers, with a final CZ
+    const BENCHMARK: &str = "OPENQASM 2.0;
+include \"qelib1.inc\";
+qreg a[2];
+qreg b[1];
+creg c[3];
+h b[0];
+cx a[1], b[0];
+tdg b[0];
+cx a[0], b[0];
+t b[0];
+cx a[1], b[0];
+tdg b[0];
+cx a[0], b[0];
+t a[1];
+t b[0];
+h b[0];
+rz(pi/4) a[0];
+cz a[0], a[1];
+barrier a, b;
+";
+
+    #[test]
+    fn benchmark() {
+        let c = Circuit::from_qasm(BENCHMARK).unwrap();
+        assert_eq!(c.num_qubits, 3);
+        assert_eq!(c.gates.len(), 13);
+        assert_eq!(c.gates[1], Gate::Cx(1, 2));
+        assert_eq!(c.gates[11], Gate::Rz(0, Phase::new(1, 4)));
+
+        let g: GGraph = c.to_graph();
+        assert_eq!(g.tcount(), 6);
+        assert_eq!(g.inputs().count(), 3);
+        assert_eq!(g.outputs().count(), 3);
+        // 16 spiders and 6 boundaries, with a wire edge before each spider and output and an
+        // edge inside each two-qubit gate.
+        assert_eq!(g.num_vertices(), 22);
+        assert_eq!(g.num_edges(), 19 + 5);
+        let hadamards = g
+            .edges_with_data()
+            .filter(|(_, _, e)| e.kind() == EdgeKind::Hadam)
+            .count();
+        assert_eq!(hadamards, 3);
+    }
+
+    #[test]
+    fn broadcast_over_registers() {
+        let src = "OPENQASM 2.0;\nqreg a[2];\nqreg b[2];\ncx a, b;\nh a[1];\n";
+        let c = Circuit::from_qasm(src).unwrap();
+        assert_eq!(c.gates, vec![Gate::Cx(0, 2), Gate::Cx(1, 3), Gate::H(1)]);
+    }
+
+    #[test]
+    fn angles() {
+        let src = "OPENQASM 2.0;\nqreg q[1];\n\
+            rz(pi/4) q[0];\nrz(-3*pi/2) q[0];\nrx(0.25*pi) q[0];\nrz(pi/2 + pi/4) q[0];\n";
+        let c = Circuit::from_qasm(src).unwrap();
+        assert_eq!(
+            c.gates,
+            vec![
+                Gate::Rz(0, Phase::new(1, 4)),
+                Gate::Rz(0, Phase::new(1, 2)),
+                Gate::Rx(0, Phase::new(1, 4)),
+                Gate::Rz(0, Phase::new(3, 4)),
+            ]
+        );
+
+        let err = Circuit::from_qasm("OPENQASM 2.0;\nqreg q[1];\nrz(0.785) q[0];\n").unwrap_err();
+        assert_eq!(err.line, 3);
+        assert_eq!(err.message, "angle is not a rational multiple of pi");
+    }
+
+    #[test]
+    fn unknown_gate_reports_its_line() {
+        let src = "OPENQASM 2.0;\nqreg q[2];\nh q[0];\n\nfoo q[1];\n";
+        let err = Circuit::from_qasm(src).unwrap_err();
+        assert_eq!(err.message, "unknown gate `foo`");
+        assert_eq!((err.line, err.col), (5, 1));
+        assert_eq!(err.found.as_deref(), Some("foo"));
+
+        let err = Circuit::from_qasm("OPENQASM 2.0;\nqreg q[2];\ncx q[0], q[2];\n").unwrap_err();
+        assert_eq!(err.line, 3);
+        assert_eq!(err.message, "index 2 is out of range for `q[2]`");
+    }
+
+    #[test]
+    fn every_error_is_reported() {
+        let src = "OPENQASM 2.0;
+qreg q[3];
+h q[0];
+foo q[1];
+cx q[0], q[3];
+t q[2];
+rz(pi/4) q[0] $;
+cz q[0], q[1];
+";
+        let errors = parse_all(src).unwrap_err();
+        let found: Vec<_> = errors
+            .iter()
+            .map(|e| (e.line, e.message.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (4, "unknown gate `foo`"),
+                (5, "index 3 is out of range for `q[3]`"),
+                (7, "unexpected character `$`"),
+            ]
+        );
+        // `parse` stops at the first one.
+        assert_eq!(parse(src).unwrap_err(), errors[0]);
+
+        // Statements after a bad header are still checked.
+        let errors = parse_all(
+            "qreg q[1];
+h r[0];
+x q[0] q[0];
+",
+        )
+        .unwrap_err();
+        let lines: Vec<_> = errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, [1, 2, 3]);
+    }
+
+    /// A circuit using every gate, with angles that need each form of `angle_text`
+    fn every_gate() -> Circuit {
+        let mut c = Circuit::new(3);
+        for gate in [
+            Gate::H(0),
+            Gate::X(1),
+            Gate::Z(2),
+            Gate::S(0),
+            Gate::Sdg(1),
+            Gate::T(2),
+            Gate::Tdg(0),
+            Gate::Rz(1, Phase::new(1, 4)),
+            Gate::Rz(2, Phase::new(3, 4)),
+            Gate::Rx(0, Phase::pi()),
+            Gate::Rx(1, Phase::new(7, 8)),
+            Gate::Rz(2, Phase::zero()),
+            Gate::Cx(0, 1),
+            Gate::Cz(1, 2),
+            Gate::CPhase(2, 0, Phase::new(1, 2)),
+            Gate::Ccx(0, 1, 2),
+            Gate::Ccz(2, 1, 0),
+            Gate::Swap(0, 2),
+        ] {
+            c.add_gate(gate);
+        }
+        c
+    }
+
+    #[test]
+    fn round_trip() {
+        let c = every_gate();
+        let src = c.to_qasm();
+        assert!(src.contains("rz(pi/4) q[1];\n"));
+        assert!(src.contains("rz(3*pi/4) q[2];\n"));
+        assert!(src.contains("rx(pi) q[0];\n"));
+        assert!(src.contains("rz(0) q[2];\n"));
+        assert!(src.contains("cu1(pi/2) q[2], q[0];\n"));
+        let d = Circuit::from_qasm(&src).unwrap();
+        assert_eq!(d, c);
+        assert_eq!(d.to_qasm(), src);
+    }
+
+    #[test]
+    fn decomposed_export_uses_standard_gates() {
+        let c = every_gate().decompose_composite();
+        let src = c.to_qasm();
+        for name in ["ccx", "ccz", "cu1", "swap"] {
+            assert!(!src.contains(name), "{} in\n{}", name, src);
+        }
+        assert_eq!(Circuit::from_qasm(&src).unwrap(), c);
+    }
+}
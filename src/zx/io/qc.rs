@@ -0,0 +1,316 @@
+//! `.qc` import.
+//!
+//! Reads the `.qc` circuits of the reversible and Clifford+T benchmark suites. A header of
+//! directives starting with a dot declares the qubits with `.v a b c`, numbered in that order;
+//! the other directives, like `.i` and `.o`, are ignored. The gates follow between `BEGIN` and
+//! `END`, one per line, as a name followed by qubit labels. `#` starts a comment.
+//!
+//! The supported gates are `H`, `X`, `Y`, `Z`, `S` (or `P`), `S*` (or `P*`), `T`, `T*`, `tof`,
+//! `cnot` and `swap`. `X`, `tof` and `cnot` apply X to their last qubit, controlled by the
+//! others, and `Z` applies a Z controlled by its other qubits, with at most two controls (see
+//! `Gate::controlled_x`). `Y` is written as Z followed by X, dropping a global phase of i.
+//! Named subcircuits, declared by `BEGIN name`, are rejected.
+//!
+//! A line with an error is skipped, so that `parse_all` reports the errors of every line.
+
+use crate::zx::circuit::{Circuit, Gate};
+use crate::zx::io::span::{Diagnostic, SourceMap, Span};
+use std::collections::HashMap;
+
+/// Error produced while parsing a `.qc` circuit
+pub type QcError = Diagnostic;
+
+/// Part of the file being read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Header,
+    Body,
+    End,
+}
+
+/// Parse a `.qc` circuit, failing with its first error.
+pub fn parse(src: &str) -> Result<Circuit, QcError> {
+    parse_all(src).map_err(|mut errors| errors.swap_remove(0))
+}
+
+/// Parse a `.qc` circuit, failing with all its errors in source order.
+///
+/// A line with an error is skipped, and the following ones are still read. A `BEGIN` without
+/// the qubits declared before it stops the parsing.
+pub fn parse_all(src: &str) -> Result<Circuit, Vec<QcError>> {
+    let source = SourceMap::new(src);
+    let mut labels: Option<HashMap<&str, u32>> = None;
+    let mut circuit = Circuit::default();
+    let mut section = Section::Header;
+    let mut errors = Vec::new();
+    let mut offset = 0;
+    for line in src.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        let line = line.split('#').next().unwrap();
+        let words: Vec<(&str, Span)> = words(line, start).collect();
+        let (name, span) = match words.first() {
+            Some(&word) => word,
+            None => continue,
+        };
+        let error = |span: Span, message: String| source.diagnostic(span, message);
+        match section {
+            Section::Header if name.starts_with('.') => {
+                if name != ".v" {
+                    continue;
+                }
+                if labels.is_some() {
+                    errors.push(error(span, "qubits are declared twice".into()));
+                    continue;
+                }
+                let mut declared = HashMap::new();
+                for &(label, label_span) in &words[1..] {
+                    if declared.contains_key(label) {
+                        let message = format!("qubit `{}` is declared twice", label);
+                        errors.push(error(label_span, message));
+                    } else {
+                        declared.insert(label, declared.len() as u32);
+                    }
+                }
+                circuit.num_qubits = declared.len() as u32;
+                labels = Some(declared);
+            }
+            Section::Header if name == "BEGIN" => {
+                if let Some(&(sub, sub_span)) = words.get(1) {
+                    let message = format!("subcircuit `{}` is not supported", sub);
+                    errors.push(error(sub_span, message));
+                }
+                if labels.is_none() {
+                    errors.push(error(span, "missing `.v` line before `BEGIN`".into()));
+                    return Err(errors);
+                }
+                section = Section::Body;
+            }
+            Section::Header => errors.push(
+                error(span, format!("expected a directive, found `{}`", name))
+                    .with_found(name)
+                    .with_expected(["a directive like `.v`", "`BEGIN`"]),
+            ),
+            Section::Body if name == "END" => section = Section::End,
+            Section::Body => match gate_line(&source, labels.as_ref().unwrap(), &words) {
+                Ok(gates) => circuit.gates.extend(gates),
+                Err(e) => errors.push(e),
+            },
+            Section::End => errors.push(error(span, "line after `END`".into())),
+        }
+    }
+    if section != Section::End {
+        let span = Span::new(src.len(), src.len());
+        let message = match section {
+            Section::Header => "missing `BEGIN`",
+            _ => "missing `END`",
+        };
+        errors.push(source.diagnostic(span, message));
+    }
+    if errors.is_empty() {
+        Ok(circuit)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Gates of a line of the body, given as its words
+fn gate_line(
+    source: &SourceMap,
+    labels: &HashMap<&str, u32>,
+    words: &[(&str, Span)],
+) -> Result<Vec<Gate>, QcError> {
+    let (name, span) = words[0];
+    let mut qubits = Vec::new();
+    for &(label, label_span) in &words[1..] {
+        let q = labels.get(label).copied().ok_or_else(|| {
+            source
+                .diagnostic(label_span, format!("unknown qubit `{}`", label))
+                .with_found(label)
+        })?;
+        if qubits.contains(&q) {
+            let message = format!("qubit `{}` is used twice by gate `{}`", label, name);
+            return Err(source.diagnostic(label_span, message));
+        }
+        qubits.push(q);
+    }
+    gate(name, &qubits).map_err(|m| source.diagnostic(span, m))
+}
+
+/// Words of a line starting at byte `start` of the source, separated by spaces or commas
+fn words(line: &str, start: usize) -> impl Iterator<Item = (&str, Span)> {
+    line.split(|c: char| c.is_whitespace() || c == ',')
+        .scan(start, |pos, word| {
+            let word_start = *pos;
+            *pos += word.len() + 1;
+            Some((word, Span::new(word_start, word_start + word.len())))
+        })
+        .filter(|(word, _)| !word.is_empty())
+}
+
+/// Gates implementing a `.qc` gate, or an error message
+fn gate(name: &str, qubits: &[u32]) -> Result<Vec<Gate>, String> {
+    let (&target, controls) = match (name, qubits) {
+        (_, []) => return Err(format!("gate `{}` has no qubits", name)),
+        ("swap", [a, b]) => return Ok(vec![Gate::Swap(*a, *b)]),
+        ("swap", _) => return Err("gate `swap` takes 2 qubits".into()),
+        (_, [.., target]) => (target, &qubits[..qubits.len() - 1]),
+    };
+    let too_many = || "gates with more than two controls are not supported".to_string();
+    match name {
+        "X" | "tof" | "cnot" => return Gate::controlled_x(controls, target).ok_or_else(too_many),
+        "Z" => return Gate::controlled_z(controls, target).ok_or_else(too_many),
+        _ => {}
+    }
+    if !controls.is_empty() {
+        return Err(format!("controlled `{}` gates are not supported", name));
+    }
+    Ok(match name {
+        "H" => vec![Gate::H(target)],
+        "S" | "P" => vec![Gate::S(target)],
+        "S*" | "P*" => vec![Gate::Sdg(target)],
+        "T" => vec![Gate::T(target)],
+        "T*" => vec![Gate::Tdg(target)],
+        "Y" => vec![Gate::Z(target), Gate::X(target)],
+        _ => return Err(format!("unsupported gate `{}`", name)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::circuit::{CircuitFormat, GateSet};
+    use crate::zx::graph::*;
+    use crate::zx::simple_graph::GGraph;
+
+    const BENCHMARK: &str = "# A Toffoli followed by each other supported gate
+.v a b c
+.i a b c
+.o a b c
+
+BEGIN
+H c
+tof a b c
+T* c
+cnot b c
+T a
+P b
+S* b
+Z a b
+X c
+Y a
+swap a c
+T c # trailing comment
+END
+";
+
+    fn t_count(c: &Circuit) -> usize {
+        c.gates
+            .iter()
+            .filter(|g| matches!(g, Gate::T(_) | Gate::Tdg(_)))
+            .count()
+    }
+
+    #[test]
+    fn benchmark() {
+        assert_eq!(CircuitFormat::sniff(BENCHMARK), Some(CircuitFormat::Qc));
+        let c = Circuit::from_qc(BENCHMARK).unwrap();
+        assert_eq!(c.num_qubits, 3);
+        assert_eq!(c.gates.len(), 15);
+        assert_eq!(t_count(&c), 3);
+        assert_eq!(
+            c.gates[..6],
+            [
+                Gate::H(2),
+                Gate::H(2),
+                Gate::Ccz(0, 1, 2),
+                Gate::H(2),
+                Gate::Tdg(2),
+                Gate::Cx(1, 2),
+            ]
+        );
+        assert_eq!(
+            c.gates[9..],
+            [
+                Gate::Cz(0, 1),
+                Gate::X(2),
+                Gate::Z(0),
+                Gate::X(0),
+                Gate::Swap(0, 2),
+                Gate::T(2),
+            ]
+        );
+        let g: GGraph = c.to_graph();
+        assert_eq!(g.tcount(), 3);
+
+        let mut lowered = c.clone();
+        lowered.decompose(GateSet::CliffordT);
+        assert_eq!(t_count(&lowered), 10);
+    }
+
+    #[test]
+    fn errors_have_positions() {
+        let err = |src: &str| Circuit::from_qc(src).unwrap_err();
+        let e = err(".v a b\nBEGIN\nH a\ncnot a d\nEND\n");
+        assert_eq!(e.message, "unknown qubit `d`");
+        assert_eq!((e.line, e.col), (4, 8));
+        assert_eq!(e.found.as_deref(), Some("d"));
+
+        let e = err(".v a b\nBEGIN\nRz a\nEND\n");
+        assert_eq!(e.message, "unsupported gate `Rz`");
+        assert_eq!((e.line, e.col), (3, 1));
+
+        let e = err(".v a b c d\nBEGIN\ntof a b c d\nEND\n");
+        assert_eq!(
+            e.message,
+            "gates with more than two controls are not supported"
+        );
+        let e = err(".v a b\nBEGIN\nH a b\nEND\n");
+        assert_eq!(e.message, "controlled `H` gates are not supported");
+        let e = err(".v a b\nBEGIN\ncnot a a\nEND\n");
+        assert_eq!(e.message, "qubit `a` is used twice by gate `cnot`");
+        let e = err(".v a a\n");
+        assert_eq!((e.line, e.col), (1, 6));
+        let e = err(".v a\nBEGIN sub\nEND\n");
+        assert_eq!(e.message, "subcircuit `sub` is not supported");
+        assert_eq!(err(".v a\nBEGIN\nH a\n").message, "missing `END`");
+        assert_eq!(err(".v a\nBEGIN\nEND\nH a\n").message, "line after `END`");
+        assert_eq!(
+            err("BEGIN\nEND\n").message,
+            "missing `.v` line before `BEGIN`"
+        );
+    }
+
+    #[test]
+    fn every_error_is_reported() {
+        let src = ".v a b c
+.i a b
+BEGIN
+H a
+cnot a e
+tof a b c
+Rz b
+T c
+swap b b
+END
+";
+        let errors = parse_all(src).unwrap_err();
+        let found: Vec<_> = errors
+            .iter()
+            .map(|e| (e.line, e.message.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (5, "unknown qubit `e`"),
+                (7, "unsupported gate `Rz`"),
+                (9, "qubit `b` is used twice by gate `swap`"),
+            ]
+        );
+        assert_eq!(parse(src).unwrap_err(), errors[0]);
+
+        // Nothing is read after a `BEGIN` without qubits.
+        let errors = parse_all("BEGIN\nRz a\n").unwrap_err();
+        assert_eq!(errors.len(), 1);
+    }
+}
@@ -0,0 +1,465 @@
+//! Quipper ASCII import.
+//!
+//! Reads the circuits printed by Quipper's ASCII output, the format of many benchmark circuits
+//! of the optimization literature. An `Inputs:` line lists the qubit wires, like
+//! `Inputs: 0:Qbit, 1:Qbit`, which are numbered in that order. It is followed by one gate per
+//! line, like `QGate["not"](2) with controls=[+0,+1]`, and by an `Outputs:` line. `Comment`
+//! lines are ignored.
+//!
+//! The supported gates are `not` (or `X`), `Z`, `H`, `S`, `T`, `Y` and `swap`, with a trailing
+//! `*` for the inverse. `not` and `Z` take up to two controls, becoming X, CX, Toffoli (see
+//! `Gate::controlled_x`), Z, CZ or CCZ gates, and negative controls like `-1` are conjugated by
+//! X gates. `Y` is written as Z followed by X, dropping a global phase of i. The
+//! `with nocontrol` flag is ignored. Initializations, terminations, measurements, rotations and
+//! subroutines are rejected.
+//!
+//! A line with an error is skipped, so that `parse_all` reports the errors of every line.
+
+use crate::zx::circuit::{Circuit, Gate};
+use crate::zx::io::span::{Diagnostic, SourceMap, Span};
+use std::collections::HashMap;
+
+/// Error produced while parsing a Quipper ASCII circuit
+pub type QuipperError = Diagnostic;
+
+/// Parse a Quipper ASCII circuit, failing with its first error.
+pub fn parse(src: &str) -> Result<Circuit, QuipperError> {
+    parse_all(src).map_err(|mut errors| errors.swap_remove(0))
+}
+
+/// Parse a Quipper ASCII circuit, failing with all its errors in source order.
+///
+/// A line with an error is skipped, and the following ones are still read. Errors before or on
+/// the `Inputs:` line stop the parsing, since the wires are unknown.
+pub fn parse_all(src: &str) -> Result<Circuit, Vec<QuipperError>> {
+    let source = SourceMap::new(src);
+    let mut wires: Option<HashMap<&str, u32>> = None;
+    let mut circuit = Circuit::default();
+    let mut ended = false;
+    let mut errors = Vec::new();
+    let mut offset = 0;
+    for line in src.split_inclusive('\n') {
+        let mut cursor = Cursor {
+            source: &source,
+            line: line.trim_end_matches(&['\n', '\r'][..]),
+            start: offset,
+            pos: 0,
+        };
+        offset += line.len();
+        cursor.skip_spaces();
+        if cursor.rest().is_empty() {
+            continue;
+        }
+        if ended {
+            errors.push(cursor.error(cursor.pos, cursor.line.len(), "line after `Outputs:`"));
+            continue;
+        }
+        if cursor.eat("Inputs:") {
+            if wires.is_some() {
+                errors.push(cursor.error(0, cursor.pos, "inputs are declared twice"));
+                continue;
+            }
+            let inputs = match cursor.inputs() {
+                Ok(inputs) => inputs,
+                Err(e) => {
+                    errors.push(e);
+                    return Err(errors);
+                }
+            };
+            circuit.num_qubits = inputs.len() as u32;
+            wires = Some(inputs);
+        } else if cursor.eat("Comment[") {
+            continue;
+        } else if let Some(wires) = &wires {
+            if cursor.eat("Outputs:") {
+                ended = true;
+            } else if cursor.eat("QGate[") {
+                match cursor.gate(wires) {
+                    Ok(gates) => circuit.gates.extend(gates),
+                    Err(e) => errors.push(e),
+                }
+            } else {
+                let end = cursor
+                    .rest()
+                    .find(['[', '(', ' ', ':'])
+                    .map_or(cursor.line.len(), |i| cursor.pos + i);
+                let word = &cursor.line[cursor.pos..end];
+                let message = format!("unsupported statement `{}`", word);
+                errors.push(cursor.error(cursor.pos, end, message).with_found(word));
+            }
+        } else {
+            errors.push(
+                cursor
+                    .error(cursor.pos, cursor.line.len(), "expected the `Inputs:` line")
+                    .with_expected(["`Inputs:`"]),
+            );
+            return Err(errors);
+        }
+    }
+    if wires.is_none() {
+        let span = Span::new(src.len(), src.len());
+        errors.push(source.diagnostic(span, "missing `Inputs:` line"));
+    }
+    if errors.is_empty() {
+        Ok(circuit)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Position in a line of the source
+struct Cursor<'a, 's> {
+    source: &'a SourceMap<'s>,
+    line: &'s str,
+    /// Byte offset of the line in the source
+    start: usize,
+    /// Byte offset in the line
+    pos: usize,
+}
+
+impl<'s> Cursor<'_, 's> {
+    fn rest(&self) -> &'s str {
+        &self.line[self.pos..]
+    }
+
+    /// Diagnostic for a byte range of the line
+    fn error(&self, from: usize, to: usize, message: impl Into<String>) -> QuipperError {
+        let span = Span::new(self.start + from, self.start + to.max(from + 1));
+        self.source.diagnostic(span, message)
+    }
+
+    fn skip_spaces(&mut self) {
+        self.pos = self.line.len() - self.rest().trim_start().len();
+    }
+
+    /// Consume `s` after optional spaces, if it comes next
+    fn eat(&mut self, s: &str) -> bool {
+        self.skip_spaces();
+        if self.rest().starts_with(s) {
+            self.pos += s.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, s: &str) -> Result<(), QuipperError> {
+        if self.eat(s) {
+            return Ok(());
+        }
+        let found = self.rest().chars().next();
+        let message = match found {
+            Some(c) => format!("expected `{}`, found `{}`", s, c),
+            None => format!("expected `{}`, found end of line", s),
+        };
+        let error = self
+            .error(self.pos, self.pos + 1, message)
+            .with_expected([format!("`{}`", s)]);
+        Err(match found {
+            Some(c) => error.with_found(c.to_string()),
+            None => error,
+        })
+    }
+
+    /// Consume the text up to `end` and `end` itself, returning the text and its start.
+    fn until(&mut self, end: char) -> Result<(&'s str, usize), QuipperError> {
+        let start = self.pos;
+        match self.rest().find(end) {
+            Some(i) => {
+                self.pos += i + end.len_utf8();
+                Ok((&self.line[start..start + i], start))
+            }
+            None => Err(self
+                .error(
+                    self.line.len(),
+                    self.line.len(),
+                    format!("missing `{}`", end),
+                )
+                .with_expected([format!("`{}`", end)])),
+        }
+    }
+
+    /// Split a comma separated list starting at `start` in the line into its trimmed items
+    fn items(text: &'s str, start: usize) -> Vec<(&'s str, usize)> {
+        let mut items = Vec::new();
+        let mut pos = start;
+        for item in text.split(',') {
+            let trimmed = item.trim_start();
+            let item_start = pos + item.len() - trimmed.len();
+            items.push((trimmed.trim_end(), item_start));
+            pos += item.len() + 1;
+        }
+        items
+    }
+
+    /// Parse the wires of the `Inputs:` line, numbering them in order
+    fn inputs(&mut self) -> Result<HashMap<&'s str, u32>, QuipperError> {
+        let start = self.pos;
+        let mut wires = HashMap::new();
+        for (item, at) in Self::items(self.rest(), start) {
+            let (label, kind) = item.split_once(':').ok_or_else(|| {
+                self.error(at, at + item.len(), "expected a wire like `0:Qbit`")
+                    .with_found(item)
+            })?;
+            if kind.trim() != "Qbit" {
+                let message = format!("unsupported wire type `{}`", kind.trim());
+                return Err(self
+                    .error(at, at + item.len(), message)
+                    .with_expected(["`Qbit`"]));
+            }
+            let label = label.trim_end();
+            if wires.insert(label, wires.len() as u32).is_some() {
+                let message = format!("wire `{}` is declared twice", label);
+                return Err(self.error(at, at + label.len(), message));
+            }
+        }
+        self.pos = self.line.len();
+        Ok(wires)
+    }
+
+    /// Qubit of a wire label at a byte offset of the line
+    fn wire(
+        &self,
+        wires: &HashMap<&str, u32>,
+        label: &str,
+        at: usize,
+    ) -> Result<u32, QuipperError> {
+        wires.get(label).copied().ok_or_else(|| {
+            self.error(at, at + label.len(), format!("unknown wire `{}`", label))
+                .with_found(label)
+        })
+    }
+
+    /// Parse a gate after `QGate[`, returning the gates implementing it.
+    fn gate(&mut self, wires: &HashMap<&str, u32>) -> Result<Vec<Gate>, QuipperError> {
+        self.expect("\"")?;
+        let (name, name_at) = self.until('"')?;
+        let name_end = name_at + name.len();
+        self.expect("]")?;
+        let inverse = self.eat("*");
+        self.expect("(")?;
+        let (targets, targets_at) = self.until(')')?;
+        let mut qubits = Vec::new();
+        for (label, at) in Self::items(targets, targets_at) {
+            qubits.push((self.wire(wires, label, at)?, at));
+        }
+
+        let mut controls = Vec::new();
+        let mut negated = Vec::new();
+        while self.eat("with") {
+            if self.eat("controls=[") {
+                let (list, list_at) = self.until(']')?;
+                for (control, at) in Self::items(list, list_at) {
+                    let (positive, label) = match control.split_at_checked(1) {
+                        Some(("+", label)) => (true, label),
+                        Some(("-", label)) => (false, label),
+                        _ => {
+                            let message = "expected a control like `+0` or `-0`";
+                            return Err(self.error(at, at + control.len(), message));
+                        }
+                    };
+                    let q = self.wire(wires, label, at + 1)?;
+                    controls.push(q);
+                    qubits.push((q, at));
+                    if !positive {
+                        negated.push(Gate::X(q));
+                    }
+                }
+            } else if !self.eat("nocontrol") {
+                return Err(self
+                    .error(self.pos, self.line.len(), "unknown gate flag")
+                    .with_expected(["`controls=[`", "`nocontrol`"]));
+            }
+        }
+        self.skip_spaces();
+        if !self.rest().is_empty() {
+            return Err(self.error(self.pos, self.line.len(), "unexpected text after the gate"));
+        }
+        if let Some(j) = (1..qubits.len()).find(|&j| qubits[..j].iter().any(|q| q.0 == qubits[j].0))
+        {
+            let (q, at) = qubits[j];
+            let message = format!("qubit {} is used twice by gate `{}`", q, name);
+            return Err(self.error(at, at + 1, message));
+        }
+
+        let num_targets = qubits.len() - controls.len();
+        let expected_targets = if name == "swap" { 2 } else { 1 };
+        if num_targets != expected_targets {
+            let message = format!(
+                "gate `{}` takes {} target qubits, found {}",
+                name, expected_targets, num_targets
+            );
+            return Err(self.error(name_at, name_end, message));
+        }
+        let target = qubits[0].0;
+        let unsupported = |message: String| self.error(name_at, name_end, message);
+        let too_many = || unsupported("gates with more than two controls are not supported".into());
+        let gates = match name {
+            "not" | "X" => Gate::controlled_x(&controls, target).ok_or_else(too_many)?,
+            "Z" => Gate::controlled_z(&controls, target).ok_or_else(too_many)?,
+            "H" | "S" | "T" | "Y" | "swap" if !controls.is_empty() => {
+                return Err(unsupported(format!(
+                    "controlled `{}` gates are not supported",
+                    name
+                )))
+            }
+            "H" => vec![Gate::H(target)],
+            "S" if inverse => vec![Gate::Sdg(target)],
+            "S" => vec![Gate::S(target)],
+            "T" if inverse => vec![Gate::Tdg(target)],
+            "T" => vec![Gate::T(target)],
+            "Y" => vec![Gate::Z(target), Gate::X(target)],
+            "swap" => vec![Gate::Swap(target, qubits[1].0)],
+            _ => return Err(unsupported(format!("unsupported gate `{}`", name)).with_found(name)),
+        };
+        Ok(negated
+            .iter()
+            .chain(&gates)
+            .chain(&negated)
+            .copied()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::circuit::{CircuitFormat, GateSet};
+    use crate::zx::graph::*;
+    use crate::zx::simple_graph::GGraph;
+
+    /// Clifford+T gates on a Toffoli-like pattern, followed by each other supported statement
+    const BENCHMARK: &str = r#"Inputs: 0:Qbit, 1:Qbit, 2:Qbit
+QGate["H"](2)
+QGate["not"](2) with controls=[+1]
+QGate["T"]*(2)
+QGate["not"](2) with controls=[+0]
+QGate["T"](2)
+QGate["not"](2) with controls=[+1]
+QGate["T"]*(2) with nocontrol
+QGate["not"](2) with controls=[+0]
+QGate["T"](1)
+QGate["T"](2)
+QGate["H"](2)
+Comment["toffoli"](0:"a", 1:"b")
+QGate["not"](2) with controls=[+0,-1]
+QGate["Z"](1) with controls=[+0]
+QGate["swap"](0,1)
+QGate["S"]*(0)
+Outputs: 0:Qbit, 1:Qbit, 2:Qbit
+"#;
+
+    fn t_count(c: &Circuit) -> usize {
+        c.gates
+            .iter()
+            .filter(|g| matches!(g, Gate::T(_) | Gate::Tdg(_)))
+            .count()
+    }
+
+    #[test]
+    fn benchmark() {
+        assert_eq!(
+            CircuitFormat::sniff(BENCHMARK),
+            Some(CircuitFormat::Quipper)
+        );
+        let c = Circuit::from_quipper(BENCHMARK).unwrap();
+        assert_eq!(c.num_qubits, 3);
+        assert_eq!(c.gates.len(), 19);
+        assert_eq!(t_count(&c), 5);
+        assert_eq!(c.gates[1], Gate::Cx(1, 2));
+        assert_eq!(c.gates[2], Gate::Tdg(2));
+        assert_eq!(
+            c.gates[11..],
+            [
+                Gate::X(1),
+                Gate::H(2),
+                Gate::Ccz(0, 1, 2),
+                Gate::H(2),
+                Gate::X(1),
+                Gate::Cz(0, 1),
+                Gate::Swap(0, 1),
+                Gate::Sdg(0),
+            ]
+        );
+        let g: GGraph = c.to_graph();
+        assert_eq!(g.tcount(), 5);
+
+        let mut lowered = c.clone();
+        lowered.decompose(GateSet::CliffordT);
+        assert_eq!(t_count(&lowered), 12);
+    }
+
+    #[test]
+    fn y_and_inverses() {
+        let src = "Inputs: a:Qbit\nQGate[\"Y\"](a)\nQGate[\"S\"](a)\nQGate[\"X\"](a)\n";
+        let c = Circuit::from_quipper(src).unwrap();
+        assert_eq!(c.gates, [Gate::Z(0), Gate::X(0), Gate::S(0), Gate::X(0)]);
+    }
+
+    #[test]
+    fn errors_have_positions() {
+        let err = |src: &str| Circuit::from_quipper(src).unwrap_err();
+        let e = err("Inputs: 0:Qbit, 1:Qbit\nQGate[\"H\"](0)\nQGate[\"H\"](2)\n");
+        assert_eq!(e.message, "unknown wire `2`");
+        assert_eq!((e.line, e.col), (3, 12));
+        assert_eq!(e.found.as_deref(), Some("2"));
+
+        let e = err("Inputs: 0:Qbit\nQGate[\"rot\"](0)\n");
+        assert_eq!(e.message, "unsupported gate `rot`");
+        assert_eq!((e.line, e.col), (2, 8));
+
+        let e = err("Inputs: 0:Qbit, 1:Qbit, 2:Qbit, 3:Qbit\n\
+                     QGate[\"not\"](3) with controls=[+0,+1,+2]\n");
+        assert_eq!(
+            e.message,
+            "gates with more than two controls are not supported"
+        );
+        assert_eq!(e.line, 2);
+
+        let e = err("Inputs: 0:Qbit\nQGate[\"H\"](0) with controls=[+0]\n");
+        assert_eq!(e.message, "qubit 0 is used twice by gate `H`");
+
+        let e = err("Inputs: 0:Cbit\n");
+        assert_eq!(e.message, "unsupported wire type `Cbit`");
+        assert_eq!(e.expected, ["`Qbit`"]);
+
+        let e = err("QGate[\"H\"](0)\n");
+        assert_eq!(e.message, "expected the `Inputs:` line");
+        let e = err("Inputs: 0:Qbit\nQInit0(0)\n");
+        assert_eq!(e.message, "unsupported statement `QInit0`");
+        let e = err("Inputs: 0:Qbit\nOutputs: 0:Qbit\nQGate[\"H\"](0)\n");
+        assert_eq!(e.message, "line after `Outputs:`");
+        assert_eq!(err("").message, "missing `Inputs:` line");
+    }
+
+    #[test]
+    fn every_error_is_reported() {
+        let src = r#"Inputs: 0:Qbit, 1:Qbit
+QGate["H"](0)
+QGate["rot"](1)
+QGate["not"](1) with controls=[+0]
+QGate["H"](5)
+QMeas(0)
+QGate["T"](1)
+Outputs: 0:Qbit, 1:Qbit
+"#;
+        let errors = parse_all(src).unwrap_err();
+        let found: Vec<_> = errors
+            .iter()
+            .map(|e| (e.line, e.message.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (3, "unsupported gate `rot`"),
+                (5, "unknown wire `5`"),
+                (6, "unsupported statement `QMeas`"),
+            ]
+        );
+        assert_eq!(parse(src).unwrap_err(), errors[0]);
+
+        // Nothing is read after a bad `Inputs:` line.
+        let errors = parse_all("Inputs: 0:Cbit\nQGate[\"rot\"](0)\n").unwrap_err();
+        assert_eq!(errors.len(), 1);
+    }
+}
@@ -0,0 +1,400 @@
+//! Backend-independent serde representation of diagrams, behind the `serde` feature.
+//!
+//! A diagram is stored as a list of vertices, each with an explicit id, a list of edges given
+//! by the ids of their endpoints, the ids of the inputs and outputs in wire order, and the
+//! scalar. When writing, the ids are the positions of the vertices in the list. When reading,
+//! any distinct ids are accepted, and the vertices are added in list order. Backend indices
+//! never appear, so a diagram written by one backend can be read by any other.
+//!
+//! Reading fails with a serde error if two vertices share an id, if an edge, input or output
+//! refers to a missing vertex, or if a vertex is listed twice as an input or as an output.
+
+use crate::zx::graph::*;
+use crate::zx::simple_graph::{GGraph, VertexData};
+use crate::zx::stable_graph::StableGGraph;
+use crate::zx::vec_graph::VecGraph;
+use petgraph::graph::IndexType;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "Graph")]
+struct SerialGraph {
+    vertices: Vec<SerialVertex>,
+    edges: Vec<SerialEdge>,
+    inputs: Vec<u64>,
+    outputs: Vec<u64>,
+    scalar: Scalar,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "Vertex")]
+struct SerialVertex {
+    id: u64,
+    kind: VertexKind,
+    phase: Phase,
+    qubit: i32,
+    row: i32,
+    ground: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hole: Option<Hole>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "Edge")]
+struct SerialEdge {
+    source: u64,
+    target: u64,
+    kind: EdgeKind,
+}
+
+impl SerialGraph {
+    fn new<G: Graph<VertexData = VertexData>>(g: &G) -> Self {
+        let ids: HashMap<_, _> = g
+            .vertices()
+            .enumerate()
+            .map(|(i, v)| (v, i as u64))
+            .collect();
+        let vertices = g
+            .vertices()
+            .map(|v| {
+                let vdata = g.vertex(v).unwrap();
+                SerialVertex {
+                    id: ids[&v],
+                    kind: vdata.kind,
+                    phase: vdata.phase,
+                    qubit: vdata.qubit,
+                    row: vdata.row,
+                    ground: vdata.ground,
+                    hole: vdata.hole().cloned(),
+                }
+            })
+            .collect();
+        let edges = g
+            .edges_with_data()
+            .map(|(source, target, edata)| SerialEdge {
+                source: ids[&source],
+                target: ids[&target],
+                kind: edata.kind(),
+            })
+            .collect();
+        SerialGraph {
+            vertices,
+            edges,
+            inputs: g.inputs().map(|v| ids[&v]).collect(),
+            outputs: g.outputs().map(|v| ids[&v]).collect(),
+            scalar: *g.scalar(),
+        }
+    }
+
+    /// Build the diagram, checking the ids.
+    fn build<G: Graph<VertexData = VertexData> + Default>(self) -> Result<G, String> {
+        let mut g = G::default();
+        let mut map = HashMap::new();
+        for vertex in self.vertices {
+            let entry = match map.entry(vertex.id) {
+                Entry::Occupied(_) => return Err(format!("duplicate vertex id {}", vertex.id)),
+                Entry::Vacant(entry) => entry,
+            };
+            let v = g.add_vertex(vertex.kind, vertex.phase);
+            *g.vertex_mut(v).unwrap() = VertexData {
+                kind: vertex.kind,
+                phase: vertex.phase,
+                qubit: vertex.qubit,
+                row: vertex.row,
+                ground: vertex.ground,
+                hole: vertex.hole.map(Box::new),
+            };
+            entry.insert(v);
+        }
+        let lookup = |id: u64, what: &str| {
+            map.get(&id)
+                .copied()
+                .ok_or_else(|| format!("{} refers to a missing vertex {}", what, id))
+        };
+        for edge in &self.edges {
+            let (source, target) = (lookup(edge.source, "edge")?, lookup(edge.target, "edge")?);
+            // `add_edge(v, u)` stores the edge as `(u, v)`, so the endpoints read back in the
+            // same order and writing the graph again gives the same representation.
+            g.add_edge(target, source, edge.kind);
+        }
+        for (ids, what, is_input) in [
+            (&self.inputs, "input", true),
+            (&self.outputs, "output", false),
+        ] {
+            let mut seen = HashSet::new();
+            for &id in ids {
+                let v = lookup(id, what)?;
+                if !seen.insert(id) {
+                    return Err(format!("vertex {} is listed twice as an {}", id, what));
+                }
+                if is_input {
+                    g.set_input(v, true);
+                } else {
+                    g.set_output(v, true);
+                }
+            }
+        }
+        *g.scalar_mut() = self.scalar;
+        Ok(g)
+    }
+}
+
+fn serialize<G, S>(g: &G, serializer: S) -> Result<S::Ok, S::Error>
+where
+    G: Graph<VertexData = VertexData>,
+    S: Serializer,
+{
+    SerialGraph::new(g).serialize(serializer)
+}
+
+fn deserialize<'de, G, D>(deserializer: D) -> Result<G, D::Error>
+where
+    G: Graph<VertexData = VertexData> + Default,
+    D: Deserializer<'de>,
+{
+    SerialGraph::deserialize(deserializer)?
+        .build()
+        .map_err(de::Error::custom)
+}
+
+impl<Ix: IndexType + MaybeSync> Serialize for GGraph<Ix> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize(self, serializer)
+    }
+}
+
+impl<'de, Ix: IndexType + MaybeSync> Deserialize<'de> for GGraph<Ix> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize(deserializer)
+    }
+}
+
+impl<Ix: IndexType + MaybeSync> Serialize for StableGGraph<Ix> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize(self, serializer)
+    }
+}
+
+impl<'de, Ix: IndexType + MaybeSync> Deserialize<'de> for StableGGraph<Ix> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize(deserializer)
+    }
+}
+
+impl Serialize for VecGraph {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for VecGraph {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize(deserializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::analysis::is_isomorphic;
+    use crate::zx::circuit::{Circuit, Gate};
+    use crate::zx::templates;
+    use serde::de::DeserializeOwned;
+
+    /// A Toffoli with a scalar, grounded spider and a placed vertex, so every field is used
+    fn diagram() -> GGraph {
+        let mut g = templates::toffoli();
+        let v = g.add_vertex(VertexKind::Z, Phase::new(3, 4));
+        g.vertex_mut(v).unwrap().ground = true;
+        g.set_qubit(v, 5);
+        g.set_row(v, -2);
+        *g.scalar_mut() = Scalar::new(-3, Phase::new(1, 4));
+        g
+    }
+
+    /// The vertices are read back in order, with the same data, and so is the rest of `g`.
+    fn assert_same<G: Graph<VertexData = VertexData>>(g: &GGraph, h: &G) {
+        assert!(is_isomorphic(g, h));
+        for (v, u) in g.vertices().zip(h.vertices()) {
+            assert_eq!(g.vertex(v), h.vertex(u));
+        }
+        assert_eq!(h.scalar(), g.scalar());
+    }
+
+    /// Round trip `g` through JSON and CBOR, reading it back as `G`.
+    fn check_round_trip<G>(g: &GGraph)
+    where
+        G: Graph<VertexData = VertexData> + Serialize + DeserializeOwned,
+    {
+        let json = serde_json::to_string(g).unwrap();
+        let h: G = serde_json::from_str(&json).unwrap();
+        assert_same(g, &h);
+        assert_eq!(serde_json::to_string(&h).unwrap(), json);
+
+        let cbor = serde_cbor::to_vec(g).unwrap();
+        let h: G = serde_cbor::from_slice(&cbor).unwrap();
+        assert_same(g, &h);
+        assert_eq!(serde_cbor::to_vec(&h).unwrap(), cbor);
+    }
+
+    #[test]
+    fn round_trip_on_every_backend() {
+        for g in [diagram(), templates::cluster_state(3, 2), GGraph::new()] {
+            check_round_trip::<GGraph>(&g);
+            check_round_trip::<StableGGraph>(&g);
+            check_round_trip::<VecGraph>(&g);
+        }
+    }
+
+    #[test]
+    fn ids_do_not_depend_on_indices() {
+        let mut g: StableGGraph =
+            serde_json::from_str(&serde_json::to_string(&diagram()).unwrap()).unwrap();
+        let first = g
+            .vertices()
+            .find(|&v| g.vertex(v).unwrap().kind() != VertexKind::Boundary)
+            .unwrap();
+        g.remove_vertex(first);
+        let value = serde_json::to_value(&g).unwrap();
+        let ids: Vec<_> = value["vertices"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|v| v["id"].as_u64().unwrap())
+            .collect();
+        assert_eq!(ids, (0..g.num_vertices() as u64).collect::<Vec<_>>());
+        check_round_trip::<VecGraph>(&serde_json::from_value(value).unwrap());
+    }
+
+    #[test]
+    fn arbitrary_ids() {
+        let src = r#"{
+            "vertices": [
+                {"id": 7, "kind": "Boundary", "phase": [0, 1], "qubit": 0, "row": 0, "ground": false},
+                {"id": 3, "kind": "Z", "phase": [1, 2], "qubit": 0, "row": 1, "ground": false},
+                {"id": 12, "kind": "Boundary", "phase": [0, 1], "qubit": 0, "row": 2, "ground": false}
+            ],
+            "edges": [
+                {"source": 7, "target": 3, "kind": "Regular"},
+                {"source": 3, "target": 12, "kind": "Hadam"}
+            ],
+            "inputs": [7],
+            "outputs": [12],
+            "scalar": {"power2": 0, "phase": [0, 1], "is_zero": false}
+        }"#;
+        let g: VecGraph = serde_json::from_str(src).unwrap();
+        assert_eq!(g.num_vertices(), 3);
+        assert_eq!(g.num_edges(), 2);
+        assert_eq!(g.tcount(), 0);
+        let input = g.inputs().next().unwrap();
+        let z = g.neighbours(input).next().unwrap();
+        assert_eq!(g.vertex(z).unwrap().phase(), Phase::new(1, 2));
+        assert_eq!(
+            g.edge_kind_between(z, g.outputs().next().unwrap()),
+            Some(EdgeKind::Hadam)
+        );
+    }
+
+    #[test]
+    fn invalid_graphs() {
+        let vertex = |id: u64| {
+            format!(
+                r#"{{"id": {}, "kind": "Z", "phase": [0, 1], "qubit": 0, "row": 0, "ground": false}}"#,
+                id
+            )
+        };
+        let graph = |vertices: &[u64], edges: &str, inputs: &str, outputs: &str| {
+            let vertices: Vec<_> = vertices.iter().map(|&id| vertex(id)).collect();
+            format!(
+                r#"{{"vertices": [{}], "edges": [{}], "inputs": {}, "outputs": {},
+                    "scalar": {{"power2": 0, "phase": [0, 1], "is_zero": false}}}}"#,
+                vertices.join(", "),
+                edges,
+                inputs,
+                outputs
+            )
+        };
+        let error = |src: String| {
+            serde_json::from_str::<GGraph>(&src)
+                .unwrap_err()
+                .to_string()
+        };
+        assert!(serde_json::from_str::<GGraph>(&graph(&[0, 1], "", "[0]", "[1]")).is_ok());
+        assert_eq!(
+            error(graph(&[0, 0], "", "[]", "[]")),
+            "duplicate vertex id 0"
+        );
+        assert_eq!(
+            error(graph(
+                &[0, 1],
+                r#"{"source": 0, "target": 2, "kind": "Regular"}"#,
+                "[]",
+                "[]"
+            )),
+            "edge refers to a missing vertex 2"
+        );
+        assert_eq!(
+            error(graph(&[0, 1], "", "[5]", "[]")),
+            "input refers to a missing vertex 5"
+        );
+        assert_eq!(
+            error(graph(&[0, 1], "", "[0, 1, 0]", "[]")),
+            "vertex 0 is listed twice as an input"
+        );
+        assert_eq!(
+            error(graph(&[0, 1], "", "[0]", "[1, 1]")),
+            "vertex 1 is listed twice as an output"
+        );
+        assert!(serde_json::from_str::<Phase>("[1, 0]").is_err());
+    }
+
+    #[test]
+    fn kinds_and_phases() {
+        assert_eq!(serde_json::to_string(&Phase::new(7, 4)).unwrap(), "[7,4]");
+        // Phases are normalized when read.
+        let phase: Phase = serde_json::from_str("[9, 4]").unwrap();
+        assert_eq!(phase, Phase::new(1, 4));
+        for kind in [
+            VertexKind::Z,
+            VertexKind::X,
+            VertexKind::HBox,
+            VertexKind::Boundary,
+        ] {
+            let cbor = serde_cbor::to_vec(&kind).unwrap();
+            assert_eq!(serde_cbor::from_slice::<VertexKind>(&cbor).unwrap(), kind);
+        }
+        for kind in [EdgeKind::Regular, EdgeKind::Hadam] {
+            let json = serde_json::to_string(&kind).unwrap();
+            assert_eq!(serde_json::from_str::<EdgeKind>(&json).unwrap(), kind);
+        }
+    }
+
+    #[test]
+    fn circuits() {
+        let mut c = Circuit::new(3);
+        for gate in [
+            Gate::H(0),
+            Gate::Rz(1, Phase::new(5, 8)),
+            Gate::Cx(0, 2),
+            Gate::CPhase(1, 2, Phase::new(1, 2)),
+            Gate::Ccx(0, 1, 2),
+            Gate::Swap(2, 0),
+        ] {
+            c.add_gate(gate);
+        }
+        let json = serde_json::to_string(&c).unwrap();
+        assert_eq!(serde_json::from_str::<Circuit>(&json).unwrap(), c);
+        let cbor = serde_cbor::to_vec(&c).unwrap();
+        assert_eq!(serde_cbor::from_slice::<Circuit>(&cbor).unwrap(), c);
+
+        let err =
+            serde_json::from_str::<Circuit>(r#"{"num_qubits": 2, "gates": [{"Cx": [0, 2]}]}"#)
+                .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "gate Cx(0, 2) acts outside a circuit of 2 qubits"
+        );
+    }
+}
@@ -0,0 +1,157 @@
+//! Batch diagram construction for importers.
+//!
+//! Importers describe a diagram to a `GraphSink` as a flat list of vertices and edges, and the
+//! sink builds the backend graph in one go. Vertices are identified by the order in which they
+//! were emitted, starting from 0.
+
+use crate::zx::graph::*;
+use std::fmt;
+
+/// Boundary role of an emitted vertex
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct IoFlags {
+    pub input: bool,
+    pub output: bool,
+}
+
+impl IoFlags {
+    pub const NONE: IoFlags = IoFlags {
+        input: false,
+        output: false,
+    };
+    pub const INPUT: IoFlags = IoFlags {
+        input: true,
+        output: false,
+    };
+    pub const OUTPUT: IoFlags = IoFlags {
+        input: false,
+        output: true,
+    };
+}
+
+/// Inconsistency found when finishing a `GraphSink`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SinkError {
+    /// The `edge`-th emitted edge has an endpoint that was never emitted
+    UnknownVertex { edge: usize, vertex: u32 },
+}
+
+impl fmt::Display for SinkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SinkError::UnknownVertex { edge, vertex } => {
+                write!(f, "edge {} refers to unknown vertex {}", edge, vertex)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SinkError {}
+
+/// Receiver of a diagram description.
+///
+/// Calls are not validated as they are made. `finish` checks the whole description at once
+/// and reports every error found.
+pub trait GraphSink {
+    /// The graph produced by the sink
+    type Graph;
+
+    /// Announce the expected number of vertices and edges, so they can be allocated up front.
+    fn begin(&mut self, vertices_hint: usize, edges_hint: usize);
+
+    /// Add a vertex, returning its sequential id.
+    fn vertex(&mut self, kind: VertexKind, phase: Phase, flags: IoFlags) -> u32;
+
+    /// Add an edge between two emitted vertices.
+    fn edge(&mut self, u: u32, v: u32, kind: EdgeKind);
+
+    /// Set the layout position of a vertex, on qubit `q` and row `r`.
+    ///
+    /// Backends without layout information ignore it.
+    fn position(&mut self, _v: u32, _q: f64, _r: f64) {}
+
+    /// Multiply the scalar of the diagram.
+    ///
+    /// Backends without a scalar ignore it.
+    fn scalar(&mut self, _scalar: Scalar) {}
+
+    /// Validate the description and build the graph.
+    fn finish(self) -> Result<Self::Graph, Vec<SinkError>>;
+}
+
+/// Check that every edge endpoint is one of the `num_vertices` emitted vertices.
+pub(crate) fn check_edges(num_vertices: usize, edges: &[(u32, u32, EdgeKind)]) -> Vec<SinkError> {
+    edges
+        .iter()
+        .enumerate()
+        .flat_map(|(edge, &(u, v, _))| {
+            let unknown = |vertex: u32| (vertex as usize >= num_vertices).then_some(vertex);
+            unknown(u)
+                .into_iter()
+                .chain(unknown(v).filter(|&v| v != u))
+                .map(move |vertex| SinkError::UnknownVertex { edge, vertex })
+        })
+        .collect()
+}
+
+/// `GraphSink` building any backend through the `Graph` trait.
+///
+/// Backends with a more efficient bulk path provide their own sink, like
+/// `GGraph::builder`.
+#[derive(Debug, Clone, Default)]
+pub struct Builder<G: Graph> {
+    graph: G,
+    vertices: Vec<G::VertexIx>,
+    edges: Vec<(u32, u32, EdgeKind)>,
+}
+
+impl<G: Graph + Default> Builder<G> {
+    pub fn new() -> Self {
+        Builder {
+            graph: G::default(),
+            vertices: Vec::new(),
+            edges: Vec::new(),
+        }
+    }
+}
+
+impl<G: Graph> GraphSink for Builder<G> {
+    type Graph = G;
+
+    fn begin(&mut self, vertices_hint: usize, edges_hint: usize) {
+        self.vertices.reserve(vertices_hint);
+        self.edges.reserve(edges_hint);
+    }
+
+    fn vertex(&mut self, kind: VertexKind, phase: Phase, flags: IoFlags) -> u32 {
+        let v = self.graph.add_vertex(kind, phase);
+        if flags.input {
+            self.graph.set_input(v, true);
+        }
+        if flags.output {
+            self.graph.set_output(v, true);
+        }
+        self.vertices.push(v);
+        self.vertices.len() as u32 - 1
+    }
+
+    fn edge(&mut self, u: u32, v: u32, kind: EdgeKind) {
+        self.edges.push((u, v, kind));
+    }
+
+    fn scalar(&mut self, scalar: Scalar) {
+        *self.graph.scalar_mut() *= scalar;
+    }
+
+    fn finish(mut self) -> Result<G, Vec<SinkError>> {
+        let errors = check_edges(self.vertices.len(), &self.edges);
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        for &(u, v, kind) in &self.edges {
+            self.graph
+                .add_edge(self.vertices[u as usize], self.vertices[v as usize], kind);
+        }
+        Ok(self.graph)
+    }
+}
@@ -0,0 +1,136 @@
+//! Source positions and error reporting shared by the text format parsers.
+
+use std::fmt;
+
+/// Byte range in a source text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+}
+
+/// Source text with an index of line starts, to translate byte offsets into positions.
+#[derive(Debug, Clone)]
+pub struct SourceMap<'s> {
+    src: &'s str,
+    line_starts: Vec<usize>,
+}
+
+impl<'s> SourceMap<'s> {
+    pub fn new(src: &'s str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(src.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        SourceMap { src, line_starts }
+    }
+
+    pub fn source(&self) -> &'s str {
+        self.src
+    }
+
+    /// 1-based line and column (in characters) of a byte offset
+    pub fn position(&self, offset: usize) -> (usize, usize) {
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(l) => l,
+            Err(l) => l - 1,
+        };
+        let col = self.src[self.line_starts[line]..offset].chars().count() + 1;
+        (line + 1, col)
+    }
+
+    /// Text of a 1-based line, without the line terminator
+    pub fn line_text(&self, line: usize) -> &'s str {
+        let start = self.line_starts[line - 1];
+        let end = self
+            .line_starts
+            .get(line)
+            .copied()
+            .unwrap_or(self.src.len());
+        self.src[start..end].trim_end_matches(&['\n', '\r'][..])
+    }
+
+    /// Build a diagnostic pointing at a span of this source
+    pub fn diagnostic(&self, span: Span, message: impl Into<String>) -> Diagnostic {
+        let (line, col) = self.position(span.start);
+        let line_text = self.line_text(line);
+        // Underline the span, clipped to the end of its first line.
+        let line_end = self.line_starts[line - 1] + line_text.len();
+        let end = span.end.min(line_end).max(span.start);
+        let width = self.src[span.start.min(line_end)..end].chars().count();
+        Diagnostic {
+            message: message.into(),
+            span,
+            line: line as u32,
+            col: col as u32,
+            found: None,
+            expected: Vec::new(),
+            line_text: line_text.into(),
+            width: width.max(1) as u32,
+        }
+    }
+}
+
+/// Parse error with its location, rendered like a compiler diagnostic.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Span,
+    /// 1-based line of the start of the span
+    pub line: u32,
+    /// 1-based column of the start of the span, in characters
+    pub col: u32,
+    /// The offending token, if any
+    pub found: Option<String>,
+    /// Tokens that would have been accepted instead
+    pub expected: Vec<String>,
+    /// Text of the line containing the error, for rendering
+    line_text: Box<str>,
+    /// Width of the underlined span, in characters
+    width: u32,
+}
+
+impl Diagnostic {
+    pub fn with_found(mut self, found: impl Into<String>) -> Self {
+        self.found = Some(found.into());
+        self
+    }
+
+    pub fn with_expected(mut self, expected: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.expected = expected.into_iter().map(Into::into).collect();
+        self
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "error: {}", self.message)?;
+        let gutter = " ".repeat(self.line.to_string().len());
+        writeln!(f, "{}--> {}:{}", gutter, self.line, self.col)?;
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", self.line, self.line_text)?;
+        write!(
+            f,
+            "{} | {}{}",
+            gutter,
+            " ".repeat(self.col as usize - 1),
+            "^".repeat(self.width as usize)
+        )?;
+        if !self.expected.is_empty() {
+            write!(
+                f,
+                "\n{} = expected one of: {}",
+                gutter,
+                self.expected.join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostic {}
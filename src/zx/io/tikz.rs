@@ -0,0 +1,234 @@
+//! TikZ export, in the tikzit format used by pyzx.
+//!
+//! Vertices use the node styles `Z dot`, `X dot`, `H box` and `none` (for boundaries), with the
+//! phase as the node text, and Hadamard edges use the `hadamard edge` style. The styles
+//! themselves are defined in the tikzit style file of the document. Nodes are numbered in the
+//! order of `Graph::vertices`.
+
+use crate::zx::graph::*;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+
+/// Write a diagram as a tikzit picture, laid out in layers by distance from the inputs.
+///
+/// Inputs are placed on the first layer and outputs on the last one, in wire order. Use
+/// `to_tikz_with_layout` to keep the positions of a `CartesianGraph`.
+pub fn to_tikz<G: Graph>(g: &G) -> String {
+    let positions = layered_layout(g);
+    write_tikz(g, |v| positions[&v])
+}
+
+/// Write a diagram as a tikzit picture, placing each vertex at its qubit and row.
+pub fn to_tikz_with_layout<G>(g: &G) -> String
+where
+    G: CartesianGraph,
+    G::Qubit: Into<f64>,
+    G::Row: Into<f64>,
+{
+    write_tikz(g, |v| (g.qubit(v).into(), g.row(v).into()))
+}
+
+/// Qubit and row of each vertex, with the rows given by a breadth-first search from the inputs.
+///
+/// Vertices not reachable from an input start a new search of their own. Vertices on the same
+/// row are stacked in vertex order, and boundaries are placed on their wire index.
+fn layered_layout<G: Graph>(g: &G) -> HashMap<G::VertexIx, (f64, f64)> {
+    let mut layer: HashMap<G::VertexIx, usize> = HashMap::new();
+    let starts = g.inputs().chain(g.vertices());
+    for start in starts {
+        if layer.contains_key(&start) {
+            continue;
+        }
+        let mut queue: VecDeque<_> = if g.is_input(start) {
+            g.inputs().filter(|v| !layer.contains_key(v)).collect()
+        } else {
+            vec![start].into()
+        };
+        for &v in &queue {
+            layer.insert(v, 0);
+        }
+        while let Some(v) = queue.pop_front() {
+            let next = layer[&v] + 1;
+            for n in g.neighbours(v) {
+                if let Entry::Vacant(entry) = layer.entry(n) {
+                    entry.insert(next);
+                    queue.push_back(n);
+                }
+            }
+        }
+    }
+
+    let last = layer
+        .iter()
+        .filter(|&(&v, _)| !g.is_output(v))
+        .map(|(_, &l)| l)
+        .max()
+        .unwrap_or(0)
+        + 1;
+    let mut heights = vec![0; last + 1];
+    let mut positions = HashMap::new();
+    for v in g.vertices() {
+        let row = if g.is_output(v) && !g.is_input(v) {
+            last
+        } else if g.is_input(v) {
+            0
+        } else {
+            layer[&v]
+        };
+        let qubit = if let Some(q) = g.inputs().position(|u| u == v) {
+            q
+        } else if let Some(q) = g.outputs().position(|u| u == v) {
+            q
+        } else {
+            heights[row] += 1;
+            heights[row] - 1
+        };
+        positions.insert(v, (qubit as f64, row as f64));
+    }
+    positions
+}
+
+/// Node text of a phase, empty for zero.
+fn phase_label(phase: Phase) -> String {
+    match (phase.numerator(), phase.denominator()) {
+        (0, _) => String::new(),
+        (1, 1) => "$\\pi$".to_string(),
+        (num, 1) => format!("${}\\pi$", num),
+        (1, den) => format!("$\\frac{{\\pi}}{{{}}}$", den),
+        (num, den) => format!("$\\frac{{{}\\pi}}{{{}}}$", num, den),
+    }
+}
+
+fn write_tikz<G: Graph>(g: &G, position: impl Fn(G::VertexIx) -> (f64, f64)) -> String {
+    let mut ids = HashMap::new();
+    let mut out = String::new();
+    writeln!(out, "\\begin{{tikzpicture}}").unwrap();
+    writeln!(out, "\t\\begin{{pgfonlayer}}{{nodelayer}}").unwrap();
+    for (i, v) in g.vertices().enumerate() {
+        ids.insert(v, i);
+        let vdata = g.vertex(v).unwrap();
+        let (style, label) = match vdata.kind() {
+            VertexKind::Z => ("Z dot", phase_label(vdata.phase())),
+            VertexKind::X => ("X dot", phase_label(vdata.phase())),
+            // The Hadamard box is drawn without its label.
+            VertexKind::HBox if vdata.phase() == Phase::pi() => ("H box", String::new()),
+            VertexKind::HBox => ("H box", phase_label(vdata.phase())),
+            VertexKind::Boundary => ("none", String::new()),
+            VertexKind::Hole => ("hole", String::new()),
+        };
+        let (q, r) = position(v);
+        writeln!(
+            out,
+            "\t\t\\node [style={}] ({}) at ({:.2}, {:.2}) {{{}}};",
+            style,
+            i,
+            r + 0.0,
+            -q + 0.0,
+            label
+        )
+        .unwrap();
+    }
+    writeln!(out, "\t\\end{{pgfonlayer}}").unwrap();
+    writeln!(out, "\t\\begin{{pgfonlayer}}{{edgelayer}}").unwrap();
+    for (s, t, edata) in g.edges_with_data() {
+        let style = match edata.kind() {
+            EdgeKind::Regular => "",
+            EdgeKind::Hadam => " [style=hadamard edge]",
+        };
+        writeln!(out, "\t\t\\draw{} ({}) to ({});", style, ids[&s], ids[&t]).unwrap();
+    }
+    writeln!(out, "\t\\end{{pgfonlayer}}").unwrap();
+    writeln!(out, "\\end{{tikzpicture}}").unwrap();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::simple_graph::GGraph;
+
+    /// `in -- Z(π/4) -H- X(π) -- H-box -- out`, with the H-box placed on qubit 1
+    fn every_kind() -> GGraph {
+        let mut g: GGraph = GGraph::new();
+        let i = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        let z = g.add_vertex(VertexKind::Z, Phase::new(1, 4));
+        let x = g.add_vertex(VertexKind::X, Phase::pi());
+        let h = g.add_vertex(VertexKind::HBox, Phase::pi());
+        let o = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        g.add_edge(i, z, EdgeKind::Regular);
+        g.add_edge(z, x, EdgeKind::Hadam);
+        g.add_edge(x, h, EdgeKind::Regular);
+        g.add_edge(h, o, EdgeKind::Regular);
+        g.set_input(i, true);
+        g.set_output(o, true);
+        for (v, row) in [(i, 0), (z, 1), (x, 2), (h, 2), (o, 3)] {
+            g.set_row(v, row);
+        }
+        g.set_qubit(h, 1);
+        g
+    }
+
+    /// A picture with the given node lines, and the edges of `every_kind`
+    fn picture(nodes: &[&str]) -> String {
+        let mut res = "\\begin{tikzpicture}\n\t\\begin{pgfonlayer}{nodelayer}\n".to_string();
+        for node in nodes {
+            res += &format!("\t\t{}\n", node);
+        }
+        res += "\t\\end{pgfonlayer}\n\t\\begin{pgfonlayer}{edgelayer}\n";
+        res += "\t\t\\draw (1) to (0);\n";
+        res += "\t\t\\draw [style=hadamard edge] (2) to (1);\n";
+        res += "\t\t\\draw (3) to (2);\n";
+        res += "\t\t\\draw (4) to (3);\n";
+        res += "\t\\end{pgfonlayer}\n\\end{tikzpicture}\n";
+        res
+    }
+
+    #[test]
+    fn snapshot() {
+        let expected = picture(&[
+            "\\node [style=none] (0) at (0.00, 0.00) {};",
+            "\\node [style=Z dot] (1) at (1.00, 0.00) {$\\frac{\\pi}{4}$};",
+            "\\node [style=X dot] (2) at (2.00, 0.00) {$\\pi$};",
+            "\\node [style=H box] (3) at (3.00, 0.00) {};",
+            "\\node [style=none] (4) at (4.00, 0.00) {};",
+        ]);
+        assert_eq!(to_tikz(&every_kind()), expected);
+    }
+
+    #[test]
+    fn snapshot_with_layout() {
+        let expected = picture(&[
+            "\\node [style=none] (0) at (0.00, 0.00) {};",
+            "\\node [style=Z dot] (1) at (1.00, 0.00) {$\\frac{\\pi}{4}$};",
+            "\\node [style=X dot] (2) at (2.00, 0.00) {$\\pi$};",
+            "\\node [style=H box] (3) at (2.00, -1.00) {};",
+            "\\node [style=none] (4) at (3.00, 0.00) {};",
+        ]);
+        assert_eq!(to_tikz_with_layout(&every_kind()), expected);
+    }
+
+    #[test]
+    fn labels() {
+        assert_eq!(phase_label(Phase::zero()), "");
+        assert_eq!(phase_label(Phase::new(3, 2)), "$\\frac{3\\pi}{2}$");
+        let mut g: GGraph = GGraph::new();
+        g.add_vertex(VertexKind::HBox, Phase::new(1, 2));
+        assert!(
+            to_tikz(&g).contains("\\node [style=H box] (0) at (0.00, 0.00) {$\\frac{\\pi}{2}$};")
+        );
+    }
+
+    #[test]
+    fn layered_layout_of_disconnected_parts() {
+        let mut g = every_kind();
+        let a = g.add_vertex(VertexKind::Z, Phase::zero());
+        let b = g.add_vertex(VertexKind::Z, Phase::zero());
+        g.add_edge(a, b, EdgeKind::Regular);
+        let positions = layered_layout(&g);
+        // The second part starts its own search from layer 0, stacked under the first part.
+        assert_eq!(positions[&a], (0.0, 0.0));
+        assert_eq!(positions[&b], (1.0, 1.0));
+        assert_eq!(to_tikz(&g), to_tikz(&g.clone()));
+    }
+}
@@ -0,0 +1,251 @@
+//! Coordinates of the vertices of a `CartesianGraph`.
+//!
+//! The simplifications leave the qubit and row of the vertices they keep untouched, so after
+//! many rewrites the rows have gaps and vertices overlap. These functions repair the layout
+//! used by the DOT and TikZ output and by the extraction heuristics. Both keep the inputs on
+//! the first row and the outputs on the last one.
+
+use crate::zx::graph::*;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+
+/// Renumber the rows as consecutive integers from 0, keeping their order. Returns the number
+/// of rows, which is the new depth of the graph.
+pub fn normalize_rows<G: CartesianGraph<Row = i32>>(g: &mut G) -> u32 {
+    let rows: BTreeSet<i32> = g.vertices().map(|v| g.row(v)).collect();
+    let index: HashMap<i32, i32> = rows
+        .iter()
+        .enumerate()
+        .map(|(i, &r)| (r, i as i32))
+        .collect();
+    let vertices: Vec<_> = g.vertices().collect();
+    for v in vertices {
+        let row = index[&g.row(v)];
+        g.set_row(v, row);
+    }
+    rows.len() as u32
+}
+
+/// Assign new coordinates to every vertex, ignoring the current ones but for the order of
+/// the boundaries.
+///
+/// The edges are directed away from the inputs, following the breadth-first distance from
+/// them, and each vertex is placed on the row given by the longest path that reaches it.
+/// Components without inputs start from their first vertex. The inputs go on row 0 and the
+/// outputs on the row after the last vertex, keeping the relative order of their qubits.
+/// Within every other row, the vertices are sorted by the mean qubit of their neighbours on
+/// earlier rows, which avoids most edge crossings, and numbered from qubit 0. No two vertices
+/// end up with the same coordinates.
+pub fn auto_layout<G: CartesianGraph<Qubit = i32, Row = i32>>(g: &mut G) {
+    let inputs: Vec<_> = g.inputs().filter(|&v| g.vertex(v).is_some()).collect();
+    let outputs: Vec<_> = g
+        .outputs()
+        .filter(|&v| g.vertex(v).is_some() && !g.is_input(v))
+        .collect();
+
+    let mut distance = HashMap::new();
+    let mut queue = VecDeque::new();
+    // All the inputs start at distance 0, then each component without inputs from its first
+    // vertex.
+    let mut seeds = vec![inputs.clone()];
+    seeds.extend(g.vertices().map(|v| vec![v]));
+    for seed in seeds {
+        for v in seed {
+            if let Entry::Vacant(entry) = distance.entry(v) {
+                entry.insert(0);
+                queue.push_back(v);
+            }
+        }
+        while let Some(v) = queue.pop_front() {
+            let d = distance[&v];
+            for u in g.neighbours(v) {
+                if let Entry::Vacant(entry) = distance.entry(u) {
+                    entry.insert(d + 1);
+                    queue.push_back(u);
+                }
+            }
+        }
+    }
+
+    // Sorting by distance, with the index as a tie-break, is a topological order of the
+    // directed edges.
+    let mut order: Vec<_> = g.vertices().collect();
+    order.sort_by_key(|&v| (distance[&v], v));
+    let key = |v: G::VertexIx| (distance[&v], v);
+    let mut layer: HashMap<G::VertexIx, i32> = HashMap::new();
+    for &v in &order {
+        let l = *layer.entry(v).or_insert(0);
+        for u in g.neighbours(v) {
+            if key(u) > key(v) {
+                let entry = layer.entry(u).or_insert(0);
+                *entry = (*entry).max(l + 1);
+            }
+        }
+    }
+
+    let mut rows: BTreeMap<i32, Vec<G::VertexIx>> = BTreeMap::new();
+    for &v in &order {
+        if !g.is_input(v) && !outputs.contains(&v) {
+            rows.entry(layer[&v].max(1)).or_default().push(v);
+        }
+    }
+    let last = rows.keys().next_back().map_or(1, |&r| r + 1);
+
+    let mut qubit: HashMap<G::VertexIx, f64> = HashMap::new();
+    place(g, &mut qubit, boundary_order(g, &inputs), 0);
+    for (row, vertices) in rows {
+        let mut sorted: Vec<_> = vertices
+            .into_iter()
+            .map(|v| {
+                // Only the inputs and the vertices on earlier rows are placed so far.
+                let placed: Vec<f64> = g
+                    .neighbours(v)
+                    .filter_map(|u| qubit.get(&u).copied())
+                    .collect();
+                let center = if placed.is_empty() {
+                    f64::INFINITY
+                } else {
+                    placed.iter().sum::<f64>() / placed.len() as f64
+                };
+                (center, v)
+            })
+            .collect();
+        sorted.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+        place(
+            g,
+            &mut qubit,
+            sorted.into_iter().map(|(_, v)| v).collect(),
+            row,
+        );
+    }
+    place(g, &mut qubit, boundary_order(g, &outputs), last);
+}
+
+/// Boundaries sorted by their current qubit, keeping the wire order between equal qubits
+fn boundary_order<G: CartesianGraph<Qubit = i32>>(
+    g: &G,
+    boundary: &[G::VertexIx],
+) -> Vec<G::VertexIx> {
+    let mut sorted = boundary.to_vec();
+    sorted.sort_by_key(|&v| g.qubit(v));
+    sorted
+}
+
+/// Place vertices on a row, numbering their qubits from 0 in order
+fn place<G: CartesianGraph<Qubit = i32, Row = i32>>(
+    g: &mut G,
+    qubit: &mut HashMap<G::VertexIx, f64>,
+    vertices: Vec<G::VertexIx>,
+    row: i32,
+) {
+    for (q, v) in vertices.into_iter().enumerate() {
+        g.set_position(v, q as i32, row);
+        qubit.insert(v, q as f64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::circuit::{Circuit, Gate};
+    use crate::zx::generate::random_clifford_t_circuit;
+    use crate::zx::random::Seed;
+    use crate::zx::simple_graph::GGraph;
+    use crate::zx::simplify::{clifford_simp, fuse_spiders};
+    use std::collections::HashSet;
+
+    fn assert_layout(g: &GGraph) {
+        let positions: HashSet<_> = g.vertices().map(|v| (g.qubit(v), g.row(v))).collect();
+        assert_eq!(positions.len(), g.num_vertices() as usize);
+        let last = g.vertices().map(|v| g.row(v)).max().unwrap();
+        for (i, v) in g.inputs().enumerate() {
+            assert_eq!((g.qubit(v), g.row(v)), (i as i32, 0));
+        }
+        for (i, v) in g.outputs().enumerate() {
+            assert_eq!((g.qubit(v), g.row(v)), (i as i32, last));
+        }
+        for v in g.vertices().filter(|&v| !g.is_input(v) && !g.is_output(v)) {
+            assert!(0 < g.row(v) && g.row(v) < last);
+        }
+    }
+
+    #[test]
+    fn removing_a_row_reduces_the_depth() {
+        let mut c = Circuit::new(2);
+        for gate in [
+            Gate::T(0),
+            Gate::S(1),
+            Gate::Cx(0, 1),
+            Gate::H(0),
+            Gate::T(1),
+        ] {
+            c.add_gate(gate);
+        }
+        let mut g: GGraph = c.to_graph_into(GGraph::builder());
+        let depth = g.depth();
+        // Drop the CNOT, which has a row of its own, keeping the wires connected.
+        let cnot: Vec<_> = g.vertices().filter(|&v| g.vertex_degree(v) == 3).collect();
+        assert_eq!(cnot.len(), 2);
+        let row = g.row(cnot[0]);
+        for v in cnot {
+            let ends: Vec<_> = g.neighbours(v).filter(|&u| g.row(u) != row).collect();
+            g.add_edge(ends[0], ends[1], EdgeKind::Regular);
+            g.remove_vertex(v);
+        }
+        assert_eq!(g.depth(), depth);
+        let before: Vec<_> = g.vertices().map(|v| g.row(v)).collect();
+        assert_eq!(normalize_rows(&mut g), depth - 1);
+        assert_eq!(g.depth(), depth - 1);
+        // The order of the rows is kept.
+        let after: Vec<_> = g.vertices().map(|v| g.row(v)).collect();
+        for i in 0..before.len() {
+            for j in 0..before.len() {
+                assert_eq!(before[i] < before[j], after[i] < after[j]);
+            }
+        }
+        assert_eq!(normalize_rows(&mut g), depth - 1);
+    }
+
+    #[test]
+    fn auto_layout_after_simplification() {
+        for seed in 0..5 {
+            let c = random_clifford_t_circuit(4, 40, 0.2, Seed(seed));
+            let mut g: GGraph = c.to_graph_into(GGraph::builder());
+            clifford_simp(&mut g);
+            auto_layout(&mut g);
+            assert_layout(&g);
+        }
+
+        // Vertices with no input in their component, and an input that is also an output
+        let mut g: GGraph = Circuit::new(2).to_graph_into(GGraph::builder());
+        fuse_spiders(&mut g);
+        let a = g.add_vertex(VertexKind::Z, Phase::zero());
+        let b = g.add_vertex(VertexKind::X, Phase::zero());
+        g.add_edge(a, b, EdgeKind::Regular);
+        let wire = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        g.set_input(wire, true);
+        g.set_output(wire, true);
+        auto_layout(&mut g);
+        let positions: HashSet<_> = g.vertices().map(|v| (g.qubit(v), g.row(v))).collect();
+        assert_eq!(positions.len(), g.num_vertices() as usize);
+        assert_eq!(g.row(wire), 0);
+    }
+
+    #[test]
+    fn auto_layout_keeps_the_qubit_order_of_the_boundaries() {
+        let mut c = Circuit::new(3);
+        c.add_gate(Gate::Swap(0, 2));
+        c.add_gate(Gate::Cz(1, 2));
+        let mut g: GGraph = c.to_graph_into(GGraph::builder());
+        let outputs: Vec<_> = g.outputs().collect();
+        // Reverse the qubits of the outputs.
+        for (q, &v) in outputs.iter().enumerate() {
+            g.set_qubit(v, 2 - q as i32);
+        }
+        auto_layout(&mut g);
+        let qubits: Vec<_> = outputs.iter().map(|&v| g.qubit(v)).collect();
+        assert_eq!(qubits, [2, 1, 0]);
+        let rows: HashSet<_> = outputs.iter().map(|&v| g.row(v)).collect();
+        assert_eq!(rows.len(), 1);
+    }
+}
@@ -1,3 +1,30 @@
 /// Z* diagrams representation
+pub mod analysis;
+pub mod bit_graph;
+pub mod checked_graph;
+pub mod circuit;
+pub mod diff;
+pub mod evaluate;
+pub mod flow;
+pub mod generate;
 pub mod graph;
+pub mod ground;
+pub mod io;
+pub mod layout;
+pub mod normal_form;
+pub mod optimize;
+pub mod pattern;
+pub mod pauli_web;
+pub mod pet_adaptor;
+pub mod random;
+pub mod recorder;
 pub mod simple_graph;
+pub mod simplify;
+pub mod stable_graph;
+pub mod stats;
+pub mod templates;
+pub mod tensor;
+pub mod transform;
+pub mod vec_graph;
+pub mod view;
+pub mod zh;
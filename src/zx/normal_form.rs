@@ -0,0 +1,276 @@
+//! Canonical forms of reduced diagrams.
+//!
+//! Reductions that only differ in the order their rules are applied tend to leave the same
+//! diagram with its phase gadgets numbered differently, so comparing their results would need a
+//! full isomorphism check, see `analysis::is_isomorphic`. `canonicalize_gadgets` renumbers the
+//! vertices so that such diagrams become equal vertex by vertex, and `reduced_eq` compares them.
+
+use crate::zx::graph::*;
+use crate::zx::simplify::{gadget_at, Gadget};
+use std::collections::{HashMap, HashSet};
+
+/// Move the phase of gadget axles with phase pi onto their leaf, as in `simplify::gadgets`.
+///
+/// An axle with phase pi is a NOT on the leaf of an axle without phase, which negates the phase
+/// `a` of the leaf up to a global phase of `a`.
+fn clear_axle_phases<G: Graph>(g: &mut G) {
+    let axles: Vec<_> = g
+        .vertices()
+        .filter(|&v| g.vertex(v).unwrap().phase() == Phase::pi())
+        .collect();
+    for axle in axles {
+        g.set_phase(axle, Phase::zero());
+        match gadget_at(g, axle) {
+            Some(gadget) => {
+                g.set_phase(gadget.leaf, -gadget.phase);
+                g.scalar_mut().add_phase(gadget.phase);
+            }
+            None => g.set_phase(axle, Phase::pi()),
+        }
+    }
+}
+
+/// Phase gadgets whose targets are not themselves part of a gadget
+fn independent_gadgets<G: Graph>(g: &G) -> Vec<Gadget<G::VertexIx>> {
+    let gadgets: Vec<_> = g.vertices().filter_map(|v| gadget_at(g, v)).collect();
+    let parts: HashSet<_> = gadgets
+        .iter()
+        .flat_map(|gadget| [gadget.axle, gadget.leaf])
+        .collect();
+    gadgets
+        .into_iter()
+        .filter(|gadget| gadget.targets.iter().all(|t| !parts.contains(t)))
+        .collect()
+}
+
+/// Renumber the vertices of a diagram so that its phase gadgets come last, in a canonical order.
+///
+/// Axles with phase pi are first cleared, so that every gadget carries its phase on the leaf,
+/// normalized to [0, 2) like all phases. The diagram is then packed into consecutive indices:
+/// the inputs and outputs in wire order, the other neighbours of those in the same order, the
+/// remaining vertices that are not part of a gadget in index order, and finally the axle and
+/// leaf of each gadget. Gadgets are sorted by the packed indices of their targets, then by
+/// phase.
+///
+/// The scalar is kept, and changes only by the global phases of the cleared axles. Vertex
+/// indices are all invalidated.
+pub fn canonicalize_gadgets<G>(g: &mut G)
+where
+    G: Graph + Default,
+    G::VertexData: Clone,
+{
+    clear_axle_phases(g);
+    let gadgets = independent_gadgets(g);
+    let parts: HashSet<_> = gadgets
+        .iter()
+        .flat_map(|gadget| [gadget.axle, gadget.leaf])
+        .collect();
+
+    let mut order = Vec::with_capacity(g.num_vertices() as usize);
+    let mut packed = HashSet::new();
+    let boundaries: Vec<_> = g.inputs().chain(g.outputs()).collect();
+    let mut next_to_boundaries = Vec::new();
+    for &b in &boundaries {
+        next_to_boundaries.extend(g.neighbours(b));
+    }
+    let rest: Vec<_> = g.vertices().collect();
+    for v in boundaries.into_iter().chain(next_to_boundaries).chain(rest) {
+        if !parts.contains(&v) && packed.insert(v) {
+            order.push(v);
+        }
+    }
+    let position: HashMap<_, _> = order.iter().enumerate().map(|(i, &v)| (v, i)).collect();
+    let mut keyed: Vec<_> = gadgets
+        .into_iter()
+        .map(|gadget| {
+            let mut legs: Vec<_> = gadget.targets.iter().map(|t| position[t]).collect();
+            legs.sort_unstable();
+            (legs, gadget.phase, gadget.axle, gadget.leaf)
+        })
+        .collect();
+    keyed.sort_by(|a, b| (&a.0, a.1).partial_cmp(&(&b.0, b.1)).unwrap());
+    for (_, _, axle, leaf) in keyed {
+        order.extend([axle, leaf]);
+    }
+
+    let (mut res, _) = g.induced_subgraph(&order, false);
+    *res.scalar_mut() = *g.scalar();
+    *g = res;
+}
+
+/// A diagram with its vertices numbered by `canonicalize_gadgets`, as plain data
+#[derive(Debug, PartialEq)]
+struct Canonical {
+    vertices: Vec<(VertexKind, Phase, bool)>,
+    edges: Vec<(usize, usize, EdgeKind)>,
+    inputs: Vec<usize>,
+    outputs: Vec<usize>,
+    scalar: Scalar,
+}
+
+impl Canonical {
+    fn new<G>(g: &G) -> Self
+    where
+        G: Graph + Clone + Default,
+        G::VertexData: Clone,
+    {
+        let mut g = g.clone();
+        canonicalize_gadgets(&mut g);
+        let position: HashMap<_, _> = g.vertices().enumerate().map(|(i, v)| (v, i)).collect();
+        let vertices = g
+            .vertices()
+            .map(|v| {
+                let data = g.vertex(v).unwrap();
+                (data.kind(), data.phase(), g.is_ground(v))
+            })
+            .collect();
+        let mut edges: Vec<_> = g
+            .edges()
+            .map(|e| {
+                let (a, b) = g.edge_endpoints(e).unwrap();
+                let (a, b) = (position[&a], position[&b]);
+                (a.min(b), a.max(b), g.edge(e).unwrap().kind())
+            })
+            .collect();
+        edges.sort_unstable_by_key(|&(a, b, kind)| (a, b, kind == EdgeKind::Hadam));
+        Canonical {
+            vertices,
+            edges,
+            inputs: g.inputs().map(|v| position[&v]).collect(),
+            outputs: g.outputs().map(|v| position[&v]).collect(),
+            scalar: *g.scalar(),
+        }
+    }
+}
+
+/// Whether two reduced diagrams are equal once their gadgets are canonicalized.
+///
+/// Both diagrams are packed and canonicalized with `canonicalize_gadgets`, and then compared
+/// exactly: vertex kinds, phases and grounds, edges with their kinds, inputs and outputs, and
+/// scalars. This is much cheaper than `analysis::is_isomorphic`, but only sees through a
+/// different numbering of the phase gadgets and of the vertices away from the boundaries.
+pub fn reduced_eq<G>(a: &G, b: &G) -> bool
+where
+    G: Graph + Clone + Default,
+    G::VertexData: Clone,
+{
+    a.num_vertices() == b.num_vertices()
+        && a.num_edges() == b.num_edges()
+        && Canonical::new(a) == Canonical::new(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::analysis::is_isomorphic;
+    use crate::zx::circuit::Gate;
+    use crate::zx::generate::random_clifford_t_circuit;
+    use crate::zx::random::Seed;
+    use crate::zx::simple_graph::GGraph;
+    use crate::zx::simplify::{full_reduce, full_simp, gadgets};
+    use crate::zx::tensor::assert_graph_eq;
+
+    type V = <GGraph as Graph>::VertexIx;
+
+    /// Wires from inputs to outputs through a Z spider each, and phase gadgets on them in the
+    /// given order, with the given axle phase.
+    fn gadget_diagram(num_qubits: usize, gadgets: &[(&[usize], Phase, Phase)]) -> GGraph {
+        let mut g: GGraph = GGraph::new();
+        let mut inputs = Vec::new();
+        let mut outputs = Vec::new();
+        let mut spiders: Vec<V> = Vec::new();
+        for _ in 0..num_qubits {
+            let i = g.add_vertex(VertexKind::Boundary, Phase::zero());
+            let z = g.add_vertex(VertexKind::Z, Phase::zero());
+            let o = g.add_vertex(VertexKind::Boundary, Phase::zero());
+            g.add_edge(i, z, EdgeKind::Regular);
+            g.add_edge(z, o, EdgeKind::Regular);
+            inputs.push(i);
+            outputs.push(o);
+            spiders.push(z);
+        }
+        g.set_io_order(inputs, outputs);
+        for &(targets, phase, axle_phase) in gadgets {
+            let axle = g.add_vertex(VertexKind::Z, axle_phase);
+            let leaf = g.add_vertex(VertexKind::Z, phase);
+            g.add_edge(axle, leaf, EdgeKind::Hadam);
+            for &t in targets {
+                g.add_edge(axle, spiders[t], EdgeKind::Hadam);
+            }
+        }
+        g
+    }
+
+    #[test]
+    fn gadgets_in_any_order() {
+        let (zero, t) = (Phase::zero(), Phase::new(1, 4));
+        let a = gadget_diagram(
+            3,
+            &[(&[0, 1], t, zero), (&[1, 2], t, zero), (&[0, 2], -t, zero)],
+        );
+        let b = gadget_diagram(
+            3,
+            &[(&[2, 0], -t, zero), (&[1, 0], t, zero), (&[2, 1], t, zero)],
+        );
+        assert!(reduced_eq(&a, &b));
+
+        let mut c = a.clone();
+        canonicalize_gadgets(&mut c);
+        assert_graph_eq(&a, &c);
+        // Boundaries, then the spiders on the wires, then gadgets by targets.
+        let found: Vec<_> = gadgets(&c)
+            .iter()
+            .map(|g| (g.axle.index(), g.leaf.index(), g.phase))
+            .collect();
+        assert_eq!(found, [(9, 10, t), (11, 12, -t), (13, 14, t)]);
+
+        let different = gadget_diagram(
+            3,
+            &[(&[0, 1], t, zero), (&[1, 2], t, zero), (&[0, 2], t, zero)],
+        );
+        assert!(!reduced_eq(&a, &different));
+    }
+
+    #[test]
+    fn axle_phases_move_to_the_leaf() {
+        let (pi, t) = (Phase::pi(), Phase::new(1, 4));
+        let mut g = gadget_diagram(2, &[(&[0, 1], t, pi)]);
+        let original = g.clone();
+        canonicalize_gadgets(&mut g);
+        assert_graph_eq(&original, &g);
+        let found = gadgets(&g);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].phase, Phase::new(7, 4));
+        let mut cleared = gadget_diagram(2, &[(&[0, 1], Phase::new(7, 4), Phase::zero())]);
+        assert!(!reduced_eq(&original, &cleared));
+        cleared.scalar_mut().add_phase(t);
+        assert!(reduced_eq(&original, &cleared));
+    }
+
+    #[test]
+    fn rule_orderings() {
+        for seed in [0, 2, 3, 5] {
+            let c = random_clifford_t_circuit(4, 40, 0.3, Seed(seed));
+            let mut a: GGraph = c.to_graph();
+            let mut b: GGraph = c.to_graph();
+            full_reduce(&mut a);
+            full_simp(&mut b);
+            assert!(is_isomorphic(&a, &b));
+            assert!(reduced_eq(&a, &b));
+
+            // A single T gate turned into its adjoint.
+            let mut d = c.clone();
+            let t = d
+                .gates
+                .iter()
+                .position(|g| matches!(g, Gate::T(_)))
+                .unwrap();
+            if let Gate::T(q) = d.gates[t] {
+                d.gates[t] = Gate::Tdg(q);
+            }
+            let mut d: GGraph = d.to_graph();
+            full_reduce(&mut d);
+            assert!(!reduced_eq(&a, &d));
+        }
+    }
+}
@@ -0,0 +1,413 @@
+//! Phase-polynomial optimization of reduced diagrams.
+//!
+//! After `simplify::full_reduce`, the non-Clifford phases of a diagram sit on Z spiders and on
+//! phase gadgets. Summing over the value `x_v` of each Z spider, a phase `a` on a spider `v`
+//! contributes the factor `e^{i a x_v}`, and a gadget with phase `a` on the targets `T`
+//! contributes `sqrt(2)^(1 - |T|) e^{i a p_T(x)}`, where `p_T` is the parity of the values of
+//! `T`. Together the phases form a phase polynomial: a table of parities over GF(2), one column
+//! per spider, each with a phase. Two tables with the same polynomial denote the same map, so
+//! the terms can be rewritten without looking at the rest of the diagram.
+//!
+//! The spiders and gadgets are only read as a table when that is sound: grounded spiders and
+//! gadgets acting on grounded spiders or on other gadgets are left out.
+
+use crate::linalg::Mat2;
+use crate::zx::graph::*;
+use crate::zx::simplify::{self, Gadget};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::Hash;
+
+/// Terms of the phase polynomial with the same parity
+struct Term<V> {
+    /// Sum of the phases of the terms
+    phase: Phase,
+    /// Gadgets carrying the terms, the phase of a single spider is not listed
+    gadgets: Vec<Gadget<V>>,
+}
+
+impl<V> Term<V> {
+    fn new(phase: Phase) -> Self {
+        Term {
+            phase,
+            gadgets: Vec::new(),
+        }
+    }
+}
+
+/// Phase polynomial of a diagram, with its terms grouped by parity over `vars`
+struct PhaseTable<V> {
+    vars: Vec<V>,
+    terms: BTreeMap<Vec<bool>, Term<V>>,
+}
+
+impl<V: Copy + Eq + Hash> PhaseTable<V> {
+    fn new<G: Graph<VertexIx = V>>(g: &G) -> Self {
+        let all = simplify::gadgets(g);
+        let axles: HashSet<_> = all.iter().map(|gadget| gadget.axle).collect();
+        let gadgets: Vec<_> = all
+            .into_iter()
+            .filter(|gadget| {
+                gadget
+                    .targets
+                    .iter()
+                    .all(|&t| !axles.contains(&t) && !g.is_ground(t))
+            })
+            .collect();
+        let in_gadget: HashSet<_> = gadgets
+            .iter()
+            .flat_map(|gadget| [gadget.axle, gadget.leaf])
+            .collect();
+        let vars: Vec<_> = g
+            .vertices()
+            .filter(|&v| {
+                g.vertex(v).unwrap().kind() == VertexKind::Z
+                    && !g.is_ground(v)
+                    && !in_gadget.contains(&v)
+            })
+            .collect();
+        let column: HashMap<_, _> = vars.iter().enumerate().map(|(i, &v)| (v, i)).collect();
+
+        // One row per phased spider, then one per gadget.
+        let spiders: Vec<_> = vars
+            .iter()
+            .filter(|&&v| !g.vertex(v).unwrap().phase().is_zero())
+            .collect();
+        let mut parities = Mat2::zeros(spiders.len() + gadgets.len(), vars.len());
+        for (r, v) in spiders.iter().enumerate() {
+            parities.set(r, column[v], true);
+        }
+        for (r, gadget) in gadgets.iter().enumerate() {
+            for t in &gadget.targets {
+                parities.set(spiders.len() + r, column[t], true);
+            }
+        }
+
+        let mut terms: BTreeMap<_, Term<V>> = BTreeMap::new();
+        for (r, &&v) in spiders.iter().enumerate() {
+            let phase = g.vertex(v).unwrap().phase();
+            terms.insert(parities.row(r), Term::new(phase));
+        }
+        for (r, gadget) in gadgets.into_iter().enumerate() {
+            let term = terms
+                .entry(parities.row(spiders.len() + r))
+                .or_insert_with(|| Term::new(Phase::zero()));
+            term.phase += gadget.phase;
+            term.gadgets.push(gadget);
+        }
+        PhaseTable { vars, terms }
+    }
+
+    /// Write the merged terms back into the diagram, returning whether a gadget was left with
+    /// a Clifford phase.
+    ///
+    /// A parity over one spider becomes the phase of the spider. Otherwise the first gadget
+    /// of the term keeps the summed phase, or a gadget is added if there is none, and the
+    /// other gadgets are removed. Gadgets with phase 0 or no targets are removed as well.
+    fn apply<G: Graph<VertexIx = V>>(self, g: &mut G) -> bool {
+        let mut clifford = false;
+        let mut removed = Vec::new();
+        for (parity, term) in self.terms {
+            let targets: Vec<_> = self
+                .vars
+                .iter()
+                .zip(&parity)
+                .filter(|&(_, &bit)| bit)
+                .map(|(&v, _)| v)
+                .collect();
+            let k = targets.len() as i32;
+            if k == 1 {
+                g.set_phase(targets[0], term.phase);
+            }
+            let keep = k > 1 && !term.phase.is_zero();
+            for (i, gadget) in term.gadgets.iter().enumerate() {
+                if keep && i == 0 {
+                    g.set_phase(gadget.leaf, term.phase);
+                } else {
+                    removed.extend([gadget.axle, gadget.leaf]);
+                    g.scalar_mut().add_power(1 - k);
+                }
+            }
+            if keep && term.gadgets.is_empty() {
+                let axle = g.add_vertex(VertexKind::Z, Phase::zero());
+                let leaf = g.add_vertex(VertexKind::Z, term.phase);
+                g.add_edge(axle, leaf, EdgeKind::Hadam);
+                for t in targets {
+                    g.add_edge(axle, t, EdgeKind::Hadam);
+                }
+                g.scalar_mut().add_power(k - 1);
+            }
+            clifford |= keep && term.phase.is_clifford();
+        }
+        g.remove_vertices(removed);
+        clifford
+    }
+
+    /// Add pi/4 to every non-zero parity of a 4-dimensional space of parities, if that turns
+    /// more T-like terms into Clifford ones than the other way around. Returns whether the
+    /// table changed.
+    ///
+    /// The phases `pi/4 p(x)` over the 15 non-zero parities `p` of such a space add up to a
+    /// multiple of `2 pi` for every `x`, so the polynomial is unchanged. Any space with more
+    /// than 7 T-like terms is spanned by three of them and one more, so the search goes over
+    /// the triples of T-like terms, and counts the terms in each coset of the space they span
+    /// in a single pass. It takes `O(t^3 m)` row operations for `t` T-like terms out of `m`.
+    fn todd_step(&mut self) -> bool {
+        // Terms turning Clifford weigh 2, the ones turning T-like weigh 0, and the space
+        // improves when the weights of its 15 parities add up to more than 15.
+        fn weight(phase: Phase) -> i32 {
+            if phase.is_proper_t() {
+                2
+            } else if phase.is_clifford() {
+                0
+            } else {
+                1
+            }
+        }
+        let tlike: Vec<_> = self
+            .terms
+            .iter()
+            .filter(|(parity, term)| term.phase.is_proper_t() && parity.contains(&true))
+            .map(|(parity, _)| parity.clone())
+            .collect();
+        // Every non-zero combination of three rows
+        let combinations = Mat2::from_fn(7, 3, |r, c| (r + 1) >> c & 1 == 1);
+        for (i, a) in tlike.iter().enumerate() {
+            for (j, b) in tlike.iter().enumerate().skip(i + 1) {
+                for c in &tlike[j + 1..] {
+                    let mut basis = Mat2::from_rows(&[a.clone(), b.clone(), c.clone()]);
+                    if basis.gauss(true, None) < 3 {
+                        continue;
+                    }
+                    let pivots: Vec<_> = (0..3)
+                        .map(|r| (0..basis.cols()).find(|&col| basis.get(r, col)).unwrap())
+                        .collect();
+                    let mut inside = 0;
+                    let mut cosets: BTreeMap<Vec<bool>, i32> = BTreeMap::new();
+                    for (parity, term) in &self.terms {
+                        // Reduce the parity to the representative of its coset.
+                        let mut rep = parity.clone();
+                        for (r, &pivot) in pivots.iter().enumerate() {
+                            if rep[pivot] {
+                                xor(&mut rep, &basis.row(r));
+                            }
+                        }
+                        if rep.contains(&true) {
+                            *cosets.entry(rep).or_default() += weight(term.phase);
+                        } else if parity.contains(&true) {
+                            inside += weight(term.phase);
+                        }
+                    }
+                    let best = cosets.into_iter().max_by_key(|&(_, w)| w);
+                    if let Some((rep, _)) = best.filter(|&(_, w)| inside + w > 15) {
+                        let span = &combinations * &basis;
+                        let mut space = vec![rep.clone()];
+                        for r in 0..7 {
+                            let row = span.row(r);
+                            let mut shifted = rep.clone();
+                            xor(&mut shifted, &row);
+                            space.extend([row, shifted]);
+                        }
+                        for parity in space {
+                            self.terms
+                                .entry(parity)
+                                .or_insert_with(|| Term::new(Phase::zero()))
+                                .phase += Phase::new(1, 4);
+                        }
+                        return true;
+                    }
+                }
+            }
+        }
+        false
+    }
+}
+
+fn xor(dst: &mut [bool], src: &[bool]) {
+    for (d, s) in dst.iter_mut().zip(src) {
+        *d ^= s;
+    }
+}
+
+fn phase_poly_with<G: Graph>(g: &mut G, todd: bool) -> u32 {
+    let before = g.tcount();
+    let mut table = PhaseTable::new(g);
+    if todd {
+        while table.todd_step() {}
+    }
+    if table.apply(g) {
+        simplify::clifford_simp(g);
+    }
+    before.saturating_sub(g.tcount())
+}
+
+/// Merge the terms of the phase polynomial of a diagram with the same parity.
+///
+/// Gadgets on the same targets are fused, a gadget on a single spider is moved into its phase,
+/// and gadgets whose summed phase is 0 are removed. Gadgets left with a Clifford phase are
+/// pushed back into the rest of the diagram with `simplify::clifford_simp`. Returns the
+/// decrease of the T-count.
+///
+/// The diagram should already be reduced, see `simplify::full_reduce`.
+pub fn phase_poly<G: Graph>(g: &mut G) -> u32 {
+    phase_poly_with(g, false)
+}
+
+/// Like `phase_poly`, but also rewrites the table with a TODD-like pass before writing it
+/// back.
+///
+/// The pass repeatedly adds pi/4 to the 15 non-zero parities of a 4-dimensional space of
+/// parities when that lowers the number of T-like terms. These are the third-order
+/// cancellations missed by merging equal parities. The search is cubic in the number of
+/// T-like terms, so the pass is only practical for small diagrams.
+pub fn phase_poly_todd<G: Graph>(g: &mut G) -> u32 {
+    phase_poly_with(g, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::generate::random_clifford_t_circuit;
+    use crate::zx::random::Seed;
+    use crate::zx::simple_graph::GGraph;
+    use crate::zx::simplify::full_reduce;
+    use crate::zx::tensor::assert_graph_eq;
+
+    type V = <GGraph as Graph>::VertexIx;
+
+    /// `n` wires, each through a Z spider
+    fn wires(n: usize) -> (GGraph, Vec<V>) {
+        let mut g: GGraph = GGraph::new();
+        let spiders = (0..n)
+            .map(|_| {
+                let i = g.add_vertex(VertexKind::Boundary, Phase::zero());
+                let v = g.add_vertex(VertexKind::Z, Phase::zero());
+                let o = g.add_vertex(VertexKind::Boundary, Phase::zero());
+                g.add_edge(i, v, EdgeKind::Regular);
+                g.add_edge(v, o, EdgeKind::Regular);
+                g.set_input(i, true);
+                g.set_output(o, true);
+                v
+            })
+            .collect();
+        (g, spiders)
+    }
+
+    /// Add the term `phase` on the parity of the spiders in `mask`, as the phase of a spider
+    /// for a single one and as a gadget otherwise
+    fn add_term(g: &mut GGraph, vs: &[V], mask: usize, phase: Phase) {
+        let targets: Vec<_> = (0..vs.len())
+            .filter(|&i| mask >> i & 1 == 1)
+            .map(|i| vs[i])
+            .collect();
+        if let [v] = targets[..] {
+            let phase = g.vertex(v).unwrap().phase() + phase;
+            g.set_phase(v, phase);
+            return;
+        }
+        let axle = g.add_vertex(VertexKind::Z, Phase::zero());
+        let leaf = g.add_vertex(VertexKind::Z, phase);
+        g.add_edge(axle, leaf, EdgeKind::Hadam);
+        for t in targets {
+            g.add_edge(axle, t, EdgeKind::Hadam);
+        }
+    }
+
+    #[test]
+    fn duplicate_parities() {
+        let (mut g, vs) = wires(3);
+        let t = Phase::new(1, 4);
+        // Two gadgets on the same parity add up to a Clifford phase, and a gadget on a single
+        // spider moves into its phase.
+        for &(mask, phase) in &[(0b011, t), (0b011, t), (0b110, t), (0b001, t)] {
+            add_term(&mut g, &vs, mask, phase);
+        }
+        let axle = g.add_vertex(VertexKind::Z, Phase::zero());
+        let leaf = g.add_vertex(VertexKind::Z, t);
+        g.add_edge(axle, leaf, EdgeKind::Hadam);
+        g.add_edge(axle, vs[0], EdgeKind::Hadam);
+        let original = g.clone();
+        assert_eq!(g.tcount(), 5);
+        assert_eq!(phase_poly(&mut g), 4);
+        assert_eq!(g.tcount(), 1);
+        assert_eq!(simplify::gadgets(&g).len(), 1);
+        assert_graph_eq(&g, &original);
+        assert_eq!(phase_poly(&mut g), 0);
+    }
+
+    #[test]
+    fn cancelling_gadgets_are_removed() {
+        let (mut g, vs) = wires(3);
+        add_term(&mut g, &vs, 0b111, Phase::new(1, 4));
+        add_term(&mut g, &vs, 0b111, Phase::new(-1, 4));
+        let original = g.clone();
+        assert_eq!(phase_poly(&mut g), 2);
+        assert_eq!(g.num_vertices(), 9);
+        assert_graph_eq(&g, &original);
+    }
+
+    /// Value of a phase polynomial at `x`
+    fn evaluate<V>(table: &PhaseTable<V>, x: usize) -> Phase {
+        table
+            .terms
+            .iter()
+            .filter(|(parity, _)| {
+                parity
+                    .iter()
+                    .enumerate()
+                    .filter(|&(i, &b)| b && x >> i & 1 == 1)
+                    .count()
+                    % 2
+                    == 1
+            })
+            .fold(Phase::zero(), |acc, (_, term)| acc + term.phase)
+    }
+
+    #[test]
+    fn todd_finds_a_third_order_cancellation() {
+        // T on 8 of the 15 parities of 4 spiders. Adding T to all 15 leaves 7 T gates.
+        let (mut g, vs) = wires(4);
+        for mask in 1..=8 {
+            add_term(&mut g, &vs, mask, Phase::new(1, 4));
+        }
+        let mut table = PhaseTable::new(&g);
+        let tlike = |table: &PhaseTable<V>| {
+            table
+                .terms
+                .values()
+                .filter(|term| term.phase.is_proper_t())
+                .count()
+        };
+        assert_eq!(tlike(&table), 8);
+        let values: Vec<_> = (0..16).map(|x| evaluate(&table, x)).collect();
+        assert!(table.todd_step());
+        assert_eq!(tlike(&table), 7);
+        assert!(!table.todd_step());
+        // The diagram is too large for the tensor evaluator, so compare the polynomials.
+        assert_eq!(
+            (0..16).map(|x| evaluate(&table, x)).collect::<Vec<_>>(),
+            values
+        );
+
+        let mut merged = g.clone();
+        assert_eq!(phase_poly(&mut merged), 0);
+        assert_eq!(phase_poly_todd(&mut g), 1);
+        assert_eq!(g.tcount(), 7);
+    }
+
+    #[test]
+    fn random_reduced_circuits() {
+        for seed in 0..8 {
+            let mut g: GGraph = random_clifford_t_circuit(3, 30, 0.4, Seed(seed)).to_graph();
+            full_reduce(&mut g);
+            let reduced = g.clone();
+            let before = g.tcount();
+            let removed = phase_poly(&mut g);
+            assert_eq!(g.tcount() + removed, before);
+            assert_graph_eq(&g, &reduced);
+            let mut h = reduced.clone();
+            phase_poly_todd(&mut h);
+            assert!(h.tcount() <= g.tcount());
+            assert_graph_eq(&h, &reduced);
+        }
+    }
+}
@@ -0,0 +1,524 @@
+//! Measurement patterns of the one-way model, exported from graph-like diagrams with gflow.
+//!
+//! The diagram is read as an open graph as in `flow`: each Z spider is a qubit, and the Hadamard
+//! edges between spiders are the entangling CZ gates. A measured spider with phase `a` is the
+//! outcome-0 effect of a measurement in the XY plane at angle `-a`, and the gflow gives the
+//! corrections making the other outcomes equivalent to it.
+
+use crate::zx::flow::gflow;
+use crate::zx::graph::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Debug};
+
+/// Measurement plane of the Bloch sphere
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Plane {
+    XY,
+    XZ,
+    YZ,
+}
+
+impl fmt::Display for Plane {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Plane::XY => "XY",
+            Plane::XZ => "XZ",
+            Plane::YZ => "YZ",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Command of a measurement pattern, on qubits labelled by `V`.
+///
+/// Domains are sorted sets of measured qubits, and a dependent command acts when the parity of
+/// their outcomes is odd.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command<V> {
+    /// Prepare a qubit in the `|+>` state
+    N(V),
+    /// Entangle two qubits with a CZ gate
+    E(V, V),
+    /// Measure a qubit in a plane at an angle. The angle is negated when the parity of
+    /// `s_domain` is odd, and then shifted by pi when the parity of `t_domain` is odd.
+    M {
+        qubit: V,
+        plane: Plane,
+        angle: Phase,
+        s_domain: Vec<V>,
+        t_domain: Vec<V>,
+    },
+    /// Apply an X correction
+    X { qubit: V, domain: Vec<V> },
+    /// Apply a Z correction
+    Z { qubit: V, domain: Vec<V> },
+}
+
+fn fmt_domain<V: Debug>(f: &mut fmt::Formatter, name: &str, domain: &[V]) -> fmt::Result {
+    if !domain.is_empty() {
+        write!(f, ", {}={:?}", name, domain)?;
+    }
+    Ok(())
+}
+
+/// One command as `N(v)`, `E(v, u)`, `M(v, XY, π/4, s=[..], t=[..])`, `X(v, [..])` or
+/// `Z(v, [..])`, leaving out empty measurement domains.
+impl<V: Debug> fmt::Display for Command<V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Command::N(v) => write!(f, "N({:?})", v),
+            Command::E(v, u) => write!(f, "E({:?}, {:?})", v, u),
+            Command::M {
+                qubit,
+                plane,
+                angle,
+                s_domain,
+                t_domain,
+            } => {
+                write!(f, "M({:?}, {}, {}", qubit, plane, angle)?;
+                fmt_domain(f, "s", s_domain)?;
+                fmt_domain(f, "t", t_domain)?;
+                write!(f, ")")
+            }
+            Command::X { qubit, domain } => write!(f, "X({:?}, {:?})", qubit, domain),
+            Command::Z { qubit, domain } => write!(f, "Z({:?}, {:?})", qubit, domain),
+        }
+    }
+}
+
+/// Measurement pattern: commands run in order on the input qubits and the prepared ones,
+/// leaving the output qubits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern<V> {
+    /// Input qubits, in wire order
+    pub inputs: Vec<V>,
+    /// Output qubits, in wire order
+    pub outputs: Vec<V>,
+    pub commands: Vec<Command<V>>,
+}
+
+/// Toggle the membership of each element of `domain` in `set`, since outcomes add modulo 2
+fn toggle_all<V: Ord + Copy>(set: &mut BTreeSet<V>, domain: &[V]) {
+    for &v in domain {
+        if !set.remove(&v) {
+            set.insert(v);
+        }
+    }
+}
+
+impl<V: Ord + Copy> Pattern<V> {
+    /// Rewrite the pattern into standard form, with the preparations first, then the
+    /// entanglements, the measurements, and the corrections last.
+    ///
+    /// Corrections are moved to the end, adding `Z` corrections when they cross an entanglement
+    /// and becoming domains of the measurements of the qubits they act on: in the XY plane an `X`
+    /// correction negates the angle and a `Z` one shifts it by pi, in the XZ plane `X` does both
+    /// and `Z` negates it, and in the YZ plane `X` shifts it and `Z` negates it. The remaining
+    /// corrections are merged into at most one `X` and one `Z` for each qubit, in this order.
+    pub fn standardize(&mut self) {
+        let mut xs: BTreeMap<V, BTreeSet<V>> = BTreeMap::new();
+        let mut zs: BTreeMap<V, BTreeSet<V>> = BTreeMap::new();
+        let mut prepared = Vec::new();
+        let mut entangled = Vec::new();
+        let mut measured = Vec::new();
+        for command in std::mem::take(&mut self.commands) {
+            match command {
+                Command::N(_) => prepared.push(command),
+                Command::E(v, u) => {
+                    // CZ X_v = X_v Z_u CZ, and Z commutes with CZ.
+                    for (a, b) in [(v, u), (u, v)] {
+                        if let Some(domain) = xs.get(&a) {
+                            let domain: Vec<_> = domain.iter().copied().collect();
+                            toggle_all(zs.entry(b).or_default(), &domain);
+                        }
+                    }
+                    entangled.push(command);
+                }
+                Command::M {
+                    qubit,
+                    plane,
+                    angle,
+                    s_domain,
+                    t_domain,
+                } => {
+                    let mut s: BTreeSet<_> = s_domain.into_iter().collect();
+                    let mut t: BTreeSet<_> = t_domain.into_iter().collect();
+                    let x: Vec<_> = xs.remove(&qubit).unwrap_or_default().into_iter().collect();
+                    let z: Vec<_> = zs.remove(&qubit).unwrap_or_default().into_iter().collect();
+                    match plane {
+                        Plane::XY => {
+                            toggle_all(&mut s, &x);
+                            toggle_all(&mut t, &z);
+                        }
+                        Plane::XZ => {
+                            toggle_all(&mut s, &x);
+                            toggle_all(&mut t, &x);
+                            toggle_all(&mut s, &z);
+                        }
+                        Plane::YZ => {
+                            toggle_all(&mut t, &x);
+                            toggle_all(&mut s, &z);
+                        }
+                    }
+                    measured.push(Command::M {
+                        qubit,
+                        plane,
+                        angle,
+                        s_domain: s.into_iter().collect(),
+                        t_domain: t.into_iter().collect(),
+                    });
+                }
+                Command::X { qubit, domain } => toggle_all(xs.entry(qubit).or_default(), &domain),
+                Command::Z { qubit, domain } => toggle_all(zs.entry(qubit).or_default(), &domain),
+            }
+        }
+        let qubits: BTreeSet<_> = xs.keys().chain(zs.keys()).copied().collect();
+        let mut corrections = Vec::new();
+        for qubit in qubits {
+            let x = xs.remove(&qubit).unwrap_or_default();
+            if !x.is_empty() {
+                let domain = x.into_iter().collect();
+                corrections.push(Command::X { qubit, domain });
+            }
+            let z = zs.remove(&qubit).unwrap_or_default();
+            if !z.is_empty() {
+                let domain = z.into_iter().collect();
+                corrections.push(Command::Z { qubit, domain });
+            }
+        }
+        self.commands = prepared
+            .into_iter()
+            .chain(entangled)
+            .chain(measured)
+            .chain(corrections)
+            .collect();
+    }
+
+    /// Number of measurements
+    pub fn num_measurements(&self) -> usize {
+        self.commands
+            .iter()
+            .filter(|c| matches!(c, Command::M { .. }))
+            .count()
+    }
+}
+
+/// One command per line, in execution order
+impl<V: Debug> fmt::Display for Pattern<V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for command in &self.commands {
+            writeln!(f, "{}", command)?;
+        }
+        Ok(())
+    }
+}
+
+/// Error produced by `to_mbqc_pattern`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatternError<V> {
+    /// A vertex a pattern cannot express: anything but an ungrounded Z spider or a boundary, a
+    /// spider with a self-loop, or with a regular edge or parallel edges to another spider, a
+    /// boundary that is not an input or an output connected to a single spider by a regular
+    /// edge, or an output spider with a phase
+    Unsupported { vertex: V },
+    /// The diagram has no gflow, so the outcomes of the measurements cannot be corrected
+    NoGFlow,
+}
+
+impl<V: Debug> fmt::Display for PatternError<V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PatternError::Unsupported { vertex } => {
+                write!(f, "vertex {:?} cannot be expressed in a pattern", vertex)
+            }
+            PatternError::NoGFlow => write!(f, "the diagram has no gflow"),
+        }
+    }
+}
+
+impl<V: Debug> std::error::Error for PatternError<V> {}
+
+/// The spider behind each boundary in wire order, or the first vertex breaking the form
+/// expected by `to_mbqc_pattern`
+fn boundary_spiders<G: Graph>(
+    g: &G,
+    boundaries: impl Iterator<Item = G::VertexIx>,
+) -> Result<Vec<G::VertexIx>, PatternError<G::VertexIx>> {
+    boundaries
+        .map(
+            |b| match g.neighbours_with_kind(b).collect::<Vec<_>>()[..] {
+                [(s, EdgeKind::Regular)] if g.vertex(s).unwrap().kind() == VertexKind::Z => Ok(s),
+                _ => Err(PatternError::Unsupported { vertex: b }),
+            },
+        )
+        .collect()
+}
+
+/// Export a graph-like diagram as a measurement pattern.
+///
+/// Each Z spider is a qubit, prepared unless it is an input, and entangled with the spiders it
+/// shares a Hadamard edge with. The non-output spiders are measured in the XY plane at the
+/// negated phase, in the order of the layers of `flow::gflow`, each followed by its
+/// corrections: `X` on its correction set and `Z` on the odd neighbourhood of the set, except
+/// the spider itself. See `Pattern::standardize` to move the corrections to the end.
+///
+/// Spiders are connected to the boundaries by regular edges, and output spiders have phase 0,
+/// so that the outputs of the pattern are the outputs of the diagram.
+pub fn to_mbqc_pattern<G: Graph>(g: &G) -> Result<Pattern<G::VertexIx>, PatternError<G::VertexIx>> {
+    let is_spider = |v| g.vertex(v).unwrap().kind() == VertexKind::Z;
+    for v in g.vertices() {
+        let supported = match g.vertex(v).unwrap().kind() {
+            VertexKind::Z => {
+                let mut seen = BTreeSet::new();
+                !g.is_ground(v)
+                    && g.neighbours_with_kind(v).all(|(n, kind)| {
+                        !is_spider(n) || (n != v && kind == EdgeKind::Hadam && seen.insert(n))
+                    })
+            }
+            VertexKind::Boundary => true,
+            _ => false,
+        };
+        if !supported {
+            return Err(PatternError::Unsupported { vertex: v });
+        }
+    }
+    let inputs = boundary_spiders(g, g.inputs())?;
+    let outputs = boundary_spiders(g, g.outputs())?;
+    if let Some(&v) = outputs
+        .iter()
+        .find(|&&v| !g.vertex(v).unwrap().phase().is_zero())
+    {
+        return Err(PatternError::Unsupported { vertex: v });
+    }
+    if let Some(b) = g.vertices().find(|&b| {
+        g.vertex(b).unwrap().kind() == VertexKind::Boundary && !g.is_input(b) && !g.is_output(b)
+    }) {
+        return Err(PatternError::Unsupported { vertex: b });
+    }
+    let flow = gflow(g).ok_or(PatternError::NoGFlow)?;
+
+    let mut spiders: Vec<_> = g.vertices().filter(|&v| is_spider(v)).collect();
+    spiders.sort_unstable();
+    let mut commands: Vec<_> = spiders
+        .iter()
+        .filter(|v| !inputs.contains(v))
+        .map(|&v| Command::N(v))
+        .collect();
+    let mut edges: Vec<_> = g
+        .edges()
+        .map(|e| g.edge_endpoints(e).unwrap())
+        .filter(|&(v, u)| is_spider(v) && is_spider(u))
+        .map(|(v, u)| (v.min(u), v.max(u)))
+        .collect();
+    edges.sort_unstable();
+    commands.extend(edges.into_iter().map(|(v, u)| Command::E(v, u)));
+
+    // The layers count from the outputs, so the measurements start from the last one.
+    let mut order: Vec<_> = flow.correction.keys().copied().collect();
+    order.sort_by_key(|v| (std::cmp::Reverse(flow.layer[v]), *v));
+    for v in order {
+        commands.push(Command::M {
+            qubit: v,
+            plane: Plane::XY,
+            angle: -g.vertex(v).unwrap().phase(),
+            s_domain: Vec::new(),
+            t_domain: Vec::new(),
+        });
+        let set = &flow.correction[&v];
+        let mut odd = BTreeSet::new();
+        for &w in set {
+            let neighbours: Vec<_> = g.neighbours(w).filter(|&n| is_spider(n)).collect();
+            toggle_all(&mut odd, &neighbours);
+        }
+        for &w in set {
+            commands.push(Command::X {
+                qubit: w,
+                domain: vec![v],
+            });
+        }
+        for w in odd.into_iter().filter(|&w| w != v) {
+            commands.push(Command::Z {
+                qubit: w,
+                domain: vec![v],
+            });
+        }
+    }
+    Ok(Pattern {
+        inputs,
+        outputs,
+        commands,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::simple_graph::GGraph;
+
+    type V = <GGraph as Graph>::VertexIx;
+
+    /// Graph-state CNOT: the control spider `c` is an input and an output, the target goes
+    /// through `t -H- m -H- o`, and `c -H- m`. The measured spider `t` has the given phase.
+    /// Returns the diagram and `[c, t, m, o]`.
+    fn cnot(phase: Phase) -> (GGraph, [V; 4]) {
+        let mut g: GGraph = GGraph::new();
+        let spiders = [Phase::zero(), phase, Phase::zero(), Phase::zero()]
+            .map(|p| g.add_vertex(VertexKind::Z, p));
+        let [c, t, m, o] = spiders;
+        for (v, u) in [(t, m), (m, o), (c, m)] {
+            g.add_edge(v, u, EdgeKind::Hadam);
+        }
+        for v in [c, t] {
+            let b = g.add_vertex(VertexKind::Boundary, Phase::zero());
+            g.add_edge(b, v, EdgeKind::Regular);
+            g.set_input(b, true);
+        }
+        for v in [c, o] {
+            let b = g.add_vertex(VertexKind::Boundary, Phase::zero());
+            g.add_edge(v, b, EdgeKind::Regular);
+            g.set_output(b, true);
+        }
+        (g, spiders)
+    }
+
+    fn kinds(p: &Pattern<V>) -> String {
+        p.commands
+            .iter()
+            .map(|c| match c {
+                Command::N(_) => 'N',
+                Command::E(..) => 'E',
+                Command::M { .. } => 'M',
+                Command::X { .. } => 'X',
+                Command::Z { .. } => 'Z',
+            })
+            .collect()
+    }
+
+    #[test]
+    fn cnot_pattern() {
+        let (g, [c, t, m, o]) = cnot(Phase::new(1, 4));
+        let mut p = to_mbqc_pattern(&g).unwrap();
+        assert_eq!(p.inputs, [c, t]);
+        assert_eq!(p.outputs, [c, o]);
+        assert_eq!(kinds(&p), "NNEEEMXZZMX");
+        assert_eq!(p.num_measurements(), 2);
+        assert_eq!(&p.commands[..2], [Command::N(m), Command::N(o)]);
+        // The target is measured first, at the negated phase, and corrected through `m`.
+        assert_eq!(
+            p.commands[5..9],
+            [
+                Command::M {
+                    qubit: t,
+                    plane: Plane::XY,
+                    angle: Phase::new(-1, 4),
+                    s_domain: vec![],
+                    t_domain: vec![],
+                },
+                Command::X {
+                    qubit: m,
+                    domain: vec![t],
+                },
+                Command::Z {
+                    qubit: c,
+                    domain: vec![t],
+                },
+                Command::Z {
+                    qubit: o,
+                    domain: vec![t],
+                },
+            ]
+        );
+        assert_eq!(
+            p.commands[9],
+            Command::M {
+                qubit: m,
+                plane: Plane::XY,
+                angle: Phase::zero(),
+                s_domain: vec![],
+                t_domain: vec![],
+            }
+        );
+
+        p.standardize();
+        assert_eq!(kinds(&p), "NNEEEMMZXZ");
+        // The X correction of `m` became the s-domain of its measurement.
+        match &p.commands[6] {
+            Command::M {
+                qubit, s_domain, ..
+            } => assert_eq!((*qubit, &s_domain[..]), (m, &[t][..])),
+            other => panic!("expected a measurement, found {}", other),
+        }
+        assert_eq!(
+            p.commands[7..],
+            [
+                Command::Z {
+                    qubit: c,
+                    domain: vec![t],
+                },
+                Command::X {
+                    qubit: o,
+                    domain: vec![m],
+                },
+                Command::Z {
+                    qubit: o,
+                    domain: vec![t],
+                },
+            ]
+        );
+        let text = p.to_string();
+        assert_eq!(text.lines().count(), p.commands.len());
+        assert!(text.starts_with(&format!("N({:?})\nN({:?})\nE(", m, o)));
+        assert!(text.contains(&format!("M({:?}, XY, 0, s=[{:?}])", m, t)));
+
+        let once = p.clone();
+        p.standardize();
+        assert_eq!(p, once);
+    }
+
+    #[test]
+    fn unsupported_diagrams() {
+        let (mut g, [c, _, m, _]) = cnot(Phase::zero());
+        let e = g.edge_between(c, m).unwrap();
+        g.set_edge_kind(e, EdgeKind::Regular);
+        assert!(matches!(
+            to_mbqc_pattern(&g),
+            Err(PatternError::Unsupported { vertex }) if vertex == c || vertex == m
+        ));
+
+        let (mut g, [_, _, _, o]) = cnot(Phase::zero());
+        g.set_phase(o, Phase::new(1, 2));
+        assert_eq!(
+            to_mbqc_pattern(&g),
+            Err(PatternError::Unsupported { vertex: o })
+        );
+
+        let (mut g, [_, _, m, _]) = cnot(Phase::zero());
+        g.set_vertex_kind(m, VertexKind::X);
+        assert_eq!(
+            to_mbqc_pattern(&g),
+            Err(PatternError::Unsupported { vertex: m })
+        );
+    }
+
+    #[test]
+    fn no_gflow() {
+        // Two inputs merged into one output
+        let mut g: GGraph = GGraph::new();
+        let vs = [0; 3].map(|_| g.add_vertex(VertexKind::Z, Phase::zero()));
+        g.add_edge(vs[0], vs[2], EdgeKind::Hadam);
+        g.add_edge(vs[1], vs[2], EdgeKind::Hadam);
+        for v in [vs[0], vs[1]] {
+            let b = g.add_vertex(VertexKind::Boundary, Phase::zero());
+            g.add_edge(b, v, EdgeKind::Regular);
+            g.set_input(b, true);
+        }
+        let b = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        g.add_edge(vs[2], b, EdgeKind::Regular);
+        g.set_output(b, true);
+        assert_eq!(to_mbqc_pattern(&g), Err(PatternError::NoGFlow));
+        assert_eq!(
+            PatternError::<V>::NoGFlow.to_string(),
+            "the diagram has no gflow"
+        );
+    }
+}
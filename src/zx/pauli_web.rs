@@ -0,0 +1,466 @@
+//! Pauli webs, the correlation surfaces of a diagram.
+//!
+//! A Pauli web labels each edge-end of a diagram with a Pauli operator, up to a phase, such
+//! that the labels on the legs of every spider are a stabilizer of the spider:
+//!
+//! - on a Z spider the X components are on all the legs or on none, and the Z components are
+//!   on an even number of legs;
+//! - on an X spider, dually, the Z components are on all the legs or on none, and the X
+//!   components are on an even number of legs;
+//! - a spider whose phase is not a multiple of pi only allows the even-parity component.
+//!
+//! The two ends of a regular edge carry the same label, and a Hadamard edge exchanges X and Z
+//! between its ends, so a web stores one label per edge, at its first endpoint as given by
+//! `Graph::edge_endpoints`. The labels at the boundaries then give a stabilizer of the whole
+//! diagram, like `X ⊗ I -> X ⊗ X` for the control of a CNOT. H-boxes are not supported.
+
+use crate::linalg::Mat2;
+use crate::zx::graph::*;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Mul;
+
+/// Single-qubit Pauli operator, up to a phase
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Pauli {
+    #[default]
+    I,
+    X,
+    Y,
+    Z,
+}
+
+impl Pauli {
+    /// The Pauli with the given X and Z components
+    pub fn from_bits(x: bool, z: bool) -> Self {
+        match (x, z) {
+            (false, false) => Pauli::I,
+            (true, false) => Pauli::X,
+            (true, true) => Pauli::Y,
+            (false, true) => Pauli::Z,
+        }
+    }
+
+    /// Whether the Pauli has an X component, that is, it is X or Y
+    pub fn x(self) -> bool {
+        matches!(self, Pauli::X | Pauli::Y)
+    }
+
+    /// Whether the Pauli has a Z component, that is, it is Z or Y
+    pub fn z(self) -> bool {
+        matches!(self, Pauli::Z | Pauli::Y)
+    }
+
+    /// The Pauli conjugated by a Hadamard gate, exchanging X and Z
+    pub fn hadamard(self) -> Self {
+        Pauli::from_bits(self.z(), self.x())
+    }
+}
+
+/// Product of Paulis, dropping the phase
+impl Mul for Pauli {
+    type Output = Pauli;
+
+    fn mul(self, rhs: Pauli) -> Pauli {
+        Pauli::from_bits(self.x() ^ rhs.x(), self.z() ^ rhs.z())
+    }
+}
+
+impl fmt::Display for Pauli {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// Reason a web is not valid or cannot be built
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebError<V> {
+    /// The labels on the legs of a spider are not one of its stabilizers, or no web extends
+    /// the boundary assignment past the spider
+    Inconsistent { vertex: V },
+    /// An assignment is on a vertex that is not an input or output boundary with a single edge
+    NotBoundary { vertex: V },
+    /// A vertex that is not a spider or a boundary has labelled legs
+    Unsupported { vertex: V },
+}
+
+impl<V: fmt::Debug> fmt::Display for WebError<V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WebError::Inconsistent { vertex } => {
+                write!(f, "the web is inconsistent at vertex {:?}", vertex)
+            }
+            WebError::NotBoundary { vertex } => {
+                write!(
+                    f,
+                    "vertex {:?} is not a boundary with a single edge",
+                    vertex
+                )
+            }
+            WebError::Unsupported { vertex } => {
+                write!(f, "webs through vertex {:?} are not supported", vertex)
+            }
+        }
+    }
+}
+
+impl<V: fmt::Debug> std::error::Error for WebError<V> {}
+
+/// Pauli label of every edge-end of a diagram, see the module documentation.
+///
+/// Edges without a label carry the identity.
+pub struct PauliWeb<G: Graph> {
+    /// Label of each edge at its first endpoint
+    labels: BTreeMap<G::EdgeIx, Pauli>,
+}
+
+impl<G: Graph> Clone for PauliWeb<G> {
+    fn clone(&self) -> Self {
+        PauliWeb {
+            labels: self.labels.clone(),
+        }
+    }
+}
+
+impl<G: Graph> fmt::Debug for PauliWeb<G> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PauliWeb")
+            .field("labels", &self.labels)
+            .finish()
+    }
+}
+
+impl<G: Graph> PartialEq for PauliWeb<G> {
+    fn eq(&self, other: &Self) -> bool {
+        self.labels == other.labels
+    }
+}
+
+impl<G: Graph> Eq for PauliWeb<G> {}
+
+impl<G: Graph> Default for PauliWeb<G> {
+    fn default() -> Self {
+        PauliWeb {
+            labels: BTreeMap::new(),
+        }
+    }
+}
+
+impl<G: Graph> PauliWeb<G> {
+    /// The empty web, labelling every edge-end with the identity
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Label of the end of edge `e` at vertex `v`, which must be one of its endpoints
+    pub fn label(&self, g: &G, e: G::EdgeIx, v: G::VertexIx) -> Pauli {
+        let label = self.labels.get(&e).copied().unwrap_or_default();
+        match g.edge_endpoints(e) {
+            Some((first, _)) if first != v && g.edge(e).unwrap().kind() == EdgeKind::Hadam => {
+                label.hadamard()
+            }
+            _ => label,
+        }
+    }
+
+    /// Label the end of edge `e` at vertex `v`, which determines the label of the other end
+    pub fn set_label(&mut self, g: &G, e: G::EdgeIx, v: G::VertexIx, label: Pauli) {
+        let label = match g.edge_endpoints(e) {
+            Some((first, _)) if first != v && g.edge(e).unwrap().kind() == EdgeKind::Hadam => {
+                label.hadamard()
+            }
+            _ => label,
+        };
+        if label == Pauli::I {
+            self.labels.remove(&e);
+        } else {
+            self.labels.insert(e, label);
+        }
+    }
+
+    /// Label of a boundary, at the end of its edge on the boundary side. The identity for a
+    /// boundary without edges.
+    pub fn boundary_label(&self, g: &G, b: G::VertexIx) -> Pauli {
+        g.incident_edges(b)
+            .next()
+            .map_or(Pauli::I, |e| self.label(g, e, b))
+    }
+
+    /// Edges with a label other than the identity, with their label at the first endpoint
+    pub fn edges(&self) -> impl Iterator<Item = (G::EdgeIx, Pauli)> + '_ {
+        self.labels.iter().map(|(&e, &label)| (e, label))
+    }
+
+    /// Check that the labels around every spider are one of its stabilizers. Returns the
+    /// first offending vertex, in the order of `Graph::vertices`.
+    pub fn check(&self, g: &G) -> Result<(), WebError<G::VertexIx>> {
+        let mut ends: BTreeMap<G::VertexIx, Vec<Pauli>> = BTreeMap::new();
+        for e in g.edges() {
+            let (a, b) = g.edge_endpoints(e).unwrap();
+            let label = self.labels.get(&e).copied().unwrap_or_default();
+            let other = match g.edge(e).unwrap().kind() {
+                EdgeKind::Regular => label,
+                EdgeKind::Hadam => label.hadamard(),
+            };
+            ends.entry(a).or_default().push(label);
+            ends.entry(b).or_default().push(other);
+        }
+        for v in g.vertices() {
+            let labels = match ends.get(&v) {
+                Some(labels) => labels,
+                None => continue,
+            };
+            let vdata = g.vertex(v).unwrap();
+            let swap = match vdata.kind() {
+                VertexKind::Boundary => continue,
+                VertexKind::Z => false,
+                VertexKind::X => true,
+                VertexKind::HBox if labels.iter().all(|&l| l == Pauli::I) => continue,
+                VertexKind::HBox | VertexKind::Hole => {
+                    return Err(WebError::Unsupported { vertex: v })
+                }
+            };
+            // Count the legs with the all-or-nothing component and with the even one.
+            let (all, even) = labels.iter().fold((0, 0), |(all, even), &l| {
+                let (a, e) = if swap { (l.z(), l.x()) } else { (l.x(), l.z()) };
+                (all + a as usize, even + e as usize)
+            });
+            let all_ok = all == 0 || (all == labels.len() && vdata.phase().is_pauli());
+            let even_ok = even % 2 == 0;
+            if !all_ok || !even_ok {
+                return Err(WebError::Inconsistent { vertex: v });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Extend labels on some boundaries to a valid web of the whole diagram.
+///
+/// The given boundaries must be inputs or outputs with a single edge, and the last label given
+/// for a boundary is the one used. The other boundaries are free, and get the labels the web
+/// needs. When several webs extend the assignment, the one returned is a basic solution of the
+/// linear system over GF(2) the rules define. If no web does, the error names the first
+/// spider, in the order of `Graph::vertices`, whose rules make the assignment impossible
+/// together with the spiders before it.
+pub fn propagate_from_boundary<G: Graph>(
+    g: &G,
+    assignments: impl IntoIterator<Item = (G::VertexIx, Pauli)>,
+) -> Result<PauliWeb<G>, WebError<G::VertexIx>> {
+    let edges: Vec<_> = g.edges().collect();
+    let index: BTreeMap<_, _> = edges.iter().enumerate().map(|(i, &e)| (e, i)).collect();
+    // Column `2 i` is the X component of the label of edge `i` at its first endpoint, column
+    // `2 i + 1` its Z component, and the last column is the right-hand side.
+    let cols = 2 * edges.len() + 1;
+    let mut ends: BTreeMap<G::VertexIx, Vec<(usize, usize)>> = BTreeMap::new();
+    for (i, &e) in edges.iter().enumerate() {
+        let (a, b) = g.edge_endpoints(e).unwrap();
+        let (x, z) = (2 * i, 2 * i + 1);
+        ends.entry(a).or_default().push((x, z));
+        ends.entry(b)
+            .or_default()
+            .push(match g.edge(e).unwrap().kind() {
+                EdgeKind::Regular => (x, z),
+                EdgeKind::Hadam => (z, x),
+            });
+    }
+
+    let row = |entries: &[usize], rhs: bool| {
+        let mut row = vec![false; cols];
+        for &c in entries {
+            row[c] ^= true;
+        }
+        row[cols - 1] = rhs;
+        row
+    };
+    let assignments: BTreeMap<_, _> = assignments.into_iter().collect();
+    let mut rows = Vec::new();
+    for (b, label) in assignments {
+        let edge = match g.incident_edges(b).collect::<Vec<_>>()[..] {
+            [e] if g.vertex(b).unwrap().kind() == VertexKind::Boundary
+                && (g.is_input(b) || g.is_output(b)) =>
+            {
+                e
+            }
+            _ => return Err(WebError::NotBoundary { vertex: b }),
+        };
+        let (x, z) = ends[&b]
+            .iter()
+            .copied()
+            .find(|&(x, _)| x / 2 == index[&edge])
+            .unwrap();
+        rows.push(row(&[x], label.x()));
+        rows.push(row(&[z], label.z()));
+    }
+    let boundary_rows = rows.len();
+
+    // Rows of each spider, as the end of its range of rows.
+    let mut groups = Vec::new();
+    for v in g.vertices() {
+        let legs = match ends.get(&v) {
+            Some(legs) => legs,
+            None => continue,
+        };
+        let vdata = g.vertex(v).unwrap();
+        let swap = match vdata.kind() {
+            VertexKind::Boundary => continue,
+            VertexKind::Z => false,
+            VertexKind::X => true,
+            VertexKind::HBox | VertexKind::Hole => return Err(WebError::Unsupported { vertex: v }),
+        };
+        let (all, even): (Vec<_>, Vec<_>) = legs
+            .iter()
+            .map(|&(x, z)| if swap { (z, x) } else { (x, z) })
+            .unzip();
+        rows.push(row(&even, false));
+        if vdata.phase().is_pauli() {
+            for pair in all.windows(2) {
+                rows.push(row(pair, false));
+            }
+        } else {
+            for &c in &all {
+                rows.push(row(&[c], false));
+            }
+        }
+        groups.push((v, rows.len()));
+    }
+
+    let consistent = |end: usize| {
+        let augmented = Mat2::from_rows(&rows[..end]);
+        let coefficients = Mat2::from_fn(end, cols - 1, |r, c| augmented.get(r, c));
+        coefficients.rank() == augmented.rank()
+    };
+    if !consistent(rows.len()) {
+        // Adding rows only removes solutions, so search for the first spider that leaves none.
+        let (mut lo, mut hi) = (0, groups.len() - 1);
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if consistent(groups[mid].1) {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        return Err(WebError::Inconsistent {
+            vertex: groups[lo].0,
+        });
+    }
+    debug_assert!(consistent(boundary_rows));
+
+    let mut m = Mat2::from_rows(&rows);
+    let rank = m.gauss(true, None);
+    let mut bits = vec![false; cols - 1];
+    for r in 0..rank {
+        // The system is consistent, so no pivot is on the right-hand side.
+        let pivot = (0..cols - 1).find(|&c| m.get(r, c)).unwrap();
+        bits[pivot] = m.get(r, cols - 1);
+    }
+    let mut web = PauliWeb::new();
+    for (i, &e) in edges.iter().enumerate() {
+        let label = Pauli::from_bits(bits[2 * i], bits[2 * i + 1]);
+        if label != Pauli::I {
+            web.labels.insert(e, label);
+        }
+    }
+    Ok(web)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::simple_graph::GGraph;
+    use crate::zx::templates;
+
+    /// Labels of the outputs of the web extending the given labels of the inputs
+    fn outputs(g: &GGraph, inputs: [Pauli; 2]) -> Vec<Pauli> {
+        let web = propagate_from_boundary(g, g.inputs().zip(inputs)).unwrap();
+        assert_eq!(web.check(g), Ok(()));
+        for (b, label) in g.inputs().zip(inputs) {
+            assert_eq!(web.boundary_label(g, b), label);
+        }
+        g.outputs().map(|b| web.boundary_label(g, b)).collect()
+    }
+
+    #[test]
+    fn cnot_correlation_surfaces() {
+        use Pauli::*;
+        let g = templates::cnot();
+        // X spreads from the control to the target, and Z from the target to the control.
+        assert_eq!(outputs(&g, [X, I]), [X, X]);
+        assert_eq!(outputs(&g, [I, Z]), [Z, Z]);
+        assert_eq!(outputs(&g, [Z, I]), [Z, I]);
+        assert_eq!(outputs(&g, [I, X]), [I, X]);
+        assert_eq!(outputs(&g, [Y, Y]), [X, Z]);
+        assert_eq!(outputs(&g, [I, I]), [I, I]);
+    }
+
+    #[test]
+    fn hadamard_edges_exchange_labels() {
+        use Pauli::*;
+        // The CNOT after a colour change of the target, whose edges all become Hadamard edges
+        let mut g = templates::cnot();
+        let target = g
+            .vertices()
+            .find(|&v| g.vertex(v).unwrap().kind == VertexKind::X)
+            .unwrap();
+        g.set_vertex_kind(target, VertexKind::Z);
+        for e in g.incident_edges(target).collect::<Vec<_>>() {
+            g.set_edge_kind(e, EdgeKind::Hadam);
+        }
+        assert_eq!(outputs(&g, [X, I]), [X, X]);
+        assert_eq!(outputs(&g, [I, Z]), [Z, Z]);
+        assert_eq!(outputs(&g, [I, X]), [I, X]);
+        let web = propagate_from_boundary(&g, g.inputs().zip([I, X])).unwrap();
+        // The X of the target wire is a Z on the wire legs of the Z spider.
+        for e in g.incident_edges(target) {
+            let (a, b) = g.edge_endpoints(e).unwrap();
+            let on_wire = g.is_input(a) || g.is_output(b) || g.is_input(b) || g.is_output(a);
+            assert_eq!(web.label(&g, e, target), if on_wire { Z } else { I });
+        }
+    }
+
+    #[test]
+    fn inconsistent_assignments() {
+        use Pauli::*;
+        // A T gate only lets Z through.
+        let mut g: GGraph = GGraph::new();
+        let i = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        let t = g.add_vertex(VertexKind::Z, Phase::new(1, 4));
+        let o = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        let ei = g.add_edge(i, t, EdgeKind::Regular).unwrap();
+        let eo = g.add_edge(t, o, EdgeKind::Regular).unwrap();
+        g.set_input(i, true);
+        g.set_output(o, true);
+        let web = propagate_from_boundary(&g, [(i, Z)]).unwrap();
+        assert_eq!(web.boundary_label(&g, o), Z);
+        assert_eq!(
+            propagate_from_boundary(&g, [(i, X)]),
+            Err(WebError::Inconsistent { vertex: t })
+        );
+        assert_eq!(
+            propagate_from_boundary(&g, [(i, Z), (o, I)]),
+            Err(WebError::Inconsistent { vertex: t })
+        );
+        assert_eq!(
+            propagate_from_boundary(&g, [(t, Z)]),
+            Err(WebError::NotBoundary { vertex: t })
+        );
+
+        // Labels set by hand are checked by `check`.
+        let mut web = PauliWeb::new();
+        web.set_label(&g, ei, i, Z);
+        assert_eq!(web.check(&g), Err(WebError::Inconsistent { vertex: t }));
+        web.set_label(&g, eo, o, Z);
+        assert_eq!(web.check(&g), Ok(()));
+        assert_eq!(web.edges().count(), 2);
+        web.set_label(&g, ei, i, I);
+        web.set_label(&g, eo, t, I);
+        assert_eq!(web, PauliWeb::new());
+
+        g.set_vertex_kind(t, VertexKind::HBox);
+        assert_eq!(
+            propagate_from_boundary(&g, [(i, Z)]),
+            Err(WebError::Unsupported { vertex: t })
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use crate::zx::graph::*;
+use crate::zx::simple_graph::GGraph;
+use petgraph::visit::{
+    GraphBase, GraphRef, IntoNeighbors, IntoNodeIdentifiers, NodeCount, Visitable,
+};
+use std::collections::HashSet;
+
+/// Read-only view of any `Graph` implementing the petgraph visit traits,
+/// so petgraph's traversals and algorithms can run on a diagram without copying it.
+///
+/// Edges are treated as undirected and their kinds are ignored.
+pub struct PetAdaptor<'a, G> {
+    g: &'a G,
+}
+
+const _: fn() = assert_send_sync::<PetAdaptor<GGraph>>;
+
+impl<'a, G> PetAdaptor<'a, G> {
+    pub fn new(g: &'a G) -> Self {
+        PetAdaptor { g }
+    }
+
+    /// The wrapped graph
+    pub fn graph(&self) -> &'a G {
+        self.g
+    }
+}
+
+impl<G> Clone for PetAdaptor<'_, G> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<G> Copy for PetAdaptor<'_, G> {}
+
+impl<'a, G: Graph> GraphBase for PetAdaptor<'a, G> {
+    type EdgeId = G::EdgeIx;
+    type NodeId = G::VertexIx;
+}
+
+impl<'a, G: Graph> GraphRef for PetAdaptor<'a, G> {}
+
+impl<'a, G: Graph> IntoNeighbors for PetAdaptor<'a, G> {
+    type Neighbors = G::NeighbourIterator<'a>;
+
+    fn neighbors(self, a: Self::NodeId) -> Self::Neighbors {
+        self.g.neighbours(a)
+    }
+}
+
+impl<'a, G: Graph> IntoNodeIdentifiers for PetAdaptor<'a, G> {
+    type NodeIdentifiers = G::VertexIterator<'a>;
+
+    fn node_identifiers(self) -> Self::NodeIdentifiers {
+        self.g.vertices()
+    }
+}
+
+impl<'a, G: Graph> NodeCount for PetAdaptor<'a, G> {
+    fn node_count(&self) -> usize {
+        self.g.num_vertices() as usize
+    }
+}
+
+impl<'a, G: Graph> Visitable for PetAdaptor<'a, G> {
+    type Map = HashSet<G::VertexIx>;
+
+    fn visit_map(&self) -> Self::Map {
+        HashSet::with_capacity(self.g.num_vertices() as usize)
+    }
+
+    fn reset_map(&self, map: &mut Self::Map) {
+        map.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::circuit::{Circuit, Gate};
+    use petgraph::algo::{dominators, has_path_connecting, is_bipartite_undirected};
+    use petgraph::visit::{Bfs, NodeFiltered};
+
+    #[test]
+    fn traversals() {
+        let g: GGraph =
+            Circuit::from_gates(3, &[Gate::H(0), Gate::Cx(0, 1), Gate::T(1), Gate::Cz(1, 2)])
+                .to_graph();
+        let pet = PetAdaptor::new(&g);
+        let input = g.inputs().next().unwrap();
+        let mut bfs = Bfs::new(pet, input);
+        let mut reached = 0;
+        while bfs.next(pet).is_some() {
+            reached += 1;
+        }
+        assert_eq!(reached, g.num_vertices());
+        assert!(has_path_connecting(
+            pet,
+            input,
+            g.outputs().last().unwrap(),
+            None
+        ));
+        assert!(is_bipartite_undirected(pet, input));
+
+        let mut triangle: GGraph = GGraph::new();
+        let vs: Vec<_> = (0..3)
+            .map(|_| triangle.add_vertex(VertexKind::Z, Phase::zero()))
+            .collect();
+        for (a, b) in [(0, 1), (1, 2), (2, 0)] {
+            triangle.add_edge(vs[a], vs[b], EdgeKind::Hadam);
+        }
+        assert!(!is_bipartite_undirected(PetAdaptor::new(&triangle), vs[0]));
+    }
+
+    /// Petgraph 0.5 has no flow or matching algorithms, but its dominators give the vertex cuts
+    /// of size one between a vertex and the others.
+    #[test]
+    fn dominators_are_vertex_cuts() {
+        let g: GGraph =
+            Circuit::from_gates(2, &[Gate::T(0), Gate::Cx(0, 1), Gate::T(1)]).to_graph();
+        let pet = PetAdaptor::new(&g);
+        let (input, target) = (g.inputs().next().unwrap(), g.outputs().last().unwrap());
+        let doms = dominators::simple_fast(pet, input);
+        let cuts: Vec<_> = doms.strict_dominators(target).unwrap().collect();
+        // The output is reached through the T, the CNOT and the T on the other qubit.
+        assert_eq!(cuts.len(), 5);
+        assert_eq!(cuts.last(), Some(&input));
+        for v in g.vertices().filter(|&v| v != input && v != target) {
+            let without = NodeFiltered::from_fn(pet, |u| u != v);
+            let connected = has_path_connecting(&without, input, target, None);
+            assert_eq!(connected, !cuts.contains(&v), "{:?}", v);
+        }
+    }
+}
@@ -0,0 +1,36 @@
+//! Reproducible randomness.
+//!
+//! Every randomized function in the crate takes an explicit `Seed` and draws from the `Rng`
+//! it produces, never from a thread-local or OS-seeded generator, so results are identical
+//! across runs and platforms.
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+
+/// Random number generator used by all the randomized components
+pub type Rng = ChaCha12Rng;
+
+/// Seed for a randomized computation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Seed(pub u64);
+
+impl Seed {
+    /// A fresh generator for this seed
+    pub fn rng(self) -> Rng {
+        Rng::seed_from_u64(self.0)
+    }
+}
+
+impl From<u64> for Seed {
+    fn from(seed: u64) -> Self {
+        Seed(seed)
+    }
+}
+
+/// Result of a randomized computation, together with the seed that produced it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Seeded<T> {
+    pub seed: Seed,
+    pub value: T,
+}
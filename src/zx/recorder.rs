@@ -0,0 +1,936 @@
+//! Recording of the mutations of a graph, so they can be undone.
+//!
+//! `GraphRecorder` wraps a backend and logs every mutation as a `GraphOp` holding what is needed
+//! to revert it. The vertices and edges of the recorder have stable indices that are never
+//! reused, so the log keeps pointing at the right elements even when the backend renumbers them
+//! on removal (as `GGraph` does) or gives them new indices when a removal is undone.
+//!
+//! Only the primitive mutations of `Graph` are recorded; the provided methods (`add_edges`,
+//! `remove_vertices`, `add_edge_smart`, ...) go through them, so any rewrite applied to a
+//! recorder can be undone.
+
+use crate::zx::graph::*;
+use std::collections::BTreeMap;
+
+/// Stable vertex index of a `GraphRecorder`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RecordedVertex(pub u32);
+
+/// Stable edge index of a `GraphRecorder`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RecordedEdge(pub u32);
+
+/// Position in the log of a `GraphRecorder`, see `GraphRecorder::undo_to`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Checkpoint(usize);
+
+/// A recorded mutation, with the state it overwrote
+pub enum GraphOp<G: Graph> {
+    AddVertex {
+        vertex: RecordedVertex,
+        kind: VertexKind,
+        phase: Phase,
+    },
+    /// Removal of a vertex without edges, which is neither an input nor an output. Removing a
+    /// vertex first records the removal of its edges and boundary flags.
+    RemoveVertex {
+        vertex: RecordedVertex,
+        data: G::VertexData,
+    },
+    AddEdge {
+        edge: RecordedEdge,
+        endpoints: (RecordedVertex, RecordedVertex),
+        kind: EdgeKind,
+    },
+    RemoveEdge {
+        edge: RecordedEdge,
+        endpoints: (RecordedVertex, RecordedVertex),
+        data: G::EdgeData,
+    },
+    SetPhase {
+        vertex: RecordedVertex,
+        old: Phase,
+        new: Phase,
+    },
+    SetVertexKind {
+        vertex: RecordedVertex,
+        old: VertexKind,
+        new: VertexKind,
+    },
+    SetEdgeKind {
+        edge: RecordedEdge,
+        old: EdgeKind,
+        new: EdgeKind,
+    },
+    /// Access to the data of a vertex through `vertex_mut`, which may have changed it
+    VertexData {
+        vertex: RecordedVertex,
+        old: G::VertexData,
+    },
+    /// Access to the data of an edge through `edge_mut`, which may have changed it
+    EdgeData {
+        edge: RecordedEdge,
+        old: G::EdgeData,
+    },
+    /// Access to the scalar through `scalar_mut`, which may have changed it
+    Scalar { old: Scalar },
+    /// Change of the inputs, with the inputs before it in wire order
+    Inputs { old: Vec<RecordedVertex> },
+    /// Change of the outputs, with the outputs before it in wire order
+    Outputs { old: Vec<RecordedVertex> },
+    /// `adjoint`, `compose` or `tensor`, which act on the whole graph, with the state before it
+    Bulk { old: Box<Snapshot<G>> },
+}
+
+/// State of a `GraphRecorder` before a bulk operation
+pub struct Snapshot<G: Graph> {
+    state: State<G>,
+}
+
+/// Backend and index maps of a `GraphRecorder`
+#[derive(Clone)]
+struct State<G: Graph> {
+    g: G,
+    /// Backend index of each recorded vertex, `None` once removed
+    vertices: Vec<Option<G::VertexIx>>,
+    vertex_ids: BTreeMap<G::VertexIx, RecordedVertex>,
+    /// Backend index of each recorded edge, `None` once removed
+    edges: Vec<Option<G::EdgeIx>>,
+    edge_ids: BTreeMap<G::EdgeIx, RecordedEdge>,
+}
+
+impl<G: Graph> State<G> {
+    fn new(g: G) -> Self {
+        let mut state = State {
+            g,
+            vertices: Vec::new(),
+            vertex_ids: BTreeMap::new(),
+            edges: Vec::new(),
+            edge_ids: BTreeMap::new(),
+        };
+        state.register_new();
+        state
+    }
+
+    fn raw_vertex(&self, v: RecordedVertex) -> G::VertexIx {
+        self.vertices
+            .get(v.0 as usize)
+            .copied()
+            .flatten()
+            .unwrap_or_else(|| panic!("{:?} is not in the graph", v))
+    }
+
+    fn raw_edge(&self, e: RecordedEdge) -> G::EdgeIx {
+        self.edges
+            .get(e.0 as usize)
+            .copied()
+            .flatten()
+            .unwrap_or_else(|| panic!("{:?} is not in the graph", e))
+    }
+
+    fn vertex_id(&self, v: G::VertexIx) -> RecordedVertex {
+        self.vertex_ids[&v]
+    }
+
+    fn register_vertex(&mut self, v: G::VertexIx) -> RecordedVertex {
+        let id = RecordedVertex(self.vertices.len() as u32);
+        self.vertices.push(Some(v));
+        self.vertex_ids.insert(v, id);
+        id
+    }
+
+    fn register_edge(&mut self, e: G::EdgeIx) -> RecordedEdge {
+        let id = RecordedEdge(self.edges.len() as u32);
+        self.edges.push(Some(e));
+        self.edge_ids.insert(e, id);
+        id
+    }
+
+    /// Give new indices to the vertices and edges of the backend that have none, in backend
+    /// order.
+    fn register_new(&mut self) {
+        let vertices: Vec<_> = self.g.vertices().collect();
+        for v in vertices {
+            if !self.vertex_ids.contains_key(&v) {
+                self.register_vertex(v);
+            }
+        }
+        let edges: Vec<_> = self.g.edges().collect();
+        for e in edges {
+            if !self.edge_ids.contains_key(&e) {
+                self.register_edge(e);
+            }
+        }
+    }
+
+    /// Remove a vertex from the backend, following the vertex the backend moves into the freed
+    /// slot, if any.
+    fn remove_vertex(&mut self, v: RecordedVertex) {
+        let raw = self.raw_vertex(v);
+        self.vertices[v.0 as usize] = None;
+        self.vertex_ids.remove(&raw);
+        self.g.remove_vertex(raw);
+        if self.g.vertex(raw).is_none() {
+            return;
+        }
+        // Backends only renumber vertices with higher indices, see `Graph::remove_vertex`.
+        let moved = self
+            .vertex_ids
+            .range(raw..)
+            .rev()
+            .map(|(&w, &id)| (w, id))
+            .find(|&(w, _)| self.g.vertex(w).is_none());
+        if let Some((w, id)) = moved {
+            self.vertex_ids.remove(&w);
+            self.vertex_ids.insert(raw, id);
+            self.vertices[id.0 as usize] = Some(raw);
+        }
+    }
+
+    /// Remove an edge from the backend, following the edge the backend moves into the freed
+    /// slot, if any.
+    fn remove_edge(&mut self, e: RecordedEdge) {
+        let raw = self.raw_edge(e);
+        self.edges[e.0 as usize] = None;
+        self.edge_ids.remove(&raw);
+        self.g.remove_edge(raw);
+        if self.g.edge(raw).is_none() {
+            return;
+        }
+        let moved = self
+            .edge_ids
+            .range(raw..)
+            .rev()
+            .map(|(&f, &id)| (f, id))
+            .find(|&(f, _)| self.g.edge(f).is_none());
+        if let Some((f, id)) = moved {
+            self.edge_ids.remove(&f);
+            self.edge_ids.insert(raw, id);
+            self.edges[id.0 as usize] = Some(raw);
+        }
+    }
+
+    fn inputs(&self) -> Vec<RecordedVertex> {
+        self.g.inputs().map(|v| self.vertex_id(v)).collect()
+    }
+
+    fn outputs(&self) -> Vec<RecordedVertex> {
+        self.g.outputs().map(|v| self.vertex_id(v)).collect()
+    }
+
+    /// Replace the inputs, keeping the given wire order.
+    fn set_inputs(&mut self, inputs: &[RecordedVertex]) {
+        let current: Vec<_> = self.g.inputs().collect();
+        for v in current {
+            self.g.set_input(v, false);
+        }
+        for &v in inputs {
+            let raw = self.raw_vertex(v);
+            self.g.set_input(raw, true);
+        }
+    }
+
+    /// Replace the outputs, keeping the given wire order.
+    fn set_outputs(&mut self, outputs: &[RecordedVertex]) {
+        let current: Vec<_> = self.g.outputs().collect();
+        for v in current {
+            self.g.set_output(v, false);
+        }
+        for &v in outputs {
+            let raw = self.raw_vertex(v);
+            self.g.set_output(raw, true);
+        }
+    }
+}
+
+/// Wrapper over any backend that records its mutations, so they can be undone.
+///
+/// Vertices and edges are addressed by `RecordedVertex` and `RecordedEdge` indices, given in
+/// order of creation and never reused. Removing a vertex records the removal of its edges and
+/// its input and output flags first, so each step can be reverted on its own. `adjoint`,
+/// `compose` and `tensor` record a copy of the whole state; `compose` may renumber the backend,
+/// so it gives new indices to every vertex and edge.
+pub struct GraphRecorder<G: Graph> {
+    state: State<G>,
+    ops: Vec<GraphOp<G>>,
+}
+
+impl<G: Graph + Clone> GraphRecorder<G>
+where
+    G::VertexData: Clone,
+    G::EdgeData: Clone,
+{
+    /// Wrap a graph, with an empty log. The existing vertices and edges are indexed in backend
+    /// order.
+    pub fn new(g: G) -> Self {
+        GraphRecorder {
+            state: State::new(g),
+            ops: Vec::new(),
+        }
+    }
+
+    /// The wrapped graph
+    pub fn inner(&self) -> &G {
+        &self.state.g
+    }
+
+    /// Unwrap the graph, dropping the log
+    pub fn into_inner(self) -> G {
+        self.state.g
+    }
+
+    /// The recorded operations, oldest first
+    pub fn ops(&self) -> &[GraphOp<G>] {
+        &self.ops
+    }
+
+    /// Extract the log, oldest first
+    pub fn into_ops(self) -> Vec<GraphOp<G>> {
+        self.ops
+    }
+
+    /// The current position in the log
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(self.ops.len())
+    }
+
+    /// Revert the operations recorded after a checkpoint, newest first, and drop them from the
+    /// log. Panics if the log is shorter than the checkpoint, because it was already undone
+    /// past it.
+    pub fn undo_to(&mut self, checkpoint: Checkpoint) {
+        assert!(
+            checkpoint.0 <= self.ops.len(),
+            "the log was already undone past the checkpoint"
+        );
+        while self.ops.len() > checkpoint.0 {
+            let op = self.ops.pop().unwrap();
+            self.revert(op);
+        }
+    }
+
+    fn revert(&mut self, op: GraphOp<G>) {
+        let s = &mut self.state;
+        match op {
+            GraphOp::AddVertex { vertex, .. } => s.remove_vertex(vertex),
+            GraphOp::RemoveVertex { vertex, data } => {
+                let raw = s.g.add_vertex(data.kind(), data.phase());
+                *s.g.vertex_mut(raw).unwrap() = data;
+                s.vertices[vertex.0 as usize] = Some(raw);
+                s.vertex_ids.insert(raw, vertex);
+            }
+            GraphOp::AddEdge { edge, .. } => s.remove_edge(edge),
+            GraphOp::RemoveEdge {
+                edge,
+                endpoints: (v, u),
+                data,
+            } => {
+                let (v, u) = (s.raw_vertex(v), s.raw_vertex(u));
+                let raw =
+                    s.g.add_edge(v, u, data.kind())
+                        .expect("the removed edge had nothing to merge with");
+                *s.g.edge_mut(raw).unwrap() = data;
+                s.edges[edge.0 as usize] = Some(raw);
+                s.edge_ids.insert(raw, edge);
+            }
+            GraphOp::SetPhase { vertex, old, .. } => {
+                let raw = s.raw_vertex(vertex);
+                s.g.set_phase(raw, old);
+            }
+            GraphOp::SetVertexKind { vertex, old, .. } => {
+                let raw = s.raw_vertex(vertex);
+                s.g.set_vertex_kind(raw, old);
+            }
+            GraphOp::SetEdgeKind { edge, old, .. } => {
+                let raw = s.raw_edge(edge);
+                s.g.set_edge_kind(raw, old);
+            }
+            GraphOp::VertexData { vertex, old } => {
+                let raw = s.raw_vertex(vertex);
+                *s.g.vertex_mut(raw).unwrap() = old;
+            }
+            GraphOp::EdgeData { edge, old } => {
+                let raw = s.raw_edge(edge);
+                *s.g.edge_mut(raw).unwrap() = old;
+            }
+            GraphOp::Scalar { old } => *s.g.scalar_mut() = old,
+            GraphOp::Inputs { old } => s.set_inputs(&old),
+            GraphOp::Outputs { old } => s.set_outputs(&old),
+            GraphOp::Bulk { old } => self.state = old.state,
+        }
+    }
+
+    fn record_bulk(&mut self) {
+        let old = Box::new(Snapshot {
+            state: self.state.clone(),
+        });
+        self.ops.push(GraphOp::Bulk { old });
+    }
+
+    /// Insert an edge in the wrapped graph and log it
+    fn record_edge(&mut self, v: RecordedVertex, u: RecordedVertex, ty: EdgeKind) -> RecordedEdge {
+        let raw = self
+            .state
+            .g
+            .add_edge(self.state.raw_vertex(v), self.state.raw_vertex(u), ty)
+            .expect("merged edges are inserted only where there is no edge to merge with");
+        let edge = self.state.register_edge(raw);
+        self.ops.push(GraphOp::AddEdge {
+            edge,
+            endpoints: (v, u),
+            kind: ty,
+        });
+        edge
+    }
+}
+
+/// Iterator mapping backend indices to recorded ones
+pub struct RecordedIter<'a, I, K, T> {
+    iter: I,
+    ids: &'a BTreeMap<K, T>,
+}
+
+impl<I, K, T> Iterator for RecordedIter<'_, I, K, T>
+where
+    I: Iterator<Item = K>,
+    K: Ord,
+    T: Copy,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.iter.next().map(|k| self.ids[&k])
+    }
+}
+
+/// Iterator mapping the endpoints of edges with data to recorded indices
+pub struct RecordedEdgeIter<'a, I, V> {
+    iter: I,
+    ids: &'a BTreeMap<V, RecordedVertex>,
+}
+
+impl<'a, I, V, D> Iterator for RecordedEdgeIter<'a, I, V>
+where
+    I: Iterator<Item = (V, V, &'a D)>,
+    V: Ord,
+    D: 'a,
+{
+    type Item = (RecordedVertex, RecordedVertex, &'a D);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (a, b, data) = self.iter.next()?;
+        Some((self.ids[&a], self.ids[&b], data))
+    }
+}
+
+/// Iterator mapping incident edges and their neighbours to recorded indices
+pub struct RecordedIncidentIter<'a, I, V, E> {
+    iter: I,
+    vertex_ids: &'a BTreeMap<V, RecordedVertex>,
+    edge_ids: &'a BTreeMap<E, RecordedEdge>,
+}
+
+impl<I, V, E> Iterator for RecordedIncidentIter<'_, I, V, E>
+where
+    I: Iterator<Item = (E, V)>,
+    V: Ord,
+    E: Ord,
+{
+    type Item = (RecordedEdge, RecordedVertex);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (e, v) = self.iter.next()?;
+        Some((self.edge_ids[&e], self.vertex_ids[&v]))
+    }
+}
+
+impl<G: Graph + Clone> Graph for GraphRecorder<G>
+where
+    G::VertexData: Clone + MaybeSync,
+    G::EdgeData: Clone + MaybeSync,
+{
+    type VertexIx = RecordedVertex;
+    type EdgeIx = RecordedEdge;
+    type VertexData = G::VertexData;
+    type EdgeData = G::EdgeData;
+    type EdgeIterator<'a>
+        = RecordedIter<'a, G::EdgeIterator<'a>, G::EdgeIx, RecordedEdge>
+    where
+        Self: 'a;
+    type VertexIterator<'a>
+        = RecordedIter<'a, G::VertexIterator<'a>, G::VertexIx, RecordedVertex>
+    where
+        Self: 'a;
+    type NeighbourIterator<'a>
+        = RecordedIter<'a, G::NeighbourIterator<'a>, G::VertexIx, RecordedVertex>
+    where
+        Self: 'a;
+    type IncidentEdgeIterator<'a>
+        = RecordedIter<'a, G::IncidentEdgeIterator<'a>, G::EdgeIx, RecordedEdge>
+    where
+        Self: 'a;
+    type EdgeDataIterator<'a>
+        = RecordedEdgeIter<'a, G::EdgeDataIterator<'a>, G::VertexIx>
+    where
+        Self: 'a;
+    type IncidentNeighbourIterator<'a>
+        = RecordedIncidentIter<'a, G::IncidentNeighbourIterator<'a>, G::VertexIx, G::EdgeIx>
+    where
+        Self: 'a;
+    type NeighbourKindIterator<'a>
+        = NeighboursWithKind<'a, Self>
+    where
+        Self: 'a;
+    type InputIterator<'a>
+        = RecordedIter<'a, G::InputIterator<'a>, G::VertexIx, RecordedVertex>
+    where
+        Self: 'a;
+    type OutputIterator<'a>
+        = RecordedIter<'a, G::OutputIterator<'a>, G::VertexIx, RecordedVertex>
+    where
+        Self: 'a;
+
+    const BACKEND: &'static str = G::BACKEND;
+
+    fn set_input(&mut self, v: Self::VertexIx, flag: bool) {
+        if self.is_input(v) == flag {
+            return;
+        }
+        let old = self.state.inputs();
+        self.ops.push(GraphOp::Inputs { old });
+        let raw = self.state.raw_vertex(v);
+        self.state.g.set_input(raw, flag)
+    }
+
+    fn set_output(&mut self, v: Self::VertexIx, flag: bool) {
+        if self.is_output(v) == flag {
+            return;
+        }
+        let old = self.state.outputs();
+        self.ops.push(GraphOp::Outputs { old });
+        let raw = self.state.raw_vertex(v);
+        self.state.g.set_output(raw, flag)
+    }
+
+    fn is_input(&self, v: Self::VertexIx) -> bool {
+        self.state.g.is_input(self.state.raw_vertex(v))
+    }
+
+    fn is_output(&self, v: Self::VertexIx) -> bool {
+        self.state.g.is_output(self.state.raw_vertex(v))
+    }
+
+    fn inputs(&self) -> Self::InputIterator<'_> {
+        RecordedIter {
+            iter: self.state.g.inputs(),
+            ids: &self.state.vertex_ids,
+        }
+    }
+
+    fn outputs(&self) -> Self::OutputIterator<'_> {
+        RecordedIter {
+            iter: self.state.g.outputs(),
+            ids: &self.state.vertex_ids,
+        }
+    }
+
+    /// Replace the inputs and the outputs, recording a single step for each.
+    fn set_io_order(&mut self, inputs: Vec<Self::VertexIx>, outputs: Vec<Self::VertexIx>) {
+        let old = self.state.inputs();
+        self.ops.push(GraphOp::Inputs { old });
+        self.state.set_inputs(&inputs);
+        let old = self.state.outputs();
+        self.ops.push(GraphOp::Outputs { old });
+        self.state.set_outputs(&outputs);
+    }
+
+    fn num_vertices(&self) -> u32 {
+        self.state.g.num_vertices()
+    }
+
+    fn num_edges(&self) -> u32 {
+        self.state.g.num_edges()
+    }
+
+    fn vertices(&self) -> Self::VertexIterator<'_> {
+        RecordedIter {
+            iter: self.state.g.vertices(),
+            ids: &self.state.vertex_ids,
+        }
+    }
+
+    fn edges(&self) -> Self::EdgeIterator<'_> {
+        RecordedIter {
+            iter: self.state.g.edges(),
+            ids: &self.state.edge_ids,
+        }
+    }
+
+    fn edges_with_data(&self) -> Self::EdgeDataIterator<'_> {
+        RecordedEdgeIter {
+            iter: self.state.g.edges_with_data(),
+            ids: &self.state.vertex_ids,
+        }
+    }
+
+    fn vertex(&self, v: Self::VertexIx) -> Option<&Self::VertexData> {
+        let raw = self.state.vertices.get(v.0 as usize).copied().flatten()?;
+        self.state.g.vertex(raw)
+    }
+
+    fn vertex_mut(&mut self, v: Self::VertexIx) -> Option<&mut Self::VertexData> {
+        let raw = self.state.vertices.get(v.0 as usize).copied().flatten()?;
+        let old = self.state.g.vertex(raw)?.clone();
+        self.ops.push(GraphOp::VertexData { vertex: v, old });
+        self.state.g.vertex_mut(raw)
+    }
+
+    fn edge(&self, e: Self::EdgeIx) -> Option<&Self::EdgeData> {
+        let raw = self.state.edges.get(e.0 as usize).copied().flatten()?;
+        self.state.g.edge(raw)
+    }
+
+    fn edge_mut(&mut self, e: Self::EdgeIx) -> Option<&mut Self::EdgeData> {
+        let raw = self.state.edges.get(e.0 as usize).copied().flatten()?;
+        let old = self.state.g.edge(raw)?.clone();
+        self.ops.push(GraphOp::EdgeData { edge: e, old });
+        self.state.g.edge_mut(raw)
+    }
+
+    fn scalar(&self) -> &Scalar {
+        self.state.g.scalar()
+    }
+
+    fn scalar_mut(&mut self) -> &mut Scalar {
+        let old = *self.state.g.scalar();
+        self.ops.push(GraphOp::Scalar { old });
+        self.state.g.scalar_mut()
+    }
+
+    fn set_phase(&mut self, v: Self::VertexIx, phase: Phase) {
+        let raw = self.state.raw_vertex(v);
+        let old = self.state.g.vertex(raw).unwrap().phase();
+        self.ops.push(GraphOp::SetPhase {
+            vertex: v,
+            old,
+            new: phase,
+        });
+        self.state.g.set_phase(raw, phase);
+    }
+
+    fn set_vertex_kind(&mut self, v: Self::VertexIx, kind: VertexKind) {
+        let raw = self.state.raw_vertex(v);
+        let old = self.state.g.vertex(raw).unwrap().kind();
+        self.ops.push(GraphOp::SetVertexKind {
+            vertex: v,
+            old,
+            new: kind,
+        });
+        self.state.g.set_vertex_kind(raw, kind);
+    }
+
+    fn set_edge_kind(&mut self, e: Self::EdgeIx, kind: EdgeKind) {
+        let raw = self.state.raw_edge(e);
+        let old = self.state.g.edge(raw).unwrap().kind();
+        self.ops.push(GraphOp::SetEdgeKind {
+            edge: e,
+            old,
+            new: kind,
+        });
+        self.state.g.set_edge_kind(raw, kind);
+    }
+
+    fn edge_endpoints(&self, e: Self::EdgeIx) -> Option<(Self::VertexIx, Self::VertexIx)> {
+        let raw = self.state.edges.get(e.0 as usize).copied().flatten()?;
+        let (v, u) = self.state.g.edge_endpoints(raw)?;
+        Some((self.state.vertex_id(v), self.state.vertex_id(u)))
+    }
+
+    fn vertex_degree(&self, v: Self::VertexIx) -> u32 {
+        self.state.g.vertex_degree(self.state.raw_vertex(v))
+    }
+
+    fn neighbours(&self, v: Self::VertexIx) -> Self::NeighbourIterator<'_> {
+        RecordedIter {
+            iter: self.state.g.neighbours(self.state.raw_vertex(v)),
+            ids: &self.state.vertex_ids,
+        }
+    }
+
+    fn incident_edges(&self, v: Self::VertexIx) -> Self::IncidentEdgeIterator<'_> {
+        RecordedIter {
+            iter: self.state.g.incident_edges(self.state.raw_vertex(v)),
+            ids: &self.state.edge_ids,
+        }
+    }
+
+    fn incident_edges_with_neighbours(
+        &self,
+        v: Self::VertexIx,
+    ) -> Self::IncidentNeighbourIterator<'_> {
+        RecordedIncidentIter {
+            iter: self
+                .state
+                .g
+                .incident_edges_with_neighbours(self.state.raw_vertex(v)),
+            vertex_ids: &self.state.vertex_ids,
+            edge_ids: &self.state.edge_ids,
+        }
+    }
+
+    fn neighbours_with_kind(&self, v: Self::VertexIx) -> Self::NeighbourKindIterator<'_> {
+        NeighboursWithKind::new(self, v)
+    }
+
+    fn connected(&self, v: Self::VertexIx, u: Self::VertexIx) -> bool {
+        let s = &self.state;
+        s.g.connected(s.raw_vertex(v), s.raw_vertex(u))
+    }
+
+    fn edge_between(&self, v: Self::VertexIx, u: Self::VertexIx) -> Option<Self::EdgeIx> {
+        let s = &self.state;
+        let e = s.g.edge_between(s.raw_vertex(v), s.raw_vertex(u))?;
+        Some(s.edge_ids[&e])
+    }
+
+    fn is_ground(&self, v: Self::VertexIx) -> bool {
+        self.state.g.is_ground(self.state.raw_vertex(v))
+    }
+
+    fn adjoint(&mut self) {
+        self.record_bulk();
+        self.state.g.adjoint()
+    }
+
+    /// Composition may remove and renumber vertices of the backend, so every vertex and edge
+    /// gets a new index.
+    fn compose(&mut self, other: Self) -> Result<(), ComposeError> {
+        let old = self.state.clone();
+        self.state.g.compose(other.state.g)?;
+        self.ops.push(GraphOp::Bulk {
+            old: Box::new(Snapshot { state: old }),
+        });
+        let s = &mut self.state;
+        s.vertices.iter_mut().for_each(|v| *v = None);
+        s.edges.iter_mut().for_each(|e| *e = None);
+        s.vertex_ids.clear();
+        s.edge_ids.clear();
+        s.register_new();
+        Ok(())
+    }
+
+    fn tensor(&mut self, other: Self) {
+        self.record_bulk();
+        self.state.g.tensor(other.state.g);
+        self.state.register_new();
+    }
+
+    fn add_vertices(&mut self, count: u32) -> Vec<Self::VertexIx> {
+        let raw = self.state.g.add_vertices(count);
+        raw.into_iter()
+            .map(|v| {
+                let vdata = self.state.g.vertex(v).unwrap();
+                let (kind, phase) = (vdata.kind(), vdata.phase());
+                let vertex = self.state.register_vertex(v);
+                self.ops.push(GraphOp::AddVertex {
+                    vertex,
+                    kind,
+                    phase,
+                });
+                vertex
+            })
+            .collect()
+    }
+
+    fn add_vertex(&mut self, kind: VertexKind, phase: Phase) -> Self::VertexIx {
+        let raw = self.state.g.add_vertex(kind, phase);
+        let vertex = self.state.register_vertex(raw);
+        self.ops.push(GraphOp::AddVertex {
+            vertex,
+            kind,
+            phase,
+        });
+        vertex
+    }
+
+    fn mode(&self) -> GraphMode {
+        self.state.g.mode()
+    }
+
+    /// Add an edge. In `GraphMode::Simple` the merge is done here, so each of its steps is
+    /// logged.
+    fn add_edge(
+        &mut self,
+        v: Self::VertexIx,
+        u: Self::VertexIx,
+        ty: EdgeKind,
+    ) -> Option<Self::EdgeIx> {
+        match self.mode() {
+            GraphMode::Simple => {
+                merge_edge(self, v, u, ty, |g, v, u, ty| Some(g.record_edge(v, u, ty)))
+            }
+            GraphMode::Multi => Some(self.record_edge(v, u, ty)),
+        }
+    }
+
+    fn remove_vertex(&mut self, v: Self::VertexIx) {
+        self.set_input(v, false);
+        self.set_output(v, false);
+        let edges: Vec<_> = self.incident_edges(v).collect();
+        for e in edges {
+            self.remove_edge(e);
+        }
+        let data = self.vertex(v).unwrap().clone();
+        self.ops.push(GraphOp::RemoveVertex { vertex: v, data });
+        self.state.remove_vertex(v);
+    }
+
+    fn remove_edge(&mut self, e: Self::EdgeIx) {
+        let endpoints = self.edge_endpoints(e).unwrap();
+        let data = self.edge(e).unwrap().clone();
+        self.ops.push(GraphOp::RemoveEdge {
+            edge: e,
+            endpoints,
+            data,
+        });
+        self.state.remove_edge(e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::analysis::is_isomorphic;
+    use crate::zx::generate::random_clifford_t_circuit;
+    use crate::zx::random::Seed;
+    use crate::zx::simple_graph::GGraph;
+    use crate::zx::simplify::{clifford_simp, fuse_spiders};
+    use crate::zx::stable_graph::StableGGraph;
+    use crate::zx::templates;
+    use crate::zx::tensor::assert_graph_eq;
+    use crate::zx::vec_graph::VecGraph;
+
+    fn check_fusion_undo<G>()
+    where
+        G: Graph + Clone + Default,
+        G::VertexData: Clone + MaybeSync,
+        G::EdgeData: Clone + MaybeSync,
+    {
+        let original: G = random_clifford_t_circuit(3, 30, 0.3, Seed(5)).to_graph();
+        let mut rec = GraphRecorder::new(original.clone());
+        let start = rec.checkpoint();
+        assert!(fuse_spiders(&mut rec) > 0);
+        assert!(rec.num_vertices() < original.num_vertices());
+        assert_graph_eq(&rec, &original);
+        assert!(!rec.ops().is_empty());
+        rec.undo_to(start);
+        assert!(rec.ops().is_empty());
+        assert!(is_isomorphic(rec.inner(), &original));
+        assert_eq!(rec.scalar(), original.scalar());
+        assert!(rec.inputs().eq((0..3).map(RecordedVertex)));
+    }
+
+    #[test]
+    fn undo_fusion_on_every_backend() {
+        check_fusion_undo::<GGraph>();
+        check_fusion_undo::<StableGGraph>();
+        check_fusion_undo::<VecGraph>();
+    }
+
+    #[test]
+    fn nested_checkpoints() {
+        let original = templates::toffoli();
+        let mut rec = GraphRecorder::new(original.clone());
+        let start = rec.checkpoint();
+        fuse_spiders(&mut rec);
+        let fused = rec.inner().clone();
+        let middle = rec.checkpoint();
+        clifford_simp(&mut rec);
+        assert_graph_eq(&rec, &original);
+        rec.undo_to(middle);
+        assert!(is_isomorphic(rec.inner(), &fused));
+        // The log can grow again after an undo.
+        clifford_simp(&mut rec);
+        rec.undo_to(start);
+        assert!(is_isomorphic(rec.inner(), &original));
+        assert_eq!(rec.scalar(), original.scalar());
+    }
+
+    #[test]
+    fn recorded_operations() {
+        let g: GGraph = GGraph::new();
+        let mut rec = GraphRecorder::new(g);
+        let a = rec.add_vertex(VertexKind::Z, Phase::zero());
+        let b = rec.add_vertex(VertexKind::X, Phase::zero());
+        let e = rec.add_edge(a, b, EdgeKind::Regular).unwrap();
+        rec.set_phase(a, Phase::new(1, 4));
+        rec.set_edge_kind(e, EdgeKind::Hadam);
+        rec.set_input(a, true);
+        // Removing a vertex removes its edges and boundary flags first.
+        rec.remove_vertex(a);
+        assert_eq!(
+            (a, b, e),
+            (RecordedVertex(0), RecordedVertex(1), RecordedEdge(0))
+        );
+        let c = rec.add_vertex(VertexKind::Z, Phase::zero());
+        assert_eq!(c, RecordedVertex(2));
+
+        let ops = rec.into_ops();
+        let names: Vec<_> = ops
+            .iter()
+            .map(|op| match op {
+                GraphOp::AddVertex { .. } => "add vertex",
+                GraphOp::RemoveVertex { .. } => "remove vertex",
+                GraphOp::AddEdge { .. } => "add edge",
+                GraphOp::RemoveEdge { .. } => "remove edge",
+                GraphOp::SetPhase { .. } => "set phase",
+                GraphOp::SetEdgeKind { .. } => "set edge kind",
+                GraphOp::Inputs { .. } => "inputs",
+                _ => "other",
+            })
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "add vertex",
+                "add vertex",
+                "add edge",
+                "set phase",
+                "set edge kind",
+                "inputs",
+                "inputs",
+                "remove edge",
+                "remove vertex",
+                "add vertex",
+            ]
+        );
+        match &ops[3] {
+            GraphOp::SetPhase { vertex, old, new } => {
+                assert_eq!((*vertex, *old, *new), (a, Phase::zero(), Phase::new(1, 4)))
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn undo_bulk_operations() {
+        let original = templates::cnot();
+        let mut rec = GraphRecorder::new(original.clone());
+        let start = rec.checkpoint();
+        rec.adjoint();
+        rec.compose(GraphRecorder::new(templates::cnot())).unwrap();
+        rec.tensor(GraphRecorder::new(templates::bell_pair()));
+        assert_eq!(rec.inputs().count(), 2);
+        rec.undo_to(start);
+        assert!(is_isomorphic(rec.inner(), &original));
+    }
+
+    #[test]
+    #[should_panic(expected = "undone past the checkpoint")]
+    fn undo_past_a_checkpoint() {
+        let mut rec = GraphRecorder::new(templates::cnot());
+        rec.add_vertex(VertexKind::Z, Phase::zero());
+        let later = rec.checkpoint();
+        rec.undo_to(Checkpoint(0));
+        rec.undo_to(later);
+    }
+}
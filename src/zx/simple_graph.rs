@@ -1,11 +1,30 @@
 use crate::zx::graph::*;
-use petgraph;
-use std::collections::{hash_set, HashSet};
+use crate::zx::io::sink::{check_edges, GraphSink, IoFlags, SinkError};
+use crate::zx::stats::TrackedStats;
+use indexmap::{set, IndexSet};
+use petgraph::graph::{EdgeIndex, IndexType, NodeIndex};
+use petgraph::visit::EdgeRef;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fmt;
+use std::iter::Copied;
 
-#[derive(Debug, PartialEq, Eq, Default)]
-struct VertexData {
-    phase: Phase,
-    kind: VertexKind,
+/// Data of a vertex, shared by the graph backends of the crate.
+///
+/// The fields are public so that graphs can be built directly with petgraph, see
+/// `GGraph::from_petgraph`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct VertexData {
+    pub phase: Phase,
+    pub kind: VertexKind,
+    /// Qubit of the vertex, see `CartesianGraph`
+    pub qubit: i32,
+    /// Row of the vertex, see `CartesianGraph`
+    pub row: i32,
+    /// Whether the vertex is grounded, see `GroundGraph`
+    pub ground: bool,
+    /// Interface of a vertex of kind `VertexKind::Hole`, see `HoleData`
+    pub hole: Option<Box<Hole>>,
 }
 
 impl VData for VertexData {
@@ -26,9 +45,30 @@ impl VData for VertexData {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Default)]
-struct EdgeData {
-    kind: EdgeKind,
+impl HoleData for VertexData {
+    fn hole(&self) -> Option<&Hole> {
+        self.hole.as_deref()
+    }
+
+    fn set_hole(&mut self, hole: Option<Hole>) {
+        self.hole = hole.map(Box::new);
+    }
+}
+
+impl GroundData for VertexData {
+    fn ground(&self) -> bool {
+        self.ground
+    }
+
+    fn ground_mut(&mut self) -> &mut bool {
+        &mut self.ground
+    }
+}
+
+/// Data of an edge, shared by the graph backends of the crate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct EdgeData {
+    pub kind: EdgeKind,
 }
 
 impl EData for EdgeData {
@@ -41,58 +81,376 @@ impl EData for EdgeData {
     }
 }
 
-struct GGraph<Ix = u32> {
+/// Error produced by `GGraph::from_petgraph` when the inputs or outputs are not valid
+/// boundaries of the petgraph graph
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportError {
+    /// A boundary is not a node of the graph
+    UnknownVertex { index: usize },
+    /// A node is listed twice among the inputs, or twice among the outputs
+    DuplicateBoundary { index: usize },
+    /// A boundary is not a vertex of kind `VertexKind::Boundary`
+    NotBoundary { index: usize, kind: VertexKind },
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportError::UnknownVertex { index } => {
+                write!(f, "boundary {} is not a node of the graph", index)
+            }
+            ImportError::DuplicateBoundary { index } => {
+                write!(f, "boundary {} is listed twice", index)
+            }
+            ImportError::NotBoundary { index, kind } => {
+                write!(f, "boundary {} has kind {:?}", index, kind)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+/// Open graph backed by a `petgraph::Graph`.
+///
+/// Inputs and outputs are kept in the order they were marked, which defines the wire order.
+/// Each vertex has an integer qubit and row, see `CartesianGraph`. New vertices are placed at
+/// qubit 0, row 0. Vertices can be grounded, see `GroundGraph`.
+///
+/// Removing a vertex or an edge moves the last one into its slot, renumbering it (see
+/// `Graph::remove_vertex`). `StableGGraph` keeps the indices stable instead.
+///
+/// The graph holds no caches or interior mutability, so it is `Send + Sync` and can be shared
+/// behind an `Arc` by threads running read-only analyses.
+///
+/// The `Debug` output is the scalar followed by `Graph::dump`.
+#[derive(Clone)]
+pub struct GGraph<Ix: IndexType = u32> {
     g: petgraph::Graph<VertexData, EdgeData, petgraph::Undirected, Ix>,
-    inputs: HashSet<petgraph::graph::NodeIndex<Ix>>,
-    outputs: HashSet<petgraph::graph::NodeIndex<Ix>>,
+    inputs: IndexSet<NodeIndex<Ix>>,
+    outputs: IndexSet<NodeIndex<Ix>>,
+    scalar: Scalar,
+    mode: GraphMode,
+    tracker: Option<TrackedStats>,
+}
+
+const _: fn() = assert_send_sync::<GGraph>;
+
+impl<Ix: IndexType + MaybeSync> fmt::Debug for GGraph<Ix> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "GGraph(scalar={})", self.scalar)?;
+        f.write_str(&self.dump())
+    }
+}
+
+impl<Ix: IndexType> Default for GGraph<Ix> {
+    fn default() -> Self {
+        GGraph {
+            g: petgraph::Graph::default(),
+            inputs: IndexSet::new(),
+            outputs: IndexSet::new(),
+            scalar: Scalar::one(),
+            mode: GraphMode::default(),
+            tracker: None,
+        }
+    }
+}
+
+impl<Ix: IndexType + MaybeSync> GGraph<Ix> {
+    /// Create an empty graph
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set the edge policy of `add_edge`, see `GraphMode`. Existing edges are kept as they are,
+    /// and `Graph::check_invariants` reports those breaking the new mode.
+    pub fn set_mode(&mut self, mode: GraphMode) {
+        self.mode = mode;
+    }
+
+    /// Start maintaining the `TrackedStats` counters on every mutation.
+    ///
+    /// The counters are updated by the graph-level mutators (`add_*`, `remove_*`, `set_phase`,
+    /// `set_vertex_kind`, `set_edge_kind`). Changing data through `vertex_mut` or `edge_mut`
+    /// bypasses them and invalidates the counters until `recompute_tracked_stats` is called.
+    ///
+    /// Tracking is specific to `GGraph`, it is not part of the `Graph` trait. See
+    /// `benches/stats.rs` for its effect on a cost model evaluated after every rewrite.
+    pub fn enable_stat_tracking(&mut self) {
+        self.tracker = Some(TrackedStats::new(self));
+    }
+
+    /// Stop maintaining the counters
+    pub fn disable_stat_tracking(&mut self) {
+        self.tracker = None;
+    }
+
+    /// The tracked counters, in constant time. `None` if tracking is not enabled.
+    ///
+    /// Debug builds check the counters against a full recomputation.
+    pub fn tracked_stats(&self) -> Option<TrackedStats> {
+        debug_assert!(
+            self.tracker.is_none_or(|t| t == TrackedStats::new(self)),
+            "tracked stats drifted, was the graph modified through vertex_mut or edge_mut?"
+        );
+        self.tracker
+    }
+
+    /// Recompute the tracked counters from scratch, after modifying data through `vertex_mut` or
+    /// `edge_mut`.
+    pub fn recompute_tracked_stats(&mut self) {
+        if self.tracker.is_some() {
+            self.enable_stat_tracking();
+        }
+    }
+
+    /// A `GraphSink` writing directly into a new graph
+    pub fn builder() -> GGraphBuilder<Ix> {
+        GGraphBuilder {
+            graph: Self::new(),
+            edges: Vec::new(),
+        }
+    }
+
+    /// Insert an edge, whatever the mode
+    fn insert_edge(&mut self, v: NodeIndex<Ix>, u: NodeIndex<Ix>, kind: EdgeKind) -> EdgeIndex<Ix> {
+        if let Some(t) = &mut self.tracker {
+            t.add_edge(kind);
+        }
+        self.g.add_edge(u, v, EdgeData { kind })
+    }
+
+    /// Add a vertex with all its data, including the position and ground flag.
+    fn add_vertex_data(&mut self, vdata: VertexData) -> NodeIndex<Ix> {
+        if let Some(t) = &mut self.tracker {
+            t.add_vertex(vdata.kind, vdata.phase);
+        }
+        self.g.add_node(vdata)
+    }
+
+    /// Read-only access to the underlying petgraph structure
+    pub fn as_petgraph(&self) -> &petgraph::Graph<VertexData, EdgeData, petgraph::Undirected, Ix> {
+        &self.g
+    }
+
+    /// Wrap a petgraph graph, marking the given nodes as inputs and outputs in wire order.
+    ///
+    /// Each boundary must be a node of kind `VertexKind::Boundary`, listed at most once among
+    /// the inputs and at most once among the outputs. The rest of the structure is not checked,
+    /// see `Graph::check_invariants`. The scalar of the result is one.
+    pub fn from_petgraph(
+        g: petgraph::Graph<VertexData, EdgeData, petgraph::Undirected, Ix>,
+        inputs: impl IntoIterator<Item = NodeIndex<Ix>>,
+        outputs: impl IntoIterator<Item = NodeIndex<Ix>>,
+    ) -> Result<Self, ImportError> {
+        let boundary_set = |vs: &mut dyn Iterator<Item = NodeIndex<Ix>>| {
+            let mut set = IndexSet::new();
+            for v in vs {
+                let index = v.index();
+                match g.node_weight(v) {
+                    None => return Err(ImportError::UnknownVertex { index }),
+                    Some(vdata) if vdata.kind != VertexKind::Boundary => {
+                        return Err(ImportError::NotBoundary {
+                            index,
+                            kind: vdata.kind,
+                        })
+                    }
+                    Some(_) => {}
+                }
+                if !set.insert(v) {
+                    return Err(ImportError::DuplicateBoundary { index });
+                }
+            }
+            Ok(set)
+        };
+        let inputs = boundary_set(&mut inputs.into_iter())?;
+        let outputs = boundary_set(&mut outputs.into_iter())?;
+        Ok(GGraph {
+            g,
+            inputs,
+            outputs,
+            scalar: Scalar::one(),
+            mode: GraphMode::default(),
+            tracker: None,
+        })
+    }
+}
+
+/// Replace `from` by `to` in a boundary set, keeping its position in the wire order.
+fn reindex<Ix: IndexType>(
+    boundary: &mut IndexSet<NodeIndex<Ix>>,
+    from: NodeIndex<Ix>,
+    to: NodeIndex<Ix>,
+) {
+    if let Some(i) = boundary.get_index_of(&from) {
+        boundary.insert(to);
+        let end = boundary.len() - 1;
+        boundary.swap_indices(i, end);
+        boundary.pop();
+    }
+}
+
+/// Bulk `GraphSink` for `GGraph`, see `GGraph::builder`.
+///
+/// Vertices are added to the graph as they are emitted, and edges are buffered until `finish`
+/// has validated their endpoints. Positions are rounded to the nearest qubit and row.
+#[derive(Clone)]
+pub struct GGraphBuilder<Ix: IndexType = u32> {
+    graph: GGraph<Ix>,
+    edges: Vec<(u32, u32, EdgeKind)>,
+}
+
+impl<Ix: IndexType + MaybeSync> fmt::Debug for GGraphBuilder<Ix> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("GGraphBuilder")
+            .field("graph", &self.graph)
+            .field("edges", &self.edges)
+            .finish()
+    }
+}
+
+impl<Ix: IndexType + MaybeSync> GraphSink for GGraphBuilder<Ix> {
+    type Graph = GGraph<Ix>;
+
+    fn begin(&mut self, vertices_hint: usize, edges_hint: usize) {
+        self.graph.g.reserve_nodes(vertices_hint);
+        self.graph.g.reserve_edges(edges_hint);
+        self.edges.reserve(edges_hint);
+    }
+
+    fn vertex(&mut self, kind: VertexKind, phase: Phase, flags: IoFlags) -> u32 {
+        let v = self.graph.g.add_node(VertexData {
+            kind,
+            phase,
+            ..Default::default()
+        });
+        if flags.input {
+            self.graph.inputs.insert(v);
+        }
+        if flags.output {
+            self.graph.outputs.insert(v);
+        }
+        v.index() as u32
+    }
+
+    fn edge(&mut self, u: u32, v: u32, kind: EdgeKind) {
+        self.edges.push((u, v, kind));
+    }
+
+    fn position(&mut self, v: u32, q: f64, r: f64) {
+        if let Some(vdata) = self.graph.g.node_weight_mut(NodeIndex::new(v as usize)) {
+            vdata.qubit = q.round() as i32;
+            vdata.row = r.round() as i32;
+        }
+    }
+
+    fn scalar(&mut self, scalar: Scalar) {
+        self.graph.scalar *= scalar;
+    }
+
+    fn finish(mut self) -> Result<GGraph<Ix>, Vec<SinkError>> {
+        let errors = check_edges(self.graph.g.node_count(), &self.edges);
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        for &(u, v, kind) in &self.edges {
+            // Same endpoint order as `Graph::add_edge`.
+            let (u, v) = (NodeIndex::new(u as usize), NodeIndex::new(v as usize));
+            self.graph.g.add_edge(v, u, EdgeData { kind });
+        }
+        Ok(self.graph)
+    }
 }
 
-impl<'a, Ix: Copy> Graph<'a> for GGraph<Ix>
+impl<Ix: Copy> Graph for GGraph<Ix>
 where
-    Ix: petgraph::graph::IndexType,
+    Ix: IndexType + MaybeSync,
 {
-    type VertexIx = petgraph::graph::NodeIndex<Ix>;
+    type VertexIx = NodeIndex<Ix>;
 
-    type EdgeIx = petgraph::graph::EdgeIndex<Ix>;
+    type EdgeIx = EdgeIndex<Ix>;
 
     type VertexData = VertexData;
 
     type EdgeData = EdgeData;
 
-    type EdgeIterator = petgraph::graph::EdgeIndices<Ix>;
+    type EdgeIterator<'a> = petgraph::graph::EdgeIndices<Ix>;
+
+    type VertexIterator<'a> = petgraph::graph::NodeIndices<Ix>;
+
+    type NeighbourIterator<'a> = petgraph::graph::Neighbors<'a, EdgeData, Ix>;
 
-    type VertexIterator = petgraph::graph::NodeIndices<Ix>;
+    type IncidentEdgeIterator<'a> = std::iter::Map<
+        petgraph::graph::Edges<'a, EdgeData, petgraph::Undirected, Ix>,
+        fn(petgraph::graph::EdgeReference<'a, EdgeData, Ix>) -> EdgeIndex<Ix>,
+    >;
 
-    type NeighbourIterator = petgraph::graph::Neighbors<'a, EdgeData, Ix>;
+    type EdgeDataIterator<'a> = std::iter::Map<
+        petgraph::graph::EdgeReferences<'a, EdgeData, Ix>,
+        fn(
+            petgraph::graph::EdgeReference<'a, EdgeData, Ix>,
+        ) -> (NodeIndex<Ix>, NodeIndex<Ix>, &'a EdgeData),
+    >;
 
-    type InputIterator = hash_set::Iter<'a, petgraph::graph::NodeIndex<Ix>>;
+    type IncidentNeighbourIterator<'a> = std::iter::Map<
+        petgraph::graph::Edges<'a, EdgeData, petgraph::Undirected, Ix>,
+        fn(petgraph::graph::EdgeReference<'a, EdgeData, Ix>) -> (EdgeIndex<Ix>, NodeIndex<Ix>),
+    >;
 
-    type OutputIterator = hash_set::Iter<'a, petgraph::graph::NodeIndex<Ix>>;
+    type NeighbourKindIterator<'a> = std::iter::Map<
+        petgraph::graph::Edges<'a, EdgeData, petgraph::Undirected, Ix>,
+        fn(petgraph::graph::EdgeReference<'a, EdgeData, Ix>) -> (NodeIndex<Ix>, EdgeKind),
+    >;
+
+    type InputIterator<'a> = Copied<set::Iter<'a, NodeIndex<Ix>>>;
+
+    type OutputIterator<'a> = Copied<set::Iter<'a, NodeIndex<Ix>>>;
 
     const BACKEND: &'static str = "GGraph-rs";
 
     fn set_input(&mut self, v: Self::VertexIx, flag: bool) {
-        todo!()
+        if flag {
+            self.inputs.insert(v);
+        } else {
+            self.inputs.shift_remove(&v);
+        }
     }
 
     fn set_output(&mut self, v: Self::VertexIx, flag: bool) {
-        todo!()
+        if flag {
+            self.outputs.insert(v);
+        } else {
+            self.outputs.shift_remove(&v);
+        }
     }
 
     fn is_input(&self, v: Self::VertexIx) -> bool {
-        todo!()
+        self.inputs.contains(&v)
     }
 
     fn is_output(&self, v: Self::VertexIx) -> bool {
-        todo!()
+        self.outputs.contains(&v)
     }
 
-    fn inputs(&self) -> Self::InputIterator {
-        todo!()
+    fn inputs(&self) -> Self::InputIterator<'_> {
+        self.inputs.iter().copied()
     }
 
-    fn outputs(&self) -> Self::OutputIterator {
-        todo!()
+    fn outputs(&self) -> Self::OutputIterator<'_> {
+        self.outputs.iter().copied()
+    }
+
+    fn input_at(&self, i: usize) -> Option<Self::VertexIx> {
+        self.inputs.get_index(i).copied()
+    }
+
+    fn output_at(&self, i: usize) -> Option<Self::VertexIx> {
+        self.outputs.get_index(i).copied()
+    }
+
+    fn set_io_order(&mut self, inputs: Vec<Self::VertexIx>, outputs: Vec<Self::VertexIx>) {
+        self.inputs = inputs.into_iter().collect();
+        self.outputs = outputs.into_iter().collect();
     }
 
     fn num_vertices(&self) -> u32 {
@@ -103,80 +461,899 @@ where
         self.g.edge_count() as u32
     }
 
-    fn vertices(&self) -> Self::VertexIterator {
+    fn vertices(&self) -> Self::VertexIterator<'_> {
         self.g.node_indices()
     }
 
-    fn edges(&self) -> Self::EdgeIterator {
+    fn edges(&self) -> Self::EdgeIterator<'_> {
         self.g.edge_indices()
     }
 
-    fn vertex(&'a self, v: Self::VertexIx) -> Option<&'a Self::VertexData> {
+    fn edges_with_data(&self) -> Self::EdgeDataIterator<'_> {
+        self.g
+            .edge_references()
+            .map(|e| (e.source(), e.target(), e.weight()))
+    }
+
+    fn vertex(&self, v: Self::VertexIx) -> Option<&Self::VertexData> {
         self.g.node_weight(v)
     }
 
-    fn vertex_mut(&'a mut self, v: Self::VertexIx) -> Option<&'a mut Self::VertexData> {
+    fn vertex_mut(&mut self, v: Self::VertexIx) -> Option<&mut Self::VertexData> {
         self.g.node_weight_mut(v)
     }
 
-    fn edge(&'a self, e: Self::EdgeIx) -> Option<&'a Self::EdgeData> {
+    fn edge(&self, e: Self::EdgeIx) -> Option<&Self::EdgeData> {
         self.g.edge_weight(e)
     }
 
-    fn edge_mut(&'a mut self, e: Self::EdgeIx) -> Option<&'a mut Self::EdgeData> {
+    fn edge_mut(&mut self, e: Self::EdgeIx) -> Option<&mut Self::EdgeData> {
         self.g.edge_weight_mut(e)
     }
 
+    fn scalar(&self) -> &Scalar {
+        &self.scalar
+    }
+
+    fn scalar_mut(&mut self) -> &mut Scalar {
+        &mut self.scalar
+    }
+
     fn edge_endpoints(&self, e: Self::EdgeIx) -> Option<(Self::VertexIx, Self::VertexIx)> {
         self.g.edge_endpoints(e)
     }
 
-    fn vertex_degree(&self, v: Self::VertexIx) -> u32 {
-        self.neighbours(v).count() as u32
+    fn neighbours(&self, v: Self::VertexIx) -> Self::NeighbourIterator<'_> {
+        self.g.neighbors(v)
     }
 
-    fn neighbours(&'a self, v: Self::VertexIx) -> Self::NeighbourIterator {
-        self.g.neighbors(v)
+    fn incident_edges(&self, v: Self::VertexIx) -> Self::IncidentEdgeIterator<'_> {
+        self.g.edges(v).map(|e| e.id())
+    }
+
+    /// The edges of an undirected petgraph have `v` as their source, so the other endpoint is
+    /// the target.
+    fn incident_edges_with_neighbours(
+        &self,
+        v: Self::VertexIx,
+    ) -> Self::IncidentNeighbourIterator<'_> {
+        self.g.edges(v).map(|e| (e.id(), e.target()))
+    }
+
+    fn neighbours_with_kind(&self, v: Self::VertexIx) -> Self::NeighbourKindIterator<'_> {
+        self.g.edges(v).map(|e| (e.target(), e.weight().kind))
     }
 
-    fn connected(&self, v: Self::VertexIx, u: Self::VertexIx) -> bool {
-        self.g.contains_edge(v, u)
+    fn edge_between(&self, v: Self::VertexIx, u: Self::VertexIx) -> Option<Self::EdgeIx> {
+        self.g.edges_connecting(v, u).next().map(|e| e.id())
+    }
+
+    fn is_ground(&self, v: Self::VertexIx) -> bool {
+        self.g[v].ground
     }
 
     fn adjoint(&mut self) {
-        todo!()
+        std::mem::swap(&mut self.inputs, &mut self.outputs);
+        self.scalar = self.scalar.conjugate();
+        // Mirror the rows, so the new inputs are on the left.
+        let last_row = self.depth() as i32 - 1;
+        // Negation keeps the phase denominators, so the tracked stats are unchanged.
+        for vdata in self.g.node_weights_mut() {
+            vdata.phase = -vdata.phase;
+            vdata.row = last_row - vdata.row;
+        }
     }
 
-    fn compose(&mut self, other: Self) {
-        todo!()
+    fn compose(&mut self, other: Self) -> Result<(), ComposeError> {
+        if self.outputs.len() != other.inputs.len() {
+            return Err(ComposeError::ArityMismatch {
+                outputs: self.outputs.len(),
+                inputs: other.inputs.len(),
+            });
+        }
+        self.scalar *= other.scalar;
+        let seams: Vec<_> = self.outputs.iter().copied().collect();
+
+        // Each input of `other` is identified with the matching output of `self`, and the
+        // rows of `other` continue from the last row of `self`.
+        let offset = self.depth().saturating_sub(1) as i32;
+        let mut map = HashMap::new();
+        for (&i, &o) in other.inputs.iter().zip(&seams) {
+            map.insert(i, o);
+            self.g[o].ground |= other.g[i].ground;
+        }
+        for v in other.g.node_indices() {
+            if let Entry::Vacant(entry) = map.entry(v) {
+                let vdata = other.g[v].clone();
+                entry.insert(self.add_vertex_data(VertexData {
+                    row: vdata.row + offset,
+                    ..vdata
+                }));
+            }
+        }
+        for e in other.g.edge_references() {
+            self.add_edge(map[&e.source()], map[&e.target()], e.weight().kind);
+        }
+        self.outputs = other.outputs.iter().map(|v| map[v]).collect();
+
+        // Turn the joined boundaries into plain wires, skipping the ones that are still
+        // boundaries of the result. Grounded joins are kept as grounded spiders. Removing the
+        // higher indices first keeps the lower ones valid.
+        let mut seams: Vec<_> = seams
+            .into_iter()
+            .filter(|v| !self.inputs.contains(v) && !self.outputs.contains(v))
+            .collect();
+        seams.sort_unstable_by(|a, b| b.cmp(a));
+        for s in seams {
+            self.set_vertex_kind(s, VertexKind::Z);
+            self.set_phase(s, Phase::zero());
+            let wire: Vec<_> = self
+                .g
+                .edges(s)
+                .map(|e| {
+                    let n = if e.source() == s {
+                        e.target()
+                    } else {
+                        e.source()
+                    };
+                    (n, e.weight().kind)
+                })
+                .collect();
+            if let [(a, k1), (b, k2)] = wire[..] {
+                if a != s && b != s && a != b && !self.g[s].ground {
+                    let last = NodeIndex::new(self.g.node_count() - 1);
+                    let moved = |v| if v == last { s } else { v };
+                    let (a, b) = (moved(a), moved(b));
+                    self.remove_vertex(s);
+                    let kind = if k1 == k2 {
+                        EdgeKind::Regular
+                    } else {
+                        EdgeKind::Hadam
+                    };
+                    self.add_edge(a, b, kind);
+                }
+            }
+        }
+        Ok(())
     }
 
     fn tensor(&mut self, other: Self) {
-        todo!()
+        // Vertices are appended, so each one is shifted by the current vertex count. The qubits
+        // of `other` are placed after the ones of `self`.
+        let offset = self.g.node_count();
+        let shift = |v: NodeIndex<Ix>| NodeIndex::new(v.index() + offset);
+        let qubit_offset = self.qubit_count() as i32;
+        self.scalar *= other.scalar;
+        for v in other.g.node_indices() {
+            let vdata = other.g[v].clone();
+            self.add_vertex_data(VertexData {
+                qubit: vdata.qubit + qubit_offset,
+                ..vdata
+            });
+        }
+        for e in other.g.edge_references() {
+            self.add_edge(shift(e.source()), shift(e.target()), e.weight().kind);
+        }
+        self.inputs.extend(other.inputs.iter().map(|&v| shift(v)));
+        self.outputs.extend(other.outputs.iter().map(|&v| shift(v)));
+    }
+
+    fn set_phase(&mut self, v: Self::VertexIx, phase: Phase) {
+        let vdata = &mut self.g[v];
+        if let Some(t) = &mut self.tracker {
+            t.remove_vertex(vdata.kind, vdata.phase);
+            t.add_vertex(vdata.kind, phase);
+        }
+        vdata.phase = phase;
+    }
+
+    fn set_vertex_kind(&mut self, v: Self::VertexIx, kind: VertexKind) {
+        let vdata = &mut self.g[v];
+        if let Some(t) = &mut self.tracker {
+            t.remove_vertex(vdata.kind, vdata.phase);
+            t.add_vertex(kind, vdata.phase);
+        }
+        vdata.kind = kind;
+    }
+
+    fn set_edge_kind(&mut self, e: Self::EdgeIx, kind: EdgeKind) {
+        let edata = &mut self.g[e];
+        if let Some(t) = &mut self.tracker {
+            t.remove_edge(edata.kind);
+            t.add_edge(kind);
+        }
+        edata.kind = kind;
     }
 
     fn add_vertices(&mut self, count: u32) -> Vec<Self::VertexIx> {
         let mut res = Vec::new();
         for _ in 0..count {
-            let v = self.g.add_node(Default::default());
+            let v = self.add_vertex(Default::default(), Default::default());
             res.push(v);
         }
         res
     }
 
-    fn add_vertex(&'a mut self, kind: VertexKind, phase: Phase) -> Self::VertexIx {
-        self.g.add_node(VertexData { kind, phase })
+    fn add_vertex(&mut self, kind: VertexKind, phase: Phase) -> Self::VertexIx {
+        self.add_vertex_data(VertexData {
+            kind,
+            phase,
+            ..Default::default()
+        })
     }
 
-    fn add_edge(&mut self, v: Self::VertexIx, u: Self::VertexIx, kind: EdgeKind) -> Self::EdgeIx {
-        self.g.add_edge(u, v, EdgeData { kind })
+    fn mode(&self) -> GraphMode {
+        self.mode
+    }
+
+    fn add_edge(
+        &mut self,
+        v: Self::VertexIx,
+        u: Self::VertexIx,
+        kind: EdgeKind,
+    ) -> Option<Self::EdgeIx> {
+        match self.mode {
+            GraphMode::Simple => merge_edge(self, v, u, kind, |g, v, u, kind| {
+                Some(g.insert_edge(v, u, kind))
+            }),
+            GraphMode::Multi => Some(self.insert_edge(v, u, kind)),
+        }
     }
 
     fn remove_vertex(&mut self, v: Self::VertexIx) {
-        self.g.remove_node(v);
+        if let Some(t) = &mut self.tracker {
+            for e in self.g.edges(v) {
+                t.remove_edge(e.weight().kind);
+            }
+        }
+        // `remove_node` moves the last vertex into the removed slot.
+        let last = NodeIndex::new(self.g.node_count().wrapping_sub(1));
+        if let Some(vdata) = self.g.remove_node(v) {
+            if let Some(t) = &mut self.tracker {
+                t.remove_vertex(vdata.kind, vdata.phase);
+            }
+            for boundary in [&mut self.inputs, &mut self.outputs] {
+                boundary.shift_remove(&v);
+                if last != v {
+                    reindex(boundary, last, v);
+                }
+            }
+        }
     }
 
     fn remove_edge(&mut self, e: Self::EdgeIx) {
-        self.g.remove_edge(e);
+        if let Some(edata) = self.g.remove_edge(e) {
+            if let Some(t) = &mut self.tracker {
+                t.remove_edge(edata.kind);
+            }
+        }
+    }
+}
+
+impl<Ix: IndexType + MaybeSync> CartesianGraph for GGraph<Ix> {
+    type Qubit = i32;
+    type Row = i32;
+
+    fn row(&self, v: Self::VertexIx) -> i32 {
+        self.g[v].row
+    }
+
+    fn qubit(&self, v: Self::VertexIx) -> i32 {
+        self.g[v].qubit
+    }
+
+    fn set_row(&mut self, v: Self::VertexIx, r: i32) {
+        self.g[v].row = r;
+    }
+
+    fn set_qubit(&mut self, v: Self::VertexIx, q: i32) {
+        self.g[v].qubit = q;
+    }
+
+    fn set_position(&mut self, v: Self::VertexIx, q: i32, r: i32) {
+        let vdata = &mut self.g[v];
+        vdata.qubit = q;
+        vdata.row = r;
+    }
+
+    /// One more than the largest row, or 0 if there are no vertices on a non-negative row
+    fn depth(&self) -> u32 {
+        self.g
+            .raw_nodes()
+            .iter()
+            .map(|node| node.weight.row + 1)
+            .max()
+            .unwrap_or(0)
+            .max(0) as u32
+    }
+
+    /// One more than the largest qubit, or 0 if there are no vertices on a non-negative qubit
+    fn qubit_count(&self) -> u32 {
+        self.g
+            .raw_nodes()
+            .iter()
+            .map(|node| node.weight.qubit + 1)
+            .max()
+            .unwrap_or(0)
+            .max(0) as u32
+    }
+}
+
+impl<Ix: IndexType + MaybeSync> GroundGraph for GGraph<Ix> {
+    type GroundIterator = std::vec::IntoIter<NodeIndex<Ix>>;
+
+    fn grounds(&self) -> Self::GroundIterator {
+        self.g
+            .node_indices()
+            .filter(|&v| self.g[v].ground)
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::analysis::{connected_components, is_isomorphic};
+    use crate::zx::checked_graph::CheckedGraph;
+    use crate::zx::circuit::{Circuit, Gate};
+    use crate::zx::simplify::gadgets;
+    use crate::zx::stable_graph::StableGGraph;
+    use crate::zx::templates;
+    use crate::zx::tensor::{assert_graph_eq, to_tensor};
+    use crate::zx::vec_graph::VecGraph;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn assert_sync<T: Sync>() {}
+    fn assert_send<T: Send>() {}
+
+    #[test]
+    fn backends_are_send_and_sync() {
+        assert_sync::<GGraph>();
+        assert_send::<GGraph>();
+        assert_sync::<StableGGraph>();
+        assert_send::<StableGGraph>();
+        assert_sync::<VecGraph>();
+        assert_send::<VecGraph>();
+        assert_sync::<CheckedGraph<VecGraph>>();
+        assert_send::<CheckedGraph<VecGraph>>();
+    }
+
+    #[test]
+    fn shared_read_only_analyses() {
+        let g = Arc::new(templates::toffoli_gadgets());
+        let expected = Arc::new((
+            g.graph_stats(),
+            to_tensor(&*g),
+            g.dump(),
+            g.tcount(),
+            gadgets(&*g).len(),
+            connected_components(&*g).len(),
+        ));
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let (g, expected) = (Arc::clone(&g), Arc::clone(&expected));
+                thread::spawn(move || match i {
+                    0 => assert_eq!(g.graph_stats(), expected.0),
+                    1 => assert_eq!(to_tensor(&*g), expected.1),
+                    2 => assert_eq!(g.dump(), expected.2),
+                    3 => assert_eq!(g.tcount(), expected.3),
+                    4 => assert_eq!(gadgets(&*g).len(), expected.4),
+                    5 => assert_eq!(connected_components(&*g).len(), expected.5),
+                    6 => assert!(is_isomorphic(&*g, &g.as_ref().clone())),
+                    _ => assert!(g.check_invariants().is_ok()),
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn tracked_stats_follow_mutations() {
+        let mut g = templates::toffoli_gadgets();
+        assert_eq!(g.tracked_stats(), None);
+        g.enable_stat_tracking();
+        let initial = TrackedStats::new(&g);
+        assert_eq!(g.tracked_stats(), Some(initial));
+        assert_eq!(initial.tcount, 7);
+
+        let v = g.add_vertex(VertexKind::X, Phase::new(1, 4));
+        let w = g.add_vertex(VertexKind::HBox, Phase::pi());
+        let e = g.add_edge(v, w, EdgeKind::Hadam).unwrap();
+        g.set_phase(v, Phase::new(1, 2));
+        g.set_vertex_kind(w, VertexKind::Z);
+        g.set_edge_kind(e, EdgeKind::Regular);
+        g.set_edge_kind(e, EdgeKind::Hadam);
+        let stats = g.tracked_stats().unwrap();
+        assert_eq!(stats.tcount, initial.tcount);
+        assert_eq!(stats.num_x, initial.num_x + 1);
+        assert_eq!(stats.num_z, initial.num_z + 1);
+        assert_eq!(stats.hadamard_edges, initial.hadamard_edges + 1);
+
+        g.remove_edge(e);
+        g.remove_vertex(w);
+        g.remove_vertex(v);
+        assert_eq!(g.tracked_stats(), Some(initial));
+
+        crate::zx::simplify::full_reduce(&mut g);
+        assert_eq!(g.tracked_stats(), Some(TrackedStats::new(&g)));
+        g.disable_stat_tracking();
+        assert_eq!(g.tracked_stats(), None);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "tracked stats drifted")]
+    fn raw_mutation_is_caught() {
+        let mut g = templates::cnot();
+        g.enable_stat_tracking();
+        let v = g.vertices().nth(4).unwrap();
+        g.vertex_mut(v).unwrap().phase = Phase::new(1, 4);
+        g.tracked_stats();
+    }
+
+    #[test]
+    fn recompute_after_raw_mutation() {
+        let mut g = templates::cnot();
+        g.enable_stat_tracking();
+        let v = g.vertices().nth(4).unwrap();
+        g.vertex_mut(v).unwrap().phase = Phase::new(1, 4);
+        g.recompute_tracked_stats();
+        assert_eq!(g.tracked_stats().unwrap().tcount, 1);
+    }
+
+    /// `n` bare wires, with each boundary on the qubit of its wire
+    fn wires(n: i32) -> GGraph {
+        let mut g: GGraph = GGraph::new();
+        let inputs: Vec<_> = (0..n)
+            .map(|q| {
+                let v = g.add_vertex(VertexKind::Boundary, Phase::zero());
+                g.set_input(v, true);
+                g.set_qubit(v, q);
+                v
+            })
+            .collect();
+        for (q, &i) in inputs.iter().enumerate() {
+            let o = g.add_vertex(VertexKind::Boundary, Phase::zero());
+            g.set_output(o, true);
+            g.set_qubit(o, q as i32);
+            g.add_edge(i, o, EdgeKind::Regular);
+        }
+        g
+    }
+
+    #[test]
+    fn remove_input_before_last_index() {
+        let mut g = wires(4);
+        let removed = g.inputs().nth(1).unwrap();
+        assert_ne!(removed.index(), g.num_vertices() as usize - 1);
+        g.remove_vertex(removed);
+
+        // The output of qubit 3 moved into the freed index.
+        assert_eq!(g.qubit(removed), 3);
+        assert!(g.is_output(removed));
+        assert!(!g.is_input(removed));
+        let inputs: Vec<_> = g.inputs().map(|v| g.qubit(v)).collect();
+        assert_eq!(inputs, [0, 2, 3]);
+        let outputs: Vec<_> = g.outputs().map(|v| g.qubit(v)).collect();
+        assert_eq!(outputs, [0, 1, 2, 3]);
+        assert!(g.inputs().chain(g.outputs()).all(|u| g.vertex(u).is_some()));
+        for i in g.inputs() {
+            let o = g.neighbours(i).next().unwrap();
+            assert!(g.is_output(o));
+            assert_eq!(g.qubit(o), g.qubit(i));
+        }
+    }
+
+    #[test]
+    fn remove_last_and_double_boundary() {
+        let mut g = wires(2);
+        let last = g.outputs().last().unwrap();
+        assert_eq!(last.index(), g.num_vertices() as usize - 1);
+        g.remove_vertex(last);
+        assert_eq!(g.outputs().count(), 1);
+        assert_eq!(g.inputs().count(), 2);
+
+        // A vertex that is both an input and an output leaves both sets.
+        let v = g.inputs().next().unwrap();
+        g.set_output(v, true);
+        g.remove_vertex(v);
+        assert!(g.inputs().chain(g.outputs()).all(|u| g.vertex(u).is_some()));
+        let inputs: Vec<_> = g.inputs().map(|u| g.qubit(u)).collect();
+        assert_eq!(inputs, [1]);
+        let outputs: Vec<_> = g.outputs().map(|u| g.qubit(u)).collect();
+        assert_eq!(outputs, [0]);
+    }
+
+    /// A circuit with phases, Hadamard edges and an H-box, with its layout
+    fn circuit_with_hbox() -> GGraph {
+        let mut c = Circuit::new(3);
+        c.add_gate(Gate::T(0));
+        c.add_gate(Gate::H(1));
+        c.add_gate(Gate::Cx(0, 1));
+        c.add_gate(Gate::Ccz(0, 1, 2));
+        c.add_gate(Gate::Rz(2, Phase::new(1, 3)));
+        c.add_gate(Gate::H(2));
+        c.to_graph_into(GGraph::builder())
+    }
+
+    #[test]
+    fn adjoint_twice() {
+        let mut g = circuit_with_hbox();
+        g.scalar_mut().add_power(1);
+        assert!(g
+            .vertices()
+            .any(|v| g.vertex(v).unwrap().kind() == VertexKind::HBox));
+        assert!(g
+            .edges()
+            .any(|e| g.edge(e).unwrap().kind() == EdgeKind::Hadam));
+        let original = g.clone();
+        g.adjoint();
+        assert_ne!(g.dump(), original.dump());
+        g.adjoint();
+        assert_eq!(g.dump(), original.dump());
+        assert!(g.inputs().eq(original.inputs()));
+        assert!(g.outputs().eq(original.outputs()));
+        assert_eq!(g.scalar(), original.scalar());
+        for v in g.vertices() {
+            assert_eq!((g.row(v), g.qubit(v)), (original.row(v), original.qubit(v)));
+        }
+    }
+
+    #[test]
+    fn adjoint_mirrors_the_diagram() {
+        let original = circuit_with_hbox();
+        let mut g = original.clone();
+        g.adjoint();
+        assert!(g.inputs().eq(original.outputs()));
+        assert!(g.outputs().eq(original.inputs()));
+        let last = original.depth() as i32 - 1;
+        for v in g.vertices() {
+            assert_eq!(g.row(v), last - original.row(v));
+            assert_eq!(
+                g.vertex(v).unwrap().phase(),
+                -original.vertex(v).unwrap().phase()
+            );
+        }
+        assert!(g.check_layout().is_ok());
+        let t = g
+            .vertices()
+            .find(|&v| original.vertex(v).unwrap().phase() == Phase::new(1, 4));
+        assert_eq!(g.vertex(t.unwrap()).unwrap().phase(), Phase::new(7, 4));
+
+        // The linear map is the conjugate transpose.
+        let (ta, tg) = (to_tensor(&original), to_tensor(&g));
+        for i in 0..8 {
+            for o in 0..8 {
+                assert!((tg.entry(o, i) - ta.entry(i, o).conj()).norm() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn compose_cnots() {
+        let mut g = templates::cnot();
+        let inputs: Vec<_> = g.inputs().collect();
+        g.compose(templates::cnot()).unwrap();
+        assert_eq!(g.num_vertices(), 8);
+        assert_eq!(g.num_edges(), 8);
+        assert_eq!(g.inputs().collect::<Vec<_>>(), inputs);
+        assert_eq!(g.outputs().count(), 2);
+        assert!(g.outputs().all(|v| !inputs.contains(&v)));
+        assert!(g.check_invariants().is_ok());
+
+        // Two CNOTs are the identity, and composing multiplies their scalars.
+        let t = to_tensor(&g);
+        for i in 0..4 {
+            for o in 0..4 {
+                let expected = if i == o { 1.0 } else { 0.0 };
+                assert!((t.entry(i, o) - expected).norm() < 1e-9);
+            }
+        }
+    }
+
+    /// Wire `in -a- Z(phase) -b- out` with the given edge kinds
+    fn wire(phase: Phase, a: EdgeKind, b: EdgeKind) -> GGraph {
+        let mut g: GGraph = GGraph::new();
+        let i = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        let z = g.add_vertex(VertexKind::Z, phase);
+        let o = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        g.add_edge(i, z, a);
+        g.add_edge(z, o, b);
+        g.set_input(i, true);
+        g.set_output(o, true);
+        g
+    }
+
+    #[test]
+    fn compose_joins_edge_kinds() {
+        let (r, h) = (EdgeKind::Regular, EdgeKind::Hadam);
+        let mut g = wire(Phase::new(1, 4), r, r);
+        g.compose(wire(Phase::new(1, 2), h, r)).unwrap();
+        assert_eq!(g.num_vertices(), 4);
+        let mut expected: GGraph = GGraph::new();
+        let i = expected.add_vertex(VertexKind::Boundary, Phase::zero());
+        let a = expected.add_vertex(VertexKind::Z, Phase::new(1, 4));
+        let b = expected.add_vertex(VertexKind::Z, Phase::new(1, 2));
+        let o = expected.add_vertex(VertexKind::Boundary, Phase::zero());
+        expected.add_edge(i, a, r);
+        expected.add_edge(a, b, h);
+        expected.add_edge(b, o, r);
+        expected.set_input(i, true);
+        expected.set_output(o, true);
+        assert!(is_isomorphic(&g, &expected));
+        assert_graph_eq(&g, &expected);
+
+        // Two Hadamards cancel.
+        let mut g = wire(Phase::new(1, 4), r, h);
+        g.compose(wire(Phase::new(1, 2), h, r)).unwrap();
+        let spiders: Vec<_> = g
+            .vertices()
+            .filter(|&v| g.vertex(v).unwrap().kind() == VertexKind::Z)
+            .collect();
+        let e = g.edge_between(spiders[0], spiders[1]).unwrap();
+        assert_eq!(g.edge(e).unwrap().kind(), r);
+    }
+
+    #[test]
+    fn compose_arity_mismatch() {
+        let mut g = templates::cnot();
+        let before = g.dump();
+        assert_eq!(
+            g.compose(templates::ghz(3)),
+            Err(ComposeError::ArityMismatch {
+                outputs: 2,
+                inputs: 0
+            })
+        );
+        assert_eq!(g.dump(), before);
+    }
+
+    /// Measurement of qubit 0 and discard of qubit 1, as grounded Z spiders. Returns the diagram
+    /// and the two spiders.
+    fn measure_and_discard() -> (GGraph, [NodeIndex; 2]) {
+        let mut g: GGraph = GGraph::new();
+        let i0 = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        let i1 = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        let m = g.add_vertex(VertexKind::Z, Phase::zero());
+        let d = g.add_vertex(VertexKind::Z, Phase::zero());
+        let o = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        g.add_edge(i0, m, EdgeKind::Regular);
+        g.add_edge(m, o, EdgeKind::Regular);
+        g.add_edge(i1, d, EdgeKind::Regular);
+        g.set_input(i0, true);
+        g.set_input(i1, true);
+        g.set_output(o, true);
+        g.set_ground(m, true);
+        g.set_ground(d, true);
+        (g, [m, d])
+    }
+
+    #[test]
+    fn grounds_survive_composition() {
+        let (g, [m, d]) = measure_and_discard();
+        assert_eq!(g.grounds().collect::<Vec<_>>(), vec![m, d]);
+        assert!(g.is_ground(m) && !g.is_ground(g.outputs().next().unwrap()));
+
+        let mut adjoint = g.clone();
+        adjoint.adjoint();
+        assert_eq!(adjoint.grounds().collect::<Vec<_>>(), vec![m, d]);
+
+        let mut c = Circuit::new(2);
+        c.add_gate(Gate::H(0));
+        c.add_gate(Gate::Cx(0, 1));
+        let mut h: GGraph = c.to_graph_into(GGraph::builder());
+        h.compose(g.clone()).unwrap();
+        h.compose(wires(1)).unwrap();
+        let grounds: Vec<_> = h.grounds().collect();
+        assert_eq!(grounds.len(), 2);
+        for v in grounds {
+            assert_eq!(h.vertex(v).unwrap().kind(), VertexKind::Z);
+        }
+        assert_eq!((h.inputs().count(), h.outputs().count()), (2, 1));
+
+        let mut t = g.clone();
+        t.tensor(g);
+        assert_eq!(t.grounds().count(), 4);
+
+        // A grounded input joined to an output is kept as a grounded spider.
+        let mut grounded = wires(1);
+        let i = grounded.inputs().next().unwrap();
+        grounded.set_ground(i, true);
+        let mut h = wires(1);
+        h.compose(grounded).unwrap();
+        let grounds: Vec<_> = h.grounds().collect();
+        assert_eq!(grounds.len(), 1);
+        assert_eq!(h.vertex(grounds[0]).unwrap().kind(), VertexKind::Z);
+        assert_eq!(h.neighbours(grounds[0]).count(), 2);
+    }
+
+    /// Two wires through a T spider each, with the inputs, spiders and outputs on rows 0, 1, 2
+    fn grid() -> GGraph {
+        let mut g: GGraph = GGraph::new();
+        for q in 0..2 {
+            let vs: Vec<_> = [VertexKind::Boundary, VertexKind::Z, VertexKind::Boundary]
+                .iter()
+                .copied()
+                .enumerate()
+                .map(|(row, kind)| {
+                    let phase = if kind == VertexKind::Z {
+                        Phase::new(1, 4)
+                    } else {
+                        Phase::zero()
+                    };
+                    let v = g.add_vertex(kind, phase);
+                    g.set_qubit(v, q);
+                    g.set_row(v, row as i32);
+                    v
+                })
+                .collect();
+            g.add_edge(vs[0], vs[1], EdgeKind::Regular);
+            g.add_edge(vs[1], vs[2], EdgeKind::Regular);
+            g.set_input(vs[0], true);
+            g.set_output(vs[2], true);
+        }
+        g
+    }
+
+    #[test]
+    fn depth_and_qubit_count() {
+        let mut g = grid();
+        assert_eq!(g.depth(), 3);
+        assert_eq!(g.qubit_count(), 2);
+        let empty: GGraph = GGraph::new();
+        assert_eq!(empty.depth(), 0);
+
+        // Both are computed from the current coordinates.
+        let spider = g.neighbours(g.inputs().next().unwrap()).next().unwrap();
+        g.set_row(spider, 6);
+        assert_eq!(g.depth(), 7);
+        g.set_row(spider, 1);
+        let last = g.outputs().last().unwrap();
+        g.set_qubit(last, 4);
+        assert_eq!(g.qubit_count(), 5);
+        g.set_qubit(last, 1);
+
+        // Composing continues the rows from the last row of the first diagram.
+        let mut h = grid();
+        h.compose(grid()).unwrap();
+        assert_eq!(h.depth(), 5);
+        assert_eq!(h.qubit_count(), 2);
+        for q in 0..2 {
+            let rows: Vec<_> = h.qubit_slice(q).into_iter().map(|v| h.row(v)).collect();
+            assert_eq!(rows, vec![0, 1, 3, 4]);
+        }
+    }
+
+    #[test]
+    fn tensor_stacks_diagrams() {
+        let mut c = Circuit::new(2);
+        c.add_gate(Gate::T(0));
+        c.add_gate(Gate::Cz(0, 1));
+        let top: GGraph = c.to_graph_into(GGraph::builder());
+        let bottom = templates::cnot();
+        let mut g = top.clone();
+        g.tensor(bottom.clone());
+
+        assert_eq!(g.inputs().count(), 4);
+        assert_eq!(g.outputs().count(), 4);
+        assert_eq!(g.num_vertices(), top.num_vertices() + bottom.num_vertices());
+        assert_eq!(g.num_edges(), top.num_edges() + bottom.num_edges());
+        let components = connected_components(&g);
+        assert_eq!(components.len(), 2);
+
+        // No edge crosses between the halves, and the second one is on the qubits after the
+        // first.
+        let n = top.num_vertices() as usize;
+        for (v, u, _) in g.edges_with_data() {
+            assert_eq!(v.index() < n, u.index() < n);
+        }
+        for v in g.vertices().filter(|v| v.index() >= n) {
+            assert!(g.qubit(v) >= top.qubit_count() as i32);
+        }
+        assert_eq!(g.qubit_count(), 2 + bottom.qubit_count());
+
+        // The linear map is the Kronecker product.
+        let (tt, tb, tg) = (to_tensor(&top), to_tensor(&bottom), to_tensor(&g));
+        for i in 0..16 {
+            for o in 0..16 {
+                let expected = tt.entry(i >> 2, o >> 2) * tb.entry(i & 3, o & 3);
+                assert!((tg.entry(i, o) - expected).norm() < 1e-9);
+            }
+        }
+    }
+
+    /// A Bell pair next to a CNOT, with a spider that has no boundary
+    fn disconnected() -> GGraph {
+        let mut g = templates::bell_pair();
+        g.tensor(templates::cnot());
+        g.add_vertex(VertexKind::Z, Phase::new(1, 4));
+        g
+    }
+
+    #[test]
+    fn components_and_their_boundaries() {
+        let g = disconnected();
+        let components = connected_components(&g);
+        assert_eq!(components.len(), 3);
+        // Components are in vertex order, and cover every vertex once.
+        let flat: Vec<_> = components.iter().flatten().copied().collect();
+        assert_eq!(flat.len(), g.num_vertices() as usize);
+        assert!(components.windows(2).all(|w| w[0][0] < w[1][0]));
+
+        let boundaries = |c: &[NodeIndex], flag: fn(&GGraph, NodeIndex) -> bool| {
+            c.iter().filter(|&&v| flag(&g, v)).count()
+        };
+        let counts: Vec<_> = components
+            .iter()
+            .map(|c| {
+                (
+                    c.len(),
+                    boundaries(c, GGraph::is_input),
+                    boundaries(c, GGraph::is_output),
+                )
+            })
+            .collect();
+        let bell = templates::bell_pair().num_vertices() as usize;
+        let cnot = templates::cnot().num_vertices() as usize;
+        assert_eq!(counts, [(bell, 0, 2), (cnot, 2, 2), (1, 0, 0)]);
+    }
+
+    #[test]
+    fn petgraph_round_trip() {
+        let g = disconnected();
+        let pg = g.as_petgraph().clone();
+        assert_eq!(petgraph::algo::connected_components(&pg), 3);
+        assert_eq!(g.to_petgraph().node_count(), pg.node_count());
+        let h = GGraph::from_petgraph(pg, g.inputs(), g.outputs()).unwrap();
+        assert!(is_isomorphic(&g, &h));
+        assert!(h.inputs().eq(g.inputs()));
+        assert!(h.outputs().eq(g.outputs()));
+
+        // The copy of another backend numbers the nodes in vertex order.
+        let mut v = VecGraph::new();
+        let a = v.add_vertex(VertexKind::X, Phase::pi());
+        let b = v.add_vertex(VertexKind::Z, Phase::zero());
+        v.add_edge(a, b, EdgeKind::Hadam);
+        let pg = v.to_petgraph();
+        assert_eq!(pg[NodeIndex::new(0)].kind, VertexKind::X);
+        let e = pg.edge_indices().next().unwrap();
+        assert_eq!(pg[e].kind, EdgeKind::Hadam);
+    }
+
+    #[test]
+    fn petgraph_import_errors() {
+        let g = templates::cnot();
+        let pg = || g.as_petgraph().clone();
+        let spider = g
+            .vertices()
+            .find(|&v| g.vertex(v).unwrap().kind != VertexKind::Boundary)
+            .unwrap();
+        let input = g.inputs().next().unwrap();
+        let missing = NodeIndex::new(g.num_vertices() as usize);
+        assert_eq!(
+            GGraph::from_petgraph(pg(), [missing], []).unwrap_err(),
+            ImportError::UnknownVertex {
+                index: missing.index()
+            }
+        );
+        assert_eq!(
+            GGraph::from_petgraph(pg(), [], [spider]).unwrap_err(),
+            ImportError::NotBoundary {
+                index: spider.index(),
+                kind: VertexKind::Z
+            }
+        );
+        assert_eq!(
+            GGraph::from_petgraph(pg(), [input, input], []).unwrap_err(),
+            ImportError::DuplicateBoundary {
+                index: input.index()
+            }
+        );
+        // A bare wire is both an input and an output.
+        assert!(GGraph::from_petgraph(pg(), [input], [input]).is_ok());
     }
 }
@@ -0,0 +1,2357 @@
+//! Rewrite rules simplifying diagrams.
+//!
+//! The rules are applied in rounds: each round finds a set of non-overlapping matches, rewrites
+//! them, and removes the deleted vertices at the end with `Graph::remove_vertices`, which keeps
+//! the indices collected during the round valid.
+//!
+//! With the `rayon` feature, local complementation and pivoting match all the vertices of a
+//! round in parallel against the graph at the start of the round, and then pick the matches in
+//! vertex order as usual. A match only depends on the neighbourhood of the vertex, and matches
+//! overlapping a rewritten neighbourhood are skipped anyway, so the result does not depend on
+//! the feature.
+//!
+//! Every rule multiplies the scalar of the diagram by the factor it drops, so the rewritten
+//! diagram denotes the same linear map (see `Graph::scalar`). In debug builds, the drivers
+//! (`clifford_simp`, `full_simp` and `full_reduce`) check `Graph::check_invariants` after each
+//! pass and panic if the diagram was broken.
+//!
+//! The drivers accept graphs in either `GraphMode`. They start with `to_gh`, which reduces the
+//! parallel edges and self-loops between spiders, and in `GraphMode::Simple` the invariant
+//! checks then assert that every pass keeps the diagram simple.
+//!
+//! Grounded spiders (see `GroundGraph`) are only fused. Their phase is absorbed by the ground,
+//! so it is reset to 0. The other rules skip them.
+
+use crate::zx::graph::*;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+/// Matcher of a rule for a round over `vertices`, called with the graph during the round, the
+/// position of a vertex in `vertices` and the vertex.
+///
+/// Each vertex is matched when the round reaches it, so vertices touched earlier in the round
+/// are never matched.
+#[cfg(not(feature = "rayon"))]
+fn round_matcher<G, M, F>(
+    _g: &G,
+    _vertices: &[G::VertexIx],
+    matcher: F,
+) -> impl FnMut(&G, usize, G::VertexIx) -> Option<M>
+where
+    G: Graph,
+    F: Fn(&G, G::VertexIx) -> Option<M>,
+{
+    move |g, _, v| matcher(g, v)
+}
+
+/// Matcher of a rule for a round over `vertices`, called with the graph during the round, the
+/// position of a vertex in `vertices` and the vertex.
+///
+/// All the vertices are matched upfront in parallel, against the graph at the start of the
+/// round.
+#[cfg(feature = "rayon")]
+fn round_matcher<G, M, F>(
+    g: &G,
+    vertices: &[G::VertexIx],
+    matcher: F,
+) -> impl FnMut(&G, usize, G::VertexIx) -> Option<M>
+where
+    G: Graph,
+    M: MaybeSync,
+    F: Fn(&G, G::VertexIx) -> Option<M> + MaybeSync,
+{
+    use rayon::prelude::*;
+    let mut matches: Vec<_> = vertices.par_iter().map(|&v| matcher(g, v)).collect();
+    move |_, i, _| matches[i].take()
+}
+
+/// Whether a vertex is a Z or X spider not marked as a boundary of the diagram
+pub(crate) fn is_interior_spider<G: Graph>(g: &G, v: G::VertexIx) -> bool {
+    let kind = g.vertex(v).unwrap().kind();
+    (kind == VertexKind::Z || kind == VertexKind::X) && !g.is_input(v) && !g.is_output(v)
+}
+
+/// Kind of an edge with an extra Hadamard gate on it
+pub(crate) fn toggle(kind: EdgeKind) -> EdgeKind {
+    match kind {
+        EdgeKind::Regular => EdgeKind::Hadam,
+        EdgeKind::Hadam => EdgeKind::Regular,
+    }
+}
+
+/// Merge the spider `u` into `v`, moving its edges and adding its phase.
+///
+/// The edges between the two spiders become self-loops, which are dropped when regular and add
+/// a phase of pi and a factor of 1/sqrt(2) when Hadamard. `u` is left without edges, and must
+/// be removed by the caller. The phase of a grounded `v` is set to 0.
+///
+/// In `GraphMode::Simple` adding the moved edges may merge them with the edges of `v`, adding
+/// pi to its phase, so the phase is set first. The edges of `u` are removed before, so the
+/// merges of later fusions in the round cannot reach them.
+fn merge_spider<G: Graph>(g: &mut G, v: G::VertexIx, u: G::VertexIx) {
+    let mut phase = g.vertex(v).unwrap().phase() + g.vertex(u).unwrap().phase();
+    let (edges, legs): (Vec<_>, Vec<_>) = g
+        .incident_edges_with_neighbours(u)
+        .map(|(e, n)| (e, (n, g.edge(e).unwrap().kind())))
+        .unzip();
+    g.remove_edges(edges);
+    for &(n, kind) in &legs {
+        if (n == v || n == u) && kind == EdgeKind::Hadam {
+            phase += Phase::pi();
+            g.scalar_mut().add_power(-1);
+        }
+    }
+    g.set_phase(v, phase);
+    for (n, kind) in legs {
+        if n != v && n != u {
+            g.add_edge(v, n, kind);
+        }
+    }
+    if g.is_ground(v) {
+        g.set_phase(v, Phase::zero());
+    }
+}
+
+/// Fuse adjacent spiders of the same colour connected by a regular edge, until no more can be
+/// fused. Returns the number of fusions.
+///
+/// Vertices marked as inputs or outputs are never fused. The result of fusing a grounded spider
+/// is grounded, with phase 0.
+pub fn fuse_spiders<G: Graph>(g: &mut G) -> u32 {
+    let mut count = 0;
+    loop {
+        let mut touched = HashSet::new();
+        let mut removed = Vec::new();
+        let edges: Vec<_> = g.edges().collect();
+        for e in edges {
+            // Fusing removes edges, which can invalidate or renumber the collected indices, so
+            // each one is matched against the current graph.
+            let (v, u) = match g.edge_endpoints(e) {
+                Some(ends) => ends,
+                None => continue,
+            };
+            if v == u
+                || g.edge(e).unwrap().kind() != EdgeKind::Regular
+                || touched.contains(&v)
+                || touched.contains(&u)
+                || !is_interior_spider(g, v)
+                || g.vertex(v).unwrap().kind() != g.vertex(u).unwrap().kind()
+                || !is_interior_spider(g, u)
+            {
+                continue;
+            }
+            // Keep the grounded spider, if any, so its ground is not lost.
+            let (v, u) = if g.is_ground(u) { (u, v) } else { (v, u) };
+            merge_spider(g, v, u);
+            touched.insert(v);
+            touched.insert(u);
+            removed.push(u);
+        }
+        if removed.is_empty() {
+            return count;
+        }
+        count += removed.len() as u32;
+        g.remove_vertices(removed);
+    }
+}
+
+/// Remove phase-free spiders with two neighbours, connecting the neighbours directly, until no
+/// more can be removed. Returns the number of removed spiders.
+///
+/// The new edge is a Hadamard edge when exactly one of the two removed edges was. If the
+/// neighbours were already connected this leaves parallel edges between them, unless the graph
+/// is in `GraphMode::Simple`.
+pub fn remove_identities<G: Graph>(g: &mut G) -> u32 {
+    let mut count = 0;
+    loop {
+        let mut touched = HashSet::new();
+        let mut removed = Vec::new();
+        let vertices: Vec<_> = g.vertices().collect();
+        for v in vertices {
+            if touched.contains(&v)
+                || !is_interior_spider(g, v)
+                || g.is_ground(v)
+                || !g.vertex(v).unwrap().phase().is_zero()
+            {
+                continue;
+            }
+            let wire: Vec<_> = g.neighbours_with_kind(v).collect();
+            let (a, k1, b, k2) = match wire[..] {
+                [(a, k1), (b, k2)] if a != v && b != v && a != b => (a, k1, b, k2),
+                _ => continue,
+            };
+            if touched.contains(&a) || touched.contains(&b) {
+                continue;
+            }
+            let kind = if k1 == k2 {
+                EdgeKind::Regular
+            } else {
+                EdgeKind::Hadam
+            };
+            g.add_edge(a, b, kind);
+            touched.extend([v, a, b]);
+            removed.push(v);
+        }
+        if removed.is_empty() {
+            return count;
+        }
+        count += removed.len() as u32;
+        g.remove_vertices(removed);
+    }
+}
+
+/// Replace the arity-2 H-boxes with phase pi by Hadamard edges. Returns the number of removed
+/// H-boxes.
+fn absorb_hadamard_boxes<G: Graph>(g: &mut G) -> u32 {
+    let mut count = 0;
+    loop {
+        let mut touched = HashSet::new();
+        let mut removed = Vec::new();
+        let vertices: Vec<_> = g.vertices().collect();
+        for v in vertices {
+            let vdata = g.vertex(v).unwrap();
+            if touched.contains(&v)
+                || vdata.kind() != VertexKind::HBox
+                || vdata.phase() != Phase::pi()
+                || g.is_input(v)
+                || g.is_output(v)
+                || g.is_ground(v)
+            {
+                continue;
+            }
+            let wire: Vec<_> = g.neighbours_with_kind(v).collect();
+            let (a, k1, b, k2) = match wire[..] {
+                [(a, k1), (b, k2)] if a != v && b != v && a != b => (a, k1, b, k2),
+                _ => continue,
+            };
+            if touched.contains(&a) || touched.contains(&b) {
+                continue;
+            }
+            let kind = if k1 == k2 {
+                EdgeKind::Hadam
+            } else {
+                EdgeKind::Regular
+            };
+            // The H-box is sqrt(2) times a Hadamard gate.
+            g.add_edge(a, b, kind);
+            g.scalar_mut().add_power(1);
+            touched.extend([v, a, b]);
+            removed.push(v);
+        }
+        if removed.is_empty() {
+            return count;
+        }
+        count += removed.len() as u32;
+        g.remove_vertices(removed);
+    }
+}
+
+/// Simplify the parallel edges and self-loops between Z and X spiders, following the Hopf law.
+/// Returns the number of removed edges.
+///
+/// Between two spiders, edges that they could be fused along (see `fuse_spiders`) are reduced
+/// to a single one, and the other edges cancel in pairs, each contributing a factor of 1/2. If
+/// one of the latter is left next to a fusing edge, it is removed adding pi to the phase of one
+/// of the spiders and a factor of 1/sqrt(2). Regular self-loops are removed, and Hadamard
+/// self-loops are removed adding pi to the phase of the spider and a factor of 1/sqrt(2).
+pub fn reduce_parallel_edges<G: Graph>(g: &mut G) -> u32 {
+    let kind = |g: &G, v| g.vertex(v).unwrap().kind();
+    let is_spider = |g: &G, v| matches!(kind(g, v), VertexKind::Z | VertexKind::X);
+    // Sorting the edges by their endpoints puts the parallel ones next to each other.
+    let mut edges: Vec<_> = g
+        .edges()
+        .filter_map(|e| {
+            let (v, u) = g.edge_endpoints(e).unwrap();
+            (is_spider(g, v) && is_spider(g, u)).then_some(((v.min(u), v.max(u)), e))
+        })
+        .collect();
+    edges.sort_unstable();
+
+    let mut removed = Vec::new();
+    for group in edges.chunk_by(|a, b| a.0 == b.0) {
+        let (v, u) = group[0].0;
+        let group: Vec<_> = group.iter().map(|&(_, e)| e).collect();
+        let mut phase = g.vertex(v).unwrap().phase();
+        if v == u {
+            for e in group {
+                if g.edge(e).unwrap().kind() == EdgeKind::Hadam {
+                    phase += Phase::pi();
+                    g.scalar_mut().add_power(-1);
+                }
+                removed.push(e);
+            }
+            g.set_phase(v, phase);
+            continue;
+        }
+        let (fusing, hopf): (Vec<_>, Vec<_>) = group
+            .into_iter()
+            .partition(|&e| is_fusing_edge(g.edge(e).unwrap().kind(), kind(g, v), kind(g, u)));
+        removed.extend(fusing.iter().skip(1));
+        let pairs = hopf.len() / 2;
+        g.scalar_mut().add_power(-2 * pairs as i32);
+        removed.extend(hopf.iter().take(2 * pairs));
+        if hopf.len() % 2 == 1 && !fusing.is_empty() {
+            removed.push(hopf[2 * pairs]);
+            g.scalar_mut().add_power(-1);
+            g.set_phase(v, phase + Phase::pi());
+        }
+    }
+    let count = removed.len() as u32;
+    g.remove_edges(removed);
+    count
+}
+
+/// Whether a diagram has only Z spiders besides boundaries and H-boxes, and no parallel
+/// Hadamard edges.
+fn is_gh<G: Graph>(g: &G) -> bool {
+    let no_x = g
+        .vertices()
+        .all(|v| g.vertex(v).unwrap().kind() != VertexKind::X);
+    let mut seen = HashSet::new();
+    let no_parallel = g
+        .edges()
+        .filter(|&e| g.edge(e).unwrap().kind() == EdgeKind::Hadam)
+        .all(|e| {
+            let (v, u) = g.edge_endpoints(e).unwrap();
+            let z = |v| g.vertex(v).unwrap().kind() == VertexKind::Z;
+            v == u || !z(v) || !z(u) || seen.insert((v.min(u), v.max(u)))
+        });
+    no_x && no_parallel
+}
+
+/// Turn every X spider into a Z spider by toggling the kind of its edges, so the only spiders
+/// left are Z spiders.
+///
+/// Arity-2 H-boxes with phase pi become Hadamard edges, and the parallel edges between spiders
+/// are reduced with `reduce_parallel_edges`.
+pub fn to_gh<G: Graph>(g: &mut G) {
+    absorb_hadamard_boxes(g);
+
+    let is_x = |g: &G, v| g.vertex(v).unwrap().kind() == VertexKind::X;
+    let edges: Vec<_> = g.edges().collect();
+    for e in edges {
+        // A self-loop gets a Hadamard gate at each end, which cancel out.
+        let (v, u) = g.edge_endpoints(e).unwrap();
+        if v != u && is_x(g, v) != is_x(g, u) {
+            let kind = g.edge(e).unwrap().kind();
+            g.set_edge_kind(e, toggle(kind));
+        }
+    }
+    let vertices: Vec<_> = g.vertices().collect();
+    for v in vertices {
+        if is_x(g, v) {
+            g.set_vertex_kind(v, VertexKind::Z);
+        }
+    }
+
+    reduce_parallel_edges(g);
+    debug_assert!(is_gh(g), "to_gh left X spiders or parallel Hadamard edges");
+}
+
+/// Neighbours of a vertex through Hadamard edges, if it is an interior Z spider connected only
+/// by single Hadamard edges to other interior Z spiders.
+fn hadamard_neighbourhood<G: Graph>(g: &G, v: G::VertexIx) -> Option<Vec<G::VertexIx>> {
+    if g.vertex(v).unwrap().kind() != VertexKind::Z || !is_interior_spider(g, v) {
+        return None;
+    }
+    let mut neighbours = Vec::new();
+    for (n, kind) in g.neighbours_with_kind(v) {
+        if n == v
+            || kind != EdgeKind::Hadam
+            || g.vertex(n).unwrap().kind() != VertexKind::Z
+            || !is_interior_spider(g, n)
+        {
+            return None;
+        }
+        neighbours.push(n);
+    }
+    neighbours.sort_unstable();
+    let before = neighbours.len();
+    neighbours.dedup();
+    if neighbours.len() != before {
+        return None;
+    }
+    Some(neighbours)
+}
+
+/// Neighbourhood of a vertex where local complementation applies, see `local_complementation`.
+fn lcomp_match<G: Graph>(g: &G, v: G::VertexIx) -> Option<Vec<G::VertexIx>> {
+    let phase = g.vertex(v).unwrap().phase();
+    if (phase != Phase::new(1, 2) && phase != Phase::new(3, 2)) || g.is_ground(v) {
+        return None;
+    }
+    hadamard_neighbourhood(g, v)
+}
+
+/// Rewrite the neighbourhood of `v` for local complementation, leaving `v` to be removed.
+fn apply_lcomp<G: Graph>(g: &mut G, v: G::VertexIx, neighbours: &[G::VertexIx]) {
+    let delta = -g.vertex(v).unwrap().phase();
+    // Complementing a pair multiplies the scalar by sqrt(2) when it adds a Hadamard edge and by
+    // 1/sqrt(2) when it removes one, while `add_edge_smart` accounts for 1 and 1/2.
+    let n = neighbours.len() as i32;
+    g.scalar_mut().add_power(1 - n + n * (n - 1) / 2);
+    g.scalar_mut().add_phase(if delta == Phase::new(3, 2) {
+        Phase::new(1, 4)
+    } else {
+        Phase::new(-1, 4)
+    });
+    for (i, &a) in neighbours.iter().enumerate() {
+        for &b in &neighbours[i + 1..] {
+            g.add_edge_smart(a, b, EdgeKind::Hadam);
+        }
+        let phase = g.vertex(a).unwrap().phase() + delta;
+        g.set_phase(a, phase);
+    }
+}
+
+/// Remove a Z spider with phase ±pi/2 by complementing the edges between its neighbours and
+/// adding ∓pi/2 to their phases.
+///
+/// The spider and its neighbours must be interior Z spiders, connected by single Hadamard
+/// edges. Returns `false` without modifying the graph if the rule does not apply. Removing the
+/// spider may renumber other vertices, see `Graph::remove_vertex`.
+pub fn local_complementation<G: Graph>(g: &mut G, v: G::VertexIx) -> bool {
+    match lcomp_match(g, v) {
+        Some(neighbours) => {
+            apply_lcomp(g, v, &neighbours);
+            g.remove_vertex(v);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Apply local complementation until no vertex matches. Returns the number of removed spiders.
+pub fn lcomp_simp<G: Graph>(g: &mut G) -> u32 {
+    let mut count = 0;
+    loop {
+        let mut touched = HashSet::new();
+        let mut removed = Vec::new();
+        let vertices: Vec<_> = g.vertices().collect();
+        let mut matcher = round_matcher(g, &vertices, lcomp_match);
+        for (i, &v) in vertices.iter().enumerate() {
+            if touched.contains(&v) {
+                continue;
+            }
+            let neighbours = match matcher(g, i, v) {
+                Some(neighbours) => neighbours,
+                None => continue,
+            };
+            if neighbours.iter().any(|n| touched.contains(n)) {
+                continue;
+            }
+            apply_lcomp(g, v, &neighbours);
+            touched.insert(v);
+            touched.extend(neighbours);
+            removed.push(v);
+        }
+        if removed.is_empty() {
+            return count;
+        }
+        count += removed.len() as u32;
+        g.remove_vertices(removed);
+    }
+}
+
+/// Neighbourhoods of the two vertices of a pivot
+type PivotNeighbourhoods<V> = (Vec<V>, Vec<V>);
+
+/// Neighbourhoods of two adjacent vertices where pivoting applies, see `pivot`.
+fn pivot_match<G: Graph>(
+    g: &G,
+    u: G::VertexIx,
+    v: G::VertexIx,
+) -> Option<PivotNeighbourhoods<G::VertexIx>> {
+    let pauli = |w| g.vertex(w).unwrap().phase().is_pauli();
+    if u == v || !pauli(u) || !pauli(v) || g.is_ground(u) || g.is_ground(v) {
+        return None;
+    }
+    let nu = hadamard_neighbourhood(g, u)?;
+    if !nu.contains(&v) {
+        return None;
+    }
+    let nv = hadamard_neighbourhood(g, v)?;
+    Some((nu, nv))
+}
+
+/// Rewrite the neighbourhoods of `u` and `v` for pivoting, leaving them to be removed.
+fn apply_pivot<G: Graph>(
+    g: &mut G,
+    u: G::VertexIx,
+    v: G::VertexIx,
+    nu: &[G::VertexIx],
+    nv: &[G::VertexIx],
+) {
+    let (phase_u, phase_v) = (g.vertex(u).unwrap().phase(), g.vertex(v).unwrap().phase());
+    let only_u: Vec<_> = nu
+        .iter()
+        .copied()
+        .filter(|w| *w != v && !nv.contains(w))
+        .collect();
+    let only_v: Vec<_> = nv
+        .iter()
+        .copied()
+        .filter(|w| *w != u && !nu.contains(w))
+        .collect();
+    let shared: Vec<_> = nu.iter().copied().filter(|w| nv.contains(w)).collect();
+
+    // As in `apply_lcomp`, each complemented pair contributes a factor of sqrt(2).
+    let dropped = only_u.len() + only_v.len() + 2 * shared.len();
+    let pairs = only_u.len() * only_v.len() + (only_u.len() + only_v.len()) * shared.len();
+    g.scalar_mut().add_power(1 - dropped as i32 + pairs as i32);
+    if phase_u == Phase::pi() && phase_v == Phase::pi() {
+        g.scalar_mut().add_phase(Phase::pi());
+    }
+
+    for (xs, ys) in [(&only_u, &only_v), (&only_u, &shared), (&only_v, &shared)] {
+        for &x in xs.iter() {
+            for &y in ys.iter() {
+                g.add_edge_smart(x, y, EdgeKind::Hadam);
+            }
+        }
+    }
+    let deltas = [
+        (&only_u, phase_v),
+        (&only_v, phase_u),
+        (&shared, phase_u + phase_v + Phase::pi()),
+    ];
+    for (ws, delta) in deltas {
+        for &w in ws.iter() {
+            let phase = g.vertex(w).unwrap().phase() + delta;
+            g.set_phase(w, phase);
+        }
+    }
+}
+
+/// Variant of the pivoting rule, see `pivot`, `pivot_boundary` and `pivot_gadget`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PivotRule {
+    Interior,
+    Boundary,
+    Gadget,
+}
+
+/// Neighbourhoods of two adjacent vertices where a pivoting variant applies. For a boundary
+/// pivot, the boundary vertex is included in the neighbourhood of `v`.
+fn pivot_rule_match<G: Graph>(
+    g: &G,
+    rule: PivotRule,
+    u: G::VertexIx,
+    v: G::VertexIx,
+) -> Option<PivotNeighbourhoods<G::VertexIx>> {
+    match rule {
+        PivotRule::Interior => pivot_match(g, u, v),
+        PivotRule::Boundary => boundary_pivot_match(g, u, v),
+        PivotRule::Gadget => gadget_pivot_match(g, u, v),
+    }
+}
+
+/// Neighbourhoods of an interior Pauli spider `u` and a Pauli spider `v` next to a single
+/// boundary, see `pivot_boundary`.
+fn boundary_pivot_match<G: Graph>(
+    g: &G,
+    u: G::VertexIx,
+    v: G::VertexIx,
+) -> Option<PivotNeighbourhoods<G::VertexIx>> {
+    let pauli = |w| g.vertex(w).unwrap().phase().is_pauli();
+    if u == v || !pauli(u) || !pauli(v) || g.is_ground(u) || g.is_ground(v) {
+        return None;
+    }
+    let nu = hadamard_neighbourhood(g, u)?;
+    if !nu.contains(&v) {
+        return None;
+    }
+    // `v` is an interior Z spider, since it is a neighbour of `u`.
+    let mut boundary = None;
+    let mut nv = Vec::new();
+    for (n, kind) in g.neighbours_with_kind(v) {
+        if n != v && g.vertex(n).unwrap().kind() == VertexKind::Boundary {
+            if boundary.replace(n).is_some() {
+                return None;
+            }
+        } else if n == v
+            || kind != EdgeKind::Hadam
+            || g.vertex(n).unwrap().kind() != VertexKind::Z
+            || !is_interior_spider(g, n)
+        {
+            return None;
+        } else {
+            nv.push(n);
+        }
+    }
+    nv.sort_unstable();
+    let before = nv.len();
+    nv.dedup();
+    if nv.len() != before {
+        return None;
+    }
+    nv.push(boundary?);
+    Some((nu, nv))
+}
+
+/// Neighbourhoods of an interior Pauli spider `u` and an interior non-Pauli spider `v`, see
+/// `pivot_gadget`.
+///
+/// Gadget leaves are left alone: `v` must not be one, and no neighbour of `u` or `v` may have
+/// a single neighbour. Otherwise the pivot could turn a leaf back into a spider to gadgetize.
+fn gadget_pivot_match<G: Graph>(
+    g: &G,
+    u: G::VertexIx,
+    v: G::VertexIx,
+) -> Option<PivotNeighbourhoods<G::VertexIx>> {
+    let pauli = |w| g.vertex(w).unwrap().phase().is_pauli();
+    if u == v || !pauli(u) || pauli(v) || g.is_ground(u) || g.is_ground(v) {
+        return None;
+    }
+    let nu = hadamard_neighbourhood(g, u)?;
+    if !nu.contains(&v) {
+        return None;
+    }
+    let nv = hadamard_neighbourhood(g, v)?;
+    if nv.len() < 2 || nu.iter().chain(&nv).any(|&w| g.vertex_degree(w) == 1) {
+        return None;
+    }
+    Some((nu, nv))
+}
+
+/// Rewrite `v` so that the pivot applies, then pivot, leaving `u` and `v` to be removed.
+fn apply_pivot_rule<G: Graph>(
+    g: &mut G,
+    rule: PivotRule,
+    u: G::VertexIx,
+    v: G::VertexIx,
+    nu: &[G::VertexIx],
+    nv: &[G::VertexIx],
+) {
+    match rule {
+        PivotRule::Interior => apply_pivot(g, u, v, nu, nv),
+        PivotRule::Boundary => {
+            // Unfuse the boundary wire into a phase-free spider, which is the identity:
+            // `v -k- b` becomes `v -H- z -k'- b`, where `k'` is `k` with an extra Hadamard.
+            let b = *nv.last().unwrap();
+            let e = g.edge_between(v, b).unwrap();
+            let kind = g.edge(e).unwrap().kind();
+            g.remove_edge(e);
+            let z = g.add_vertex(VertexKind::Z, Phase::zero());
+            g.add_edge(v, z, EdgeKind::Hadam);
+            g.add_edge(z, b, toggle(kind));
+            let mut nv = nv[..nv.len() - 1].to_vec();
+            nv.push(z);
+            apply_pivot(g, u, v, nu, &nv);
+        }
+        PivotRule::Gadget => {
+            // Move the phase of `v` into a new gadget, which is the identity: a spider with
+            // phase `a` is a phase-free spider fused with a leaf of phase `a`, and the plain
+            // edge to the leaf is two Hadamard edges around a phase-free axle.
+            let phase = g.vertex(v).unwrap().phase();
+            g.set_phase(v, Phase::zero());
+            let axle = g.add_vertex(VertexKind::Z, Phase::zero());
+            let leaf = g.add_vertex(VertexKind::Z, phase);
+            g.add_edge(v, axle, EdgeKind::Hadam);
+            g.add_edge(axle, leaf, EdgeKind::Hadam);
+            let mut nv = nv.to_vec();
+            nv.push(axle);
+            apply_pivot(g, u, v, nu, &nv);
+            // The axle got the phase of `u`. An axle with phase pi is a phase-free axle with
+            // the leaf phase negated, times `e^{i a}`.
+            if g.vertex(axle).unwrap().phase() == Phase::pi() {
+                g.set_phase(axle, Phase::zero());
+                g.set_phase(leaf, -phase);
+                g.scalar_mut().add_phase(phase);
+            }
+        }
+    }
+}
+
+/// Apply a pivoting variant until no pair matches. Returns the number of pivots.
+fn pivot_rule_simp<G: Graph>(g: &mut G, rule: PivotRule) -> u32 {
+    let mut count = 0;
+    loop {
+        let mut touched = HashSet::new();
+        let mut removed = Vec::new();
+        let vertices: Vec<_> = g.vertices().collect();
+        // All the pivots at `u`, so that the round can pick one that does not overlap.
+        let mut matcher = round_matcher(g, &vertices, |g, u| {
+            let found: Vec<_> = g
+                .neighbours(u)
+                .filter_map(|v| pivot_rule_match(g, rule, u, v).map(|(nu, nv)| (v, nu, nv)))
+                .collect();
+            Some(found)
+        });
+        for (i, &u) in vertices.iter().enumerate() {
+            if touched.contains(&u) {
+                continue;
+            }
+            for (v, nu, nv) in matcher(g, i, u).unwrap() {
+                if nu.iter().chain(&nv).any(|w| touched.contains(w)) {
+                    continue;
+                }
+                apply_pivot_rule(g, rule, u, v, &nu, &nv);
+                touched.extend(nu.into_iter().chain(nv));
+                removed.extend([u, v]);
+                break;
+            }
+        }
+        if removed.is_empty() {
+            return count;
+        }
+        count += removed.len() as u32 / 2;
+        g.remove_vertices(removed);
+    }
+}
+
+/// Apply a pivoting variant to a single pair, removing it if it matches.
+fn pivot_rule<G: Graph>(g: &mut G, rule: PivotRule, u: G::VertexIx, v: G::VertexIx) -> bool {
+    match pivot_rule_match(g, rule, u, v) {
+        Some((nu, nv)) => {
+            apply_pivot_rule(g, rule, u, v, &nu, &nv);
+            g.remove_vertices(vec![u, v]);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Remove two adjacent Z spiders with phases 0 or pi, complementing the edges between their
+/// neighbourhoods and updating the neighbours' phases.
+///
+/// Both spiders and all their neighbours must be interior Z spiders connected by single
+/// Hadamard edges, so pairs next to a boundary are rejected. Returns `false` without modifying
+/// the graph if the rule does not apply. Removing the spiders may renumber other vertices, see
+/// `Graph::remove_vertex`.
+pub fn pivot<G: Graph>(g: &mut G, u: G::VertexIx, v: G::VertexIx) -> bool {
+    pivot_rule(g, PivotRule::Interior, u, v)
+}
+
+/// Apply pivoting until no pair matches. Returns the number of pivots.
+pub fn pivot_simp<G: Graph>(g: &mut G) -> u32 {
+    pivot_rule_simp(g, PivotRule::Interior)
+}
+
+/// Pivot an interior Z spider `u` with phase 0 or pi and a Z spider `v` with phase 0 or pi
+/// that is also connected to a single boundary.
+///
+/// The boundary wire of `v` is first unfused into a new phase-free Z spider, which then takes
+/// the place of the boundary in the neighbourhood of `v`, so `pivot` applies. Apart from the
+/// boundary, the conditions of `pivot` hold. Returns `false` without modifying the graph if the
+/// rule does not apply. Other vertices may be renumbered, see `Graph::remove_vertex`.
+pub fn pivot_boundary<G: Graph>(g: &mut G, u: G::VertexIx, v: G::VertexIx) -> bool {
+    pivot_rule(g, PivotRule::Boundary, u, v)
+}
+
+/// Apply `pivot_boundary` until no pair matches. Returns the number of pivots.
+pub fn pivot_boundary_simp<G: Graph>(g: &mut G) -> u32 {
+    pivot_rule_simp(g, PivotRule::Boundary)
+}
+
+/// Pivot an interior Z spider `u` with phase 0 or pi and an interior Z spider `v` with any
+/// other phase.
+///
+/// The phase of `v` is first moved into a new phase gadget attached to it, so `pivot` applies,
+/// and the gadget is left acting on the former neighbours of `u` and `v`. Gadget leaves are
+/// never gadgetized again, so repeated application terminates. Returns `false` without
+/// modifying the graph if the rule does not apply. Other vertices may be renumbered, see
+/// `Graph::remove_vertex`.
+pub fn pivot_gadget<G: Graph>(g: &mut G, u: G::VertexIx, v: G::VertexIx) -> bool {
+    pivot_rule(g, PivotRule::Gadget, u, v)
+}
+
+/// Apply `pivot_gadget` until no pair matches. Returns the number of pivots.
+pub fn pivot_gadget_simp<G: Graph>(g: &mut G) -> u32 {
+    pivot_rule_simp(g, PivotRule::Gadget)
+}
+
+/// A degree-1 spider with phase 0 or pi and the spider it copies through, see `copy_simp`
+struct CopyMatch<V> {
+    state: V,
+    spider: V,
+    /// Whether the state has phase pi
+    pi: bool,
+    /// Other edges of the copied spider
+    legs: Vec<(V, EdgeKind)>,
+}
+
+/// Match the copy rule at a degree-1 spider.
+fn copy_match<G: Graph>(g: &G, s: G::VertexIx) -> Option<CopyMatch<G::VertexIx>> {
+    if !is_interior_spider(g, s) || g.is_ground(s) {
+        return None;
+    }
+    let phase = g.vertex(s).unwrap().phase();
+    if !phase.is_pauli() {
+        return None;
+    }
+    let (v, kind) = match g.neighbours_with_kind(s).collect::<Vec<_>>()[..] {
+        [(v, kind)] if v != s => (v, kind),
+        _ => return None,
+    };
+    if !is_interior_spider(g, v)
+        || g.is_ground(v)
+        || is_fusing_edge(
+            kind,
+            g.vertex(s).unwrap().kind(),
+            g.vertex(v).unwrap().kind(),
+        )
+    {
+        return None;
+    }
+    let mut legs = Vec::new();
+    let mut seen_state = false;
+    for (w, k) in g.neighbours_with_kind(v) {
+        if w == v {
+            return None;
+        } else if w == s && !seen_state {
+            seen_state = true;
+        } else {
+            legs.push((w, k));
+        }
+    }
+    Some(CopyMatch {
+        state: s,
+        spider: v,
+        pi: !phase.is_zero(),
+        legs,
+    })
+}
+
+/// Apply the copy rule, leaving the state and the copied spider to be removed.
+///
+/// The copies are Z spiders: a degree-1 X spider is the same map as a degree-1 Z spider with the
+/// same phase behind a Hadamard edge.
+fn apply_copy<G: Graph>(g: &mut G, m: &CopyMatch<G::VertexIx>) {
+    let data = g.vertex(m.spider).unwrap();
+    let (spider_kind, spider_phase) = (data.kind(), data.phase());
+    // The state is sqrt(2) times a basis state of the copied spider, which becomes that basis
+    // state on each leg, and each copy is sqrt(2) times it.
+    g.scalar_mut().add_power(1 - m.legs.len() as i32);
+    let phase = if m.pi {
+        g.scalar_mut().add_phase(spider_phase);
+        Phase::pi()
+    } else {
+        Phase::zero()
+    };
+    for &(w, kind) in &m.legs {
+        let kind = match spider_kind {
+            VertexKind::Z => toggle(kind),
+            _ => kind,
+        };
+        let copy = g.add_vertex(VertexKind::Z, phase);
+        g.add_edge(copy, w, kind);
+    }
+}
+
+/// Apply the copy rule until no spider matches. Returns the number of copied spiders.
+///
+/// A degree-1 spider with phase 0 or pi is a basis state of the spiders of the other colour:
+/// connected to one of them by a regular edge (or to one of the same colour by a Hadamard
+/// edge), it is copied to each of the other legs of that spider, which is removed. The copied
+/// spider can have any phase, which becomes a global phase when the state has phase pi. Both
+/// spiders must be interior and not grounded, and the copied spider must not have self-loops.
+pub fn copy_simp<G: Graph>(g: &mut G) -> u32 {
+    let mut count = 0;
+    loop {
+        let mut touched = HashSet::new();
+        let mut removed = Vec::new();
+        let vertices: Vec<_> = g.vertices().collect();
+        for v in vertices {
+            if touched.contains(&v) {
+                continue;
+            }
+            let m = match copy_match(g, v) {
+                Some(m) => m,
+                None => continue,
+            };
+            if touched.contains(&m.spider) || m.legs.iter().any(|(w, _)| touched.contains(w)) {
+                continue;
+            }
+            apply_copy(g, &m);
+            touched.extend([m.state, m.spider]);
+            touched.extend(m.legs.iter().map(|&(w, _)| w));
+            removed.extend([m.state, m.spider]);
+        }
+        if removed.is_empty() {
+            return count;
+        }
+        count += removed.len() as u32 / 2;
+        g.remove_vertices(removed);
+    }
+}
+
+/// Push a pi phase through a spider.
+///
+/// `v` must be a phase-pi spider with two neighbours, one of them the spider `through`, with
+/// which it does not fuse: a Pauli gate on one of the legs of `through`. The pi phase is moved
+/// to all the other legs of `through` as new spiders of the colour of `v`, the phase of
+/// `through` is negated, and `v` is removed, connecting its other neighbour to `through`. The
+/// scalar picks up the old phase of `through`. Both spiders must be interior and not grounded,
+/// and `through` must not have self-loops.
+///
+/// Returns `false` without modifying the graph if the rule does not apply. Removing `v` may
+/// renumber other vertices, see `Graph::remove_vertex`.
+pub fn pi_commute<G: Graph>(g: &mut G, v: G::VertexIx, through: G::VertexIx) -> bool {
+    if v == through
+        || !is_interior_spider(g, v)
+        || !is_interior_spider(g, through)
+        || g.is_ground(v)
+        || g.is_ground(through)
+        || g.vertex(v).unwrap().phase() != Phase::pi()
+    {
+        return false;
+    }
+    let (edge, other, other_kind) = match g.neighbours_with_kind(v).collect::<Vec<_>>()[..] {
+        [(a, ka), (b, kb)] if a == through && b != through && b != v => (ka, b, kb),
+        [(a, ka), (b, kb)] if b == through && a != through && a != v => (kb, a, ka),
+        _ => return false,
+    };
+    let (kind_v, kind_through) = (
+        g.vertex(v).unwrap().kind(),
+        g.vertex(through).unwrap().kind(),
+    );
+    if is_fusing_edge(edge, kind_v, kind_through) {
+        return false;
+    }
+    let legs: Vec<_> = g.incident_edges_with_neighbours(through).collect();
+    if legs.iter().any(|&(_, w)| w == through) {
+        return false;
+    }
+
+    // The Pauli gate of `v`, as a spider connected to `through` by a regular edge.
+    let pauli = match kind_through {
+        VertexKind::Z => VertexKind::X,
+        _ => VertexKind::Z,
+    };
+    let legs: Vec<_> = legs.into_iter().filter(|&(_, w)| w != v).collect();
+    let kinds: Vec<_> = legs
+        .iter()
+        .map(|&(e, _)| g.edge(e).unwrap().kind())
+        .collect();
+    g.remove_edges(legs.iter().map(|&(e, _)| e));
+    for (&(_, w), kind) in legs.iter().zip(kinds) {
+        let p = g.add_vertex(pauli, Phase::pi());
+        g.add_edge(through, p, EdgeKind::Regular);
+        g.add_edge(p, w, kind);
+    }
+    let phase = g.vertex(through).unwrap().phase();
+    g.scalar_mut().add_phase(phase);
+    g.set_phase(through, -phase);
+    let kind = if edge == other_kind {
+        EdgeKind::Regular
+    } else {
+        EdgeKind::Hadam
+    };
+    g.add_edge(other, through, kind);
+    g.remove_vertex(v);
+    true
+}
+
+/// Other edges of a phase-free spider of the given colour, or `None` if it is not an interior,
+/// ungrounded, phase-free spider of that colour without self-loops.
+fn bialgebra_legs<G: Graph>(
+    g: &G,
+    v: G::VertexIx,
+    kind: VertexKind,
+) -> Option<Vec<(G::VertexIx, EdgeKind)>> {
+    let data = g.vertex(v)?;
+    if data.kind() != kind || !data.phase().is_zero() || !is_interior_spider(g, v) || g.is_ground(v)
+    {
+        return None;
+    }
+    let legs: Vec<_> = g.neighbours_with_kind(v).collect();
+    if legs.iter().any(|&(w, _)| w == v) {
+        return None;
+    }
+    Some(legs)
+}
+
+/// √2 power of the scalar dropped by the bialgebra rule, for a Z spider with `p` other legs and
+/// an X spider with `q` other legs.
+fn bialgebra_power(p: usize, q: usize) -> i32 {
+    (p as i32 - 1) * (q as i32 - 1)
+}
+
+/// Apply the bialgebra rule to a Z spider `z` and an X spider `x`.
+///
+/// Both spiders must be phase-free, interior and not grounded, without self-loops, and
+/// connected by a single regular edge. With `p` other legs on `z` and `q` on `x`, they are
+/// replaced by `p` X spiders, one on each leg of `z`, and `q` Z spiders, one on each leg of `x`,
+/// with a regular edge between each X and each Z spider. The legs keep their kinds. Each side
+/// of the rule has the other as its reverse, see `rev_bialgebra`.
+///
+/// Returns `false` without modifying the graph if the rule does not apply. Removing the two
+/// spiders may renumber other vertices, see `Graph::remove_vertex`.
+pub fn bialgebra<G: Graph>(g: &mut G, z: G::VertexIx, x: G::VertexIx) -> bool {
+    let (z_legs, x_legs) = match (
+        bialgebra_legs(g, z, VertexKind::Z),
+        bialgebra_legs(g, x, VertexKind::X),
+    ) {
+        (Some(z_legs), Some(x_legs)) => (z_legs, x_legs),
+        _ => return false,
+    };
+    let (shared, z_legs): (Vec<_>, Vec<_>) = z_legs.into_iter().partition(|&(w, _)| w == x);
+    if shared[..] != [(x, EdgeKind::Regular)] {
+        return false;
+    }
+    let x_legs: Vec<_> = x_legs.into_iter().filter(|&(w, _)| w != z).collect();
+
+    g.scalar_mut()
+        .add_power(bialgebra_power(z_legs.len(), x_legs.len()));
+    let xs: Vec<_> = z_legs
+        .iter()
+        .map(|&(w, kind)| {
+            let v = g.add_vertex(VertexKind::X, Phase::zero());
+            g.add_edge(v, w, kind);
+            v
+        })
+        .collect();
+    for &(w, kind) in &x_legs {
+        let v = g.add_vertex(VertexKind::Z, Phase::zero());
+        g.add_edge(v, w, kind);
+        for &u in &xs {
+            g.add_edge(v, u, EdgeKind::Regular);
+        }
+    }
+    g.remove_vertices([z, x]);
+    true
+}
+
+/// Collapse a complete bipartite pattern of Z spiders `zs` and X spiders `xs`, the reverse of
+/// `bialgebra`.
+///
+/// All the spiders must be phase-free, interior and not grounded. Each Z spider must be
+/// connected by a single regular edge to each X spider, and have exactly one other edge, to a
+/// vertex outside the pattern; likewise for the X spiders. The pattern is replaced by a Z
+/// spider on the other legs of `xs` and an X spider on the other legs of `zs`, connected by a
+/// regular edge. Both lists must be non-empty and without repetitions.
+///
+/// Returns `false` without modifying the graph if the rule does not apply. Removing the pattern
+/// may renumber other vertices, see `Graph::remove_vertex`.
+pub fn rev_bialgebra<G: Graph>(g: &mut G, zs: &[G::VertexIx], xs: &[G::VertexIx]) -> bool {
+    // The leg of each spider leaving the pattern, if it matches.
+    let outer_leg = |v, kind, others: &[G::VertexIx]| {
+        let legs = bialgebra_legs(g, v, kind)?;
+        let (inner, outer): (Vec<_>, Vec<_>) =
+            legs.into_iter().partition(|&(w, _)| others.contains(&w));
+        let complete = inner.len() == others.len()
+            && inner.iter().all(|&(_, k)| k == EdgeKind::Regular)
+            && others.iter().all(|o| inner.iter().any(|&(w, _)| w == *o));
+        match outer[..] {
+            [(w, k)] if complete && !zs.contains(&w) && !xs.contains(&w) => Some((w, k)),
+            _ => None,
+        }
+    };
+    if zs.is_empty() || xs.is_empty() {
+        return false;
+    }
+    let mut z_outer = Vec::new();
+    for (i, &v) in zs.iter().enumerate() {
+        if zs[..i].contains(&v) {
+            return false;
+        }
+        match outer_leg(v, VertexKind::Z, xs) {
+            Some(leg) => z_outer.push(leg),
+            None => return false,
+        }
+    }
+    let mut x_outer = Vec::new();
+    for (i, &v) in xs.iter().enumerate() {
+        if xs[..i].contains(&v) {
+            return false;
+        }
+        match outer_leg(v, VertexKind::X, zs) {
+            Some(leg) => x_outer.push(leg),
+            None => return false,
+        }
+    }
+
+    g.scalar_mut()
+        .add_power(-bialgebra_power(xs.len(), zs.len()));
+    let z = g.add_vertex(VertexKind::Z, Phase::zero());
+    let x = g.add_vertex(VertexKind::X, Phase::zero());
+    g.add_edge(z, x, EdgeKind::Regular);
+    for (w, kind) in x_outer {
+        g.add_edge(z, w, kind);
+    }
+    for (w, kind) in z_outer {
+        g.add_edge(x, w, kind);
+    }
+    g.remove_vertices(zs.iter().chain(xs).copied());
+    true
+}
+
+/// Number of rule applications of a simplification strategy
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SimplifyStats {
+    /// Passes over all the rules, including the final one that found nothing to do
+    pub iterations: u32,
+    pub fusions: u32,
+    pub identities: u32,
+    pub lcomps: u32,
+    pub pivots: u32,
+    /// Spiders copied through by a state, see `copy_simp`
+    pub copies: u32,
+    /// Phase gadgets removed by `gadget_simp`, in the strategies that use it
+    pub gadgets: u32,
+    /// Pivots next to a boundary, see `pivot_boundary`
+    pub boundary_pivots: u32,
+    /// Pivots that gadgetize a non-Clifford spider, see `pivot_gadget`
+    pub gadget_pivots: u32,
+    /// Rounds of the outer loop of `full_reduce`
+    pub rounds: u32,
+    /// T-count before and after, in the strategies that track it
+    pub tcount: Option<(u32, u32)>,
+    /// Whether `full_reduce_with_limit` stopped at its round limit before reaching a fixpoint
+    pub stopped_early: bool,
+}
+
+impl SimplifyStats {
+    /// Add the rule applications of another run.
+    fn absorb(&mut self, other: SimplifyStats) {
+        self.iterations += other.iterations;
+        self.fusions += other.fusions;
+        self.identities += other.identities;
+        self.lcomps += other.lcomps;
+        self.pivots += other.pivots;
+        self.copies += other.copies;
+        self.gadgets += other.gadgets;
+        self.boundary_pivots += other.boundary_pivots;
+        self.gadget_pivots += other.gadget_pivots;
+        self.rounds += other.rounds;
+    }
+}
+
+impl fmt::Display for SimplifyStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "SimplifyStats(iterations={})", self.iterations)?;
+        writeln!(f, "rule applications:")?;
+        writeln!(f, "  fusion: {}", self.fusions)?;
+        writeln!(f, "  identity removal: {}", self.identities)?;
+        writeln!(f, "  local complementation: {}", self.lcomps)?;
+        writeln!(f, "  pivot: {}", self.pivots)?;
+        writeln!(f, "  copy: {}", self.copies)?;
+        writeln!(f, "  gadget removal: {}", self.gadgets)?;
+        writeln!(f, "  boundary pivot: {}", self.boundary_pivots)?;
+        writeln!(f, "  gadget pivot: {}", self.gadget_pivots)?;
+        if let Some((before, after)) = self.tcount {
+            writeln!(f, "T-count: {} -> {}", before, after)?;
+        }
+        if self.stopped_early {
+            writeln!(f, "stopped after {} rounds", self.rounds)?;
+        }
+        Ok(())
+    }
+}
+
+/// Panic if a pass broke the invariants of the diagram, see `Graph::check_invariants`. Only
+/// checks in debug builds.
+fn debug_check<G: Graph>(g: &G, pass: &str) {
+    if cfg!(debug_assertions) {
+        if let Err(violations) = g.check_invariants() {
+            let messages: Vec<_> = violations.iter().map(|v| v.to_string()).collect();
+            panic!("{} broke the diagram: {}", pass, messages.join("; "));
+        }
+    }
+}
+
+/// Simplify the interior of a diagram with the Clifford rules until none applies.
+///
+/// After converting to the `to_gh` form, each iteration copies states through spiders, fuses
+/// spiders, reduces parallel edges, and applies local complementation, pivoting and identity
+/// removal.
+pub fn clifford_simp<G: Graph>(g: &mut G) -> SimplifyStats {
+    let mut stats = SimplifyStats::default();
+    to_gh(g);
+    debug_check(g, "to_gh");
+    loop {
+        stats.iterations += 1;
+        let copies = copy_simp(g);
+        debug_check(g, "copy_simp");
+        let fusions = fuse_spiders(g);
+        debug_check(g, "fuse_spiders");
+        reduce_parallel_edges(g);
+        debug_check(g, "reduce_parallel_edges");
+        let lcomps = lcomp_simp(g);
+        debug_check(g, "lcomp_simp");
+        let pivots = pivot_simp(g);
+        debug_check(g, "pivot_simp");
+        let identities = remove_identities(g);
+        debug_check(g, "remove_identities");
+        stats.fusions += fusions;
+        stats.lcomps += lcomps;
+        stats.pivots += pivots;
+        stats.identities += identities;
+        stats.copies += copies;
+        if copies + fusions + lcomps + pivots + identities == 0 {
+            return stats;
+        }
+    }
+}
+
+/// Simplify a diagram as far as the available rules go.
+///
+/// Each round runs `clifford_simp`, then pivots next to boundaries, removes phase gadgets and
+/// gadgetizes non-Clifford spiders by pivoting, until a round changes nothing. The iteration
+/// count is the total over the `clifford_simp` runs.
+pub fn full_simp<G: Graph>(g: &mut G) -> SimplifyStats {
+    let mut stats = SimplifyStats::default();
+    loop {
+        stats.absorb(clifford_simp(g));
+        let boundary_pivots = pivot_boundary_simp(g);
+        debug_check(g, "pivot_boundary_simp");
+        let gadgets = gadget_simp(g);
+        debug_check(g, "gadget_simp");
+        let gadget_pivots = pivot_gadget_simp(g);
+        debug_check(g, "pivot_gadget_simp");
+        stats.boundary_pivots += boundary_pivots;
+        stats.gadgets += gadgets;
+        stats.gadget_pivots += gadget_pivots;
+        if boundary_pivots + gadgets + gadget_pivots == 0 {
+            return stats;
+        }
+    }
+}
+
+/// Reduce a diagram with all the rules, following the `full_reduce` strategy of PyZX.
+///
+/// After `clifford_simp` and a first pass of `pivot_gadget_simp`, each round runs
+/// `clifford_simp` alternated with `pivot_boundary_simp`, removes phase gadgets, runs
+/// `clifford_simp` again and gadgetizes non-Clifford spiders, until a round changes nothing.
+/// The stats count the rounds and record the T-count before and after.
+///
+/// The result only depends on the iteration order of the vertices and edges of the graph, so it
+/// is deterministic for all the backends of this crate.
+pub fn full_reduce<G: Graph>(g: &mut G) -> SimplifyStats {
+    full_reduce_with_limit(g, None)
+}
+
+/// Like `full_reduce`, but stop after `max_rounds` rounds if given.
+///
+/// If the limit is reached before a fixpoint, the graph is left partially simplified and
+/// `stopped_early` is set in the stats. The diagram still has the same linear map.
+pub fn full_reduce_with_limit<G: Graph>(g: &mut G, max_rounds: Option<u32>) -> SimplifyStats {
+    let tcount = g.tcount();
+    let mut stats = clifford_simp(g);
+    stats.gadget_pivots += pivot_gadget_simp(g);
+    debug_check(g, "pivot_gadget_simp");
+    loop {
+        if max_rounds.is_some_and(|max| stats.rounds >= max) {
+            stats.stopped_early = true;
+            break;
+        }
+        stats.rounds += 1;
+        let mut boundary_pivots = 0;
+        loop {
+            stats.absorb(clifford_simp(g));
+            let pivots = pivot_boundary_simp(g);
+            debug_check(g, "pivot_boundary_simp");
+            if pivots == 0 {
+                break;
+            }
+            boundary_pivots += pivots;
+        }
+        let gadgets = gadget_simp(g);
+        debug_check(g, "gadget_simp");
+        stats.absorb(clifford_simp(g));
+        let gadget_pivots = pivot_gadget_simp(g);
+        debug_check(g, "pivot_gadget_simp");
+        stats.boundary_pivots += boundary_pivots;
+        stats.gadgets += gadgets;
+        stats.gadget_pivots += gadget_pivots;
+        if boundary_pivots + gadgets + gadget_pivots == 0 {
+            break;
+        }
+    }
+    stats.tcount = Some((tcount, g.tcount()));
+    stats
+}
+
+/// A phase gadget: a leaf Z spider with phase `phase`, connected by a Hadamard edge to a
+/// phase-free axle Z spider, itself connected by Hadamard edges to the targets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gadget<V> {
+    pub axle: V,
+    pub leaf: V,
+    pub phase: Phase,
+    /// Neighbours of the axle other than the leaf, sorted
+    pub targets: Vec<V>,
+}
+
+/// The gadget with the given axle, if any.
+///
+/// The leaf must be the only neighbour of the axle with no other neighbours, and the targets
+/// must be Z spiders connected by single Hadamard edges.
+pub(crate) fn gadget_at<G: Graph>(g: &G, axle: G::VertexIx) -> Option<Gadget<G::VertexIx>> {
+    if g.vertex(axle).unwrap().kind() != VertexKind::Z
+        || !is_interior_spider(g, axle)
+        || g.is_ground(axle)
+        || !g.vertex(axle).unwrap().phase().is_zero()
+    {
+        return None;
+    }
+    let mut leaf = None;
+    let mut targets = Vec::new();
+    for (n, kind) in g.neighbours_with_kind(axle) {
+        if n == axle || kind != EdgeKind::Hadam || g.vertex(n).unwrap().kind() != VertexKind::Z {
+            return None;
+        }
+        if g.incident_edges(n).count() == 1 && is_interior_spider(g, n) && !g.is_ground(n) {
+            if leaf.replace(n).is_some() {
+                return None;
+            }
+        } else {
+            targets.push(n);
+        }
+    }
+    let leaf = leaf?;
+    targets.sort_unstable();
+    let before = targets.len();
+    targets.dedup();
+    if targets.len() != before {
+        return None;
+    }
+    Some(Gadget {
+        axle,
+        leaf,
+        phase: g.vertex(leaf).unwrap().phase(),
+        targets,
+    })
+}
+
+/// Phase gadgets of a diagram, in increasing axle order.
+pub fn gadgets<G: Graph>(g: &G) -> Vec<Gadget<G::VertexIx>> {
+    g.vertices().filter_map(|v| gadget_at(g, v)).collect()
+}
+
+/// Fuse phase gadgets acting on the same targets into one with the summed phase, and remove
+/// gadgets with phase 0 or no targets, until no more can be simplified. Returns the number of
+/// removed gadgets.
+///
+/// A gadget with `k` targets and phase 0 is `sqrt(2)^(1 - k)` times the identity, which is
+/// also the factor dropped by each fusion.
+pub fn gadget_simp<G: Graph>(g: &mut G) -> u32 {
+    let mut count = 0;
+    loop {
+        let mut groups: BTreeMap<Vec<G::VertexIx>, Vec<Gadget<G::VertexIx>>> = BTreeMap::new();
+        for gadget in gadgets(g) {
+            groups
+                .entry(gadget.targets.clone())
+                .or_default()
+                .push(gadget);
+        }
+        let mut touched = HashSet::new();
+        let mut removed = Vec::new();
+        for (targets, group) in groups {
+            let vertices: Vec<_> = group
+                .iter()
+                .flat_map(|gadget| [gadget.axle, gadget.leaf])
+                .chain(targets.iter().copied())
+                .collect();
+            let phase = group
+                .iter()
+                .fold(Phase::zero(), |acc, gadget| acc + gadget.phase);
+            if (group.len() == 1 && !phase.is_zero() && !targets.is_empty())
+                || vertices.iter().any(|v| touched.contains(v))
+            {
+                continue;
+            }
+            touched.extend(vertices);
+            let (kept, fused) = group.split_first().unwrap();
+            for gadget in fused {
+                removed.extend([gadget.axle, gadget.leaf]);
+            }
+            let mut dropped = fused.len() as i32;
+            if phase.is_zero() || targets.is_empty() {
+                removed.extend([kept.axle, kept.leaf]);
+                dropped += 1;
+            } else {
+                g.set_phase(kept.leaf, phase);
+            }
+            g.scalar_mut()
+                .add_power(dropped * (1 - targets.len() as i32));
+        }
+        if removed.is_empty() {
+            return count;
+        }
+        count += removed.len() as u32 / 2;
+        g.remove_vertices(removed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::analysis::is_isomorphic;
+    use crate::zx::circuit::{Circuit, Gate, GateSet};
+    use crate::zx::generate::{
+        random_clifford_circuit, random_clifford_t_circuit, random_graph_like,
+    };
+    use crate::zx::io::json::to_json;
+    use crate::zx::random::Seed;
+    use crate::zx::simple_graph::GGraph;
+    use crate::zx::templates;
+    use crate::zx::tensor::assert_graph_eq;
+    use crate::zx::vec_graph::VecGraph;
+
+    type V = <GGraph as Graph>::VertexIx;
+
+    /// Wire from an input through spiders of the given kinds and phases to an output, joined by
+    /// edges of kind `edge`. Returns the diagram and its spiders.
+    fn chain(spiders: &[(VertexKind, Phase)], edge: EdgeKind) -> (GGraph, Vec<V>) {
+        let mut g: GGraph = GGraph::new();
+        let i = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        g.set_input(i, true);
+        let vs: Vec<_> = spiders
+            .iter()
+            .map(|&(kind, phase)| g.add_vertex(kind, phase))
+            .collect();
+        let o = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        g.set_output(o, true);
+        let mut last = i;
+        for &v in &vs {
+            g.add_edge(last, v, if last == i { EdgeKind::Regular } else { edge });
+            last = v;
+        }
+        g.add_edge(last, o, EdgeKind::Regular);
+        (g, vs)
+    }
+
+    fn z(num: i64, den: u64) -> (VertexKind, Phase) {
+        (VertexKind::Z, Phase::new(num, den))
+    }
+
+    fn x(num: i64, den: u64) -> (VertexKind, Phase) {
+        (VertexKind::X, Phase::new(num, den))
+    }
+
+    #[test]
+    fn chain_of_five_z_spiders() {
+        let phases = [z(1, 4), z(1, 2), z(1, 3), z(3, 4), z(1, 6)];
+        let (mut g, _) = chain(&phases, EdgeKind::Regular);
+        let original = g.clone();
+        assert_eq!(fuse_spiders(&mut g), 4);
+        assert_eq!(g.num_vertices(), 3);
+        let spider = g
+            .vertices()
+            .find(|&v| g.vertex(v).unwrap().kind() == VertexKind::Z)
+            .unwrap();
+        // π/4 + π/2 + π/3 + 3π/4 + π/6 = 2π
+        assert_eq!(g.vertex(spider).unwrap().phase(), Phase::zero());
+        assert_eq!(g.inputs().count(), 1);
+        assert_eq!(g.outputs().count(), 1);
+        assert!(g.check_invariants().is_ok());
+        assert_graph_eq(&g, &original);
+        assert_eq!(fuse_spiders(&mut g), 0);
+    }
+
+    #[test]
+    fn only_same_colour_regular_edges_fuse() {
+        let (mut g, _) = chain(&[z(1, 4), x(1, 2), x(1, 4), z(1, 2)], EdgeKind::Regular);
+        let original = g.clone();
+        assert_eq!(fuse_spiders(&mut g), 1);
+        assert_eq!(g.num_vertices(), 5);
+        assert_graph_eq(&g, &original);
+
+        let (mut g, _) = chain(&[z(1, 4), z(1, 2)], EdgeKind::Hadam);
+        assert_eq!(fuse_spiders(&mut g), 0);
+    }
+
+    #[test]
+    fn boundaries_are_not_fused() {
+        let (mut g, vs) = chain(&[z(1, 4), z(1, 2)], EdgeKind::Regular);
+        // A spider marked as an output stays, with its own phase.
+        g.set_output(vs[0], true);
+        assert_eq!(fuse_spiders(&mut g), 0);
+        assert_eq!(g.vertex(vs[0]).unwrap().phase(), Phase::new(1, 4));
+    }
+
+    #[test]
+    fn parallel_edges_become_self_loops() {
+        let (mut g, vs) = chain(&[z(1, 4), z(1, 2)], EdgeKind::Regular);
+        g.set_mode(GraphMode::Multi);
+        g.add_edge(vs[0], vs[1], EdgeKind::Regular);
+        g.add_edge(vs[0], vs[1], EdgeKind::Hadam);
+        let original = g.clone();
+        assert_eq!(fuse_spiders(&mut g), 1);
+        assert_eq!(g.num_vertices(), 3);
+        assert_eq!(g.num_edges(), 2);
+        // The Hadamard self-loop adds pi.
+        let spider = g.neighbours(g.inputs().next().unwrap()).next().unwrap();
+        assert_eq!(g.vertex(spider).unwrap().phase(), Phase::new(7, 4));
+        assert_graph_eq(&g, &original);
+    }
+
+    #[test]
+    fn grounded_spiders_absorb_phases() {
+        let (mut g, vs) = chain(&[z(1, 4), z(1, 2), z(1, 3)], EdgeKind::Regular);
+        g.set_ground(vs[1], true);
+        assert_eq!(fuse_spiders(&mut g), 2);
+        let grounds: Vec<_> = g.grounds().collect();
+        assert_eq!(grounds.len(), 1);
+        assert_eq!(g.vertex(grounds[0]).unwrap().phase(), Phase::zero());
+        assert_eq!(g.num_vertices(), 3);
+
+        // Grounded spiders are not removed as identities.
+        let (mut g, vs) = chain(&[z(0, 1)], EdgeKind::Regular);
+        g.set_ground(vs[0], true);
+        assert_eq!(remove_identities(&mut g), 0);
+    }
+
+    #[test]
+    fn parallel_edges_scale_the_diagram() {
+        // Z spiders joined by a regular and a Hadamard edge: the Hadamard self-loop left after
+        // fusing adds pi and a factor of 1/sqrt(2).
+        let (mut g, vs) = chain(&[z(0, 1), z(0, 1)], EdgeKind::Regular);
+        g.set_mode(GraphMode::Multi);
+        g.add_edge(vs[0], vs[1], EdgeKind::Hadam);
+        let original = g.clone();
+        assert_eq!(fuse_spiders(&mut g), 1);
+        assert_eq!(*g.scalar(), Scalar::new(-1, Phase::zero()));
+        assert_graph_eq(&g, &original);
+        let (mut expected, _) = chain(&[z(1, 1)], EdgeKind::Regular);
+        expected.scalar_mut().add_power(-1);
+        assert_graph_eq(&g, &expected);
+
+        // A Z and an X spider joined by two regular edges disconnect, with a factor of 1/2.
+        let (mut g, vs) = chain(&[z(0, 1), x(0, 1)], EdgeKind::Regular);
+        g.set_mode(GraphMode::Multi);
+        g.add_edge(vs[0], vs[1], EdgeKind::Regular);
+        let original = g.clone();
+        assert_eq!(reduce_parallel_edges(&mut g), 2);
+        assert_eq!(*g.scalar(), Scalar::new(-2, Phase::zero()));
+        assert_eq!(g.num_edges(), 2);
+        assert_graph_eq(&g, &original);
+    }
+
+    #[test]
+    fn hopf_law_for_each_combination() {
+        let (r, h) = (EdgeKind::Regular, EdgeKind::Hadam);
+        for (a, b) in [(z(1, 4), z(1, 2)), (z(1, 4), x(1, 2)), (x(1, 4), x(1, 2))] {
+            for kinds in [&[r, r][..], &[r, h], &[h, h], &[r, r, h], &[h, h, h]] {
+                let (mut g, vs) = chain(&[a, b], kinds[0]);
+                g.set_mode(GraphMode::Multi);
+                for &kind in &kinds[1..] {
+                    g.add_edge(vs[0], vs[1], kind);
+                }
+                let original = g.clone();
+                let fusing = kinds
+                    .iter()
+                    .filter(|&&k| is_fusing_edge(k, a.0, b.0))
+                    .count();
+                let hopf = kinds.len() - fusing;
+                let left = if fusing > 0 { 1 } else { hopf % 2 };
+                let removed = reduce_parallel_edges(&mut g);
+                assert_eq!(
+                    removed as usize,
+                    kinds.len() - left,
+                    "{:?} {:?}",
+                    (a, b),
+                    kinds
+                );
+                // Besides the two boundary edges
+                assert_eq!(g.num_edges() as usize, 2 + left);
+                assert_graph_eq(&g, &original);
+            }
+        }
+    }
+
+    #[test]
+    fn self_loops() {
+        for spider in [z(1, 4), x(1, 4)] {
+            let (mut g, vs) = chain(&[spider], EdgeKind::Regular);
+            g.set_mode(GraphMode::Multi);
+            g.add_edge(vs[0], vs[0], EdgeKind::Regular);
+            g.add_edge(vs[0], vs[0], EdgeKind::Hadam);
+            let original = g.clone();
+            assert_eq!(reduce_parallel_edges(&mut g), 2);
+            assert_eq!(g.num_edges(), 2);
+            assert_eq!(g.vertex(vs[0]).unwrap().phase(), Phase::new(5, 4));
+            assert_eq!(*g.scalar(), Scalar::new(-1, Phase::zero()));
+            assert_graph_eq(&g, &original);
+        }
+    }
+
+    /// Wire from an input to an output through phase-free Z spiders, with edges of the given
+    /// kinds
+    fn identity_wire(edges: &[EdgeKind]) -> GGraph {
+        let mut g: GGraph = GGraph::new();
+        let i = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        g.set_input(i, true);
+        let mut last = i;
+        for (k, &kind) in edges.iter().enumerate() {
+            let v = if k + 1 == edges.len() {
+                let o = g.add_vertex(VertexKind::Boundary, Phase::zero());
+                g.set_output(o, true);
+                o
+            } else {
+                g.add_vertex(VertexKind::Z, Phase::zero())
+            };
+            g.add_edge(last, v, kind);
+            last = v;
+        }
+        g
+    }
+
+    #[test]
+    fn wire_of_identities() {
+        let (r, h) = (EdgeKind::Regular, EdgeKind::Hadam);
+        for (edges, expected) in [
+            (vec![r, r, r, r, r, r], r),
+            (vec![r, h, r, r, r, r], h),
+            (vec![h, r, h, r, r, h], h),
+            (vec![h, h, r, h, h, r], r),
+        ] {
+            let mut g = identity_wire(&edges);
+            let original = g.clone();
+            assert_eq!(remove_identities(&mut g), 5);
+            assert_eq!(g.num_vertices(), 2);
+            let e = g.edges().next().unwrap();
+            assert_eq!(g.edge(e).unwrap().kind(), expected);
+            assert_graph_eq(&g, &original);
+        }
+    }
+
+    #[test]
+    fn identity_next_to_boundary() {
+        let (mut g, vs) = chain(&[z(0, 1), z(1, 4)], EdgeKind::Hadam);
+        let original = g.clone();
+        assert_eq!(remove_identities(&mut g), 1);
+        assert_eq!(g.num_vertices(), 3);
+        let i = g.inputs().next().unwrap();
+        let (n, kind) = g.neighbours_with_kind(i).next().unwrap();
+        assert_eq!(g.vertex(n).unwrap().phase(), Phase::new(1, 4));
+        assert_eq!(kind, EdgeKind::Hadam);
+        assert!(g.vertex(vs[1]).is_some());
+        assert_graph_eq(&g, &original);
+
+        // Boundaries themselves are never removed.
+        let mut g = identity_wire(&[EdgeKind::Regular]);
+        assert_eq!(remove_identities(&mut g), 0);
+    }
+
+    #[test]
+    fn identity_between_connected_spiders() {
+        for mode in [GraphMode::Simple, GraphMode::Multi] {
+            let (mut g, vs) = chain(&[z(1, 4), z(0, 1), z(1, 2)], EdgeKind::Hadam);
+            g.set_mode(mode);
+            g.add_edge(vs[0], vs[2], EdgeKind::Regular);
+            let original = g.clone();
+            assert_eq!(remove_identities(&mut g), 1);
+            assert!(g.check_invariants().is_ok());
+            assert_graph_eq(&g, &original);
+        }
+    }
+
+    #[test]
+    fn colour_change() {
+        let mut g = templates::toffoli();
+        let (i, o) = (g.inputs().next().unwrap(), g.outputs().next().unwrap());
+        let xs: Vec<_> = [x(1, 4), x(0, 1), z(1, 2)]
+            .iter()
+            .map(|&(kind, phase)| g.add_vertex(kind, phase))
+            .collect();
+        let first = g.neighbours(i).next().unwrap();
+        g.add_edge(first, xs[0], EdgeKind::Hadam);
+        g.add_edge(xs[0], xs[1], EdgeKind::Regular);
+        g.add_edge(xs[1], xs[2], EdgeKind::Regular);
+        g.add_edge(xs[2], first, EdgeKind::Hadam);
+        let last = g.neighbours(o).next().unwrap();
+        g.add_edge(last, xs[1], EdgeKind::Regular);
+        // An arity-2 H-box between two spiders.
+        let hbox = g.add_vertex(VertexKind::HBox, Phase::pi());
+        g.add_edge(xs[0], hbox, EdgeKind::Regular);
+        g.add_edge(hbox, xs[2], EdgeKind::Regular);
+        let original = g.clone();
+
+        to_gh(&mut g);
+        assert!(is_gh(&g));
+        assert!(g
+            .vertices()
+            .all(|v| g.vertex(v).unwrap().kind() != VertexKind::X));
+        // Only the arity-3 H-box of the Toffoli is left.
+        let hboxes = g
+            .vertices()
+            .filter(|&v| g.vertex(v).unwrap().kind() == VertexKind::HBox)
+            .count();
+        assert_eq!(hboxes, 1);
+        assert!(g.check_invariants().is_ok());
+        assert_graph_eq(&g, &original);
+    }
+
+    #[test]
+    fn colour_change_reduces_parallel_edges() {
+        // A Z and an X spider connected by two regular edges, which become parallel Hadamard
+        // edges and cancel.
+        let (mut g, vs) = chain(&[z(1, 4), x(1, 2)], EdgeKind::Regular);
+        g.set_mode(GraphMode::Multi);
+        g.add_edge(vs[0], vs[1], EdgeKind::Regular);
+        let original = g.clone();
+        to_gh(&mut g);
+        assert!(is_gh(&g));
+        assert_eq!(g.edge_between(vs[0], vs[1]), None);
+        assert_graph_eq(&g, &original);
+    }
+
+    /// Graph state on `n` qubits with the given edges, returning the spider of each qubit
+    fn graph_state(n: usize, edges: &[(usize, usize)]) -> (GGraph, Vec<V>) {
+        let mut adjacency = vec![Vec::new(); n];
+        for &(a, b) in edges {
+            adjacency[a].push(b);
+            adjacency[b].push(a);
+        }
+        let g = templates::graph_state(&adjacency);
+        let spiders = g
+            .outputs()
+            .map(|o| g.neighbours(o).next().unwrap())
+            .collect();
+        (g, spiders)
+    }
+
+    /// Hadamard edges between the given spiders, as sorted pairs of positions in `vs`
+    fn hadamard_edges(g: &GGraph, vs: &[V]) -> Vec<(usize, usize)> {
+        let position = |v| vs.iter().position(|&u| u == v);
+        let mut res: Vec<_> = g
+            .edges()
+            .filter(|&e| g.edge(e).unwrap().kind() == EdgeKind::Hadam)
+            .filter_map(|e| {
+                let (a, b) = g.edge_endpoints(e).unwrap();
+                let (a, b) = (position(a)?, position(b)?);
+                Some((a.min(b), a.max(b)))
+            })
+            .collect();
+        res.sort_unstable();
+        res
+    }
+
+    /// Adds a spider with the given phase, connected by Hadamard edges to `neighbours`
+    fn add_hub(g: &mut GGraph, phase: Phase, neighbours: &[V]) -> V {
+        let v = g.add_vertex(VertexKind::Z, phase);
+        for &n in neighbours {
+            g.add_edge(v, n, EdgeKind::Hadam);
+        }
+        v
+    }
+
+    #[test]
+    fn local_complementation_by_hand() {
+        // Neighbours 0, 1 and 2 of the hub, with 0 and 1 already connected and 3 outside.
+        let (mut g, vs) = graph_state(4, &[(0, 1), (2, 3)]);
+        g.set_phase(vs[2], Phase::new(1, 4));
+        let hub = add_hub(&mut g, Phase::new(1, 2), &vs[..3]);
+        let original = g.clone();
+        assert!(local_complementation(&mut g, hub));
+        assert_eq!(g.num_vertices(), 8);
+        assert_eq!(hadamard_edges(&g, &vs), [(0, 2), (1, 2), (2, 3)]);
+        let phase = |g: &GGraph, q: usize| g.vertex(vs[q]).unwrap().phase();
+        assert_eq!(phase(&g, 0), Phase::new(3, 2));
+        assert_eq!(phase(&g, 1), Phase::new(3, 2));
+        assert_eq!(phase(&g, 2), Phase::new(7, 4));
+        assert_eq!(phase(&g, 3), Phase::zero());
+        assert_graph_eq(&g, &original);
+
+        // With -pi/2 the neighbours get pi/2.
+        let (mut g, vs) = graph_state(2, &[]);
+        let hub = add_hub(&mut g, Phase::new(3, 2), &vs);
+        let original = g.clone();
+        assert!(local_complementation(&mut g, hub));
+        assert_eq!(hadamard_edges(&g, &vs), [(0, 1)]);
+        assert_eq!(g.vertex(vs[0]).unwrap().phase(), Phase::new(1, 2));
+        assert_graph_eq(&g, &original);
+    }
+
+    #[test]
+    fn local_complementation_refusals() {
+        let (mut g, vs) = graph_state(3, &[]);
+        let hub = add_hub(&mut g, Phase::new(1, 4), &vs);
+        let before = g.dump();
+        assert!(!local_complementation(&mut g, hub));
+        assert_eq!(g.dump(), before);
+
+        // A regular edge.
+        g.set_phase(hub, Phase::new(1, 2));
+        let e = g.edge_between(hub, vs[0]).unwrap();
+        g.set_edge_kind(e, EdgeKind::Regular);
+        assert!(!local_complementation(&mut g, hub));
+        g.set_edge_kind(e, EdgeKind::Hadam);
+
+        // A boundary neighbour.
+        let o = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        g.set_output(o, true);
+        g.add_edge(hub, o, EdgeKind::Hadam);
+        assert!(!local_complementation(&mut g, hub));
+        g.remove_vertex(o);
+
+        // The hub itself on an output wire.
+        let (mut g, vs) = graph_state(2, &[(0, 1)]);
+        g.set_phase(vs[0], Phase::new(1, 2));
+        assert!(!local_complementation(&mut g, vs[0]));
+    }
+
+    #[test]
+    fn lcomp_simp_removes_all_proper_clifford_hubs() {
+        let (mut g, vs) = graph_state(4, &[(0, 1), (1, 2), (2, 3)]);
+        add_hub(&mut g, Phase::new(1, 2), &vs[..2]);
+        add_hub(&mut g, Phase::new(3, 2), &vs[2..]);
+        add_hub(&mut g, Phase::new(1, 2), &[vs[0], vs[3]]);
+        let original = g.clone();
+        assert_eq!(lcomp_simp(&mut g), 3);
+        assert_eq!(g.num_vertices(), 8);
+        assert_graph_eq(&g, &original);
+        assert_eq!(lcomp_simp(&mut g), 0);
+    }
+
+    /// Adds two connected spiders with the given phases to a graph state, each connected to
+    /// some of the qubits. The spiders are the last vertices, so removing them does not
+    /// renumber the others.
+    fn add_pivot_pair(g: &mut GGraph, phases: (Phase, Phase), nu: &[V], nv: &[V]) -> (V, V) {
+        let u = add_hub(g, phases.0, nu);
+        let v = add_hub(g, phases.1, nv);
+        g.add_edge(u, v, EdgeKind::Hadam);
+        (u, v)
+    }
+
+    #[test]
+    fn pivot_disjoint_neighbourhoods() {
+        let (mut g, vs) = graph_state(4, &[]);
+        let (u, v) = add_pivot_pair(&mut g, (Phase::zero(), Phase::pi()), &vs[..2], &vs[2..]);
+        let original = g.clone();
+        assert!(pivot(&mut g, u, v));
+        assert_eq!(g.num_vertices(), 8);
+        assert_eq!(hadamard_edges(&g, &vs), [(0, 2), (0, 3), (1, 2), (1, 3)]);
+        let phases: Vec<_> = vs.iter().map(|&w| g.vertex(w).unwrap().phase()).collect();
+        assert_eq!(
+            phases,
+            [Phase::pi(), Phase::pi(), Phase::zero(), Phase::zero()]
+        );
+        assert_graph_eq(&g, &original);
+    }
+
+    #[test]
+    fn pivot_overlapping_neighbourhoods() {
+        let (mut g, vs) = graph_state(4, &[(0, 1), (2, 3)]);
+        let (u, v) = add_pivot_pair(&mut g, (Phase::zero(), Phase::zero()), &vs[..2], &vs[1..3]);
+        let original = g.clone();
+        assert!(pivot(&mut g, u, v));
+        // Only u: 0, only v: 2, shared: 1. The existing edge between 0 and 1 is toggled off.
+        assert_eq!(hadamard_edges(&g, &vs), [(0, 2), (1, 2), (2, 3)]);
+        let phases: Vec<_> = vs.iter().map(|&w| g.vertex(w).unwrap().phase()).collect();
+        assert_eq!(
+            phases,
+            [Phase::zero(), Phase::pi(), Phase::zero(), Phase::zero()]
+        );
+        assert_graph_eq(&g, &original);
+    }
+
+    #[test]
+    fn pivot_skips_invalid_pairs() {
+        // A non-Pauli phase.
+        let (mut g, vs) = graph_state(2, &[]);
+        let (u, v) = add_pivot_pair(
+            &mut g,
+            (Phase::zero(), Phase::new(1, 2)),
+            &vs[..1],
+            &vs[1..],
+        );
+        assert!(!pivot(&mut g, u, v));
+
+        // A spider on a boundary wire.
+        let (mut g, vs) = graph_state(3, &[(0, 1)]);
+        let u = add_hub(&mut g, Phase::zero(), &vs[1..]);
+        let before = g.dump();
+        assert!(!pivot(&mut g, u, vs[0]));
+        assert!(!pivot(&mut g, vs[0], u));
+        assert_eq!(g.dump(), before);
+        assert_eq!(pivot_simp(&mut g), 0);
+    }
+
+    #[test]
+    fn pivot_simp_to_fixpoint() {
+        let (mut g, vs) = graph_state(4, &[(0, 3)]);
+        add_pivot_pair(&mut g, (Phase::zero(), Phase::pi()), &vs[..2], &vs[2..]);
+        add_pivot_pair(
+            &mut g,
+            (Phase::pi(), Phase::pi()),
+            &vs[1..3],
+            &[vs[0], vs[3]],
+        );
+        let original = g.clone();
+        assert_eq!(pivot_simp(&mut g), 2);
+        assert_eq!(g.num_vertices(), 8);
+        assert_graph_eq(&g, &original);
+    }
+
+    #[test]
+    fn pivot_boundary_by_hand() {
+        let (mut g, vs) = graph_state(3, &[]);
+        let u = add_hub(&mut g, Phase::pi(), &vs);
+        let original = g.clone();
+        // `vs[0]` is next to a spider other than `u`, which is not a pivot partner.
+        assert!(!pivot_boundary(&mut g, u, u));
+        assert!(!pivot(&mut g, u, vs[2]));
+        assert!(pivot_boundary(&mut g, u, vs[2]));
+        // `u` and `vs[2]` are replaced by the spider unfused from the boundary wire.
+        assert_eq!(g.num_vertices(), 6);
+        assert_eq!(g.vertices().filter(|&v| g.is_output(v)).count(), 3);
+        assert_graph_eq(&g, &original);
+    }
+
+    #[test]
+    fn pivot_gadget_by_hand() {
+        let (mut g, vs) = graph_state(4, &[]);
+        let (u, v) = add_pivot_pair(
+            &mut g,
+            (Phase::zero(), Phase::new(1, 4)),
+            &vs[..2],
+            &vs[2..],
+        );
+        let (axle, leaf) = add_gadget(&mut g, Phase::new(3, 4), &vs[1..3]);
+        let original = g.clone();
+        // Gadget leaves are never gadgetized.
+        assert!(!pivot_gadget(&mut g, axle, leaf));
+        assert!(!pivot(&mut g, u, v));
+        assert!(pivot_gadget(&mut g, u, v));
+        assert_eq!(g.num_vertices(), 12);
+        assert_eq!(gadgets(&g).len(), 2);
+        assert_eq!(g.tcount(), 2);
+        assert_graph_eq(&g, &original);
+    }
+
+    #[test]
+    fn pivot_variants_on_random_circuits() {
+        let (mut boundary_pivots, mut gadget_pivots) = (0, 0);
+        for qubits in 1..=6 {
+            for seed in 0..3 {
+                let c = random_clifford_t_circuit(qubits, 6 * qubits as usize, 0.25, Seed(seed));
+                let original: GGraph = c.to_graph();
+                let mut g = original.clone();
+                clifford_simp(&mut g);
+                boundary_pivots += pivot_boundary_simp(&mut g);
+                assert_graph_eq(&g, &original);
+                clifford_simp(&mut g);
+                gadget_pivots += pivot_gadget_simp(&mut g);
+                assert_graph_eq(&g, &original);
+
+                let mut g = original.clone();
+                let stats = full_simp(&mut g);
+                assert!(g.tcount() <= original.tcount());
+                assert_eq!(full_simp(&mut g).gadget_pivots, 0, "{}", stats);
+                assert_graph_eq(&g, &original);
+            }
+        }
+        assert!(boundary_pivots > 0 && gadget_pivots > 0);
+    }
+
+    #[test]
+    fn full_reduce_cancels_a_toffoli_pair() {
+        let mut c = Circuit::new(3);
+        c.add_gate(Gate::Ccx(0, 1, 2));
+        c.add_gate(Gate::Ccx(0, 1, 2));
+        c.decompose(GateSet::CliffordT);
+        let original: GGraph = c.to_graph();
+        let mut g = original.clone();
+        let stats = full_reduce(&mut g);
+        assert_eq!(stats.tcount, Some((14, 0)));
+        assert!(!stats.stopped_early);
+        assert!(stats.to_string().contains("T-count: 14 -> 0\n"));
+        assert_graph_eq(&g, &original);
+    }
+
+    #[test]
+    fn full_reduce_on_random_circuits() {
+        for seed in 0..6 {
+            let c = random_clifford_t_circuit(4, 40, 0.3, Seed(seed));
+            let original: GGraph = c.to_graph();
+            let mut g = original.clone();
+            let stats = full_reduce(&mut g);
+            let (before, after) = stats.tcount.unwrap();
+            assert_eq!((before, after), (original.tcount(), g.tcount()));
+            assert!(after <= before);
+            assert!(stats.rounds >= 1);
+            assert_graph_eq(&g, &original);
+
+            // The same input gives the same result, on each backend. The backends iterate in
+            // different orders, so they may apply different rules.
+            let mut h = original.clone();
+            assert_eq!(full_reduce(&mut h), stats);
+            assert!(is_isomorphic(&g, &h));
+            let reduce = || {
+                let mut h: VecGraph = c.to_graph();
+                (full_reduce(&mut h), h)
+            };
+            let (first, second) = (reduce(), reduce());
+            assert_eq!(first.0, second.0);
+            assert!(is_isomorphic(&first.1, &second.1));
+            assert_graph_eq(&first.1, &original);
+
+            // A second run finds nothing left to do.
+            let again = full_reduce(&mut g);
+            assert_eq!(again.tcount, Some((after, after)));
+            assert_eq!(
+                (
+                    again.rounds,
+                    again.boundary_pivots,
+                    again.gadgets,
+                    again.gadget_pivots
+                ),
+                (1, 0, 0, 0)
+            );
+        }
+    }
+
+    #[test]
+    fn full_reduce_round_limit() {
+        let original: GGraph = random_clifford_t_circuit(4, 40, 0.3, Seed(1)).to_graph();
+        let mut full = original.clone();
+        let rounds = full_reduce(&mut full).rounds;
+        assert!(rounds > 1);
+        for limit in 0..rounds {
+            let mut g = original.clone();
+            let stats = full_reduce_with_limit(&mut g, Some(limit));
+            assert!(stats.stopped_early);
+            assert_eq!(stats.rounds, limit);
+            assert!(stats
+                .to_string()
+                .ends_with(&format!("stopped after {} rounds\n", limit)));
+            assert_graph_eq(&g, &original);
+        }
+        let mut g = original.clone();
+        let stats = full_reduce_with_limit(&mut g, Some(rounds));
+        assert!(!stats.stopped_early);
+        assert!(is_isomorphic(&g, &full));
+    }
+
+    /// Whether `v` is connected to an input or output
+    fn on_boundary(g: &GGraph, v: V) -> bool {
+        g.neighbours(v).any(|n| g.is_input(n) || g.is_output(n))
+    }
+
+    #[test]
+    fn clifford_simp_leaves_no_interior_clifford_structure() {
+        for seed in 0..10 {
+            let mut g: GGraph = random_clifford_circuit(4, 60, Seed(seed)).to_graph();
+            let original = g.clone();
+            let stats = clifford_simp(&mut g);
+            assert!(stats.iterations >= 1);
+            assert!(is_gh(&g));
+            for v in g.vertices() {
+                let vdata = g.vertex(v).unwrap();
+                if vdata.kind() != VertexKind::Z {
+                    continue;
+                }
+                assert!(vdata.phase().is_clifford());
+                if on_boundary(&g, v) {
+                    continue;
+                }
+                // Local complementation removes the interior ±pi/2 spiders, and pivoting the
+                // pairs of interior Pauli spiders.
+                assert!(vdata.phase().is_pauli(), "seed {}", seed);
+                assert!(g.neighbours(v).all(|n| on_boundary(&g, n)), "seed {}", seed);
+            }
+            assert_graph_eq(&g, &original);
+        }
+    }
+
+    #[test]
+    fn clifford_simp_stats() {
+        let mut g: GGraph = random_clifford_circuit(3, 30, Seed(3)).to_graph();
+        let stats = clifford_simp(&mut g);
+        assert_eq!(clifford_simp(&mut g).iterations, 1);
+        let text = stats.to_string();
+        assert!(text.starts_with(&format!("SimplifyStats(iterations={})\n", stats.iterations)));
+        assert!(text.contains(&format!("  fusion: {}\n", stats.fusions)));
+        assert!(text.contains(&format!("  pivot: {}\n", stats.pivots)));
+        assert!(!text.contains("T-count"));
+    }
+
+    #[test]
+    fn matches_do_not_depend_on_rayon() {
+        // Pinned from a build without the `rayon` feature, so that both builds check them.
+        let mut g = random_graph_like(100, 0.1, Seed(3));
+        let stats = clifford_simp(&mut g);
+        assert_eq!((stats.iterations, stats.lcomps, stats.pivots), (2, 49, 1));
+        assert_eq!((g.num_vertices(), g.num_edges()), (96, 625));
+        let mut h = random_graph_like(100, 0.1, Seed(3));
+        clifford_simp(&mut h);
+        assert_eq!(to_json(&h), to_json(&g));
+
+        let mut g = random_graph_like(120, 0.08, Seed(7));
+        let stats = full_reduce(&mut g);
+        assert_eq!(
+            (stats.lcomps, stats.pivots, stats.boundary_pivots),
+            (58, 0, 2)
+        );
+        assert_eq!((g.num_vertices(), g.num_edges()), (120, 921));
+    }
+
+    /// Adds a phase gadget on the given targets, returning its axle and leaf
+    fn add_gadget(g: &mut GGraph, phase: Phase, targets: &[V]) -> (V, V) {
+        let axle = add_hub(g, Phase::zero(), targets);
+        let leaf = add_hub(g, phase, &[axle]);
+        (axle, leaf)
+    }
+
+    #[test]
+    fn gadget_query() {
+        let (mut g, vs) = graph_state(3, &[(0, 1)]);
+        let (a, l) = add_gadget(&mut g, Phase::new(1, 4), &[vs[2], vs[0]]);
+        let (b, m) = add_gadget(&mut g, Phase::new(3, 4), &[vs[1]]);
+        assert_eq!(
+            gadgets(&g),
+            [
+                Gadget {
+                    axle: a,
+                    leaf: l,
+                    phase: Phase::new(1, 4),
+                    targets: vec![vs[0], vs[2]],
+                },
+                Gadget {
+                    axle: b,
+                    leaf: m,
+                    phase: Phase::new(3, 4),
+                    targets: vec![vs[1]],
+                },
+            ]
+        );
+
+        // A leaf with a phase on the axle is not a gadget.
+        g.set_phase(a, Phase::pi());
+        assert_eq!(gadgets(&g).len(), 1);
+    }
+
+    #[test]
+    fn duplicate_gadgets_fuse() {
+        let (mut g, vs) = graph_state(3, &[(0, 1), (1, 2)]);
+        let t = Phase::new(1, 4);
+        add_gadget(&mut g, t, &[vs[0], vs[1]]);
+        add_gadget(&mut g, t, &[vs[1], vs[0]]);
+        add_gadget(&mut g, t, &[vs[1], vs[2]]);
+        add_gadget(&mut g, t, &[vs[0], vs[2]]);
+        add_gadget(&mut g, -t, &[vs[2], vs[0]]);
+        let original = g.clone();
+        assert_eq!(g.tcount(), 5);
+
+        // Two fused, and the cancelling pair removed with its axles.
+        assert_eq!(gadget_simp(&mut g), 3);
+        assert_eq!(g.tcount(), 1);
+        assert_eq!(g.num_vertices(), 6 + 2 * 2);
+        let left: Vec<_> = gadgets(&g)
+            .into_iter()
+            .map(|gadget| (gadget.phase, gadget.targets))
+            .collect();
+        assert_eq!(
+            left,
+            [
+                (Phase::new(1, 2), vec![vs[0], vs[1]]),
+                (t, vec![vs[1], vs[2]])
+            ]
+        );
+        assert_graph_eq(&g, &original);
+        assert_eq!(gadget_simp(&mut g), 0);
+    }
+
+    /// A degree-1 spider `state` on a spider `v` whose three other legs go to an input and two
+    /// outputs, one of them through a Hadamard edge
+    fn state_on_spider(
+        state: (VertexKind, Phase),
+        edge: EdgeKind,
+        spider: (VertexKind, Phase),
+    ) -> (GGraph, V, V) {
+        let mut g: GGraph = GGraph::new();
+        let s = g.add_vertex(state.0, state.1);
+        let v = g.add_vertex(spider.0, spider.1);
+        g.add_edge(s, v, edge);
+        let i = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        g.set_input(i, true);
+        g.add_edge(i, v, EdgeKind::Regular);
+        for kind in [EdgeKind::Regular, EdgeKind::Hadam] {
+            let o = g.add_vertex(VertexKind::Boundary, Phase::zero());
+            g.set_output(o, true);
+            g.add_edge(v, o, kind);
+        }
+        (g, s, v)
+    }
+
+    #[test]
+    fn copy_rule_for_each_combination() {
+        for state in [z(0, 1), z(1, 1), x(0, 1), x(1, 1)] {
+            for edge in [EdgeKind::Regular, EdgeKind::Hadam] {
+                for spider in [z(1, 4), x(3, 4), z(0, 1)] {
+                    let (mut g, _, _) = state_on_spider(state, edge, spider);
+                    let original = g.clone();
+                    let copies = copy_simp(&mut g);
+                    let fuses = (edge == EdgeKind::Regular) == (state.0 == spider.0);
+                    assert_eq!(copies, !fuses as u32, "{:?} {:?} {:?}", state, edge, spider);
+                    if !fuses {
+                        assert_eq!(g.num_vertices(), 6);
+                    }
+                    assert_graph_eq(&g, &original);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn copy_rule_refusals() {
+        // A non-Pauli state, and a state on a boundary
+        let (mut g, _, _) = state_on_spider(x(1, 2), EdgeKind::Regular, z(1, 4));
+        assert_eq!(copy_simp(&mut g), 0);
+        let (mut g, s, _) = state_on_spider(x(0, 1), EdgeKind::Regular, z(1, 4));
+        g.set_output(s, true);
+        assert_eq!(copy_simp(&mut g), 0);
+        // A grounded spider, and a self-loop on the copied spider
+        let (mut g, _, v) = state_on_spider(x(0, 1), EdgeKind::Regular, z(1, 4));
+        g.set_ground(v, true);
+        assert_eq!(copy_simp(&mut g), 0);
+        let (mut g, _, v) = state_on_spider(x(0, 1), EdgeKind::Regular, z(1, 4));
+        g.set_mode(GraphMode::Multi);
+        g.add_edge(v, v, EdgeKind::Hadam);
+        assert_eq!(copy_simp(&mut g), 0);
+    }
+
+    /// `c` with the inputs of the given qubits replaced by states: |0⟩ for an X spider and
+    /// |+⟩ for a Z spider
+    fn with_states(c: &Circuit, states: &[(usize, VertexKind)]) -> GGraph {
+        let mut g: GGraph = c.to_graph();
+        let inputs: Vec<_> = g.inputs().collect();
+        for &(q, kind) in states {
+            g.set_input(inputs[q], false);
+            g.set_vertex_kind(inputs[q], kind);
+        }
+        g
+    }
+
+    #[test]
+    fn copy_rule_on_state_inputs() {
+        let mut c = Circuit::new(3);
+        for gate in [
+            Gate::Cx(0, 1),
+            Gate::Cx(0, 2),
+            Gate::T(1),
+            Gate::Cz(1, 2),
+            Gate::Cx(2, 0),
+            Gate::S(0),
+        ] {
+            c.add_gate(gate);
+        }
+        for states in [
+            vec![(0, VertexKind::X)],
+            vec![(0, VertexKind::Z), (1, VertexKind::Z)],
+            vec![(1, VertexKind::Z), (2, VertexKind::Z)],
+        ] {
+            let original = with_states(&c, &states);
+            let mut g = original.clone();
+            assert!(copy_simp(&mut g) > 0);
+            assert_graph_eq(&g, &original);
+            // The copies unlock fusions in the main loop.
+            let mut g = original.clone();
+            let stats = clifford_simp(&mut g);
+            assert!(stats.copies > 0);
+            assert_graph_eq(&g, &original);
+        }
+    }
+
+    #[test]
+    fn pi_commutation() {
+        for (pauli, spider) in [(x(1, 1), z(1, 4)), (z(1, 1), x(3, 4))] {
+            for edge in [EdgeKind::Regular, EdgeKind::Hadam] {
+                // in -- Pauli -edge- spider, with the spider on two outputs
+                let (mut g, vs) = chain(&[pauli, spider], edge);
+                let extra = g.add_vertex(VertexKind::Boundary, Phase::zero());
+                g.set_output(extra, true);
+                g.add_edge(vs[1], extra, EdgeKind::Hadam);
+                let original = g.clone();
+                let fuses = (edge == EdgeKind::Regular) == (pauli.0 == spider.0);
+                // The Pauli is not the spider itself, nor a neighbour of it.
+                assert!(!pi_commute(&mut g, vs[1], vs[0]));
+                let applied = pi_commute(&mut g, vs[0], vs[1]);
+                assert_eq!(applied, !fuses);
+                assert_graph_eq(&g, &original);
+                if applied {
+                    assert_eq!(g.num_vertices(), 6);
+                    let through = g
+                        .vertices()
+                        .find(|&v| g.vertex(v).unwrap().phase() == -spider.1)
+                        .unwrap();
+                    assert_eq!(g.vertex(through).unwrap().kind(), spider.0);
+                }
+            }
+        }
+
+        // A non-pi phase is not a Pauli gate.
+        let (mut g, vs) = chain(&[x(1, 2), z(1, 4)], EdgeKind::Regular);
+        assert!(!pi_commute(&mut g, vs[0], vs[1]));
+    }
+
+    /// Z spider and X spider joined by a regular edge, with `p` and `q` other legs to outputs
+    fn bialgebra_pair(p: usize, q: usize) -> (GGraph, V, V) {
+        let mut g: GGraph = GGraph::new();
+        let z = g.add_vertex(VertexKind::Z, Phase::zero());
+        let x = g.add_vertex(VertexKind::X, Phase::zero());
+        g.add_edge(z, x, EdgeKind::Regular);
+        for (v, n) in [(z, p), (x, q)] {
+            for i in 0..n {
+                let b = g.add_vertex(VertexKind::Boundary, Phase::zero());
+                g.set_output(b, true);
+                let kind = if i == 0 {
+                    EdgeKind::Hadam
+                } else {
+                    EdgeKind::Regular
+                };
+                g.add_edge(v, b, kind);
+            }
+        }
+        (g, z, x)
+    }
+
+    #[test]
+    fn bialgebra_and_its_reverse() {
+        for (p, q) in [(2, 2), (1, 3), (3, 2), (2, 1)] {
+            let (mut g, z, x) = bialgebra_pair(p, q);
+            let original = g.clone();
+            assert!(bialgebra(&mut g, z, x));
+            assert_eq!(g.num_vertices() as usize, 2 * (p + q));
+            assert_eq!(g.num_edges() as usize, p * q + p + q);
+            assert_graph_eq(&g, &original);
+
+            let spiders = |kind| -> Vec<V> {
+                g.vertices()
+                    .filter(|&v| g.vertex(v).unwrap().kind() == kind)
+                    .collect()
+            };
+            let (zs, xs) = (spiders(VertexKind::Z), spiders(VertexKind::X));
+            assert_eq!((zs.len(), xs.len()), (q, p));
+            assert!(rev_bialgebra(&mut g, &zs, &xs));
+            assert!(is_isomorphic(&g, &original));
+            assert_eq!(g.scalar(), original.scalar());
+        }
+    }
+
+    #[test]
+    fn bialgebra_refusals() {
+        let (mut g, z, x) = bialgebra_pair(2, 2);
+        assert!(!bialgebra(&mut g, x, z));
+        g.set_phase(z, Phase::new(1, 4));
+        assert!(!bialgebra(&mut g, z, x));
+        g.set_phase(z, Phase::zero());
+        let e = g.edge_between(z, x).unwrap();
+        g.set_edge_kind(e, EdgeKind::Hadam);
+        assert!(!bialgebra(&mut g, z, x));
+
+        let (mut g, z, x) = bialgebra_pair(2, 2);
+        assert!(bialgebra(&mut g, z, x));
+        let zs: Vec<V> = g
+            .vertices()
+            .filter(|&v| g.vertex(v).unwrap().kind() == VertexKind::Z)
+            .collect();
+        let xs: Vec<V> = g
+            .vertices()
+            .filter(|&v| g.vertex(v).unwrap().kind() == VertexKind::X)
+            .collect();
+        // An incomplete pattern, a repeated spider, and an empty side
+        assert!(!rev_bialgebra(&mut g, &zs[..1], &xs));
+        assert!(!rev_bialgebra(&mut g, &[zs[0], zs[0]], &xs));
+        assert!(!rev_bialgebra(&mut g, &zs, &[]));
+        let before = g.num_vertices();
+        let e = g.edge_between(zs[0], xs[0]).unwrap();
+        g.remove_edge(e);
+        assert!(!rev_bialgebra(&mut g, &zs, &xs));
+        assert_eq!(g.num_vertices(), before);
+    }
+}
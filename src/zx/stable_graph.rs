@@ -0,0 +1,507 @@
+use crate::zx::graph::*;
+use crate::zx::simple_graph::{EdgeData, VertexData};
+use indexmap::{set, IndexSet};
+use petgraph::graph::{EdgeIndex, IndexType, NodeIndex};
+use petgraph::stable_graph::{self, StableGraph};
+use petgraph::visit::{EdgeRef, IntoEdgeReferences};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::iter::Copied;
+
+/// Open graph backed by a `petgraph::stable_graph::StableGraph`.
+///
+/// Behaves like `GGraph`, except that removing a vertex or an edge never renumbers the others,
+/// so indices collected before a removal stay valid. The slots of removed vertices and edges are
+/// reused by later additions. Iterating skips the free slots, so graphs that shrank a lot are
+/// slower to traverse than the equivalent `GGraph`. Stat tracking is not supported.
+#[derive(Debug, Clone)]
+pub struct StableGGraph<Ix: IndexType = u32> {
+    g: StableGraph<VertexData, EdgeData, petgraph::Undirected, Ix>,
+    inputs: IndexSet<NodeIndex<Ix>>,
+    outputs: IndexSet<NodeIndex<Ix>>,
+    scalar: Scalar,
+    mode: GraphMode,
+}
+
+const _: fn() = assert_send_sync::<StableGGraph>;
+
+impl<Ix: IndexType> Default for StableGGraph<Ix> {
+    fn default() -> Self {
+        StableGGraph {
+            g: StableGraph::default(),
+            inputs: IndexSet::new(),
+            outputs: IndexSet::new(),
+            scalar: Scalar::one(),
+            mode: GraphMode::default(),
+        }
+    }
+}
+
+impl<Ix: IndexType> StableGGraph<Ix> {
+    /// Create an empty graph
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set the edge policy of `add_edge`, see `GraphMode`. Existing edges are kept as they are,
+    /// and `Graph::check_invariants` reports those breaking the new mode.
+    pub fn set_mode(&mut self, mode: GraphMode) {
+        self.mode = mode;
+    }
+
+    /// Insert an edge, whatever the mode
+    fn insert_edge(&mut self, v: NodeIndex<Ix>, u: NodeIndex<Ix>, kind: EdgeKind) -> EdgeIndex<Ix> {
+        self.g.add_edge(u, v, EdgeData { kind })
+    }
+
+    /// Read-only access to the underlying petgraph structure
+    pub fn as_petgraph(&self) -> &StableGraph<VertexData, EdgeData, petgraph::Undirected, Ix> {
+        &self.g
+    }
+}
+
+impl<Ix: IndexType + MaybeSync> Graph for StableGGraph<Ix> {
+    type VertexIx = NodeIndex<Ix>;
+
+    type EdgeIx = EdgeIndex<Ix>;
+
+    type VertexData = VertexData;
+
+    type EdgeData = EdgeData;
+
+    type EdgeIterator<'a> = stable_graph::EdgeIndices<'a, EdgeData, Ix>;
+
+    type VertexIterator<'a> = stable_graph::NodeIndices<'a, VertexData, Ix>;
+
+    type NeighbourIterator<'a> = stable_graph::Neighbors<'a, EdgeData, Ix>;
+
+    type IncidentEdgeIterator<'a> = std::iter::Map<
+        stable_graph::Edges<'a, EdgeData, petgraph::Undirected, Ix>,
+        fn(stable_graph::EdgeReference<'a, EdgeData, Ix>) -> EdgeIndex<Ix>,
+    >;
+
+    type EdgeDataIterator<'a> = std::iter::Map<
+        stable_graph::EdgeReferences<'a, EdgeData, Ix>,
+        fn(
+            stable_graph::EdgeReference<'a, EdgeData, Ix>,
+        ) -> (NodeIndex<Ix>, NodeIndex<Ix>, &'a EdgeData),
+    >;
+
+    type IncidentNeighbourIterator<'a> = std::iter::Map<
+        stable_graph::Edges<'a, EdgeData, petgraph::Undirected, Ix>,
+        fn(stable_graph::EdgeReference<'a, EdgeData, Ix>) -> (EdgeIndex<Ix>, NodeIndex<Ix>),
+    >;
+
+    type NeighbourKindIterator<'a> = std::iter::Map<
+        stable_graph::Edges<'a, EdgeData, petgraph::Undirected, Ix>,
+        fn(stable_graph::EdgeReference<'a, EdgeData, Ix>) -> (NodeIndex<Ix>, EdgeKind),
+    >;
+
+    type InputIterator<'a> = Copied<set::Iter<'a, NodeIndex<Ix>>>;
+
+    type OutputIterator<'a> = Copied<set::Iter<'a, NodeIndex<Ix>>>;
+
+    const BACKEND: &'static str = "StableGGraph-rs";
+
+    fn set_input(&mut self, v: Self::VertexIx, flag: bool) {
+        if flag {
+            self.inputs.insert(v);
+        } else {
+            self.inputs.shift_remove(&v);
+        }
+    }
+
+    fn set_output(&mut self, v: Self::VertexIx, flag: bool) {
+        if flag {
+            self.outputs.insert(v);
+        } else {
+            self.outputs.shift_remove(&v);
+        }
+    }
+
+    fn is_input(&self, v: Self::VertexIx) -> bool {
+        self.inputs.contains(&v)
+    }
+
+    fn is_output(&self, v: Self::VertexIx) -> bool {
+        self.outputs.contains(&v)
+    }
+
+    fn inputs(&self) -> Self::InputIterator<'_> {
+        self.inputs.iter().copied()
+    }
+
+    fn outputs(&self) -> Self::OutputIterator<'_> {
+        self.outputs.iter().copied()
+    }
+
+    fn input_at(&self, i: usize) -> Option<Self::VertexIx> {
+        self.inputs.get_index(i).copied()
+    }
+
+    fn output_at(&self, i: usize) -> Option<Self::VertexIx> {
+        self.outputs.get_index(i).copied()
+    }
+
+    fn set_io_order(&mut self, inputs: Vec<Self::VertexIx>, outputs: Vec<Self::VertexIx>) {
+        self.inputs = inputs.into_iter().collect();
+        self.outputs = outputs.into_iter().collect();
+    }
+
+    fn num_vertices(&self) -> u32 {
+        self.g.node_count() as u32
+    }
+
+    fn num_edges(&self) -> u32 {
+        self.g.edge_count() as u32
+    }
+
+    fn vertices(&self) -> Self::VertexIterator<'_> {
+        self.g.node_indices()
+    }
+
+    fn edges(&self) -> Self::EdgeIterator<'_> {
+        self.g.edge_indices()
+    }
+
+    fn edges_with_data(&self) -> Self::EdgeDataIterator<'_> {
+        self.g
+            .edge_references()
+            .map(|e| (e.source(), e.target(), e.weight()))
+    }
+
+    fn vertex(&self, v: Self::VertexIx) -> Option<&Self::VertexData> {
+        self.g.node_weight(v)
+    }
+
+    fn vertex_mut(&mut self, v: Self::VertexIx) -> Option<&mut Self::VertexData> {
+        self.g.node_weight_mut(v)
+    }
+
+    fn edge(&self, e: Self::EdgeIx) -> Option<&Self::EdgeData> {
+        self.g.edge_weight(e)
+    }
+
+    fn edge_mut(&mut self, e: Self::EdgeIx) -> Option<&mut Self::EdgeData> {
+        self.g.edge_weight_mut(e)
+    }
+
+    fn scalar(&self) -> &Scalar {
+        &self.scalar
+    }
+
+    fn scalar_mut(&mut self) -> &mut Scalar {
+        &mut self.scalar
+    }
+
+    fn edge_endpoints(&self, e: Self::EdgeIx) -> Option<(Self::VertexIx, Self::VertexIx)> {
+        self.g.edge_endpoints(e)
+    }
+
+    fn neighbours(&self, v: Self::VertexIx) -> Self::NeighbourIterator<'_> {
+        self.g.neighbors(v)
+    }
+
+    fn incident_edges(&self, v: Self::VertexIx) -> Self::IncidentEdgeIterator<'_> {
+        self.g.edges(v).map(|e| e.id())
+    }
+
+    /// As in `GGraph`, the edges have `v` as their source.
+    fn incident_edges_with_neighbours(
+        &self,
+        v: Self::VertexIx,
+    ) -> Self::IncidentNeighbourIterator<'_> {
+        self.g.edges(v).map(|e| (e.id(), e.target()))
+    }
+
+    fn neighbours_with_kind(&self, v: Self::VertexIx) -> Self::NeighbourKindIterator<'_> {
+        self.g.edges(v).map(|e| (e.target(), e.weight().kind))
+    }
+
+    fn is_ground(&self, v: Self::VertexIx) -> bool {
+        self.g[v].ground
+    }
+
+    fn adjoint(&mut self) {
+        std::mem::swap(&mut self.inputs, &mut self.outputs);
+        self.scalar = self.scalar.conjugate();
+        let last_row = self.depth() as i32 - 1;
+        for vdata in self.g.node_weights_mut() {
+            vdata.phase = -vdata.phase;
+            vdata.row = last_row - vdata.row;
+        }
+    }
+
+    fn compose(&mut self, other: Self) -> Result<(), ComposeError> {
+        if self.outputs.len() != other.inputs.len() {
+            return Err(ComposeError::ArityMismatch {
+                outputs: self.outputs.len(),
+                inputs: other.inputs.len(),
+            });
+        }
+        self.scalar *= other.scalar;
+        let seams: Vec<_> = self.outputs.iter().copied().collect();
+
+        // Same layout as `GGraph::compose`: the inputs of `other` are identified with the
+        // outputs of `self`, and the rows of `other` continue from the last row of `self`.
+        let offset = self.depth().saturating_sub(1) as i32;
+        let mut map = HashMap::new();
+        for (&i, &o) in other.inputs.iter().zip(&seams) {
+            map.insert(i, o);
+            self.g[o].ground |= other.g[i].ground;
+        }
+        for v in other.g.node_indices() {
+            if let Entry::Vacant(entry) = map.entry(v) {
+                let vdata = other.g[v].clone();
+                entry.insert(self.g.add_node(VertexData {
+                    row: vdata.row + offset,
+                    ..vdata
+                }));
+            }
+        }
+        for e in other.g.edge_references() {
+            self.add_edge(map[&e.source()], map[&e.target()], e.weight().kind);
+        }
+        self.outputs = other.outputs.iter().map(|v| map[v]).collect();
+
+        // Turn the joined boundaries into plain wires, as `GGraph::compose` does. Indices are
+        // stable, so the seams can be removed in any order.
+        for s in seams {
+            if self.inputs.contains(&s) || self.outputs.contains(&s) {
+                continue;
+            }
+            self.set_vertex_kind(s, VertexKind::Z);
+            self.set_phase(s, Phase::zero());
+            let wire: Vec<_> = self
+                .g
+                .edges(s)
+                .map(|e| {
+                    let n = if e.source() == s {
+                        e.target()
+                    } else {
+                        e.source()
+                    };
+                    (n, e.weight().kind)
+                })
+                .collect();
+            if let [(a, k1), (b, k2)] = wire[..] {
+                if a != s && b != s && a != b && !self.g[s].ground {
+                    self.remove_vertex(s);
+                    let kind = if k1 == k2 {
+                        EdgeKind::Regular
+                    } else {
+                        EdgeKind::Hadam
+                    };
+                    self.add_edge(a, b, kind);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn tensor(&mut self, other: Self) {
+        let qubit_offset = self.qubit_count() as i32;
+        self.scalar *= other.scalar;
+        let map: HashMap<_, _> = other
+            .g
+            .node_indices()
+            .map(|v| {
+                let vdata = other.g[v].clone();
+                let new_v = self.g.add_node(VertexData {
+                    qubit: vdata.qubit + qubit_offset,
+                    ..vdata
+                });
+                (v, new_v)
+            })
+            .collect();
+        for e in other.g.edge_references() {
+            self.add_edge(map[&e.source()], map[&e.target()], e.weight().kind);
+        }
+        self.inputs.extend(other.inputs.iter().map(|v| map[v]));
+        self.outputs.extend(other.outputs.iter().map(|v| map[v]));
+    }
+
+    fn add_vertices(&mut self, count: u32) -> Vec<Self::VertexIx> {
+        (0..count)
+            .map(|_| self.add_vertex(Default::default(), Default::default()))
+            .collect()
+    }
+
+    fn add_vertex(&mut self, kind: VertexKind, phase: Phase) -> Self::VertexIx {
+        self.g.add_node(VertexData {
+            kind,
+            phase,
+            ..Default::default()
+        })
+    }
+
+    fn mode(&self) -> GraphMode {
+        self.mode
+    }
+
+    fn add_edge(
+        &mut self,
+        v: Self::VertexIx,
+        u: Self::VertexIx,
+        kind: EdgeKind,
+    ) -> Option<Self::EdgeIx> {
+        match self.mode {
+            GraphMode::Simple => merge_edge(self, v, u, kind, |g, v, u, kind| {
+                Some(g.insert_edge(v, u, kind))
+            }),
+            GraphMode::Multi => Some(self.insert_edge(v, u, kind)),
+        }
+    }
+
+    /// Remove a vertex and its edges. The other vertices keep their indices.
+    fn remove_vertex(&mut self, v: Self::VertexIx) {
+        if self.g.remove_node(v).is_some() {
+            self.inputs.shift_remove(&v);
+            self.outputs.shift_remove(&v);
+        }
+    }
+
+    /// Remove an edge. The other edges keep their indices.
+    fn remove_edge(&mut self, e: Self::EdgeIx) {
+        self.g.remove_edge(e);
+    }
+}
+
+impl<Ix: IndexType + MaybeSync> CartesianGraph for StableGGraph<Ix> {
+    type Qubit = i32;
+    type Row = i32;
+
+    fn row(&self, v: Self::VertexIx) -> i32 {
+        self.g[v].row
+    }
+
+    fn qubit(&self, v: Self::VertexIx) -> i32 {
+        self.g[v].qubit
+    }
+
+    fn set_row(&mut self, v: Self::VertexIx, r: i32) {
+        self.g[v].row = r;
+    }
+
+    fn set_qubit(&mut self, v: Self::VertexIx, q: i32) {
+        self.g[v].qubit = q;
+    }
+
+    fn set_position(&mut self, v: Self::VertexIx, q: i32, r: i32) {
+        let vdata = &mut self.g[v];
+        vdata.qubit = q;
+        vdata.row = r;
+    }
+
+    /// One more than the largest row, or 0 if there are no vertices on a non-negative row
+    fn depth(&self) -> u32 {
+        self.g
+            .node_indices()
+            .map(|v| self.g[v].row + 1)
+            .max()
+            .unwrap_or(0)
+            .max(0) as u32
+    }
+
+    /// One more than the largest qubit, or 0 if there are no vertices on a non-negative qubit
+    fn qubit_count(&self) -> u32 {
+        self.g
+            .node_indices()
+            .map(|v| self.g[v].qubit + 1)
+            .max()
+            .unwrap_or(0)
+            .max(0) as u32
+    }
+}
+
+impl<Ix: IndexType + MaybeSync> GroundGraph for StableGGraph<Ix> {
+    type GroundIterator = std::vec::IntoIter<NodeIndex<Ix>>;
+
+    fn grounds(&self) -> Self::GroundIterator {
+        self.g
+            .node_indices()
+            .filter(|&v| self.g[v].ground)
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::simple_graph::GGraph;
+    use crate::zx::vec_graph::VecGraph;
+    use std::collections::BTreeSet;
+
+    /// Ring of `n` Z spiders, each labelled by a distinct phase
+    fn ring<G: Graph + Default>(n: i64) -> G {
+        let mut g = G::default();
+        let vs: Vec<_> = (0..n)
+            .map(|i| g.add_vertex(VertexKind::Z, Phase::new(i, 64)))
+            .collect();
+        for (i, &v) in vs.iter().enumerate() {
+            g.add_edge(v, vs[(i + 1) % vs.len()], EdgeKind::Hadam);
+        }
+        g
+    }
+
+    /// Label of each vertex, read back from its phase, with the labels of its neighbours
+    fn labelled<G: Graph>(g: &G) -> BTreeSet<(i64, BTreeSet<i64>)> {
+        let label = |v| (g.vertex(v).unwrap().phase().to_f64() * 64.0).round() as i64;
+        g.vertices()
+            .map(|v| (label(v), g.neighbours(v).map(label).collect()))
+            .collect()
+    }
+
+    /// Remove every third vertex of a ring from a list collected beforehand, in a scrambled
+    /// order, and check that the rest of the diagram is untouched.
+    fn check_batch_removal<G: Graph + Default>() {
+        let mut g: G = ring(30);
+        let vs: Vec<_> = g.vertices().collect();
+        let mut removed: Vec<_> = vs.iter().copied().step_by(3).collect();
+        removed.reverse();
+        removed.swap(1, 6);
+        let removed_labels: BTreeSet<_> = (0..30).step_by(3).collect();
+        let expected: BTreeSet<_> = labelled(&g)
+            .into_iter()
+            .filter(|(l, _)| !removed_labels.contains(l))
+            .map(|(l, ns)| (l, &ns - &removed_labels))
+            .collect();
+        g.remove_vertices(removed);
+        assert_eq!(g.num_vertices(), 20);
+        assert_eq!(g.num_edges(), 10);
+        assert_eq!(labelled(&g), expected);
+    }
+
+    #[test]
+    fn batch_removal() {
+        check_batch_removal::<GGraph>();
+        check_batch_removal::<StableGGraph>();
+        check_batch_removal::<VecGraph>();
+    }
+
+    #[test]
+    fn removal_keeps_indices() {
+        let mut g: StableGGraph = ring(10);
+        let vs: Vec<_> = g.vertices().collect();
+        let data: Vec<_> = vs.iter().map(|&v| g.vertex(v).unwrap().clone()).collect();
+        // One at a time, from a list collected before the first removal
+        for &v in &[vs[0], vs[4], vs[9], vs[5]] {
+            g.remove_vertex(v);
+        }
+        for (i, &v) in vs.iter().enumerate() {
+            if [0, 4, 5, 9].contains(&i) {
+                assert!(g.vertex(v).is_none());
+            } else {
+                assert_eq!(*g.vertex(v).unwrap(), data[i]);
+            }
+        }
+        assert_eq!(g.neighbours(vs[1]).collect::<Vec<_>>(), vec![vs[2]]);
+
+        // Freed slots are reused by later additions, without touching the others.
+        let w = g.add_vertex(VertexKind::X, Phase::pi());
+        assert!(vs.contains(&w));
+        assert_eq!(g.num_vertices(), 7);
+        assert_eq!(*g.vertex(vs[1]).unwrap(), data[1]);
+    }
+}
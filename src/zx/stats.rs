@@ -0,0 +1,328 @@
+use crate::zx::graph::*;
+use std::collections::{BTreeMap, HashMap};
+
+/// Summary of the structure of a graph, for comparing backends and diagrams.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct GraphStats {
+    pub backend: &'static str,
+    pub num_vertices: u32,
+    pub num_edges: u32,
+    /// Degree percentiles, using the nearest-rank method
+    pub degree_p50: u32,
+    pub degree_p90: u32,
+    pub degree_p99: u32,
+    pub degree_max: u32,
+    /// Average degree of the vertices of each kind, zero if there are none
+    pub avg_degree_z: f64,
+    pub avg_degree_x: f64,
+    pub avg_degree_boundary: f64,
+    pub avg_degree_hbox: f64,
+    /// Number of connected components
+    pub components: u32,
+    /// Fraction of the vertices contained in the largest connected component
+    pub largest_component_fraction: f64,
+    /// Number of vertices for each phase denominator
+    pub phase_denominators: BTreeMap<u64, u32>,
+}
+
+impl GraphStats {
+    /// Column names matching `to_csv_row`.
+    pub const CSV_HEADER: &'static str = "backend,num_vertices,num_edges,\
+        degree_p50,degree_p90,degree_p99,degree_max,\
+        avg_degree_z,avg_degree_x,avg_degree_boundary,avg_degree_hbox,\
+        components,largest_component_fraction,phase_denominators";
+
+    /// Compute the statistics with a single pass over the vertices and one over the edges.
+    pub fn new<G: Graph>(g: &G) -> Self {
+        let mut ids: HashMap<G::VertexIx, usize> = HashMap::new();
+        let mut degrees = Vec::new();
+        let mut kind_degrees: HashMap<VertexKind, (u32, u32)> = HashMap::new();
+        let mut phase_denominators = BTreeMap::new();
+
+        for v in g.vertices() {
+            let degree = g.vertex_degree(v);
+            let vdata = g.vertex(v).unwrap();
+            ids.insert(v, degrees.len());
+            degrees.push(degree);
+            let entry = kind_degrees.entry(vdata.kind()).or_insert((0, 0));
+            entry.0 += 1;
+            entry.1 += degree;
+            *phase_denominators
+                .entry(vdata.phase().denominator())
+                .or_insert(0) += 1;
+        }
+
+        let mut components = UnionFind::new(degrees.len());
+        for e in g.edges() {
+            let (v, u) = g.edge_endpoints(e).unwrap();
+            components.union(ids[&v], ids[&u]);
+        }
+        let (num_components, largest_component) = components.summary();
+
+        degrees.sort_unstable();
+        let avg_degree = |kind| match kind_degrees.get(&kind) {
+            Some(&(count, total)) => total as f64 / count as f64,
+            None => 0.,
+        };
+        let largest_component_fraction = match degrees.len() {
+            0 => 0.,
+            n => largest_component as f64 / n as f64,
+        };
+
+        GraphStats {
+            backend: G::BACKEND,
+            num_vertices: g.num_vertices(),
+            num_edges: g.num_edges(),
+            degree_p50: percentile(&degrees, 50),
+            degree_p90: percentile(&degrees, 90),
+            degree_p99: percentile(&degrees, 99),
+            degree_max: degrees.last().copied().unwrap_or(0),
+            avg_degree_z: avg_degree(VertexKind::Z),
+            avg_degree_x: avg_degree(VertexKind::X),
+            avg_degree_boundary: avg_degree(VertexKind::Boundary),
+            avg_degree_hbox: avg_degree(VertexKind::HBox),
+            components: num_components,
+            largest_component_fraction,
+            phase_denominators,
+        }
+    }
+
+    /// A CSV row with the columns listed in `CSV_HEADER`.
+    ///
+    /// The phase denominator histogram is packed in the last column as `den:count` pairs
+    /// separated by semicolons.
+    pub fn to_csv_row(&self) -> String {
+        let denominators = self
+            .phase_denominators
+            .iter()
+            .map(|(den, count)| format!("{}:{}", den, count))
+            .collect::<Vec<_>>()
+            .join(";");
+        format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            self.backend,
+            self.num_vertices,
+            self.num_edges,
+            self.degree_p50,
+            self.degree_p90,
+            self.degree_p99,
+            self.degree_max,
+            self.avg_degree_z,
+            self.avg_degree_x,
+            self.avg_degree_boundary,
+            self.avg_degree_hbox,
+            self.components,
+            self.largest_component_fraction,
+            denominators
+        )
+    }
+}
+
+/// Nearest-rank percentile of a sorted slice
+fn percentile(sorted: &[u32], p: usize) -> u32 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (p * sorted.len()).div_ceil(100);
+    sorted[rank.max(1) - 1]
+}
+
+/// Disjoint-set forest over `0..n`
+struct UnionFind {
+    parent: Vec<usize>,
+    size: Vec<u32>,
+}
+
+impl UnionFind {
+    fn new(n: usize) -> Self {
+        UnionFind {
+            parent: (0..n).collect(),
+            size: vec![1; n],
+        }
+    }
+
+    fn find(&mut self, mut x: usize) -> usize {
+        while self.parent[x] != x {
+            self.parent[x] = self.parent[self.parent[x]];
+            x = self.parent[x];
+        }
+        x
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+        if self.size[a] < self.size[b] {
+            std::mem::swap(&mut a, &mut b);
+        }
+        self.parent[b] = a;
+        self.size[a] += self.size[b];
+    }
+
+    /// Number of sets and size of the largest one
+    fn summary(&mut self) -> (u32, u32) {
+        let mut count = 0;
+        let mut largest = 0;
+        for x in 0..self.parent.len() {
+            if self.find(x) == x {
+                count += 1;
+                largest = largest.max(self.size[x]);
+            }
+        }
+        (count, largest)
+    }
+}
+
+/// Counters that `GGraph` can keep up to date on every mutation, see
+/// `GGraph::enable_stat_tracking`. The other backends do not track them, and `TrackedStats::new`
+/// computes them with a full scan of any graph.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TrackedStats {
+    pub num_z: u32,
+    pub num_x: u32,
+    pub num_hbox: u32,
+    pub num_boundary: u32,
+    pub num_hole: u32,
+    pub hadamard_edges: u32,
+    /// Number of vertices with a phase that is an odd multiple of pi/4
+    pub tcount: u32,
+}
+
+impl TrackedStats {
+    /// Compute the counters from scratch.
+    pub fn new<G: Graph>(g: &G) -> Self {
+        let mut stats = TrackedStats::default();
+        for v in g.vertices() {
+            let vdata = g.vertex(v).unwrap();
+            stats.add_vertex(vdata.kind(), vdata.phase());
+        }
+        for e in g.edges() {
+            stats.add_edge(g.edge(e).unwrap().kind());
+        }
+        stats
+    }
+
+    fn kind_count(&mut self, kind: VertexKind) -> &mut u32 {
+        match kind {
+            VertexKind::Z => &mut self.num_z,
+            VertexKind::X => &mut self.num_x,
+            VertexKind::HBox => &mut self.num_hbox,
+            VertexKind::Boundary => &mut self.num_boundary,
+            VertexKind::Hole => &mut self.num_hole,
+        }
+    }
+
+    pub(crate) fn add_vertex(&mut self, kind: VertexKind, phase: Phase) {
+        *self.kind_count(kind) += 1;
+        if phase.is_proper_t() {
+            self.tcount += 1;
+        }
+    }
+
+    pub(crate) fn remove_vertex(&mut self, kind: VertexKind, phase: Phase) {
+        *self.kind_count(kind) -= 1;
+        if phase.is_proper_t() {
+            self.tcount -= 1;
+        }
+    }
+
+    pub(crate) fn add_edge(&mut self, kind: EdgeKind) {
+        if kind == EdgeKind::Hadam {
+            self.hadamard_edges += 1;
+        }
+    }
+
+    pub(crate) fn remove_edge(&mut self, kind: EdgeKind) {
+        if kind == EdgeKind::Hadam {
+            self.hadamard_edges -= 1;
+        }
+    }
+}
+
+/// Statistics of a single qubit of a `CartesianGraph`, see `CartesianGraph::wire_profile`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct WireStats {
+    /// Z and X spiders on the qubit
+    pub spiders: u32,
+    /// Spiders with a phase that is an odd multiple of pi/4
+    pub t_spiders: u32,
+    /// Edges between a vertex on the qubit and one on another qubit
+    pub crossing_edges: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::templates;
+
+    /// A CNOT and a disconnected `Z(π/4)` spider
+    fn fixture() -> crate::zx::simple_graph::GGraph {
+        let mut g = templates::cnot();
+        g.add_vertex(VertexKind::Z, Phase::new(1, 4));
+        g
+    }
+
+    #[test]
+    fn hand_computed_values() {
+        let stats = fixture().graph_stats();
+        // Degrees are 0 for the lone spider, 1 for the four boundaries and 3 for the CNOT.
+        assert_eq!(stats.num_vertices, 7);
+        assert_eq!(stats.num_edges, 5);
+        assert_eq!(stats.degree_p50, 1);
+        assert_eq!(stats.degree_p90, 3);
+        assert_eq!(stats.degree_p99, 3);
+        assert_eq!(stats.degree_max, 3);
+        assert_eq!(stats.avg_degree_z, 1.5);
+        assert_eq!(stats.avg_degree_x, 3.);
+        assert_eq!(stats.avg_degree_boundary, 1.);
+        assert_eq!(stats.avg_degree_hbox, 0.);
+        assert_eq!(stats.components, 2);
+        assert_eq!(stats.largest_component_fraction, 6. / 7.);
+        let denominators: Vec<_> = stats.phase_denominators.into_iter().collect();
+        assert_eq!(denominators, [(1, 6), (4, 1)]);
+    }
+
+    #[test]
+    fn empty_graph() {
+        let stats = crate::zx::simple_graph::GGraph::<u32>::new().graph_stats();
+        assert_eq!(
+            (stats.degree_p50, stats.degree_max, stats.components),
+            (0, 0, 0)
+        );
+        assert_eq!(stats.largest_component_fraction, 0.);
+    }
+
+    #[test]
+    fn csv_columns_are_stable() {
+        assert_eq!(
+            GraphStats::CSV_HEADER,
+            "backend,num_vertices,num_edges,degree_p50,degree_p90,degree_p99,degree_max,\
+             avg_degree_z,avg_degree_x,avg_degree_boundary,avg_degree_hbox,\
+             components,largest_component_fraction,phase_denominators"
+        );
+        let row = fixture().graph_stats().to_csv_row();
+        assert_eq!(
+            row,
+            "GGraph-rs,7,5,1,3,3,3,1.5,3,1,0,2,0.8571428571428571,1:6;4:1"
+        );
+        assert_eq!(
+            row.split(',').count(),
+            GraphStats::CSV_HEADER.split(',').count()
+        );
+    }
+
+    #[test]
+    fn percentiles_use_nearest_rank() {
+        let sorted: Vec<u32> = (1..=10).collect();
+        assert_eq!(percentile(&sorted, 50), 5);
+        assert_eq!(percentile(&sorted, 90), 9);
+        assert_eq!(percentile(&sorted, 99), 10);
+        assert_eq!(percentile(&[7], 50), 7);
+        assert_eq!(percentile(&[], 50), 0);
+    }
+}
@@ -0,0 +1,338 @@
+//! Common diagram constructions.
+//!
+//! Boundary vertices are created in wire order, inputs before outputs. Every vertex is
+//! positioned, with the inputs on row 0, the outputs on the last row and boundary `i` on qubit
+//! `i`, so the templates pass `CartesianGraph::check_layout`. Vertices that are not on a wire,
+//! like H-boxes and gadgets, are placed on the qubits below the wires.
+//!
+//! The templates carry the scalars that make them denote their definitions exactly, as
+//! `Circuit::to_graph` does.
+
+use crate::zx::circuit::{Circuit, Gate};
+use crate::zx::graph::*;
+use crate::zx::simple_graph::GGraph;
+
+type V = <GGraph as Graph>::VertexIx;
+
+/// Add `count` boundaries on row `row`, as inputs or outputs
+fn boundaries(g: &mut GGraph, count: usize, input: bool, row: i32) -> Vec<V> {
+    (0..count)
+        .map(|q| {
+            let v = g.add_vertex(VertexKind::Boundary, Phase::default());
+            g.set_position(v, q as i32, row);
+            if input {
+                g.set_input(v, true);
+            } else {
+                g.set_output(v, true);
+            }
+            v
+        })
+        .collect()
+}
+
+/// Add a vertex at the given qubit and row
+fn vertex_at(g: &mut GGraph, kind: VertexKind, phase: Phase, qubit: usize, row: i32) -> V {
+    let v = g.add_vertex(kind, phase);
+    g.set_position(v, qubit as i32, row);
+    v
+}
+
+/// The Bell state (|00⟩ + |11⟩), up to normalization: two outputs joined by a Z spider.
+pub fn bell_pair() -> GGraph {
+    ghz(2)
+}
+
+/// The `n`-qubit GHZ state, up to normalization: a Z spider connected to `n` outputs.
+pub fn ghz(n: usize) -> GGraph {
+    let mut g = GGraph::new();
+    let outputs = boundaries(&mut g, n, false, 1);
+    let z = vertex_at(
+        &mut g,
+        VertexKind::Z,
+        Phase::default(),
+        n.saturating_sub(1) / 2,
+        0,
+    );
+    for o in outputs {
+        g.add_edge(z, o, EdgeKind::Regular);
+    }
+    g
+}
+
+/// The `n`-qubit quantum Fourier transform, as the translation of its textbook circuit.
+///
+/// Qubit 0 is the most significant: the basis state `j` is mapped to
+/// `Σ_k e^{2πi jk / 2^n} |k⟩ / √2^n`. Each qubit gets a Hadamard followed by controlled phases
+/// of `π / 2^d` from the qubits at distance `d` below it, and the wires are reversed at the end.
+pub fn qft(n: u32) -> GGraph {
+    let mut c = Circuit::new(n);
+    for q in 0..n {
+        c.add_gate(Gate::H(q));
+        for d in 1..n - q {
+            c.add_gate(Gate::CPhase(q + d, q, Phase::new(1, 1 << d)));
+        }
+    }
+    for q in 0..n / 2 {
+        c.add_gate(Gate::Swap(q, n - 1 - q));
+    }
+    c.to_graph_into(GGraph::builder())
+}
+
+/// CNOT gate with control on wire 0 and target on wire 1.
+///
+/// The X spider contributes a factor of 1/√2, which the scalar compensates.
+pub fn cnot() -> GGraph {
+    let mut g = GGraph::new();
+    let inputs = boundaries(&mut g, 2, true, 0);
+    let outputs = boundaries(&mut g, 2, false, 2);
+    let control = vertex_at(&mut g, VertexKind::Z, Phase::default(), 0, 1);
+    let target = vertex_at(&mut g, VertexKind::X, Phase::default(), 1, 1);
+    g.add_edge(inputs[0], control, EdgeKind::Regular);
+    g.add_edge(control, outputs[0], EdgeKind::Regular);
+    g.add_edge(inputs[1], target, EdgeKind::Regular);
+    g.add_edge(target, outputs[1], EdgeKind::Regular);
+    g.add_edge(control, target, EdgeKind::Regular);
+    *g.scalar_mut() = Scalar::new(1, Phase::zero());
+    g
+}
+
+/// Toffoli gate with controls on wires 0 and 1 and target on wire 2, in H-box form.
+///
+/// The three wires carry Z spiders joined to a single arity-3 H-box, implementing a CCZ, and the
+/// target wire is conjugated by Hadamard edges.
+pub fn toffoli() -> GGraph {
+    let mut g = GGraph::new();
+    let inputs = boundaries(&mut g, 3, true, 0);
+    let outputs = boundaries(&mut g, 3, false, 2);
+    let hbox = vertex_at(&mut g, VertexKind::HBox, Phase::pi(), 3, 1);
+    for q in 0..3 {
+        let z = vertex_at(&mut g, VertexKind::Z, Phase::default(), q, 1);
+        let wire = if q == 2 {
+            EdgeKind::Hadam
+        } else {
+            EdgeKind::Regular
+        };
+        g.add_edge(inputs[q], z, wire);
+        g.add_edge(z, outputs[q], wire);
+        g.add_edge(z, hbox, EdgeKind::Regular);
+    }
+    g
+}
+
+/// Toffoli gate with controls on wires 0 and 1 and target on wire 2, in phase gadget form.
+///
+/// The CCZ is decomposed as a `π/4` phase on each wire, a `-π/4` gadget on each pair of wires and
+/// a `π/4` gadget on all three, with the target wire conjugated by Hadamard edges. Each gadget is
+/// an X spider connected to the Z spider of every wire it acts on and to a phase-carrying Z leaf,
+/// with the gadgets side by side below the wires.
+pub fn toffoli_gadgets() -> GGraph {
+    let mut g = GGraph::new();
+    let inputs = boundaries(&mut g, 3, true, 0);
+    let outputs = boundaries(&mut g, 3, false, 5);
+    let wires: Vec<_> = (0..3)
+        .map(|q| {
+            let z = vertex_at(&mut g, VertexKind::Z, Phase::new(1, 4), q, 1);
+            let wire = if q == 2 {
+                EdgeKind::Hadam
+            } else {
+                EdgeKind::Regular
+            };
+            g.add_edge(inputs[q], z, wire);
+            g.add_edge(z, outputs[q], wire);
+            z
+        })
+        .collect();
+    let gadgets: [(&[usize], i64); 4] =
+        [(&[0, 1], -1), (&[0, 2], -1), (&[1, 2], -1), (&[0, 1, 2], 1)];
+    for (i, (qubits, num)) in gadgets.iter().enumerate() {
+        let row = 1 + i as i32;
+        let hub = vertex_at(&mut g, VertexKind::X, Phase::default(), 3, row);
+        let leaf = vertex_at(&mut g, VertexKind::Z, Phase::new(*num, 4), 4, row);
+        g.add_edge(hub, leaf, EdgeKind::Regular);
+        for &q in qubits.iter() {
+            g.add_edge(wires[q], hub, EdgeKind::Regular);
+        }
+    }
+    // The four gadgets and the Hadamard edges leave a factor of 1/√2^5.
+    *g.scalar_mut() = Scalar::new(5, Phase::zero());
+    g
+}
+
+/// Graph state with a qubit per vertex of the given adjacency lists.
+///
+/// Each qubit is a Z spider connected to its output, and each edge of the graph becomes a
+/// Hadamard edge between spiders.
+pub fn graph_state(adjacency: &[Vec<usize>]) -> GGraph {
+    let mut g = GGraph::new();
+    let outputs = boundaries(&mut g, adjacency.len(), false, 1);
+    let spiders: Vec<_> = outputs
+        .iter()
+        .enumerate()
+        .map(|(q, &o)| {
+            let z = vertex_at(&mut g, VertexKind::Z, Phase::default(), q, 0);
+            g.add_edge(z, o, EdgeKind::Regular);
+            z
+        })
+        .collect();
+    for (v, neighbours) in adjacency.iter().enumerate() {
+        for &u in neighbours.iter().filter(|&&u| u > v) {
+            g.add_edge(spiders[v], spiders[u], EdgeKind::Hadam);
+        }
+    }
+    g
+}
+/// Cluster state on a `rows` × `cols` grid, with qubit `r * cols + c` at row `r` and column `c`.
+pub fn cluster_state(rows: usize, cols: usize) -> GGraph {
+    let mut adjacency = vec![Vec::new(); rows * cols];
+    for r in 0..rows {
+        for c in 0..cols {
+            let v = r * cols + c;
+            if c + 1 < cols {
+                adjacency[v].push(v + 1);
+                adjacency[v + 1].push(v);
+            }
+            if r + 1 < rows {
+                adjacency[v].push(v + cols);
+                adjacency[v + cols].push(v);
+            }
+        }
+    }
+    graph_state(&adjacency)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::tensor::{to_tensor, Complex64, Tensor};
+    use std::f64::consts::{FRAC_1_SQRT_2, PI};
+
+    /// Check that `t` is `factor` times the map with `expected(inputs, outputs)` as entries
+    fn assert_tensor(t: &Tensor, factor: f64, expected: impl Fn(usize, usize) -> f64) {
+        for i in 0..1 << t.num_inputs() {
+            for o in 0..1 << t.num_outputs() {
+                let want = Complex64::new(factor * expected(i, o), 0.0);
+                assert!(
+                    (t.entry(i, o) - want).norm() < 1e-9,
+                    "entry ({:b}, {:b}) is {}, expected {}",
+                    i,
+                    o,
+                    t.entry(i, o),
+                    want
+                );
+            }
+        }
+    }
+
+    /// Whether `o` is the image of the basis state `i` under a Toffoli with controls on the two
+    /// most significant bits
+    fn toffoli_entry(i: usize, o: usize) -> f64 {
+        let flip = if i & 0b110 == 0b110 { 1 } else { 0 };
+        (o == i ^ flip) as u8 as f64
+    }
+
+    #[test]
+    fn ghz_states() {
+        assert_tensor(&to_tensor(&bell_pair()), 1.0, |_, o| {
+            (o == 0b00 || o == 0b11) as u8 as f64
+        });
+        for n in 1..6 {
+            let t = to_tensor(&ghz(n));
+            assert_eq!((t.num_inputs(), t.num_outputs()), (0, n));
+            assert_tensor(&t, 1.0, |_, o| (o == 0 || o == (1 << n) - 1) as u8 as f64);
+        }
+    }
+
+    #[test]
+    fn cnot_gate() {
+        assert_tensor(&to_tensor(&cnot()), 1.0, |i, o| {
+            let flip = (i >> 1) & 1;
+            (o == i ^ flip) as u8 as f64
+        });
+    }
+
+    #[test]
+    fn toffoli_gates() {
+        assert_tensor(&to_tensor(&toffoli()), 1.0, toffoli_entry);
+        assert_tensor(&to_tensor(&toffoli_gadgets()), 1.0, toffoli_entry);
+    }
+
+    #[test]
+    fn qft_matrix() {
+        for n in 1..5 {
+            let t = to_tensor(&qft(n));
+            let dim = 1 << n;
+            for j in 0..dim {
+                for k in 0..dim {
+                    let angle = 2.0 * PI * (j * k) as f64 / dim as f64;
+                    let want = Complex64::from_polar(&(dim as f64).sqrt().recip(), &angle);
+                    assert!(
+                        (t.entry(j, k) - want).norm() < 1e-9,
+                        "entry ({}, {}) of qft({}) is {}, expected {}",
+                        j,
+                        k,
+                        n,
+                        t.entry(j, k),
+                        want
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn templates_are_positioned() {
+        let adjacency = vec![vec![1, 2], vec![0], vec![0]];
+        let templates = [
+            bell_pair(),
+            ghz(5),
+            qft(3),
+            cnot(),
+            toffoli(),
+            toffoli_gadgets(),
+            graph_state(&adjacency),
+            cluster_state(2, 3),
+        ];
+        for (i, g) in templates.iter().enumerate() {
+            assert_eq!(g.check_layout(), Ok(()), "template {}", i);
+            for (q, v) in g.inputs().enumerate() {
+                assert_eq!(g.qubit(v), q as i32, "template {}", i);
+            }
+            for (q, v) in g.outputs().enumerate() {
+                assert_eq!(g.qubit(v), q as i32, "template {}", i);
+            }
+        }
+    }
+
+    /// Amplitudes of the graph state with the given edges, on qubits numbered from the most
+    /// significant bit
+    fn graph_state_entry(n: usize, edges: &[(usize, usize)], o: usize) -> f64 {
+        let bit = |q: usize| (o >> (n - 1 - q)) & 1;
+        let parity = edges.iter().fold(0, |p, &(u, v)| p ^ (bit(u) & bit(v)));
+        if parity == 0 {
+            1.0
+        } else {
+            -1.0
+        }
+    }
+
+    #[test]
+    fn graph_states() {
+        let edges = [(0, 1), (1, 2), (2, 0), (2, 3)];
+        let mut adjacency = vec![Vec::new(); 4];
+        for &(u, v) in &edges {
+            adjacency[u].push(v);
+            adjacency[v].push(u);
+        }
+        // Each Hadamard edge contributes a 1/√2 factor.
+        assert_tensor(&to_tensor(&graph_state(&adjacency)), 0.25, |_, o| {
+            graph_state_entry(4, &edges, o)
+        });
+
+        let grid = [(0, 1), (1, 2), (3, 4), (4, 5), (0, 3), (1, 4), (2, 5)];
+        let t = to_tensor(&cluster_state(2, 3));
+        assert_eq!(t.num_outputs(), 6);
+        assert_tensor(&t, FRAC_1_SQRT_2.powi(7), |_, o| {
+            graph_state_entry(6, &grid, o)
+        });
+    }
+}
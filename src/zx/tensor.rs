@@ -0,0 +1,471 @@
+//! Dense evaluation of small diagrams, for checking rewrites.
+//!
+//! The diagram is contracted one vertex at a time, so the cost grows exponentially with the
+//! number of edges crossing between the contracted and remaining parts. This is only meant for
+//! diagrams with a dozen or so boundary wires.
+
+use crate::zx::graph::*;
+pub use num_complex::Complex64;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::f64::consts::{FRAC_1_SQRT_2, PI};
+
+/// Largest number of open indices allowed while contracting
+const MAX_LEGS: usize = 26;
+
+/// Linear map of a diagram, as a dense tensor with one qubit index per boundary wire.
+///
+/// The indices are the inputs followed by the outputs, each in wire order. Entries are stored
+/// with the first index as the most significant bit, so the entry for inputs `i` and outputs
+/// `o` is at `(i << num_outputs) | o`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tensor {
+    num_inputs: usize,
+    num_outputs: usize,
+    data: Vec<Complex64>,
+}
+
+impl Tensor {
+    pub fn num_inputs(&self) -> usize {
+        self.num_inputs
+    }
+
+    pub fn num_outputs(&self) -> usize {
+        self.num_outputs
+    }
+
+    /// Entries of the tensor, see `Tensor`
+    pub fn data(&self) -> &[Complex64] {
+        &self.data
+    }
+
+    /// Entry for the given input and output basis states, as bit strings with the first wire
+    /// as the most significant bit.
+    pub fn entry(&self, inputs: usize, outputs: usize) -> Complex64 {
+        self.data[(inputs << self.num_outputs) | outputs]
+    }
+
+    /// Largest absolute difference between the entries of two tensors of the same shape.
+    ///
+    /// Panics if the shapes differ.
+    pub fn max_abs_diff(&self, other: &Tensor) -> f64 {
+        assert_eq!(
+            (self.num_inputs, self.num_outputs),
+            (other.num_inputs, other.num_outputs),
+            "comparing tensors of different shapes"
+        );
+        self.data
+            .iter()
+            .zip(&other.data)
+            .map(|(a, b)| (a - b).norm())
+            .fold(0.0, f64::max)
+    }
+
+    /// Whether two tensors have the same shape and entries within `tolerance` of each other
+    pub fn approx_eq(&self, other: &Tensor, tolerance: f64) -> bool {
+        (self.num_inputs, self.num_outputs) == (other.num_inputs, other.num_outputs)
+            && self.max_abs_diff(other) <= tolerance
+    }
+}
+
+/// Index of a tensor under contraction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Leg {
+    /// An edge with one contracted endpoint, by its position in the edge list
+    Edge(usize),
+    /// An end of an edge at the vertex being contracted, before the edge tensor is applied
+    End(usize, bool),
+    Input(usize),
+    Output(usize),
+}
+
+/// Dense tensor over named qubit indices, with the first one as the most significant bit
+#[derive(Debug, Clone)]
+struct Dense {
+    legs: Vec<Leg>,
+    data: Vec<Complex64>,
+}
+
+impl Dense {
+    fn from_fn(legs: Vec<Leg>, f: impl Fn(usize) -> Complex64) -> Self {
+        assert!(
+            legs.len() <= MAX_LEGS,
+            "tensor with {} open indices is too large to evaluate",
+            legs.len()
+        );
+        let data = (0..1usize << legs.len()).map(f).collect();
+        Dense { legs, data }
+    }
+
+    /// Bit of a leg in the entry indices, or 0 if the tensor does not have it
+    fn mask(&self, leg: Leg) -> usize {
+        match self.legs.iter().position(|&l| l == leg) {
+            Some(k) => 1 << (self.legs.len() - 1 - k),
+            None => 0,
+        }
+    }
+
+    /// Contract the legs shared by two tensors. The remaining legs of `self` come first.
+    fn contract(&self, other: &Dense) -> Dense {
+        let shared: Vec<Leg> = self
+            .legs
+            .iter()
+            .copied()
+            .filter(|l| other.legs.contains(l))
+            .collect();
+        let legs: Vec<Leg> = self
+            .legs
+            .iter()
+            .chain(&other.legs)
+            .copied()
+            .filter(|l| !shared.contains(l))
+            .collect();
+        let masks = |legs: &[Leg]| -> Vec<(usize, usize)> {
+            legs.iter()
+                .map(|&l| (self.mask(l), other.mask(l)))
+                .collect()
+        };
+        let (out_masks, sum_masks) = (masks(&legs), masks(&shared));
+        let n = legs.len();
+        Dense::from_fn(legs, |r| {
+            let (mut a, mut b) = (0, 0);
+            for (k, &(ma, mb)) in out_masks.iter().enumerate() {
+                if (r >> (n - 1 - k)) & 1 == 1 {
+                    a |= ma;
+                    b |= mb;
+                }
+            }
+            (0..1usize << shared.len())
+                .map(|s| {
+                    let (mut a, mut b) = (a, b);
+                    for (k, &(ma, mb)) in sum_masks.iter().enumerate() {
+                        if (s >> k) & 1 == 1 {
+                            a |= ma;
+                            b |= mb;
+                        }
+                    }
+                    self.data[a] * other.data[b]
+                })
+                .sum()
+        })
+    }
+}
+
+/// `e^{i phase}`
+fn phase_factor(phase: Phase) -> Complex64 {
+    Complex64::from_polar(&1.0, &(PI * phase.to_f64()))
+}
+
+/// Tensor of a vertex, over the given legs
+fn vertex_tensor(kind: VertexKind, phase: Phase, legs: Vec<Leg>) -> Dense {
+    let n = legs.len();
+    let all = (1usize << n) - 1;
+    let alpha = phase_factor(phase);
+    match kind {
+        // Without legs, the all-zeros and all-ones entries coincide and add up.
+        VertexKind::Z | VertexKind::Boundary if n == 0 => {
+            Dense::from_fn(legs, |_| Complex64::new(1.0, 0.0) + alpha)
+        }
+        VertexKind::Z | VertexKind::Boundary => Dense::from_fn(legs, |i| {
+            if i == 0 {
+                Complex64::new(1.0, 0.0)
+            } else if i == all {
+                alpha
+            } else {
+                Complex64::new(0.0, 0.0)
+            }
+        }),
+        VertexKind::X => {
+            let norm = FRAC_1_SQRT_2.powi(n as i32);
+            Dense::from_fn(legs, |i| {
+                let sign = if i.count_ones() % 2 == 0 { 1.0 } else { -1.0 };
+                (Complex64::new(1.0, 0.0) + alpha * sign) * norm
+            })
+        }
+        VertexKind::HBox => Dense::from_fn(legs, |i| {
+            if i == all {
+                alpha
+            } else {
+                Complex64::new(1.0, 0.0)
+            }
+        }),
+        VertexKind::Hole => panic!("a hole has no tensor, substitute it first"),
+    }
+}
+
+/// Tensor of an edge between two legs
+fn edge_tensor(kind: EdgeKind, a: Leg, b: Leg) -> Dense {
+    Dense::from_fn(vec![a, b], |i| match kind {
+        EdgeKind::Regular if i == 0 || i == 3 => Complex64::new(1.0, 0.0),
+        EdgeKind::Regular => Complex64::new(0.0, 0.0),
+        EdgeKind::Hadam if i == 3 => Complex64::new(-FRAC_1_SQRT_2, 0.0),
+        EdgeKind::Hadam => Complex64::new(FRAC_1_SQRT_2, 0.0),
+    })
+}
+
+/// Order in which to contract the vertices: breadth-first from the inputs, to keep the number
+/// of open edges small on circuit-like diagrams.
+fn contraction_order<G: Graph>(g: &G) -> Vec<G::VertexIx> {
+    let mut seen = HashSet::new();
+    let mut order = Vec::new();
+    let mut starts = vec![g.inputs().collect::<Vec<_>>()];
+    starts.extend(g.vertices().map(|v| vec![v]));
+    for start in starts {
+        let mut queue: VecDeque<_> = start.into_iter().filter(|&v| seen.insert(v)).collect();
+        while let Some(v) = queue.pop_front() {
+            order.push(v);
+            for n in g.neighbours(v) {
+                if seen.insert(n) {
+                    queue.push_back(n);
+                }
+            }
+        }
+    }
+    order
+}
+
+/// Evaluate a diagram to its linear map.
+///
+/// Z and X spiders with phase `α` are the usual spiders with an `e^{iα}` phase, H-boxes with
+/// phase `α` have entry `e^{iα}` on the all-ones index and 1 elsewhere, and Hadamard edges are
+/// normalized Hadamard gates. Boundaries connect their wire to their edges. The result is
+/// multiplied by the scalar of the diagram. Panics if an intermediate tensor gets too large, or
+/// if the diagram has grounded vertices, since it is then not a linear map of pure states, or
+/// holes, see `CartesianGraph::substitute`.
+pub fn to_tensor<G: Graph>(g: &G) -> Tensor {
+    assert!(
+        g.vertices().all(|v| !g.is_ground(v)),
+        "cannot evaluate a diagram with grounded vertices"
+    );
+    let edges: HashMap<G::EdgeIx, usize> = g.edges().enumerate().map(|(i, e)| (e, i)).collect();
+    let inputs: HashMap<G::VertexIx, usize> = g.inputs().enumerate().map(|(i, v)| (v, i)).collect();
+    let outputs: HashMap<G::VertexIx, usize> =
+        g.outputs().enumerate().map(|(i, v)| (v, i)).collect();
+
+    let mut done = HashSet::new();
+    let mut res = Dense::from_fn(Vec::new(), |_| Complex64::new(1.0, 0.0));
+    for v in contraction_order(g) {
+        let mut legs = Vec::new();
+        let mut pending = Vec::new();
+        for e in g.incident_edges(v) {
+            let id = edges[&e];
+            let kind = g.edge(e).unwrap().kind();
+            let (a, b) = g.edge_endpoints(e).unwrap();
+            if a == b {
+                legs.extend([Leg::End(id, false), Leg::End(id, true)]);
+                pending.push(edge_tensor(kind, Leg::End(id, false), Leg::End(id, true)));
+            } else if done.contains(&a) || done.contains(&b) {
+                // The other endpoint already applied the edge tensor.
+                legs.push(Leg::Edge(id));
+            } else {
+                legs.push(Leg::End(id, false));
+                pending.push(edge_tensor(kind, Leg::End(id, false), Leg::Edge(id)));
+            }
+        }
+        legs.extend(inputs.get(&v).map(|&i| Leg::Input(i)));
+        legs.extend(outputs.get(&v).map(|&i| Leg::Output(i)));
+        let vdata = g.vertex(v).unwrap();
+        let mut t = vertex_tensor(vdata.kind(), vdata.phase(), legs);
+        for edge in pending {
+            t = t.contract(&edge);
+        }
+        res = res.contract(&t);
+        done.insert(v);
+    }
+
+    let wires: Vec<Leg> = (0..inputs.len())
+        .map(Leg::Input)
+        .chain((0..outputs.len()).map(Leg::Output))
+        .collect();
+    let masks: Vec<usize> = wires.iter().map(|&l| res.mask(l)).collect();
+    let n = wires.len();
+    let scalar = g.scalar().to_complex();
+    let data = (0..1usize << n)
+        .map(|r| {
+            let i = (0..n)
+                .filter(|k| (r >> (n - 1 - k)) & 1 == 1)
+                .fold(0, |acc, k| acc | masks[k]);
+            res.data[i] * scalar
+        })
+        .collect();
+    Tensor {
+        num_inputs: inputs.len(),
+        num_outputs: outputs.len(),
+        data,
+    }
+}
+
+/// Check that two diagrams have the same linear map, up to a numerical tolerance.
+///
+/// Panics with the largest difference between entries if they differ.
+pub fn assert_graph_eq<A: Graph, B: Graph>(a: &A, b: &B) {
+    let (ta, tb) = (to_tensor(a), to_tensor(b));
+    assert_eq!(
+        (ta.num_inputs(), ta.num_outputs()),
+        (tb.num_inputs(), tb.num_outputs()),
+        "diagrams have different numbers of inputs and outputs"
+    );
+    let diff = ta.max_abs_diff(&tb);
+    assert!(
+        diff <= 1e-9,
+        "diagrams differ: largest entry difference is {}",
+        diff
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::simple_graph::GGraph;
+    use std::f64::consts::SQRT_2;
+
+    fn c(re: f64, im: f64) -> Complex64 {
+        Complex64::new(re, im)
+    }
+
+    /// Single vertex between an input and an output, joined by regular edges
+    fn gate(kind: VertexKind, phase: Phase) -> GGraph {
+        let mut g: GGraph = GGraph::new();
+        let i = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        let v = g.add_vertex(kind, phase);
+        let o = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        g.add_edge(i, v, EdgeKind::Regular);
+        g.add_edge(v, o, EdgeKind::Regular);
+        g.set_input(i, true);
+        g.set_output(o, true);
+        g
+    }
+
+    /// Check the entries of a one-qubit map, as a row-major matrix from inputs to outputs
+    fn assert_matrix(t: &Tensor, m: [[Complex64; 2]; 2]) {
+        assert_eq!((t.num_inputs(), t.num_outputs()), (1, 1));
+        for (i, row) in m.iter().enumerate() {
+            for (o, &entry) in row.iter().enumerate() {
+                assert!(
+                    (t.entry(i, o) - entry).norm() < 1e-12,
+                    "entry ({}, {}) is {}, expected {}",
+                    i,
+                    o,
+                    t.entry(i, o),
+                    entry
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn spiders() {
+        let (zero, one) = (c(0.0, 0.0), c(1.0, 0.0));
+        let t = phase_factor(Phase::new(1, 4));
+        assert_matrix(
+            &to_tensor(&gate(VertexKind::Z, Phase::new(1, 4))),
+            [[one, zero], [zero, t]],
+        );
+        assert_matrix(
+            &to_tensor(&gate(VertexKind::X, Phase::pi())),
+            [[zero, one], [one, zero]],
+        );
+        let (p, m) = ((one + t) / 2.0, (one - t) / 2.0);
+        assert_matrix(
+            &to_tensor(&gate(VertexKind::X, Phase::new(1, 4))),
+            [[p, m], [m, p]],
+        );
+        // An arity-2 H-box is an unnormalized Hadamard gate.
+        assert_matrix(
+            &to_tensor(&gate(VertexKind::HBox, Phase::pi())),
+            [[one, one], [one, -one]],
+        );
+    }
+
+    #[test]
+    fn hadamard_edge_and_bare_wire() {
+        let mut g: GGraph = GGraph::new();
+        let i = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        let o = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        g.add_edge(i, o, EdgeKind::Hadam);
+        g.set_input(i, true);
+        g.set_output(o, true);
+        let h = c(FRAC_1_SQRT_2, 0.0);
+        assert_matrix(&to_tensor(&g), [[h, h], [h, -h]]);
+
+        // A boundary that is both an input and an output is a wire on its own.
+        let mut g: GGraph = GGraph::new();
+        let v = g.add_vertex(VertexKind::Boundary, Phase::zero());
+        g.set_input(v, true);
+        g.set_output(v, true);
+        let (zero, one) = (c(0.0, 0.0), c(1.0, 0.0));
+        assert_matrix(&to_tensor(&g), [[one, zero], [zero, one]]);
+    }
+
+    #[test]
+    fn three_legged_hbox() {
+        let mut g: GGraph = GGraph::new();
+        let h = g.add_vertex(VertexKind::HBox, Phase::new(1, 2));
+        for input in [true, true, false] {
+            let b = g.add_vertex(VertexKind::Boundary, Phase::zero());
+            g.add_edge(b, h, EdgeKind::Regular);
+            if input {
+                g.set_input(b, true);
+            } else {
+                g.set_output(b, true);
+            }
+        }
+        let t = to_tensor(&g);
+        assert_eq!((t.num_inputs(), t.num_outputs()), (2, 1));
+        for i in 0..4 {
+            for o in 0..2 {
+                let expected = if i == 3 && o == 1 {
+                    c(0.0, 1.0)
+                } else {
+                    c(1.0, 0.0)
+                };
+                assert!((t.entry(i, o) - expected).norm() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn scalars_and_self_loops() {
+        // A spider without legs is the sum of its two terms.
+        let mut g: GGraph = GGraph::new();
+        g.add_vertex(VertexKind::Z, Phase::new(1, 2));
+        assert!((to_tensor(&g).entry(0, 0) - c(1.0, 1.0)).norm() < 1e-12);
+        let x = g.add_vertex(VertexKind::X, Phase::pi());
+        assert!((to_tensor(&g).entry(0, 0)).norm() < 1e-12);
+        g.remove_vertex(x);
+
+        *g.scalar_mut() = Scalar::new(-1, Phase::new(1, 2));
+        let expected = c(1.0, 1.0) * c(0.0, 1.0) / SQRT_2;
+        assert!((to_tensor(&g).entry(0, 0) - expected).norm() < 1e-12);
+        *g.scalar_mut() = Scalar::zero();
+        assert!((to_tensor(&g).entry(0, 0)).norm() < 1e-12);
+
+        // A regular self-loop changes nothing, and a Hadamard one adds pi and 1/sqrt(2).
+        let mut g = gate(VertexKind::Z, Phase::new(1, 4));
+        let v = g.neighbours(g.inputs().next().unwrap()).next().unwrap();
+        g.set_mode(GraphMode::Multi);
+        g.add_edge(v, v, EdgeKind::Regular);
+        assert_graph_eq(&g, &gate(VertexKind::Z, Phase::new(1, 4)));
+        g.add_edge(v, v, EdgeKind::Hadam);
+        let mut expected = gate(VertexKind::Z, Phase::new(5, 4));
+        expected.scalar_mut().add_power(-1);
+        assert_graph_eq(&g, &expected);
+    }
+
+    #[test]
+    #[should_panic(expected = "diagrams differ")]
+    fn different_phases_are_not_equal() {
+        assert_graph_eq(
+            &gate(VertexKind::Z, Phase::new(1, 4)),
+            &gate(VertexKind::Z, Phase::new(1, 2)),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "different numbers of inputs and outputs")]
+    fn different_shapes_are_not_equal() {
+        let mut g = gate(VertexKind::Z, Phase::zero());
+        let o = g.outputs().next().unwrap();
+        g.set_output(o, false);
+        assert_graph_eq(&g, &gate(VertexKind::Z, Phase::zero()));
+    }
+}
@@ -0,0 +1,540 @@
+//! Transformations of whole diagrams: coarsening their phases in place, and building their
+//! controlled versions.
+
+use crate::zx::graph::*;
+use crate::zx::layout::normalize_rows;
+use crate::zx::simplify::toggle;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::f64::consts::PI;
+
+/// Outcome of `coarsen_phases`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CoarsenReport {
+    /// Phases replaced by a simpler fraction
+    pub coarsened: u32,
+    /// Phases left untouched because their nearest simple fraction was too far away
+    pub rejected: u32,
+    /// Sum of the angular errors introduced, in radians. This is a crude bound on the distance
+    /// between the original and the coarsened diagram.
+    pub total_error: f64,
+}
+
+/// Snap every phase to the nearest fraction of pi with denominator at most `max_denominator`,
+/// when the angular error is at most `tol` radians.
+///
+/// Phases whose denominator is already within the limit are untouched. The search takes
+/// `O(max_denominator)` time per phase.
+pub fn coarsen_phases<G: Graph>(g: &mut G, max_denominator: u64, tol: f64) -> CoarsenReport {
+    let mut report = CoarsenReport::default();
+    let vertices: Vec<_> = g.vertices().collect();
+    for v in vertices {
+        let phase = g.vertex(v).unwrap().phase();
+        if phase.denominator() <= max_denominator {
+            continue;
+        }
+        let x = phase.to_f64();
+        if !x.is_finite() {
+            continue;
+        }
+        let (num, den) = nearest_fraction(x, max_denominator);
+        let error = (x - num as f64 / den as f64).abs() * PI;
+        if error > tol {
+            report.rejected += 1;
+            continue;
+        }
+        g.set_phase(v, Phase::new(num, den));
+        report.coarsened += 1;
+        report.total_error += error;
+    }
+    report
+}
+
+/// Closest fraction to `x` with denominator at most `max_denominator`, preferring the smallest
+/// denominator on ties.
+fn nearest_fraction(x: f64, max_denominator: u64) -> (i64, u64) {
+    let mut best = (x.round() as i64, 1);
+    let mut best_error = (x - x.round()).abs();
+    for den in 2..=max_denominator {
+        let num = (x * den as f64).round();
+        let error = (x - num / den as f64).abs();
+        if error < best_error {
+            best = (num as i64, den);
+            best_error = error;
+        }
+    }
+    best
+}
+
+/// Rows of the controlled diagram for each row of the original one, leaving room for the
+/// decomposed Hadamard edges and the gadgets between them
+const ROW_SPREAD: i32 = 8;
+
+/// Edges along the wires of a circuit-like diagram, each with its endpoint closer to the inputs.
+///
+/// Each wire is followed from its input through the spiders not yet visited, preferring later
+/// rows and then the same qubit when there is a choice. Panics if a wire does not end at the
+/// output in the same position, or if a spider is on no wire.
+fn trace_wires<G: CartesianGraph<Qubit = i32, Row = i32>>(
+    g: &G,
+) -> HashMap<G::EdgeIx, G::VertexIx> {
+    let on_wire = |v| g.vertex(v).unwrap().kind() != VertexKind::HBox;
+    let outputs: Vec<_> = g.outputs().collect();
+    let mut wires = HashMap::new();
+    let mut visited = HashSet::new();
+    for (i, input) in g.inputs().enumerate() {
+        let mut v = input;
+        visited.insert(v);
+        while v == input || g.vertex(v).unwrap().kind() != VertexKind::Boundary {
+            let mut next: Vec<_> = g
+                .incident_edges_with_neighbours(v)
+                .filter(|&(_, n)| on_wire(n) && !visited.contains(&n))
+                .collect();
+            if next.len() > 1 {
+                next.retain(|&(_, n)| g.row(n) > g.row(v));
+            }
+            if next.len() > 1 {
+                next.retain(|&(_, n)| g.qubit(n) == g.qubit(v));
+            }
+            let (e, n) = match next[..] {
+                [first, ..] if next.iter().all(|&(_, n)| n == first.1) => first,
+                _ => panic!("the wire of input {} does not continue past {:?}", i, v),
+            };
+            wires.insert(e, v);
+            visited.insert(n);
+            v = n;
+        }
+        assert_eq!(
+            outputs.get(i),
+            Some(&v),
+            "the wire of input {} does not end at the output in the same position",
+            i
+        );
+    }
+    if let Some(v) = g.vertices().find(|&v| on_wire(v) && !visited.contains(&v)) {
+        panic!("vertex {:?} is not on a wire", v);
+    }
+    wires
+}
+
+/// Construction of a controlled diagram, see `add_control`
+struct Controlled<G: Graph> {
+    g: G,
+    /// Qubit of the control wire
+    qubit: i32,
+    /// Spiders of the control wire, by row
+    spiders: BTreeMap<i32, G::VertexIx>,
+}
+
+impl<G: CartesianGraph<Qubit = i32, Row = i32>> Controlled<G> {
+    /// Spider of the control wire on a row
+    fn control(&mut self, row: i32) -> G::VertexIx {
+        let Controlled { g, qubit, spiders } = self;
+        *spiders.entry(row).or_insert_with(|| {
+            let v = g.add_vertex(VertexKind::Z, Phase::zero());
+            g.set_position(v, *qubit, row);
+            v
+        })
+    }
+
+    /// Add an H-box with the given phase at a position, connected to `v` by an edge of the given
+    /// kind and to the control wire. Multiplies the branch where the control is set by the
+    /// phase when `v` is set, or in the X basis through a Hadamard edge.
+    fn gadget(&mut self, v: G::VertexIx, kind: EdgeKind, phase: Phase, pos: (i32, i32)) {
+        let c = self.control(pos.1);
+        let h = self.g.add_vertex(VertexKind::HBox, phase);
+        self.g.set_position(h, pos.0, pos.1);
+        self.g.add_edge(v, h, kind);
+        self.g.add_edge(c, h, EdgeKind::Regular);
+    }
+
+    /// Add a spider with the given phase at a position, controlled by an H-box on the next row.
+    fn controlled_spider(
+        &mut self,
+        kind: VertexKind,
+        phase: Phase,
+        pos: (i32, i32),
+    ) -> G::VertexIx {
+        let v = self.g.add_vertex(kind, Phase::zero());
+        self.g.set_position(v, pos.0, pos.1);
+        let edge = if kind == VertexKind::X {
+            EdgeKind::Hadam
+        } else {
+            EdgeKind::Regular
+        };
+        self.gadget(v, edge, phase, (pos.0, pos.1 + 1));
+        v
+    }
+}
+
+/// Controlled version of a circuit-like diagram, with a new control wire at position
+/// `fresh_qubit_position`.
+///
+/// The result has one more input and output, at that position in wire order and on that qubit,
+/// and the vertices on the later qubits move one qubit down. When the control is 0 the result is
+/// the identity, and when it is 1 it is the original diagram, including its scalar.
+///
+/// The diagram must be circuit-like: after changing the colour of the X spiders, each input is
+/// connected by a wire of Z spiders to the output in the same position, every spider is on a
+/// wire, the other edges between spiders are Hadamard edges, and H-boxes are only connected to
+/// Z spiders and boundaries by regular edges. The wires are followed as in the layout, so the
+/// rows must increase along them. Circuits without swap gates translated by
+/// `Circuit::to_graph_into` into a sink keeping the layout, like `GGraph::builder()`, have this
+/// form. Panics otherwise, or if the diagram has grounded vertices.
+///
+/// Every spider phase `a` becomes a 2-ary H-box with phase `a` between the spider and the
+/// control wire, and every H-box gets one more leg on the control wire, so that they all vanish
+/// when the control is 0. Each Hadamard edge between two wires is a 3-ary H-box with phase pi
+/// on both spiders and the control wire, and each Hadamard edge along a wire is replaced by its
+/// Euler decomposition `e^{-i pi/4} Z(pi/2) X(pi/2) Z(pi/2)`, with the three phases controlled
+/// in the same way. The factors of sqrt(2) and the phases that these replacements and the
+/// original scalar leave on the controlled branch are made explicit with a phase on the control
+/// wire and, for the powers of sqrt(2), gadgets with a Z spider and an H-box.
+pub fn add_control<G>(g: &G, fresh_qubit_position: u32) -> G
+where
+    G: CartesianGraph<Qubit = i32, Row = i32> + Default,
+{
+    assert!(
+        g.vertices().all(|v| !g.is_ground(v)),
+        "cannot control a diagram with grounded vertices"
+    );
+    let num_wires = g.inputs().count();
+    assert_eq!(
+        num_wires,
+        g.outputs().count(),
+        "the diagram must have as many inputs as outputs"
+    );
+    let position = fresh_qubit_position as usize;
+    assert!(
+        position <= num_wires,
+        "the control must be next to or between the wires"
+    );
+    let qubit = fresh_qubit_position as i32;
+    let pos = |v| {
+        let q = g.qubit(v);
+        (if q >= qubit { q + 1 } else { q }, g.row(v) * ROW_SPREAD)
+    };
+    let kind = |v| g.vertex(v).unwrap().kind();
+    let wires = trace_wires(g);
+
+    let mut c = Controlled {
+        g: G::default(),
+        qubit,
+        spiders: BTreeMap::new(),
+    };
+    // The X spiders become Z spiders, with their edges toggled below.
+    let mut map = HashMap::new();
+    for v in g.vertices() {
+        let vkind = match kind(v) {
+            VertexKind::X => VertexKind::Z,
+            k => k,
+        };
+        let phase = g.vertex(v).unwrap().phase();
+        let (q, r) = pos(v);
+        let w = if vkind == VertexKind::Z && !phase.is_zero() {
+            c.controlled_spider(VertexKind::Z, phase, (q, r))
+        } else {
+            let w = c.g.add_vertex(vkind, phase);
+            c.g.set_position(w, q, r);
+            w
+        };
+        if vkind == VertexKind::HBox {
+            let control = c.control(r);
+            c.g.add_edge(control, w, EdgeKind::Regular);
+        }
+        map.insert(v, w);
+    }
+
+    // Powers of sqrt(2) and multiples of pi/4 left on the branch where the control is set
+    let mut power2 = 0;
+    let mut eighths = 0;
+    for e in g.edges() {
+        let (a, b) = g.edge_endpoints(e).unwrap();
+        assert_ne!(a, b, "cannot control a diagram with self-loops");
+        let mut ekind = g.edge(e).unwrap().kind();
+        for v in [a, b] {
+            if kind(v) == VertexKind::X {
+                ekind = toggle(ekind);
+            }
+        }
+        let spider = |v| matches!(kind(v), VertexKind::Z | VertexKind::X);
+        if kind(a) == VertexKind::HBox || kind(b) == VertexKind::HBox {
+            assert!(
+                ekind == EdgeKind::Regular && kind(a) != kind(b),
+                "H-boxes must be connected to Z spiders and boundaries by regular edges"
+            );
+            c.g.add_edge(map[&a], map[&b], EdgeKind::Regular);
+        } else if let Some(&from) = wires.get(&e) {
+            let to = if from == a { b } else { a };
+            if ekind == EdgeKind::Regular {
+                c.g.add_edge(map[&from], map[&to], EdgeKind::Regular);
+                continue;
+            }
+            let (q, r) = pos(from);
+            let mut last = map[&from];
+            for (i, &vkind) in [VertexKind::Z, VertexKind::X, VertexKind::Z]
+                .iter()
+                .enumerate()
+            {
+                let v = c.controlled_spider(vkind, Phase::new(1, 2), (q, r + 2 * i as i32 + 2));
+                c.g.add_edge(last, v, EdgeKind::Regular);
+                last = v;
+            }
+            c.g.add_edge(last, map[&to], EdgeKind::Regular);
+            eighths += 1;
+        } else {
+            assert!(
+                spider(a) && spider(b) && ekind == EdgeKind::Hadam,
+                "edges between wires must be Hadamard edges between spiders"
+            );
+            let ((qa, ra), (qb, rb)) = (pos(a), pos(b));
+            let h = c.g.add_vertex(VertexKind::HBox, Phase::pi());
+            let row = ra.max(rb) + 1;
+            c.g.set_position(h, (qa + qb) / 2, row);
+            c.g.add_edge(map[&a], h, EdgeKind::Regular);
+            c.g.add_edge(map[&b], h, EdgeKind::Regular);
+            let control = c.control(row);
+            c.g.add_edge(control, h, EdgeKind::Regular);
+            power2 += 1;
+        }
+    }
+
+    // Scale the controlled branch by the original scalar and undo the factors left by the
+    // replacements above.
+    let scalar = *g.scalar();
+    let first = c.control(1);
+    if scalar.is_zero() {
+        // Project the control onto 0.
+        let leaf = c.g.add_vertex(VertexKind::X, Phase::zero());
+        c.g.set_position(leaf, qubit, 2);
+        c.g.add_edge(first, leaf, EdgeKind::Regular);
+        c.g.scalar_mut().add_power(-1);
+    } else {
+        let mut phase = scalar.phase() - Phase::new(eighths, 4);
+        for _ in 0..(scalar.power2() - power2).abs() {
+            // A Z spider with phase `b` connected to the control through an H-box with phase
+            // `a` multiplies the branches by `1 + e^{ib}` and `1 + e^{i(a+b)}`.
+            let (a, b) = if scalar.power2() > power2 {
+                (Phase::new(-1, 2), Phase::new(1, 2))
+            } else {
+                (Phase::new(1, 2), Phase::zero())
+            };
+            let leaf = c.g.add_vertex(VertexKind::Z, b);
+            c.g.set_position(leaf, qubit, 3);
+            c.gadget(leaf, EdgeKind::Regular, a, (qubit, 2));
+            if scalar.power2() > power2 {
+                phase += Phase::new(1, 4);
+                *c.g.scalar_mut() *= Scalar::new(-1, Phase::new(-1, 4));
+            } else {
+                phase += Phase::new(-1, 4);
+                c.g.scalar_mut().add_power(-2);
+            }
+        }
+        c.g.set_phase(first, phase);
+    }
+
+    let last_row = g.vertices().map(|v| pos(v).1).max().unwrap_or(0).max(4);
+    let input = c.g.add_vertex(VertexKind::Boundary, Phase::zero());
+    c.g.set_position(input, qubit, 0);
+    let output = c.g.add_vertex(VertexKind::Boundary, Phase::zero());
+    c.g.set_position(output, qubit, last_row);
+    let spiders: Vec<_> = c.spiders.values().copied().collect();
+    for (&v, &u) in std::iter::once(&input)
+        .chain(&spiders)
+        .zip(spiders.iter().chain(std::iter::once(&output)))
+    {
+        c.g.add_edge(v, u, EdgeKind::Regular);
+    }
+    let mut inputs: Vec<_> = g.inputs().map(|v| map[&v]).collect();
+    let mut outputs: Vec<_> = g.outputs().map(|v| map[&v]).collect();
+    inputs.insert(position, input);
+    outputs.insert(position, output);
+    c.g.set_io_order(inputs, outputs);
+    normalize_rows(&mut c.g);
+    c.g
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::analysis::phase_spectrum;
+    use crate::zx::circuit::{Circuit, Gate};
+    use crate::zx::generate::random_clifford_t_circuit;
+    use crate::zx::random::Seed;
+    use crate::zx::simple_graph::GGraph;
+    use crate::zx::simplify::full_reduce;
+    use crate::zx::tensor::{to_tensor, Complex64, Tensor};
+
+    /// Angle of `0.785398` radians, as written by a tool approximating pi by `3.141593`
+    fn imported_t() -> Phase {
+        Phase::new(785_398, 3_141_593)
+    }
+
+    /// T rotations on both qubits of a CNOT pair, pairs of which fuse into Cliffords
+    fn circuit(t: Phase) -> Circuit {
+        let mut c = Circuit::new(3);
+        c.add_gate(Gate::Rz(0, t));
+        c.add_gate(Gate::Cx(0, 1));
+        c.add_gate(Gate::Rz(0, t));
+        c.add_gate(Gate::Rz(1, t));
+        c.add_gate(Gate::Cx(0, 1));
+        c.add_gate(Gate::Rz(1, t));
+        c.add_gate(Gate::Cx(1, 2));
+        c.add_gate(Gate::Rz(2, t));
+        c
+    }
+
+    /// Number of spiders with a non-Clifford phase
+    fn non_clifford<G: Graph>(g: &G) -> usize {
+        g.vertices()
+            .filter(|&v| !g.vertex(v).unwrap().phase().is_clifford())
+            .count()
+    }
+
+    #[test]
+    fn snaps_imported_angles() {
+        let mut g: GGraph = circuit(imported_t()).to_graph();
+        assert_eq!(phase_spectrum(&g)[&3_141_593], 5);
+        let original = to_tensor(&g);
+
+        let report = coarsen_phases(&mut g, 8, 1e-4);
+        assert_eq!(report.coarsened, 5);
+        assert_eq!(report.rejected, 0);
+        let per_phase = (imported_t().to_f64() - 0.25).abs() * PI;
+        assert!((report.total_error - 5.0 * per_phase).abs() < 1e-9);
+        assert!(report.total_error < 1e-5);
+        assert!(phase_spectrum(&g).keys().all(|&d| d <= 4));
+        assert!(to_tensor(&g).max_abs_diff(&original) < 1e-5);
+
+        let exact: GGraph = circuit(Phase::new(1, 4)).to_graph();
+        assert_eq!(g.dump(), exact.dump());
+    }
+
+    #[test]
+    fn exact_and_distant_phases_are_kept() {
+        let mut g: GGraph = circuit(Phase::new(1, 4)).to_graph();
+        let before = g.dump();
+        assert_eq!(coarsen_phases(&mut g, 8, 1e-4), CoarsenReport::default());
+        assert_eq!(g.dump(), before);
+
+        // 1/10 is further than the tolerance from every fraction with denominator at most 4.
+        let mut g: GGraph = circuit(Phase::new(1, 10)).to_graph();
+        let report = coarsen_phases(&mut g, 4, 1e-2);
+        assert_eq!((report.coarsened, report.rejected), (0, 5));
+        assert_eq!(report.total_error, 0.0);
+    }
+
+    #[test]
+    fn coarsening_exposes_clifford_t_structure() {
+        let mut imported: GGraph = circuit(imported_t()).to_graph();
+        let mut coarsened = imported.clone();
+        coarsen_phases(&mut coarsened, 8, 1e-4);
+        full_reduce(&mut imported);
+        full_reduce(&mut coarsened);
+        assert!(non_clifford(&coarsened) < non_clifford(&imported));
+        assert_eq!(non_clifford(&coarsened) as u32, coarsened.tcount());
+    }
+
+    /// Diagram of a circuit, with its layout
+    fn gates(num_qubits: u32, gates: &[Gate]) -> GGraph {
+        let mut c = Circuit::new(num_qubits);
+        for &gate in gates {
+            c.add_gate(gate);
+        }
+        c.to_graph_into(GGraph::builder())
+    }
+
+    /// Check that `controlled` is the block matrix `[[I, 0], [0, U]]` of the map `U` of `g`,
+    /// with the control wire at `position`.
+    fn assert_controlled(g: &GGraph, controlled: &GGraph, position: usize) {
+        let (u, t): (Tensor, Tensor) = (to_tensor(g), to_tensor(controlled));
+        let n = u.num_inputs();
+        assert_eq!((t.num_inputs(), t.num_outputs()), (n + 1, n + 1));
+        // Split a bit string over `n + 1` wires into the control bit and the others.
+        let split = |x: usize| {
+            let shift = n - position;
+            let low = x & ((1 << shift) - 1);
+            ((x >> shift) & 1, ((x >> (shift + 1)) << shift) | low)
+        };
+        for i in 0..1 << (n + 1) {
+            for o in 0..1 << (n + 1) {
+                let ((ci, i2), (co, o2)) = (split(i), split(o));
+                let expected = match (ci, co) {
+                    (0, 0) if i2 == o2 => Complex64::new(1.0, 0.0),
+                    (1, 1) => u.entry(i2, o2),
+                    _ => Complex64::new(0.0, 0.0),
+                };
+                let diff = (t.entry(i, o) - expected).norm();
+                assert!(diff < 1e-9, "entry ({}, {}) is off by {}", i, o, diff);
+            }
+        }
+    }
+
+    #[test]
+    fn controlled_circuits() {
+        let third = Phase::new(1, 3);
+        let circuits = [
+            gates(
+                2,
+                &[Gate::H(0), Gate::Cx(0, 1), Gate::T(1), Gate::Rx(0, third)],
+            ),
+            gates(2, &[Gate::Cz(1, 0), Gate::H(1), Gate::CPhase(0, 1, third)]),
+            gates(3, &[Gate::H(2), Gate::Ccz(0, 1, 2), Gate::S(0), Gate::X(1)]),
+            gates(
+                3,
+                &[Gate::Ccx(0, 1, 2), Gate::Cx(2, 0), Gate::Tdg(2), Gate::H(0)],
+            ),
+        ];
+        for g in &circuits {
+            for position in 0..=g.inputs().count() {
+                let controlled = add_control(g, position as u32);
+                assert_controlled(g, &controlled, position);
+                assert!(controlled.check_layout().is_ok());
+                let control = controlled.input_at(position).unwrap();
+                assert_eq!(controlled.qubit(control), position as i32);
+            }
+        }
+    }
+
+    #[test]
+    fn controlled_random_circuits() {
+        for seed in 0..3 {
+            let c = random_clifford_t_circuit(3, 6, 0.3, Seed(seed));
+            let g = c.to_graph_into(GGraph::builder());
+            assert_controlled(&g, &add_control(&g, 0), 0);
+        }
+    }
+
+    #[test]
+    fn global_scalar_becomes_controlled() {
+        let mut g = gates(2, &[Gate::Cz(0, 1), Gate::T(0)]);
+        g.scalar_mut().add_phase(Phase::new(2, 3));
+        let controlled = add_control(&g, 0);
+        assert_controlled(&g, &controlled, 0);
+        // The phase of the scalar is the only phase on the control wire.
+        assert_eq!(
+            controlled
+                .vertices()
+                .filter(|&v| controlled.qubit(v) == 0
+                    && controlled.vertex(v).unwrap().kind() == VertexKind::Z)
+                .map(|v| controlled.vertex(v).unwrap().phase())
+                .find(|p| !p.is_zero()),
+            Some(Phase::new(2, 3))
+        );
+
+        for power in [-2, 1, 3] {
+            let mut scaled = g.clone();
+            scaled.scalar_mut().add_power(power);
+            assert_controlled(&scaled, &add_control(&scaled, 1), 1);
+        }
+        let mut zero = g.clone();
+        *zero.scalar_mut() = Scalar::zero();
+        assert_controlled(&zero, &add_control(&zero, 2), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "does not end at the output in the same position")]
+    fn swapped_wires_cannot_be_controlled() {
+        let g = gates(2, &[Gate::T(0), Gate::Swap(0, 1)]);
+        add_control(&g, 0);
+    }
+}
@@ -0,0 +1,545 @@
+use crate::zx::graph::*;
+use crate::zx::simple_graph::{EdgeData, VertexData};
+use indexmap::{set, IndexSet};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::iter::{Copied, Enumerate, FilterMap, Map};
+use std::slice;
+
+/// Open graph stored in flat vectors, for large diagrams.
+///
+/// Vertices and edges are numbered by their slot in a vector, and each vertex keeps the list of
+/// its neighbours together with the connecting edges, in insertion order. Removing a vertex or an
+/// edge frees its slot without renumbering the others, and the freed slots are reused by later
+/// additions, last freed first. Positions and ground flags are stored as in `GGraph`.
+///
+/// Compared to `GGraph`, neighbour iteration is a scan over a contiguous list and adding an
+/// element does not touch any linked list, at the cost of a linear scan of the endpoints'
+/// neighbour lists when removing an edge.
+#[derive(Debug, Clone)]
+pub struct VecGraph {
+    vdata: Vec<Option<VertexData>>,
+    /// Neighbour and connecting edge of each incident edge, with self-loops listed once
+    adjacency: Vec<Vec<(u32, u32)>>,
+    /// Endpoints and data of each edge, in the order given to `add_edge`
+    edata: Vec<Option<(u32, u32, EdgeData)>>,
+    free_vertices: Vec<u32>,
+    free_edges: Vec<u32>,
+    num_vertices: u32,
+    num_edges: u32,
+    inputs: IndexSet<u32>,
+    outputs: IndexSet<u32>,
+    scalar: Scalar,
+    mode: GraphMode,
+}
+
+const _: fn() = assert_send_sync::<VecGraph>;
+
+impl Default for VecGraph {
+    fn default() -> Self {
+        VecGraph {
+            vdata: Vec::new(),
+            adjacency: Vec::new(),
+            edata: Vec::new(),
+            free_vertices: Vec::new(),
+            free_edges: Vec::new(),
+            num_vertices: 0,
+            num_edges: 0,
+            inputs: IndexSet::new(),
+            outputs: IndexSet::new(),
+            scalar: Scalar::one(),
+            mode: GraphMode::default(),
+        }
+    }
+}
+
+impl VecGraph {
+    /// Create an empty graph
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Create an empty graph with room for the given number of vertices and edges
+    pub fn with_capacity(vertices: usize, edges: usize) -> Self {
+        VecGraph {
+            vdata: Vec::with_capacity(vertices),
+            adjacency: Vec::with_capacity(vertices),
+            edata: Vec::with_capacity(edges),
+            ..Default::default()
+        }
+    }
+
+    /// Set the edge policy of `add_edge`, see `GraphMode`. Existing edges are kept as they are,
+    /// and `Graph::check_invariants` reports those breaking the new mode.
+    pub fn set_mode(&mut self, mode: GraphMode) {
+        self.mode = mode;
+    }
+
+    /// Insert an edge, whatever the mode
+    fn insert_edge(&mut self, v: u32, u: u32, kind: EdgeKind) -> u32 {
+        assert!(
+            self.vertex(v).is_some() && self.vertex(u).is_some(),
+            "adding an edge between {} and {}, which do not both exist",
+            v,
+            u
+        );
+        // Same endpoint order as `GGraph`.
+        let slot = Some((u, v, EdgeData { kind }));
+        let e = match self.free_edges.pop() {
+            Some(e) => {
+                self.edata[e as usize] = slot;
+                e
+            }
+            None => {
+                self.edata.push(slot);
+                self.edata.len() as u32 - 1
+            }
+        };
+        self.num_edges += 1;
+        self.adjacency[u as usize].push((v, e));
+        if u != v {
+            self.adjacency[v as usize].push((u, e));
+        }
+        e
+    }
+
+    fn add_vertex_data(&mut self, vdata: VertexData) -> u32 {
+        self.num_vertices += 1;
+        match self.free_vertices.pop() {
+            Some(v) => {
+                self.vdata[v as usize] = Some(vdata);
+                v
+            }
+            None => {
+                self.vdata.push(Some(vdata));
+                self.adjacency.push(Vec::new());
+                self.vdata.len() as u32 - 1
+            }
+        }
+    }
+
+    fn vdata(&self, v: u32) -> &VertexData {
+        self.vdata[v as usize]
+            .as_ref()
+            .unwrap_or_else(|| panic!("vertex {} does not exist", v))
+    }
+
+    fn vdata_mut(&mut self, v: u32) -> &mut VertexData {
+        self.vdata[v as usize]
+            .as_mut()
+            .unwrap_or_else(|| panic!("vertex {} does not exist", v))
+    }
+
+    /// Remove the entry of edge `e` from the neighbour list of `v`.
+    fn unlink(&mut self, v: u32, e: u32) {
+        let list = &mut self.adjacency[v as usize];
+        if let Some(i) = list.iter().position(|&(_, f)| f == e) {
+            list.remove(i);
+        }
+    }
+}
+
+fn occupied<T>((i, slot): (usize, &Option<T>)) -> Option<u32> {
+    slot.as_ref().map(|_| i as u32)
+}
+
+fn neighbour(&(n, _): &(u32, u32)) -> u32 {
+    n
+}
+
+fn incident_edge(&(_, e): &(u32, u32)) -> u32 {
+    e
+}
+
+fn edge_with_data(slot: &Option<(u32, u32, EdgeData)>) -> Option<(u32, u32, &EdgeData)> {
+    slot.as_ref().map(|(a, b, edata)| (*a, *b, edata))
+}
+
+fn incident_edge_with_neighbour(&(n, e): &(u32, u32)) -> (u32, u32) {
+    (e, n)
+}
+
+impl Graph for VecGraph {
+    type VertexIx = u32;
+
+    type EdgeIx = u32;
+
+    type VertexData = VertexData;
+
+    type EdgeData = EdgeData;
+
+    type EdgeIterator<'a> = FilterMap<
+        Enumerate<slice::Iter<'a, Option<(u32, u32, EdgeData)>>>,
+        fn((usize, &Option<(u32, u32, EdgeData)>)) -> Option<u32>,
+    >;
+
+    type VertexIterator<'a> = FilterMap<
+        Enumerate<slice::Iter<'a, Option<VertexData>>>,
+        fn((usize, &Option<VertexData>)) -> Option<u32>,
+    >;
+
+    type NeighbourIterator<'a> = Map<slice::Iter<'a, (u32, u32)>, fn(&(u32, u32)) -> u32>;
+
+    type IncidentEdgeIterator<'a> = Map<slice::Iter<'a, (u32, u32)>, fn(&(u32, u32)) -> u32>;
+
+    type EdgeDataIterator<'a> = FilterMap<
+        slice::Iter<'a, Option<(u32, u32, EdgeData)>>,
+        fn(&Option<(u32, u32, EdgeData)>) -> Option<(u32, u32, &EdgeData)>,
+    >;
+
+    type IncidentNeighbourIterator<'a> =
+        Map<slice::Iter<'a, (u32, u32)>, fn(&(u32, u32)) -> (u32, u32)>;
+
+    type NeighbourKindIterator<'a> = NeighboursWithKind<'a, VecGraph>;
+
+    type InputIterator<'a> = Copied<set::Iter<'a, u32>>;
+
+    type OutputIterator<'a> = Copied<set::Iter<'a, u32>>;
+
+    const BACKEND: &'static str = "VecGraph-rs";
+
+    fn set_input(&mut self, v: u32, flag: bool) {
+        if flag {
+            self.inputs.insert(v);
+        } else {
+            self.inputs.shift_remove(&v);
+        }
+    }
+
+    fn set_output(&mut self, v: u32, flag: bool) {
+        if flag {
+            self.outputs.insert(v);
+        } else {
+            self.outputs.shift_remove(&v);
+        }
+    }
+
+    fn is_input(&self, v: u32) -> bool {
+        self.inputs.contains(&v)
+    }
+
+    fn is_output(&self, v: u32) -> bool {
+        self.outputs.contains(&v)
+    }
+
+    fn inputs(&self) -> Self::InputIterator<'_> {
+        self.inputs.iter().copied()
+    }
+
+    fn outputs(&self) -> Self::OutputIterator<'_> {
+        self.outputs.iter().copied()
+    }
+
+    fn input_at(&self, i: usize) -> Option<Self::VertexIx> {
+        self.inputs.get_index(i).copied()
+    }
+
+    fn output_at(&self, i: usize) -> Option<Self::VertexIx> {
+        self.outputs.get_index(i).copied()
+    }
+
+    fn set_io_order(&mut self, inputs: Vec<Self::VertexIx>, outputs: Vec<Self::VertexIx>) {
+        self.inputs = inputs.into_iter().collect();
+        self.outputs = outputs.into_iter().collect();
+    }
+
+    fn num_vertices(&self) -> u32 {
+        self.num_vertices
+    }
+
+    fn num_edges(&self) -> u32 {
+        self.num_edges
+    }
+
+    fn vertices(&self) -> Self::VertexIterator<'_> {
+        self.vdata.iter().enumerate().filter_map(occupied)
+    }
+
+    fn edges(&self) -> Self::EdgeIterator<'_> {
+        self.edata.iter().enumerate().filter_map(occupied)
+    }
+
+    fn edges_with_data(&self) -> Self::EdgeDataIterator<'_> {
+        self.edata.iter().filter_map(edge_with_data)
+    }
+
+    fn vertex(&self, v: u32) -> Option<&VertexData> {
+        self.vdata.get(v as usize)?.as_ref()
+    }
+
+    fn vertex_mut(&mut self, v: u32) -> Option<&mut VertexData> {
+        self.vdata.get_mut(v as usize)?.as_mut()
+    }
+
+    fn edge(&self, e: u32) -> Option<&EdgeData> {
+        self.edata.get(e as usize)?.as_ref().map(|(_, _, d)| d)
+    }
+
+    fn edge_mut(&mut self, e: u32) -> Option<&mut EdgeData> {
+        self.edata.get_mut(e as usize)?.as_mut().map(|(_, _, d)| d)
+    }
+
+    fn scalar(&self) -> &Scalar {
+        &self.scalar
+    }
+
+    fn scalar_mut(&mut self) -> &mut Scalar {
+        &mut self.scalar
+    }
+
+    fn edge_endpoints(&self, e: u32) -> Option<(u32, u32)> {
+        self.edata
+            .get(e as usize)?
+            .as_ref()
+            .map(|&(a, b, _)| (a, b))
+    }
+
+    fn vertex_degree(&self, v: u32) -> u32 {
+        self.adjacency[v as usize].len() as u32
+    }
+
+    fn neighbours(&self, v: u32) -> Self::NeighbourIterator<'_> {
+        self.adjacency[v as usize].iter().map(neighbour)
+    }
+
+    fn incident_edges(&self, v: u32) -> Self::IncidentEdgeIterator<'_> {
+        self.adjacency[v as usize].iter().map(incident_edge)
+    }
+
+    fn incident_edges_with_neighbours(&self, v: u32) -> Self::IncidentNeighbourIterator<'_> {
+        self.adjacency[v as usize]
+            .iter()
+            .map(incident_edge_with_neighbour)
+    }
+
+    fn neighbours_with_kind(&self, v: u32) -> Self::NeighbourKindIterator<'_> {
+        NeighboursWithKind::new(self, v)
+    }
+
+    fn connected(&self, v: u32, u: u32) -> bool {
+        // Scan the shorter list.
+        let (v, u) = if self.adjacency[v as usize].len() <= self.adjacency[u as usize].len() {
+            (v, u)
+        } else {
+            (u, v)
+        };
+        self.adjacency[v as usize].iter().any(|&(n, _)| n == u)
+    }
+
+    fn is_ground(&self, v: u32) -> bool {
+        self.vdata(v).ground
+    }
+
+    fn adjoint(&mut self) {
+        std::mem::swap(&mut self.inputs, &mut self.outputs);
+        self.scalar = self.scalar.conjugate();
+        let last_row = self.depth() as i32 - 1;
+        for vdata in self.vdata.iter_mut().flatten() {
+            vdata.phase = -vdata.phase;
+            vdata.row = last_row - vdata.row;
+        }
+    }
+
+    fn compose(&mut self, other: Self) -> Result<(), ComposeError> {
+        if self.outputs.len() != other.inputs.len() {
+            return Err(ComposeError::ArityMismatch {
+                outputs: self.outputs.len(),
+                inputs: other.inputs.len(),
+            });
+        }
+        self.scalar *= other.scalar;
+        let seams: Vec<_> = self.outputs.iter().copied().collect();
+
+        // Same layout as `GGraph::compose`: the inputs of `other` are identified with the
+        // outputs of `self`, and the rows of `other` continue from the last row of `self`.
+        let offset = self.depth().saturating_sub(1) as i32;
+        let mut map = HashMap::new();
+        for (&i, &o) in other.inputs.iter().zip(&seams) {
+            map.insert(i, o);
+            self.vdata_mut(o).ground |= other.vdata(i).ground;
+        }
+        for v in other.vertices() {
+            if let Entry::Vacant(entry) = map.entry(v) {
+                let vdata = other.vdata(v).clone();
+                entry.insert(self.add_vertex_data(VertexData {
+                    row: vdata.row + offset,
+                    ..vdata
+                }));
+            }
+        }
+        for &(a, b, edata) in other.edata.iter().flatten() {
+            self.add_edge(map[&b], map[&a], edata.kind);
+        }
+        self.outputs = other.outputs.iter().map(|v| map[v]).collect();
+
+        // Turn the joined boundaries into plain wires, as `GGraph::compose` does. Indices are
+        // stable, so the seams can be removed in any order.
+        for s in seams {
+            if self.inputs.contains(&s) || self.outputs.contains(&s) {
+                continue;
+            }
+            self.set_vertex_kind(s, VertexKind::Z);
+            self.set_phase(s, Phase::zero());
+            let wire: Vec<_> = self.adjacency[s as usize]
+                .iter()
+                .map(|&(n, e)| (n, self.edge(e).unwrap().kind))
+                .collect();
+            if let [(a, k1), (b, k2)] = wire[..] {
+                if a != s && b != s && a != b && !self.vdata(s).ground {
+                    self.remove_vertex(s);
+                    let kind = if k1 == k2 {
+                        EdgeKind::Regular
+                    } else {
+                        EdgeKind::Hadam
+                    };
+                    self.add_edge(a, b, kind);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn tensor(&mut self, other: Self) {
+        let qubit_offset = self.qubit_count() as i32;
+        self.scalar *= other.scalar;
+        let mut map = vec![0; other.vdata.len()];
+        for v in other.vertices() {
+            let vdata = other.vdata(v).clone();
+            map[v as usize] = self.add_vertex_data(VertexData {
+                qubit: vdata.qubit + qubit_offset,
+                ..vdata
+            });
+        }
+        for &(a, b, edata) in other.edata.iter().flatten() {
+            self.add_edge(map[b as usize], map[a as usize], edata.kind);
+        }
+        self.inputs
+            .extend(other.inputs.iter().map(|&v| map[v as usize]));
+        self.outputs
+            .extend(other.outputs.iter().map(|&v| map[v as usize]));
+    }
+
+    fn add_vertices(&mut self, count: u32) -> Vec<u32> {
+        (0..count)
+            .map(|_| self.add_vertex(Default::default(), Default::default()))
+            .collect()
+    }
+
+    fn add_vertex(&mut self, kind: VertexKind, phase: Phase) -> u32 {
+        self.add_vertex_data(VertexData {
+            kind,
+            phase,
+            ..Default::default()
+        })
+    }
+
+    fn mode(&self) -> GraphMode {
+        self.mode
+    }
+
+    fn add_edge(
+        &mut self,
+        v: Self::VertexIx,
+        u: Self::VertexIx,
+        kind: EdgeKind,
+    ) -> Option<Self::EdgeIx> {
+        match self.mode {
+            GraphMode::Simple => merge_edge(self, v, u, kind, |g, v, u, kind| {
+                Some(g.insert_edge(v, u, kind))
+            }),
+            GraphMode::Multi => Some(self.insert_edge(v, u, kind)),
+        }
+    }
+
+    /// Remove a vertex and its edges. The other vertices and edges keep their indices.
+    fn remove_vertex(&mut self, v: u32) {
+        if self.vertex(v).is_none() {
+            return;
+        }
+        for (n, e) in std::mem::take(&mut self.adjacency[v as usize]) {
+            if n != v {
+                self.unlink(n, e);
+            }
+            self.edata[e as usize] = None;
+            self.free_edges.push(e);
+            self.num_edges -= 1;
+        }
+        self.vdata[v as usize] = None;
+        self.free_vertices.push(v);
+        self.num_vertices -= 1;
+        self.inputs.shift_remove(&v);
+        self.outputs.shift_remove(&v);
+    }
+
+    /// Remove an edge. The other edges keep their indices.
+    fn remove_edge(&mut self, e: u32) {
+        if let Some((a, b, _)) = self.edata.get_mut(e as usize).and_then(Option::take) {
+            self.unlink(a, e);
+            if a != b {
+                self.unlink(b, e);
+            }
+            self.free_edges.push(e);
+            self.num_edges -= 1;
+        }
+    }
+}
+
+impl CartesianGraph for VecGraph {
+    type Qubit = i32;
+    type Row = i32;
+
+    fn row(&self, v: u32) -> i32 {
+        self.vdata(v).row
+    }
+
+    fn qubit(&self, v: u32) -> i32 {
+        self.vdata(v).qubit
+    }
+
+    fn set_row(&mut self, v: u32, r: i32) {
+        self.vdata_mut(v).row = r;
+    }
+
+    fn set_qubit(&mut self, v: u32, q: i32) {
+        self.vdata_mut(v).qubit = q;
+    }
+
+    fn set_position(&mut self, v: u32, q: i32, r: i32) {
+        let vdata = self.vdata_mut(v);
+        vdata.qubit = q;
+        vdata.row = r;
+    }
+
+    /// One more than the largest row, or 0 if there are no vertices on a non-negative row
+    fn depth(&self) -> u32 {
+        self.vdata
+            .iter()
+            .flatten()
+            .map(|vdata| vdata.row + 1)
+            .max()
+            .unwrap_or(0)
+            .max(0) as u32
+    }
+
+    /// One more than the largest qubit, or 0 if there are no vertices on a non-negative qubit
+    fn qubit_count(&self) -> u32 {
+        self.vdata
+            .iter()
+            .flatten()
+            .map(|vdata| vdata.qubit + 1)
+            .max()
+            .unwrap_or(0)
+            .max(0) as u32
+    }
+}
+
+impl GroundGraph for VecGraph {
+    type GroundIterator = std::vec::IntoIter<u32>;
+
+    fn grounds(&self) -> Self::GroundIterator {
+        self.vertices()
+            .filter(|&v| self.vdata(v).ground)
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+}
@@ -0,0 +1,283 @@
+//! Summarized views of large diagrams.
+
+use crate::zx::graph::*;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Range;
+
+/// Aggregate information about a group of vertices in a `CollapsedView`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GroupSummary {
+    pub num_vertices: u32,
+    pub num_z: u32,
+    pub num_x: u32,
+    pub num_hbox: u32,
+    pub num_boundary: u32,
+    pub num_hole: u32,
+    /// Edges with both endpoints in the group
+    pub internal_edges: u32,
+    /// Internal edges of Hadamard kind
+    pub internal_hadamard_edges: u32,
+}
+
+/// A diagram with groups of vertices collapsed into single summary nodes.
+///
+/// The summary graph has one node per group, and an edge between two groups weighted by the
+/// number of original edges crossing between them. Vertices not contained in any group are left
+/// out of the summary. `dot::write_collapsed_dot` renders the summary graph.
+pub struct CollapsedView<'a, G: Graph> {
+    g: &'a G,
+    groups: Vec<Vec<G::VertexIx>>,
+    group_of: HashMap<G::VertexIx, usize>,
+    summaries: Vec<GroupSummary>,
+    crossing: BTreeMap<(usize, usize), u32>,
+}
+
+impl<'a, G: Graph> CollapsedView<'a, G> {
+    /// Collapse each group of the partition. Panics if a vertex appears in more than one group.
+    pub fn new(g: &'a G, partition: &[Vec<G::VertexIx>]) -> Self {
+        let mut group_of = HashMap::new();
+        let mut summaries = vec![GroupSummary::default(); partition.len()];
+        for (i, group) in partition.iter().enumerate() {
+            for &v in group {
+                if group_of.insert(v, i).is_some() {
+                    panic!("vertex {:?} is in more than one group", v);
+                }
+                let s = &mut summaries[i];
+                s.num_vertices += 1;
+                match g.vertex(v).unwrap().kind() {
+                    VertexKind::Z => s.num_z += 1,
+                    VertexKind::X => s.num_x += 1,
+                    VertexKind::HBox => s.num_hbox += 1,
+                    VertexKind::Boundary => s.num_boundary += 1,
+                    VertexKind::Hole => s.num_hole += 1,
+                }
+            }
+        }
+
+        let mut crossing = BTreeMap::new();
+        for e in g.edges() {
+            let (v, u) = g.edge_endpoints(e).unwrap();
+            let (a, b) = match (group_of.get(&v), group_of.get(&u)) {
+                (Some(&a), Some(&b)) => (a, b),
+                _ => continue,
+            };
+            if a == b {
+                summaries[a].internal_edges += 1;
+                if g.edge(e).unwrap().kind() == EdgeKind::Hadam {
+                    summaries[a].internal_hadamard_edges += 1;
+                }
+            } else {
+                *crossing.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+            }
+        }
+
+        CollapsedView {
+            g,
+            groups: partition.to_vec(),
+            group_of,
+            summaries,
+            crossing,
+        }
+    }
+
+    /// Number of summary nodes
+    pub fn num_groups(&self) -> usize {
+        self.groups.len()
+    }
+
+    /// Aggregate information of a group
+    pub fn summary(&self, group: usize) -> &GroupSummary {
+        &self.summaries[group]
+    }
+
+    /// Group containing a vertex, if any
+    pub fn group_of(&self, v: G::VertexIx) -> Option<usize> {
+        self.group_of.get(&v).copied()
+    }
+
+    /// Edges of the summary graph as `((group, group), crossing edge count)`, with the smaller
+    /// group first.
+    pub fn summary_edges(&self) -> impl Iterator<Item = ((usize, usize), u32)> + '_ {
+        self.crossing.iter().map(|(&k, &v)| (k, v))
+    }
+
+    /// Number of original edges between two groups
+    pub fn crossing_edges(&self, a: usize, b: usize) -> u32 {
+        self.crossing
+            .get(&(a.min(b), a.max(b)))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Extract the subdiagram of a group, with `Graph::induced_subgraph`.
+    ///
+    /// Edges leaving the group are replaced by regular edges to fresh boundary vertices marked
+    /// as outputs, so `Graph::replace_subgraph` puts the result back in place. Returns the new
+    /// graph and a map from the original indices to the new ones.
+    pub fn expand(&self, group: usize) -> (G, HashMap<G::VertexIx, G::VertexIx>)
+    where
+        G: Default,
+        G::VertexData: Clone,
+    {
+        self.g.induced_subgraph(&self.groups[group], true)
+    }
+}
+
+/// Partition the vertices of a graph into its connected components.
+pub fn components<G: Graph>(g: &G) -> Vec<Vec<G::VertexIx>> {
+    let mut seen = HashSet::new();
+    let mut res = Vec::new();
+    for start in g.vertices() {
+        if !seen.insert(start) {
+            continue;
+        }
+        let mut component = vec![start];
+        let mut i = 0;
+        while i < component.len() {
+            for u in g.neighbours(component[i]) {
+                if seen.insert(u) {
+                    component.push(u);
+                }
+            }
+            i += 1;
+        }
+        res.push(component);
+    }
+    res
+}
+
+/// Partition the vertices of a graph by ranges of qubits, one group per range.
+///
+/// Each group lists the vertices of its qubits in order, each qubit ordered by row as in
+/// `CartesianGraph::qubit_slice`. Vertices on qubits outside the ranges are left out, and
+/// overlapping ranges put a vertex in more than one group, which `CollapsedView::new` rejects.
+pub fn qubit_ranges<G>(g: &G, ranges: &[Range<i32>]) -> Vec<Vec<G::VertexIx>>
+where
+    G: CartesianGraph,
+    G::Qubit: From<i32> + PartialEq,
+    G::Row: PartialOrd,
+{
+    ranges
+        .iter()
+        .map(|range| {
+            range
+                .clone()
+                .flat_map(|q| g.qubit_slice(q.into()))
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::analysis::is_isomorphic;
+    use crate::zx::simple_graph::GGraph;
+    use crate::zx::templates;
+
+    /// Two wires with a CNOT and a Hadamard edge on the target, split into the inputs, the
+    /// gate, and the outputs
+    fn fixture() -> (GGraph, Vec<Vec<<GGraph as Graph>::VertexIx>>) {
+        let mut g = templates::cnot();
+        let vs: Vec<_> = g.vertices().collect();
+        let (inputs, outputs, control, target) = (&vs[0..2], &vs[2..4], vs[4], vs[5]);
+        let e = g.edge_between(target, outputs[1]).unwrap();
+        g.set_edge_kind(e, EdgeKind::Hadam);
+        let partition = vec![inputs.to_vec(), vec![control, target], outputs.to_vec()];
+        (g, partition)
+    }
+
+    #[test]
+    fn summaries_and_crossing_edges() {
+        let (g, partition) = fixture();
+        let view = CollapsedView::new(&g, &partition);
+        assert_eq!(view.num_groups(), 3);
+        assert_eq!(
+            *view.summary(1),
+            GroupSummary {
+                num_vertices: 2,
+                num_z: 1,
+                num_x: 1,
+                internal_edges: 1,
+                ..Default::default()
+            }
+        );
+        assert_eq!(view.summary(0).num_boundary, 2);
+        assert_eq!(view.summary(0).internal_edges, 0);
+        let edges: Vec<_> = view.summary_edges().collect();
+        assert_eq!(edges, [((0, 1), 2), ((1, 2), 2)]);
+        assert_eq!(view.crossing_edges(2, 1), 2);
+        assert_eq!(view.crossing_edges(0, 2), 0);
+        assert_eq!(view.group_of(partition[1][0]), Some(1));
+    }
+
+    #[test]
+    fn vertices_outside_the_partition_are_left_out() {
+        let (g, partition) = fixture();
+        let view = CollapsedView::new(&g, &partition[1..2]);
+        assert_eq!(view.summary_edges().count(), 0);
+        assert_eq!(view.group_of(partition[0][0]), None);
+    }
+
+    #[test]
+    fn expand_round_trips() {
+        let (g, partition) = fixture();
+        let view = CollapsedView::new(&g, &partition);
+        let (expanded, map) = view.expand(1);
+        assert_eq!(map.len(), 2);
+        assert_eq!(expanded.outputs().count(), 4);
+        let (induced, _) = g.induced_subgraph(&partition[1], true);
+        assert!(is_isomorphic(&expanded, &induced));
+
+        // Putting the expansion back, with its first two stubs as inputs, gives the original.
+        let mut h = g.clone();
+        let cut = h.cut_edges(&partition[1]);
+        let stubs: Vec<_> = expanded.outputs().collect();
+        let mut expanded = expanded;
+        expanded.set_io_order(stubs[..2].to_vec(), stubs[2..].to_vec());
+        h.replace_subgraph(&partition[1], expanded, &cut);
+        assert!(is_isomorphic(&g, &h));
+    }
+
+    #[test]
+    fn qubit_range_partition() {
+        let mut g = templates::cnot();
+        g.tensor(templates::ghz(3));
+        let parts = qubit_ranges(&g, &[0..1, 1..2, 2..5]);
+        for (part, range) in parts.iter().zip([0..1, 1..2, 2..5].iter()) {
+            assert!(part.iter().all(|&v| range.contains(&g.qubit(v))));
+        }
+        assert_eq!(
+            parts.iter().map(Vec::len).sum::<usize>(),
+            g.num_vertices() as usize
+        );
+        // Each qubit is ordered by row.
+        let rows: Vec<_> = parts[0].iter().map(|&v| g.row(v)).collect();
+        assert_eq!(rows, [0, 1, 2]);
+
+        let view = CollapsedView::new(&g, &parts);
+        assert_eq!(view.crossing_edges(0, 1), 1);
+        assert_eq!(view.summary(2).num_boundary, 3);
+        assert_eq!(view.summary_edges().count(), 1);
+        assert_eq!(qubit_ranges(&g, &[5..7, 7..7]), [Vec::new(), Vec::new()]);
+    }
+
+    #[test]
+    #[should_panic(expected = "more than one group")]
+    fn overlapping_groups() {
+        let (g, partition) = fixture();
+        let overlapping = vec![partition[1].clone(), partition[1].clone()];
+        CollapsedView::new(&g, &overlapping);
+    }
+
+    #[test]
+    fn connected_components_partition() {
+        let mut g = templates::cnot();
+        g.tensor(templates::bell_pair());
+        let parts = components(&g);
+        assert_eq!(parts.iter().map(Vec::len).collect::<Vec<_>>(), [6, 3]);
+        let view = CollapsedView::new(&g, &parts);
+        assert_eq!(view.summary_edges().count(), 0);
+        assert_eq!(view.summary(1).internal_edges, 2);
+    }
+}
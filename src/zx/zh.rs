@@ -0,0 +1,417 @@
+//! Rewrite rules of the ZH-calculus for H-boxes.
+//!
+//! An H-box with phase `a` is labelled `e^{i a}`: its tensor is 1 everywhere but on the
+//! all-ones index, where it is `e^{i a}`. The default label is -1, that is phase pi (see
+//! `VertexKind::default_phase`). Z spiders copy the values of their legs, so an H-box whose legs
+//! all end on Z spiders acts on the values of those spiders. Three Z spiders joined to an H-box
+//! labelled -1 make up a CCZ gate, which is how `Circuit::to_graph` translates it.
+//!
+//! The rules follow `simplify`: they run in rounds of non-overlapping matches, multiply the
+//! scalar of the diagram by the factor they drop, and skip grounded vertices and vertices
+//! flagged as inputs or outputs.
+
+use crate::zx::circuit::Gate;
+use crate::zx::graph::*;
+use std::collections::{BTreeMap, HashSet};
+
+/// Whether an H-box can be rewritten: it is not an input, an output, or grounded, and has no
+/// self-loops.
+fn is_free_hbox<G: Graph>(g: &G, h: G::VertexIx) -> bool {
+    g.vertex(h).unwrap().kind() == VertexKind::HBox
+        && !g.is_input(h)
+        && !g.is_output(h)
+        && !g.is_ground(h)
+        && g.neighbours(h).all(|n| n != h)
+}
+
+/// The computational basis state plugged into a leg of an H-box by a vertex, if any.
+///
+/// An arity-1 X spider with phase 0 or pi on a regular edge is `sqrt(2)` times `|0⟩` or `|1⟩`,
+/// and so is an arity-1 Z spider on a Hadamard edge. Returns whether the state is `|1⟩`.
+fn basis_state<G: Graph>(g: &G, u: G::VertexIx, kind: EdgeKind) -> Option<bool> {
+    let udata = g.vertex(u).unwrap();
+    let colour = match kind {
+        EdgeKind::Regular => VertexKind::X,
+        EdgeKind::Hadam => VertexKind::Z,
+    };
+    (udata.kind() == colour
+        && udata.phase().is_pauli()
+        && g.incident_edges(u).count() == 1
+        && !g.is_input(u)
+        && !g.is_output(u)
+        && !g.is_ground(u))
+    .then(|| udata.phase() == Phase::pi())
+}
+
+/// Plug computational basis states into H-boxes, until no more can be simplified. Returns the
+/// number of removed states.
+///
+/// A `|1⟩` state, such as a NOT applied to `|0⟩` or a Z spider with phase pi behind a Hadamard
+/// edge, removes the leg and keeps the label of the H-box. A `|0⟩` state turns the H-box into
+/// the all-ones tensor, so the H-box is replaced by an arity-1 Z spider with phase 0 on each of
+/// its other legs. Each state contributes a factor of `sqrt(2)`.
+pub fn hbox_state_simp<G: Graph>(g: &mut G) -> u32 {
+    let mut count = 0;
+    loop {
+        let mut touched = HashSet::new();
+        let mut removed = Vec::new();
+        let vertices: Vec<_> = g.vertices().collect();
+        for h in vertices {
+            if touched.contains(&h) || !is_free_hbox(g, h) {
+                continue;
+            }
+            let legs: Vec<_> = g.neighbours_with_kind(h).collect();
+            let state = legs.iter().find_map(|&(u, kind)| {
+                if touched.contains(&u) {
+                    return None;
+                }
+                basis_state(g, u, kind).map(|one| (u, one))
+            });
+            let (u, one) = match state {
+                Some(state) => state,
+                None => continue,
+            };
+            touched.extend([h, u]);
+            removed.push(u);
+            count += 1;
+            g.scalar_mut().add_power(1);
+            if one {
+                continue;
+            }
+            removed.push(h);
+            for (w, kind) in legs {
+                if w != u {
+                    let z = g.add_vertex(VertexKind::Z, Phase::zero());
+                    g.add_edge(z, w, kind);
+                    touched.insert(w);
+                }
+            }
+        }
+        if removed.is_empty() {
+            return count;
+        }
+        g.remove_vertices(removed);
+    }
+}
+
+/// Rewrite the H-boxes with fewer than two legs. Returns the number of rewritten H-boxes.
+///
+/// An H-box without legs is the scalar `e^{i a}`, and an arity-1 H-box is the state
+/// `|0⟩ + e^{i a} |1⟩`, that is an arity-1 Z spider with the same phase. The latter can then be
+/// fused with `simplify::fuse_spiders`.
+pub fn hbox_arity_simp<G: Graph>(g: &mut G) -> u32 {
+    let mut count = 0;
+    let mut removed = Vec::new();
+    let vertices: Vec<_> = g.vertices().collect();
+    for h in vertices {
+        if !is_free_hbox(g, h) {
+            continue;
+        }
+        match g.incident_edges(h).count() {
+            0 => {
+                let phase = g.vertex(h).unwrap().phase();
+                g.scalar_mut().add_phase(phase);
+                removed.push(h);
+            }
+            1 => g.set_vertex_kind(h, VertexKind::Z),
+            _ => continue,
+        }
+        count += 1;
+    }
+    g.remove_vertices(removed);
+    count
+}
+
+/// The Z spiders at the ends of the legs of an H-box, sorted, if each leg is a single regular
+/// edge to a different Z spider that is not grounded.
+fn hbox_targets<G: Graph>(g: &G, h: G::VertexIx) -> Option<Vec<G::VertexIx>> {
+    if !is_free_hbox(g, h) {
+        return None;
+    }
+    let mut targets = Vec::new();
+    for (n, kind) in g.neighbours_with_kind(h) {
+        if kind != EdgeKind::Regular
+            || g.vertex(n).unwrap().kind() != VertexKind::Z
+            || g.is_ground(n)
+        {
+            return None;
+        }
+        targets.push(n);
+    }
+    targets.sort_unstable();
+    let before = targets.len();
+    targets.dedup();
+    (targets.len() == before).then_some(targets)
+}
+
+/// Multiply the H-boxes acting on the same Z spiders into one, and remove the H-boxes labelled
+/// 1. Returns the number of removed H-boxes.
+///
+/// Since the Z spiders copy their values to every H-box, H-boxes on the same spiders multiply
+/// entrywise, giving an H-box labelled with the product of the labels, that is with the sum of
+/// the phases. An H-box labelled 1 is the all-ones tensor, which the spiders absorb.
+pub fn hbox_multiply_simp<G: Graph>(g: &mut G) -> u32 {
+    let mut groups: BTreeMap<Vec<G::VertexIx>, Vec<G::VertexIx>> = BTreeMap::new();
+    for h in g.vertices() {
+        if let Some(targets) = hbox_targets(g, h) {
+            groups.entry(targets).or_default().push(h);
+        }
+    }
+    let mut removed = Vec::new();
+    for group in groups.into_values() {
+        let phase = group
+            .iter()
+            .fold(Phase::zero(), |acc, &h| acc + g.vertex(h).unwrap().phase());
+        let (&kept, multiplied) = group.split_first().unwrap();
+        removed.extend(multiplied);
+        if phase.is_zero() {
+            removed.push(kept);
+        } else {
+            g.set_phase(kept, phase);
+        }
+    }
+    let count = removed.len() as u32;
+    g.remove_vertices(removed);
+    count
+}
+
+/// Apply `hbox_state_simp`, `hbox_arity_simp` and `hbox_multiply_simp` until none of them
+/// changes the diagram. Returns the total number of rewrites.
+pub fn zh_simp<G: Graph>(g: &mut G) -> u32 {
+    let mut count = 0;
+    loop {
+        let round = hbox_state_simp(g) + hbox_arity_simp(g) + hbox_multiply_simp(g);
+        if round == 0 {
+            return count;
+        }
+        count += round;
+    }
+}
+
+/// The Z spiders of the CCZ made of an H-box, if any.
+///
+/// The H-box must be labelled -1 and have exactly three legs, each a regular edge to a
+/// different Z spider that is not grounded. The spiders are returned sorted.
+pub fn ccz_at<G: Graph>(g: &G, h: G::VertexIx) -> Option<[G::VertexIx; 3]> {
+    if g.vertex(h).unwrap().phase() != Phase::pi() {
+        return None;
+    }
+    match hbox_targets(g, h)?[..] {
+        [a, b, c] => Some([a, b, c]),
+        _ => None,
+    }
+}
+
+/// The CCZ gate made of an H-box, acting on the qubits of its spiders (see `ccz_at`).
+///
+/// This is the inverse of the translation of `Gate::Ccz` by `Circuit::to_graph_into`, when the
+/// sink records the positions of the spiders, as `GGraphBuilder` does. Returns `None` if the
+/// spiders are not on three different qubits.
+pub fn ccz_gate<G: CartesianGraph<Qubit = i32>>(g: &G, h: G::VertexIx) -> Option<Gate> {
+    let mut qubits = ccz_at(g, h)?.map(|v| g.qubit(v));
+    qubits.sort_unstable();
+    if qubits[0] < 0 || qubits[0] == qubits[1] || qubits[1] == qubits[2] {
+        return None;
+    }
+    let [a, b, c] = qubits.map(|q| q as u32);
+    Some(Gate::Ccz(a, b, c))
+}
+
+/// Add a phase gadget on the given Z spiders, compensating its scalar.
+fn add_gadget<G: Graph>(g: &mut G, targets: &[G::VertexIx], phase: Phase) {
+    let axle = g.add_vertex(VertexKind::Z, Phase::zero());
+    let leaf = g.add_vertex(VertexKind::Z, phase);
+    g.add_edge(axle, leaf, EdgeKind::Hadam);
+    for &t in targets {
+        g.add_edge(axle, t, EdgeKind::Hadam);
+    }
+    g.scalar_mut().add_power(targets.len() as i32 - 1);
+}
+
+/// Replace the CCZs made of H-boxes (see `ccz_at`) by phase gadgets, so that the other
+/// simplifications can act on them. Returns the number of replaced H-boxes.
+///
+/// As in `templates::toffoli_gadgets`, the CCZ on `a`, `b` and `c` becomes a `π/4` phase on
+/// each spider, a `-π/4` gadget on each pair of spiders and a `π/4` gadget on all three, since
+/// `4abc = a + b + c - (a ⊕ b) - (a ⊕ c) - (b ⊕ c) + (a ⊕ b ⊕ c)`.
+pub fn ccz_to_gadgets<G: Graph>(g: &mut G) -> u32 {
+    let cczs: Vec<_> = g
+        .vertices()
+        .filter_map(|h| ccz_at(g, h).map(|spiders| (h, spiders)))
+        .collect();
+    let count = cczs.len() as u32;
+    let mut removed = Vec::new();
+    for (h, [a, b, c]) in cczs {
+        removed.push(h);
+        for v in [a, b, c] {
+            let phase = g.vertex(v).unwrap().phase();
+            g.set_phase(v, phase + Phase::new(1, 4));
+        }
+        for pair in [[a, b], [a, c], [b, c]] {
+            add_gadget(g, &pair, Phase::new(-1, 4));
+        }
+        add_gadget(g, &[a, b, c], Phase::new(1, 4));
+    }
+    g.remove_vertices(removed);
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::zx::circuit::Circuit;
+    use crate::zx::simple_graph::GGraph;
+    use crate::zx::tensor::{assert_graph_eq, to_tensor};
+
+    type V = <GGraph as Graph>::VertexIx;
+
+    fn ccz() -> GGraph {
+        let mut c = Circuit::new(3);
+        c.add_gate(Gate::Ccz(0, 1, 2));
+        c.to_graph_into(GGraph::builder())
+    }
+
+    /// An H-box with the given phase on the spiders of `n` wires, returning the H-box and the
+    /// spiders
+    fn hbox_on_wires(n: usize, phase: Phase) -> (GGraph, V, Vec<V>) {
+        let mut g: GGraph = GGraph::new();
+        let h = g.add_vertex(VertexKind::HBox, phase);
+        let spiders = (0..n)
+            .map(|_| {
+                let i = g.add_vertex(VertexKind::Boundary, Phase::zero());
+                let v = g.add_vertex(VertexKind::Z, Phase::zero());
+                let o = g.add_vertex(VertexKind::Boundary, Phase::zero());
+                g.add_edge(i, v, EdgeKind::Regular);
+                g.add_edge(v, o, EdgeKind::Regular);
+                g.add_edge(v, h, EdgeKind::Regular);
+                g.set_input(i, true);
+                g.set_output(o, true);
+                v
+            })
+            .collect();
+        (g, h, spiders)
+    }
+
+    #[test]
+    fn ccz_matrix() {
+        let t = to_tensor(&ccz());
+        assert_eq!((t.num_inputs(), t.num_outputs()), (3, 3));
+        for i in 0..8 {
+            for o in 0..8 {
+                let expected = match (i == o, i) {
+                    (false, _) => 0.0,
+                    (true, 7) => -1.0,
+                    (true, _) => 1.0,
+                };
+                assert!((t.entry(i, o).re - expected).abs() < 1e-12, "{} {}", i, o);
+                assert!(t.entry(i, o).im.abs() < 1e-12);
+            }
+        }
+        let (g, _, _) = hbox_on_wires(3, VertexKind::HBox.default_phase());
+        assert_graph_eq(&g, &ccz());
+    }
+
+    #[test]
+    fn ccz_round_trip() {
+        let g = ccz();
+        let h = g
+            .vertices()
+            .find(|&v| g.vertex(v).unwrap().kind() == VertexKind::HBox)
+            .unwrap();
+        assert_eq!(ccz_gate(&g, h), Some(Gate::Ccz(0, 1, 2)));
+        let mut spiders = ccz_at(&g, h).unwrap().map(|v| g.qubit(v));
+        spiders.sort_unstable();
+        assert_eq!(spiders, [0, 1, 2]);
+
+        let (g, h, _) = hbox_on_wires(3, Phase::new(1, 2));
+        assert_eq!(ccz_at(&g, h), None);
+        let (g, h, _) = hbox_on_wires(2, Phase::pi());
+        assert_eq!(ccz_at(&g, h), None);
+    }
+
+    #[test]
+    fn ccz_as_gadgets() {
+        let mut g = ccz();
+        let original = g.clone();
+        assert_eq!(ccz_to_gadgets(&mut g), 1);
+        assert_eq!(g.tcount(), 7);
+        assert!(g
+            .vertices()
+            .all(|v| g.vertex(v).unwrap().kind() != VertexKind::HBox));
+        assert_graph_eq(&g, &original);
+    }
+
+    #[test]
+    fn basis_states() {
+        for (kind, edge, phase) in [
+            (VertexKind::X, EdgeKind::Regular, Phase::zero()),
+            (VertexKind::X, EdgeKind::Regular, Phase::pi()),
+            (VertexKind::Z, EdgeKind::Hadam, Phase::zero()),
+            (VertexKind::Z, EdgeKind::Hadam, Phase::pi()),
+        ] {
+            let (mut g, h, _) = hbox_on_wires(2, Phase::new(1, 2));
+            let state = g.add_vertex(kind, phase);
+            g.add_edge(state, h, edge);
+            let original = g.clone();
+            assert_eq!(hbox_state_simp(&mut g), 1);
+            let hboxes = g
+                .vertices()
+                .filter(|&v| g.vertex(v).unwrap().kind() == VertexKind::HBox)
+                .count();
+            // `|1⟩` removes the leg, and `|0⟩` the H-box.
+            assert_eq!(hboxes, if phase.is_zero() { 0 } else { 1 });
+            assert_graph_eq(&g, &original);
+        }
+        // A state with another phase is not a basis state.
+        let (mut g, h, _) = hbox_on_wires(2, Phase::pi());
+        let state = g.add_vertex(VertexKind::X, Phase::new(1, 2));
+        g.add_edge(state, h, EdgeKind::Regular);
+        assert_eq!(hbox_state_simp(&mut g), 0);
+    }
+
+    #[test]
+    fn small_arities() {
+        let (mut g, h, _) = hbox_on_wires(1, Phase::new(1, 4));
+        // An H-box without legs is a scalar.
+        g.add_vertex(VertexKind::HBox, Phase::new(1, 2));
+        let original = g.clone();
+        assert_eq!(hbox_arity_simp(&mut g), 2);
+        assert_eq!(g.vertex(h).unwrap().kind(), VertexKind::Z);
+        assert_eq!(g.num_vertices(), 4);
+        assert_graph_eq(&g, &original);
+    }
+
+    #[test]
+    fn multiply_hboxes() {
+        let (mut g, _, vs) = hbox_on_wires(3, Phase::new(1, 2));
+        let second = g.add_vertex(VertexKind::HBox, Phase::new(1, 4));
+        for &v in &vs {
+            g.add_edge(second, v, EdgeKind::Regular);
+        }
+        // On other spiders, so not multiplied
+        let other = g.add_vertex(VertexKind::HBox, Phase::pi());
+        g.add_edge(other, vs[0], EdgeKind::Regular);
+        g.add_edge(other, vs[1], EdgeKind::Regular);
+        let original = g.clone();
+        assert_eq!(hbox_multiply_simp(&mut g), 1);
+        let mut phases: Vec<_> = g
+            .vertices()
+            .filter(|&v| g.vertex(v).unwrap().kind() == VertexKind::HBox)
+            .map(|v| g.vertex(v).unwrap().phase())
+            .collect();
+        phases.sort_by_key(|p| p.denominator());
+        assert_eq!(phases, [Phase::pi(), Phase::new(3, 4)]);
+        assert_graph_eq(&g, &original);
+
+        // Labels multiplying to 1 remove the H-boxes.
+        let (mut g, _, vs) = hbox_on_wires(2, Phase::new(1, 2));
+        let second = g.add_vertex(VertexKind::HBox, Phase::new(-1, 2));
+        for &v in &vs {
+            g.add_edge(second, v, EdgeKind::Regular);
+        }
+        let original = g.clone();
+        assert_eq!(zh_simp(&mut g), 2);
+        assert_eq!(g.num_vertices(), 6);
+        assert_graph_eq(&g, &original);
+    }
+}
@@ -0,0 +1,193 @@
+//! Interface of the `hermit-q` command-line tool, on the circuits in `tests/fixtures`.
+
+use hermitq::zx::analysis::is_isomorphic;
+use hermitq::zx::circuit::Circuit;
+use hermitq::zx::graph::Graph;
+use hermitq::zx::io::{self, dot, json};
+use hermitq::zx::simple_graph::GGraph;
+use hermitq::zx::tensor::assert_graph_eq;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Output};
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+/// A directory for the files written by one test, unique to this process
+fn temp_dir(test: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("hermitq-cli-{}-{}", test, process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn hermit_q(args: &[&Path]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_hermit-q"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
+
+#[test]
+fn stats() {
+    let output = hermit_q(&[Path::new("stats"), &fixture("toffoli.qasm")]);
+    assert!(output.status.success());
+    assert_eq!(
+        stdout(&output),
+        "Graph(backend=GGraph-rs, qubits=3, depth=12, tcount=7, two_qubit_edges=6, \
+         inputs=3, outputs=3)\n\
+         Clifford phases: 0\n\
+         non-Clifford phases:\n  1/4 pi: 4\n  7/4 pi: 3\n"
+    );
+}
+
+#[test]
+fn stats_with_resources() {
+    let output = hermit_q(&[
+        Path::new("stats"),
+        &fixture("toffoli.qasm"),
+        Path::new("--resources"),
+    ]);
+    assert!(output.status.success());
+    assert!(stdout(&output).ends_with(
+        "non-Clifford phases:\n  1/4 pi: 4\n  7/4 pi: 3\n\
+         qubits: 3\nT-count: 7\nCCZ-count: 0\ngadget parities: 0\nestimated CNOTs: 11\n\
+         measurement depth: no gflow\n"
+    ));
+}
+
+#[test]
+fn simplify_each_strategy() {
+    let dir = temp_dir("simplify");
+    let input = fixture("toffoli.qasm");
+    let original: GGraph = Circuit::from_qasm(&fs::read_to_string(&input).unwrap())
+        .unwrap()
+        .to_graph();
+    for strategy in ["clifford", "full", "gadgets"] {
+        let out = dir.join(format!("{}.json", strategy));
+        let output = hermit_q(&[
+            Path::new("simplify"),
+            &input,
+            Path::new("-o"),
+            &out,
+            Path::new("--strategy"),
+            Path::new(strategy),
+        ]);
+        assert!(output.status.success(), "{}", stderr(&output));
+        let text = stdout(&output);
+        assert!(text.starts_with("SimplifyStats(iterations="), "{}", text);
+        assert!(text.contains("T-count: 7 -> "), "{}", text);
+        let g: GGraph = json::from_json(&fs::read_to_string(&out).unwrap()).unwrap();
+        // The gadget strategy only acts on graph-like diagrams, so it leaves the circuit as is.
+        assert!(g.num_vertices() <= original.num_vertices());
+        assert_graph_eq(&g, &original);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn convert_by_extension() {
+    let dir = temp_dir("convert");
+    let input = fixture("toffoli.qasm");
+    let circuit = Circuit::from_qasm(&fs::read_to_string(&input).unwrap()).unwrap();
+    let original: GGraph = circuit.to_graph();
+    for ext in ["qasm", "json", "dot", "tikz"] {
+        let out = dir.join(format!("toffoli.{}", ext));
+        let output = hermit_q(&[Path::new("convert"), &input, Path::new("-o"), &out]);
+        assert!(output.status.success(), "{}", stderr(&output));
+        let contents = fs::read_to_string(&out).unwrap();
+        match ext {
+            "qasm" => assert_eq!(Circuit::from_qasm(&contents).unwrap(), circuit),
+            "json" => assert!(is_isomorphic(
+                &json::from_json(&contents).unwrap(),
+                &original
+            )),
+            "dot" => assert!(is_isomorphic(&dot::parse(&contents).unwrap(), &original)),
+            _ => assert!(contents.starts_with("\\begin{tikzpicture}")),
+        }
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn gzipped_round_trip() {
+    let dir = temp_dir("gzip");
+    let input = fixture("toffoli.qasm");
+    let original: GGraph = Circuit::from_qasm(&fs::read_to_string(&input).unwrap())
+        .unwrap()
+        .to_graph();
+    let (gzipped, dot, plain) = (
+        dir.join("toffoli.json.gz"),
+        dir.join("toffoli.dot.gz"),
+        dir.join("toffoli.json"),
+    );
+    for (from, to) in [(&input, &gzipped), (&gzipped, &dot), (&dot, &plain)] {
+        let output = hermit_q(&[Path::new("convert"), from, Path::new("-o"), to]);
+        assert!(output.status.success(), "{}", stderr(&output));
+    }
+    for path in [&gzipped, &dot] {
+        assert!(fs::read(path).unwrap().starts_with(&[0x1f, 0x8b]));
+        assert!(is_isomorphic(&io::read_auto(path).unwrap(), &original));
+    }
+    let contents = fs::read_to_string(&plain).unwrap();
+    assert!(is_isomorphic(
+        &json::from_json(&contents).unwrap(),
+        &original
+    ));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn parse_errors_exit_with_1() {
+    let output = hermit_q(&[Path::new("stats"), &fixture("unknown_gate.qasm")]);
+    assert_eq!(output.status.code(), Some(1));
+    let text = stderr(&output);
+    assert!(text.starts_with("hermit-q: cannot parse `"), "{}", text);
+    assert!(
+        text.contains("error: unknown gate `u3`\n --> 5:1\n"),
+        "{}",
+        text
+    );
+    // The parser recovers, and reports the next error too.
+    assert!(
+        text.contains("error: index 2 is out of range for `q[2]`\n --> 6:3\n"),
+        "{}",
+        text
+    );
+}
+
+#[test]
+fn usage_errors_exit_with_2() {
+    let input = fixture("toffoli.qasm");
+    for args in [
+        vec![
+            Path::new("simplify"),
+            &input,
+            Path::new("--strategy"),
+            Path::new("fast"),
+        ],
+        vec![Path::new("simplify"), &input],
+        vec![
+            Path::new("convert"),
+            &input,
+            Path::new("-o"),
+            Path::new("out.txt"),
+        ],
+        vec![Path::new("extract"), &input],
+    ] {
+        let output = hermit_q(&args);
+        assert_eq!(output.status.code(), Some(2), "{:?}", args);
+        assert!(stderr(&output).contains("usage: hermit-q"));
+    }
+}
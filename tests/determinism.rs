@@ -0,0 +1,105 @@
+//! Reproducibility of the randomized components, see `zx::random`.
+
+use hermitq::zx::generate::{random_clifford_t_circuit, random_graph_like};
+use hermitq::zx::graph::Graph;
+use hermitq::zx::random::Seed;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::{self, Command};
+
+/// 64-bit FNV-1a, which unlike `DefaultHasher` is fixed across Rust versions and platforms
+fn fnv1a(data: &str) -> u64 {
+    data.bytes().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn rust_sources(dir: &Path, files: &mut Vec<String>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            rust_sources(&path, files);
+        } else if path.extension().is_some_and(|ext| ext == "rs") {
+            files.push(path.display().to_string());
+        }
+    }
+}
+
+#[test]
+fn no_unseeded_randomness() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut files = Vec::new();
+    rust_sources(&root.join("src"), &mut files);
+    rust_sources(&root.join("benches"), &mut files);
+    assert!(!files.is_empty());
+    for file in files {
+        let src = fs::read_to_string(&file).unwrap();
+        for forbidden in ["thread_rng", "from_entropy", "rand::random", "OsRng"] {
+            assert!(
+                !src.contains(forbidden),
+                "{} uses `{}`, take a `Seed` instead",
+                file,
+                forbidden
+            );
+        }
+    }
+}
+
+#[test]
+fn recorded_circuits() {
+    let circuit = random_clifford_t_circuit(5, 40, 0.3, Seed(1234));
+    assert_eq!(fnv1a(&circuit.to_qasm()), 1389022926666056484);
+    let circuit = random_clifford_t_circuit(3, 10, 0.0, Seed(0));
+    assert_eq!(fnv1a(&circuit.to_qasm()), 2843840202051326952);
+}
+
+#[test]
+fn recorded_graph_like_diagrams() {
+    let g = random_graph_like(12, 0.4, Seed(7));
+    assert_eq!(fnv1a(&g.dump()), 15272763188623169624);
+}
+
+#[test]
+fn same_seed_same_result() {
+    for seed in 0..5 {
+        let a = random_clifford_t_circuit(4, 30, 0.2, Seed(seed));
+        let b = random_clifford_t_circuit(4, 30, 0.2, Seed(seed));
+        assert_eq!(a.to_qasm(), b.to_qasm());
+        let a = random_graph_like(10, 0.3, Seed(seed));
+        let b = random_graph_like(10, 0.3, Seed(seed));
+        assert_eq!(a.dump(), b.dump());
+    }
+}
+
+/// Simplify `input` with the command-line tool in a new process, returning the written diagram
+fn simplify_in_new_process(input: &Path, output: &Path) -> Vec<u8> {
+    let status = Command::new(env!("CARGO_BIN_EXE_hermit-q"))
+        .arg("simplify")
+        .arg(input)
+        .arg("-o")
+        .arg(output)
+        .args(["--strategy", "full"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    fs::read(output).unwrap()
+}
+
+/// Each process seeds its hash maps differently, so any output depending on their iteration
+/// order shows up as a difference between runs.
+#[test]
+fn full_reduce_across_processes() {
+    let dir = env::temp_dir().join(format!("hermitq-determinism-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    for seed in 0..3 {
+        let input = dir.join(format!("circuit{}.qasm", seed));
+        let circuit = random_clifford_t_circuit(5, 60, 0.2, Seed(seed));
+        fs::write(&input, circuit.to_qasm()).unwrap();
+        let first = simplify_in_new_process(&input, &dir.join(format!("first{}.json", seed)));
+        let second = simplify_in_new_process(&input, &dir.join(format!("second{}.json", seed)));
+        assert!(!first.is_empty());
+        assert!(first == second, "seed {} simplifies differently", seed);
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,18 @@
+// Ladder of CCZs between Hadamard layers, with a few phases
+OPENQASM 2.0;
+include "qelib1.inc";
+qreg q[4];
+h q[0];
+h q[1];
+h q[2];
+h q[3];
+ccz q[0], q[1], q[2];
+t q[3];
+cx q[2], q[3];
+ccz q[1], q[2], q[3];
+s q[0];
+cx q[0], q[1];
+t q[1];
+h q[2];
+ccx q[0], q[2], q[3];
+tdg q[0];
@@ -0,0 +1,6 @@
+OPENQASM 2.0;
+qreg q[2];
+h q[0];
+cx q[0], q[1];
+u3(pi, 0, pi) q[1];
+t q[2];